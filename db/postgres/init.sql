@@ -1,13 +1,32 @@
+create table workspaces (
+    id bigint primary key generated always as identity,
+    uid varchar not null unique,
+    name varchar not null unique,
+    host varchar unique,
+    path_prefix varchar unique,
+    created timestamp with time zone not null,
+    updated timestamp with time zone
+);
+
 create table users (
     id bigint primary key generated always as identity,
     uid varchar not null unique,
     username varchar not null unique,
     password varchar not null,
     version bigint not null default 0,
+    disabled timestamp with time zone,
     created timestamp with time zone not null,
     updated timestamp with time zone
 );
 
+create table workspace_users (
+    workspaces_id bigint not null references workspaces (id),
+    users_id bigint not null references users (id),
+    admin boolean not null default false,
+    added timestamp with time zone not null,
+    primary key (workspaces_id, users_id)
+);
+
 create table groups (
     id bigint primary key generated always as identity,
     uid varchar not null unique,
@@ -31,13 +50,62 @@ create table authn_totp (
     secret bytea not null
 );
 
+create table authn_recovery_email (
+    users_id bigint primary key not null references users (id),
+    email varchar not null,
+    verify_token bytea unique,
+    verify_expires timestamp with time zone,
+    verified timestamp with time zone,
+    created timestamp with time zone not null,
+    updated timestamp with time zone
+);
+
+create table authn_recovery (
+    id bigint primary key generated always as identity,
+    token bytea not null unique,
+    users_id bigint not null references users (id),
+    kind varchar not null,
+    issued_on timestamp with time zone not null,
+    expires_on timestamp with time zone not null,
+    approved_by bigint references users (id),
+    approved_on timestamp with time zone,
+    used_on timestamp with time zone
+);
+
+create table authn_passkeys (
+    id bigint primary key generated always as identity,
+    users_id bigint not null references users (id),
+    credential_id bytea not null unique,
+    public_key bytea not null,
+    alg integer not null,
+    sign_count bigint not null default 0,
+    name varchar,
+    created timestamp with time zone not null,
+    last_used timestamp with time zone
+);
+
+create table authn_passkey_challenges (
+    id bigint primary key generated always as identity,
+    users_id bigint references users (id),
+    kind varchar not null,
+    challenge bytea not null,
+    issued_on timestamp with time zone not null,
+    expires_on timestamp with time zone not null
+);
+
 create table authn_sessions (
     token bytea primary key not null,
+    id bigint not null unique generated always as identity,
     users_id bigint not null references users (id),
     issued_on timestamp with time zone not null,
     expires_on timestamp with time zone not null,
     authenticated boolean not null default false,
-    verified boolean not null default false
+    verified boolean not null default false,
+    user_agent varchar,
+    ip_addr inet,
+    last_used timestamp with time zone,
+    name varchar,
+    csrf_token bytea not null
 );
 
 create table authz_roles (
@@ -75,14 +143,104 @@ create table group_roles (
 create table journals (
     id bigint primary key generated always as identity,
     uid varchar not null unique,
+    workspaces_id bigint not null references workspaces (id),
     users_id bigint not null references users (id),
     name varchar not null,
     description varchar,
+    e2e boolean not null default false,
+    next_entry_number bigint not null default 1,
+    schema_version bigint not null default 1,
+    archived timestamp with time zone,
+    settings jsonb not null default '{}',
     created timestamp with time zone not null,
     updated timestamp with time zone,
     unique (users_id, name)
 );
 
+create table journal_keys (
+    journals_id bigint primary key references journals (id),
+    wrapped bytea not null,
+    created timestamp with time zone not null
+);
+
+create table journal_e2e_keys (
+    journals_id bigint primary key references journals (id),
+    kdf varchar not null,
+    kdf_memory integer not null,
+    kdf_iterations integer not null,
+    kdf_parallelism integer not null,
+    salt bytea not null,
+    wrapped_key bytea,
+    created timestamp with time zone not null,
+    updated timestamp with time zone
+);
+
+create table journal_orders (
+    users_id bigint not null references users (id),
+    journals_id bigint not null references journals (id),
+    position integer not null,
+    pinned boolean not null default false,
+    primary key (users_id, journals_id)
+);
+
+create table journal_locations (
+    journals_id bigint primary key references journals (id),
+    location varchar not null,
+    created timestamp with time zone not null,
+    updated timestamp with time zone
+);
+
+create table journal_upload_policies (
+    journals_id bigint primary key references journals (id),
+    max_file_size bigint,
+    allowed_mime_types varchar[],
+    max_files_per_entry integer,
+    strip_metadata boolean,
+    created timestamp with time zone not null,
+    updated timestamp with time zone
+);
+
+create table journal_goals (
+    id bigint primary key generated always as identity,
+    journals_id bigint not null references journals (id),
+    name varchar not null,
+    kind jsonb not null,
+    period varchar not null,
+    target bigint not null,
+    created timestamp with time zone not null,
+    updated timestamp with time zone
+);
+
+create table journal_subjects (
+    id bigint primary key generated always as identity,
+    journals_id bigint not null references journals (id),
+    kind varchar not null,
+    name varchar not null,
+    aliases varchar[] not null default '{}',
+    description varchar,
+    created timestamp with time zone not null,
+    updated timestamp with time zone,
+    unique (journals_id, kind, name)
+);
+
+create table journal_delete_tokens (
+    journals_id bigint primary key references journals (id),
+    token bytea not null,
+    expires timestamp with time zone not null
+);
+
+create table journal_feed_tokens (
+    journals_id bigint primary key references journals (id),
+    users_id bigint not null references users (id),
+    token bytea not null unique,
+    created timestamp with time zone not null
+);
+
+create table journal_sorts (
+    users_id bigint primary key references users (id),
+    sort varchar not null
+);
+
 create table custom_fields (
     id bigint primary key generated always as identity,
     uid varchar not null unique,
@@ -96,19 +254,36 @@ create table custom_fields (
     unique (journals_id, name)
 );
 
+create table prompts (
+    id bigint primary key generated always as identity,
+    users_id bigint references users (id),
+    text varchar not null,
+    created timestamp with time zone not null,
+    updated timestamp with time zone
+);
+
 create table entries (
     id bigint primary key generated always as identity,
     uid varchar not null unique,
     journals_id bigint not null references journals (id),
     users_id bigint not null references users (id),
+    number bigint not null,
     entry_date date not null,
+    planned boolean not null default false,
     title varchar,
     contents varchar,
+    ciphertext bytea,
+    prompts_id bigint references prompts (id),
+    word_count integer not null default 0,
     created timestamp with time zone not null,
     updated timestamp with time zone,
-    unique (journals_id, entry_date)
+    unique (journals_id, number)
 );
 
+create index entries_journal_date on entries (journals_id, entry_date);
+
+create index entries_prompts on entries (prompts_id);
+
 create table entry_tags (
     entries_id bigint not null references entries (id),
     key varchar not null,
@@ -118,6 +293,25 @@ create table entry_tags (
     primary key (entries_id, key)
 );
 
+create table entry_mentions (
+    entries_id bigint not null references entries (id),
+    subjects_id bigint not null references journal_subjects (id),
+    source varchar not null,
+    primary key (entries_id, subjects_id, source)
+);
+
+create index entry_mentions_subjects on entry_mentions (subjects_id);
+
+create table entry_tasks (
+    id bigint primary key generated always as identity,
+    entries_id bigint not null references entries (id),
+    position integer not null,
+    text varchar not null,
+    done boolean not null default false,
+    created timestamp with time zone not null,
+    updated timestamp with time zone
+);
+
 create table file_entries (
     id bigint primary key generated always as identity,
     uid varchar not null unique,
@@ -127,10 +321,45 @@ create table file_entries (
     mime_subtype varchar not null,
     mime_param varchar,
     size bigint default 0,
+    hash varchar,
+    encrypted boolean not null default false,
     created timestamp with time zone not null,
     updated timestamp with time zone
 );
 
+create table file_texts (
+    file_entries_id bigint primary key references file_entries (id) on delete cascade,
+    contents varchar,
+    error varchar,
+    search tsvector generated always as (to_tsvector('simple', coalesce(contents, ''))) stored,
+    extracted timestamp with time zone,
+    claimed timestamp with time zone
+);
+
+create index file_texts_search on file_texts using gin (search);
+
+create table file_audio (
+    file_entries_id bigint primary key references file_entries (id) on delete cascade,
+    duration double precision,
+    transcript varchar,
+    error varchar,
+    transcribed timestamp with time zone,
+    claimed timestamp with time zone
+);
+
+create table file_images (
+    file_entries_id bigint primary key references file_entries (id) on delete cascade,
+    captured timestamp,
+    stripped boolean not null default false
+);
+
+create table scan_verdicts (
+    hash varchar primary key,
+    signature varchar,
+    definitions varchar,
+    scanned timestamp with time zone not null
+);
+
 create table custom_field_entries (
     custom_fields_id bigint not null references custom_fields (id),
     entries_id bigint not null references entries (id),
@@ -139,3 +368,159 @@ create table custom_field_entries (
     updated timestamp with time zone,
     primary key (custom_fields_id, entries_id)
 );
+
+create table entry_views (
+    users_id bigint not null references users (id),
+    entries_id bigint not null references entries (id),
+    viewed timestamp with time zone not null,
+    primary key (users_id, entries_id)
+);
+
+create table entry_weather (
+    entries_id bigint primary key references entries (id) on delete cascade,
+    location varchar,
+    summary varchar,
+    error varchar,
+    queued timestamp with time zone not null,
+    fetched timestamp with time zone,
+    claimed timestamp with time zone
+);
+
+create index entry_weather_pending on entry_weather (queued) where fetched is null;
+
+create table entry_revisions (
+    id bigint primary key generated always as identity,
+    entries_id bigint not null references entries (id),
+    users_id bigint not null references users (id),
+    revision bigint not null,
+    snapshot jsonb not null,
+    created timestamp with time zone not null,
+    unique (entries_id, revision)
+);
+
+create table journal_exports (
+    id bigint primary key generated always as identity,
+    journals_id bigint not null references journals (id),
+    users_id bigint not null references users (id),
+    format varchar not null,
+    created timestamp with time zone not null,
+    completed timestamp with time zone,
+    failed timestamp with time zone
+);
+
+create table journal_imports (
+    id bigint primary key generated always as identity,
+    journals_id bigint not null references journals (id),
+    users_id bigint not null references users (id),
+    source varchar not null,
+    total integer,
+    processed integer not null default 0,
+    imported integer not null default 0,
+    files integer not null default 0,
+    error varchar,
+    warnings varchar[] not null default '{}',
+    created timestamp with time zone not null,
+    uploaded timestamp with time zone,
+    started timestamp with time zone,
+    completed timestamp with time zone,
+    failed timestamp with time zone
+);
+
+create table journal_freezes (
+    journals_id bigint primary key references journals (id),
+    users_id bigint not null references users (id),
+    journal_exports_id bigint references journal_exports (id),
+    created timestamp with time zone not null,
+    expires timestamp with time zone not null
+);
+
+create table journal_webhooks (
+    id bigint primary key generated always as identity,
+    journals_id bigint not null references journals (id),
+    users_id bigint not null references users (id),
+    url varchar not null,
+    events varchar[] not null,
+    secret varchar not null,
+    previous_secret varchar,
+    previous_expires timestamp with time zone,
+    enabled boolean not null default true,
+    created timestamp with time zone not null,
+    updated timestamp with time zone
+);
+
+create table webhook_deliveries (
+    id bigint primary key generated always as identity,
+    journal_webhooks_id bigint not null references journal_webhooks (id) on delete cascade,
+    event varchar not null,
+    payload jsonb not null,
+    status varchar not null,
+    attempts integer not null default 0,
+    next_attempt timestamp with time zone not null,
+    response_status integer,
+    latency_ms integer,
+    error varchar,
+    created timestamp with time zone not null,
+    delivered timestamp with time zone
+);
+
+create table journal_shares (
+    journals_id bigint not null references journals (id),
+    users_id bigint not null references users (id),
+    role_id bigint not null unique references authz_roles (id),
+    created timestamp with time zone not null,
+    updated timestamp with time zone,
+    primary key (journals_id, users_id)
+);
+
+create table user_reminders (
+    users_id bigint primary key references users (id),
+    journals_id bigint not null references journals (id),
+    remind_at time not null,
+    timezone varchar not null,
+    notify varchar not null,
+    enabled boolean not null default true,
+    last_sent date,
+    created timestamp with time zone not null,
+    updated timestamp with time zone
+);
+
+create table user_preferences (
+    users_id bigint primary key references users (id),
+    timezone varchar not null,
+    locale varchar not null,
+    date_format varchar not null,
+    default_journals_id bigint references journals (id),
+    week_start varchar not null,
+    unit_system varchar,
+    updated timestamp with time zone not null
+);
+
+create table journal_deletions (
+    id bigint primary key generated always as identity,
+    journals_id bigint not null,
+    users_id bigint not null references users (id),
+    directory varchar not null,
+    created timestamp with time zone not null,
+    cleaned timestamp with time zone
+);
+
+create table usage_reports (
+    report_date date primary key,
+    total_users bigint not null,
+    active_users bigint not null,
+    entries_written bigint not null,
+    total_entries bigint not null,
+    storage_growth bigint not null,
+    storage_total bigint not null,
+    created timestamp with time zone not null
+);
+
+create table jobs (
+    name varchar primary key,
+    schedule varchar not null,
+    enabled boolean not null default true,
+    last_run timestamp with time zone,
+    last_status varchar,
+    last_error varchar,
+    last_duration bigint
+);
@@ -16,6 +16,7 @@ interface LoginForm {
 enum LoginFailure {
     UsernameNotFound = "UsernameNotFound",
     InvalidPassword = "InvalidPassword",
+    UserDisabled = "UserDisabled",
 }
 
 interface LoginSuccess {
@@ -3,6 +3,7 @@ import { BrowserRouter, Routes, Route } from "react-router-dom";
 
 import Login from "./Login";
 import App from "./App";
+import { install_csrf } from "./net";
 
 import "./media";
 
@@ -24,6 +25,8 @@ if (get_theme_preference() === "dark") {
     document.documentElement.classList.add("dark");
 }
 
+install_csrf();
+
 document.addEventListener("DOMContentLoaded", () => {
     const root = document.getElementById("root");
     const renderer = createRoot(root);
@@ -8,12 +8,16 @@ export namespace custom_field {
         FloatRange = "FloatRange",
         Time = "Time",
         TimeRange = "TimeRange",
+        Select = "Select",
+        Boolean = "Boolean",
+        Text = "Text",
     }
 
     export interface IntegerType {
         type: TypeName.Integer,
         minimum: number | null,
         maximum: number | null,
+        unit: string | null,
     }
 
     export interface IntegerValue {
@@ -25,6 +29,7 @@ export namespace custom_field {
         type: TypeName.IntegerRange,
         minimum: number | null,
         maximum: number | null,
+        unit: string | null,
     }
 
     export interface IntegerRangeValue {
@@ -39,6 +44,7 @@ export namespace custom_field {
         maximum: number | null,
         step: number,
         precision: number,
+        unit: string | null,
     }
 
     export interface FloatValue {
@@ -52,6 +58,7 @@ export namespace custom_field {
         maximum: number | null,
         step: number,
         precision: number,
+        unit: string | null,
     }
 
     export interface FloatRangeValue {
@@ -80,13 +87,53 @@ export namespace custom_field {
         high: string,
     }
 
+    export interface SelectOption {
+        value: string,
+        color: string | null,
+    }
+
+    export interface SelectType {
+        type: TypeName.Select,
+        options: SelectOption[],
+    }
+
+    export interface SelectValue {
+        type: TypeName.Select,
+        value: string,
+    }
+
+    export interface BooleanType {
+        type: TypeName.Boolean,
+        default: boolean,
+        label: string | null,
+    }
+
+    export interface BooleanValue {
+        type: TypeName.Boolean,
+        value: boolean,
+    }
+
+    export interface TextType {
+        type: TypeName.Text,
+        minimum: number | null,
+        maximum: number | null,
+    }
+
+    export interface TextValue {
+        type: TypeName.Text,
+        value: string,
+    }
+
     export type Type =
         IntegerType |
         IntegerRangeType |
         FloatType |
         FloatRangeType |
         TimeType |
-        TimeRangeType;
+        TimeRangeType |
+        SelectType |
+        BooleanType |
+        TextType;
 
     export type Value =
         IntegerValue |
@@ -94,7 +141,10 @@ export namespace custom_field {
         FloatValue |
         FloatRangeValue |
         TimeValue |
-        TimeRangeValue;
+        TimeRangeValue |
+        SelectValue |
+        BooleanValue |
+        TextValue;
 
     export function make_type(given: TypeName): Type {
         switch (given) {
@@ -103,12 +153,14 @@ export namespace custom_field {
                 type: TypeName.Integer,
                 minimum: null,
                 maximum: null,
+                unit: null,
             };
         case TypeName.IntegerRange:
             return {
                 type: TypeName.IntegerRange,
                 minimum: null,
                 maximum: null,
+                unit: null,
             };
         case TypeName.Float:
             return {
@@ -117,6 +169,7 @@ export namespace custom_field {
                 maximum: null,
                 step: 0.01,
                 precision: 2,
+                unit: null,
             };
         case TypeName.FloatRange:
             return {
@@ -125,6 +178,7 @@ export namespace custom_field {
                 maximum: null,
                 step: 0.01,
                 precision: 2,
+                unit: null,
             };
         case TypeName.Time:
             return {
@@ -135,6 +189,23 @@ export namespace custom_field {
                 type: TypeName.TimeRange,
                 show_diff: false
             };
+        case TypeName.Select:
+            return {
+                type: TypeName.Select,
+                options: [],
+            };
+        case TypeName.Boolean:
+            return {
+                type: TypeName.Boolean,
+                default: false,
+                label: null,
+            };
+        case TypeName.Text:
+            return {
+                type: TypeName.Text,
+                minimum: null,
+                maximum: null,
+            };
         default:
             throw new Error("unknown type name given");
         }
@@ -147,10 +218,15 @@ export interface JournalPartial {
     users_id: number,
     name: string,
     description: string | null,
+    schema_version: number,
+    pinned: boolean,
+    last_entry: string | null,
     created: string,
     updated: string | null
 }
 
+export type JournalSort = "manual" | "name" | "last_entry";
+
 export interface JournalCustomField {
     id: number,
     uid: string,
@@ -168,6 +244,7 @@ export interface JournalFull {
     users_id: number,
     name: string,
     description: string | null,
+    schema_version: number,
     created: string,
     updated: string | null,
     custom_fields: JournalCustomField[],
@@ -175,10 +252,13 @@ export interface JournalFull {
 
 export interface EntryPartial {
     id: number,
+    number: number,
     date: string,
+    planned: boolean,
     created: string,
     updated: string | null,
-    tags: EntryTagsPartial
+    tags: EntryTagsPartial,
+    open_tasks: number,
 }
 
 export interface EntryTagsPartial {
@@ -197,12 +277,16 @@ export interface Entry {
     uid: string,
     journals_id: number,
     users_id: number,
+    number: number,
     date: string,
+    planned: boolean,
     title: string | null,
     contents: string | null,
+    contents_html?: string,
     created: string,
     updated: string | null,
     tags: EntryTag[],
+    tasks: EntryTask[],
     files: EntryFile[],
     custom_fields: EntryCustomField[],
 }
@@ -214,6 +298,15 @@ export interface EntryTag {
     updated: string | null,
 }
 
+export interface EntryTask {
+    id: number,
+    position: number,
+    text: string,
+    done: boolean,
+    created: string,
+    updated: string | null,
+}
+
 export interface EntryFile {
     id: number,
     uid: string,
@@ -223,6 +316,7 @@ export interface EntryFile {
     mime_subtype: string,
     mime_param: string | null,
     size: number,
+    hash: string | null,
     created: string,
     updated: string | null,
     attached?: ClientData,
@@ -35,3 +35,43 @@ export async function send_json(
         body
     });
 }
+
+const CSRF_COOKIE = "csrf_token";
+const CSRF_HEADER = "x-csrf-token";
+const SAFE_METHODS = ["GET", "HEAD", "OPTIONS"];
+
+function get_csrf_token(): string | null {
+    for (let cookie of document.cookie.split("; ")) {
+        let [key, value] = cookie.split("=", 2);
+
+        if (key === CSRF_COOKIE && value != null && value.length !== 0) {
+            return value;
+        }
+    }
+
+    return null;
+}
+
+/**
+ * adds the csrf token of the session to every request that changes state
+ */
+export function install_csrf() {
+    const original = window.fetch.bind(window);
+
+    window.fetch = (input: RequestInfo | URL, init: RequestInit = {}) => {
+        let method = (init.method ?? (input instanceof Request ? input.method : "GET")).toUpperCase();
+        let token = get_csrf_token();
+
+        if (token == null || SAFE_METHODS.includes(method)) {
+            return original(input, init);
+        }
+
+        let headers = new Headers(init.headers ?? (input instanceof Request ? input.headers : undefined));
+
+        if (!headers.has(CSRF_HEADER)) {
+            headers.set(CSRF_HEADER, token);
+        }
+
+        return original(input, {...init, headers});
+    };
+}
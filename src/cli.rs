@@ -0,0 +1,289 @@
+//! commands for working with the server from the command line
+//!
+//! commands connect to the database specified in the config file and exit
+//! once they are done instead of starting the server. ex:
+//!
+//! ```text
+//! TJ2 server.toml entry add --user admin --journal logs --content-file - --tag source=cron
+//! ```
+
+use std::path::{Path, PathBuf};
+
+use chrono::{NaiveDate, Utc};
+use clap::{Args, Subcommand};
+use serde::Serialize;
+use tokio::io::AsyncReadExt;
+
+use crate::config;
+use crate::db::ids::{EntryId, EntryUid, JournalId};
+use crate::error::{self, Context};
+use crate::journal::{Journal, MultiEntry, entry_word_count, is_planned_date, mention, tag};
+use crate::journal::freeze::Freeze;
+use crate::journal::webhook::{self, WebhookEvent};
+use crate::state;
+use crate::user::User;
+
+mod db;
+mod journal;
+mod sec;
+mod user;
+
+pub use db::DbCommand;
+pub use journal::JournalCommand;
+pub use sec::SecCommand;
+pub use user::UserCommand;
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// manages journal entries
+    #[command(subcommand)]
+    Entry(EntryCommand),
+
+    /// moves journals between servers
+    #[command(subcommand)]
+    Journal(JournalCommand),
+
+    /// manages user accounts
+    #[command(subcommand)]
+    User(UserCommand),
+
+    /// backs up and restores the server
+    #[command(subcommand)]
+    Db(DbCommand),
+
+    /// manages the keys used to encrypt journal files
+    #[command(subcommand)]
+    Sec(SecCommand),
+}
+
+#[derive(Debug, Subcommand)]
+pub enum EntryCommand {
+    /// adds a new entry to a journal
+    Add(AddEntry),
+}
+
+#[derive(Debug, Args)]
+pub struct AddEntry {
+    /// the username of the owner of the journal
+    #[arg(long)]
+    user: String,
+
+    /// the name of the journal to add the entry to
+    #[arg(long)]
+    journal: String,
+
+    /// the date of the entry. defaults to the current UTC date
+    #[arg(long)]
+    date: Option<NaiveDate>,
+
+    /// the title of the entry
+    #[arg(long)]
+    title: Option<String>,
+
+    /// the file to read the contents of the entry from. "-" will read from
+    /// stdin
+    #[arg(long)]
+    content_file: Option<PathBuf>,
+
+    /// a tag for the entry as "key" or "key=value". can be specified
+    /// multiple times
+    #[arg(long = "tag", value_parser = parse_tag)]
+    tags: Vec<(String, Option<String>)>,
+}
+
+fn parse_tag(given: &str) -> Result<(String, Option<String>), String> {
+    let (key, value) = match given.split_once('=') {
+        Some((key, value)) => (key.trim(), Some(value.trim())),
+        None => (given.trim(), None),
+    };
+
+    if key.is_empty() {
+        return Err(String::from("tag key is empty"));
+    }
+
+    let Some(key) = tag::normalize_key(key) else {
+        return Err(String::from("tag key has an empty \"/\" separated segment"));
+    };
+
+    Ok((key, value.filter(|v| !v.is_empty()).map(str::to_owned)))
+}
+
+/// runs the parts of a command that must happen before the server state is
+/// created
+///
+/// returns true if the command is done and the state does not need to be
+/// created
+pub async fn prepare(config: &config::Config, command: &Command) -> Result<bool, error::Error> {
+    match command {
+        Command::Db(DbCommand::Restore(args)) => db::restore_backup(config, args).await
+            .map(|_| false),
+        Command::Sec(command) => sec::prepare(config, command),
+        _ => Ok(false),
+    }
+}
+
+/// runs the given command
+pub async fn run(config: &config::Config, state: &state::SharedState, command: Command) -> Result<(), error::Error> {
+    match command {
+        Command::Entry(EntryCommand::Add(args)) => add_entry(state, args).await,
+        Command::Journal(command) => journal::run(state, command).await,
+        Command::User(command) => user::run(state, command).await,
+        Command::Db(command) => db::run(config, state, command).await,
+        Command::Sec(command) => sec::run(config, state, command).await,
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct AddedTag {
+    key: String,
+    value: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct AddedEntry {
+    id: EntryId,
+    uid: EntryUid,
+    journals_id: JournalId,
+    number: i64,
+    date: NaiveDate,
+    title: Option<String>,
+    contents: Option<String>,
+    planned: bool,
+    tags: Vec<AddedTag>,
+}
+
+async fn read_contents(path: &Path) -> Result<String, error::Error> {
+    if path == Path::new("-") {
+        let mut rtn = String::new();
+
+        tokio::io::stdin()
+            .read_to_string(&mut rtn)
+            .await
+            .context("failed to read contents from stdin")?;
+
+        Ok(rtn)
+    } else {
+        tokio::fs::read_to_string(path)
+            .await
+            .context(format!("failed to read content file: \"{}\"", path.display()))
+    }
+}
+
+async fn add_entry(state: &state::SharedState, args: AddEntry) -> Result<(), error::Error> {
+    let contents = match &args.content_file {
+        Some(path) => Some(read_contents(path).await?),
+        None => None,
+    };
+    let contents = contents.filter(|c| !c.trim().is_empty());
+    let title = args.title.map(|t| t.trim().to_owned())
+        .filter(|t| !t.is_empty());
+    let date = args.date.unwrap_or_else(|| Utc::now().date_naive());
+    let created = Utc::now();
+
+    let mut conn = state.db_conn().await?;
+    let transaction = conn.transaction()
+        .await
+        .context("failed to create transaction")?;
+
+    let user = User::retrieve_username(&transaction, &args.user)
+        .await
+        .context("failed to retrieve user")?
+        .context(format!("user \"{}\" was not found", args.user))?;
+
+    let journal = Journal::retrieve_name(&transaction, &user.id, &args.journal)
+        .await
+        .context("failed to retrieve journal")?
+        .context(format!("journal \"{}\" was not found for user \"{}\"", args.journal, args.user))?;
+
+    if journal.e2e {
+        return Err(error::Error::context(
+            "journal is end-to-end encrypted. entries must be encrypted by a client"
+        ));
+    }
+
+    let frozen = Freeze::retrieve_active(&transaction, &journal.id)
+        .await
+        .context("failed to retrieve journal freeze")?;
+
+    if let Some(freeze) = frozen {
+        return Err(error::Error::context(format!(
+            "journal is frozen until {}", freeze.expires
+        )));
+    }
+
+    if journal.settings.multi_entry == MultiEntry::Single {
+        let exists = Journal::date_in_use(&transaction, &journal.id, &date, None)
+            .await
+            .context("failed to check for existing entry")?;
+
+        if exists {
+            return Err(error::Error::context(format!(
+                "journal already has an entry for {date}"
+            )));
+        }
+    }
+
+    let uid = EntryUid::gen();
+    let planned = is_planned_date(&date);
+    let word_count = entry_word_count(title.as_deref(), contents.as_deref());
+    let number = Journal::next_entry_number(&transaction, &journal.id)
+        .await
+        .context("failed to retrieve next entry number")?;
+
+    let id: EntryId = transaction.query_one(
+        "\
+        insert into entries (uid, journals_id, users_id, number, entry_date, planned, title, contents, word_count, created) \
+        values ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10) \
+        returning id",
+        &[&uid, &journal.id, &user.id, &number, &date, &planned, &title, &contents, &word_count, &created]
+    )
+        .await
+        .context("failed to insert entry into database")?
+        .get(0);
+
+    mention::sync_body(&transaction, &journal.id, &id, contents.as_deref())
+        .await
+        .context("failed to sync entry mentions")?;
+
+    let mut tags = Vec::with_capacity(args.tags.len());
+
+    for (key, value) in args.tags {
+        transaction.execute(
+            "\
+            insert into entry_tags (entries_id, key, value, created) \
+            values ($1, $2, $3, $4) \
+            on conflict (entries_id, key) do update set \
+                value = excluded.value",
+            &[&id, &key, &value, &created]
+        )
+            .await
+            .context("failed to insert entry tag")?;
+
+        tags.retain(|tag: &AddedTag| tag.key != key);
+        tags.push(AddedTag { key, value });
+    }
+
+    let entry = AddedEntry {
+        id,
+        uid,
+        journals_id: journal.id,
+        number,
+        date,
+        title,
+        contents,
+        planned,
+        tags,
+    };
+
+    webhook::queue(&transaction, &journal.id, WebhookEvent::EntryCreated, &entry)
+        .await
+        .context("failed to queue entry created webhooks")?;
+
+    transaction.commit()
+        .await
+        .context("failed to commit transaction")?;
+
+    println!("added entry #{} ({}) to \"{}\" for {date}", entry.number, entry.uid, journal.name);
+
+    Ok(())
+}
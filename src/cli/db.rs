@@ -0,0 +1,522 @@
+//! commands for backing up and restoring the server. ex:
+//!
+//! ```text
+//! TJ2 server.toml db backup --output tj2-backup.zip
+//! TJ2 server.toml db restore --input tj2-backup.zip
+//! ```
+//!
+//! a backup is a zip archive with a "database.dump" created by pg_dump in
+//! its custom format and a "storage" directory with the contents of the
+//! storage directory. the database is dumped first so any files added while
+//! the storage directory is being copied are ignored by a restore
+
+use std::fs::File;
+use std::io::{BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command as ProcessCommand, Stdio};
+
+use chrono::{DateTime, Utc};
+use clap::{Args, Subcommand};
+use serde::{Serialize, Deserialize};
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipArchive, ZipWriter};
+
+use crate::config::{self, DbSslMode};
+use crate::db::ids::{FileEntryId, JournalId, UserId};
+use crate::error::{self, Context};
+use crate::journal::Journal;
+use crate::state;
+
+/// the version of the backup layout
+const BACKUP_VERSION: u32 = 1;
+
+/// the name of the database dump in a backup
+const DATABASE_DUMP: &str = "database.dump";
+
+/// the name of the manifest in a backup
+const MANIFEST: &str = "manifest.json";
+
+/// the directory of a backup that holds the storage directory
+const STORAGE_PREFIX: &str = "storage/";
+
+#[derive(Debug, Subcommand)]
+pub enum DbCommand {
+    /// writes the database and the storage directory to a zip archive
+    Backup(Backup),
+
+    /// restores the database and storage directory from a backup and checks
+    /// the restored files against their recorded hashes
+    Restore(Restore),
+}
+
+#[derive(Debug, Args)]
+pub struct Backup {
+    /// the path to write the backup to
+    #[arg(long)]
+    output: PathBuf,
+
+    /// the pg_dump program to run
+    #[arg(long, default_value = "pg_dump")]
+    pg_dump: String,
+}
+
+#[derive(Debug, Args)]
+pub struct Restore {
+    /// the backup to restore
+    #[arg(long)]
+    input: PathBuf,
+
+    /// the pg_restore program to run
+    #[arg(long, default_value = "pg_restore")]
+    pg_restore: String,
+
+    /// skips checking the restored files against their recorded hashes
+    #[arg(long)]
+    skip_verify: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Manifest {
+    version: u32,
+    created: DateTime<Utc>,
+    database: String,
+    files: u64,
+}
+
+/// creates a command for a postgres client program that connects with the
+/// database settings of the config
+fn pg_command(program: &str, db: &config::Db) -> ProcessCommand {
+    let mut command = ProcessCommand::new(program);
+
+    command.env("PGHOST", &db.host)
+        .env("PGPORT", db.port.to_string())
+        .env("PGUSER", &db.user)
+        .env("PGDATABASE", &db.dbname);
+
+    if let Some(password) = &db.password {
+        command.env("PGPASSWORD", password);
+    }
+
+    let ssl_mode = match (db.ssl_mode, &db.root_cert) {
+        (DbSslMode::Disable, _) => "disable",
+        (DbSslMode::Prefer, _) => "prefer",
+        (DbSslMode::Require, Some(_)) => "verify-full",
+        (DbSslMode::Require, None) => "require",
+    };
+
+    command.env("PGSSLMODE", ssl_mode);
+
+    if let Some(root_cert) = &db.root_cert {
+        command.env("PGSSLROOTCERT", root_cert);
+    }
+
+    command
+}
+
+/// adds all the files of a directory to the archive under the given prefix
+fn add_directory(
+    archive: &mut ZipWriter<File>,
+    options: SimpleFileOptions,
+    root: &Path,
+    prefix: &str,
+) -> Result<u64, error::Error> {
+    let mut count = 0;
+    let mut queue = vec![root.to_path_buf()];
+
+    while let Some(dir) = queue.pop() {
+        let read = std::fs::read_dir(&dir)
+            .context(format!("failed to read directory: \"{}\"", dir.display()))?;
+
+        for entry in read {
+            let entry = entry.context("failed to read directory entry")?;
+            let path = entry.path();
+            let file_type = entry.file_type()
+                .context("failed to retrieve file type")?;
+
+            if file_type.is_dir() {
+                queue.push(path);
+
+                continue;
+            }
+
+            if !file_type.is_file() {
+                continue;
+            }
+
+            let relative = path.strip_prefix(root)
+                .context("file is not in the storage directory")?;
+            let Some(relative) = relative.to_str() else {
+                tracing::warn!("skipping file with a non utf-8 path: \"{}\"", path.display());
+
+                continue;
+            };
+
+            let mut file = File::open(&path)
+                .context(format!("failed to open file: \"{}\"", path.display()))?;
+
+            archive.start_file(format!("{prefix}{}", relative.replace('\\', "/")), options)
+                .context("failed to start archive file")?;
+
+            std::io::copy(&mut file, archive)
+                .context("failed to copy file to archive")?;
+
+            count += 1;
+        }
+    }
+
+    Ok(count)
+}
+
+fn write_backup(
+    output: &Path,
+    storage: &Path,
+    mut dump: ProcessCommand,
+) -> Result<Manifest, error::Error> {
+    let file = File::create_new(output)
+        .context(format!("failed to create backup: \"{}\"", output.display()))?;
+    let mut archive = ZipWriter::new(file);
+    let options = SimpleFileOptions::default()
+        .compression_method(CompressionMethod::Deflated)
+        .large_file(true);
+
+    // the custom format of pg_dump is already compressed
+    archive.start_file(DATABASE_DUMP, options.compression_method(CompressionMethod::Stored))
+        .context("failed to start database dump")?;
+
+    let mut child = dump.arg("--format=custom")
+        .arg("--no-owner")
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::inherit())
+        .spawn()
+        .context("failed to run pg_dump")?;
+
+    let mut stdout = child.stdout.take()
+        .context("pg_dump stdout is not available")?;
+
+    std::io::copy(&mut stdout, &mut archive)
+        .context("failed to copy database dump to archive")?;
+
+    let status = child.wait()
+        .context("failed to wait for pg_dump")?;
+
+    if !status.success() {
+        return Err(error::Error::context(format!("pg_dump exited with {status}")));
+    }
+
+    let files = if storage.exists() {
+        add_directory(&mut archive, options, storage, STORAGE_PREFIX)?
+    } else {
+        0
+    };
+
+    let manifest = Manifest {
+        version: BACKUP_VERSION,
+        created: Utc::now(),
+        database: String::from(DATABASE_DUMP),
+        files,
+    };
+
+    archive.start_file(MANIFEST, options)
+        .context("failed to start backup manifest")?;
+    serde_json::to_writer_pretty(&mut archive, &manifest)
+        .context("failed to write backup manifest")?;
+
+    archive.finish()
+        .context("failed to finish backup")?
+        .flush()
+        .context("failed to flush backup")?;
+
+    Ok(manifest)
+}
+
+async fn backup(config: &config::Config, args: Backup) -> Result<(), error::Error> {
+    let storage = std::path::absolute(&config.settings.storage)
+        .context("failed to resolve storage directory")?;
+    let output = std::path::absolute(&args.output)
+        .context("failed to resolve backup path")?;
+
+    if output.starts_with(&storage) {
+        return Err(error::Error::context("backup cannot be written to the storage directory"));
+    }
+
+    if output.exists() {
+        return Err(error::Error::context(format!(
+            "backup already exists: \"{}\"",
+            output.display()
+        )));
+    }
+
+    let dump = pg_command(&args.pg_dump, &config.settings.db);
+    let written = output.clone();
+
+    let result = tokio::task::spawn_blocking(move || write_backup(&written, &storage, dump))
+        .await
+        .context("failed to join backup task")?;
+
+    match result {
+        Ok(manifest) => {
+            println!(
+                "wrote backup with {} files to \"{}\"",
+                manifest.files,
+                output.display()
+            );
+
+            Ok(())
+        }
+        Err(err) => {
+            if output.exists() {
+                if let Err(err) = std::fs::remove_file(&output) {
+                    error::log_prefix_error("failed to remove incomplete backup", &err);
+                }
+            }
+
+            Err(err)
+        }
+    }
+}
+
+fn read_backup(
+    input: &Path,
+    storage: &Path,
+    mut restore: ProcessCommand,
+) -> Result<Manifest, error::Error> {
+    let file = File::open(input)
+        .context(format!("failed to open backup: \"{}\"", input.display()))?;
+    let mut archive = ZipArchive::new(BufReader::new(file))
+        .context("failed to open backup zip")?;
+
+    let manifest: Manifest = {
+        let reader = archive.by_name(MANIFEST)
+            .context("backup does not have a manifest")?;
+
+        serde_json::from_reader(reader)
+            .context("invalid backup manifest")?
+    };
+
+    if manifest.version > BACKUP_VERSION {
+        return Err(error::Error::context(format!(
+            "backup version {} is newer than the supported version {BACKUP_VERSION}",
+            manifest.version
+        )));
+    }
+
+    {
+        let mut dump = archive.by_name(&manifest.database)
+            .context("backup does not have a database dump")?;
+
+        let mut child = restore.arg("--clean")
+            .arg("--if-exists")
+            .arg("--no-owner")
+            .arg("--single-transaction")
+            .arg("--exit-on-error")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::inherit())
+            .stderr(Stdio::inherit())
+            .spawn()
+            .context("failed to run pg_restore")?;
+
+        let mut stdin = child.stdin.take()
+            .context("pg_restore stdin is not available")?;
+
+        let copied = std::io::copy(&mut dump, &mut stdin)
+            .context("failed to send database dump to pg_restore");
+
+        drop(stdin);
+
+        let status = child.wait()
+            .context("failed to wait for pg_restore")?;
+
+        copied?;
+
+        if !status.success() {
+            return Err(error::Error::context(format!("pg_restore exited with {status}")));
+        }
+    }
+
+    std::fs::create_dir_all(storage)
+        .context("failed to create storage directory")?;
+
+    for index in 0..archive.len() {
+        let mut file = archive.by_index(index)
+            .context("failed to read backup file")?;
+
+        if !file.is_file() || !file.name().starts_with(STORAGE_PREFIX) {
+            continue;
+        }
+
+        let Some(relative) = file.enclosed_name()
+            .and_then(|name| name.strip_prefix(STORAGE_PREFIX).ok().map(Path::to_path_buf)) else {
+            tracing::warn!("skipping backup file with an unsafe path: \"{}\"", file.name());
+
+            continue;
+        };
+
+        let path = storage.join(relative);
+
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)
+                .context("failed to create storage directory")?;
+        }
+
+        let mut output = File::create(&path)
+            .context(format!("failed to create file: \"{}\"", path.display()))?;
+
+        std::io::copy(&mut file, &mut output)
+            .context("failed to restore file from backup")?;
+    }
+
+    Ok(manifest)
+}
+
+/// restores the database and storage directory of a backup
+///
+/// this runs before the server state is created since creating the state
+/// adds the default workspace and admin to an empty database which would
+/// conflict with the restored data
+pub async fn restore_backup(config: &config::Config, args: &Restore) -> Result<(), error::Error> {
+    let input = args.input.clone();
+    let storage = config.settings.storage.clone();
+    let mut restore = pg_command(&args.pg_restore, &config.settings.db);
+
+    // pg_restore prints the dump as sql unless it is given a database
+    restore.arg(format!("--dbname={}", config.settings.db.dbname));
+
+    let manifest = tokio::task::spawn_blocking(move || read_backup(&input, &storage, restore))
+        .await
+        .context("failed to join restore task")??;
+
+    println!(
+        "restored backup from {} with {} files",
+        manifest.created,
+        manifest.files
+    );
+
+    Ok(())
+}
+
+/// checks the files of every file entry against their recorded hash
+async fn verify_files(state: &state::SharedState) -> Result<(), error::Error> {
+    let conn = state.db_conn().await?;
+
+    let journals: Vec<(JournalId, UserId)> = conn.query(
+        "select journals.id, journals.users_id from journals",
+        &[]
+    )
+        .await
+        .context("failed to retrieve journals")?
+        .into_iter()
+        .map(|row| (row.get(0), row.get(1)))
+        .collect();
+
+    let mut checked = 0;
+    let mut failed = 0;
+
+    for (journals_id, users_id) in journals {
+        let Some(journal) = Journal::retrieve_id(&conn, &journals_id, &users_id)
+            .await
+            .context("failed to retrieve journal")? else {
+            continue;
+        };
+
+        let journal_dir = state.storage().journal_dir(&journal);
+        let key = state.storage().journal_key(&conn, &journal).await?;
+
+        let files: Vec<(FileEntryId, Option<String>, bool)> = conn.query(
+            "\
+            select file_entries.id, \
+                   file_entries.hash, \
+                   file_entries.encrypted \
+            from file_entries \
+                join entries on \
+                    file_entries.entries_id = entries.id \
+            where entries.journals_id = $1",
+            &[&journal.id]
+        )
+            .await
+            .context("failed to retrieve journal files")?
+            .into_iter()
+            .map(|row| (row.get(0), row.get(1), row.get(2)))
+            .collect();
+
+        for (file_entries_id, hash, encrypted) in files {
+            // files without a hash were never uploaded
+            let Some(hash) = hash else {
+                continue;
+            };
+
+            let path = journal_dir.file_path(&file_entries_id);
+            let file_key = if encrypted {
+                match &key {
+                    Some(key) => Some(key.clone()),
+                    None => {
+                        println!("file {file_entries_id}: encrypted but encryption is not configured");
+                        failed += 1;
+
+                        continue;
+                    }
+                }
+            } else {
+                None
+            };
+
+            let result = tokio::task::spawn_blocking(move || -> Result<String, error::Error> {
+                let mut file = File::open(&path)
+                    .context("file is missing")?;
+                let mut hasher = blake3::Hasher::new();
+
+                if let Some(key) = file_key {
+                    key.decrypt_copy(&mut file, &mut hasher)
+                        .context("failed to decrypt file")?;
+                } else {
+                    std::io::copy(&mut file, &mut hasher)
+                        .context("failed to read file")?;
+                }
+
+                Ok(hasher.finalize().to_hex().to_string())
+            })
+                .await
+                .context("failed to join verify task")?;
+
+            checked += 1;
+
+            match result {
+                Ok(found) if found == hash => {}
+                Ok(_) => {
+                    println!("file {file_entries_id}: hash does not match");
+                    failed += 1;
+                }
+                Err(err) => {
+                    println!("file {file_entries_id}: {err}");
+                    failed += 1;
+                }
+            }
+        }
+    }
+
+    if failed > 0 {
+        return Err(error::Error::context(format!(
+            "{failed} of {checked} restored files failed verification"
+        )));
+    }
+
+    println!("verified {checked} files");
+
+    Ok(())
+}
+
+/// runs the given db command
+///
+/// the database and files of a restore are expected to have been restored
+/// by restore_backup before the state was created so only the files are
+/// verified
+pub async fn run(config: &config::Config, state: &state::SharedState, command: DbCommand) -> Result<(), error::Error> {
+    match command {
+        DbCommand::Backup(args) => backup(config, args).await,
+        DbCommand::Restore(args) => if args.skip_verify {
+            Ok(())
+        } else {
+            verify_files(state).await
+        },
+    }
+}
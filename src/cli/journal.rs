@@ -0,0 +1,228 @@
+//! commands for moving a journal between servers without the http api. ex:
+//!
+//! ```text
+//! TJ2 server.toml journal export --user alice --journal logs --output logs.zip
+//! TJ2 other.toml journal import --user alice --input logs.zip
+//! ```
+//!
+//! the archive is the same as a json export of the journal
+
+use std::path::PathBuf;
+
+use clap::{Args, Subcommand};
+
+use crate::db::GenericClient;
+use crate::error::{self, Context};
+use crate::journal::{export, CustomField, CustomFieldOptions, Journal, JournalCreateError};
+use crate::journal::export::ExportFormat;
+use crate::journal::import::{self, ImportSource, JournalImport, FieldNames, FileOptions};
+use crate::journal::upload::UploadPolicy;
+use crate::state;
+use crate::user::User;
+use crate::workspace::Workspace;
+
+#[derive(Debug, Subcommand)]
+pub enum JournalCommand {
+    /// writes the entries, files, and custom fields of a journal to a zip
+    Export(ExportJournal),
+
+    /// creates a new journal from a zip written by export
+    Import(ImportJournal),
+}
+
+#[derive(Debug, Args)]
+pub struct ExportJournal {
+    /// the username of the owner of the journal
+    #[arg(long)]
+    user: String,
+
+    /// the name of the journal to export
+    #[arg(long)]
+    journal: String,
+
+    /// the path of the zip to create
+    #[arg(long)]
+    output: PathBuf,
+}
+
+#[derive(Debug, Args)]
+pub struct ImportJournal {
+    /// the username of the user that will own the journal
+    #[arg(long)]
+    user: String,
+
+    /// the path of the zip to import
+    #[arg(long)]
+    input: PathBuf,
+
+    /// the name of the new journal. defaults to the name in the archive
+    #[arg(long)]
+    name: Option<String>,
+}
+
+/// runs the given journal command
+pub async fn run(state: &state::SharedState, command: JournalCommand) -> Result<(), error::Error> {
+    match command {
+        JournalCommand::Export(args) => export_journal(state, args).await,
+        JournalCommand::Import(args) => import_journal(state, args).await,
+    }
+}
+
+async fn retrieve_user(conn: &impl GenericClient, username: &str) -> Result<User, error::Error> {
+    User::retrieve_username(conn, username)
+        .await
+        .context("failed to retrieve user")?
+        .context(format!("user \"{username}\" was not found"))
+}
+
+async fn export_journal(state: &state::SharedState, args: ExportJournal) -> Result<(), error::Error> {
+    if tokio::fs::try_exists(&args.output).await.unwrap_or(true) {
+        return Err(error::Error::context(format!(
+            "output file already exists: \"{}\"", args.output.display()
+        )));
+    }
+
+    let conn = state.db_conn().await?;
+    let user = retrieve_user(&conn, &args.user).await?;
+
+    let journal = Journal::retrieve_name(&conn, &user.id, &args.journal)
+        .await
+        .context("failed to retrieve journal")?
+        .context(format!("journal \"{}\" was not found for user \"{}\"", args.journal, args.user))?;
+
+    if journal.e2e {
+        println!("journal is end-to-end encrypted. entries will be exported as ciphertext and cannot be imported");
+    }
+
+    let journal_dir = state.storage().journal_dir(&journal);
+    let key = state.storage().journal_key(&conn, &journal).await?;
+
+    let result = export::write_archive(
+        &conn,
+        args.output.clone(),
+        &user.id,
+        ExportFormat::Json,
+        &journal,
+        &journal_dir,
+        key.as_ref(),
+    ).await;
+
+    if let Err(err) = result {
+        if let Err(err) = tokio::fs::remove_file(&args.output).await {
+            error::log_prefix_error("failed to remove partial export", &err);
+        }
+
+        return Err(err);
+    }
+
+    println!("exported \"{}\" to \"{}\"", journal.name, args.output.display());
+
+    Ok(())
+}
+
+async fn import_journal(state: &state::SharedState, args: ImportJournal) -> Result<(), error::Error> {
+    let path = args.input.clone();
+    let (header, fields) = tokio::task::spawn_blocking(move || import::archive::read_details(path))
+        .await
+        .context("failed to join archive reader")??;
+
+    let name = args.name.unwrap_or(header.name);
+
+    let mut conn = state.db_conn().await?;
+    let transaction = conn.transaction()
+        .await
+        .context("failed to create transaction")?;
+
+    let user = retrieve_user(&transaction, &args.user).await?;
+
+    let workspace = Workspace::retrieve_default(&transaction)
+        .await
+        .context("failed to retrieve default workspace")?
+        .context("default workspace was not found")?;
+
+    let mut options = Journal::create_options(workspace.id, user.id, name.clone());
+
+    if let Some(description) = header.description {
+        options = options.description(description);
+    }
+
+    let journal = match Journal::create(&transaction, options).await {
+        Ok(journal) => journal,
+        Err(JournalCreateError::NameExists) => return Err(error::Error::context(format!(
+            "journal \"{name}\" already exists for user \"{}\"", args.user
+        ))),
+        Err(err) => return Err(error::Error::context_source(
+            "failed to create journal",
+            err
+        )),
+    };
+
+    for field in fields {
+        let mut options = CustomFieldOptions::new(journal.id, field.name, field.config);
+        options.order = field.order;
+        options.description = field.description;
+
+        CustomField::create_field(&transaction, options)
+            .await
+            .context("failed to create custom field")?;
+    }
+
+    let journal_dir = state.storage().journal_dir(&journal);
+
+    journal_dir.create()
+        .await
+        .context("failed to create journal directory")?;
+
+    let mut import = JournalImport::create(&transaction, &journal.id, &user.id, ImportSource::Archive)
+        .await
+        .context("failed to create journal import")?;
+
+    transaction.commit()
+        .await
+        .context("failed to commit transaction")?;
+
+    let key = state.storage().journal_key(&conn, &journal).await?;
+    let policy = UploadPolicy::retrieve(&conn, state.upload(), &journal.id)
+        .await
+        .context("failed to retrieve journal upload policy")?;
+    let files = FileOptions {
+        journal_dir: &journal_dir,
+        key: key.as_ref(),
+        storage: state.storage(),
+        policy: &policy,
+        scan: state.scan(),
+    };
+
+    let result = import.run(
+        &mut conn,
+        &journal,
+        &FieldNames::from_state(state),
+        &files,
+        args.input,
+    ).await;
+
+    let updated = match &result {
+        Ok(()) => import.mark_completed(&conn).await,
+        Err(err) => import.mark_failed(&conn, err.to_string()).await,
+    };
+
+    if let Err(err) = updated {
+        error::log_prefix_error("failed to update journal import", &err);
+    }
+
+    result?;
+
+    for warning in &import.warnings {
+        println!("warning: {warning}");
+    }
+
+    println!(
+        "imported {} of {} entries and {} files into \"{}\"",
+        import.imported,
+        import.processed,
+        import.files,
+        journal.name
+    );
+
+    Ok(())
+}
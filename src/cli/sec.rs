@@ -0,0 +1,289 @@
+//! commands for managing the master key used to encrypt journal files. ex:
+//!
+//! ```text
+//! TJ2 server.toml sec keys generate --output master.key
+//! TJ2 server.toml sec keys inspect
+//! TJ2 server.toml sec keys backup --output master.key.bak
+//! TJ2 server.toml sec keys rotate --output master-2.key
+//! TJ2 server.toml sec keys rewrap --old-key master.key --new-key master-2.key
+//! ```
+//!
+//! the files of a journal are encrypted with the key of the journal and only
+//! the journal keys are wrapped by the master key. rotating the master key
+//! re-wraps the journal keys in a single transaction so the files on disk are
+//! left as is. once a rotate is done the "encryption.key_file" of the config
+//! needs to point to the new key before the server is started again
+
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use clap::{Args, Subcommand};
+
+use crate::config;
+use crate::error::{self, Context};
+use crate::sec::encryption::{self, MasterKey};
+use crate::state;
+
+#[derive(Debug, Subcommand)]
+pub enum SecCommand {
+    /// manages the master key
+    #[command(subcommand)]
+    Keys(KeysCommand),
+}
+
+#[derive(Debug, Subcommand)]
+pub enum KeysCommand {
+    /// writes a new master key to a file
+    Generate(Generate),
+
+    /// shows the fingerprint of a master key and the journal keys it can
+    /// unwrap
+    Inspect(Inspect),
+
+    /// copies the configured master key to another file
+    Backup(KeyBackup),
+
+    /// generates a new master key and re-wraps the journal keys with it
+    Rotate(Rotate),
+
+    /// re-wraps the journal keys from one master key to another
+    Rewrap(Rewrap),
+}
+
+#[derive(Debug, Args)]
+pub struct Generate {
+    /// the file to write the key to. an existing file will not be
+    /// overwritten
+    #[arg(long)]
+    output: PathBuf,
+}
+
+#[derive(Debug, Args)]
+pub struct Inspect {
+    /// the key file to inspect. defaults to the configured key file
+    #[arg(long)]
+    key_file: Option<PathBuf>,
+}
+
+#[derive(Debug, Args)]
+pub struct KeyBackup {
+    /// the file to copy the key to. an existing file will not be
+    /// overwritten
+    #[arg(long)]
+    output: PathBuf,
+}
+
+#[derive(Debug, Args)]
+pub struct Rotate {
+    /// the file to write the new key to. an existing file will not be
+    /// overwritten
+    #[arg(long)]
+    output: PathBuf,
+
+    /// the key file that currently wraps the journal keys. defaults to the
+    /// configured key file
+    #[arg(long)]
+    old_key: Option<PathBuf>,
+}
+
+#[derive(Debug, Args)]
+pub struct Rewrap {
+    /// the key file that currently wraps the journal keys. defaults to the
+    /// configured key file
+    #[arg(long)]
+    old_key: Option<PathBuf>,
+
+    /// the key file to wrap the journal keys with
+    #[arg(long)]
+    new_key: PathBuf,
+}
+
+/// retrieves the given key file or the configured key file if not given
+fn key_file<'a>(config: &'a config::Config, given: Option<&'a PathBuf>) -> Result<&'a Path, error::Error> {
+    if let Some(path) = given {
+        return Ok(path);
+    }
+
+    config.settings.encryption.as_ref()
+        .map(|encryption| encryption.key_file.as_path())
+        .context("encryption is not configured and no key file was given")
+}
+
+/// writes the contents to a new file that only the owner can read
+fn write_key_file(path: &Path, contents: &str) -> Result<(), error::Error> {
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+
+        options.mode(0o600);
+    }
+
+    let mut file = options.open(path)
+        .context(format!("failed to create key file: \"{}\"", path.display()))?;
+
+    writeln!(file, "{contents}")
+        .context("failed to write key file")?;
+
+    file.sync_all()
+        .context("failed to sync key file")
+}
+
+fn generate(args: &Generate) -> Result<(), error::Error> {
+    let (key, encoded) = MasterKey::generate()
+        .context("failed to generate master key")?;
+
+    write_key_file(&args.output, &encoded)?;
+
+    println!("wrote master key {} to \"{}\"", key.fingerprint(), args.output.display());
+
+    Ok(())
+}
+
+fn backup(config: &config::Config, args: &KeyBackup) -> Result<(), error::Error> {
+    let path = key_file(config, None)?;
+    let key = MasterKey::load(path)?;
+    let contents = std::fs::read_to_string(path)
+        .context("failed to read master key file")?;
+
+    write_key_file(&args.output, contents.trim())?;
+
+    println!("copied master key {} to \"{}\"", key.fingerprint(), args.output.display());
+
+    Ok(())
+}
+
+/// runs the sec commands that do not need the server state. these only work
+/// with key files so they can be run before encryption is configured
+///
+/// returns true if the command is done
+pub fn prepare(config: &config::Config, command: &SecCommand) -> Result<bool, error::Error> {
+    match command {
+        SecCommand::Keys(KeysCommand::Generate(args)) => generate(args).map(|_| true),
+        SecCommand::Keys(KeysCommand::Backup(args)) => backup(config, args).map(|_| true),
+        _ => Ok(false),
+    }
+}
+
+async fn inspect(config: &config::Config, state: &state::SharedState, args: Inspect) -> Result<(), error::Error> {
+    let path = key_file(config, args.key_file.as_ref())?;
+    let key = MasterKey::load(path)?;
+
+    let conn = state.db_conn().await?;
+
+    let wrapped = encryption::retrieve_all_wrapped(&conn)
+        .await
+        .context("failed to retrieve journal keys")?;
+
+    let unwraps = wrapped.iter()
+        .filter(|(journals_id, wrapped)| key.can_unwrap(journals_id, wrapped))
+        .count();
+
+    println!("key file: \"{}\"", path.display());
+    println!("fingerprint: {}", key.fingerprint());
+    println!("journal keys: {}", wrapped.len());
+    println!("unwrapped by key: {unwraps}");
+
+    if unwraps != wrapped.len() {
+        println!("{} journal keys are wrapped by a different master key", wrapped.len() - unwraps);
+    }
+
+    Ok(())
+}
+
+/// re-wraps every journal key from the old key to the new key
+///
+/// keys that are already wrapped by the new key are left as is so that a
+/// rewrap can be run again with the same keys. if any key cannot be
+/// unwrapped by either key then no changes are made
+async fn rewrap_all(state: &state::SharedState, old: &MasterKey, new: &MasterKey) -> Result<(), error::Error> {
+    let mut conn = state.db_conn().await?;
+    let transaction = conn.transaction()
+        .await
+        .context("failed to create transaction")?;
+
+    let wrapped = encryption::retrieve_all_wrapped(&transaction)
+        .await
+        .context("failed to retrieve journal keys")?;
+    let total = wrapped.len();
+    let mut rewrapped = 0;
+    let mut skipped = 0;
+
+    for (index, (journals_id, wrapped)) in wrapped.into_iter().enumerate() {
+        if new.can_unwrap(&journals_id, &wrapped) {
+            println!("[{}/{total}] journal {journals_id}: already wrapped by the new key", index + 1);
+            skipped += 1;
+
+            continue;
+        }
+
+        let updated = old.rewrap(new, &journals_id, &wrapped)
+            .context(format!("failed to unwrap the key of journal {journals_id} with the old key"))?;
+
+        encryption::replace_wrapped(&transaction, &journals_id, &updated)
+            .await
+            .context("failed to update journal key")?;
+
+        println!("[{}/{total}] journal {journals_id}: re-wrapped", index + 1);
+        rewrapped += 1;
+    }
+
+    transaction.commit()
+        .await
+        .context("failed to commit transaction")?;
+
+    println!(
+        "re-wrapped {rewrapped} journal keys from {} to {}. {skipped} were already wrapped by the new key",
+        old.fingerprint(),
+        new.fingerprint()
+    );
+
+    Ok(())
+}
+
+async fn rotate(config: &config::Config, state: &state::SharedState, args: Rotate) -> Result<(), error::Error> {
+    let old = MasterKey::load(key_file(config, args.old_key.as_ref())?)?;
+    let (new, encoded) = MasterKey::generate()
+        .context("failed to generate master key")?;
+
+    // the new key is written before any journal keys are changed so that
+    // it cannot be lost if the rewrap succeeds
+    write_key_file(&args.output, &encoded)?;
+
+    println!("wrote master key {} to \"{}\"", new.fingerprint(), args.output.display());
+
+    rewrap_all(state, &old, &new).await?;
+
+    println!("set encryption.key_file to \"{}\" before starting the server", args.output.display());
+
+    Ok(())
+}
+
+async fn rewrap(config: &config::Config, state: &state::SharedState, args: Rewrap) -> Result<(), error::Error> {
+    let old = MasterKey::load(key_file(config, args.old_key.as_ref())?)?;
+    let new = MasterKey::load(&args.new_key)?;
+
+    if old.fingerprint() == new.fingerprint() {
+        return Err(error::Error::context("the old and new keys are the same"));
+    }
+
+    rewrap_all(state, &old, &new).await
+}
+
+/// runs the given sec command
+///
+/// generate and backup are done by prepare before the state is created
+pub async fn run(config: &config::Config, state: &state::SharedState, command: SecCommand) -> Result<(), error::Error> {
+    match command {
+        SecCommand::Keys(command) => match command {
+            KeysCommand::Generate(_) |
+            KeysCommand::Backup(_) => Ok(()),
+            KeysCommand::Inspect(args) => inspect(config, state, args).await,
+            KeysCommand::Rotate(args) => rotate(config, state, args).await,
+            KeysCommand::Rewrap(args) => rewrap(config, state, args).await,
+        },
+    }
+}
@@ -0,0 +1,305 @@
+//! commands for managing user accounts. ex:
+//!
+//! ```text
+//! TJ2 server.toml user create alice --role admin --password-file -
+//! TJ2 server.toml user disable alice
+//! ```
+
+use std::path::PathBuf;
+
+use clap::{Args, Subcommand};
+
+use crate::db::GenericClient;
+use crate::error::{self, Context};
+use crate::sec::authz::Role;
+use crate::sec::password;
+use crate::state;
+use crate::user::User;
+use crate::workspace::{Workspace, WorkspaceUser};
+
+use super::read_contents;
+
+#[derive(Debug, Subcommand)]
+pub enum UserCommand {
+    /// creates a new user in the default workspace
+    Create(CreateUser),
+
+    /// sets the password of a user
+    Password(SetPassword),
+
+    /// disables a user so that they can no longer log in. any active
+    /// sessions of the user are removed
+    Disable(Username),
+
+    /// enables a previously disabled user
+    Enable(Username),
+
+    /// assigns a role to a user
+    AddRole(UserRole),
+
+    /// removes a role from a user
+    RemoveRole(UserRole),
+}
+
+#[derive(Debug, Args)]
+pub struct Username {
+    /// the username of the user
+    username: String,
+}
+
+#[derive(Debug, Args)]
+pub struct CreateUser {
+    /// the username of the new user
+    username: String,
+
+    /// the file to read the password from. "-" will read from stdin. a
+    /// temporary password is generated and printed if not specified
+    #[arg(long)]
+    password_file: Option<PathBuf>,
+
+    /// the name of a role to assign to the user. can be specified multiple
+    /// times
+    #[arg(long = "role")]
+    roles: Vec<String>,
+
+    /// makes the user an admin of the default workspace
+    #[arg(long)]
+    workspace_admin: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct SetPassword {
+    /// the username of the user
+    username: String,
+
+    /// the file to read the password from. "-" will read from stdin. a
+    /// temporary password is generated and printed if not specified
+    #[arg(long)]
+    password_file: Option<PathBuf>,
+}
+
+#[derive(Debug, Args)]
+pub struct UserRole {
+    /// the username of the user
+    username: String,
+
+    /// the name of the role
+    #[arg(long)]
+    role: String,
+}
+
+/// runs the given user command
+pub async fn run(state: &state::SharedState, command: UserCommand) -> Result<(), error::Error> {
+    match command {
+        UserCommand::Create(args) => create_user(state, args).await,
+        UserCommand::Password(args) => set_password(state, args).await,
+        UserCommand::Disable(args) => set_disabled(state, args, true).await,
+        UserCommand::Enable(args) => set_disabled(state, args, false).await,
+        UserCommand::AddRole(args) => add_role(state, args).await,
+        UserCommand::RemoveRole(args) => remove_role(state, args).await,
+    }
+}
+
+/// reads the password from the given file or generates a temporary one.
+/// the bool is true if the password was generated
+async fn get_password(path: Option<&PathBuf>) -> Result<(String, bool), error::Error> {
+    let Some(path) = path else {
+        return Ok((password::temporary(), true));
+    };
+
+    let given = read_contents(path).await?;
+    let given = given.trim_end_matches(['\r', '\n']);
+
+    if given.is_empty() {
+        return Err(error::Error::context("password is empty"));
+    }
+
+    Ok((given.to_owned(), false))
+}
+
+async fn hash_password(given: String) -> Result<String, error::Error> {
+    tokio::task::spawn_blocking(move || password::create(given))
+        .await
+        .context("failed to join password hash task")?
+        .context("failed to hash password")
+}
+
+async fn retrieve_user(conn: &impl GenericClient, username: &str) -> Result<User, error::Error> {
+    User::retrieve_username(conn, username)
+        .await
+        .context("failed to retrieve user")?
+        .context(format!("user \"{username}\" was not found"))
+}
+
+async fn retrieve_role(conn: &impl GenericClient, name: &str) -> Result<Role, error::Error> {
+    Role::retrieve_name(conn, name)
+        .await
+        .context("failed to retrieve role")?
+        .context(format!("role \"{name}\" was not found"))
+}
+
+async fn create_user(state: &state::SharedState, args: CreateUser) -> Result<(), error::Error> {
+    let username = args.username.trim();
+
+    if username.is_empty() {
+        return Err(error::Error::context("username is empty"));
+    }
+
+    let (given, generated) = get_password(args.password_file.as_ref()).await?;
+    let hash = hash_password(given.clone()).await?;
+
+    let mut conn = state.db_conn().await?;
+    let transaction = conn.transaction()
+        .await
+        .context("failed to create transaction")?;
+
+    let mut roles = Vec::with_capacity(args.roles.len());
+
+    for name in &args.roles {
+        roles.push(retrieve_role(&transaction, name).await?);
+    }
+
+    let user = User::create(&transaction, username, &hash, 0)
+        .await
+        .context("failed to create user")?
+        .context(format!("user \"{username}\" already exists"))?;
+
+    let workspace = Workspace::retrieve_default(&transaction)
+        .await
+        .context("failed to retrieve default workspace")?
+        .context("default workspace was not found")?;
+
+    WorkspaceUser::upsert(&transaction, &workspace.id, &user.id, args.workspace_admin)
+        .await
+        .context("failed to add user to workspace")?;
+
+    for role in &roles {
+        role.assign_user(&transaction, user.id)
+            .await
+            .context("failed to assign role to user")?;
+    }
+
+    transaction.commit()
+        .await
+        .context("failed to commit transaction")?;
+
+    println!("created user \"{}\" ({})", user.username, user.uid);
+
+    if generated {
+        println!("temporary password: {given}");
+    }
+
+    Ok(())
+}
+
+async fn set_password(state: &state::SharedState, args: SetPassword) -> Result<(), error::Error> {
+    let (given, generated) = get_password(args.password_file.as_ref()).await?;
+    let hash = hash_password(given.clone()).await?;
+
+    let conn = state.db_conn().await?;
+    let mut user = retrieve_user(&conn, &args.username).await?;
+
+    user.password = hash;
+    user.version = 0;
+
+    user.update(&conn)
+        .await
+        .context("failed to update user")?;
+
+    println!("updated password for \"{}\"", user.username);
+
+    if generated {
+        println!("temporary password: {given}");
+    }
+
+    Ok(())
+}
+
+async fn set_disabled(state: &state::SharedState, args: Username, disabled: bool) -> Result<(), error::Error> {
+    let mut conn = state.db_conn().await?;
+    let transaction = conn.transaction()
+        .await
+        .context("failed to create transaction")?;
+
+    let mut user = retrieve_user(&transaction, &args.username).await?;
+
+    if user.disabled.is_some() == disabled {
+        println!(
+            "user \"{}\" is already {}",
+            user.username,
+            if disabled { "disabled" } else { "enabled" }
+        );
+
+        return Ok(());
+    }
+
+    user.set_disabled(&transaction, disabled)
+        .await
+        .context("failed to update user")?;
+
+    if disabled {
+        transaction.execute(
+            "delete from authn_sessions where users_id = $1",
+            &[&user.id]
+        )
+            .await
+            .context("failed to delete user sessions")?;
+    }
+
+    transaction.commit()
+        .await
+        .context("failed to commit transaction")?;
+
+    println!(
+        "{} user \"{}\"",
+        if disabled { "disabled" } else { "enabled" },
+        user.username
+    );
+
+    Ok(())
+}
+
+async fn add_role(state: &state::SharedState, args: UserRole) -> Result<(), error::Error> {
+    let conn = state.db_conn().await?;
+    let user = retrieve_user(&conn, &args.username).await?;
+    let role = retrieve_role(&conn, &args.role).await?;
+
+    let added = conn.execute(
+        "\
+        insert into user_roles (users_id, role_id, added) \
+        values ($1, $2, now()) \
+        on conflict (users_id, role_id) do nothing",
+        &[&user.id, &role.id]
+    )
+        .await
+        .context("failed to assign role to user")?;
+
+    if added == 0 {
+        println!("user \"{}\" already has role \"{}\"", user.username, role.name);
+    } else {
+        println!("assigned role \"{}\" to \"{}\"", role.name, user.username);
+    }
+
+    Ok(())
+}
+
+async fn remove_role(state: &state::SharedState, args: UserRole) -> Result<(), error::Error> {
+    let conn = state.db_conn().await?;
+    let user = retrieve_user(&conn, &args.username).await?;
+    let role = retrieve_role(&conn, &args.role).await?;
+
+    let removed = conn.execute(
+        "delete from user_roles where users_id = $1 and role_id = $2",
+        &[&user.id, &role.id]
+    )
+        .await
+        .context("failed to remove role from user")?;
+
+    if removed == 0 {
+        println!("user \"{}\" does not have role \"{}\"", user.username, role.name);
+    } else {
+        println!("removed role \"{}\" from \"{}\"", role.name, user.username);
+    }
+
+    Ok(())
+}
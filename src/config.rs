@@ -5,13 +5,17 @@ use std::collections::HashMap;
 use std::default::Default;
 use std::io::Read;
 use std::net::{SocketAddr, IpAddr, Ipv6Addr};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
 
 use clap::{Parser, ValueEnum};
+use ipnet::IpNet;
 use serde::Deserialize;
 
+use crate::cli::Command;
 use crate::error::{self, Context};
+use crate::jobs::JobKind;
+use crate::jobs::schedule::parse_schedule;
 use crate::path::{metadata, normalize_from};
 
 pub mod meta;
@@ -23,6 +27,7 @@ use meta::{
     Quote,
     get_cwd,
     check_path,
+    read_secret,
     sanitize_url_key,
 };
 
@@ -49,7 +54,16 @@ pub struct CliArgs {
     /// attempts to generate test data for the server to use for testing
     /// purposes
     #[arg(long)]
-    pub gen_test_data: bool
+    pub gen_test_data: bool,
+
+    /// prints the telemetry report that would be sent and exits. the report
+    /// is not sent
+    #[arg(long)]
+    pub preview_telemetry: bool,
+
+    /// runs a command instead of starting the server
+    #[command(subcommand)]
+    pub command: Option<Command>,
 }
 
 /// a stack struct used when creating the Config struct
@@ -63,6 +77,8 @@ struct ConfigStack {
 /// the final server configuration created from the loaded config file
 #[derive(Debug)]
 pub struct Config {
+    /// the resolved path of the config file that was loaded
+    pub path: PathBuf,
     pub settings: Settings,
 }
 
@@ -75,8 +91,15 @@ impl Config {
     /// overwrite the settings of the other and each file can also specify
     /// a list of files to preload before the current file.
     pub fn from_args(args: &CliArgs) -> Result<Self, error::Error> {
-        let resolved = normalize_from(get_cwd()?, args.config_path.clone());
+        Self::from_path(&args.config_path)
+    }
+
+    /// loads the config file at the given path along with the files that it
+    /// preloads
+    pub fn from_path(path: &Path) -> Result<Self, error::Error> {
+        let resolved = normalize_from(get_cwd()?, path.to_path_buf());
         let mut shape = Self::load_file(&resolved)?;
+        let config_path = resolved.clone();
 
         let mut settings = Settings::try_default()?;
         let dot = DotPath::new(&"settings");
@@ -157,7 +180,14 @@ impl Config {
             ));
         }
 
+        if settings.telemetry.enabled && settings.telemetry.endpoint.is_none() {
+            return Err(error::Error::context(
+                "settings.telemetry.endpoint must be specified when telemetry is enabled"
+            ));
+        }
+
         Ok(Config {
+            path: config_path,
             settings
         })
     }
@@ -206,12 +236,29 @@ pub struct SettingsShape {
     preload: Option<Vec<PathBuf>>,
     data: Option<PathBuf>,
     storage: Option<PathBuf>,
+    storage_reserve: Option<u64>,
+    log_filter: Option<String>,
     thread_pool: Option<usize>,
     blocking_pool: Option<usize>,
     listeners: Option<Vec<ListenerShape>>,
+    health: Option<HealthShape>,
     assets: Option<AssetsShape>,
     templates: Option<TemplatesShape>,
     db: Option<DbShape>,
+    network: Option<NetworkShape>,
+    api: Option<ApiShape>,
+    cors: Option<CorsShape>,
+    security_headers: Option<SecurityHeadersShape>,
+    webauthn: Option<WebauthnShape>,
+    encryption: Option<EncryptionShape>,
+    smtp: Option<SmtpShape>,
+    telemetry: Option<TelemetryShape>,
+    extraction: Option<ExtractionShape>,
+    transcription: Option<TranscriptionShape>,
+    scan: Option<ScanShape>,
+    upload: Option<UploadShape>,
+    weather: Option<WeatherShape>,
+    jobs: Option<HashMap<String, JobShape>>,
 }
 
 /// the root settings that are avaible for the server to use
@@ -229,6 +276,18 @@ pub struct Settings {
     /// defaults to "{CWD}/storage"
     pub storage: PathBuf,
 
+    /// the number of bytes that must stay free in the storage directory.
+    /// writes that would go below this amount are rejected
+    ///
+    /// defaults to 512MiB
+    pub storage_reserve: u64,
+
+    /// directives added to the tracing filter. uses the same format as
+    /// RUST_LOG and is applied again when the config is reloaded
+    ///
+    /// defaults to None
+    pub log_filter: Option<String>,
+
     /// the number of asynchronous threads that tokio will use for the thread
     /// pool.
     ///
@@ -244,6 +303,9 @@ pub struct Settings {
     /// the list of available listeners for the server to use
     pub listeners: Vec<Listener>,
 
+    /// options for the health check endpoints
+    pub health: Health,
+
     /// the list of available public assets for the server to respond with
     pub assets: Assets,
 
@@ -252,6 +314,58 @@ pub struct Settings {
 
     /// configuration information for connecting to the database
     pub db: Db,
+
+    /// network options for proxies and access control
+    pub network: Network,
+
+    /// options for how the api responds to clients
+    pub api: Api,
+
+    /// options for cross-origin requests from browser clients. cross-origin
+    /// requests are not allowed if not specified
+    pub cors: Option<Cors>,
+
+    /// the security headers added to every response
+    pub security_headers: SecurityHeaders,
+
+    /// options for passkey logins. passkeys are disabled if not specified
+    pub webauthn: Option<Webauthn>,
+
+    /// options for encrypting journal files. files are stored unencrypted if
+    /// not specified
+    pub encryption: Option<Encryption>,
+
+    /// options for sending emails. emails are written to the server logs if
+    /// not specified
+    pub smtp: Option<Smtp>,
+
+    /// options for the opt-in usage telemetry
+    pub telemetry: Telemetry,
+
+    /// options for extracting searchable text from file entries
+    pub extraction: Extraction,
+
+    /// options for transcribing audio file entries. audio is not
+    /// transcribed if not specified
+    pub transcription: Option<Transcription>,
+
+    /// options for scanning uploaded files for malware. files are not
+    /// scanned if not specified
+    pub scan: Option<Scan>,
+
+    /// the default limits of uploaded files. journals can override them
+    pub upload: Upload,
+
+    /// options for filling in the weather of new entries. the weather is
+    /// not retrieved if not specified
+    pub weather: Option<Weather>,
+
+    /// the schedules of the background jobs keyed by the name of the job.
+    /// these are applied to the jobs table every time the server starts
+    ///
+    /// when loading config files, the settings for a job are merged with the
+    /// settings from previous files
+    pub jobs: HashMap<JobKind, Job>,
 }
 
 impl Settings {
@@ -269,6 +383,20 @@ impl Settings {
             check_path(&self.storage, src, dot.push(&"data"), false)?;
         }
 
+        if let Some(storage_reserve) = settings.storage_reserve {
+            self.storage_reserve = storage_reserve;
+        }
+
+        if let Some(log_filter) = settings.log_filter {
+            if let Err(err) = tracing_subscriber::EnvFilter::try_new(&log_filter) {
+                return Err(error::Error::context(format!(
+                    "{dot}.log_filter is invalid: {err} in {src}"
+                )));
+            }
+
+            self.log_filter = Some(log_filter).filter(|value| !value.trim().is_empty());
+        }
+
         if let Some(thread_pool) = settings.thread_pool {
             if thread_pool == 0 {
                 return Err(error::Error::context(format!(
@@ -306,6 +434,10 @@ impl Settings {
             }
         }
 
+        if let Some(health) = settings.health {
+            self.health.merge(src, dot.push(&"health"), health)?;
+        }
+
         if let Some(assets) = settings.assets {
             self.assets.merge(src, dot.push(&"assets"), assets)?;
         }
@@ -318,10 +450,98 @@ impl Settings {
             self.db.merge(src, dot.push(&"db"), db)?;
         }
 
+        if let Some(network) = settings.network {
+            self.network.merge(src, dot.push(&"network"), network)?;
+        }
+
+        if let Some(api) = settings.api {
+            self.api.merge(src, dot.push(&"api"), api)?;
+        }
+
+        if let Some(cors) = settings.cors {
+            self.cors = Some(Cors::from_shape(src, dot.push(&"cors"), cors)?);
+        }
+
+        if let Some(security_headers) = settings.security_headers {
+            self.security_headers.merge(src, dot.push(&"security_headers"), security_headers)?;
+        }
+
+        if let Some(webauthn) = settings.webauthn {
+            self.webauthn = Some(Webauthn::from_shape(src, dot.push(&"webauthn"), webauthn)?);
+        }
+
+        if let Some(encryption) = settings.encryption {
+            self.encryption = Some(Encryption::from_shape(src, dot.push(&"encryption"), encryption)?);
+        }
+
+        if let Some(smtp) = settings.smtp {
+            self.smtp = Some(Smtp::from_shape(src, dot.push(&"smtp"), smtp)?);
+        }
+
+        if let Some(telemetry) = settings.telemetry {
+            self.telemetry.merge(src, dot.push(&"telemetry"), telemetry)?;
+        }
+
+        if let Some(extraction) = settings.extraction {
+            self.extraction.merge(src, dot.push(&"extraction"), extraction)?;
+        }
+
+        if let Some(transcription) = settings.transcription {
+            self.transcription = Some(Transcription::from_shape(src, dot.push(&"transcription"), transcription)?);
+        }
+
+        if let Some(scan) = settings.scan {
+            self.scan = Some(Scan::from_shape(src, dot.push(&"scan"), scan)?);
+        }
+
+        if let Some(upload) = settings.upload {
+            self.upload.merge(src, dot.push(&"upload"), upload)?;
+        }
+
+        if let Some(weather) = settings.weather {
+            self.weather = Some(Weather::from_shape(src, dot.push(&"weather"), weather)?);
+        }
+
+        if let Some(jobs) = settings.jobs {
+            let jobs_dot = dot.push(&"jobs");
+
+            for (name, job) in jobs {
+                let name_quote = Quote(&name);
+
+                let Ok(kind) = JobKind::from_str(&name) else {
+                    return Err(error::Error::context(format!(
+                        "{jobs_dot} \"{name}\" is not a known job. file: {src}"
+                    )));
+                };
+
+                self.jobs.entry(kind)
+                    .or_default()
+                    .merge(src, jobs_dot.push(&name_quote), job)?;
+            }
+        }
+
         Ok(())
     }
 }
 
+impl Settings {
+    /// checks if the "strict-transport-security" header is sent. defaults to
+    /// true when any of the listeners use tls
+    pub fn use_hsts(&self) -> bool {
+        if let Some(hsts) = self.security_headers.hsts {
+            return hsts;
+        }
+
+        #[cfg(feature = "rustls")] {
+            self.listeners.iter().any(|listener| listener.tls.is_some())
+        }
+
+        #[cfg(not(feature = "rustls"))] {
+            false
+        }
+    }
+}
+
 impl TryDefault for Settings {
     type Error = error::Error;
 
@@ -329,12 +549,29 @@ impl TryDefault for Settings {
         Ok(Settings {
             data: get_cwd()?.join("data"),
             storage: get_cwd()?.join("storage"),
+            storage_reserve: 512 * 1024 * 1024,
+            log_filter: None,
             thread_pool: 1,
             blocking_pool: 1,
             listeners: Vec::new(),
+            health: Health::default(),
             assets: Assets::default(),
             templates: Templates::try_default()?,
-            db: Db::default()
+            db: Db::default(),
+            network: Network::default(),
+            api: Api::default(),
+            cors: None,
+            security_headers: SecurityHeaders::default(),
+            webauthn: None,
+            encryption: None,
+            smtp: None,
+            telemetry: Telemetry::default(),
+            extraction: Extraction::default(),
+            transcription: None,
+            scan: None,
+            upload: Upload::default(),
+            weather: None,
+            jobs: HashMap::new(),
         })
     }
 }
@@ -359,18 +596,23 @@ pub struct Listener {
     pub tls: Option<tls::Tls>,
 }
 
+/// parses a socket address or an ip address that will use port 8080
+fn parse_addr(src: &SrcFile<'_>, dot: &DotPath<'_>, addr: &str) -> Result<SocketAddr, error::Error> {
+    match SocketAddr::from_str(addr) {
+        Ok(valid) => Ok(valid),
+        Err(_) => match IpAddr::from_str(addr) {
+            Ok(valid) => Ok(SocketAddr::from((valid, 8080))),
+            Err(_) => Err(error::Error::context(format!(
+                "{dot}.addr invalid: \"{addr}\" file: {src}"
+            )))
+        }
+    }
+}
+
 impl Listener {
     /// merges the given ListenerShape into the final Listener struct
     fn merge(&mut self, src: &SrcFile<'_>, dot: DotPath<'_>, listener: ListenerShape) -> Result<(), error::Error> {
-        self.addr = match SocketAddr::from_str(&listener.addr) {
-            Ok(valid) => valid,
-            Err(_) => match IpAddr::from_str(&listener.addr) {
-                Ok(valid) => SocketAddr::from((valid, 8080)),
-                Err(_) => return Err(error::Error::context(format!(
-                    "{dot}.addr invalid: \"{}\" file: {src}", listener.addr
-                )))
-            }
-        };
+        self.addr = parse_addr(src, &dot, &listener.addr)?;
 
         #[cfg(feature = "rustls")] {
             if let Some(tls) = listener.tls {
@@ -396,6 +638,34 @@ impl Default for Listener {
     }
 }
 
+/// the structure of a health config
+#[derive(Debug, Deserialize)]
+pub struct HealthShape {
+    addr: Option<String>,
+}
+
+/// the options for the "/healthz" and "/readyz" endpoints
+#[derive(Debug, Default)]
+pub struct Health {
+    /// an additional plain http listener that only responds to the health
+    /// endpoints. the endpoints are always available on the server
+    /// listeners
+    ///
+    /// defaults to None
+    pub addr: Option<SocketAddr>,
+}
+
+impl Health {
+    /// merges the given HealthShape into the final Health struct
+    fn merge(&mut self, src: &SrcFile<'_>, dot: DotPath<'_>, health: HealthShape) -> Result<(), error::Error> {
+        if let Some(addr) = health.addr {
+            self.addr = Some(parse_addr(src, &dot, &addr)?);
+        }
+
+        Ok(())
+    }
+}
+
 #[cfg(feature = "rustls")]
 pub mod tls {
     use std::path::PathBuf;
@@ -551,9 +821,33 @@ impl TryDefault for Templates {
 pub struct DbShape {
     user: Option<String>,
     password: Option<String>,
+    password_file: Option<PathBuf>,
     host: Option<String>,
     port: Option<u16>,
     dbname: Option<String>,
+    max_connections: Option<usize>,
+    min_connections: Option<usize>,
+    connect_timeout: Option<u64>,
+    idle_timeout: Option<u64>,
+    statement_timeout: Option<u64>,
+    ssl_mode: Option<DbSslMode>,
+    root_cert: Option<PathBuf>,
+}
+
+/// how tls is used when connecting to the database
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DbSslMode {
+    /// connections are never encrypted
+    #[default]
+    Disable,
+
+    /// tls is used if the server supports it
+    Prefer,
+
+    /// connections fail if tls is not available. the certificate of the
+    /// server is always verified
+    Require,
 }
 
 /// the available options when connecting to the database
@@ -564,7 +858,8 @@ pub struct Db {
     /// defaults to "postgres"
     pub user: String,
 
-    /// the optional password for the user
+    /// the optional password for the user. "password_file" can be used to
+    /// read it from a file instead
     ///
     /// defaults to None
     pub password: Option<String>,
@@ -583,17 +878,61 @@ pub struct Db {
     ///
     /// defaults to "tj2"
     pub dbname: String,
+
+    /// the max number of connections in the pool
+    ///
+    /// defaults to 4
+    pub max_connections: usize,
+
+    /// the number of connections that are opened on start and kept open
+    /// when idle connections are closed
+    ///
+    /// defaults to 0
+    pub min_connections: usize,
+
+    /// the seconds to wait when opening a new connection
+    ///
+    /// defaults to 10
+    pub connect_timeout: u64,
+
+    /// the seconds a connection can be unused before it is closed
+    ///
+    /// defaults to None
+    pub idle_timeout: Option<u64>,
+
+    /// the seconds a statement can run before the database cancels it
+    ///
+    /// defaults to None
+    pub statement_timeout: Option<u64>,
+
+    /// how tls is used when connecting
+    ///
+    /// defaults to "disable"
+    pub ssl_mode: DbSslMode,
+
+    /// a pem file of root certificates to verify the server with. the
+    /// system root certificates are used if not specified
+    ///
+    /// defaults to None
+    pub root_cert: Option<PathBuf>,
 }
 
 impl Db {
     /// merges a given DbShape into a Db structure
-    fn merge(&mut self, _src: &SrcFile<'_>, _dot: DotPath<'_>, db: DbShape) -> Result<(), error::Error> {
+    fn merge(&mut self, src: &SrcFile<'_>, dot: DotPath<'_>, db: DbShape) -> Result<(), error::Error> {
         if let Some(user) = db.user {
             self.user = user;
         }
 
-        if let Some(password) = db.password {
-            self.password = Some(password);
+        match (db.password, db.password_file) {
+            (Some(_), Some(_)) => return Err(error::Error::context(format!(
+                "{dot}.password and {dot}.password_file cannot both be specified in {src}"
+            ))),
+            (Some(password), None) => self.password = Some(password),
+            (None, Some(password_file)) => {
+                self.password = Some(read_secret(password_file, src, dot.push(&"password_file"))?);
+            }
+            (None, None) => {}
         }
 
         if let Some(host) = db.host {
@@ -608,6 +947,56 @@ impl Db {
             self.dbname = dbname;
         }
 
+        if let Some(max_connections) = db.max_connections {
+            if max_connections == 0 {
+                return Err(error::Error::context(format!(
+                    "{dot}.max_connections amount is 0 in {src}"
+                )));
+            }
+
+            self.max_connections = max_connections;
+        }
+
+        if let Some(min_connections) = db.min_connections {
+            self.min_connections = min_connections;
+        }
+
+        if self.min_connections > self.max_connections {
+            return Err(error::Error::context(format!(
+                "{dot}.min_connections is greater than max_connections in {src}"
+            )));
+        }
+
+        if let Some(connect_timeout) = db.connect_timeout {
+            if connect_timeout == 0 {
+                return Err(error::Error::context(format!(
+                    "{dot}.connect_timeout is 0 in {src}"
+                )));
+            }
+
+            self.connect_timeout = connect_timeout;
+        }
+
+        if let Some(idle_timeout) = db.idle_timeout {
+            self.idle_timeout = Some(idle_timeout).filter(|secs| *secs != 0);
+        }
+
+        if let Some(statement_timeout) = db.statement_timeout {
+            self.statement_timeout = Some(statement_timeout).filter(|secs| *secs != 0);
+        }
+
+        if let Some(ssl_mode) = db.ssl_mode {
+            self.ssl_mode = ssl_mode;
+        }
+
+        if let Some(root_cert) = db.root_cert {
+            let root_cert = src.normalize(root_cert);
+
+            check_path(&root_cert, src, dot.push(&"root_cert"), true)?;
+
+            self.root_cert = Some(root_cert);
+        }
+
         Ok(())
     }
 }
@@ -620,6 +1009,1132 @@ impl Default for Db {
             host: "localhost".to_owned(),
             port: 5432,
             dbname: "tj2".to_owned(),
+            max_connections: 4,
+            min_connections: 0,
+            connect_timeout: 10,
+            idle_timeout: None,
+            statement_timeout: None,
+            ssl_mode: DbSslMode::Disable,
+            root_cert: None,
+        }
+    }
+}
+
+/// the structure of a network config
+#[derive(Debug, Deserialize)]
+pub struct NetworkShape {
+    trusted_proxies: Option<Vec<String>>,
+    outbound_allow: Option<Vec<String>>,
+    acl: Option<NetworkAclShape>,
+}
+
+/// the available network options for the server
+#[derive(Debug, Clone, Default)]
+pub struct Network {
+    /// the list of proxies that are trusted to provide the client ip in the
+    /// "x-forwarded-for" header
+    ///
+    /// defaults to an empty list
+    pub trusted_proxies: Vec<IpNet>,
+
+    /// the list of private networks that user provided urls, like webhooks,
+    /// are allowed to send requests to
+    ///
+    /// defaults to an empty list so only public addresses are reachable
+    pub outbound_allow: Vec<IpNet>,
+
+    /// the access control lists for the different route groups
+    pub acl: NetworkAcl,
+}
+
+impl Network {
+    /// merges a given NetworkShape into a Network structure
+    fn merge(&mut self, src: &SrcFile<'_>, dot: DotPath<'_>, network: NetworkShape) -> Result<(), error::Error> {
+        if let Some(trusted_proxies) = network.trusted_proxies {
+            self.trusted_proxies = parse_ip_nets(src, dot.push(&"trusted_proxies"), trusted_proxies)?;
+        }
+
+        if let Some(outbound_allow) = network.outbound_allow {
+            self.outbound_allow = parse_ip_nets(src, dot.push(&"outbound_allow"), outbound_allow)?;
+        }
+
+        if let Some(acl) = network.acl {
+            self.acl.merge(src, dot.push(&"acl"), acl)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// the naming convention used for the keys of json bodies
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum JsonCase {
+    #[default]
+    Snake,
+    Camel,
+}
+
+/// the structure of an api config
+#[derive(Debug, Deserialize)]
+pub struct ApiShape {
+    json_case: Option<JsonCase>,
+    compression: Option<bool>,
+    compression_min_size: Option<u16>,
+}
+
+/// the available api options for the server
+#[derive(Debug, Clone)]
+pub struct Api {
+    /// the naming convention used for json request and response bodies when
+    /// the client does not specify one with the "x-json-case" header
+    ///
+    /// defaults to "snake"
+    pub json_case: JsonCase,
+
+    /// compresses json, html, and other text responses with gzip or brotli
+    /// when the client accepts it
+    ///
+    /// defaults to true
+    pub compression: bool,
+
+    /// the number of bytes a response must be larger than to be compressed
+    ///
+    /// defaults to 1024
+    pub compression_min_size: u16,
+}
+
+impl Api {
+    /// merges a given ApiShape into an Api structure
+    fn merge(&mut self, _src: &SrcFile<'_>, _dot: DotPath<'_>, api: ApiShape) -> Result<(), error::Error> {
+        if let Some(json_case) = api.json_case {
+            self.json_case = json_case;
+        }
+
+        if let Some(compression) = api.compression {
+            self.compression = compression;
+        }
+
+        if let Some(compression_min_size) = api.compression_min_size {
+            self.compression_min_size = compression_min_size;
+        }
+
+        Ok(())
+    }
+}
+
+impl Default for Api {
+    fn default() -> Self {
+        Api {
+            json_case: JsonCase::default(),
+            compression: true,
+            compression_min_size: 1024,
+        }
+    }
+}
+
+/// the structure of a cors config
+#[derive(Debug, Deserialize)]
+pub struct CorsShape {
+    allowed_origins: Vec<String>,
+    allow_credentials: Option<bool>,
+    allowed_headers: Option<Vec<String>>,
+    max_age: Option<u64>,
+}
+
+/// the origins that are allowed to make cross-origin requests
+#[derive(Debug, Clone)]
+pub struct Cors {
+    /// the origins that are allowed. ex: "https://app.example.com". a
+    /// single "*" allows any origin
+    pub allowed_origins: Vec<String>,
+
+    /// allows cookies to be sent with cross-origin requests. cannot be used
+    /// with an origin of "*"
+    ///
+    /// defaults to false
+    pub allow_credentials: bool,
+
+    /// request headers that are allowed in addition to "content-type",
+    /// "x-json-case", and "x-csrf-token"
+    ///
+    /// defaults to an empty list
+    pub allowed_headers: Vec<String>,
+
+    /// the seconds that a browser can cache the result of a preflight
+    /// request
+    ///
+    /// defaults to 3600
+    pub max_age: u64,
+}
+
+impl Cors {
+    fn from_shape(src: &SrcFile<'_>, dot: DotPath<'_>, cors: CorsShape) -> Result<Self, error::Error> {
+        let any_origin = cors.allowed_origins.iter().any(|origin| origin == "*");
+        let allow_credentials = cors.allow_credentials.unwrap_or(false);
+
+        if cors.allowed_origins.is_empty() {
+            return Err(error::Error::context(format!(
+                "{dot}.allowed_origins is empty in {src}"
+            )));
+        }
+
+        if any_origin {
+            if cors.allowed_origins.len() != 1 {
+                return Err(error::Error::context(format!(
+                    "{dot}.allowed_origins cannot contain \"*\" with other origins in {src}"
+                )));
+            }
+
+            if allow_credentials {
+                return Err(error::Error::context(format!(
+                    "{dot}.allow_credentials cannot be used with an origin of \"*\" in {src}"
+                )));
+            }
+        } else {
+            for origin in &cors.allowed_origins {
+                let valid = url::Url::parse(origin)
+                    .ok()
+                    .filter(|url| {
+                        (url.scheme() == "https" || url.scheme() == "http") &&
+                            url.origin().ascii_serialization() == *origin
+                    })
+                    .is_some();
+
+                if !valid {
+                    return Err(error::Error::context(format!(
+                        "{dot}.allowed_origins invalid origin: \"{origin}\" file: {src}"
+                    )));
+                }
+            }
+        }
+
+        let allowed_headers = cors.allowed_headers.unwrap_or_default();
+
+        for name in &allowed_headers {
+            if axum::http::HeaderName::from_bytes(name.as_bytes()).is_err() {
+                return Err(error::Error::context(format!(
+                    "{dot}.allowed_headers invalid header name: \"{name}\" file: {src}"
+                )));
+            }
         }
+
+        Ok(Cors {
+            allowed_origins: cors.allowed_origins,
+            allow_credentials,
+            allowed_headers,
+            max_age: cors.max_age.unwrap_or(3600),
+        })
+    }
+}
+
+/// the structure of a security headers config
+#[derive(Debug, Deserialize)]
+pub struct SecurityHeadersShape {
+    content_security_policy: Option<String>,
+    file_content_security_policy: Option<String>,
+    referrer_policy: Option<String>,
+    hsts: Option<bool>,
+    hsts_max_age: Option<u64>,
+}
+
+/// the headers that restrict what browsers will do with the responses of
+/// the server. an empty string will stop a header from being sent
+#[derive(Debug, Clone)]
+pub struct SecurityHeaders {
+    /// the "content-security-policy" of html pages and api responses
+    ///
+    /// defaults to only allowing resources from the server
+    pub content_security_policy: String,
+
+    /// the "content-security-policy" of every other response, such as the
+    /// files of entries. this keeps an uploaded svg or html file from
+    /// running scripts when opened directly
+    ///
+    /// defaults to "default-src 'none'; style-src 'unsafe-inline'; sandbox"
+    pub file_content_security_policy: String,
+
+    /// the "referrer-policy" of every response
+    ///
+    /// defaults to "same-origin"
+    pub referrer_policy: String,
+
+    /// sends the "strict-transport-security" header. see
+    /// [`Settings::use_hsts`] for when this is not specified
+    ///
+    /// defaults to None
+    pub hsts: Option<bool>,
+
+    /// the seconds that browsers will only use https for the server
+    ///
+    /// defaults to 31536000
+    pub hsts_max_age: u64,
+}
+
+impl SecurityHeaders {
+    fn merge(&mut self, src: &SrcFile<'_>, dot: DotPath<'_>, headers: SecurityHeadersShape) -> Result<(), error::Error> {
+        let check_value = |name: &str, value: String| -> Result<String, error::Error> {
+            if axum::http::HeaderValue::from_str(&value).is_err() {
+                return Err(error::Error::context(format!(
+                    "{dot}.{name} is not a valid header value in {src}"
+                )));
+            }
+
+            Ok(value)
+        };
+
+        if let Some(csp) = headers.content_security_policy {
+            self.content_security_policy = check_value("content_security_policy", csp)?;
+        }
+
+        if let Some(csp) = headers.file_content_security_policy {
+            self.file_content_security_policy = check_value("file_content_security_policy", csp)?;
+        }
+
+        if let Some(referrer_policy) = headers.referrer_policy {
+            self.referrer_policy = check_value("referrer_policy", referrer_policy)?;
+        }
+
+        if let Some(hsts) = headers.hsts {
+            self.hsts = Some(hsts);
+        }
+
+        if let Some(hsts_max_age) = headers.hsts_max_age {
+            self.hsts_max_age = hsts_max_age;
+        }
+
+        Ok(())
+    }
+}
+
+impl Default for SecurityHeaders {
+    fn default() -> Self {
+        SecurityHeaders {
+            content_security_policy: String::from(
+                "default-src 'self'; \
+                img-src 'self' data: blob:; \
+                media-src 'self' blob:; \
+                style-src 'self' 'unsafe-inline'; \
+                object-src 'none'; \
+                base-uri 'self'; \
+                form-action 'self'; \
+                frame-ancestors 'none'"
+            ),
+            file_content_security_policy: String::from(
+                "default-src 'none'; style-src 'unsafe-inline'; sandbox"
+            ),
+            referrer_policy: String::from("same-origin"),
+            hsts: None,
+            hsts_max_age: 31_536_000,
+        }
+    }
+}
+
+/// the structure of a webauthn config
+#[derive(Debug, Deserialize)]
+pub struct WebauthnShape {
+    rp_id: String,
+    rp_name: Option<String>,
+    origin: String,
+}
+
+/// the relying party information used when registering and authenticating
+/// passkeys
+#[derive(Debug, Clone)]
+pub struct Webauthn {
+    /// the domain that passkeys are scoped to. must be the host of the origin
+    /// or a parent domain of it
+    pub rp_id: String,
+
+    /// the name shown to users when creating a passkey
+    ///
+    /// defaults to "TJ2"
+    pub rp_name: String,
+
+    /// the origin that clients will be using to access the server. ex:
+    /// "https://journal.example.com"
+    pub origin: String,
+}
+
+impl Webauthn {
+    /// creates the Webauthn structure from the given WebauthnShape
+    fn from_shape(src: &SrcFile<'_>, dot: DotPath<'_>, webauthn: WebauthnShape) -> Result<Self, error::Error> {
+        let origin = url::Url::parse(&webauthn.origin)
+            .map_err(|_| error::Error::context(format!(
+                "{dot}.origin invalid url: \"{}\" file: {src}", webauthn.origin
+            )))?;
+
+        let Some(host) = origin.host_str() else {
+            return Err(error::Error::context(format!(
+                "{dot}.origin missing host: \"{}\" file: {src}", webauthn.origin
+            )));
+        };
+
+        if host != webauthn.rp_id && !host.ends_with(&format!(".{}", webauthn.rp_id)) {
+            return Err(error::Error::context(format!(
+                "{dot}.rp_id is not the origin host or a parent domain of it file: {src}"
+            )));
+        }
+
+        Ok(Webauthn {
+            rp_id: webauthn.rp_id,
+            rp_name: webauthn.rp_name.unwrap_or(String::from("TJ2")),
+            origin: origin.origin().ascii_serialization(),
+        })
+    }
+}
+
+/// the structure of an encryption config
+#[derive(Debug, Deserialize)]
+pub struct EncryptionShape {
+    key_file: PathBuf,
+}
+
+/// the master key used to wrap the keys of journals
+#[derive(Debug, Clone)]
+pub struct Encryption {
+    /// the file containing the base64 encoded 32 byte master key
+    pub key_file: PathBuf,
+}
+
+impl Encryption {
+    /// creates the Encryption structure from the given EncryptionShape
+    fn from_shape(src: &SrcFile<'_>, dot: DotPath<'_>, encryption: EncryptionShape) -> Result<Self, error::Error> {
+        let key_file = src.normalize(encryption.key_file);
+
+        check_path(&key_file, src, dot.push(&"key_file"), true)?;
+
+        Ok(Encryption {
+            key_file,
+        })
+    }
+}
+
+/// the available ways of securing the connection to an smtp server
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SmtpTls {
+    /// upgrades a plain connection with STARTTLS
+    Starttls,
+
+    /// connects with TLS from the start
+    Tls,
+
+    /// no encryption. only for local relays
+    None,
+}
+
+/// the structure of an smtp config
+#[derive(Debug, Deserialize)]
+pub struct SmtpShape {
+    host: String,
+    port: Option<u16>,
+    tls: Option<SmtpTls>,
+    username: Option<String>,
+    password: Option<String>,
+    password_file: Option<PathBuf>,
+    from: String,
+    alert_to: Option<Vec<String>>,
+}
+
+/// the smtp server used to send emails
+#[derive(Debug, Clone)]
+pub struct Smtp {
+    /// the host of the smtp server
+    pub host: String,
+
+    /// the port of the smtp server
+    ///
+    /// defaults to 587 for starttls, 465 for tls, and 25 for none
+    pub port: u16,
+
+    /// how the connection is secured
+    ///
+    /// defaults to starttls
+    pub tls: SmtpTls,
+
+    /// the credentials used to authenticate with the smtp server. the
+    /// password can be read from "password_file"
+    pub credentials: Option<(String, String)>,
+
+    /// the address that emails are sent from. ex: "TJ2 <tj2@example.com>"
+    pub from: String,
+
+    /// the addresses that server alerts are sent to, ex: when the free space
+    /// of the storage directory drops below the reserve
+    ///
+    /// defaults to no addresses
+    pub alert_to: Vec<String>,
+}
+
+impl Smtp {
+    /// creates the Smtp structure from the given SmtpShape
+    fn from_shape(src: &SrcFile<'_>, dot: DotPath<'_>, smtp: SmtpShape) -> Result<Self, error::Error> {
+        if smtp.from.parse::<lettre::message::Mailbox>().is_err() {
+            return Err(error::Error::context(format!(
+                "{dot}.from invalid email address: \"{}\" file: {src}", smtp.from
+            )));
+        }
+
+        let password = match (smtp.password, smtp.password_file) {
+            (Some(_), Some(_)) => return Err(error::Error::context(format!(
+                "{dot}.password and {dot}.password_file cannot both be specified in {src}"
+            ))),
+            (Some(password), None) => Some(password),
+            (None, Some(password_file)) => Some(read_secret(password_file, src, dot.push(&"password_file"))?),
+            (None, None) => None,
+        };
+
+        let credentials = match (smtp.username, password) {
+            (Some(username), Some(password)) => Some((username, password)),
+            (None, None) => None,
+            _ => return Err(error::Error::context(format!(
+                "{dot}.username and {dot}.password must both be specified file: {src}"
+            ))),
+        };
+
+        let alert_to = smtp.alert_to.unwrap_or_default();
+
+        for address in &alert_to {
+            if address.parse::<lettre::message::Mailbox>().is_err() {
+                return Err(error::Error::context(format!(
+                    "{dot}.alert_to invalid email address: \"{address}\" file: {src}"
+                )));
+            }
+        }
+
+        let tls = smtp.tls.unwrap_or(SmtpTls::Starttls);
+        let port = smtp.port.unwrap_or(match tls {
+            SmtpTls::Starttls => 587,
+            SmtpTls::Tls => 465,
+            SmtpTls::None => 25,
+        });
+
+        Ok(Smtp {
+            host: smtp.host,
+            port,
+            tls,
+            credentials,
+            from: smtp.from,
+            alert_to,
+        })
+    }
+}
+
+/// the structure of a telemetry config
+#[derive(Debug, Deserialize)]
+pub struct TelemetryShape {
+    enabled: Option<bool>,
+    endpoint: Option<String>,
+}
+
+/// the opt-in usage telemetry of the server. see [`crate::telemetry`] for
+/// what is sent
+#[derive(Debug, Clone, Default)]
+pub struct Telemetry {
+    /// sends a usage report to the endpoint once a day. setting this to false
+    /// in a later config file will turn telemetry off
+    ///
+    /// defaults to false
+    pub enabled: bool,
+
+    /// the url that reports are sent to
+    pub endpoint: Option<url::Url>,
+}
+
+impl Telemetry {
+    /// merges a given TelemetryShape into a Telemetry structure
+    fn merge(&mut self, src: &SrcFile<'_>, dot: DotPath<'_>, telemetry: TelemetryShape) -> Result<(), error::Error> {
+        if let Some(enabled) = telemetry.enabled {
+            self.enabled = enabled;
+        }
+
+        if let Some(endpoint) = telemetry.endpoint {
+            let url = url::Url::parse(&endpoint)
+                .map_err(|_| error::Error::context(format!(
+                    "{dot}.endpoint invalid url: \"{endpoint}\" file: {src}"
+                )))?;
+
+            if url.scheme() != "https" && url.scheme() != "http" {
+                return Err(error::Error::context(format!(
+                    "{dot}.endpoint must be an http or https url: \"{endpoint}\" file: {src}"
+                )));
+            }
+
+            self.endpoint = Some(url);
+        }
+
+        Ok(())
+    }
+}
+
+/// the structure of a text extraction config
+#[derive(Debug, Deserialize)]
+pub struct ExtractionShape {
+    pdf: Option<Vec<String>>,
+    ocr: Option<Vec<String>>,
+    max_size: Option<u64>,
+}
+
+/// the external commands used to extract text from file entries. a command
+/// is given the contents of the file on stdin and is expected to write the
+/// text to stdout. plain text files are always extracted
+#[derive(Debug, Clone)]
+pub struct Extraction {
+    /// the command for pdf files. ex: ["pdftotext", "-", "-"]
+    pub pdf: Option<Vec<String>>,
+
+    /// the command for image files. ex: ["tesseract", "stdin", "stdout"]
+    pub ocr: Option<Vec<String>>,
+
+    /// files larger than this number of bytes are skipped
+    ///
+    /// defaults to 32MiB
+    pub max_size: u64,
+}
+
+impl Default for Extraction {
+    fn default() -> Self {
+        Extraction {
+            pdf: None,
+            ocr: None,
+            max_size: 32 * 1024 * 1024,
+        }
+    }
+}
+
+impl Extraction {
+    /// merges a given ExtractionShape into an Extraction structure
+    fn merge(&mut self, src: &SrcFile<'_>, dot: DotPath<'_>, extraction: ExtractionShape) -> Result<(), error::Error> {
+        if let Some(pdf) = extraction.pdf {
+            if pdf.is_empty() {
+                return Err(error::Error::context(format!(
+                    "{dot}.pdf command is empty. file: {src}"
+                )));
+            }
+
+            self.pdf = Some(pdf);
+        }
+
+        if let Some(ocr) = extraction.ocr {
+            if ocr.is_empty() {
+                return Err(error::Error::context(format!(
+                    "{dot}.ocr command is empty. file: {src}"
+                )));
+            }
+
+            self.ocr = Some(ocr);
+        }
+
+        if let Some(max_size) = extraction.max_size {
+            self.max_size = max_size;
+        }
+
+        Ok(())
+    }
+}
+
+/// the structure of a transcription config
+#[derive(Debug, Deserialize)]
+pub struct TranscriptionShape {
+    command: Option<Vec<String>>,
+    endpoint: Option<String>,
+    custom_field: Option<String>,
+    max_size: Option<u64>,
+}
+
+/// where audio is sent to be transcribed
+#[derive(Debug, Clone)]
+pub enum Transcriber {
+    /// a command that is given the audio on stdin and writes the transcript
+    /// to stdout
+    Command(Vec<String>),
+
+    /// an http endpoint that is sent the audio as the body of a POST request
+    /// and responds with the transcript as plain text or as json with a
+    /// "text" field
+    Endpoint(url::Url),
+}
+
+/// options for transcribing audio file entries
+#[derive(Debug, Clone)]
+pub struct Transcription {
+    pub transcriber: Transcriber,
+
+    /// the name of a text custom field to store the transcript in. the
+    /// transcript is added to the contents of the entry if the journal does
+    /// not have the field
+    pub custom_field: Option<String>,
+
+    /// files larger than this number of bytes are not transcribed
+    ///
+    /// defaults to 64MiB
+    pub max_size: u64,
+}
+
+impl Transcription {
+    fn from_shape(src: &SrcFile<'_>, dot: DotPath<'_>, transcription: TranscriptionShape) -> Result<Self, error::Error> {
+        let transcriber = match (transcription.command, transcription.endpoint) {
+            (Some(command), None) => {
+                if command.is_empty() {
+                    return Err(error::Error::context(format!(
+                        "{dot}.command is empty. file: {src}"
+                    )));
+                }
+
+                Transcriber::Command(command)
+            }
+            (None, Some(endpoint)) => {
+                let url = url::Url::parse(&endpoint)
+                    .map_err(|_| error::Error::context(format!(
+                        "{dot}.endpoint invalid url: \"{endpoint}\" file: {src}"
+                    )))?;
+
+                if url.scheme() != "https" && url.scheme() != "http" {
+                    return Err(error::Error::context(format!(
+                        "{dot}.endpoint must be an http or https url: \"{endpoint}\" file: {src}"
+                    )));
+                }
+
+                Transcriber::Endpoint(url)
+            }
+            _ => return Err(error::Error::context(format!(
+                "{dot} requires either a command or an endpoint. file: {src}"
+            ))),
+        };
+
+        Ok(Transcription {
+            transcriber,
+            custom_field: transcription.custom_field,
+            max_size: transcription.max_size.unwrap_or(64 * 1024 * 1024),
+        })
+    }
+}
+
+/// the structure of an upload config
+#[derive(Debug, Deserialize)]
+pub struct UploadShape {
+    max_file_size: Option<u64>,
+    allowed_mime_types: Option<Vec<String>>,
+    max_files_per_entry: Option<u32>,
+    strip_metadata: Option<bool>,
+}
+
+/// the default limits of the files uploaded to journals
+#[derive(Debug, Clone, Default)]
+pub struct Upload {
+    /// the max number of bytes of a single file. unlimited if not specified
+    pub max_file_size: Option<u64>,
+
+    /// the mime types that can be uploaded. a type can end with "/*" to
+    /// allow all of its subtypes. ex: "image/*". any type is allowed if
+    /// empty
+    pub allowed_mime_types: Vec<String>,
+
+    /// the max number of files an entry can have. unlimited if not
+    /// specified
+    pub max_files_per_entry: Option<u32>,
+
+    /// removes the exif, xmp, and text metadata of jpeg, png, and webp
+    /// images before they are stored
+    ///
+    /// defaults to false
+    pub strip_metadata: bool,
+}
+
+impl Upload {
+    fn merge(&mut self, src: &SrcFile<'_>, dot: DotPath<'_>, upload: UploadShape) -> Result<(), error::Error> {
+        if let Some(max_file_size) = upload.max_file_size {
+            if max_file_size == 0 {
+                return Err(error::Error::context(format!(
+                    "{dot}.max_file_size must be greater than 0. file: {src}"
+                )));
+            }
+
+            self.max_file_size = Some(max_file_size);
+        }
+
+        if let Some(allowed_mime_types) = upload.allowed_mime_types {
+            for mime_type in &allowed_mime_types {
+                if !crate::journal::upload::valid_mime_pattern(mime_type) {
+                    return Err(error::Error::context(format!(
+                        "{dot}.allowed_mime_types invalid mime type: \"{mime_type}\" file: {src}"
+                    )));
+                }
+            }
+
+            self.allowed_mime_types = allowed_mime_types;
+        }
+
+        if let Some(max_files_per_entry) = upload.max_files_per_entry {
+            self.max_files_per_entry = Some(max_files_per_entry);
+        }
+
+        if let Some(strip_metadata) = upload.strip_metadata {
+            self.strip_metadata = strip_metadata;
+        }
+
+        Ok(())
+    }
+}
+
+/// the structure of a scan config
+#[derive(Debug, Deserialize)]
+pub struct ScanShape {
+    clamd_socket: Option<PathBuf>,
+    clamd_addr: Option<String>,
+    command: Option<Vec<String>>,
+    timeout: Option<u64>,
+    max_verdict_age: Option<u64>,
+}
+
+/// what uploaded files are sent to be scanned
+#[derive(Debug, Clone)]
+pub enum Scanner {
+    /// the unix socket of a clamd daemon
+    ClamdSocket(PathBuf),
+
+    /// the host and port of a clamd daemon
+    ClamdAddr(String),
+
+    /// a command that is given the file on stdin. an exit code of 0 is
+    /// clean, 1 is infected with the name of the threat written to stdout,
+    /// and anything else is an error. ex: `["clamdscan", "--no-summary", "-"]`
+    Command(Vec<String>),
+}
+
+/// options for scanning uploaded files
+#[derive(Debug, Clone)]
+pub struct Scan {
+    pub scanner: Scanner,
+
+    /// the max number of seconds a scan can take before the upload fails
+    ///
+    /// defaults to 60
+    pub timeout: u64,
+
+    /// the max number of seconds a cached verdict is used for files with
+    /// the same contents. 0 will scan every file
+    ///
+    /// defaults to 86400 (1 day)
+    pub max_verdict_age: u64,
+}
+
+/// the largest max_verdict_age allowed, 1 year
+const MAX_VERDICT_AGE: u64 = 365 * 24 * 60 * 60;
+
+impl Scan {
+    fn from_shape(src: &SrcFile<'_>, dot: DotPath<'_>, scan: ScanShape) -> Result<Self, error::Error> {
+        let scanner = match (scan.clamd_socket, scan.clamd_addr, scan.command) {
+            (Some(socket), None, None) => {
+                if !cfg!(unix) {
+                    return Err(error::Error::context(format!(
+                        "{dot}.clamd_socket is only available on unix. file: {src}"
+                    )));
+                }
+
+                Scanner::ClamdSocket(src.normalize(socket))
+            }
+            (None, Some(addr), None) => {
+                if addr.is_empty() {
+                    return Err(error::Error::context(format!(
+                        "{dot}.clamd_addr is empty. file: {src}"
+                    )));
+                }
+
+                Scanner::ClamdAddr(addr)
+            }
+            (None, None, Some(command)) => {
+                if command.is_empty() {
+                    return Err(error::Error::context(format!(
+                        "{dot}.command is empty. file: {src}"
+                    )));
+                }
+
+                Scanner::Command(command)
+            }
+            _ => return Err(error::Error::context(format!(
+                "{dot} requires one of clamd_socket, clamd_addr, or command. file: {src}"
+            ))),
+        };
+
+        let timeout = scan.timeout.unwrap_or(60);
+
+        if timeout == 0 {
+            return Err(error::Error::context(format!(
+                "{dot}.timeout must be greater than 0. file: {src}"
+            )));
+        }
+
+        let max_verdict_age = scan.max_verdict_age.unwrap_or(24 * 60 * 60);
+
+        if max_verdict_age > MAX_VERDICT_AGE {
+            return Err(error::Error::context(format!(
+                "{dot}.max_verdict_age must not be greater than {MAX_VERDICT_AGE}. file: {src}"
+            )));
+        }
+
+        Ok(Scan {
+            scanner,
+            timeout,
+            max_verdict_age,
+        })
+    }
+}
+
+/// the structure of a weather config
+#[derive(Debug, Deserialize)]
+pub struct WeatherShape {
+    url: String,
+    api_key: Option<String>,
+    api_key_file: Option<PathBuf>,
+    location_field: Option<String>,
+    weather_field: Option<String>,
+}
+
+/// options for filling in the weather of new entries
+///
+/// the provider is sent a GET request with the "location" and "date" query
+/// parameters and responds with a summary of the weather as plain text or
+/// as json with a "summary" field
+#[derive(Debug, Clone)]
+pub struct Weather {
+    pub url: url::Url,
+
+    /// sent to the provider as a bearer token if specified. can be read
+    /// from "api_key_file"
+    pub api_key: Option<String>,
+
+    /// the name of the text custom field that holds the location of an
+    /// entry. the default location of the journal is used if the entry
+    /// does not have a value for it
+    ///
+    /// defaults to "location"
+    pub location_field: String,
+
+    /// the name of the text custom field to store the weather in
+    ///
+    /// defaults to "weather"
+    pub weather_field: String,
+}
+
+impl Weather {
+    fn from_shape(src: &SrcFile<'_>, dot: DotPath<'_>, weather: WeatherShape) -> Result<Self, error::Error> {
+        let url = url::Url::parse(&weather.url)
+            .map_err(|_| error::Error::context(format!(
+                "{dot}.url invalid url: \"{}\" file: {src}",
+                weather.url
+            )))?;
+
+        if url.scheme() != "https" && url.scheme() != "http" {
+            return Err(error::Error::context(format!(
+                "{dot}.url must be an http or https url: \"{}\" file: {src}",
+                weather.url
+            )));
+        }
+
+        let api_key = match (weather.api_key, weather.api_key_file) {
+            (Some(_), Some(_)) => return Err(error::Error::context(format!(
+                "{dot}.api_key and {dot}.api_key_file cannot both be specified in {src}"
+            ))),
+            (Some(api_key), None) => Some(api_key),
+            (None, Some(api_key_file)) => Some(read_secret(api_key_file, src, dot.push(&"api_key_file"))?),
+            (None, None) => None,
+        };
+
+        Ok(Weather {
+            url,
+            api_key,
+            location_field: weather.location_field.unwrap_or_else(|| String::from("location")),
+            weather_field: weather.weather_field.unwrap_or_else(|| String::from("weather")),
+        })
+    }
+}
+
+/// the structure of a job config
+#[derive(Debug, Deserialize)]
+pub struct JobShape {
+    schedule: Option<String>,
+    enabled: Option<bool>,
+}
+
+/// the schedule of a background job. anything not specified is left as it
+/// is in the jobs table
+#[derive(Debug, Clone, Default)]
+pub struct Job {
+    /// the cron expression for the job, including a seconds field. ex:
+    /// "0 0 0 * * *" for the start of every UTC day
+    pub schedule: Option<String>,
+
+    /// whether the scheduler will run the job
+    pub enabled: Option<bool>,
+}
+
+impl Job {
+    /// merges a given JobShape into a Job structure
+    fn merge(&mut self, src: &SrcFile<'_>, dot: DotPath<'_>, job: JobShape) -> Result<(), error::Error> {
+        if let Some(schedule) = job.schedule {
+            let schedule = schedule.trim().to_owned();
+
+            if let Err(err) = parse_schedule(&schedule) {
+                return Err(error::Error::context(format!(
+                    "{dot}.schedule invalid cron expression: \"{schedule}\" ({err}) file: {src}"
+                )));
+            }
+
+            self.schedule = Some(schedule);
+        }
+
+        if let Some(enabled) = job.enabled {
+            self.enabled = Some(enabled);
+        }
+
+        Ok(())
+    }
+}
+
+/// the structure of a network acl config
+#[derive(Debug, Deserialize)]
+pub struct NetworkAclShape {
+    admin: Option<AclShape>,
+}
+
+/// the access control lists available for the route groups of the server
+#[derive(Debug, Clone, Default)]
+pub struct NetworkAcl {
+    /// the acl applied to the "/admin" routes
+    pub admin: Acl,
+}
+
+impl NetworkAcl {
+    /// merges a given NetworkAclShape into a NetworkAcl structure
+    fn merge(&mut self, src: &SrcFile<'_>, dot: DotPath<'_>, acl: NetworkAclShape) -> Result<(), error::Error> {
+        if let Some(admin) = acl.admin {
+            self.admin.merge(src, dot.push(&"admin"), admin)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// the structure of an acl config
+#[derive(Debug, Deserialize)]
+pub struct AclShape {
+    allow: Option<Vec<String>>,
+    deny: Option<Vec<String>>,
+}
+
+/// a list of networks that are allowed or denied access to a route group
+///
+/// the deny list is checked first. if the allow list is not empty then the
+/// address must be in the allow list
+#[derive(Debug, Clone, Default)]
+pub struct Acl {
+    /// the list of networks that are allowed access
+    ///
+    /// defaults to an empty list
+    pub allow: Vec<IpNet>,
+
+    /// the list of networks that are denied access
+    ///
+    /// defaults to an empty list
+    pub deny: Vec<IpNet>,
+}
+
+impl Acl {
+    /// merges a given AclShape into an Acl structure
+    fn merge(&mut self, src: &SrcFile<'_>, dot: DotPath<'_>, acl: AclShape) -> Result<(), error::Error> {
+        if let Some(allow) = acl.allow {
+            self.allow = parse_ip_nets(src, dot.push(&"allow"), allow)?;
+        }
+
+        if let Some(deny) = acl.deny {
+            self.deny = parse_ip_nets(src, dot.push(&"deny"), deny)?;
+        }
+
+        Ok(())
+    }
+
+    /// checks to see if the given address is allowed by the acl
+    pub fn is_allowed(&self, addr: &IpAddr) -> bool {
+        if self.deny.iter().any(|net| net.contains(addr)) {
+            return false;
+        }
+
+        self.allow.is_empty() || self.allow.iter().any(|net| net.contains(addr))
+    }
+}
+
+/// parses a list of networks in CIDR notation
+///
+/// a single ip address will be treated as a network containing only that
+/// address
+fn parse_ip_nets(src: &SrcFile<'_>, dot: DotPath<'_>, list: Vec<String>) -> Result<Vec<IpNet>, error::Error> {
+    let mut rtn = Vec::with_capacity(list.len());
+
+    for value in list {
+        let net = match IpNet::from_str(&value) {
+            Ok(valid) => valid,
+            Err(_) => match IpAddr::from_str(&value) {
+                Ok(valid) => IpNet::from(valid),
+                Err(_) => return Err(error::Error::context(format!(
+                    "{dot} invalid network: \"{value}\" file: {src}"
+                )))
+            }
+        };
+
+        rtn.push(net);
+    }
+
+    Ok(rtn)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn acl(allow: &[&str], deny: &[&str]) -> Acl {
+        Acl {
+            allow: allow.iter().map(|net| net.parse().unwrap()).collect(),
+            deny: deny.iter().map(|net| net.parse().unwrap()).collect(),
+        }
+    }
+
+    fn addr(value: &str) -> IpAddr {
+        value.parse().unwrap()
+    }
+
+    #[test]
+    fn empty_acl_allows_all() {
+        let acl = Acl::default();
+
+        assert!(acl.is_allowed(&addr("203.0.113.5")));
+        assert!(acl.is_allowed(&addr("::1")));
+    }
+
+    #[test]
+    fn deny_before_allow() {
+        let acl = acl(&["10.0.0.0/8"], &["10.0.0.5/32"]);
+
+        assert!(!acl.is_allowed(&addr("10.0.0.5")));
+        assert!(acl.is_allowed(&addr("10.0.0.6")));
+    }
+
+    #[test]
+    fn allow_list_restricts() {
+        let acl = acl(&["10.0.0.0/8", "fd00::/8"], &[]);
+
+        assert!(acl.is_allowed(&addr("10.20.30.40")));
+        assert!(acl.is_allowed(&addr("fd00::1")));
+        assert!(!acl.is_allowed(&addr("192.168.1.1")));
+        assert!(!acl.is_allowed(&addr("2606:4700::1111")));
+    }
+
+    #[test]
+    fn deny_only() {
+        let acl = acl(&[], &["192.168.0.0/16"]);
+
+        assert!(!acl.is_allowed(&addr("192.168.1.1")));
+        assert!(acl.is_allowed(&addr("10.0.0.1")));
     }
 }
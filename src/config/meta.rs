@@ -153,6 +153,37 @@ where
     Ok(())
 }
 
+/// reads a secret from a file relative to the config file. a single
+/// trailing newline is removed so files written by `echo` work as expected
+pub fn read_secret(given: PathBuf, src: &SrcFile<'_>, dot: DotPath<'_>) -> Result<String, error::Error> {
+    let path = src.normalize(given);
+
+    check_path(&path, src, dot.clone(), true)?;
+
+    let path_display = path.display();
+    let path_quote = Quote(&path_display);
+
+    let mut contents = std::fs::read_to_string(&path).context(format!(
+        "{dot} failed to read {path_quote} in {src}"
+    ))?;
+
+    if contents.ends_with('\n') {
+        contents.pop();
+
+        if contents.ends_with('\r') {
+            contents.pop();
+        }
+    }
+
+    if contents.is_empty() {
+        return Err(error::Error::context(format!(
+            "{dot} {path_quote} is empty in {src}"
+        )));
+    }
+
+    Ok(contents)
+}
+
 /// sanitizes a given string as a url and returns the resulting string
 pub fn sanitize_url_key(given: &str, src: &SrcFile<'_>, dot: DotPath<'_>) -> Result<String, error::Error> {
     let trimmed = given.trim();
@@ -1,17 +1,24 @@
 use async_trait::async_trait;
 use axum::extract::FromRequestParts;
 use axum::http::request::Parts;
-use deadpool_postgres::{Manager, ManagerConfig, RecyclingMethod};
+use std::sync::Arc;
+use std::time::Duration;
+
+use deadpool_postgres::{Manager, ManagerConfig, RecyclingMethod, Runtime};
+use tokio_postgres::config::SslMode;
 use tokio_postgres::{Config as PgConfig, NoTls};
+use tokio_postgres_rustls::MakeRustlsConnect;
 use tokio_postgres::error::SqlState;
 use tokio_postgres::types::ToSql;
 
-use crate::config::Config;
+use crate::config::{Config, Db as DbConfig, DbSslMode};
 use crate::error::{Error, Context};
+use crate::journal::prompt;
 use crate::sec::authz::{Scope, Ability, Role};
 use crate::sec::password;
 use crate::state;
 use crate::user::User;
+use crate::workspace::{Workspace, WorkspaceUser, DEFAULT_WORKSPACE};
 
 pub use deadpool_postgres::{Pool, GenericClient, Object, Transaction};
 pub use tokio_postgres::Error as PgError;
@@ -19,6 +26,10 @@ pub use tokio_postgres::Error as PgError;
 mod test_data;
 
 pub mod ids;
+pub mod lock;
+pub mod tx;
+
+pub use tx::Tx;
 
 /// type alias for creating a Vec of ToSql references
 pub type ParamsVec<'a> = Vec<&'a (dyn ToSql + Sync)>;
@@ -26,39 +37,149 @@ pub type ParamsVec<'a> = Vec<&'a (dyn ToSql + Sync)>;
 /// type alias for creating a fixed size array of ToSql references
 pub type ParamsArray<'a, const N: usize> = [&'a (dyn ToSql + Sync); N];
 
+/// how often the pool is checked for idle connections
+const IDLE_CHECK_INTERVAL: Duration = Duration::from_secs(30);
+
+/// creates the rustls connector used for tls connections to the database
+///
+/// the server is verified with the configured root certificates or the
+/// system root certificates if none are specified
+fn tls_connector(db: &DbConfig) -> Result<MakeRustlsConnect, Error> {
+    let mut roots = rustls::RootCertStore::empty();
+
+    if let Some(path) = &db.root_cert {
+        let file = std::fs::File::open(path)
+            .context("failed to open database root certificate")?;
+        let mut reader = std::io::BufReader::new(file);
+
+        for cert in rustls_pemfile::certs(&mut reader) {
+            let cert = cert.context("failed to read database root certificate")?;
+
+            roots.add(cert)
+                .context("invalid database root certificate")?;
+        }
+    } else {
+        let native = rustls_native_certs::load_native_certs();
+
+        for err in &native.errors {
+            tracing::warn!("failed to load system root certificate: {err}");
+        }
+
+        roots.add_parsable_certificates(native.certs);
+    }
+
+    if roots.is_empty() {
+        return Err(Error::context("no root certificates available to verify the database with"));
+    }
+
+    let tls_config = rustls::ClientConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+        .with_safe_default_protocol_versions()
+        .context("failed to create database tls config")?
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+
+    Ok(MakeRustlsConnect::new(tls_config))
+}
+
+/// closes connections that have not been used within the idle timeout
+/// while keeping the min number of connections open
+async fn close_idle(pool: Pool, idle_timeout: Duration, min_connections: usize) {
+    let mut interval = tokio::time::interval(IDLE_CHECK_INTERVAL.min(idle_timeout));
+
+    loop {
+        interval.tick().await;
+
+        let mut kept = 0;
+        let result = pool.retain(|_, metrics| {
+            if kept < min_connections || metrics.last_used() < idle_timeout {
+                kept += 1;
+
+                true
+            } else {
+                false
+            }
+        });
+
+        if !result.removed.is_empty() {
+            tracing::debug!("closed {} idle database connections", result.removed.len());
+        }
+    }
+}
+
 /// creates the postgres database connection pool
 ///
-/// the connection pool will be limited for 4
+/// the min number of connections are opened before the pool is returned.
+/// if an idle timeout is configured then a task is spawned to close unused
+/// connections
 pub async fn from_config(config: &Config) -> Result<Pool, Error> {
+    let db = &config.settings.db;
     let mut pg_config = PgConfig::new();
 
-    pg_config.user(config.settings.db.user.as_str());
-    pg_config.host(config.settings.db.host.as_str());
-    pg_config.port(config.settings.db.port);
-    pg_config.dbname(config.settings.db.dbname.as_str());
+    pg_config.user(db.user.as_str());
+    pg_config.host(db.host.as_str());
+    pg_config.port(db.port);
+    pg_config.dbname(db.dbname.as_str());
+    pg_config.connect_timeout(Duration::from_secs(db.connect_timeout));
 
-    if let Some(password) = &config.settings.db.password {
+    if let Some(password) = &db.password {
         pg_config.password(password.as_str());
     }
 
+    if let Some(statement_timeout) = db.statement_timeout {
+        pg_config.options(&format!("-c statement_timeout={statement_timeout}s"));
+    }
+
     let manager_config = ManagerConfig {
         recycling_method: RecyclingMethod::Fast
     };
 
-    let manager = Manager::from_config(pg_config, NoTls, manager_config);
+    let manager = match db.ssl_mode {
+        DbSslMode::Disable => {
+            pg_config.ssl_mode(SslMode::Disable);
+
+            Manager::from_config(pg_config, NoTls, manager_config)
+        }
+        DbSslMode::Prefer | DbSslMode::Require => {
+            pg_config.ssl_mode(if db.ssl_mode == DbSslMode::Require {
+                SslMode::Require
+            } else {
+                SslMode::Prefer
+            });
+
+            Manager::from_config(pg_config, tls_connector(db)?, manager_config)
+        }
+    };
 
     let pool = Pool::builder(manager)
-        .max_size(4)
+        .max_size(db.max_connections)
+        .runtime(Runtime::Tokio1)
+        .create_timeout(Some(Duration::from_secs(db.connect_timeout)))
         .build()
         .context("failed to create postgresql connection pool")?;
 
+    // the connections are held until all of them are open so that each one
+    // is a new connection
+    let mut opened = Vec::with_capacity(db.min_connections);
+
+    for _ in 0..db.min_connections {
+        opened.push(pool.get()
+            .await
+            .context("failed to open database connection")?);
+    }
+
+    drop(opened);
+
     check_database(&pool).await?;
 
+    if let Some(idle_timeout) = db.idle_timeout {
+        tokio::spawn(close_idle(pool.clone(), Duration::from_secs(idle_timeout), db.min_connections));
+    }
+
     Ok(pool)
 }
 
-/// checks to make sure that the admin account exists in the database with
-/// the necessary permissions.
+/// checks to make sure that the default workspace and admin account exists in
+/// the database with the necessary permissions.
 ///
 /// if the admin account is not found then it will attempt to create the
 /// user and role. this is a quick check will assume that if the admin
@@ -72,6 +193,18 @@ pub async fn check_database(pool: &Pool) -> Result<(), Error> {
         .await
         .context("failed to create transaction")?;
 
+    let maybe_workspace = Workspace::retrieve_default(&transaction)
+        .await
+        .context("failed to check if default workspace was found")?;
+
+    let workspace = if let Some(found) = maybe_workspace {
+        found
+    } else {
+        Workspace::create(&transaction, DEFAULT_WORKSPACE.to_owned(), None, None)
+            .await
+            .context("failed to create default workspace")?
+    };
+
     let maybe_admin = User::retrieve_username(&transaction, "admin")
         .await
         .context("failed to check if admin user was found")?;
@@ -86,8 +219,16 @@ pub async fn check_database(pool: &Pool) -> Result<(), Error> {
         admin_role.assign_user(&transaction, admin.id)
             .await
             .context("failed to assign admin to admin role")?;
+
+        WorkspaceUser::upsert(&transaction, &workspace.id, &admin.id, true)
+            .await
+            .context("failed to add admin to default workspace")?;
     }
 
+    prompt::seed_defaults(&transaction)
+        .await
+        .context("failed to add default prompts")?;
+
     transaction.commit()
         .await
         .context("failed to commit transaction")?;
@@ -142,6 +283,19 @@ async fn create_default_roles(conn: &impl GenericClient) -> Result<Role, Error>
             Ability::Read,
             Ability::Update,
             Ability::Delete,
+        ]),
+        (Scope::Workspaces, vec![
+            Ability::Create,
+            Ability::Read,
+            Ability::Update,
+            Ability::Delete,
+        ]),
+        (Scope::Reports, vec![
+            Ability::Read,
+        ]),
+        (Scope::Server, vec![
+            Ability::Read,
+            Ability::Update,
         ])
     ];
 
@@ -165,6 +319,11 @@ pub async fn gen_test_data(state: &state::SharedState) -> Result<(), Error> {
         .await
         .context("failed to check if admin user was found")?;
 
+    let workspace = Workspace::retrieve_default(&transaction)
+        .await
+        .context("failed to retrieve default workspace")?
+        .context("default workspace was not found")?;
+
     if let Some(admin) = maybe_admin {
         let check = transaction.execute(
             "select * from journals where id = $1",
@@ -178,12 +337,13 @@ pub async fn gen_test_data(state: &state::SharedState) -> Result<(), Error> {
                 state,
                 &transaction,
                 &mut rng,
+                &workspace,
                 admin.id
             ).await?;
         }
     }
 
-    test_data::create(state, &transaction, &mut rng).await?;
+    test_data::create(state, &transaction, &mut rng, &workspace).await?;
 
     transaction.commit()
         .await
@@ -292,10 +292,20 @@ macro_rules! set_type {
     }
 }
 
+id_type!(WorkspaceId);
+uid_type!(WorkspaceUid);
+
 id_type!(UserId);
 uid_type!(UserUid);
 set_type!(UserSet, UserId, UserUid);
 
+id_type!(RecoveryId);
+
+id_type!(PasskeyId);
+id_type!(PasskeyChallengeId);
+id_type!(SessionId);
+id_type!(EntryTaskId);
+
 id_type!(GroupId);
 uid_type!(GroupUid);
 
@@ -307,6 +317,15 @@ id_type!(EntryId);
 uid_type!(EntryUid);
 set_type!(EntrySet, EntryId, EntryUid);
 
+id_type!(RevisionId);
+
+id_type!(ExportId);
+
+id_type!(ImportId);
+
+id_type!(WebhookId);
+id_type!(WebhookDeliveryId);
+
 id_type!(FileEntryId);
 uid_type!(FileEntryUid);
 
@@ -318,6 +337,12 @@ id_type!(PermissionId);
 id_type!(CustomFieldId);
 uid_type!(CustomFieldUid);
 
+id_type!(PromptId);
+
+id_type!(GoalId);
+
+id_type!(SubjectId);
+
 /// creates a list of unique ids from a given list
 ///
 /// if a current dictionary of known ids is provided then it will create a list
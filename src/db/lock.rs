@@ -0,0 +1,62 @@
+//! postgres advisory locks for critical sections
+//!
+//! the locks are scoped to the current transaction and are released when it
+//! is committed or rolled back. they must be acquired inside of a
+//! transaction otherwise they will be released as soon as the statement
+//! finishes
+
+use crate::db::{GenericClient, PgError};
+
+/// the number of bits used for the key of a lock. the remaining bits are
+/// used for the namespace
+const KEY_BITS: u32 = 56;
+
+/// the areas of the server that use advisory locks
+///
+/// the namespace is combined with the key so that the same id used in two
+/// different areas will not block each other
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Namespace {
+    /// operations for a specific user
+    User = 1,
+
+    /// operations for a specific account recovery request
+    Recovery = 2,
+
+    /// background jobs that should only run on a single instance at a time
+    Job = 3,
+}
+
+/// creates the lock key for the given namespace and key
+fn lock_key(namespace: Namespace, key: i64) -> i64 {
+    let mask = (1i64 << KEY_BITS) - 1;
+
+    ((namespace as i64) << KEY_BITS) | (key & mask)
+}
+
+/// waits until the lock for the given namespace and key is acquired
+pub async fn acquire<K>(conn: &impl GenericClient, namespace: Namespace, key: K) -> Result<(), PgError>
+where
+    K: Into<i64>
+{
+    let key = lock_key(namespace, key.into());
+
+    conn.execute("select pg_advisory_xact_lock($1)", &[&key]).await?;
+
+    Ok(())
+}
+
+/// attempts to acquire the lock for the given namespace and key without
+/// waiting
+///
+/// returns false if the lock is held by another transaction
+pub async fn try_acquire<K>(conn: &impl GenericClient, namespace: Namespace, key: K) -> Result<bool, PgError>
+where
+    K: Into<i64>
+{
+    let key = lock_key(namespace, key.into());
+
+    conn.query_one("select pg_try_advisory_xact_lock($1)", &[&key])
+        .await
+        .map(|row| row.get(0))
+}
@@ -11,11 +11,13 @@ use crate::user::{User, Group, assign_user_group};
 use crate::sec::password;
 use crate::sec::authz::{Role, Scope, Ability};
 use crate::state;
+use crate::workspace::{Workspace, WorkspaceUser};
 
 pub async fn create(
     state: &state::SharedState,
     conn: &impl GenericClient,
-    rng: &mut ThreadRng
+    rng: &mut ThreadRng,
+    workspace: &Workspace,
 ) -> Result<(), Error> {
     let password = "password";
 
@@ -61,7 +63,11 @@ pub async fn create(
             .await
             .context("failed to assign test user to journalists group")?;
 
-        create_journal(state, conn, rng, user.id).await?;
+        WorkspaceUser::upsert(conn, &workspace.id, &user.id, false)
+            .await
+            .context("failed to add test user to workspace")?;
+
+        create_journal(state, conn, rng, workspace, user.id).await?;
     }
 
     Ok(())
@@ -71,9 +77,10 @@ pub async fn create_journal(
     state: &state::SharedState,
     conn: &impl GenericClient,
     rng: &mut ThreadRng,
+    workspace: &Workspace,
     users_id: ids::UserId
 ) -> Result<(), Error> {
-    let options = Journal::create_options(users_id, "default")
+    let options = Journal::create_options(workspace.id, users_id, "default")
         .description("the default journal");
     let journal = Journal::create(conn, options)
         .await
@@ -86,6 +93,7 @@ pub async fn create_journal(
             custom_field::Type::Integer {
                 minimum: Some(1),
                 maximum: Some(10),
+                unit: None,
             }
         ))
             .await
@@ -99,6 +107,41 @@ pub async fn create_journal(
         ))
             .await
             .context("failed to create sleep field for journal")?,
+        CustomField::create_field(conn, CustomFieldOptions::new(
+            journal.id,
+            "weather",
+            custom_field::Type::Select {
+                options: ["sunny", "cloudy", "rainy", "snowy"]
+                    .into_iter()
+                    .map(|value| custom_field::SelectOption {
+                        value: value.to_owned(),
+                        color: None,
+                    })
+                    .collect(),
+            }
+        ))
+            .await
+            .context("failed to create weather field for journal")?,
+        CustomField::create_field(conn, CustomFieldOptions::new(
+            journal.id,
+            "exercised",
+            custom_field::Type::Boolean {
+                default: false,
+                label: Some(String::from("went for a run or to the gym")),
+            }
+        ))
+            .await
+            .context("failed to create exercised field for journal")?,
+        CustomField::create_field(conn, CustomFieldOptions::new(
+            journal.id,
+            "gratitude",
+            custom_field::Type::Text {
+                minimum: None,
+                maximum: Some(500),
+            }
+        ))
+            .await
+            .context("failed to create gratitude field for journal")?,
     ];
 
     let journal_dir = state.storage()
@@ -142,15 +185,20 @@ async fn create_journal_entry(
     let updated = gen_updated(rng, dist, date);
     let title = gen_entry_title(rng, dist);
 
+    let number = Journal::next_entry_number(conn, &journals_id)
+        .await
+        .context("failed to retrieve next entry number")?;
+
     let result = conn.query_one(
         "\
-        insert into entries (uid, journals_id, users_id, title, entry_date, created, updated) \
-        values ($1, $2, $3, $4, $5, $6, $7) \
+        insert into entries (uid, journals_id, users_id, number, title, entry_date, created, updated) \
+        values ($1, $2, $3, $4, $5, $6, $7, $8) \
         returning id",
         &[
             &uid,
             &journals_id,
             &users_id,
+            &number,
             &title,
             &date,
             &created,
@@ -288,6 +336,7 @@ fn gen_custom_field_value(
         custom_field::Type::Integer {
             minimum,
             maximum,
+            ..
         } => match (minimum, maximum) {
             (Some(min), Some(max)) => {
                 let value = rng.gen_range(*min..*max);
@@ -315,6 +364,7 @@ fn gen_custom_field_value(
         custom_field::Type::IntegerRange {
             minimum,
             maximum,
+            ..
         } => match (minimum, maximum) {
             (Some(min), Some(max)) => {
                 let diff = *max - *min;
@@ -470,5 +520,25 @@ fn gen_custom_field_value(
 
             custom_field::Value::TimeRange { low, high }
         }
+        custom_field::Type::Select { options } => {
+            let index = rng.gen_range(0..options.len());
+
+            custom_field::Value::Select {
+                value: options[index].value.clone()
+            }
+        }
+        custom_field::Type::Boolean { .. } => {
+            custom_field::Value::Boolean {
+                value: rng.gen_bool(0.5)
+            }
+        }
+        custom_field::Type::Text { .. } => {
+            let options = ["a good cup of coffee", "a long walk", "dinner with friends"];
+            let index = rng.gen_range(0..options.len());
+
+            custom_field::Value::Text {
+                value: options[index].to_owned()
+            }
+        }
     }
 }
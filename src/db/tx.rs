@@ -0,0 +1,125 @@
+//! request scoped database transactions
+//!
+//! the [`finalize`] middleware gives every request a slot for a transaction.
+//! the first [`Tx`] extracted for a request starts the transaction and once
+//! the handler has responded the middleware will commit it for successful
+//! and redirect responses and roll it back for everything else. handlers
+//! that need to undo other work, like files written to storage, when the
+//! commit fails can call [`Tx::commit`] themselves
+
+use std::ops::Deref;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use axum::extract::{FromRequestParts, Request};
+use axum::http::request::Parts;
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use tokio::sync::{Mutex, OwnedMutexGuard};
+
+use crate::error::{self, Context};
+use crate::state;
+
+use super::{Object, PgError};
+
+/// the connection of an open transaction
+///
+/// if this is dropped while the transaction is still open, the connection
+/// is removed from the pool so that it is closed and the database rolls back
+/// the transaction instead of another request reusing it
+#[derive(Default)]
+struct Open(Option<Object>);
+
+impl Drop for Open {
+    fn drop(&mut self) {
+        if let Some(conn) = self.0.take() {
+            drop(Object::take(conn));
+        }
+    }
+}
+
+#[derive(Clone, Default)]
+struct Slot(Arc<Mutex<Open>>);
+
+/// a database transaction that lasts for the rest of the request
+///
+/// derefs to the connection that the transaction is running on
+pub struct Tx(OwnedMutexGuard<Open>);
+
+impl Tx {
+    /// commits the transaction before the handler responds
+    pub async fn commit(mut self) -> Result<(), PgError> {
+        let Some(conn) = self.0.0.take() else {
+            return Ok(());
+        };
+
+        conn.batch_execute("commit").await
+    }
+}
+
+impl Deref for Tx {
+    type Target = Object;
+
+    fn deref(&self) -> &Self::Target {
+        self.0.0.as_ref()
+            .expect("transaction connection is only taken when committing")
+    }
+}
+
+#[async_trait]
+impl FromRequestParts<state::SharedState> for Tx {
+    type Rejection = error::Error;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &state::SharedState,
+    ) -> Result<Self, Self::Rejection> {
+        let slot = parts.extensions.get::<Slot>()
+            .cloned()
+            .context("transaction middleware is not applied to the request")?;
+
+        let mut guard = slot.0.try_lock_owned()
+            .context("transaction is already in use by the request")?;
+
+        if guard.0.is_none() {
+            let conn = state.db_conn().await?;
+
+            conn.batch_execute("begin")
+                .await
+                .context("failed to create transaction")?;
+
+            guard.0 = Some(conn);
+        }
+
+        Ok(Tx(guard))
+    }
+}
+
+/// commits or rolls back the transaction of the request depending on the
+/// status of the response
+pub async fn finalize(mut req: Request, next: Next) -> Response {
+    let slot = Slot::default();
+
+    req.extensions_mut().insert(slot.clone());
+
+    let response = next.run(req).await;
+
+    let Some(conn) = slot.0.lock().await.0.take() else {
+        return response;
+    };
+
+    let status = response.status();
+
+    if status.is_success() || status.is_redirection() {
+        if let Err(err) = conn.batch_execute("commit").await {
+            return error::Error::context_source("failed to commit transaction", err)
+                .into_response();
+        }
+    } else if let Err(err) = conn.batch_execute("rollback").await {
+        error::log_prefix_error("failed to rollback transaction", &err);
+
+        drop(Object::take(conn));
+    }
+
+    response
+}
@@ -0,0 +1,124 @@
+//! outgoing emails
+//!
+//! emails are sent through the smtp server specified in the config. if one
+//! is not specified then the messages are written to the server logs for an
+//! operator to forward
+
+use lettre::message::Mailbox;
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Tokio1Executor};
+
+use crate::config::{Smtp, SmtpTls};
+use crate::error::{self, Context};
+
+/// a plain text email waiting to be sent
+#[derive(Debug)]
+pub struct Message {
+    pub to: String,
+    pub subject: String,
+    pub body: String,
+}
+
+impl Message {
+    pub fn new<T, S, B>(to: T, subject: S, body: B) -> Self
+    where
+        T: Into<String>,
+        S: Into<String>,
+        B: Into<String>,
+    {
+        Message {
+            to: to.into(),
+            subject: subject.into(),
+            body: body.into(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Mailer {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+    from: Mailbox,
+    alert_to: Vec<String>,
+}
+
+impl std::fmt::Debug for Mailer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Mailer")
+            .field("from", &self.from)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Mailer {
+    pub fn from_config(smtp: &Smtp) -> Result<Self, error::Error> {
+        let mut builder = match smtp.tls {
+            SmtpTls::Starttls => AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&smtp.host)
+                .context("failed to create smtp transport")?,
+            SmtpTls::Tls => AsyncSmtpTransport::<Tokio1Executor>::relay(&smtp.host)
+                .context("failed to create smtp transport")?,
+            SmtpTls::None => AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(&smtp.host),
+        };
+
+        builder = builder.port(smtp.port);
+
+        if let Some((username, password)) = &smtp.credentials {
+            builder = builder.credentials(Credentials::new(username.clone(), password.clone()));
+        }
+
+        let from = smtp.from.parse()
+            .context("invalid smtp from address")?;
+
+        Ok(Mailer {
+            transport: builder.build(),
+            from,
+            alert_to: smtp.alert_to.clone(),
+        })
+    }
+
+    /// the addresses that server alerts are sent to
+    pub fn alert_to(&self) -> &[String] {
+        &self.alert_to
+    }
+
+    pub async fn send(&self, message: Message) -> Result<(), error::Error> {
+        let to: Mailbox = message.to.parse()
+            .context("invalid email address")?;
+
+        let email = lettre::Message::builder()
+            .from(self.from.clone())
+            .to(to)
+            .subject(message.subject)
+            .body(message.body)
+            .context("failed to build email")?;
+
+        self.transport.send(email)
+            .await
+            .context("failed to send email")?;
+
+        Ok(())
+    }
+}
+
+/// sends the message in the background
+///
+/// without a mailer only the recipient and subject are logged. the body is
+/// never logged since it can hold tokens, ex: a recovery link
+pub fn deliver(mailer: Option<&Mailer>, message: Message) {
+    let Some(mailer) = mailer.cloned() else {
+        tracing::info!(
+            to = message.to,
+            subject = message.subject,
+            "no mail transport available, email was not sent"
+        );
+
+        return;
+    };
+
+    tokio::spawn(async move {
+        let to = message.to.clone();
+
+        if let Err(err) = mailer.send(message).await {
+            error::log_prefix_error(&format!("failed to send email to {to}"), &err);
+        }
+    });
+}
@@ -131,6 +131,20 @@ where
     tracing::error!("{prefix}:\n{msg}");
 }
 
+/// creates a single line description of the error and its sources
+pub fn describe(err: &dyn std::error::Error) -> String {
+    let mut rtn = err.to_string();
+    let mut source = err.source();
+
+    while let Some(src) = source {
+        let _ = write!(rtn, ": {src}");
+
+        source = src.source();
+    }
+
+    rtn
+}
+
 /// wrapper method to just log an error
 pub fn log_error<E>(err: &E)
 where
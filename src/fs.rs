@@ -1,16 +1,51 @@
 use std::io::{ErrorKind, Error as IoError};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::pin::Pin;
 use std::task::{Poll, Context as TaskContext};
 
 use futures::stream::{StreamExt, FuturesOrdered};
 use pin_project::pin_project;
+use serde::Serialize;
 use tokio::fs::{File, OpenOptions};
 use tokio::io::AsyncWrite;
 
 use crate::error;
 use crate::path::{add_extension, tokio_metadata};
 
+/// there is not enough free space to store a file without going below the
+/// storage reserve
+#[derive(Debug, Clone, Serialize)]
+pub struct InsufficientStorage {
+    pub available: u64,
+    pub needed: u64,
+    pub reserve: u64,
+}
+
+/// retrieves the number of bytes available to the server on the file system
+/// of the given path
+pub async fn available_space(path: PathBuf) -> Result<u64, IoError> {
+    tokio::task::spawn_blocking(move || fs4::available_space(path))
+        .await
+        .map_err(IoError::other)?
+}
+
+/// checks if the error or any of its sources is from a full file system
+pub fn is_storage_full(err: &(dyn std::error::Error + 'static)) -> bool {
+    let mut current = Some(err);
+
+    while let Some(err) = current {
+        if let Some(io_err) = err.downcast_ref::<IoError>() {
+            if io_err.kind() == ErrorKind::StorageFull {
+                return true;
+            }
+        }
+
+        current = err.source();
+    }
+
+    false
+}
+
 /// the possible error variants when working with a FileUpdater struct
 #[derive(Debug, thiserror::Error)]
 pub enum FileUpdaterError {
@@ -121,6 +156,11 @@ impl FileUpdater {
         })
     }
 
+    /// the path of the file that is being written to
+    pub fn temp_path(&self) -> &Path {
+        &self.temp
+    }
+
     /// attempst to update the current file with new data written into "temp"
     pub async fn update(self) -> Result<UpdatedFile, UpdateError> {
         if let Err(err) = tokio::fs::rename(&self.curr, &self.prev).await {
@@ -321,6 +361,11 @@ impl RemovedFile {
         })
     }
 
+    /// the path that the file was moved to
+    pub fn marked(&self) -> &Path {
+        &self.mark
+    }
+
     /// attempts to remove the marked file
     pub async fn clean(self) -> Result<(), (Self, std::io::Error)> {
         if let Err(err) = tokio::fs::remove_file(&self.mark).await {
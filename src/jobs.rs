@@ -0,0 +1,1057 @@
+//! background tasks that run for the lifetime of the server
+//!
+//! jobs are run by the [`schedule`] module using the cron expressions stored
+//! in the jobs table
+
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::str::FromStr;
+
+use bytes::BytesMut;
+use chrono::{Days, Utc};
+use postgres_types as pg_types;
+use serde::{Serialize, Deserialize};
+
+use crate::db::lock;
+use crate::db::ids::{JournalId, UserId};
+use crate::error::{self, Context, BoxDynError};
+use crate::email::{self, Message};
+use crate::journal::{custom_field, Entry, FileEntry, Journal};
+use crate::journal::audio;
+use crate::journal::delete::JournalDeletion;
+use crate::journal::extract::{self, Pending, Source};
+use crate::journal::import::{FieldNames, FileOptions, JournalImport};
+use crate::journal::live::LiveEvent;
+use crate::journal::revision::Revision;
+use crate::journal::upload::UploadPolicy;
+use crate::journal::weather;
+use crate::journal::webhook::{self, ClaimedDelivery, Delivery, WebhookEvent};
+use crate::reminder::{Reminder, ReminderNotify};
+use crate::report::UsageReport;
+use crate::sec::authn::recovery::RecoveryEmail;
+use crate::sec::network;
+use crate::state;
+use crate::telemetry;
+
+pub mod schedule;
+
+use schedule::{Job, JobStatus};
+
+/// the advisory lock key for releasing planned entries
+const PLANNED_ROLLOVER_LOCK: i64 = 1;
+
+/// the advisory lock key for generating usage reports
+const USAGE_REPORT_LOCK: i64 = 2;
+
+/// the advisory lock key for sending webhook deliveries
+const WEBHOOK_DELIVERY_LOCK: i64 = 3;
+
+/// the advisory lock key for sending telemetry reports
+const TELEMETRY_LOCK: i64 = 4;
+
+/// the advisory lock key for sending daily reminders
+const REMINDERS_LOCK: i64 = 5;
+
+/// the advisory lock key for extracting the text of files
+const TEXT_EXTRACTION_LOCK: i64 = 6;
+
+/// the advisory lock key for transcribing audio files
+const TRANSCRIPTION_LOCK: i64 = 7;
+
+/// the advisory lock key for filling in the weather of entries
+const WEATHER_LOCK: i64 = 8;
+
+/// the advisory lock key for claiming uploaded imports
+const IMPORTS_LOCK: i64 = 9;
+
+/// the advisory lock key for removing the directories of deleted journals
+const JOURNAL_CLEANUP_LOCK: i64 = 10;
+
+/// the schedule for jobs that run at the start of every UTC day
+const DAILY: &str = "0 0 0 * * *";
+
+/// the schedule for jobs that run every fifteen minutes
+const EVERY_FIFTEEN_MINUTES: &str = "0 */15 * * * *";
+
+/// the schedule for jobs that run at the start of every minute
+const EVERY_MINUTE: &str = "0 * * * * *";
+
+#[derive(Debug, thiserror::Error)]
+#[error("the provided string is not a valid job kind")]
+pub struct InvalidJobKind;
+
+/// the jobs that the server knows how to run
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JobKind {
+    /// marks planned entries as normal entries when their date arrives
+    PlannedRollover,
+
+    /// records the usage report for the previous UTC day
+    UsageReport,
+
+    /// fails when the free space of the storage directory is below the
+    /// reserve so that it is visible to admins in the jobs list
+    StorageCheck,
+
+    /// sends pending webhook deliveries and retries failed ones
+    WebhookDelivery,
+
+    /// sends the usage telemetry report if it is enabled
+    Telemetry,
+
+    /// notifies users that have not written an entry for the day
+    Reminders,
+
+    /// extracts the searchable text of uploaded files
+    TextExtraction,
+
+    /// transcribes uploaded audio files if transcription is configured
+    Transcription,
+
+    /// fills in the weather of new entries if weather is configured
+    Weather,
+
+    /// adds the entries of uploaded imports to their journals
+    Imports,
+
+    /// removes the directories of deleted journals
+    JournalCleanup,
+}
+
+impl JobKind {
+    pub const ALL: [JobKind; 11] = [
+        JobKind::PlannedRollover,
+        JobKind::UsageReport,
+        JobKind::StorageCheck,
+        JobKind::WebhookDelivery,
+        JobKind::Telemetry,
+        JobKind::Reminders,
+        JobKind::TextExtraction,
+        JobKind::Transcription,
+        JobKind::Weather,
+        JobKind::Imports,
+        JobKind::JournalCleanup,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            JobKind::PlannedRollover => "planned_rollover",
+            JobKind::UsageReport => "usage_report",
+            JobKind::StorageCheck => "storage_check",
+            JobKind::WebhookDelivery => "webhook_delivery",
+            JobKind::Telemetry => "telemetry",
+            JobKind::Reminders => "reminders",
+            JobKind::TextExtraction => "text_extraction",
+            JobKind::Transcription => "transcription",
+            JobKind::Weather => "weather",
+            JobKind::Imports => "imports",
+            JobKind::JournalCleanup => "journal_cleanup",
+        }
+    }
+
+    /// the cron expression used when the job is first added to the jobs
+    /// table
+    pub fn default_schedule(&self) -> &'static str {
+        match self {
+            JobKind::PlannedRollover => DAILY,
+            JobKind::UsageReport => DAILY,
+            JobKind::StorageCheck => EVERY_FIFTEEN_MINUTES,
+            JobKind::WebhookDelivery => EVERY_MINUTE,
+            JobKind::Telemetry => DAILY,
+            JobKind::Reminders => EVERY_FIFTEEN_MINUTES,
+            JobKind::TextExtraction => EVERY_MINUTE,
+            JobKind::Transcription => EVERY_MINUTE,
+            JobKind::Weather => EVERY_FIFTEEN_MINUTES,
+            JobKind::Imports => EVERY_MINUTE,
+            JobKind::JournalCleanup => EVERY_FIFTEEN_MINUTES,
+        }
+    }
+
+    /// runs the job
+    ///
+    /// returns false if the job was skipped because another instance is
+    /// already running it
+    pub async fn run(&self, state: &state::SharedState) -> Result<bool, error::Error> {
+        match self {
+            JobKind::PlannedRollover => release_planned(state).await,
+            JobKind::UsageReport => generate_usage_report(state).await,
+            JobKind::StorageCheck => check_storage(state).await,
+            JobKind::WebhookDelivery => send_webhooks(state).await,
+            JobKind::Telemetry => send_telemetry(state).await,
+            JobKind::Reminders => send_reminders(state).await,
+            JobKind::TextExtraction => extract_text(state).await,
+            JobKind::Transcription => transcribe_audio(state).await,
+            JobKind::Weather => fill_weather(state).await,
+            JobKind::Imports => run_imports(state).await,
+            JobKind::JournalCleanup => clean_journals(state).await,
+        }
+    }
+}
+
+impl Display for JobKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for JobKind {
+    type Err = InvalidJobKind;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "planned_rollover" => Ok(JobKind::PlannedRollover),
+            "usage_report" => Ok(JobKind::UsageReport),
+            "storage_check" => Ok(JobKind::StorageCheck),
+            "webhook_delivery" => Ok(JobKind::WebhookDelivery),
+            "telemetry" => Ok(JobKind::Telemetry),
+            "reminders" => Ok(JobKind::Reminders),
+            "text_extraction" => Ok(JobKind::TextExtraction),
+            "transcription" => Ok(JobKind::Transcription),
+            "weather" => Ok(JobKind::Weather),
+            "imports" => Ok(JobKind::Imports),
+            "journal_cleanup" => Ok(JobKind::JournalCleanup),
+            _ => Err(InvalidJobKind)
+        }
+    }
+}
+
+impl<'a> pg_types::FromSql<'a> for JobKind {
+    fn from_sql(ty: &pg_types::Type, raw: &'a [u8]) -> Result<Self, BoxDynError> {
+        let v = <&str as pg_types::FromSql>::from_sql(ty, raw)?;
+
+        Ok(Self::from_str(v)?)
+    }
+
+    fn accepts(ty: &pg_types::Type) -> bool {
+        <&str as pg_types::FromSql>::accepts(ty)
+    }
+}
+
+impl pg_types::ToSql for JobKind {
+    fn to_sql(&self, ty: &pg_types::Type, w: &mut BytesMut) -> Result<pg_types::IsNull, BoxDynError> {
+        self.as_str()
+            .to_sql(ty, w)
+    }
+
+    fn accepts(ty: &pg_types::Type) -> bool {
+        <&str as pg_types::ToSql>::accepts(ty)
+    }
+
+    pg_types::to_sql_checked!();
+}
+
+/// starts all background tasks for the server
+pub fn start(state: &state::SharedState) {
+    tokio::spawn(schedule::run(state.clone()));
+}
+
+async fn release_planned(state: &state::SharedState) -> Result<bool, error::Error> {
+    let mut conn = state.db_conn().await?;
+    let transaction = conn.transaction()
+        .await
+        .context("failed to create transaction")?;
+
+    // another instance is already running the job
+    if !lock::try_acquire(&transaction, lock::Namespace::Job, PLANNED_ROLLOVER_LOCK)
+        .await
+        .context("failed to acquire planned rollover lock")? {
+        return Ok(false);
+    }
+
+    let released = Entry::release_planned(&transaction)
+        .await
+        .context("failed to release planned entries")?;
+
+    transaction.commit()
+        .await
+        .context("failed to commit transaction")?;
+
+    if released > 0 {
+        tracing::info!("released {released} planned entries");
+    }
+
+    Ok(true)
+}
+
+async fn generate_usage_report(state: &state::SharedState) -> Result<bool, error::Error> {
+    let Some(yesterday) = Utc::now().date_naive().checked_sub_days(Days::new(1)) else {
+        return Ok(true);
+    };
+
+    let mut conn = state.db_conn().await?;
+    let transaction = conn.transaction()
+        .await
+        .context("failed to create transaction")?;
+
+    // another instance is already running the job
+    if !lock::try_acquire(&transaction, lock::Namespace::Job, USAGE_REPORT_LOCK)
+        .await
+        .context("failed to acquire usage report lock")? {
+        return Ok(false);
+    }
+
+    let exists = UsageReport::exists(&transaction, &yesterday)
+        .await
+        .context("failed to check for existing usage report")?;
+
+    if !exists {
+        UsageReport::generate(&transaction, &yesterday)
+            .await
+            .context("failed to generate usage report")?;
+
+        transaction.commit()
+            .await
+            .context("failed to commit transaction")?;
+
+        tracing::info!("generated usage report for {yesterday}");
+    }
+
+    Ok(true)
+}
+
+/// fails while the free space of the storage directory is below the reserve
+///
+/// the alert addresses of the smtp config are emailed when the free space
+/// first drops below the reserve. no more alerts are sent until a check
+/// succeeds again
+async fn check_storage(state: &state::SharedState) -> Result<bool, error::Error> {
+    let Some(details) = state.storage().check_space(0).await? else {
+        return Ok(true);
+    };
+
+    tracing::error!(
+        available = details.available,
+        reserve = details.reserve,
+        "storage free space is below the reserve"
+    );
+
+    let conn = state.db_conn().await?;
+
+    let previous = Job::retrieve(&conn, &JobKind::StorageCheck)
+        .await
+        .context("failed to retrieve storage check job")?;
+
+    // the last check already found the free space below the reserve
+    let alerted = previous.is_some_and(|job| job.last_status == Some(JobStatus::Failed));
+
+    if let Some(mailer) = state.mailer().filter(|_| !alerted) {
+        for to in mailer.alert_to() {
+            email::deliver(Some(mailer), Message::new(
+                to,
+                "TJ2: storage space is low",
+                format!(
+                    "The free space of the storage directory ({} bytes) is below the reserve ({} bytes).\n\
+                    Uploads that would go below the reserve are rejected until space is freed.\n",
+                    details.available,
+                    details.reserve,
+                )
+            ));
+        }
+    }
+
+    Err(error::Error::context(format!(
+        "storage free space ({} bytes) is below the reserve ({} bytes)",
+        details.available,
+        details.reserve,
+    )))
+}
+
+/// sends the pending webhook deliveries
+///
+/// a batch is claimed and committed before sending so that the transaction
+/// and its connection are not held while waiting on the targets. each result
+/// is then recorded on its own
+async fn send_webhooks(state: &state::SharedState) -> Result<bool, error::Error> {
+    let ready = {
+        let mut conn = state.db_conn().await?;
+        let transaction = conn.transaction()
+            .await
+            .context("failed to create transaction")?;
+
+        // another instance is already claiming deliveries
+        if !lock::try_acquire(&transaction, lock::Namespace::Job, WEBHOOK_DELIVERY_LOCK)
+            .await
+            .context("failed to acquire webhook delivery lock")? {
+            return Ok(false);
+        }
+
+        let ready = Delivery::claim_ready(&transaction, &Utc::now())
+            .await
+            .context("failed to claim pending webhook deliveries")?;
+
+        transaction.commit()
+            .await
+            .context("failed to commit transaction")?;
+
+        ready
+    };
+
+    let allow = &state.network().outbound_allow;
+
+    for ClaimedDelivery { mut delivery, url, secret, previous_secret } in ready {
+        let body = serde_json::to_vec(&delivery.payload)
+            .context("failed to serialize webhook payload")?;
+        let signature = webhook::signature_header(&secret, previous_secret.as_deref(), &body);
+
+        let checked = match url::Url::parse(&url) {
+            Ok(parsed) => network::check_url(allow, &parsed)
+                .await
+                .map_err(|err| err.to_string()),
+            Err(err) => Err(err.to_string()),
+        };
+
+        let started = std::time::Instant::now();
+
+        let (response_status, error) = match checked {
+            Ok(()) => {
+                let result = state.outbound_http()
+                    .post(&url)
+                    .header("content-type", "application/json")
+                    .header(webhook::EVENT_HEADER, delivery.event.as_str())
+                    .header(webhook::DELIVERY_HEADER, delivery.id.to_string())
+                    .header(webhook::SIGNATURE_HEADER, signature)
+                    .body(body)
+                    .send()
+                    .await;
+
+                match result {
+                    Ok(res) if res.status().is_success() => (Some(res.status().as_u16() as i32), None),
+                    Ok(res) => (
+                        Some(res.status().as_u16() as i32),
+                        Some(format!("target responded with {}", res.status()))
+                    ),
+                    Err(err) => (None, Some(err.to_string())),
+                }
+            }
+            Err(err) => (None, Some(err)),
+        };
+
+        // attempts without a response do not have a latency
+        let latency_ms = response_status.map(|_| started.elapsed().as_millis().min(i32::MAX as u128) as i32);
+
+        if let Some(err) = &error {
+            tracing::warn!(
+                delivery = %delivery.id,
+                attempts = delivery.attempts + 1,
+                "webhook delivery failed: {err}"
+            );
+        }
+
+        let conn = state.db_conn().await?;
+
+        delivery.record_attempt(&conn, response_status, latency_ms, error)
+            .await
+            .context("failed to record webhook delivery attempt")?;
+    }
+
+    Ok(true)
+}
+
+async fn send_telemetry(state: &state::SharedState) -> Result<bool, error::Error> {
+    let telemetry = state.telemetry();
+
+    let Some(endpoint) = telemetry.endpoint.as_ref().filter(|_| telemetry.enabled) else {
+        return Ok(true);
+    };
+
+    let mut conn = state.db_conn().await?;
+    let transaction = conn.transaction()
+        .await
+        .context("failed to create transaction")?;
+
+    // another instance is already running the job
+    if !lock::try_acquire(&transaction, lock::Namespace::Job, TELEMETRY_LOCK)
+        .await
+        .context("failed to acquire telemetry lock")? {
+        return Ok(false);
+    }
+
+    let report = telemetry::Report::collect(state, &transaction).await?;
+    let body = serde_json::to_vec(&report)
+        .context("failed to serialize telemetry report")?;
+
+    state.http()
+        .post(endpoint.clone())
+        .header("content-type", "application/json")
+        .body(body)
+        .send()
+        .await
+        .context("failed to send telemetry report")?
+        .error_for_status()
+        .context("telemetry endpoint rejected report")?;
+
+    tracing::debug!("sent telemetry report");
+
+    Ok(true)
+}
+
+async fn send_reminders(state: &state::SharedState) -> Result<bool, error::Error> {
+    let mut conn = state.db_conn().await?;
+    let transaction = conn.transaction()
+        .await
+        .context("failed to create transaction")?;
+
+    // another instance is already running the job
+    if !lock::try_acquire(&transaction, lock::Namespace::Job, REMINDERS_LOCK)
+        .await
+        .context("failed to acquire reminders lock")? {
+        return Ok(false);
+    }
+
+    let now = Utc::now();
+    let reminders = Reminder::retrieve_enabled(&transaction)
+        .await
+        .context("failed to retrieve reminders")?;
+    let mut messages = Vec::new();
+
+    for mut reminder in reminders {
+        let Some(today) = reminder.is_due(&now) else {
+            continue;
+        };
+
+        let has_entry = reminder.has_entry(&transaction, &today)
+            .await
+            .context("failed to check for entry")?;
+
+        if !has_entry {
+            let journal = Journal::retrieve_id(&transaction, &reminder.journals_id, &reminder.users_id)
+                .await
+                .context("failed to retrieve journal")?;
+
+            match (reminder.notify, journal) {
+                (ReminderNotify::Email, Some(journal)) => {
+                    let backup = RecoveryEmail::retrieve(&transaction, &reminder.users_id)
+                        .await
+                        .context("failed to retrieve backup email")?;
+
+                    if let Some(backup) = backup.filter(|e| e.verified.is_some()) {
+                        messages.push(Message::new(
+                            backup.email,
+                            format!("Reminder: write in {}", journal.name),
+                            format!(
+                                "You have not written an entry in {} for {today}.\n",
+                                journal.name
+                            )
+                        ));
+                    } else {
+                        tracing::warn!(
+                            users_id = %reminder.users_id,
+                            "no verified backup email to send reminder to"
+                        );
+                    }
+                }
+                (ReminderNotify::Webhook, Some(journal)) => {
+                    webhook::queue(
+                        &transaction,
+                        &journal.id,
+                        WebhookEvent::ReminderDue,
+                        &serde_json::json!({
+                            "users_id": reminder.users_id,
+                            "date": today,
+                        })
+                    )
+                        .await
+                        .context("failed to queue reminder webhook")?;
+                }
+                (_, None) => {
+                    tracing::warn!(
+                        users_id = %reminder.users_id,
+                        journals_id = %reminder.journals_id,
+                        "journal for reminder was not found"
+                    );
+                }
+            }
+        }
+
+        reminder.mark_sent(&transaction, today)
+            .await
+            .context("failed to mark reminder as sent")?;
+    }
+
+    transaction.commit()
+        .await
+        .context("failed to commit transaction")?;
+
+    for message in messages {
+        email::deliver(state.mailer(), message);
+    }
+
+    Ok(true)
+}
+
+/// extracts the text of pending file entries
+///
+/// a batch is claimed and committed before extracting so that the
+/// transaction and its connection are not held while the external commands
+/// run. each result is then stored on its own
+async fn extract_text(state: &state::SharedState) -> Result<bool, error::Error> {
+    let pending = {
+        let mut conn = state.db_conn().await?;
+        let transaction = conn.transaction()
+            .await
+            .context("failed to create transaction")?;
+
+        // another instance is already claiming files
+        if !lock::try_acquire(&transaction, lock::Namespace::Job, TEXT_EXTRACTION_LOCK)
+            .await
+            .context("failed to acquire text extraction lock")? {
+            return Ok(false);
+        }
+
+        let pending = extract::claim_pending(&transaction, state.extraction(), &Utc::now(), extract::BATCH_SIZE)
+            .await
+            .context("failed to claim pending file entries")?;
+
+        transaction.commit()
+            .await
+            .context("failed to commit transaction")?;
+
+        pending
+    };
+
+    for file in pending {
+        let result = extract_file(state, &file)
+            .await
+            .map_err(|err| {
+                let msg = error_chain(&err);
+
+                tracing::warn!(file_entry = %file.file_entry.id, "failed to extract text: {msg}");
+
+                msg
+            });
+
+        let conn = state.db_conn().await?;
+
+        extract::store(&conn, &file.file_entry.id, result)
+            .await
+            .context("failed to store extracted text")?;
+    }
+
+    Ok(true)
+}
+
+async fn extract_file(
+    state: &state::SharedState,
+    file: &Pending,
+) -> Result<String, error::Error> {
+    let source = Source::from_file_entry(&file.file_entry)
+        .context("file type is not supported")?;
+
+    if file.file_entry.size as u64 > state.extraction().max_size {
+        return Err(error::Error::context("file is larger than the max extraction size"));
+    }
+
+    let contents = read_file(state, &file.file_entry, &file.journals_id, &file.users_id).await?;
+
+    extract::extract(state.extraction(), source, contents).await
+}
+
+/// reads and decrypts the contents of a file entry. the connection used to
+/// retrieve the journal and its key is returned to the pool before the
+/// contents are given back
+async fn read_file(
+    state: &state::SharedState,
+    file_entry: &FileEntry,
+    journals_id: &JournalId,
+    users_id: &UserId,
+) -> Result<Vec<u8>, error::Error> {
+    let conn = state.db_conn().await?;
+
+    let journal = Journal::retrieve_id(&conn, journals_id, users_id)
+        .await
+        .context("failed to retrieve journal")?
+        .context("journal not found")?;
+
+    let path = state.storage()
+        .journal_dir(&journal)
+        .file_path(&file_entry.id);
+    let contents = tokio::fs::read(&path)
+        .await
+        .context("failed to read file")?;
+
+    if !file_entry.encrypted {
+        return Ok(contents);
+    }
+
+    let key = state.storage().require_journal_key(&conn, &journal).await?;
+
+    key.decrypt_all(&contents)
+        .context("failed to decrypt file")
+}
+
+/// joins the message of an error with the messages of its sources
+fn error_chain(err: &error::Error) -> String {
+    let mut msg = err.to_string();
+    let mut source = std::error::Error::source(err);
+
+    while let Some(err) = source {
+        msg.push_str(": ");
+        msg.push_str(&err.to_string());
+
+        source = err.source();
+    }
+
+    msg
+}
+
+/// transcribes pending audio files
+///
+/// a batch is claimed and committed before transcribing so that the
+/// transaction and its connection are not held while waiting on the
+/// transcriber. each result is then recorded in its own transaction
+async fn transcribe_audio(state: &state::SharedState) -> Result<bool, error::Error> {
+    // nothing to do if transcription is not configured
+    let Some(transcription) = state.transcription() else {
+        return Ok(true);
+    };
+
+    let pending = {
+        let mut conn = state.db_conn().await?;
+        let transaction = conn.transaction()
+            .await
+            .context("failed to create transaction")?;
+
+        // another instance is already claiming files
+        if !lock::try_acquire(&transaction, lock::Namespace::Job, TRANSCRIPTION_LOCK)
+            .await
+            .context("failed to acquire transcription lock")? {
+            return Ok(false);
+        }
+
+        let pending = audio::claim_pending(&transaction, &Utc::now(), audio::BATCH_SIZE)
+            .await
+            .context("failed to claim pending audio files")?;
+
+        transaction.commit()
+            .await
+            .context("failed to commit transaction")?;
+
+        pending
+    };
+
+    for file in pending {
+        let result = transcribe_file(state, &file).await;
+
+        let transcript = match &result {
+            Ok(transcript) => Ok(transcript.as_str()),
+            Err(err) => {
+                let msg = error_chain(err);
+
+                tracing::warn!(file_entry = %file.file_entry.id, "failed to transcribe audio: {msg}");
+
+                Err(msg)
+            }
+        };
+
+        let mut conn = state.db_conn().await?;
+        let transaction = conn.transaction()
+            .await
+            .context("failed to create transaction")?;
+
+        audio::store_transcript(&transaction, &file.file_entry.id, transcript.clone())
+            .await
+            .context("failed to store audio transcript")?;
+
+        let applied = match transcript {
+            Ok(transcript) if !transcript.is_empty() => {
+                // the entry is changed by the server on behalf of the
+                // journal owner so a revision is kept of what was there
+                // before
+                Revision::record(&transaction, &file.file_entry.entries_id, &file.users_id)
+                    .await
+                    .context("failed to record entry revision")?;
+
+                audio::apply_transcript(
+                    &transaction,
+                    &file.journals_id,
+                    &file.file_entry.entries_id,
+                    transcription.custom_field.as_deref(),
+                    transcript
+                )
+                    .await
+                    .context("failed to add transcript to entry")?;
+
+                true
+            }
+            _ => false,
+        };
+
+        transaction.commit()
+            .await
+            .context("failed to commit transaction")?;
+
+        if applied {
+            state.live().publish(LiveEvent::EntryUpdated {
+                journals_id: file.journals_id,
+                entries_id: file.file_entry.entries_id,
+            });
+        }
+    }
+
+    Ok(true)
+}
+
+async fn transcribe_file(
+    state: &state::SharedState,
+    file: &audio::Pending,
+) -> Result<String, error::Error> {
+    let transcription = state.transcription()
+        .context("transcription is not configured")?;
+
+    if file.file_entry.size as u64 > transcription.max_size {
+        return Err(error::Error::context("file is larger than the max transcription size"));
+    }
+
+    let contents = read_file(state, &file.file_entry, &file.journals_id, &file.users_id).await?;
+
+    audio::transcribe(state.http(), transcription, &file.file_entry, contents).await
+}
+
+/// fills in the weather of queued entries
+///
+/// a batch is claimed and committed before asking the provider so that the
+/// transaction and its connection are not held while waiting on it. each
+/// result is then recorded in its own transaction
+async fn fill_weather(state: &state::SharedState) -> Result<bool, error::Error> {
+    // nothing to do if weather is not configured
+    let Some(config) = state.weather() else {
+        return Ok(true);
+    };
+
+    let pending = {
+        let mut conn = state.db_conn().await?;
+        let transaction = conn.transaction()
+            .await
+            .context("failed to create transaction")?;
+
+        // another instance is already claiming entries
+        if !lock::try_acquire(&transaction, lock::Namespace::Job, WEATHER_LOCK)
+            .await
+            .context("failed to acquire weather lock")? {
+            return Ok(false);
+        }
+
+        let pending = weather::claim_pending(&transaction, &Utc::now(), weather::BATCH_SIZE)
+            .await
+            .context("failed to claim pending entries")?;
+
+        transaction.commit()
+            .await
+            .context("failed to commit transaction")?;
+
+        pending
+    };
+
+    for entry in pending {
+        let (custom_fields_id, location) = {
+            let conn = state.db_conn().await?;
+
+            let field = custom_field::Type::retrieve_text_field(&conn, &entry.journals_id, &config.weather_field)
+                .await
+                .context("failed to retrieve weather field")?;
+
+            let Some(custom_fields_id) = field else {
+                weather::store(&conn, &entry.entries_id, None, Err(String::from("journal does not have a weather field")))
+                    .await
+                    .context("failed to store entry weather")?;
+
+                continue;
+            };
+
+            let location = weather::retrieve_location(&conn, config, &entry)
+                .await
+                .context("failed to retrieve entry location")?;
+
+            let Some(location) = location else {
+                weather::store(&conn, &entry.entries_id, None, Err(String::from("entry does not have a location")))
+                    .await
+                    .context("failed to store entry weather")?;
+
+                continue;
+            };
+
+            (custom_fields_id, location)
+        };
+
+        let result = weather::fetch(state.http(), config, &location, &entry.date).await;
+
+        let summary = match &result {
+            Ok(summary) => Ok(summary.as_str()),
+            Err(err) => {
+                let msg = error_chain(err);
+
+                tracing::warn!(entry = %entry.entries_id, "failed to retrieve weather: {msg}");
+
+                Err(msg)
+            }
+        };
+
+        let mut conn = state.db_conn().await?;
+        let transaction = conn.transaction()
+            .await
+            .context("failed to create transaction")?;
+
+        weather::store(&transaction, &entry.entries_id, Some(&location), summary.clone())
+            .await
+            .context("failed to store entry weather")?;
+
+        let filled = match summary {
+            Ok(summary) if !summary.is_empty() => weather::fill_field(
+                &transaction,
+                &custom_fields_id,
+                &entry.entries_id,
+                summary
+            )
+                .await
+                .context("failed to fill weather field")?,
+            _ => false,
+        };
+
+        transaction.commit()
+            .await
+            .context("failed to commit transaction")?;
+
+        if filled {
+            state.live().publish(LiveEvent::EntryUpdated {
+                journals_id: entry.journals_id,
+                entries_id: entry.entries_id,
+            });
+        }
+    }
+
+    Ok(true)
+}
+
+async fn run_imports(state: &state::SharedState) -> Result<bool, error::Error> {
+    let mut conn = state.db_conn().await?;
+
+    loop {
+        // the lock is only held while claiming an import so that other
+        // instances can claim the next one while this one runs
+        let claimed = {
+            let transaction = conn.transaction()
+                .await
+                .context("failed to create transaction")?;
+
+            // another instance is already claiming an import
+            if !lock::try_acquire(&transaction, lock::Namespace::Job, IMPORTS_LOCK)
+                .await
+                .context("failed to acquire imports lock")? {
+                return Ok(false);
+            }
+
+            let claimed = JournalImport::claim_pending(&transaction)
+                .await
+                .context("failed to claim pending import")?;
+
+            transaction.commit()
+                .await
+                .context("failed to commit transaction")?;
+
+            claimed
+        };
+
+        let Some(mut import) = claimed else {
+            return Ok(true);
+        };
+
+        let journal = Journal::retrieve_accessible(&conn, &import.journals_id, &import.users_id)
+            .await
+            .context("failed to retrieve journal")?;
+
+        let result = match journal {
+            Some(journal) => run_import(state, &mut conn, &journal, &mut import).await,
+            None => Err(error::Error::context("journal not found")),
+        };
+
+        let updated = match result {
+            Ok(()) => import.mark_completed(&conn).await,
+            Err(err) => {
+                let msg = error_chain(&err);
+
+                tracing::warn!(import = %import.id, "failed to import entries: {msg}");
+
+                import.mark_failed(&conn, msg).await
+            }
+        };
+
+        updated.context("failed to update journal import")?;
+    }
+}
+
+async fn clean_journals(state: &state::SharedState) -> Result<bool, error::Error> {
+    let mut conn = state.db_conn().await?;
+    let transaction = conn.transaction()
+        .await
+        .context("failed to create transaction")?;
+
+    // another instance is already running the job
+    if !lock::try_acquire(&transaction, lock::Namespace::Job, JOURNAL_CLEANUP_LOCK)
+        .await
+        .context("failed to acquire journal cleanup lock")? {
+        return Ok(false);
+    }
+
+    let pending = JournalDeletion::retrieve_pending(&transaction)
+        .await
+        .context("failed to retrieve pending journal deletions")?;
+
+    let mut failed = 0;
+
+    for mut deletion in pending {
+        // a failed directory is tried again the next time the job runs
+        if let Err(err) = deletion.clean(&transaction).await {
+            error::log_prefix_error("failed to clean deleted journal", &err);
+
+            failed += 1;
+        } else {
+            tracing::info!(journals_id = %deletion.journals_id, "removed directory of deleted journal");
+        }
+    }
+
+    transaction.commit()
+        .await
+        .context("failed to commit transaction")?;
+
+    if failed > 0 {
+        return Err(error::Error::context(format!(
+            "failed to remove the directories of {failed} deleted journals"
+        )));
+    }
+
+    Ok(true)
+}
+
+async fn run_import(
+    state: &state::SharedState,
+    conn: &mut crate::db::Object,
+    journal: &Journal,
+    import: &mut JournalImport,
+) -> Result<(), error::Error> {
+    let journal_dir = state.storage().journal_dir(journal);
+    let key = state.storage().journal_key(&*conn, journal).await?;
+
+    let field_names = FieldNames::from_state(state);
+
+    let path = journal_dir.import_path(&import.id);
+
+    let policy = UploadPolicy::retrieve(&*conn, state.upload(), &journal.id)
+        .await
+        .context("failed to retrieve journal upload policy")?;
+
+    let files = FileOptions {
+        journal_dir: &journal_dir,
+        key: key.as_ref(),
+        storage: state.storage(),
+        policy: &policy,
+        scan: state.scan(),
+    };
+
+    let result = import.run(conn, journal, &field_names, &files, path.clone()).await;
+
+    if let Err(err) = tokio::fs::remove_file(&path).await {
+        error::log_prefix_error("failed to remove import upload", &err);
+    }
+
+    result
+}
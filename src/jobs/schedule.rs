@@ -0,0 +1,357 @@
+//! runs jobs using the cron expressions stored in the jobs table
+//!
+//! expressions use the format of the cron crate which includes a seconds
+//! field, e.g. "0 0 0 * * *" for the start of every UTC day. a job that has
+//! never run or missed its last scheduled time while the server was down is
+//! run as soon as the scheduler starts
+//!
+//! the schedule and enabled state of a job can be set in the `jobs` section
+//! of the config, which is applied to the jobs table when the scheduler
+//! starts. changes made through the admin api last until the next start
+
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::str::FromStr;
+use std::time::Instant;
+
+use bytes::BytesMut;
+use chrono::{DateTime, Utc};
+use cron::Schedule;
+use postgres_types as pg_types;
+use serde::Serialize;
+
+use crate::config;
+use crate::db::{GenericClient, PgError};
+use crate::error::{self, Context, BoxDynError};
+use crate::state;
+
+use super::JobKind;
+
+/// the max amount of time the scheduler waits before checking the jobs table
+/// for changes
+const MAX_WAIT: std::time::Duration = std::time::Duration::from_secs(60);
+
+/// the max number of characters stored from the error of a failed run
+const ERROR_LEN: usize = 1024;
+
+#[derive(Debug, thiserror::Error)]
+#[error("the provided string is not a valid job status")]
+pub struct InvalidJobStatus;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum JobStatus {
+    Success,
+    Failed,
+}
+
+impl JobStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            JobStatus::Success => "success",
+            JobStatus::Failed => "failed",
+        }
+    }
+}
+
+impl Display for JobStatus {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for JobStatus {
+    type Err = InvalidJobStatus;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "success" => Ok(JobStatus::Success),
+            "failed" => Ok(JobStatus::Failed),
+            _ => Err(InvalidJobStatus)
+        }
+    }
+}
+
+impl<'a> pg_types::FromSql<'a> for JobStatus {
+    fn from_sql(ty: &pg_types::Type, raw: &'a [u8]) -> Result<Self, BoxDynError> {
+        let v = <&str as pg_types::FromSql>::from_sql(ty, raw)?;
+
+        Ok(Self::from_str(v)?)
+    }
+
+    fn accepts(ty: &pg_types::Type) -> bool {
+        <&str as pg_types::FromSql>::accepts(ty)
+    }
+}
+
+impl pg_types::ToSql for JobStatus {
+    fn to_sql(&self, ty: &pg_types::Type, w: &mut BytesMut) -> Result<pg_types::IsNull, BoxDynError> {
+        self.as_str()
+            .to_sql(ty, w)
+    }
+
+    fn accepts(ty: &pg_types::Type) -> bool {
+        <&str as pg_types::ToSql>::accepts(ty)
+    }
+
+    pg_types::to_sql_checked!();
+}
+
+/// a job definition and the result of its last run
+#[derive(Debug, Serialize)]
+pub struct Job {
+    pub name: JobKind,
+    pub schedule: String,
+    pub enabled: bool,
+    pub last_run: Option<DateTime<Utc>>,
+    pub last_status: Option<JobStatus>,
+    pub last_error: Option<String>,
+
+    /// the number of milliseconds the last run took
+    pub last_duration: Option<i64>,
+}
+
+impl Job {
+    fn map_row(row: tokio_postgres::Row) -> Self {
+        Self {
+            name: row.get(0),
+            schedule: row.get(1),
+            enabled: row.get(2),
+            last_run: row.get(3),
+            last_status: row.get(4),
+            last_error: row.get(5),
+            last_duration: row.get(6),
+        }
+    }
+
+    /// adds any jobs that are missing from the jobs table with their default
+    /// schedules and then applies the job settings from the config
+    pub async fn seed(conn: &impl GenericClient, configured: &HashMap<JobKind, config::Job>) -> Result<(), PgError> {
+        for kind in JobKind::ALL {
+            conn.execute(
+                "\
+                insert into jobs (name, schedule) values \
+                ($1, $2) \
+                on conflict (name) do nothing",
+                &[&kind, &kind.default_schedule()]
+            ).await?;
+
+            if let Some(job) = configured.get(&kind) {
+                conn.execute(
+                    "\
+                    update jobs \
+                    set schedule = coalesce($2, schedule), \
+                        enabled = coalesce($3, enabled) \
+                    where name = $1",
+                    &[&kind, &job.schedule, &job.enabled]
+                ).await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    pub async fn retrieve_all(conn: &impl GenericClient) -> Result<Vec<Self>, PgError> {
+        let names: Vec<&str> = JobKind::ALL.iter()
+            .map(JobKind::as_str)
+            .collect();
+
+        let rows = conn.query(
+            "\
+            select jobs.name, \
+                   jobs.schedule, \
+                   jobs.enabled, \
+                   jobs.last_run, \
+                   jobs.last_status, \
+                   jobs.last_error, \
+                   jobs.last_duration \
+            from jobs \
+            where jobs.name = any($1) \
+            order by jobs.name",
+            &[&names]
+        ).await?;
+
+        Ok(rows.into_iter().map(Self::map_row).collect())
+    }
+
+    pub async fn retrieve(conn: &impl GenericClient, kind: &JobKind) -> Result<Option<Self>, PgError> {
+        conn.query_opt(
+            "\
+            select jobs.name, \
+                   jobs.schedule, \
+                   jobs.enabled, \
+                   jobs.last_run, \
+                   jobs.last_status, \
+                   jobs.last_error, \
+                   jobs.last_duration \
+            from jobs \
+            where jobs.name = $1",
+            &[kind]
+        )
+            .await
+            .map(|maybe| maybe.map(Self::map_row))
+    }
+
+    pub async fn update(&self, conn: &impl GenericClient) -> Result<(), PgError> {
+        conn.execute(
+            "\
+            update jobs \
+            set schedule = $2, \
+                enabled = $3 \
+            where name = $1",
+            &[&self.name, &self.schedule, &self.enabled]
+        ).await?;
+
+        Ok(())
+    }
+
+    /// the next time the job should run after its last run. None if the
+    /// schedule has no more upcoming times
+    ///
+    /// a job that has never run is due immediately
+    pub fn next_run(&self, schedule: &Schedule, now: &DateTime<Utc>) -> Option<DateTime<Utc>> {
+        match &self.last_run {
+            Some(last_run) => schedule.after(last_run).next(),
+            None => Some(*now),
+        }
+    }
+}
+
+/// parses a cron expression
+pub fn parse_schedule(given: &str) -> Result<Schedule, cron::error::Error> {
+    Schedule::from_str(given)
+}
+
+/// records the result of a job run
+async fn record(
+    conn: &impl GenericClient,
+    kind: &JobKind,
+    started: &DateTime<Utc>,
+    status: JobStatus,
+    err: Option<String>,
+    duration: i64,
+) -> Result<(), PgError> {
+    conn.execute(
+        "\
+        update jobs \
+        set last_run = $2, \
+            last_status = $3, \
+            last_error = $4, \
+            last_duration = $5 \
+        where name = $1",
+        &[kind, started, &status, &err, &duration]
+    ).await?;
+
+    Ok(())
+}
+
+/// runs a job and records the result in the jobs table
+///
+/// nothing is recorded if the job was skipped because another instance is
+/// running it
+pub async fn run_job(state: &state::SharedState, kind: JobKind) {
+    let started = Utc::now();
+    let timer = Instant::now();
+
+    let (status, err) = match kind.run(state).await {
+        Ok(true) => (JobStatus::Success, None),
+        Ok(false) => return,
+        Err(err) => {
+            error::log_prefix_error(&format!("job {kind} failed"), &err);
+
+            (JobStatus::Failed, Some(err.to_string().chars().take(ERROR_LEN).collect()))
+        }
+    };
+
+    let duration = i64::try_from(timer.elapsed().as_millis()).unwrap_or(i64::MAX);
+
+    let result = match state.db_conn().await {
+        Ok(conn) => record(&conn, &kind, &started, status, err, duration)
+            .await
+            .context("failed to record job result"),
+        Err(err) => Err(err),
+    };
+
+    if let Err(err) = result {
+        error::log_prefix_error(&format!("failed to record result of job {kind}"), &err);
+    }
+}
+
+/// runs the jobs that are due and returns the amount of time until the next
+/// check
+async fn tick(state: &state::SharedState) -> Result<std::time::Duration, error::Error> {
+    let jobs = {
+        let conn = state.db_conn().await?;
+
+        Job::retrieve_all(&conn)
+            .await
+            .context("failed to retrieve jobs")?
+    };
+
+    let mut wait = MAX_WAIT;
+
+    for job in jobs {
+        if !job.enabled {
+            continue;
+        }
+
+        let schedule = match parse_schedule(&job.schedule) {
+            Ok(schedule) => schedule,
+            Err(err) => {
+                error::log_prefix_error(&format!("invalid schedule for job {}", job.name), &err);
+
+                continue;
+            }
+        };
+
+        let now = Utc::now();
+
+        let Some(next) = job.next_run(&schedule, &now) else {
+            continue;
+        };
+
+        if next <= now {
+            run_job(state, job.name).await;
+
+            // the schedule is checked again after the run to find the next
+            // time after now instead of after the last run
+            if let Some(upcoming) = schedule.upcoming(Utc).next() {
+                if let Ok(until) = (upcoming - Utc::now()).to_std() {
+                    wait = wait.min(until);
+                }
+            }
+        } else if let Ok(until) = (next - now).to_std() {
+            wait = wait.min(until);
+        }
+    }
+
+    Ok(wait)
+}
+
+/// seeds the jobs table and then runs jobs as they become due for the
+/// lifetime of the server
+pub async fn run(state: state::SharedState) {
+    let seeded = match state.db_conn().await {
+        Ok(conn) => Job::seed(&conn, state.jobs())
+            .await
+            .context("failed to seed jobs table"),
+        Err(err) => Err(err),
+    };
+
+    if let Err(err) = seeded {
+        error::log_prefix_error("failed to start job scheduler", &err);
+    }
+
+    loop {
+        let wait = match tick(&state).await {
+            Ok(wait) => wait,
+            Err(err) => {
+                error::log_prefix_error("job scheduler failed", &err);
+
+                MAX_WAIT
+            }
+        };
+
+        tokio::time::sleep(wait).await;
+    }
+}
@@ -1,14 +1,19 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
 
+use bytes::BytesMut;
 use chrono::{NaiveDate, DateTime, Utc};
 use futures::{Stream, StreamExt, TryStream, TryStreamExt};
-use serde::Serialize;
+use postgres_types as pg_types;
+use serde::{Serialize, Deserialize};
 
 use crate::db::{self, GenericClient, PgError};
+use crate::error::{self, BoxDynError, Context};
 use crate::db::ids::{
     EntryId,
     EntryUid,
+    ExportId,
+    ImportId,
     FileEntryId,
     FileEntryUid,
     JournalId,
@@ -16,9 +21,36 @@ use crate::db::ids::{
     UserId,
     CustomFieldId,
     CustomFieldUid,
+    PromptId,
+    WorkspaceId,
 };
 
+pub mod audio;
 pub mod custom_field;
+pub mod delete;
+pub mod e2e;
+pub mod export;
+pub mod extract;
+pub mod feed;
+pub mod freeze;
+pub mod goal;
+pub mod image_meta;
+pub mod import;
+pub mod live;
+pub mod markdown;
+pub mod mention;
+pub mod order;
+pub mod prompt;
+pub mod revision;
+pub mod share;
+pub mod stats;
+pub mod tag;
+pub mod task;
+pub mod thumbnail;
+pub mod upload;
+pub mod view;
+pub mod weather;
+pub mod webhook;
 
 /// the potential errors when creating a journal
 #[derive(Debug, thiserror::Error)]
@@ -53,6 +85,9 @@ pub enum JournalUpdateError {
 /// the different optionals available when creating a journal
 #[derive(Debug)]
 pub struct JournalCreateOptions {
+    /// the workspace that the journal will belong to
+    workspaces_id: WorkspaceId,
+
     /// the user to assign the journal to
     users_id: UserId,
 
@@ -61,6 +96,9 @@ pub struct JournalCreateOptions {
 
     /// an optional description of the journal
     description: Option<String>,
+
+    /// the entries of the journal are encrypted by the client
+    e2e: bool,
 }
 
 impl JournalCreateOptions {
@@ -72,6 +110,145 @@ impl JournalCreateOptions {
         self.description = Some(value.into());
         self
     }
+
+    /// marks the journal as end-to-end encrypted
+    pub fn e2e(mut self, value: bool) -> Self {
+        self.e2e = value;
+        self
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("the provided string is not a valid week start")]
+pub struct InvalidWeekStart;
+
+/// the first day of the week for the calendars and stats of a journal
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WeekStart {
+    #[default]
+    Monday,
+    Sunday,
+    Saturday,
+}
+
+impl WeekStart {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            WeekStart::Monday => "monday",
+            WeekStart::Sunday => "sunday",
+            WeekStart::Saturday => "saturday",
+        }
+    }
+
+    /// the number of days a date is moved forward so that a week that
+    /// starts on monday starts on this day instead
+    pub fn monday_offset(&self) -> i32 {
+        match self {
+            WeekStart::Monday => 0,
+            WeekStart::Sunday => 1,
+            WeekStart::Saturday => 2,
+        }
+    }
+}
+
+impl FromStr for WeekStart {
+    type Err = InvalidWeekStart;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "monday" => Ok(WeekStart::Monday),
+            "sunday" => Ok(WeekStart::Sunday),
+            "saturday" => Ok(WeekStart::Saturday),
+            _ => Err(InvalidWeekStart),
+        }
+    }
+}
+
+impl<'a> pg_types::FromSql<'a> for WeekStart {
+    fn from_sql(ty: &pg_types::Type, raw: &'a [u8]) -> Result<Self, BoxDynError> {
+        let v = <&str as pg_types::FromSql>::from_sql(ty, raw)?;
+
+        Ok(Self::from_str(v)?)
+    }
+
+    fn accepts(ty: &pg_types::Type) -> bool {
+        <&str as pg_types::FromSql>::accepts(ty)
+    }
+}
+
+impl pg_types::ToSql for WeekStart {
+    fn to_sql(&self, ty: &pg_types::Type, w: &mut BytesMut) -> Result<pg_types::IsNull, BoxDynError> {
+        self.as_str()
+            .to_sql(ty, w)
+    }
+
+    fn accepts(ty: &pg_types::Type) -> bool {
+        <&str as pg_types::ToSql>::accepts(ty)
+    }
+
+    pg_types::to_sql_checked!();
+}
+
+/// the view that clients show when a journal is opened
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DefaultView {
+    #[default]
+    Entries,
+    List,
+    Calendar,
+}
+
+/// how many entries a journal can have for the same date
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MultiEntry {
+    /// only one entry is allowed per date
+    #[default]
+    Single,
+
+    /// any number of entries can share a date
+    Multiple,
+}
+
+/// the settings of a journal
+///
+/// settings are stored as json in the journals table so that a new setting
+/// only needs a new field with a default instead of a new column. settings
+/// that are missing from the stored json use their default
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct JournalSettings {
+    pub week_start: WeekStart,
+    pub default_view: DefaultView,
+    pub multi_entry: MultiEntry,
+}
+
+impl pg_types::ToSql for JournalSettings {
+    fn to_sql(&self, ty: &pg_types::Type, w: &mut BytesMut) -> Result<pg_types::IsNull, BoxDynError> {
+        let wrapper: pg_types::Json<&Self> = pg_types::Json(self);
+
+        wrapper.to_sql(ty, w)
+    }
+
+    fn accepts(ty: &pg_types::Type) -> bool {
+        <pg_types::Json<Self> as pg_types::ToSql>::accepts(ty)
+    }
+
+    pg_types::to_sql_checked!();
+}
+
+impl<'a> pg_types::FromSql<'a> for JournalSettings {
+    fn from_sql(ty: &pg_types::Type, raw: &'a [u8]) -> Result<Self, BoxDynError> {
+        let parsed: pg_types::Json<Self> = pg_types::Json::from_sql(ty, raw)?;
+
+        Ok(parsed.0)
+    }
+
+    fn accepts(ty: &pg_types::Type) -> bool {
+        <pg_types::Json<Self> as pg_types::FromSql>::accepts(ty)
+    }
 }
 
 /// the database representation of a journal
@@ -83,6 +260,9 @@ pub struct Journal {
     /// the generated journal uid from the server
     pub uid: JournalUid,
 
+    /// the workspace that the journal belongs to
+    pub workspaces_id: WorkspaceId,
+
     /// the assigned owner of the journal
     pub users_id: UserId,
 
@@ -92,6 +272,21 @@ pub struct Journal {
     /// the optional description of the journal
     pub description: Option<String>,
 
+    /// the entries of the journal are encrypted by the client and only
+    /// ciphertext is stored
+    pub e2e: bool,
+
+    /// the version of the custom fields of the journal. increases every time
+    /// the custom fields are changed
+    pub schema_version: i64,
+
+    /// timestamp of when the journal was archived. the entries and files of
+    /// an archived journal cannot be changed
+    pub archived: Option<DateTime<Utc>>,
+
+    /// the settings of the journal
+    pub settings: JournalSettings,
+
     /// timestamp of when the journal was created
     pub created: DateTime<Utc>,
 
@@ -100,15 +295,18 @@ pub struct Journal {
 }
 
 impl Journal {
-    /// creates the [`JournalCreateOptions`] with the given [`UserId`] and name
-    pub fn create_options<N>(users_id: UserId, name: N) -> JournalCreateOptions
+    /// creates the [`JournalCreateOptions`] with the given [`WorkspaceId`],
+    /// [`UserId`], and name
+    pub fn create_options<N>(workspaces_id: WorkspaceId, users_id: UserId, name: N) -> JournalCreateOptions
     where
         N: Into<String>
     {
         JournalCreateOptions {
+            workspaces_id,
             users_id,
             name: name.into(),
-            description: None
+            description: None,
+            e2e: false,
         }
     }
 
@@ -116,20 +314,24 @@ impl Journal {
     pub async fn create(conn: &impl GenericClient, options: JournalCreateOptions) -> Result<Self, JournalCreateError> {
         let uid = JournalUid::gen();
         let created = Utc::now();
+        let workspaces_id = options.workspaces_id;
         let users_id = options.users_id;
         let name = options.name;
         let description = options.description;
+        let e2e = options.e2e;
 
         let result = conn.query_one(
             "\
-            insert into journals (uid, users_id, name, description, created) values \
-            ($1, $2, $3, $4, $5) \
+            insert into journals (uid, workspaces_id, users_id, name, description, e2e, created) values \
+            ($1, $2, $3, $4, $5, $6, $7) \
             returning id",
             &[
                 &uid,
+                &workspaces_id,
                 &users_id,
                 &name,
                 &description,
+                &e2e,
                 &created
             ]
         ).await;
@@ -138,9 +340,14 @@ impl Journal {
             Ok(row) => Ok(Self {
                 id: row.get(0),
                 uid,
+                workspaces_id,
                 users_id,
                 name,
                 description,
+                e2e,
+                schema_version: 1,
+                archived: None,
+                settings: JournalSettings::default(),
                 created,
                 updated: None
             }),
@@ -168,9 +375,14 @@ impl Journal {
             "\
             select journals.id, \
                    journals.uid, \
+                   journals.workspaces_id, \
                    journals.users_id, \
                    journals.name, \
                    journals.description, \
+                   journals.e2e, \
+                   journals.schema_version, \
+                   journals.archived, \
+                   journals.settings, \
                    journals.created, \
                    journals.updated \
             from journals \
@@ -182,14 +394,302 @@ impl Journal {
             .map(|maybe| maybe.map(|row| Self {
                 id: row.get(0),
                 uid: row.get(1),
-                users_id: row.get(2),
-                name: row.get(3),
-                description: row.get(4),
-                created: row.get(5),
-                updated: row.get(6),
+                workspaces_id: row.get(2),
+                users_id: row.get(3),
+                name: row.get(4),
+                description: row.get(5),
+                e2e: row.get(6),
+                schema_version: row.get(7),
+                archived: row.get(8),
+                settings: row.get(9),
+                created: row.get(10),
+                updated: row.get(11),
+            }))
+    }
+
+    /// attempts to retrieve the journal with the specified [`JournalId`] that
+    /// is either owned by or shared with the specified [`UserId`]
+    ///
+    /// the permissions of a shared user are not checked here so the caller
+    /// is expected to check them for the action being taken
+    pub async fn retrieve_accessible(conn: &impl GenericClient, journals_id: &JournalId, users_id: &UserId) -> Result<Option<Self>, PgError> {
+        conn.query_opt(
+            "\
+            select journals.id, \
+                   journals.uid, \
+                   journals.workspaces_id, \
+                   journals.users_id, \
+                   journals.name, \
+                   journals.description, \
+                   journals.e2e, \
+                   journals.schema_version, \
+                   journals.archived, \
+                   journals.settings, \
+                   journals.created, \
+                   journals.updated \
+            from journals \
+                left join journal_shares on \
+                    journals.id = journal_shares.journals_id and \
+                    journal_shares.users_id = $2 \
+            where journals.id = $1 and \
+                  (journals.users_id = $2 or journal_shares.users_id is not null)",
+            &[journals_id, users_id]
+        )
+            .await
+            .map(|maybe| maybe.map(|row| Self {
+                id: row.get(0),
+                uid: row.get(1),
+                workspaces_id: row.get(2),
+                users_id: row.get(3),
+                name: row.get(4),
+                description: row.get(5),
+                e2e: row.get(6),
+                schema_version: row.get(7),
+                archived: row.get(8),
+                settings: row.get(9),
+                created: row.get(10),
+                updated: row.get(11),
             }))
     }
 
+    /// attempts to retrieve the journal with the specified name for the
+    /// specified [`UserId`]
+    pub async fn retrieve_name(conn: &impl GenericClient, users_id: &UserId, name: &str) -> Result<Option<Self>, PgError> {
+        conn.query_opt(
+            "\
+            select journals.id, \
+                   journals.uid, \
+                   journals.workspaces_id, \
+                   journals.users_id, \
+                   journals.name, \
+                   journals.description, \
+                   journals.e2e, \
+                   journals.schema_version, \
+                   journals.archived, \
+                   journals.settings, \
+                   journals.created, \
+                   journals.updated \
+            from journals \
+            where journals.users_id = $1 and \
+                  journals.name = $2",
+            &[users_id, &name]
+        )
+            .await
+            .map(|maybe| maybe.map(|row| Self {
+                id: row.get(0),
+                uid: row.get(1),
+                workspaces_id: row.get(2),
+                users_id: row.get(3),
+                name: row.get(4),
+                description: row.get(5),
+                e2e: row.get(6),
+                schema_version: row.get(7),
+                archived: row.get(8),
+                settings: row.get(9),
+                created: row.get(10),
+                updated: row.get(11),
+            }))
+    }
+
+    /// retrieves all journals owned by the specified [`UserId`] ordered by
+    /// name
+    pub async fn retrieve_user(conn: &impl GenericClient, users_id: &UserId) -> Result<Vec<Self>, PgError> {
+        conn.query(
+            "\
+            select journals.id, \
+                   journals.uid, \
+                   journals.workspaces_id, \
+                   journals.users_id, \
+                   journals.name, \
+                   journals.description, \
+                   journals.e2e, \
+                   journals.schema_version, \
+                   journals.archived, \
+                   journals.settings, \
+                   journals.created, \
+                   journals.updated \
+            from journals \
+            where journals.users_id = $1 \
+            order by journals.name",
+            &[users_id]
+        )
+            .await
+            .map(|rows| rows.into_iter().map(|row| Self {
+                id: row.get(0),
+                uid: row.get(1),
+                workspaces_id: row.get(2),
+                users_id: row.get(3),
+                name: row.get(4),
+                description: row.get(5),
+                e2e: row.get(6),
+                schema_version: row.get(7),
+                archived: row.get(8),
+                settings: row.get(9),
+                created: row.get(10),
+                updated: row.get(11),
+            }).collect())
+    }
+
+    /// retrieves the journal to use for the specified [`UserId`] when one is
+    /// not given
+    ///
+    /// this is the default journal of the user if it is in the workspace and
+    /// not archived. otherwise it falls back to the first journal in the
+    /// order of the user and then the oldest journal
+    pub async fn retrieve_default(
+        conn: &impl GenericClient,
+        users_id: &UserId,
+        workspaces_id: &WorkspaceId,
+    ) -> Result<Option<Self>, PgError> {
+        conn.query_opt(
+            "\
+            select journals.id, \
+                   journals.uid, \
+                   journals.workspaces_id, \
+                   journals.users_id, \
+                   journals.name, \
+                   journals.description, \
+                   journals.e2e, \
+                   journals.schema_version, \
+                   journals.archived, \
+                   journals.settings, \
+                   journals.created, \
+                   journals.updated \
+            from journals \
+                left join user_preferences on \
+                    journals.users_id = user_preferences.users_id \
+                left join journal_orders on \
+                    journals.id = journal_orders.journals_id and \
+                    journals.users_id = journal_orders.users_id \
+            where journals.users_id = $1 and \
+                  journals.workspaces_id = $2 and \
+                  journals.archived is null \
+            order by journals.id = user_preferences.default_journals_id desc nulls last, \
+                     coalesce(journal_orders.pinned, false) desc, \
+                     journal_orders.position asc nulls last, \
+                     journals.created \
+            limit 1",
+            &[users_id, workspaces_id]
+        )
+            .await
+            .map(|maybe| maybe.map(|row| Self {
+                id: row.get(0),
+                uid: row.get(1),
+                workspaces_id: row.get(2),
+                users_id: row.get(3),
+                name: row.get(4),
+                description: row.get(5),
+                e2e: row.get(6),
+                schema_version: row.get(7),
+                archived: row.get(8),
+                settings: row.get(9),
+                created: row.get(10),
+                updated: row.get(11),
+            }))
+    }
+
+    /// reserves the next entry number for the journal
+    ///
+    /// the row for the journal will be locked until the transaction is
+    /// finished so numbers will always increase and never be reused
+    pub async fn next_entry_number(conn: &impl GenericClient, journals_id: &JournalId) -> Result<i64, PgError> {
+        conn.query_one(
+            "\
+            update journals \
+            set next_entry_number = next_entry_number + 1 \
+            where id = $1 \
+            returning next_entry_number - 1",
+            &[journals_id]
+        )
+            .await
+            .map(|row| row.get(0))
+    }
+
+    /// archives or unarchives the journal
+    pub async fn set_archived(&mut self, conn: &impl GenericClient, archived: bool) -> Result<(), PgError> {
+        let archived = archived.then(Utc::now);
+
+        conn.execute(
+            "update journals set archived = $2 where id = $1",
+            &[&self.id, &archived]
+        ).await?;
+
+        self.archived = archived;
+
+        Ok(())
+    }
+
+    /// replaces the settings of the journal
+    pub async fn update_settings(&mut self, conn: &impl GenericClient, settings: JournalSettings) -> Result<(), PgError> {
+        let updated = Utc::now();
+
+        conn.execute(
+            "update journals set settings = $2, updated = $3 where id = $1",
+            &[&self.id, &settings, &updated]
+        ).await?;
+
+        self.settings = settings;
+        self.updated = Some(updated);
+
+        Ok(())
+    }
+
+    /// checks if the journal already has an entry for the given date. the
+    /// excluded entry is ignored so that an entry can keep its own date
+    pub async fn date_in_use(
+        conn: &impl GenericClient,
+        journals_id: &JournalId,
+        date: &NaiveDate,
+        exclude: Option<&EntryId>,
+    ) -> Result<bool, PgError> {
+        conn.query_one(
+            "\
+            select exists ( \
+                select 1 \
+                from entries \
+                where journals_id = $1 and \
+                      entry_date = $2 and \
+                      ($3::bigint is null or id != $3) \
+            )",
+            &[journals_id, date, &exclude]
+        )
+            .await
+            .map(|row| row.get(0))
+    }
+
+    /// retrieves the dates of the journal that have more than one entry
+    pub async fn retrieve_shared_dates(conn: &impl GenericClient, journals_id: &JournalId) -> Result<Vec<NaiveDate>, PgError> {
+        conn.query(
+            "\
+            select entries.entry_date \
+            from entries \
+            where entries.journals_id = $1 \
+            group by entries.entry_date \
+            having count(*) > 1 \
+            order by entries.entry_date",
+            &[journals_id]
+        )
+            .await
+            .map(|rows| rows.into_iter().map(|row| row.get(0)).collect())
+    }
+
+    /// increments the schema version of the journal after its custom fields
+    /// have changed
+    pub async fn bump_schema_version(&mut self, conn: &impl GenericClient) -> Result<(), PgError> {
+        self.schema_version = conn.query_one(
+            "\
+            update journals \
+            set schema_version = schema_version + 1 \
+            where id = $1 \
+            returning schema_version",
+            &[&self.id]
+        )
+            .await?
+            .get(0);
+
+        Ok(())
+    }
+
     /// attempst to update the journal with new data
     ///
     /// only the fields updated, name, and description will be sent to the
@@ -243,6 +743,9 @@ pub struct Entry {
     /// the user that created the entry
     pub users_id: UserId,
 
+    /// the number of the entry in the journal
+    pub number: i64,
+
     /// the associated date the entry is for
     pub date: NaiveDate,
 
@@ -257,9 +760,55 @@ pub struct Entry {
 
     /// timestamp of when the entry was updated
     pub updated: Option<DateTime<Utc>>,
+
+    /// the entry is for a date that has not arrived yet
+    pub planned: bool,
+
+    /// the encrypted title, contents, and custom field values of an entry in
+    /// an end-to-end encrypted journal
+    pub ciphertext: Option<e2e::Ciphertext>,
+
+    /// the prompt that the entry was written for
+    pub prompts_id: Option<PromptId>,
+
+    /// the number of words in the title and contents. encrypted entries
+    /// are always 0
+    pub word_count: i32,
+}
+
+/// checks to see if the given entry date has not arrived yet
+pub fn is_planned_date(date: &NaiveDate) -> bool {
+    *date > Utc::now().date_naive()
+}
+
+/// counts the words in the title and contents of an entry
+pub fn entry_word_count(title: Option<&str>, contents: Option<&str>) -> i32 {
+    let title = title.map_or(0, markdown::word_count);
+    let contents = contents.map_or(0, markdown::word_count);
+
+    title.saturating_add(contents)
 }
 
 impl Entry {
+    fn map_row(row: tokio_postgres::Row) -> Self {
+        Self {
+            id: row.get(0),
+            uid: row.get(1),
+            journals_id: row.get(2),
+            users_id: row.get(3),
+            number: row.get(4),
+            date: row.get(5),
+            title: row.get(6),
+            contents: row.get(7),
+            created: row.get(8),
+            updated: row.get(9),
+            planned: row.get(10),
+            ciphertext: row.get(11),
+            prompts_id: row.get(12),
+            word_count: row.get(13),
+        }
+    }
+
     /// attempts to retrieve the specified entry for the [`JournalId`],
     /// [`UserId`], and [`EntryId`]
     pub async fn retrieve_id(
@@ -274,11 +823,16 @@ impl Entry {
                    entries.uid, \
                    entries.journals_id, \
                    entries.users_id, \
+                   entries.number, \
                    entries.entry_date, \
                    entries.title, \
                    entries.contents, \
                    entries.created, \
-                   entries.updated \
+                   entries.updated, \
+                   entries.planned, \
+                   entries.ciphertext, \
+                   entries.prompts_id, \
+                   entries.word_count \
             from entries \
             where entries.journals_id = $1 and \
                   entries.id = $3 and \
@@ -286,17 +840,76 @@ impl Entry {
             &[journals_id, users_id, entries_id]
         )
             .await
-            .map(|maybe| maybe.map(|found| Self {
-                id: found.get(0),
-                uid: found.get(1),
-                journals_id: found.get(2),
-                users_id: found.get(3),
-                date: found.get(4),
-                title: found.get(5),
-                contents: found.get(6),
-                created: found.get(7),
-                updated: found.get(8),
-            }))
+            .map(|maybe| maybe.map(Self::map_row))
+    }
+
+    /// attempts to retrieve the specified entry for the [`JournalId`],
+    /// [`UserId`], and entry number
+    pub async fn retrieve_number(
+        conn: &impl GenericClient,
+        journals_id: &JournalId,
+        users_id: &UserId,
+        number: &i64,
+    ) -> Result<Option<Self>, PgError> {
+        conn.query_opt(
+            "\
+            select entries.id, \
+                   entries.uid, \
+                   entries.journals_id, \
+                   entries.users_id, \
+                   entries.number, \
+                   entries.entry_date, \
+                   entries.title, \
+                   entries.contents, \
+                   entries.created, \
+                   entries.updated, \
+                   entries.planned, \
+                   entries.ciphertext, \
+                   entries.prompts_id, \
+                   entries.word_count \
+            from entries \
+            where entries.journals_id = $1 and \
+                  entries.number = $3 and \
+                  entries.users_id = $2",
+            &[journals_id, users_id, number]
+        )
+            .await
+            .map(|maybe| maybe.map(Self::map_row))
+    }
+
+    /// counts the words of the entry again using what is currently stored.
+    /// used when the contents are changed by the database directly
+    pub async fn refresh_word_count(conn: &impl GenericClient, entries_id: &EntryId) -> Result<(), PgError> {
+        let row = conn.query_one(
+            "select title, contents from entries where id = $1",
+            &[entries_id]
+        ).await?;
+
+        let word_count = entry_word_count(row.get(0), row.get(1));
+
+        conn.execute(
+            "update entries set word_count = $2 where id = $1",
+            &[entries_id, &word_count]
+        ).await?;
+
+        Ok(())
+    }
+
+    /// marks any planned entries that have reached their date as normal
+    /// entries
+    ///
+    /// returns the number of entries that were updated
+    pub async fn release_planned(conn: &impl GenericClient) -> Result<u64, PgError> {
+        let today = Utc::now().date_naive();
+
+        conn.execute(
+            "\
+            update entries \
+            set planned = false \
+            where planned = true and \
+                  entry_date <= $1",
+            &[&today]
+        ).await
     }
 }
 
@@ -380,6 +993,12 @@ pub struct FileEntry {
     pub mime_subtype: String,
     pub mime_param: Option<String>,
     pub size: i64,
+    /// the blake3 hash of the file contents, hex encoded. None if the file
+    /// has not been uploaded
+    pub hash: Option<String>,
+    /// the contents are encrypted with the key of the journal
+    #[serde(skip)]
+    pub encrypted: bool,
     pub created: DateTime<Utc>,
     pub updated: Option<DateTime<Utc>>,
 }
@@ -401,6 +1020,8 @@ impl FileEntry {
                    file_entries.mime_subtype, \
                    file_entries.mime_param, \
                    file_entries.size, \
+                   file_entries.hash, \
+                   file_entries.encrypted, \
                    file_entries.created, \
                    file_entries.updated \
             from file_entries \
@@ -417,8 +1038,10 @@ impl FileEntry {
                 mime_subtype: record.get(5),
                 mime_param: record.get(6),
                 size: record.get(7),
-                created: record.get(8),
-                updated: record.get(9),
+                hash: record.get(8),
+                encrypted: record.get(9),
+                created: record.get(10),
+                updated: record.get(11),
             })))
     }
 
@@ -437,6 +1060,8 @@ impl FileEntry {
                    file_entries.mime_subtype, \
                    file_entries.mime_param, \
                    file_entries.size, \
+                   file_entries.hash, \
+                   file_entries.encrypted, \
                    file_entries.created, \
                    file_entries.updated \
             from file_entries \
@@ -454,11 +1079,63 @@ impl FileEntry {
                 mime_subtype: record.get(5),
                 mime_param: record.get(6),
                 size: record.get(7),
-                created: record.get(8),
-                updated: record.get(9),
+                hash: record.get(8),
+                encrypted: record.get(9),
+                created: record.get(10),
+                updated: record.get(11),
             }))
     }
 
+    /// retrieves the requested file entries that belong to entries of the
+    /// given journal
+    ///
+    /// ids that are not found or are in a different journal are skipped
+    pub async fn retrieve_journal_ids_stream(
+        conn: &impl GenericClient,
+        journals_id: &JournalId,
+        ids: &[FileEntryId],
+    ) -> Result<impl Stream<Item = Result<Self, PgError>>, PgError> {
+        let params: db::ParamsArray<'_, 2> = [journals_id, &ids];
+
+        conn.query_raw(
+            "\
+            select file_entries.id, \
+                   file_entries.uid, \
+                   file_entries.entries_id, \
+                   file_entries.name, \
+                   file_entries.mime_type, \
+                   file_entries.mime_subtype, \
+                   file_entries.mime_param, \
+                   file_entries.size, \
+                   file_entries.hash, \
+                   file_entries.encrypted, \
+                   file_entries.created, \
+                   file_entries.updated \
+            from file_entries \
+                join entries on \
+                    file_entries.entries_id = entries.id \
+            where entries.journals_id = $1 and \
+                  file_entries.id = any($2) \
+            order by file_entries.id",
+            params
+        )
+            .await
+            .map(|top_res| top_res.map(|stream| stream.map(|record| Self {
+                id: record.get(0),
+                uid: record.get(1),
+                entries_id: record.get(2),
+                name: record.get(3),
+                mime_type: record.get(4),
+                mime_subtype: record.get(5),
+                mime_param: record.get(6),
+                size: record.get(7),
+                hash: record.get(8),
+                encrypted: record.get(9),
+                created: record.get(10),
+                updated: record.get(11),
+            })))
+    }
+
     pub fn get_mime(&self) -> mime::Mime {
         let parse = if let Some(param) = &self.mime_param {
             format!("{}/{};{param}", self.mime_type, self.mime_subtype)
@@ -479,7 +1156,9 @@ impl FileEntry {
                 mime_subtype = $4, \
                 mime_param = $5, \
                 size = $6, \
-                updated = $7 \
+                hash = $7, \
+                encrypted = $8, \
+                updated = $9 \
             where file_entries.id = $1",
             &[
                 &self.id,
@@ -488,6 +1167,8 @@ impl FileEntry {
                 &self.mime_subtype,
                 &self.mime_param,
                 &self.size,
+                &self.hash,
+                &self.encrypted,
                 &self.updated
             ]
         ).await?;
@@ -614,6 +1295,41 @@ impl CustomField {
         }
     }
 
+    pub async fn retrieve(
+        conn: &impl GenericClient,
+        journals_id: &JournalId,
+        custom_fields_id: &CustomFieldId,
+    ) -> Result<Option<Self>, PgError> {
+        conn.query_opt(
+            "\
+            select custom_fields.id, \
+                   custom_fields.uid, \
+                   custom_fields.journals_id, \
+                   custom_fields.name, \
+                   custom_fields.\"order\", \
+                   custom_fields.config, \
+                   custom_fields.description, \
+                   custom_fields.created, \
+                   custom_fields.updated \
+            from custom_fields \
+            where custom_fields.journals_id = $1 and \
+                  custom_fields.id = $2",
+            &[journals_id, custom_fields_id]
+        )
+            .await
+            .map(|maybe| maybe.map(|row| Self {
+                id: row.get(0),
+                uid: row.get(1),
+                journals_id: row.get(2),
+                name: row.get(3),
+                order: row.get(4),
+                config: row.get(5),
+                description: row.get(6),
+                created: row.get(7),
+                updated: row.get(8),
+            }))
+    }
+
     pub async fn retrieve_journal_stream(
         conn: &impl GenericClient,
         journals_id: &JournalId,
@@ -659,15 +1375,82 @@ pub struct JournalDir {
 
 impl JournalDir {
     pub fn new(root: &PathBuf, journal: &Journal) -> Self {
-        let path = format!("journals/{}", journal.id);
-
         Self {
-            root: root.join(path)
+            root: root.join(Self::relative_path(&journal.workspaces_id, &journal.id))
         }
     }
 
+    /// the path of a journal directory relative to the storage directory
+    fn relative_path(workspaces_id: &WorkspaceId, journals_id: &JournalId) -> String {
+        format!("workspaces/{workspaces_id}/journals/{journals_id}")
+    }
+
+    /// moves journal directories from the layout used before workspaces,
+    /// "journals/{id}", to the directory of the workspace that the journal
+    /// is in
+    ///
+    /// a journal that already has a directory in the new layout is left as
+    /// is and a warning is logged so that the directories can be merged by
+    /// hand
+    pub async fn migrate_legacy(conn: &impl GenericClient, root: &Path) -> Result<(), error::Error> {
+        let legacy_root = root.join("journals");
+
+        if !tokio::fs::try_exists(&legacy_root).await.context("failed to check for legacy journals directory")? {
+            return Ok(());
+        }
+
+        let rows = conn.query("select journals.id, journals.workspaces_id from journals", &[])
+            .await
+            .context("failed to retrieve journals")?;
+
+        for row in rows {
+            let journals_id: JournalId = row.get(0);
+            let workspaces_id: WorkspaceId = row.get(1);
+            let legacy = legacy_root.join(journals_id.to_string());
+
+            if !tokio::fs::try_exists(&legacy).await.context("failed to check for legacy journal directory")? {
+                continue;
+            }
+
+            let current = root.join(Self::relative_path(&workspaces_id, &journals_id));
+
+            if tokio::fs::try_exists(&current).await.context("failed to check for journal directory")? {
+                tracing::warn!(
+                    "journal {journals_id} has a legacy directory and a workspace directory. {} was not moved",
+                    legacy.display()
+                );
+
+                continue;
+            }
+
+            if let Some(parent) = current.parent() {
+                tokio::fs::create_dir_all(parent)
+                    .await
+                    .context("failed to create workspace journals directory")?;
+            }
+
+            tokio::fs::rename(&legacy, &current)
+                .await
+                .context(format!("failed to move legacy journal directory {}", legacy.display()))?;
+
+            tracing::info!("moved journal directory {} to {}", legacy.display(), current.display());
+        }
+
+        // only removed if everything was moved out of it
+        if let Err(err) = tokio::fs::remove_dir(&legacy_root).await {
+            tracing::debug!("legacy journals directory was not removed: {err}");
+        }
+
+        Ok(())
+    }
+
+    /// the directory that holds everything stored for the journal
+    pub fn root(&self) -> &PathBuf {
+        &self.root
+    }
+
     pub async fn create_root_dir(&self) -> Result<PathBuf, std::io::Error> {
-        tokio::fs::create_dir(&self.root).await?;
+        tokio::fs::create_dir_all(&self.root).await?;
 
         Ok(self.root.clone())
     }
@@ -690,4 +1473,39 @@ impl JournalDir {
     pub fn file_path(&self, file_entries_id: &FileEntryId) -> PathBuf {
         self.root.join(format!("files/{}.file", file_entries_id))
     }
+
+    /// the path of the cached thumbnail for an image file entry
+    pub fn thumbnail_path(&self, file_entries_id: &FileEntryId) -> PathBuf {
+        self.root.join(format!("files/{}.thumb", file_entries_id))
+    }
+
+    pub async fn create_exports_dir(&self) -> Result<PathBuf, std::io::Error> {
+        let exports_dir = self.root.join("exports");
+
+        tokio::fs::create_dir_all(&exports_dir).await?;
+
+        Ok(exports_dir)
+    }
+
+    pub fn export_path(&self, exports_id: &ExportId) -> PathBuf {
+        self.root.join(format!("exports/{}.zip", exports_id))
+    }
+
+    pub async fn create_imports_dir(&self) -> Result<PathBuf, std::io::Error> {
+        let imports_dir = self.root.join("imports");
+
+        tokio::fs::create_dir_all(&imports_dir).await?;
+
+        Ok(imports_dir)
+    }
+
+    /// the path of the uploaded archive for an import
+    pub fn import_path(&self, imports_id: &ImportId) -> PathBuf {
+        self.root.join(format!("imports/{}.upload", imports_id))
+    }
+
+    /// a unique path for an uploaded archive that is only previewed
+    pub fn import_preview_path(&self) -> PathBuf {
+        self.root.join(format!("imports/preview-{}.upload", uuid::Uuid::new_v4()))
+    }
 }
@@ -0,0 +1,346 @@
+//! audio file entries
+//!
+//! the duration of an audio file is read when it is uploaded. if
+//! transcription is configured, the [`JobKind::Transcription`] job sends
+//! the audio to the configured [`Transcriber`] and stores the transcript in
+//! a text custom field of the journal or adds it to the contents of the
+//! entry. files of end-to-end encrypted journals are never transcribed
+//!
+//! [`JobKind::Transcription`]: crate::jobs::JobKind::Transcription
+//! [`Transcriber`]: crate::config::Transcriber
+
+use std::collections::HashMap;
+use std::io::Cursor;
+use std::path::PathBuf;
+
+use chrono::{DateTime, Duration, Utc};
+use serde::Serialize;
+use symphonia::core::formats::FormatOptions;
+use symphonia::core::io::MediaSourceStream;
+use symphonia::core::meta::MetadataOptions;
+use symphonia::core::probe::Hint;
+
+use crate::config::{Transcriber, Transcription};
+use crate::db::{GenericClient, PgError};
+use crate::db::ids::{EntryId, FileEntryId, JournalId, UserId};
+use crate::error::{self, Context};
+use crate::sec::encryption::JournalKey;
+
+use super::{custom_field, Entry, FileEntry};
+use super::extract::run_command;
+
+/// the max number of files transcribed in a single run of the job
+pub const BATCH_SIZE: i64 = 5;
+
+/// the number of minutes that a claimed file is not claimed again. an
+/// endpoint has 5 minutes to respond for each file of the batch
+const CLAIM_MINUTES: i64 = 30;
+
+/// checks if the file entry is an audio file
+pub fn is_audio(file_entry: &FileEntry) -> bool {
+    file_entry.mime_type == "audio"
+}
+
+/// the details of an audio file entry
+#[derive(Debug, Serialize)]
+pub struct AudioDetails {
+    /// the length of the audio in seconds if it could be read
+    pub duration: Option<f64>,
+
+    /// the transcript of the audio if it has been transcribed
+    pub transcript: Option<String>,
+}
+
+fn probe_duration(contents: Vec<u8>, subtype: &str) -> Option<f64> {
+    let stream = MediaSourceStream::new(Box::new(Cursor::new(contents)), Default::default());
+    let mut hint = Hint::new();
+    hint.mime_type(&format!("audio/{subtype}"));
+
+    let probed = symphonia::default::get_probe()
+        .format(&hint, stream, &FormatOptions::default(), &MetadataOptions::default())
+        .ok()?;
+
+    let track = probed.format.default_track()?;
+    let time_base = track.codec_params.time_base?;
+    let frames = track.codec_params.n_frames?;
+    let time = time_base.calc_time(frames);
+
+    Some(time.seconds as f64 + time.frac)
+}
+
+/// reads the duration of the audio file at the given path. if a key is
+/// given then the file is decrypted first
+///
+/// reading is done on the blocking thread pool
+pub async fn duration(source: PathBuf, subtype: String, key: Option<JournalKey>) -> Result<Option<f64>, error::Error> {
+    tokio::task::spawn_blocking(move || {
+        let mut contents = std::fs::read(&source)
+            .context("failed to read audio file")?;
+
+        if let Some(key) = key {
+            contents = key.decrypt_all(&contents)
+                .context("failed to decrypt audio file")?;
+        }
+
+        Ok(probe_duration(contents, &subtype))
+    })
+        .await
+        .context("failed to join audio duration task")?
+}
+
+/// stores the duration of a newly uploaded audio file. any previous
+/// transcript is cleared so that the new audio is transcribed
+pub async fn store_upload(
+    conn: &impl GenericClient,
+    file_entries_id: &FileEntryId,
+    duration: Option<f64>,
+) -> Result<(), PgError> {
+    conn.execute(
+        "\
+        insert into file_audio (file_entries_id, duration) values ($1, $2) \
+        on conflict (file_entries_id) do update \
+            set duration = excluded.duration, \
+                transcript = null, \
+                error = null, \
+                transcribed = null",
+        &[file_entries_id, &duration]
+    ).await?;
+
+    Ok(())
+}
+
+/// retrieves the audio details of the given file entries
+pub async fn retrieve_ids(
+    conn: &impl GenericClient,
+    ids: &[FileEntryId],
+) -> Result<HashMap<FileEntryId, AudioDetails>, PgError> {
+    let rows = conn.query(
+        "\
+        select file_audio.file_entries_id, \
+               file_audio.duration, \
+               file_audio.transcript \
+        from file_audio \
+        where file_audio.file_entries_id = any($1)",
+        &[&ids]
+    ).await?;
+
+    Ok(rows.into_iter()
+        .map(|row| (row.get(0), AudioDetails {
+            duration: row.get(1),
+            transcript: row.get(2),
+        }))
+        .collect())
+}
+
+/// an audio file waiting to be transcribed along with the journal it
+/// belongs to
+#[derive(Debug)]
+pub struct Pending {
+    pub file_entry: FileEntry,
+    pub journals_id: JournalId,
+    pub users_id: UserId,
+}
+
+/// claims a batch of uploaded audio files that have not been transcribed
+///
+/// the claimed files are not claimed again for [`CLAIM_MINUTES`] so that
+/// they are not transcribed twice while waiting on the transcriber outside
+/// of a transaction. if the server stops before storing the result then
+/// they will be transcribed once the claim expires
+pub async fn claim_pending(
+    conn: &impl GenericClient,
+    now: &DateTime<Utc>,
+    limit: i64,
+) -> Result<Vec<Pending>, PgError> {
+    let claimed = *now + Duration::minutes(CLAIM_MINUTES);
+
+    conn.query(
+        "\
+        with pending as ( \
+            select file_audio.file_entries_id \
+            from file_audio \
+                join file_entries on \
+                    file_audio.file_entries_id = file_entries.id \
+                join entries on \
+                    file_entries.entries_id = entries.id \
+                join journals on \
+                    entries.journals_id = journals.id \
+            where file_audio.transcribed is null and \
+                  (file_audio.claimed is null or file_audio.claimed <= $2) and \
+                  file_entries.hash is not null and \
+                  not journals.e2e \
+            order by file_audio.file_entries_id \
+            limit $1 \
+            for update of file_audio skip locked \
+        ) \
+        update file_audio \
+        set claimed = $3 \
+        from pending, file_entries, entries, journals \
+        where file_audio.file_entries_id = pending.file_entries_id and \
+              file_audio.file_entries_id = file_entries.id and \
+              file_entries.entries_id = entries.id and \
+              entries.journals_id = journals.id \
+        returning file_entries.id, \
+                  file_entries.uid, \
+                  file_entries.entries_id, \
+                  file_entries.name, \
+                  file_entries.mime_type, \
+                  file_entries.mime_subtype, \
+                  file_entries.mime_param, \
+                  file_entries.size, \
+                  file_entries.hash, \
+                  file_entries.encrypted, \
+                  file_entries.created, \
+                  file_entries.updated, \
+                  journals.id, \
+                  journals.users_id",
+        &[&limit, now, &claimed]
+    )
+        .await
+        .map(|rows| rows.into_iter().map(|row| Pending {
+            file_entry: FileEntry {
+                id: row.get(0),
+                uid: row.get(1),
+                entries_id: row.get(2),
+                name: row.get(3),
+                mime_type: row.get(4),
+                mime_subtype: row.get(5),
+                mime_param: row.get(6),
+                size: row.get(7),
+                hash: row.get(8),
+                encrypted: row.get(9),
+                created: row.get(10),
+                updated: row.get(11),
+            },
+            journals_id: row.get(12),
+            users_id: row.get(13),
+        }).collect())
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct EndpointTranscript {
+    text: String,
+}
+
+/// sends the audio to the transcriber and returns the transcript
+pub async fn transcribe(
+    http: &reqwest::Client,
+    transcription: &Transcription,
+    file_entry: &FileEntry,
+    contents: Vec<u8>,
+) -> Result<String, error::Error> {
+    let output = match &transcription.transcriber {
+        Transcriber::Command(command) => {
+            let stdout = run_command(command, contents).await?;
+
+            String::from_utf8_lossy(&stdout).into_owned()
+        }
+        Transcriber::Endpoint(url) => {
+            let res = http.post(url.clone())
+                .header("content-type", format!("{}/{}", file_entry.mime_type, file_entry.mime_subtype))
+                .timeout(std::time::Duration::from_secs(300))
+                .body(contents)
+                .send()
+                .await
+                .context("failed to send audio to transcription endpoint")?
+                .error_for_status()
+                .context("transcription endpoint rejected audio")?;
+
+            let is_json = res.headers()
+                .get("content-type")
+                .and_then(|value| value.to_str().ok())
+                .is_some_and(|value| value.starts_with("application/json"));
+
+            let body = res.text()
+                .await
+                .context("failed to read transcription endpoint response")?;
+
+            if is_json {
+                serde_json::from_str::<EndpointTranscript>(&body)
+                    .context("invalid json from transcription endpoint")?
+                    .text
+            } else {
+                body
+            }
+        }
+    };
+
+    Ok(output.trim().replace('\0', ""))
+}
+
+/// stores the result of transcribing an audio file. the error is stored
+/// instead of the transcript so the file is not attempted again until it
+/// is uploaded again
+pub async fn store_transcript(
+    conn: &impl GenericClient,
+    file_entries_id: &FileEntryId,
+    result: Result<&str, String>,
+) -> Result<(), PgError> {
+    let (transcript, error) = match result {
+        Ok(transcript) => (Some(transcript), None),
+        Err(err) => (None, Some(err)),
+    };
+    let transcribed = Utc::now();
+
+    conn.execute(
+        "\
+        update file_audio \
+        set transcript = $2, \
+            error = $3, \
+            transcribed = $4, \
+            claimed = null \
+        where file_entries_id = $1",
+        &[file_entries_id, &transcript, &error, &transcribed]
+    ).await?;
+
+    Ok(())
+}
+
+/// writes the transcript to the entry of the audio file
+///
+/// if the journal has a text custom field with the given name then the
+/// transcript is stored there, otherwise it is added to the end of the
+/// contents of the entry
+pub async fn apply_transcript(
+    conn: &impl GenericClient,
+    journals_id: &JournalId,
+    entries_id: &EntryId,
+    field_name: Option<&str>,
+    transcript: &str,
+) -> Result<(), PgError> {
+    let now = Utc::now();
+
+    if let Some(name) = field_name {
+        if let Some(custom_fields_id) = custom_field::Type::retrieve_text_field(conn, journals_id, name).await? {
+            let value = custom_field::Value::Text {
+                value: transcript.to_owned(),
+            };
+
+            custom_field::Entry::set(conn, &custom_fields_id, entries_id, &value).await?;
+
+            conn.execute(
+                "update entries set updated = $2 where id = $1",
+                &[entries_id, &now]
+            ).await?;
+
+            return Ok(());
+        }
+    }
+
+    conn.execute(
+        "\
+        update entries \
+        set contents = case \
+                when contents is null or contents = '' then $2 \
+                else contents || E'\\n\\n' || $2 \
+            end, \
+            updated = $3 \
+        where id = $1 and \
+              ciphertext is null",
+        &[entries_id, &transcript, &now]
+    ).await?;
+
+    Entry::refresh_word_count(conn, entries_id).await?;
+
+    Ok(())
+}
@@ -10,6 +10,11 @@ use crate::error::BoxDynError;
 use crate::db::{self, GenericClient, PgError};
 use crate::db::ids::{JournalId, EntryId, CustomFieldId};
 
+pub mod filter;
+pub mod unit;
+
+use unit::{Unit, UnitSystem};
+
 fn default_time_range_show_diff() -> bool {
     false
 }
@@ -22,16 +27,28 @@ fn default_precision() -> i32 {
     2
 }
 
+/// a single option available for a select field
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SelectOption {
+    pub value: String,
+    #[serde(default)]
+    pub color: Option<String>,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(tag = "type")]
 pub enum Type {
     Integer {
         minimum: Option<i32>,
-        maximum: Option<i32>
+        maximum: Option<i32>,
+        #[serde(default)]
+        unit: Option<Unit>,
     },
     IntegerRange {
         minimum: Option<i32>,
-        maximum: Option<i32>
+        maximum: Option<i32>,
+        #[serde(default)]
+        unit: Option<Unit>,
     },
 
     Float {
@@ -40,7 +57,9 @@ pub enum Type {
         #[serde(default = "default_step")]
         step: f32,
         #[serde(default = "default_precision")]
-        precision: i32
+        precision: i32,
+        #[serde(default)]
+        unit: Option<Unit>,
     },
     FloatRange {
         minimum: Option<f32>,
@@ -48,7 +67,9 @@ pub enum Type {
         #[serde(default = "default_step")]
         step: f32,
         #[serde(default = "default_precision")]
-        precision: i32
+        precision: i32,
+        #[serde(default)]
+        unit: Option<Unit>,
     },
 
     Time {},
@@ -56,9 +77,57 @@ pub enum Type {
         #[serde(default = "default_time_range_show_diff")]
         show_diff: bool,
     },
+
+    Select {
+        options: Vec<SelectOption>,
+    },
+
+    Boolean {
+        #[serde(default)]
+        default: bool,
+        #[serde(default)]
+        label: Option<String>,
+    },
+
+    /// multi-line text with optional limits on the number of characters
+    Text {
+        minimum: Option<u32>,
+        maximum: Option<u32>,
+    },
 }
 
 impl Type {
+    /// the unit assigned to a numeric type
+    pub fn unit(&self) -> Option<Unit> {
+        match self {
+            Type::Integer { unit, .. } |
+            Type::IntegerRange { unit, .. } |
+            Type::Float { unit, .. } |
+            Type::FloatRange { unit, .. } => *unit,
+            Type::Time {} |
+            Type::TimeRange { .. } |
+            Type::Select { .. } |
+            Type::Boolean { .. } |
+            Type::Text { .. } => None,
+        }
+    }
+
+    /// checks if the type holds a number or a range of numbers
+    pub fn is_numeric(&self) -> bool {
+        matches!(
+            self,
+            Type::Integer { .. } |
+            Type::IntegerRange { .. } |
+            Type::Float { .. } |
+            Type::FloatRange { .. }
+        )
+    }
+
+    /// checks if the type holds a range of numbers
+    pub fn is_range(&self) -> bool {
+        matches!(self, Type::IntegerRange { .. } | Type::FloatRange { .. })
+    }
+
     pub async fn retrieve_journal_map(
         conn: &impl db::GenericClient,
         journals_id: &JournalId,
@@ -87,11 +156,36 @@ impl Type {
         Ok(rtn)
     }
 
+    /// retrieves the id of the text field of the journal with the given
+    /// name. None if the field does not exist or is not a text field
+    pub async fn retrieve_text_field(
+        conn: &impl db::GenericClient,
+        journals_id: &JournalId,
+        name: &str,
+    ) -> Result<Option<CustomFieldId>, PgError> {
+        let result = conn.query_opt(
+            "\
+            select custom_fields.id, \
+                   custom_fields.config \
+            from custom_fields \
+            where custom_fields.journals_id = $1 and \
+                  custom_fields.name = $2",
+            &[journals_id, &name]
+        ).await?;
+
+        Ok(result.and_then(|row| {
+            let config: Self = row.get(1);
+
+            matches!(config, Type::Text { .. }).then(|| row.get(0))
+        }))
+    }
+
     pub fn validate(&self, given: Value) -> Result<Value, Value> {
         match self {
             Type::Integer {
                 minimum,
-                maximum
+                maximum,
+                ..
             } => match given {
                 Value::Integer { value } => match (minimum, maximum) {
                     (Some(min), Some(max)) if value >= *min && value <= *max => Ok(Value::Integer { value }),
@@ -105,6 +199,7 @@ impl Type {
             Type::IntegerRange {
                 minimum,
                 maximum,
+                ..
             } => match given {
                 Value::IntegerRange { low, high } => match (minimum, maximum) {
                     (Some(min), Some(max)) if low >= *min && low < high && high <= *max => Ok(Value::IntegerRange { low, high }),
@@ -151,6 +246,32 @@ impl Type {
                 Value::TimeRange { low, high } if low < high => Ok(Value::TimeRange { low, high }),
                 _ => Err(given),
             }
+            Type::Select { options } => match given {
+                Value::Select { value } if options.iter().any(|opt| opt.value == value) => Ok(Value::Select { value }),
+                _ => Err(given),
+            }
+            Type::Boolean {..} => match given {
+                Value::Boolean { value } => Ok(Value::Boolean { value }),
+                _ => Err(given),
+            }
+            Type::Text {
+                minimum,
+                maximum,
+            } => match given {
+                Value::Text { value } => {
+                    let len = value.chars().count();
+
+                    let valid = minimum.map_or(true, |min| len >= min as usize) &&
+                        maximum.map_or(true, |max| len <= max as usize);
+
+                    if valid {
+                        Ok(Value::Text { value })
+                    } else {
+                        Err(Value::Text { value })
+                    }
+                }
+                _ => Err(given),
+            }
         }
     }
 }
@@ -190,7 +311,7 @@ pub struct Entry {
     pub updated: Option<DateTime<Utc>>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(tag = "type")]
 pub enum Value {
     Integer {
@@ -216,6 +337,125 @@ pub enum Value {
         low: DateTime<Utc>,
         high: DateTime<Utc>
     },
+
+    Select {
+        value: String
+    },
+
+    Boolean {
+        value: bool
+    },
+
+    Text {
+        value: String
+    },
+}
+
+/// a numeric value that has been converted to a different unit
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
+pub enum Converted {
+    Value {
+        value: f64,
+        unit: Unit,
+    },
+    Range {
+        low: f64,
+        high: f64,
+        unit: Unit,
+    },
+}
+
+/// formats a float with the precision of the field
+fn format_float(value: f32, precision: i32) -> String {
+    format!("{value:.0$}", precision.max(0) as usize)
+}
+
+impl Value {
+    /// formats the value for display using the config of the field
+    pub fn format(&self, config: &Type) -> String {
+        let unit = config.unit()
+            .and_then(|unit| serde_json::to_value(unit).ok())
+            .and_then(|unit| unit.as_str().map(|unit| format!(" {unit}")))
+            .unwrap_or_default();
+        let precision = match config {
+            Type::Float { precision, .. } |
+            Type::FloatRange { precision, .. } => *precision,
+            _ => 2,
+        };
+
+        match self {
+            Value::Integer { value } => format!("{value}{unit}"),
+            Value::IntegerRange { low, high } => format!("{low} - {high}{unit}"),
+            Value::Float { value } => format!("{}{unit}", format_float(*value, precision)),
+            Value::FloatRange { low, high } => format!(
+                "{} - {}{unit}",
+                format_float(*low, precision),
+                format_float(*high, precision)
+            ),
+            Value::Time { value } => value.format("%Y-%m-%d %H:%M UTC").to_string(),
+            Value::TimeRange { low, high } => {
+                let formatted = format!(
+                    "{} - {}",
+                    low.format("%Y-%m-%d %H:%M"),
+                    high.format("%Y-%m-%d %H:%M UTC")
+                );
+
+                match config {
+                    Type::TimeRange { show_diff: true } => {
+                        let diff = *high - *low;
+
+                        format!("{formatted} ({}h {}m)", diff.num_hours(), diff.num_minutes() % 60)
+                    }
+                    _ => formatted,
+                }
+            }
+            Value::Select { value } => value.clone(),
+            Value::Boolean { value } => match config {
+                Type::Boolean { label: Some(label), .. } if *value => label.clone(),
+                _ => if *value { String::from("yes") } else { String::from("no") },
+            },
+            Value::Text { value } => value.clone(),
+        }
+    }
+
+    /// converts a numeric value from the given unit to the equivalent unit
+    /// in the measurement system
+    ///
+    /// returns None for non numeric values or if the unit does not change
+    pub fn convert(&self, from: Unit, system: UnitSystem) -> Option<Converted> {
+        let to = from.in_system(system);
+
+        if to == from {
+            return None;
+        }
+
+        match self {
+            Value::Integer { value } => Some(Converted::Value {
+                value: from.convert(*value as f64, to)?,
+                unit: to,
+            }),
+            Value::IntegerRange { low, high } => Some(Converted::Range {
+                low: from.convert(*low as f64, to)?,
+                high: from.convert(*high as f64, to)?,
+                unit: to,
+            }),
+            Value::Float { value } => Some(Converted::Value {
+                value: from.convert(*value as f64, to)?,
+                unit: to,
+            }),
+            Value::FloatRange { low, high } => Some(Converted::Range {
+                low: from.convert(*low as f64, to)?,
+                high: from.convert(*high as f64, to)?,
+                unit: to,
+            }),
+            Value::Time { .. } |
+            Value::TimeRange { .. } |
+            Value::Select { .. } |
+            Value::Boolean { .. } |
+            Value::Text { .. } => None,
+        }
+    }
 }
 
 impl Entry {
@@ -264,6 +504,29 @@ impl Entry {
 
         Ok(rtn)
     }
+
+    /// sets the value of a field for an entry, replacing the previous value
+    /// if there is one
+    pub async fn set(
+        conn: &impl GenericClient,
+        custom_fields_id: &CustomFieldId,
+        entries_id: &EntryId,
+        value: &Value,
+    ) -> Result<(), PgError> {
+        let now = Utc::now();
+
+        conn.execute(
+            "\
+            insert into custom_field_entries (custom_fields_id, entries_id, value, created) values \
+            ($1, $2, $3, $4) \
+            on conflict (custom_fields_id, entries_id) do update \
+                set value = excluded.value, \
+                    updated = excluded.created",
+            &[custom_fields_id, entries_id, value, &now]
+        ).await?;
+
+        Ok(())
+    }
 }
 
 impl pg_types::ToSql for Value {
@@ -301,35 +564,43 @@ mod test {
     const INT: Type = Type::Integer {
         minimum: Some(1),
         maximum: Some(10),
+        unit: None,
     };
     const INT_LOW: Type = Type::Integer {
         minimum: Some(1),
         maximum: None,
+        unit: None,
     };
     const INT_HIGH: Type = Type::Integer {
         minimum: None,
         maximum: Some(10),
+        unit: None,
     };
     const INT_NO_LIMIT: Type = Type::Integer {
         minimum: None,
         maximum: None,
+        unit: None,
     };
 
     const INT_RANGE: Type = Type::IntegerRange {
         minimum: Some(1),
         maximum: Some(10),
+        unit: None,
     };
     const INT_RANGE_LOW: Type = Type::IntegerRange {
         minimum: Some(1),
         maximum: None,
+        unit: None,
     };
     const INT_RANGE_HIGH: Type = Type::IntegerRange {
         minimum: None,
         maximum: Some(10),
+        unit: None,
     };
     const INT_RANGE_NO_LIMIT: Type = Type::IntegerRange {
         minimum: None,
         maximum: None,
+        unit: None,
     };
 
     const FLOAT: Type = Type::Float {
@@ -337,24 +608,28 @@ mod test {
         maximum: Some(10.0),
         step: 0.1,
         precision: 2,
+        unit: None,
     };
     const FLOAT_LOW: Type = Type::Float {
         minimum: Some(1.0),
         maximum: None,
         step: 0.1,
         precision: 2,
+        unit: None,
     };
     const FLOAT_HIGH: Type = Type::Float {
         minimum: None,
         maximum: Some(10.0),
         step: 0.1,
         precision: 2,
+        unit: None,
     };
     const FLOAT_NO_LIMIT: Type = Type::Float {
         minimum: None,
         maximum: None,
         step: 0.1,
         precision: 2,
+        unit: None,
     };
 
     const FLOAT_RANGE: Type = Type::FloatRange {
@@ -362,32 +637,33 @@ mod test {
         maximum: Some(10.0),
         step: 0.1,
         precision: 2,
+        unit: None,
     };
     const FLOAT_RANGE_LOW: Type = Type::FloatRange {
         minimum: Some(1.0),
         maximum: None,
         step: 0.1,
         precision: 2,
+        unit: None,
     };
     const FLOAT_RANGE_HIGH: Type = Type::FloatRange {
         minimum: None,
         maximum: Some(10.0),
         step: 0.1,
         precision: 2,
+        unit: None,
     };
     const FLOAT_RANGE_NO_LIMIT: Type = Type::FloatRange {
         minimum: None,
         maximum: None,
         step: 0.1,
         precision: 2,
+        unit: None,
     };
 
-    const TIME: Type = Type::Time {
-        as_12hr: false
-    };
+    const TIME: Type = Type::Time {};
     const TIME_RANGE: Type = Type::TimeRange {
         show_diff: false,
-        as_12hr: false
     };
 
     #[test]
@@ -624,4 +900,92 @@ mod test {
 
         assert!(TIME_RANGE.validate(given).is_err());
     }
+
+    fn select() -> Type {
+        Type::Select {
+            options: vec![
+                SelectOption {
+                    value: "sunny".into(),
+                    color: Some("#f5c542".into()),
+                },
+                SelectOption {
+                    value: "rainy".into(),
+                    color: None,
+                },
+            ]
+        }
+    }
+
+    #[test]
+    fn select_option() {
+        let given = Value::Select { value: "rainy".into() };
+
+        assert!(select().validate(given).is_ok());
+    }
+
+    #[test]
+    fn select_unknown_option() {
+        let given = Value::Select { value: "snowy".into() };
+
+        assert!(select().validate(given).is_err());
+    }
+
+    #[test]
+    fn select_mismatch() {
+        let given = Value::Integer { value: 5 };
+
+        assert!(select().validate(given).is_err());
+    }
+
+    const BOOLEAN: Type = Type::Boolean {
+        default: false,
+        label: None,
+    };
+
+    #[test]
+    fn boolean() {
+        let given = Value::Boolean { value: true };
+
+        assert!(BOOLEAN.validate(given).is_ok());
+    }
+
+    #[test]
+    fn boolean_mismatch() {
+        let given = Value::Integer { value: 1 };
+
+        assert!(BOOLEAN.validate(given).is_err());
+    }
+
+    const TEXT: Type = Type::Text {
+        minimum: Some(2),
+        maximum: Some(5),
+    };
+
+    #[test]
+    fn text() {
+        let given = Value::Text { value: "ok".into() };
+
+        assert!(TEXT.validate(given).is_ok());
+    }
+
+    #[test]
+    fn text_too_short() {
+        let given = Value::Text { value: "a".into() };
+
+        assert!(TEXT.validate(given).is_err());
+    }
+
+    #[test]
+    fn text_too_long() {
+        let given = Value::Text { value: "abcdef".into() };
+
+        assert!(TEXT.validate(given).is_err());
+    }
+
+    #[test]
+    fn text_mismatch() {
+        let given = Value::Integer { value: 5 };
+
+        assert!(TEXT.validate(given).is_err());
+    }
 }
@@ -0,0 +1,212 @@
+//! filters on the custom field values of entries
+//!
+//! a filter is given as "{custom_fields_id}:{op}:{value}". ex: "12:lt:3" or
+//! "15:between:2024-01-01T22:00:00Z..2024-01-02T06:00:00Z". for range values
+//! the entire range has to match the filter so "lt" compares against the
+//! high value and "gt" compares against the low value. numeric values are
+//! compared in the unit they are stored in
+
+use std::collections::HashMap;
+use std::fmt::Write;
+
+use chrono::{DateTime, Utc};
+use tokio_postgres::types::ToSql;
+
+use crate::db::{self, ParamsVec};
+use crate::db::ids::CustomFieldId;
+
+use super::Type;
+
+type Param = dyn ToSql + Sync;
+
+#[derive(Debug, thiserror::Error)]
+pub enum InvalidFilter {
+    #[error("filter is not formatted as \"{{custom_fields_id}}:{{op}}:{{value}}\"")]
+    Format,
+
+    #[error("the custom field was not found in the journal")]
+    FieldNotFound,
+
+    #[error("the operator is unknown or not supported by the custom field")]
+    Operator,
+
+    #[error("the value is not valid for the custom field")]
+    Value,
+}
+
+/// the comparison to make against a custom field value
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FilterOp {
+    Eq,
+    Ne,
+    Lt,
+    Lte,
+    Gt,
+    Gte,
+    Between,
+}
+
+impl FilterOp {
+    fn from_str(given: &str) -> Option<Self> {
+        match given {
+            "eq" => Some(FilterOp::Eq),
+            "ne" => Some(FilterOp::Ne),
+            "lt" => Some(FilterOp::Lt),
+            "lte" => Some(FilterOp::Lte),
+            "gt" => Some(FilterOp::Gt),
+            "gte" => Some(FilterOp::Gte),
+            "between" => Some(FilterOp::Between),
+            _ => None
+        }
+    }
+
+    /// only equality can be checked for values that are not ordered
+    fn is_equality(&self) -> bool {
+        matches!(self, FilterOp::Eq | FilterOp::Ne)
+    }
+}
+
+/// the parsed value of a filter. "between" filters have two values
+#[derive(Debug)]
+pub enum FilterValue {
+    Number(f64, Option<f64>),
+    Time(DateTime<Utc>, Option<DateTime<Utc>>),
+    Text(String),
+    Boolean(bool),
+}
+
+#[derive(Debug)]
+pub struct FieldFilter {
+    pub custom_fields_id: CustomFieldId,
+    pub op: FilterOp,
+    pub value: FilterValue,
+}
+
+fn parse_pair<T, F>(op: FilterOp, given: &str, parse: F) -> Result<(T, Option<T>), InvalidFilter>
+where
+    F: Fn(&str) -> Option<T>
+{
+    if op == FilterOp::Between {
+        let (low, high) = given.split_once("..")
+            .ok_or(InvalidFilter::Value)?;
+
+        Ok((
+            parse(low).ok_or(InvalidFilter::Value)?,
+            Some(parse(high).ok_or(InvalidFilter::Value)?),
+        ))
+    } else {
+        Ok((parse(given).ok_or(InvalidFilter::Value)?, None))
+    }
+}
+
+impl FieldFilter {
+    /// parses a filter for one of the given custom fields of a journal
+    pub fn parse(given: &str, known: &HashMap<CustomFieldId, Type>) -> Result<Self, InvalidFilter> {
+        let mut split = given.splitn(3, ':');
+
+        let (Some(id), Some(op), Some(value)) = (split.next(), split.next(), split.next()) else {
+            return Err(InvalidFilter::Format);
+        };
+
+        let custom_fields_id = id.parse::<CustomFieldId>()
+            .map_err(|_| InvalidFilter::Format)?;
+        let op = FilterOp::from_str(op)
+            .ok_or(InvalidFilter::Operator)?;
+        let config = known.get(&custom_fields_id)
+            .ok_or(InvalidFilter::FieldNotFound)?;
+
+        let value = match config {
+            Type::Integer { .. } |
+            Type::IntegerRange { .. } |
+            Type::Float { .. } |
+            Type::FloatRange { .. } => {
+                let (low, high) = parse_pair(op, value, |v| v.trim().parse::<f64>().ok())?;
+
+                FilterValue::Number(low, high)
+            }
+            Type::Time {} |
+            Type::TimeRange { .. } => {
+                let (low, high) = parse_pair(op, value, |v| {
+                    DateTime::parse_from_rfc3339(v.trim())
+                        .ok()
+                        .map(|v| v.to_utc())
+                })?;
+
+                FilterValue::Time(low, high)
+            }
+            Type::Select { .. } |
+            Type::Text { .. } => {
+                if !op.is_equality() {
+                    return Err(InvalidFilter::Operator);
+                }
+
+                FilterValue::Text(value.to_owned())
+            }
+            Type::Boolean { .. } => {
+                if !op.is_equality() {
+                    return Err(InvalidFilter::Operator);
+                }
+
+                FilterValue::Boolean(value.parse().map_err(|_| InvalidFilter::Value)?)
+            }
+        };
+
+        Ok(Self {
+            custom_fields_id,
+            op,
+            value,
+        })
+    }
+
+    /// writes the sql condition for the filter with its parameters. the
+    /// condition expects the entries table to be available as "entries"
+    pub fn push_condition<'a>(&'a self, query: &mut String, params: &mut ParamsVec<'a>) {
+        let id = db::push_param(params, &self.custom_fields_id);
+
+        let (cast, low, high): (&str, &'a Param, Option<&'a Param>) = match &self.value {
+            FilterValue::Number(low, high) => ("float8", low, high.as_ref().map(|v| v as &Param)),
+            FilterValue::Time(low, high) => ("timestamptz", low, high.as_ref().map(|v| v as &Param)),
+            FilterValue::Text(value) => ("varchar", value, None),
+            FilterValue::Boolean(value) => ("boolean", value, None),
+        };
+
+        // single values are treated as a range where low and high are the
+        // same
+        let low_col = format!("coalesce(custom_field_entries.value ->> 'low', custom_field_entries.value ->> 'value')::{cast}");
+        let high_col = format!("coalesce(custom_field_entries.value ->> 'high', custom_field_entries.value ->> 'value')::{cast}");
+
+        params.push(low);
+        let low_param = params.len();
+
+        let condition = match self.op {
+            FilterOp::Eq => format!("{low_col} = ${low_param} and {high_col} = ${low_param}"),
+            FilterOp::Ne => format!("not ({low_col} = ${low_param} and {high_col} = ${low_param})"),
+            FilterOp::Lt => format!("{high_col} < ${low_param}"),
+            FilterOp::Lte => format!("{high_col} <= ${low_param}"),
+            FilterOp::Gt => format!("{low_col} > ${low_param}"),
+            FilterOp::Gte => format!("{low_col} >= ${low_param}"),
+            FilterOp::Between => {
+                let high_param = match high {
+                    Some(high) => {
+                        params.push(high);
+                        params.len()
+                    }
+                    None => low_param,
+                };
+
+                format!("{low_col} >= ${low_param} and {high_col} <= ${high_param}")
+            }
+        };
+
+        write!(
+            query,
+            " and exists ( \
+                select 1 \
+                from custom_field_entries \
+                where custom_field_entries.entries_id = entries.id and \
+                      custom_field_entries.custom_fields_id = ${id} and \
+                      {condition} \
+            )"
+        ).unwrap();
+    }
+}
@@ -0,0 +1,342 @@
+use std::str::FromStr;
+
+use bytes::BytesMut;
+use postgres_types as pg_types;
+use serde::{Serialize, Deserialize};
+
+use crate::error::BoxDynError;
+
+/// the kind of quantity that a unit measures
+///
+/// units can only be converted to other units of the same dimension
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Dimension {
+    Mass,
+    Length,
+    Volume,
+    Temperature,
+    Duration,
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("the provided string is not a valid unit system")]
+pub struct InvalidUnitSystem;
+
+/// the measurement system to display units in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum UnitSystem {
+    Metric,
+    Imperial,
+}
+
+impl UnitSystem {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            UnitSystem::Metric => "metric",
+            UnitSystem::Imperial => "imperial",
+        }
+    }
+}
+
+impl FromStr for UnitSystem {
+    type Err = InvalidUnitSystem;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "metric" => Ok(UnitSystem::Metric),
+            "imperial" => Ok(UnitSystem::Imperial),
+            _ => Err(InvalidUnitSystem),
+        }
+    }
+}
+
+impl<'a> pg_types::FromSql<'a> for UnitSystem {
+    fn from_sql(ty: &pg_types::Type, raw: &'a [u8]) -> Result<Self, BoxDynError> {
+        let v = <&str as pg_types::FromSql>::from_sql(ty, raw)?;
+
+        Ok(Self::from_str(v)?)
+    }
+
+    fn accepts(ty: &pg_types::Type) -> bool {
+        <&str as pg_types::FromSql>::accepts(ty)
+    }
+}
+
+impl pg_types::ToSql for UnitSystem {
+    fn to_sql(&self, ty: &pg_types::Type, w: &mut BytesMut) -> Result<pg_types::IsNull, BoxDynError> {
+        self.as_str()
+            .to_sql(ty, w)
+    }
+
+    fn accepts(ty: &pg_types::Type) -> bool {
+        <&str as pg_types::ToSql>::accepts(ty)
+    }
+
+    pg_types::to_sql_checked!();
+}
+
+/// the available units for numeric custom fields
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Unit {
+    // mass
+    #[serde(rename = "g")]
+    Gram,
+    #[serde(rename = "kg")]
+    Kilogram,
+    #[serde(rename = "oz")]
+    Ounce,
+    #[serde(rename = "lb")]
+    Pound,
+
+    // length
+    #[serde(rename = "cm")]
+    Centimeter,
+    #[serde(rename = "m")]
+    Meter,
+    #[serde(rename = "km")]
+    Kilometer,
+    #[serde(rename = "in")]
+    Inch,
+    #[serde(rename = "ft")]
+    Foot,
+    #[serde(rename = "mi")]
+    Mile,
+
+    // volume
+    #[serde(rename = "ml")]
+    Milliliter,
+    #[serde(rename = "l")]
+    Liter,
+    #[serde(rename = "floz")]
+    FluidOunce,
+    #[serde(rename = "gal")]
+    Gallon,
+
+    // temperature
+    #[serde(rename = "c")]
+    Celsius,
+    #[serde(rename = "f")]
+    Fahrenheit,
+
+    // duration
+    #[serde(rename = "s")]
+    Second,
+    #[serde(rename = "min")]
+    Minute,
+    #[serde(rename = "h")]
+    Hour,
+}
+
+impl Unit {
+    pub fn dimension(&self) -> Dimension {
+        match self {
+            Unit::Gram |
+            Unit::Kilogram |
+            Unit::Ounce |
+            Unit::Pound => Dimension::Mass,
+            Unit::Centimeter |
+            Unit::Meter |
+            Unit::Kilometer |
+            Unit::Inch |
+            Unit::Foot |
+            Unit::Mile => Dimension::Length,
+            Unit::Milliliter |
+            Unit::Liter |
+            Unit::FluidOunce |
+            Unit::Gallon => Dimension::Volume,
+            Unit::Celsius |
+            Unit::Fahrenheit => Dimension::Temperature,
+            Unit::Second |
+            Unit::Minute |
+            Unit::Hour => Dimension::Duration,
+        }
+    }
+
+    /// the amount of the base unit for the dimension that one of this unit
+    /// is equal to
+    ///
+    /// the base units are grams, meters, milliliters, and seconds.
+    /// temperature is handled separately as it is not a linear conversion
+    fn factor(&self) -> f64 {
+        match self {
+            Unit::Gram => 1.0,
+            Unit::Kilogram => 1000.0,
+            Unit::Ounce => 28.349523125,
+            Unit::Pound => 453.59237,
+            Unit::Centimeter => 0.01,
+            Unit::Meter => 1.0,
+            Unit::Kilometer => 1000.0,
+            Unit::Inch => 0.0254,
+            Unit::Foot => 0.3048,
+            Unit::Mile => 1609.344,
+            Unit::Milliliter => 1.0,
+            Unit::Liter => 1000.0,
+            Unit::FluidOunce => 29.5735295625,
+            Unit::Gallon => 3785.411784,
+            Unit::Celsius |
+            Unit::Fahrenheit => 1.0,
+            Unit::Second => 1.0,
+            Unit::Minute => 60.0,
+            Unit::Hour => 3600.0,
+        }
+    }
+
+    /// the equivalent unit in the given measurement system
+    ///
+    /// units that are the same in both systems will return themselves
+    pub fn in_system(&self, system: UnitSystem) -> Self {
+        match system {
+            UnitSystem::Metric => match self {
+                Unit::Ounce => Unit::Gram,
+                Unit::Pound => Unit::Kilogram,
+                Unit::Inch => Unit::Centimeter,
+                Unit::Foot => Unit::Meter,
+                Unit::Mile => Unit::Kilometer,
+                Unit::FluidOunce => Unit::Milliliter,
+                Unit::Gallon => Unit::Liter,
+                Unit::Fahrenheit => Unit::Celsius,
+                unit => *unit,
+            },
+            UnitSystem::Imperial => match self {
+                Unit::Gram => Unit::Ounce,
+                Unit::Kilogram => Unit::Pound,
+                Unit::Centimeter => Unit::Inch,
+                Unit::Meter => Unit::Foot,
+                Unit::Kilometer => Unit::Mile,
+                Unit::Milliliter => Unit::FluidOunce,
+                Unit::Liter => Unit::Gallon,
+                Unit::Celsius => Unit::Fahrenheit,
+                unit => *unit,
+            }
+        }
+    }
+
+    /// converts a value from this unit to the given unit
+    ///
+    /// returns None if the units are not of the same dimension
+    pub fn convert(&self, value: f64, to: Unit) -> Option<f64> {
+        if self.dimension() != to.dimension() {
+            return None;
+        }
+
+        if *self == to {
+            return Some(value);
+        }
+
+        match (self, to) {
+            (Unit::Celsius, Unit::Fahrenheit) => Some(value * 9.0 / 5.0 + 32.0),
+            (Unit::Fahrenheit, Unit::Celsius) => Some((value - 32.0) * 5.0 / 9.0),
+            _ => Some(value * self.factor() / to.factor()),
+        }
+    }
+
+    /// converts the difference between two values from this unit to the
+    /// given unit
+    ///
+    /// this is the same as [`Unit::convert`] except for temperatures where
+    /// the offset between the scales does not apply to a difference
+    pub fn convert_difference(&self, value: f64, to: Unit) -> Option<f64> {
+        if self.dimension() != to.dimension() {
+            return None;
+        }
+
+        match (self, to) {
+            (Unit::Celsius, Unit::Fahrenheit) => Some(value * 9.0 / 5.0),
+            (Unit::Fahrenheit, Unit::Celsius) => Some(value * 5.0 / 9.0),
+            _ => self.convert(value, to),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const ALL: [Unit; 19] = [
+        Unit::Gram, Unit::Kilogram, Unit::Ounce, Unit::Pound,
+        Unit::Centimeter, Unit::Meter, Unit::Kilometer, Unit::Inch, Unit::Foot, Unit::Mile,
+        Unit::Milliliter, Unit::Liter, Unit::FluidOunce, Unit::Gallon,
+        Unit::Celsius, Unit::Fahrenheit,
+        Unit::Second, Unit::Minute, Unit::Hour,
+    ];
+
+    fn assert_close(given: f64, expected: f64) {
+        assert!(
+            (given - expected).abs() <= 1e-9 * expected.abs().max(1.0),
+            "expected {expected} got {given}"
+        );
+    }
+
+    #[test]
+    fn known_values() {
+        let cases = [
+            (1.0, Unit::Kilogram, Unit::Pound, 2.2046226218487757),
+            (16.0, Unit::Ounce, Unit::Pound, 1.0),
+            (1.0, Unit::Mile, Unit::Kilometer, 1.609344),
+            (12.0, Unit::Inch, Unit::Foot, 1.0),
+            (1.0, Unit::Foot, Unit::Centimeter, 30.48),
+            (1.0, Unit::Gallon, Unit::FluidOunce, 128.0),
+            (1.0, Unit::Liter, Unit::Milliliter, 1000.0),
+            (90.0, Unit::Minute, Unit::Hour, 1.5),
+            (1.0, Unit::Hour, Unit::Second, 3600.0),
+        ];
+
+        for (value, from, to, expected) in cases {
+            assert_close(from.convert(value, to).unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn temperature_offsets() {
+        assert_close(Unit::Celsius.convert(0.0, Unit::Fahrenheit).unwrap(), 32.0);
+        assert_close(Unit::Celsius.convert(100.0, Unit::Fahrenheit).unwrap(), 212.0);
+        assert_close(Unit::Fahrenheit.convert(98.6, Unit::Celsius).unwrap(), 37.0);
+        assert_close(Unit::Celsius.convert(-40.0, Unit::Fahrenheit).unwrap(), -40.0);
+    }
+
+    #[test]
+    fn temperature_differences() {
+        assert_close(Unit::Celsius.convert_difference(10.0, Unit::Fahrenheit).unwrap(), 18.0);
+        assert_close(Unit::Fahrenheit.convert_difference(9.0, Unit::Celsius).unwrap(), 5.0);
+        assert_close(Unit::Kilogram.convert_difference(2.0, Unit::Gram).unwrap(), 2000.0);
+    }
+
+    #[test]
+    fn round_trip() {
+        let values = [-40.0, 0.0, 1.0, 37.5, 1234.5678];
+
+        for from in ALL {
+            for to in ALL.into_iter().filter(|to| to.dimension() == from.dimension()) {
+                for value in values {
+                    let there = from.convert(value, to).unwrap();
+
+                    assert_close(to.convert(there, from).unwrap(), value);
+
+                    let there = from.convert_difference(value, to).unwrap();
+
+                    assert_close(to.convert_difference(there, from).unwrap(), value);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn different_dimensions() {
+        for from in ALL {
+            for to in ALL.into_iter().filter(|to| to.dimension() != from.dimension()) {
+                assert_eq!(from.convert(1.0, to), None);
+                assert_eq!(from.convert_difference(1.0, to), None);
+            }
+        }
+    }
+
+    #[test]
+    fn in_system_keeps_dimension() {
+        for unit in ALL {
+            for system in [UnitSystem::Metric, UnitSystem::Imperial] {
+                assert_eq!(unit.in_system(system).dimension(), unit.dimension());
+            }
+        }
+    }
+}
@@ -0,0 +1,280 @@
+//! permanently deleting a journal
+//!
+//! deleting a journal requires a [`DeleteToken`] that is issued by a first
+//! request and given back with a second one so that a single request cannot
+//! remove a journal by accident. the rows of the journal are removed in one
+//! transaction while its directory is renamed and recorded as a
+//! [`JournalDeletion`]. the directory is only removed by the journal cleanup
+//! job after the transaction is committed so a failed delete can rename it
+//! back
+
+use std::path::PathBuf;
+
+use chrono::{DateTime, Duration, Utc};
+
+use crate::db::{GenericClient, PgError};
+use crate::db::ids::{JournalId, UserId};
+use crate::error::{self, Context};
+use crate::sec::authn::session::Token;
+use crate::sec::authz::Scope;
+
+use super::Journal;
+use super::share::JournalShare;
+
+/// the amount of time a delete token is valid for
+pub const DELETE_TOKEN_DURATION: Duration = Duration::minutes(10);
+
+/// hashes the given token for storage in the database
+fn hash_token(token: &Token) -> Vec<u8> {
+    blake3::hash(token.as_ref()).as_bytes().to_vec()
+}
+
+/// the confirmation that must be given to delete a journal
+pub struct DeleteToken;
+
+impl DeleteToken {
+    /// creates a new token for the journal, replacing any previous one
+    pub async fn issue(
+        conn: &impl GenericClient,
+        journals_id: &JournalId,
+    ) -> Result<(Token, DateTime<Utc>), error::Error> {
+        let token = Token::new()
+            .context("failed to create delete token")?;
+        let hashed = hash_token(&token);
+        let expires = Utc::now() + DELETE_TOKEN_DURATION;
+
+        conn.execute(
+            "\
+            insert into journal_delete_tokens (journals_id, token, expires) values \
+            ($1, $2, $3) \
+            on conflict (journals_id) do update \
+                set token = excluded.token, \
+                    expires = excluded.expires",
+            &[journals_id, &hashed, &expires]
+        )
+            .await
+            .context("failed to store delete token")?;
+
+        Ok((token, expires))
+    }
+
+    /// checks that the token was issued for the journal and has not
+    /// expired. the token is removed if it is valid
+    pub async fn consume(
+        conn: &impl GenericClient,
+        journals_id: &JournalId,
+        token: &Token,
+    ) -> Result<bool, PgError> {
+        let hashed = hash_token(token);
+        let now = Utc::now();
+
+        let result = conn.execute(
+            "\
+            delete from journal_delete_tokens \
+            where journals_id = $1 and \
+                  token = $2 and \
+                  expires > $3",
+            &[journals_id, &hashed, &now]
+        ).await?;
+
+        Ok(result == 1)
+    }
+}
+
+/// removes the rows of the journal and everything that belongs to it
+///
+/// the files of the journal are not touched
+pub async fn delete_rows(conn: &impl GenericClient, journal: &Journal) -> Result<(), error::Error> {
+    let entry_tables = [
+        "entry_tags",
+        "entry_mentions",
+        "entry_tasks",
+        "custom_field_entries",
+        "entry_views",
+        "entry_weather",
+        "entry_revisions",
+        "file_entries",
+    ];
+
+    for table in entry_tables {
+        conn.execute(
+            &format!(
+                "delete from {table} \
+                where entries_id in (select id from entries where journals_id = $1)"
+            ),
+            &[&journal.id]
+        )
+            .await
+            .context(format!("failed to delete {table} for journal"))?;
+    }
+
+    conn.execute(
+        "delete from entries where journals_id = $1",
+        &[&journal.id]
+    )
+        .await
+        .context("failed to delete entries for journal")?;
+
+    conn.execute(
+        "delete from custom_fields where journals_id = $1",
+        &[&journal.id]
+    )
+        .await
+        .context("failed to delete custom fields for journal")?;
+
+    let shares = JournalShare::retrieve_journal(conn, &journal.id)
+        .await
+        .context("failed to retrieve shares for journal")?;
+
+    for share in shares {
+        share.delete(conn)
+            .await
+            .context("failed to delete share for journal")?;
+    }
+
+    let scopes = [Scope::Journals.as_str(), Scope::Entries.as_str()];
+
+    conn.execute(
+        "\
+        delete from authz_permissions \
+        where ref_id = $1 and \
+              scope = any($2)",
+        &[&journal.id, &scopes.as_slice()]
+    )
+        .await
+        .context("failed to delete permissions for journal")?;
+
+    conn.execute(
+        "update user_preferences set default_journals_id = null where default_journals_id = $1",
+        &[&journal.id]
+    )
+        .await
+        .context("failed to clear default journal preferences")?;
+
+    // freezes reference exports so they are removed first
+    let journal_tables = [
+        "journal_freezes",
+        "journal_exports",
+        "journal_imports",
+        "journal_webhooks",
+        "user_reminders",
+        "journal_orders",
+        "journal_locations",
+        "journal_upload_policies",
+        "journal_goals",
+        "journal_subjects",
+        "journal_keys",
+        "journal_e2e_keys",
+        "journal_delete_tokens",
+        "journal_feed_tokens",
+    ];
+
+    for table in journal_tables {
+        conn.execute(
+            &format!("delete from {table} where journals_id = $1"),
+            &[&journal.id]
+        )
+            .await
+            .context(format!("failed to delete {table} for journal"))?;
+    }
+
+    let result = conn.execute(
+        "delete from journals where id = $1",
+        &[&journal.id]
+    )
+        .await
+        .context("failed to delete journal")?;
+
+    if result != 1 {
+        tracing::warn!("did not find journal?");
+    }
+
+    Ok(())
+}
+
+/// the directory of a deleted journal that is waiting to be removed
+#[derive(Debug)]
+pub struct JournalDeletion {
+    pub id: i64,
+    pub journals_id: JournalId,
+    pub directory: PathBuf,
+    pub cleaned: Option<DateTime<Utc>>,
+}
+
+impl JournalDeletion {
+    /// records the directory of a journal for the cleanup job
+    pub async fn create(
+        conn: &impl GenericClient,
+        journals_id: &JournalId,
+        users_id: &UserId,
+        directory: PathBuf,
+    ) -> Result<Self, PgError> {
+        let created = Utc::now();
+        let path = directory.to_string_lossy().into_owned();
+
+        let row = conn.query_one(
+            "\
+            insert into journal_deletions (journals_id, users_id, directory, created) values \
+            ($1, $2, $3, $4) \
+            returning id",
+            &[journals_id, users_id, &path, &created]
+        ).await?;
+
+        Ok(Self {
+            id: row.get(0),
+            journals_id: *journals_id,
+            directory,
+            cleaned: None,
+        })
+    }
+
+    /// retrieves the deletions that have not been cleaned, oldest first
+    pub async fn retrieve_pending(conn: &impl GenericClient) -> Result<Vec<Self>, PgError> {
+        let rows = conn.query(
+            "\
+            select journal_deletions.id, \
+                   journal_deletions.journals_id, \
+                   journal_deletions.directory, \
+                   journal_deletions.cleaned \
+            from journal_deletions \
+            where journal_deletions.cleaned is null \
+            order by journal_deletions.created",
+            &[]
+        ).await?;
+
+        Ok(rows.into_iter()
+            .map(|row| Self {
+                id: row.get(0),
+                journals_id: row.get(1),
+                directory: PathBuf::from(row.get::<_, String>(2)),
+                cleaned: row.get(3),
+            })
+            .collect())
+    }
+
+    /// removes the directory of the deleted journal. a directory that is
+    /// already gone is not an error
+    pub async fn clean(&mut self, conn: &impl GenericClient) -> Result<(), error::Error> {
+        match tokio::fs::remove_dir_all(&self.directory).await {
+            Ok(()) => {}
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {}
+            Err(err) => return Err(error::Error::context_source(
+                format!("failed to remove journal directory: \"{}\"", self.directory.display()),
+                err
+            )),
+        }
+
+        let now = Utc::now();
+
+        conn.execute(
+            "update journal_deletions set cleaned = $2 where id = $1",
+            &[&self.id, &now]
+        )
+            .await
+            .context("failed to update journal deletion")?;
+
+        self.cleaned = Some(now);
+
+        Ok(())
+    }
+}
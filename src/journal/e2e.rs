@@ -0,0 +1,220 @@
+//! end-to-end encrypted journals
+//!
+//! the entries of an encrypted journal store the title, contents, and custom
+//! field values in a single ciphertext created by the client. the server
+//! never sees the key used to create it. to let the user unlock a journal
+//! from any client the server stores the journal key wrapped with a key
+//! derived from the passphrase of the user along with the parameters needed
+//! to derive it again
+
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use bytes::BytesMut;
+use chrono::{DateTime, Utc};
+use postgres_types as pg_types;
+use rand::RngCore;
+use serde::{Serialize, Serializer, Deserialize, Deserializer};
+
+use crate::db::{GenericClient, PgError};
+use crate::db::ids::JournalId;
+use crate::error::BoxDynError;
+
+/// the number of random bytes generated for a salt
+pub const SALT_LEN: usize = 16;
+
+/// the kdf that clients are expected to use
+pub const KDF: &str = "argon2id";
+
+/// the default memory cost of the kdf in KiB
+pub const KDF_MEMORY: i32 = 64 * 1024;
+
+/// the default number of iterations of the kdf
+pub const KDF_ITERATIONS: i32 = 3;
+
+/// the default degree of parallelism of the kdf
+pub const KDF_PARALLELISM: i32 = 1;
+
+/// bytes that the server stores without being able to read. serialized as
+/// unpadded url safe base64
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Ciphertext(Vec<u8>);
+
+impl Ciphertext {
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl Serialize for Ciphertext {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer
+    {
+        serializer.serialize_str(&URL_SAFE_NO_PAD.encode(&self.0))
+    }
+}
+
+impl<'de> Deserialize<'de> for Ciphertext {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>
+    {
+        let given = String::deserialize(deserializer)?;
+
+        URL_SAFE_NO_PAD.decode(given)
+            .map(Ciphertext)
+            .map_err(|_| serde::de::Error::custom("invalid base64 ciphertext"))
+    }
+}
+
+impl<'a> pg_types::FromSql<'a> for Ciphertext {
+    fn from_sql(ty: &pg_types::Type, raw: &'a [u8]) -> Result<Self, BoxDynError> {
+        <Vec<u8> as pg_types::FromSql>::from_sql(ty, raw).map(Ciphertext)
+    }
+
+    fn accepts(ty: &pg_types::Type) -> bool {
+        <Vec<u8> as pg_types::FromSql>::accepts(ty)
+    }
+}
+
+impl pg_types::ToSql for Ciphertext {
+    fn to_sql(&self, ty: &pg_types::Type, w: &mut BytesMut) -> Result<pg_types::IsNull, BoxDynError> {
+        self.0.to_sql(ty, w)
+    }
+
+    fn accepts(ty: &pg_types::Type) -> bool {
+        <Vec<u8> as pg_types::ToSql>::accepts(ty)
+    }
+
+    pg_types::to_sql_checked!();
+}
+
+/// the parameters a client uses to derive the key that wraps the journal
+/// key from a passphrase
+#[derive(Debug, Clone, Serialize)]
+pub struct KdfParams {
+    pub algorithm: String,
+    pub memory: i32,
+    pub iterations: i32,
+    pub parallelism: i32,
+    pub salt: Ciphertext,
+}
+
+impl KdfParams {
+    /// creates the default parameters with a new random salt
+    pub fn generate() -> Result<Self, rand::Error> {
+        let mut salt = vec![0; SALT_LEN];
+
+        rand::thread_rng().try_fill_bytes(&mut salt)?;
+
+        Ok(KdfParams {
+            algorithm: KDF.to_owned(),
+            memory: KDF_MEMORY,
+            iterations: KDF_ITERATIONS,
+            parallelism: KDF_PARALLELISM,
+            salt: Ciphertext(salt),
+        })
+    }
+}
+
+/// the escrowed key of an encrypted journal
+#[derive(Debug, Serialize)]
+pub struct EscrowKey {
+    pub journals_id: JournalId,
+    pub kdf: KdfParams,
+
+    /// the journal key wrapped by the client. None until the client has
+    /// stored one
+    pub wrapped_key: Option<Ciphertext>,
+    pub created: DateTime<Utc>,
+    pub updated: Option<DateTime<Utc>>,
+}
+
+impl EscrowKey {
+    fn map_row(row: tokio_postgres::Row) -> Self {
+        Self {
+            journals_id: row.get(0),
+            kdf: KdfParams {
+                algorithm: row.get(1),
+                memory: row.get(2),
+                iterations: row.get(3),
+                parallelism: row.get(4),
+                salt: row.get(5),
+            },
+            wrapped_key: row.get(6),
+            created: row.get(7),
+            updated: row.get(8),
+        }
+    }
+
+    /// stores the kdf parameters for a journal that does not have a wrapped
+    /// key yet
+    pub async fn create(conn: &impl GenericClient, journals_id: &JournalId, kdf: KdfParams) -> Result<Self, PgError> {
+        let created = Utc::now();
+
+        conn.execute(
+            "\
+            insert into journal_e2e_keys ( \
+                journals_id, \
+                kdf, \
+                kdf_memory, \
+                kdf_iterations, \
+                kdf_parallelism, \
+                salt, \
+                created \
+            ) values ($1, $2, $3, $4, $5, $6, $7)",
+            &[journals_id, &kdf.algorithm, &kdf.memory, &kdf.iterations, &kdf.parallelism, &kdf.salt, &created]
+        ).await?;
+
+        Ok(Self {
+            journals_id: *journals_id,
+            kdf,
+            wrapped_key: None,
+            created,
+            updated: None,
+        })
+    }
+
+    pub async fn retrieve(conn: &impl GenericClient, journals_id: &JournalId) -> Result<Option<Self>, PgError> {
+        conn.query_opt(
+            "\
+            select journal_e2e_keys.journals_id, \
+                   journal_e2e_keys.kdf, \
+                   journal_e2e_keys.kdf_memory, \
+                   journal_e2e_keys.kdf_iterations, \
+                   journal_e2e_keys.kdf_parallelism, \
+                   journal_e2e_keys.salt, \
+                   journal_e2e_keys.wrapped_key, \
+                   journal_e2e_keys.created, \
+                   journal_e2e_keys.updated \
+            from journal_e2e_keys \
+            where journal_e2e_keys.journals_id = $1",
+            &[journals_id]
+        )
+            .await
+            .map(|maybe| maybe.map(Self::map_row))
+    }
+
+    /// replaces the wrapped key and the salt it was derived with
+    pub async fn update(&mut self, conn: &impl GenericClient) -> Result<(), PgError> {
+        let updated = Utc::now();
+
+        conn.execute(
+            "\
+            update journal_e2e_keys \
+            set salt = $2, \
+                wrapped_key = $3, \
+                updated = $4 \
+            where journals_id = $1",
+            &[&self.journals_id, &self.kdf.salt, &self.wrapped_key, &updated]
+        ).await?;
+
+        self.updated = Some(updated);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,680 @@
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::io::Write;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use bytes::BytesMut;
+use chrono::{NaiveDate, DateTime, Utc};
+use futures::StreamExt;
+use postgres_types as pg_types;
+use serde::{Serialize, Deserialize};
+use tokio::sync::mpsc;
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+use crate::db::{GenericClient, PgError};
+use crate::db::ids::{
+    EntryId,
+    EntryUid,
+    ExportId,
+    JournalId,
+    JournalUid,
+    UserId,
+    CustomFieldId,
+    CustomFieldUid,
+};
+use crate::error::{self, Context, BoxDynError};
+use crate::journal::{custom_field, CustomField, EntryTag, FileEntry, Journal, JournalDir};
+use crate::journal::e2e::Ciphertext;
+use crate::journal::task::EntryTask;
+use crate::sec::encryption::JournalKey;
+
+mod site;
+
+/// the version of the archive layout. this must be incremented whenever the
+/// structure of the archived json files changes in a way that a reader would
+/// need to know about
+pub const ARCHIVE_VERSION: u32 = 1;
+
+/// the max number of items waiting to be written to an archive
+const ARCHIVE_QUEUE: usize = 16;
+
+#[derive(Debug, thiserror::Error)]
+#[error("the provided string is not a valid export format")]
+pub struct InvalidExportFormat;
+
+/// the format that entries are written in for an export
+///
+/// json archives keep every entry in a single "entries" directory while
+/// markdown and site archives are organized into "year/month" directories
+/// with the files of an entry placed next to it
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    /// each entry is a json file with all of its data
+    #[default]
+    Json,
+
+    /// each entry is a markdown file with the entry data as yaml front
+    /// matter
+    Markdown,
+
+    /// each entry is an html page with an index for every month so the
+    /// journal can be browsed without the server
+    Site,
+}
+
+impl ExportFormat {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ExportFormat::Json => "json",
+            ExportFormat::Markdown => "markdown",
+            ExportFormat::Site => "site",
+        }
+    }
+}
+
+impl Display for ExportFormat {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for ExportFormat {
+    type Err = InvalidExportFormat;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "json" => Ok(ExportFormat::Json),
+            "markdown" => Ok(ExportFormat::Markdown),
+            "site" => Ok(ExportFormat::Site),
+            _ => Err(InvalidExportFormat)
+        }
+    }
+}
+
+impl<'a> pg_types::FromSql<'a> for ExportFormat {
+    fn from_sql(ty: &pg_types::Type, raw: &'a [u8]) -> Result<Self, BoxDynError> {
+        let v = <&str as pg_types::FromSql>::from_sql(ty, raw)?;
+
+        Ok(Self::from_str(v)?)
+    }
+
+    fn accepts(ty: &pg_types::Type) -> bool {
+        <&str as pg_types::FromSql>::accepts(ty)
+    }
+}
+
+impl pg_types::ToSql for ExportFormat {
+    fn to_sql(&self, ty: &pg_types::Type, w: &mut BytesMut) -> Result<pg_types::IsNull, BoxDynError> {
+        self.as_str()
+            .to_sql(ty, w)
+    }
+
+    fn accepts(ty: &pg_types::Type) -> bool {
+        <&str as pg_types::ToSql>::accepts(ty)
+    }
+
+    pg_types::to_sql_checked!();
+}
+
+/// a requested export of a journal
+#[derive(Debug, Serialize)]
+pub struct JournalExport {
+    pub id: ExportId,
+    pub journals_id: JournalId,
+    pub users_id: UserId,
+    pub format: ExportFormat,
+    pub created: DateTime<Utc>,
+    pub completed: Option<DateTime<Utc>>,
+    pub failed: Option<DateTime<Utc>>,
+}
+
+impl JournalExport {
+    pub async fn create(
+        conn: &impl GenericClient,
+        journals_id: &JournalId,
+        users_id: &UserId,
+        format: ExportFormat,
+    ) -> Result<Self, PgError> {
+        let created = Utc::now();
+
+        let row = conn.query_one(
+            "\
+            insert into journal_exports (journals_id, users_id, format, created) values \
+            ($1, $2, $3, $4) \
+            returning id",
+            &[journals_id, users_id, &format, &created]
+        ).await?;
+
+        Ok(Self {
+            id: row.get(0),
+            journals_id: *journals_id,
+            users_id: *users_id,
+            format,
+            created,
+            completed: None,
+            failed: None,
+        })
+    }
+
+    pub async fn retrieve(
+        conn: &impl GenericClient,
+        journals_id: &JournalId,
+        exports_id: &ExportId,
+    ) -> Result<Option<Self>, PgError> {
+        conn.query_opt(
+            "\
+            select journal_exports.id, \
+                   journal_exports.journals_id, \
+                   journal_exports.users_id, \
+                   journal_exports.format, \
+                   journal_exports.created, \
+                   journal_exports.completed, \
+                   journal_exports.failed \
+            from journal_exports \
+            where journal_exports.journals_id = $1 and \
+                  journal_exports.id = $2",
+            &[journals_id, exports_id]
+        )
+            .await
+            .map(|maybe| maybe.map(|row| Self {
+                id: row.get(0),
+                journals_id: row.get(1),
+                users_id: row.get(2),
+                format: row.get(3),
+                created: row.get(4),
+                completed: row.get(5),
+                failed: row.get(6),
+            }))
+    }
+
+    pub async fn mark_completed(&mut self, conn: &impl GenericClient) -> Result<(), PgError> {
+        let now = Utc::now();
+
+        conn.execute(
+            "update journal_exports set completed = $2 where id = $1",
+            &[&self.id, &now]
+        ).await?;
+
+        self.completed = Some(now);
+
+        Ok(())
+    }
+
+    pub async fn mark_failed(&mut self, conn: &impl GenericClient) -> Result<(), PgError> {
+        let now = Utc::now();
+
+        conn.execute(
+            "update journal_exports set failed = $2 where id = $1",
+            &[&self.id, &now]
+        ).await?;
+
+        self.failed = Some(now);
+
+        Ok(())
+    }
+
+    /// writes the archive for the export to the journal directory
+    pub async fn write_archive(
+        &self,
+        conn: &impl GenericClient,
+        journal: &Journal,
+        journal_dir: &JournalDir,
+        key: Option<&JournalKey>,
+    ) -> Result<(), error::Error> {
+        journal_dir.create_exports_dir()
+            .await
+            .context("failed to create exports directory")?;
+
+        let path = journal_dir.export_path(&self.id);
+
+        write_archive(conn, path, &journal.users_id, self.format, journal, journal_dir, key).await
+    }
+}
+
+/// writes an archive of the entries that the user has in the journal to the
+/// given path
+///
+/// entries are retrieved one at a time and sent to a blocking task that
+/// writes them to the archive so that the full archive is never held in
+/// memory
+pub async fn write_archive(
+    conn: &impl GenericClient,
+    path: PathBuf,
+    users_id: &UserId,
+    format: ExportFormat,
+    journal: &Journal,
+    journal_dir: &JournalDir,
+    key: Option<&JournalKey>,
+) -> Result<(), error::Error> {
+    let (sender, receiver) = mpsc::channel(ARCHIVE_QUEUE);
+    let writer = tokio::task::spawn_blocking(move || write_items(path, receiver));
+
+    let result = send_items(conn, users_id, format, journal, journal_dir, key, &sender).await;
+
+    // drop the sender so that the writer knows that no more items will be
+    // sent
+    drop(sender);
+
+    let written = writer.await
+        .context("failed to join archive writer")?;
+
+    result?;
+    written
+}
+
+/// an item to be written to an export archive
+enum ArchiveItem {
+    Data {
+        name: String,
+        data: Vec<u8>,
+    },
+    File {
+        name: String,
+        path: PathBuf,
+
+        /// the key to decrypt the file with if it is encrypted
+        key: Option<JournalKey>,
+    },
+}
+
+/// writes all received items to a zip archive at the given path
+fn write_items(path: PathBuf, mut receiver: mpsc::Receiver<ArchiveItem>) -> Result<(), error::Error> {
+    let file = std::fs::File::create(&path)
+        .context("failed to create export archive")?;
+    let mut archive = ZipWriter::new(file);
+    let options = SimpleFileOptions::default()
+        .compression_method(CompressionMethod::Deflated);
+
+    while let Some(item) = receiver.blocking_recv() {
+        match item {
+            ArchiveItem::Data { name, data } => {
+                archive.start_file(name, options)
+                    .context("failed to start archive file")?;
+                archive.write_all(&data)
+                    .context("failed to write archive file")?;
+            }
+            ArchiveItem::File { name, path, key } => {
+                let mut file = std::fs::File::open(&path)
+                    .context("failed to open journal file")?;
+
+                archive.start_file(name, options)
+                    .context("failed to start archive file")?;
+
+                if let Some(key) = key {
+                    key.decrypt_copy(&mut file, &mut archive)
+                        .context("failed to decrypt journal file to archive")?;
+                } else {
+                    std::io::copy(&mut file, &mut archive)
+                        .context("failed to copy journal file to archive")?;
+                }
+            }
+        }
+    }
+
+    archive.finish()
+        .context("failed to finish export archive")?;
+
+    Ok(())
+}
+
+/// the parts of "journal.json" needed to read an archive
+#[derive(Debug, Deserialize)]
+pub struct ArchiveHeader {
+    pub version: u32,
+    pub name: String,
+    pub description: Option<String>,
+    pub format: ExportFormat,
+}
+
+#[derive(Debug, Serialize)]
+struct ArchiveJournal<'a> {
+    version: u32,
+    uid: &'a JournalUid,
+    name: &'a str,
+    description: &'a Option<String>,
+    format: ExportFormat,
+    created: &'a DateTime<Utc>,
+    updated: &'a Option<DateTime<Utc>>,
+    exported: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ArchiveCustomField {
+    pub uid: CustomFieldUid,
+    pub name: String,
+    pub order: i32,
+    pub config: custom_field::Type,
+    pub description: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ArchiveTag {
+    pub key: String,
+    pub value: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ArchiveCustomFieldValue {
+    pub name: String,
+    pub value: custom_field::Value,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ArchiveTask {
+    pub text: String,
+    pub done: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ArchiveFile {
+    pub name: Option<String>,
+    pub mime: String,
+    pub size: i64,
+    pub path: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ArchiveEntry {
+    pub uid: EntryUid,
+    pub number: i64,
+    pub date: NaiveDate,
+    pub title: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub contents: Option<String>,
+    pub created: DateTime<Utc>,
+    pub updated: Option<DateTime<Utc>>,
+    pub planned: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ciphertext: Option<Ciphertext>,
+    pub tags: Vec<ArchiveTag>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tasks: Vec<ArchiveTask>,
+    pub custom_fields: Vec<ArchiveCustomFieldValue>,
+    pub files: Vec<ArchiveFile>,
+}
+
+/// replaces any characters that are not safe for a file name
+fn safe_name(name: &str) -> String {
+    name.chars()
+        .map(|ch| if ch.is_ascii_alphanumeric() || ch == '-' || ch == '_' || ch == '.' {
+            ch
+        } else {
+            '_'
+        })
+        .collect()
+}
+
+async fn send(sender: &mpsc::Sender<ArchiveItem>, item: ArchiveItem) -> Result<(), error::Error> {
+    sender.send(item)
+        .await
+        .map_err(|_| error::Error::context("archive writer stopped unexpectedly"))
+}
+
+fn to_json<T>(value: &T) -> Result<Vec<u8>, error::Error>
+where
+    T: Serialize
+{
+    serde_json::to_vec_pretty(value)
+        .context("failed to serialize archive data")
+}
+
+/// retrieves all the journal data and sends it to the archive writer
+async fn send_items(
+    conn: &impl GenericClient,
+    users_id: &UserId,
+    format: ExportFormat,
+    journal: &Journal,
+    journal_dir: &JournalDir,
+    key: Option<&JournalKey>,
+    sender: &mpsc::Sender<ArchiveItem>,
+) -> Result<(), error::Error> {
+    send(sender, ArchiveItem::Data {
+        name: String::from("journal.json"),
+        data: to_json(&ArchiveJournal {
+            version: ARCHIVE_VERSION,
+            uid: &journal.uid,
+            name: &journal.name,
+            description: &journal.description,
+            format: format,
+            created: &journal.created,
+            updated: &journal.updated,
+            exported: Utc::now(),
+        })?,
+    }).await?;
+
+    // the index of each custom field in the list of fields
+    let mut field_index: HashMap<CustomFieldId, usize> = HashMap::new();
+    let mut fields = Vec::new();
+    let stream = CustomField::retrieve_journal_stream(conn, &journal.id)
+        .await
+        .context("failed to retrieve journal custom fields")?;
+
+    futures::pin_mut!(stream);
+
+    while let Some(try_record) = stream.next().await {
+        let record = try_record.context("failed to retrieve journal custom field")?;
+
+        field_index.insert(record.id, fields.len());
+        fields.push(ArchiveCustomField {
+            uid: record.uid,
+            name: record.name,
+            order: record.order,
+            config: record.config,
+            description: record.description,
+        });
+    }
+
+    send(sender, ArchiveItem::Data {
+        name: String::from("custom_fields.json"),
+        data: to_json(&fields)?,
+    }).await?;
+
+    // the ids are retrieved first so that the other entry data can be
+    // queried on the same connection while iterating
+    let entry_ids: Vec<EntryId> = conn.query(
+        "\
+        select entries.id \
+        from entries \
+        where entries.journals_id = $1 and \
+              entries.users_id = $2 \
+        order by entries.entry_date",
+        &[&journal.id, &*users_id]
+    )
+        .await
+        .context("failed to retrieve journal entry ids")?
+        .into_iter()
+        .map(|row| row.get(0))
+        .collect();
+
+    let mut site_entries = Vec::new();
+
+    for entries_id in entry_ids {
+        let Some(entry) = super::Entry::retrieve_id(conn, &journal.id, &*users_id, &entries_id)
+            .await
+            .context("failed to retrieve journal entry")? else {
+            continue;
+        };
+
+        let tags = EntryTag::retrieve_entry(conn, entry.id)
+            .await
+            .context("failed to retrieve entry tags")?
+            .into_iter()
+            .map(|tag| ArchiveTag {
+                key: tag.key,
+                value: tag.value,
+            })
+            .collect();
+
+        let entry_fields = custom_field::Entry::retrieve_entry(conn, &entry.id)
+            .await
+            .context("failed to retrieve entry custom fields")?;
+        let mut custom_fields = Vec::with_capacity(entry_fields.len());
+        let mut formatted_fields = Vec::new();
+
+        for field in entry_fields {
+            let Some(field_config) = field_index.get(&field.custom_fields_id).map(|index| &fields[*index]) else {
+                continue;
+            };
+
+            if format == ExportFormat::Site {
+                formatted_fields.push((field_config.name.clone(), field.value.format(&field_config.config)));
+            }
+
+            custom_fields.push(ArchiveCustomFieldValue {
+                name: field_config.name.clone(),
+                value: field.value,
+            });
+        }
+
+        let tasks = EntryTask::retrieve_entry(conn, &entry.id)
+            .await
+            .context("failed to retrieve entry tasks")?
+            .into_iter()
+            .map(|task| ArchiveTask {
+                text: task.text,
+                done: task.done,
+            })
+            .collect();
+
+        let mut file_entries: Vec<FileEntry> = Vec::new();
+        let stream = FileEntry::retrieve_entry_stream(conn, &entry.id)
+            .await
+            .context("failed to retrieve entry files")?;
+
+        futures::pin_mut!(stream);
+
+        while let Some(try_record) = stream.next().await {
+            file_entries.push(try_record.context("failed to retrieve entry file")?);
+        }
+
+        let mut files = Vec::with_capacity(file_entries.len());
+        let entry_dir = match format {
+            ExportFormat::Json => None,
+            ExportFormat::Markdown | ExportFormat::Site => Some(site::entry_dir(&entry.date)),
+        };
+
+        for file_entry in file_entries {
+            let source = journal_dir.file_path(&file_entry.id);
+
+            if !tokio::fs::try_exists(&source).await.unwrap_or(false) {
+                continue;
+            }
+
+            let file_name = match &file_entry.name {
+                Some(name) => format!("{}_{}", file_entry.uid, safe_name(name)),
+                None => file_entry.uid.to_string(),
+            };
+            let path = match &entry_dir {
+                Some(dir) => format!("{dir}/files/{file_name}"),
+                None => format!("files/{}/{file_name}", entry.date),
+            };
+
+            let file_key = if file_entry.encrypted {
+                Some(key.cloned().context("journal file is encrypted but encryption is not configured")?)
+            } else {
+                None
+            };
+
+            send(sender, ArchiveItem::File {
+                name: path.clone(),
+                path: source,
+                key: file_key,
+            }).await?;
+
+            files.push(ArchiveFile {
+                mime: file_entry.get_mime().to_string(),
+                name: file_entry.name,
+                size: file_entry.size,
+                path,
+            });
+        }
+
+        let mut archive_entry = ArchiveEntry {
+            uid: entry.uid,
+            number: entry.number,
+            date: entry.date,
+            title: entry.title,
+            contents: entry.contents,
+            created: entry.created,
+            updated: entry.updated,
+            planned: entry.planned,
+            ciphertext: entry.ciphertext,
+            tags,
+            tasks,
+            custom_fields,
+            files,
+        };
+
+        let item = match format {
+            ExportFormat::Json => ArchiveItem::Data {
+                name: format!("entries/{}_{}.json", archive_entry.date, archive_entry.number),
+                data: to_json(&archive_entry)?,
+            },
+            ExportFormat::Markdown => {
+                let contents = archive_entry.contents.take();
+                let front_matter = serde_yml::to_string(&archive_entry)
+                    .context("failed to serialize entry front matter")?;
+                let mut data = format!("---\n{front_matter}---\n");
+
+                if let Some(contents) = contents {
+                    data.push('\n');
+                    data.push_str(&contents);
+                    data.push('\n');
+                }
+
+                ArchiveItem::Data {
+                    name: format!(
+                        "{}/{}_{}.md",
+                        site::entry_dir(&archive_entry.date),
+                        archive_entry.date,
+                        archive_entry.number
+                    ),
+                    data: data.into_bytes(),
+                }
+            }
+            ExportFormat::Site => {
+                let path = format!(
+                    "{}/{}_{}.html",
+                    site::entry_dir(&archive_entry.date),
+                    archive_entry.date,
+                    archive_entry.number
+                );
+                let data = site::entry_page(&journal.name, &archive_entry, &formatted_fields);
+
+                site_entries.push(site::IndexEntry {
+                    date: archive_entry.date,
+                    title: archive_entry.title,
+                    path: path.clone(),
+                });
+
+                ArchiveItem::Data {
+                    name: path,
+                    data: data.into_bytes(),
+                }
+            }
+        };
+
+        send(sender, item).await?;
+    }
+
+    if format == ExportFormat::Site {
+        let pages = site::index_pages(&journal.name, journal.description.as_deref(), &site_entries);
+
+        for (name, data) in pages {
+            send(sender, ArchiveItem::Data {
+                name,
+                data: data.into_bytes(),
+            }).await?;
+        }
+
+        send(sender, ArchiveItem::Data {
+            name: String::from("style.css"),
+            data: site::STYLESHEET.as_bytes().to_vec(),
+        }).await?;
+    }
+
+    Ok(())
+}
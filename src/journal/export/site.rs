@@ -0,0 +1,241 @@
+//! renders the pages of a static html site export
+//!
+//! entries are placed in "year/month" directories along with their files.
+//! the root index lists the months of the journal and each month has an
+//! index of its entries. all links are relative so the site can be browsed
+//! straight from the file system
+
+use chrono::NaiveDate;
+
+use crate::journal::markdown;
+
+use super::ArchiveEntry;
+
+/// a minimal stylesheet shared by all the pages of the site
+pub const STYLESHEET: &str = "\
+body { font-family: sans-serif; line-height: 1.5; max-width: 48rem; margin: 0 auto; padding: 1rem; }
+nav { margin-bottom: 1rem; }
+img, video { max-width: 100%; }
+.date { color: #666; }
+.tags { list-style: none; padding: 0; }
+.tags li { display: inline-block; margin-right: 0.5rem; padding: 0 0.5rem; border: 1px solid #ccc; border-radius: 0.25rem; }
+dl { display: grid; grid-template-columns: max-content auto; gap: 0 1rem; }
+dd { margin: 0; }
+.tasks { list-style: none; padding: 0; }
+";
+
+/// an entry listed in the index of its month
+pub struct IndexEntry {
+    pub date: NaiveDate,
+    pub title: Option<String>,
+    pub path: String,
+}
+
+/// escapes text to be placed in html
+fn escape(value: &str) -> String {
+    let mut rtn = String::with_capacity(value.len());
+
+    for ch in value.chars() {
+        match ch {
+            '&' => rtn.push_str("&amp;"),
+            '<' => rtn.push_str("&lt;"),
+            '>' => rtn.push_str("&gt;"),
+            '"' => rtn.push_str("&quot;"),
+            '\'' => rtn.push_str("&#39;"),
+            _ => rtn.push(ch),
+        }
+    }
+
+    rtn
+}
+
+/// the directory that an entry and its files are placed in
+pub fn entry_dir(date: &NaiveDate) -> String {
+    date.format("%Y/%m").to_string()
+}
+
+fn page(title: &str, root: &str, body: &str) -> String {
+    format!(
+        "<!DOCTYPE html>\n\
+        <html>\n\
+        <head>\n\
+        <meta charset=\"utf-8\">\n\
+        <meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">\n\
+        <title>{}</title>\n\
+        <link rel=\"stylesheet\" href=\"{root}style.css\">\n\
+        </head>\n\
+        <body>\n\
+        {body}\
+        </body>\n\
+        </html>\n",
+        escape(title),
+    )
+}
+
+/// renders the page of a single entry
+///
+/// fields are the names and formatted values of the custom fields of the
+/// entry
+pub fn entry_page(journal: &str, entry: &ArchiveEntry, fields: &[(String, String)]) -> String {
+    let dir = entry_dir(&entry.date);
+    let heading = entry.title.clone()
+        .unwrap_or_else(|| entry.date.to_string());
+    let mut body = format!(
+        "<nav><a href=\"../../index.html\">{}</a> / <a href=\"index.html\">{}</a></nav>\n\
+        <article>\n\
+        <h1>{}</h1>\n\
+        <p class=\"date\">{}</p>\n",
+        escape(journal),
+        entry.date.format("%B %Y"),
+        escape(&heading),
+        entry.date.format("%A, %B %-d, %Y"),
+    );
+
+    if !entry.tags.is_empty() {
+        body.push_str("<ul class=\"tags\">\n");
+
+        for tag in &entry.tags {
+            match &tag.value {
+                Some(value) => body.push_str(&format!(
+                    "<li>{}: {}</li>\n",
+                    escape(&tag.key),
+                    escape(value)
+                )),
+                None => body.push_str(&format!("<li>{}</li>\n", escape(&tag.key))),
+            }
+        }
+
+        body.push_str("</ul>\n");
+    }
+
+    if !fields.is_empty() {
+        body.push_str("<dl>\n");
+
+        for (name, value) in fields {
+            body.push_str(&format!("<dt>{}</dt><dd>{}</dd>\n", escape(name), escape(value)));
+        }
+
+        body.push_str("</dl>\n");
+    }
+
+    if entry.ciphertext.is_some() {
+        body.push_str("<p><em>this entry is end-to-end encrypted</em></p>\n");
+    } else if let Some(contents) = &entry.contents {
+        body.push_str(&markdown::render_html(contents));
+        body.push('\n');
+    }
+
+    if !entry.tasks.is_empty() {
+        body.push_str("<ul class=\"tasks\">\n");
+
+        for task in &entry.tasks {
+            body.push_str(&format!(
+                "<li><input type=\"checkbox\" disabled{}> {}</li>\n",
+                if task.done { " checked" } else { "" },
+                escape(&task.text)
+            ));
+        }
+
+        body.push_str("</ul>\n");
+    }
+
+    if !entry.files.is_empty() {
+        body.push_str("<section class=\"files\">\n");
+
+        for file in &entry.files {
+            let src = file.path.strip_prefix(&format!("{dir}/"))
+                .unwrap_or(&file.path);
+            let src = escape(src);
+            let name = escape(file.name.as_deref().unwrap_or("file"));
+
+            if file.mime.starts_with("image/") {
+                body.push_str(&format!("<p><img src=\"{src}\" alt=\"{name}\"></p>\n"));
+            } else if file.mime.starts_with("video/") {
+                body.push_str(&format!("<p><video controls src=\"{src}\"></video></p>\n"));
+            } else if file.mime.starts_with("audio/") {
+                body.push_str(&format!("<p><audio controls src=\"{src}\"></audio></p>\n"));
+            } else {
+                body.push_str(&format!("<p><a href=\"{src}\">{name}</a></p>\n"));
+            }
+        }
+
+        body.push_str("</section>\n");
+    }
+
+    body.push_str("</article>\n");
+
+    page(&heading, "../../", &body)
+}
+
+/// renders the root index and the index of every month. the entries are
+/// expected to be ordered by date
+pub fn index_pages(journal: &str, description: Option<&str>, entries: &[IndexEntry]) -> Vec<(String, String)> {
+    let mut rtn = Vec::new();
+    let mut months = String::new();
+    let mut chunk_start = 0;
+
+    while chunk_start < entries.len() {
+        let dir = entry_dir(&entries[chunk_start].date);
+        let chunk_end = entries[chunk_start..].iter()
+            .position(|entry| entry_dir(&entry.date) != dir)
+            .map(|offset| chunk_start + offset)
+            .unwrap_or(entries.len());
+        let chunk = &entries[chunk_start..chunk_end];
+        let month = entries[chunk_start].date.format("%B %Y").to_string();
+
+        let mut body = format!(
+            "<nav><a href=\"../../index.html\">{}</a></nav>\n\
+            <h1>{month}</h1>\n\
+            <ul>\n",
+            escape(journal),
+        );
+
+        for entry in chunk {
+            let href = entry.path.strip_prefix(&format!("{dir}/"))
+                .unwrap_or(&entry.path);
+
+            match &entry.title {
+                Some(title) => body.push_str(&format!(
+                    "<li>{} <a href=\"{}\">{}</a></li>\n",
+                    entry.date,
+                    escape(href),
+                    escape(title)
+                )),
+                None => body.push_str(&format!(
+                    "<li><a href=\"{}\">{}</a></li>\n",
+                    escape(href),
+                    entry.date
+                )),
+            }
+        }
+
+        body.push_str("</ul>\n");
+
+        rtn.push((format!("{dir}/index.html"), page(&month, "../../", &body)));
+
+        months.push_str(&format!(
+            "<li><a href=\"{dir}/index.html\">{month}</a> ({})</li>\n",
+            chunk.len()
+        ));
+
+        chunk_start = chunk_end;
+    }
+
+    let mut body = format!("<h1>{}</h1>\n", escape(journal));
+
+    if let Some(description) = description {
+        body.push_str(&format!("<p>{}</p>\n", escape(description)));
+    }
+
+    if months.is_empty() {
+        body.push_str("<p>this journal does not have any entries</p>\n");
+    } else {
+        body.push_str("<ul>\n");
+        body.push_str(&months);
+        body.push_str("</ul>\n");
+    }
+
+    rtn.push((String::from("index.html"), page(journal, "", &body)));
+
+    rtn
+}
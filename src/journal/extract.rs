@@ -0,0 +1,279 @@
+//! searchable text extracted from file entries
+//!
+//! the [`JobKind::TextExtraction`] job extracts the text of uploaded files
+//! and stores it in the file_texts table where it is indexed for full-text
+//! search. plain text files are read directly while pdf and image files are
+//! given to the external commands from [`config::Extraction`] if they are
+//! configured. files of end-to-end encrypted journals are never extracted
+//!
+//! [`JobKind::TextExtraction`]: crate::jobs::JobKind::TextExtraction
+
+use std::process::Stdio;
+use std::time::Duration;
+
+use chrono::{DateTime, Duration as ChronoDuration, Utc};
+use tokio::io::AsyncWriteExt;
+
+use crate::config;
+use crate::db::{GenericClient, PgError};
+use crate::db::ids::{FileEntryId, JournalId, UserId};
+use crate::error::{self, Context};
+
+use super::FileEntry;
+
+/// the max number of files extracted in a single run of the job
+pub const BATCH_SIZE: i64 = 10;
+
+/// the max number of bytes of text stored for a file
+const MAX_TEXT: usize = 512 * 1024;
+
+/// the number of minutes that a claimed file is not claimed again
+const CLAIM_MINUTES: i64 = 15;
+
+/// the amount of time an external command has to finish
+const COMMAND_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// the ways that text can be extracted from a file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Source {
+    Text,
+    Pdf,
+    Image,
+}
+
+impl Source {
+    pub fn from_file_entry(file_entry: &FileEntry) -> Option<Self> {
+        match (file_entry.mime_type.as_str(), file_entry.mime_subtype.as_str()) {
+            ("text", _) => Some(Source::Text),
+            ("application", "pdf") => Some(Source::Pdf),
+            ("image", _) => Some(Source::Image),
+            _ => None
+        }
+    }
+}
+
+/// a file entry waiting to have its text extracted along with the journal
+/// it belongs to
+#[derive(Debug)]
+pub struct Pending {
+    pub file_entry: FileEntry,
+    pub journals_id: JournalId,
+    pub users_id: UserId,
+}
+
+/// claims a batch of uploaded files that have not been extracted since they
+/// were last changed. pdf and image files are only included if their
+/// commands are configured
+///
+/// the claimed files are not claimed again for [`CLAIM_MINUTES`] so that
+/// they are not extracted twice while the commands run outside of a
+/// transaction. if the server stops before storing the result then they
+/// will be extracted once the claim expires
+pub async fn claim_pending(
+    conn: &impl GenericClient,
+    extraction: &config::Extraction,
+    now: &DateTime<Utc>,
+    limit: i64,
+) -> Result<Vec<Pending>, PgError> {
+    let pdf = extraction.pdf.is_some();
+    let ocr = extraction.ocr.is_some();
+    let claimed = *now + ChronoDuration::minutes(CLAIM_MINUTES);
+
+    conn.query(
+        "\
+        with pending as ( \
+            select file_entries.id \
+            from file_entries \
+                join entries on \
+                    file_entries.entries_id = entries.id \
+                join journals on \
+                    entries.journals_id = journals.id \
+                left join file_texts on \
+                    file_entries.id = file_texts.file_entries_id \
+            where file_entries.hash is not null and \
+                  not journals.e2e and \
+                  ( \
+                      file_entries.mime_type = 'text' or \
+                      ($1 and file_entries.mime_type = 'application' and file_entries.mime_subtype = 'pdf') or \
+                      ($2 and file_entries.mime_type = 'image') \
+                  ) and \
+                  ( \
+                      file_texts.extracted is null or \
+                      file_texts.extracted < coalesce(file_entries.updated, file_entries.created) \
+                  ) and \
+                  (file_texts.claimed is null or file_texts.claimed <= $4) \
+            order by file_entries.id \
+            limit $3 \
+        ), claimed as ( \
+            insert into file_texts (file_entries_id, claimed) \
+            select pending.id, $5 from pending \
+            on conflict (file_entries_id) do update \
+                set claimed = excluded.claimed \
+            returning file_texts.file_entries_id \
+        ) \
+        select file_entries.id, \
+               file_entries.uid, \
+               file_entries.entries_id, \
+               file_entries.name, \
+               file_entries.mime_type, \
+               file_entries.mime_subtype, \
+               file_entries.mime_param, \
+               file_entries.size, \
+               file_entries.hash, \
+               file_entries.encrypted, \
+               file_entries.created, \
+               file_entries.updated, \
+               journals.id, \
+               journals.users_id \
+        from claimed \
+            join file_entries on \
+                claimed.file_entries_id = file_entries.id \
+            join entries on \
+                file_entries.entries_id = entries.id \
+            join journals on \
+                entries.journals_id = journals.id \
+        order by file_entries.id",
+        &[&pdf, &ocr, &limit, now, &claimed]
+    )
+        .await
+        .map(|rows| rows.into_iter().map(|row| Pending {
+            file_entry: FileEntry {
+                id: row.get(0),
+                uid: row.get(1),
+                entries_id: row.get(2),
+                name: row.get(3),
+                mime_type: row.get(4),
+                mime_subtype: row.get(5),
+                mime_param: row.get(6),
+                size: row.get(7),
+                hash: row.get(8),
+                encrypted: row.get(9),
+                created: row.get(10),
+                updated: row.get(11),
+            },
+            journals_id: row.get(12),
+            users_id: row.get(13),
+        }).collect())
+}
+
+/// runs an external command with the contents on stdin and returns stdout
+pub async fn run_command(command: &[String], contents: Vec<u8>) -> Result<Vec<u8>, error::Error> {
+    let (program, args) = command.split_first()
+        .context("external command is empty")?;
+
+    let mut child = tokio::process::Command::new(program)
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .kill_on_drop(true)
+        .spawn()
+        .context("failed to start external command")?;
+
+    let mut stdin = child.stdin.take()
+        .context("external command stdin is not available")?;
+
+    // stdin is written while the output is collected so that a command
+    // writing to stdout before reading all of stdin does not block
+    let writer = tokio::spawn(async move {
+        let _ = stdin.write_all(&contents).await;
+    });
+
+    let output = tokio::time::timeout(COMMAND_TIMEOUT, child.wait_with_output())
+        .await
+        .context("external command timed out")?
+        .context("failed to run external command")?;
+
+    let _ = writer.await;
+
+    if !output.status.success() {
+        return Err(error::Error::context(format!(
+            "external command exited with {}",
+            output.status
+        )));
+    }
+
+    Ok(output.stdout)
+}
+
+/// extracts the text of a file. the text is truncated if it is too large
+pub async fn extract(
+    extraction: &config::Extraction,
+    source: Source,
+    contents: Vec<u8>,
+) -> Result<String, error::Error> {
+    let output = match source {
+        Source::Text => contents,
+        Source::Pdf => match &extraction.pdf {
+            Some(command) => run_command(command, contents).await?,
+            None => return Err(error::Error::context("pdf extraction is not configured")),
+        },
+        Source::Image => match &extraction.ocr {
+            Some(command) => run_command(command, contents).await?,
+            None => return Err(error::Error::context("ocr is not configured")),
+        },
+    };
+
+    let mut text = String::from_utf8_lossy(&output).into_owned();
+
+    if text.len() > MAX_TEXT {
+        let mut end = MAX_TEXT;
+
+        while !text.is_char_boundary(end) {
+            end -= 1;
+        }
+
+        text.truncate(end);
+    }
+
+    // postgres does not allow null characters in text
+    Ok(text.replace('\0', ""))
+}
+
+/// stores the result of extracting the text of a file and releases its
+/// claim. the error is stored instead of the text so the file is not
+/// attempted again until it changes
+pub async fn store(
+    conn: &impl GenericClient,
+    file_entries_id: &FileEntryId,
+    result: Result<String, String>,
+) -> Result<(), PgError> {
+    let (contents, error) = match result {
+        Ok(contents) => (Some(contents), None),
+        Err(err) => (None, Some(err)),
+    };
+    let extracted = Utc::now();
+
+    conn.execute(
+        "\
+        insert into file_texts (file_entries_id, contents, error, extracted) values \
+        ($1, $2, $3, $4) \
+        on conflict (file_entries_id) do update \
+            set contents = excluded.contents, \
+                error = excluded.error, \
+                extracted = excluded.extracted, \
+                claimed = null",
+        &[file_entries_id, &contents, &error, &extracted]
+    ).await?;
+
+    Ok(())
+}
+
+/// the sql condition that matches entries with a title, contents, or
+/// extracted file text that matches the search. expects the entries table
+/// to be available as "entries"
+pub fn text_filter(param: usize) -> String {
+    format!(
+        "( \
+            to_tsvector('simple', coalesce(entries.title, '') || ' ' || coalesce(entries.contents, '')) @@ websearch_to_tsquery('simple', ${param}) or \
+            exists ( \
+                select 1 \
+                from file_entries \
+                    join file_texts on \
+                        file_entries.id = file_texts.file_entries_id \
+                where file_entries.entries_id = entries.id and \
+                      file_texts.search @@ websearch_to_tsquery('simple', ${param}) \
+            ) \
+        )"
+    )
+}
@@ -0,0 +1,117 @@
+//! read only tokens for the calendar feed of a journal
+//!
+//! calendar clients are not able to log in so the ics export of a journal
+//! accepts a [`FeedToken`] in the query instead of a session. a token only
+//! allows reading the feed of the journal it was issued for and is replaced
+//! when a new one is issued
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+use crate::db::{GenericClient, PgError};
+use crate::db::ids::{JournalId, UserId};
+use crate::error::{self, Context};
+use crate::sec::authn::session::Token;
+
+/// hashes the given token for storage in the database
+fn hash_token(token: &Token) -> Vec<u8> {
+    blake3::hash(token.as_ref()).as_bytes().to_vec()
+}
+
+/// the token that allows reading the calendar feed of a journal
+#[derive(Debug, Serialize)]
+pub struct FeedToken {
+    pub journals_id: JournalId,
+
+    /// the user that issued the token
+    pub users_id: UserId,
+    pub created: DateTime<Utc>,
+}
+
+impl FeedToken {
+    /// creates a new token for the journal, replacing any previous one
+    pub async fn issue(
+        conn: &impl GenericClient,
+        journals_id: &JournalId,
+        users_id: &UserId,
+    ) -> Result<(Self, Token), error::Error> {
+        let token = Token::new()
+            .context("failed to create feed token")?;
+        let hashed = hash_token(&token);
+        let created = Utc::now();
+
+        conn.execute(
+            "\
+            insert into journal_feed_tokens (journals_id, users_id, token, created) values \
+            ($1, $2, $3, $4) \
+            on conflict (journals_id) do update \
+                set users_id = excluded.users_id, \
+                    token = excluded.token, \
+                    created = excluded.created",
+            &[journals_id, users_id, &hashed, &created]
+        )
+            .await
+            .context("failed to store feed token")?;
+
+        Ok((Self {
+            journals_id: *journals_id,
+            users_id: *users_id,
+            created,
+        }, token))
+    }
+
+    /// retrieves the current token of the journal if one was issued
+    pub async fn retrieve(conn: &impl GenericClient, journals_id: &JournalId) -> Result<Option<Self>, PgError> {
+        conn.query_opt(
+            "\
+            select journal_feed_tokens.journals_id, \
+                   journal_feed_tokens.users_id, \
+                   journal_feed_tokens.created \
+            from journal_feed_tokens \
+            where journal_feed_tokens.journals_id = $1",
+            &[journals_id]
+        )
+            .await
+            .map(|maybe| maybe.map(|row| Self {
+                journals_id: row.get(0),
+                users_id: row.get(1),
+                created: row.get(2),
+            }))
+    }
+
+    /// retrieves the token of the journal if it matches the given token
+    pub async fn verify(
+        conn: &impl GenericClient,
+        journals_id: &JournalId,
+        token: &Token,
+    ) -> Result<Option<Self>, PgError> {
+        let hashed = hash_token(token);
+
+        conn.query_opt(
+            "\
+            select journal_feed_tokens.journals_id, \
+                   journal_feed_tokens.users_id, \
+                   journal_feed_tokens.created \
+            from journal_feed_tokens \
+            where journal_feed_tokens.journals_id = $1 and \
+                  journal_feed_tokens.token = $2",
+            &[journals_id, &hashed]
+        )
+            .await
+            .map(|maybe| maybe.map(|row| Self {
+                journals_id: row.get(0),
+                users_id: row.get(1),
+                created: row.get(2),
+            }))
+    }
+
+    /// removes the token of the journal. returns false if there was no token
+    pub async fn revoke(conn: &impl GenericClient, journals_id: &JournalId) -> Result<bool, PgError> {
+        let result = conn.execute(
+            "delete from journal_feed_tokens where journals_id = $1",
+            &[journals_id]
+        ).await?;
+
+        Ok(result == 1)
+    }
+}
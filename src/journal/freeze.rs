@@ -0,0 +1,120 @@
+//! temporary write freezes for journals
+//!
+//! while a journal is frozen the entries and files of the journal cannot be
+//! created, updated, or deleted. a freeze always has an expiration so that
+//! a journal cannot be left frozen if the operation holding it never
+//! finishes
+
+use chrono::{DateTime, Duration, Utc};
+use serde::Serialize;
+
+use crate::db::{GenericClient, PgError};
+use crate::db::ids::{ExportId, JournalId, UserId};
+
+/// the longest amount of time a journal can be frozen for
+pub const MAX_DURATION: Duration = Duration::hours(24);
+
+/// the amount of time a journal is frozen for while an export runs
+pub const EXPORT_DURATION: Duration = Duration::hours(1);
+
+#[derive(Debug, Serialize)]
+pub struct Freeze {
+    pub journals_id: JournalId,
+    pub users_id: UserId,
+
+    /// the export holding the freeze. the freeze is lifted once the export
+    /// finishes
+    pub journal_exports_id: Option<ExportId>,
+    pub created: DateTime<Utc>,
+    pub expires: DateTime<Utc>,
+}
+
+impl Freeze {
+    /// freezes the journal for the given duration, replacing any existing
+    /// freeze
+    pub async fn create(
+        conn: &impl GenericClient,
+        journals_id: &JournalId,
+        users_id: &UserId,
+        journal_exports_id: Option<&ExportId>,
+        duration: Duration,
+    ) -> Result<Self, PgError> {
+        let created = Utc::now();
+        let expires = created + duration;
+
+        conn.execute(
+            "\
+            insert into journal_freezes ( \
+                journals_id, \
+                users_id, \
+                journal_exports_id, \
+                created, \
+                expires \
+            ) values ($1, $2, $3, $4, $5) \
+            on conflict (journals_id) do update \
+                set users_id = excluded.users_id, \
+                    journal_exports_id = excluded.journal_exports_id, \
+                    created = excluded.created, \
+                    expires = excluded.expires",
+            &[journals_id, users_id, &journal_exports_id, &created, &expires]
+        ).await?;
+
+        Ok(Self {
+            journals_id: *journals_id,
+            users_id: *users_id,
+            journal_exports_id: journal_exports_id.copied(),
+            created,
+            expires,
+        })
+    }
+
+    /// retrieves the freeze for the journal if it has not expired
+    pub async fn retrieve_active(
+        conn: &impl GenericClient,
+        journals_id: &JournalId,
+    ) -> Result<Option<Self>, PgError> {
+        conn.query_opt(
+            "\
+            select journal_freezes.journals_id, \
+                   journal_freezes.users_id, \
+                   journal_freezes.journal_exports_id, \
+                   journal_freezes.created, \
+                   journal_freezes.expires \
+            from journal_freezes \
+            where journal_freezes.journals_id = $1 and \
+                  journal_freezes.expires > $2",
+            &[journals_id, &Utc::now()]
+        )
+            .await
+            .map(|maybe| maybe.map(|row| Self {
+                journals_id: row.get(0),
+                users_id: row.get(1),
+                journal_exports_id: row.get(2),
+                created: row.get(3),
+                expires: row.get(4),
+            }))
+    }
+
+    /// removes the freeze for the journal
+    ///
+    /// returns false if the journal was not frozen
+    pub async fn lift(conn: &impl GenericClient, journals_id: &JournalId) -> Result<bool, PgError> {
+        conn.execute(
+            "delete from journal_freezes where journals_id = $1",
+            &[journals_id]
+        )
+            .await
+            .map(|count| count > 0)
+    }
+
+    /// removes the freeze held by the given export if it has not already
+    /// been replaced
+    pub async fn lift_export(conn: &impl GenericClient, journal_exports_id: &ExportId) -> Result<(), PgError> {
+        conn.execute(
+            "delete from journal_freezes where journal_exports_id = $1",
+            &[journal_exports_id]
+        ).await?;
+
+        Ok(())
+    }
+}
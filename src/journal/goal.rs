@@ -0,0 +1,426 @@
+//! goals for the entries of a journal
+//!
+//! a goal is a target that is reached by a weekly or monthly period of
+//! entries. ex: write 20 entries a month or sleep at least 7 hours on 5 days
+//! of a week. progress is not stored and is instead evaluated from the
+//! entries of the journal when requested so that creating, updating, or
+//! deleting an entry is reflected right away. planned entries do not count
+//! towards a goal
+
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::str::FromStr;
+
+use bytes::BytesMut;
+use chrono::{DateTime, Datelike, Days, Months, NaiveDate, Utc};
+use postgres_types as pg_types;
+use serde::{Serialize, Deserialize};
+
+use crate::db::{self, GenericClient, PgError};
+use crate::db::ids::{CustomFieldId, GoalId, JournalId};
+use crate::error::BoxDynError;
+use crate::journal::WeekStart;
+
+#[derive(Debug, thiserror::Error)]
+#[error("the provided string is not a valid goal period")]
+pub struct InvalidGoalPeriod;
+
+/// the length of time that progress towards a goal is counted for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum GoalPeriod {
+    Week,
+    Month,
+}
+
+impl GoalPeriod {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            GoalPeriod::Week => "week",
+            GoalPeriod::Month => "month",
+        }
+    }
+
+    /// the first day of the period that the date is in
+    pub fn start_of(&self, date: &NaiveDate, week_start: WeekStart) -> NaiveDate {
+        match self {
+            GoalPeriod::Week => {
+                let since = (date.weekday().num_days_from_monday() + week_start.monday_offset() as u32) % 7;
+
+                *date - Days::new(since as u64)
+            }
+            GoalPeriod::Month => date.with_day(1).unwrap(),
+        }
+    }
+
+    /// moves the start of a period back by the given number of periods
+    fn sub_periods(&self, start: &NaiveDate, count: u32) -> NaiveDate {
+        match self {
+            GoalPeriod::Week => *start - Days::new(count as u64 * 7),
+            GoalPeriod::Month => *start - Months::new(count),
+        }
+    }
+
+    /// the postgres interval of the period
+    fn as_interval(&self) -> &'static str {
+        match self {
+            GoalPeriod::Week => "1 week",
+            GoalPeriod::Month => "1 month",
+        }
+    }
+}
+
+impl Display for GoalPeriod {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for GoalPeriod {
+    type Err = InvalidGoalPeriod;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "week" => Ok(GoalPeriod::Week),
+            "month" => Ok(GoalPeriod::Month),
+            _ => Err(InvalidGoalPeriod),
+        }
+    }
+}
+
+impl<'a> pg_types::FromSql<'a> for GoalPeriod {
+    fn from_sql(ty: &pg_types::Type, raw: &'a [u8]) -> Result<Self, BoxDynError> {
+        let v = <&str as pg_types::FromSql>::from_sql(ty, raw)?;
+
+        Ok(Self::from_str(v)?)
+    }
+
+    fn accepts(ty: &pg_types::Type) -> bool {
+        <&str as pg_types::FromSql>::accepts(ty)
+    }
+}
+
+impl pg_types::ToSql for GoalPeriod {
+    fn to_sql(&self, ty: &pg_types::Type, w: &mut BytesMut) -> Result<pg_types::IsNull, BoxDynError> {
+        self.as_str()
+            .to_sql(ty, w)
+    }
+
+    fn accepts(ty: &pg_types::Type) -> bool {
+        <&str as pg_types::ToSql>::accepts(ty)
+    }
+
+    pg_types::to_sql_checked!();
+}
+
+/// how the value of a custom field is compared
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Comparison {
+    Lt,
+    Lte,
+    Eq,
+    Gte,
+    Gt,
+}
+
+impl Comparison {
+    fn as_sql(&self) -> &'static str {
+        match self {
+            Comparison::Lt => "<",
+            Comparison::Lte => "<=",
+            Comparison::Eq => "=",
+            Comparison::Gte => ">=",
+            Comparison::Gt => ">",
+        }
+    }
+}
+
+/// what is counted towards the target of a goal
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum GoalKind {
+    /// the number of entries written
+    Entries,
+
+    /// the number of words written
+    Words,
+
+    /// the number of days with a numeric custom field value that compares
+    /// to the given value. range values are measured by the distance
+    /// between the low and high
+    Field {
+        custom_fields_id: CustomFieldId,
+        comparison: Comparison,
+        value: f64,
+    },
+}
+
+impl GoalKind {
+    /// the sql that counts the progress of the goal between the "start" and
+    /// "finish" columns of the "periods" table. the journal is $1 and the
+    /// custom field and value are $5 and $6
+    fn progress_sql(&self) -> String {
+        match self {
+            GoalKind::Entries => String::from("\
+                select count(*) \
+                from entries \
+                where entries.journals_id = $1 and \
+                      entries.entry_date between periods.start and periods.finish and \
+                      not entries.planned"
+            ),
+            GoalKind::Words => String::from("\
+                select coalesce(sum(entries.word_count), 0)::bigint \
+                from entries \
+                where entries.journals_id = $1 and \
+                      entries.entry_date between periods.start and periods.finish and \
+                      not entries.planned"
+            ),
+            GoalKind::Field { comparison, .. } => format!("\
+                select count(distinct entries.entry_date) \
+                from custom_field_entries \
+                    join entries on \
+                        custom_field_entries.entries_id = entries.id \
+                where entries.journals_id = $1 and \
+                      entries.entry_date between periods.start and periods.finish and \
+                      not entries.planned and \
+                      custom_field_entries.custom_fields_id = $5 and \
+                      case custom_field_entries.value ->> 'type' \
+                          when 'Integer' then (custom_field_entries.value ->> 'value')::float8 \
+                          when 'Float' then (custom_field_entries.value ->> 'value')::float8 \
+                          when 'IntegerRange' then (custom_field_entries.value ->> 'high')::float8 - \
+                                                   (custom_field_entries.value ->> 'low')::float8 \
+                          when 'FloatRange' then (custom_field_entries.value ->> 'high')::float8 - \
+                                                 (custom_field_entries.value ->> 'low')::float8 \
+                          else null \
+                      end {} $6",
+                comparison.as_sql()
+            ),
+        }
+    }
+}
+
+impl pg_types::ToSql for GoalKind {
+    fn to_sql(&self, ty: &pg_types::Type, w: &mut BytesMut) -> Result<pg_types::IsNull, BoxDynError> {
+        let wrapper: pg_types::Json<&Self> = pg_types::Json(self);
+
+        wrapper.to_sql(ty, w)
+    }
+
+    fn accepts(ty: &pg_types::Type) -> bool {
+        <pg_types::Json<Self> as pg_types::ToSql>::accepts(ty)
+    }
+
+    pg_types::to_sql_checked!();
+}
+
+impl<'a> pg_types::FromSql<'a> for GoalKind {
+    fn from_sql(ty: &pg_types::Type, raw: &'a [u8]) -> Result<Self, BoxDynError> {
+        let parsed: pg_types::Json<Self> = pg_types::Json::from_sql(ty, raw)?;
+
+        Ok(parsed.0)
+    }
+
+    fn accepts(ty: &pg_types::Type) -> bool {
+        <pg_types::Json<Self> as pg_types::FromSql>::accepts(ty)
+    }
+}
+
+/// the progress towards a goal for a single period
+#[derive(Debug, Serialize)]
+pub struct GoalProgress {
+    /// the first day of the period
+    pub start: NaiveDate,
+
+    /// the last day of the period
+    pub end: NaiveDate,
+    pub value: i64,
+    pub target: i64,
+    pub complete: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Goal {
+    pub id: GoalId,
+    pub journals_id: JournalId,
+    pub name: String,
+    pub kind: GoalKind,
+    pub period: GoalPeriod,
+
+    /// the value that the goal must reach in a period to be complete
+    pub target: i64,
+    pub created: DateTime<Utc>,
+    pub updated: Option<DateTime<Utc>>,
+}
+
+impl Goal {
+    fn map_row(row: tokio_postgres::Row) -> Self {
+        Self {
+            id: row.get(0),
+            journals_id: row.get(1),
+            name: row.get(2),
+            kind: row.get(3),
+            period: row.get(4),
+            target: row.get(5),
+            created: row.get(6),
+            updated: row.get(7),
+        }
+    }
+
+    /// retrieves the goals of the journal ordered by name
+    pub async fn retrieve_journal(conn: &impl GenericClient, journals_id: &JournalId) -> Result<Vec<Self>, PgError> {
+        conn.query(
+            "\
+            select journal_goals.id, \
+                   journal_goals.journals_id, \
+                   journal_goals.name, \
+                   journal_goals.kind, \
+                   journal_goals.period, \
+                   journal_goals.target, \
+                   journal_goals.created, \
+                   journal_goals.updated \
+            from journal_goals \
+            where journal_goals.journals_id = $1 \
+            order by journal_goals.name",
+            &[journals_id]
+        )
+            .await
+            .map(|rows| rows.into_iter().map(Self::map_row).collect())
+    }
+
+    pub async fn retrieve_id(
+        conn: &impl GenericClient,
+        journals_id: &JournalId,
+        goals_id: &GoalId,
+    ) -> Result<Option<Self>, PgError> {
+        conn.query_opt(
+            "\
+            select journal_goals.id, \
+                   journal_goals.journals_id, \
+                   journal_goals.name, \
+                   journal_goals.kind, \
+                   journal_goals.period, \
+                   journal_goals.target, \
+                   journal_goals.created, \
+                   journal_goals.updated \
+            from journal_goals \
+            where journal_goals.journals_id = $1 and \
+                  journal_goals.id = $2",
+            &[journals_id, goals_id]
+        )
+            .await
+            .map(|maybe| maybe.map(Self::map_row))
+    }
+
+    pub async fn create(
+        conn: &impl GenericClient,
+        journals_id: &JournalId,
+        name: String,
+        kind: GoalKind,
+        period: GoalPeriod,
+        target: i64,
+    ) -> Result<Self, PgError> {
+        let created = Utc::now();
+
+        let row = conn.query_one(
+            "\
+            insert into journal_goals (journals_id, name, kind, period, target, created) values \
+            ($1, $2, $3, $4, $5, $6) \
+            returning id",
+            &[journals_id, &name, &kind, &period, &target, &created]
+        ).await?;
+
+        Ok(Self {
+            id: row.get(0),
+            journals_id: *journals_id,
+            name,
+            kind,
+            period,
+            target,
+            created,
+            updated: None,
+        })
+    }
+
+    /// saves the current name, kind, period, and target of the goal
+    pub async fn update(&mut self, conn: &impl GenericClient) -> Result<(), PgError> {
+        let updated = Utc::now();
+
+        conn.execute(
+            "\
+            update journal_goals \
+            set name = $2, \
+                kind = $3, \
+                period = $4, \
+                target = $5, \
+                updated = $6 \
+            where id = $1",
+            &[&self.id, &self.name, &self.kind, &self.period, &self.target, &updated]
+        ).await?;
+
+        self.updated = Some(updated);
+
+        Ok(())
+    }
+
+    pub async fn delete(&self, conn: &impl GenericClient) -> Result<(), PgError> {
+        conn.execute(
+            "delete from journal_goals where id = $1",
+            &[&self.id]
+        ).await?;
+
+        Ok(())
+    }
+
+    /// evaluates the progress of the goal for the period that the given date
+    /// is in and the periods before it, oldest first
+    pub async fn progress(
+        &self,
+        conn: &impl GenericClient,
+        date: &NaiveDate,
+        week_start: WeekStart,
+        periods: u32,
+    ) -> Result<Vec<GoalProgress>, PgError> {
+        let last = self.period.start_of(date, week_start);
+        let first = self.period.sub_periods(&last, periods.saturating_sub(1));
+        let interval = self.period.as_interval();
+
+        let mut params: db::ParamsVec<'_> = vec![&self.journals_id, &first, &last, &interval];
+
+        if let GoalKind::Field { custom_fields_id, value, .. } = &self.kind {
+            params.push(custom_fields_id);
+            params.push(value);
+        }
+
+        let rows = conn.query(
+            &format!(
+                "\
+                with periods as ( \
+                    select series.start::date as start, \
+                           (series.start + $4::varchar::interval - interval '1 day')::date as finish \
+                    from generate_series($2::date, $3::date, $4::varchar::interval) as series (start) \
+                ) \
+                select periods.start, \
+                       periods.finish, \
+                       ({}) as value \
+                from periods \
+                order by periods.start",
+                self.kind.progress_sql()
+            ),
+            params.as_slice()
+        ).await?;
+
+        Ok(rows.into_iter()
+            .map(|row| {
+                let value: i64 = row.get(2);
+
+                GoalProgress {
+                    start: row.get(0),
+                    end: row.get(1),
+                    value,
+                    target: self.target,
+                    complete: value >= self.target,
+                }
+            })
+            .collect())
+    }
+}
@@ -0,0 +1,233 @@
+//! metadata of image file entries
+//!
+//! jpeg, png, and webp uploads are read into memory so that the capture
+//! date in their exif data can be stored in file_images. clients can use it
+//! to suggest the date of the entry. when the upload policy of the journal
+//! strips metadata, the exif, xmp, and text metadata of the image are
+//! removed before it is stored so that gps coordinates and camera details
+//! are not shared with the file. only the orientation is kept so that the
+//! image is still displayed correctly. imported images are handled the same
+//! way before they are written
+
+use std::collections::HashMap;
+use std::io::Cursor;
+
+use bytes::{Bytes, BytesMut, BufMut};
+use chrono::{NaiveDate, NaiveDateTime};
+use exif::{Exif, Field, In, Tag, Value};
+use img_parts::{ImageEXIF, jpeg, png, webp};
+use serde::Serialize;
+
+use crate::db::{GenericClient, PgError};
+use crate::db::ids::FileEntryId;
+use crate::error::{self, Context};
+
+/// the prefix of exif data in a jpeg app1 segment
+const JPEG_EXIF_PREFIX: &[u8] = b"Exif\0\0";
+
+/// png chunks that can contain metadata
+const PNG_METADATA_CHUNKS: [[u8; 4]; 4] = [*b"eXIf", *b"tEXt", *b"zTXt", *b"iTXt"];
+
+/// checks if the metadata of the mime type can be read
+pub fn is_supported(mime: &mime::Mime) -> bool {
+    mime.type_() == mime::IMAGE && matches!(
+        mime.subtype().as_str(),
+        "jpeg" | "png" | "webp"
+    )
+}
+
+/// the details of an image file entry
+#[derive(Debug, Serialize)]
+pub struct ImageDetails {
+    /// when the image was taken according to its exif data. there is no
+    /// timezone since cameras store the local time
+    pub captured: Option<NaiveDateTime>,
+
+    /// the metadata of the image was removed when it was uploaded
+    pub stripped: bool,
+}
+
+/// an uploaded image after its metadata has been read
+#[derive(Debug)]
+pub struct ProcessedImage {
+    pub contents: Bytes,
+    pub details: ImageDetails,
+}
+
+fn exif_datetime(exif: &Exif, tag: Tag) -> Option<NaiveDateTime> {
+    let field = exif.get_field(tag, In::PRIMARY)?;
+
+    let Value::Ascii(values) = &field.value else {
+        return None;
+    };
+
+    let parsed = exif::DateTime::from_ascii(values.first()?).ok()?;
+
+    NaiveDate::from_ymd_opt(parsed.year.into(), parsed.month.into(), parsed.day.into())?
+        .and_hms_opt(parsed.hour.into(), parsed.minute.into(), parsed.second.into())
+}
+
+/// creates exif data that only contains the orientation of the image
+fn orientation_exif(exif: &Exif) -> Option<Bytes> {
+    let orientation = exif.get_field(Tag::Orientation, In::PRIMARY)?
+        .value
+        .get_uint(0)?;
+
+    // 1 is the default orientation so there is nothing to keep
+    if orientation == 1 {
+        return None;
+    }
+
+    let field = Field {
+        tag: Tag::Orientation,
+        ifd_num: In::PRIMARY,
+        value: Value::Short(vec![u16::try_from(orientation).ok()?]),
+    };
+
+    let mut writer = exif::experimental::Writer::new();
+    writer.push_field(&field);
+
+    let mut output = Cursor::new(Vec::new());
+    writer.write(&mut output, false).ok()?;
+
+    Some(Bytes::from(output.into_inner()))
+}
+
+fn strip(contents: Bytes, subtype: &str, keep: Option<Bytes>) -> Result<Bytes, error::Error> {
+    match subtype {
+        "jpeg" => {
+            let mut image = jpeg::Jpeg::from_bytes(contents)
+                .context("failed to parse jpeg")?;
+            let segments = image.segments_mut();
+
+            // app1 holds exif and xmp while app13 holds iptc
+            segments.retain(|segment| {
+                segment.marker() != jpeg::markers::APP1 && segment.marker() != jpeg::markers::APP13
+            });
+
+            if let Some(keep) = keep {
+                let mut data = BytesMut::with_capacity(JPEG_EXIF_PREFIX.len() + keep.len());
+                data.put(JPEG_EXIF_PREFIX);
+                data.put(keep);
+
+                // the exif segment goes after the jfif header if there is one
+                let index = segments.iter()
+                    .take_while(|segment| segment.marker() == jpeg::markers::APP0)
+                    .count();
+
+                segments.insert(index, jpeg::JpegSegment::new_with_contents(
+                    jpeg::markers::APP1,
+                    data.freeze()
+                ));
+            }
+
+            Ok(image.encoder().bytes())
+        }
+        "png" => {
+            let mut image = png::Png::from_bytes(contents)
+                .context("failed to parse png")?;
+
+            for kind in PNG_METADATA_CHUNKS {
+                image.remove_chunks_by_type(kind);
+            }
+
+            if keep.is_some() {
+                image.set_exif(keep);
+            }
+
+            Ok(image.encoder().bytes())
+        }
+        "webp" => {
+            let mut image = webp::WebP::from_bytes(contents)
+                .context("failed to parse webp")?;
+
+            image.remove_chunks_by_id(webp::CHUNK_XMP);
+            image.set_exif(keep);
+
+            Ok(image.encoder().bytes())
+        }
+        _ => Err(error::Error::context(format!("unsupported image type: \"{subtype}\""))),
+    }
+}
+
+/// reads the capture date of the image and removes its metadata if
+/// requested
+///
+/// this should be run on the blocking thread pool
+pub fn process(contents: Bytes, subtype: &str, strip_metadata: bool) -> Result<ProcessedImage, error::Error> {
+    let exif = exif::Reader::new()
+        .read_from_container(&mut Cursor::new(&contents))
+        .ok();
+
+    let captured = exif.as_ref().and_then(|exif| {
+        exif_datetime(exif, Tag::DateTimeOriginal)
+            .or_else(|| exif_datetime(exif, Tag::DateTime))
+    });
+
+    let contents = if strip_metadata {
+        let keep = exif.as_ref().and_then(orientation_exif);
+
+        strip(contents, subtype, keep)?
+    } else {
+        contents
+    };
+
+    Ok(ProcessedImage {
+        contents,
+        details: ImageDetails {
+            captured,
+            stripped: strip_metadata,
+        },
+    })
+}
+
+/// retrieves the details of the given image file entries
+pub async fn retrieve_ids(
+    conn: &impl GenericClient,
+    ids: &[FileEntryId],
+) -> Result<HashMap<FileEntryId, ImageDetails>, PgError> {
+    let rows = conn.query(
+        "\
+        select file_images.file_entries_id, \
+               file_images.captured, \
+               file_images.stripped \
+        from file_images \
+        where file_images.file_entries_id = any($1)",
+        &[&ids]
+    ).await?;
+
+    Ok(rows.into_iter()
+        .map(|row| (row.get(0), ImageDetails {
+            captured: row.get(1),
+            stripped: row.get(2),
+        }))
+        .collect())
+}
+
+/// stores the details of a newly uploaded image. the details of a file
+/// that is no longer an image are removed if None is given
+pub async fn store_upload(
+    conn: &impl GenericClient,
+    file_entries_id: &FileEntryId,
+    details: Option<&ImageDetails>,
+) -> Result<(), PgError> {
+    let Some(details) = details else {
+        conn.execute(
+            "delete from file_images where file_entries_id = $1",
+            &[file_entries_id]
+        ).await?;
+
+        return Ok(());
+    };
+
+    conn.execute(
+        "\
+        insert into file_images (file_entries_id, captured, stripped) values ($1, $2, $3) \
+        on conflict (file_entries_id) do update \
+            set captured = excluded.captured, \
+                stripped = excluded.stripped",
+        &[file_entries_id, &details.captured, &details.stripped]
+    ).await?;
+
+    Ok(())
+}
@@ -0,0 +1,1368 @@
+//! importing entries from other journaling apps
+//!
+//! an uploaded archive is stored in the imports directory of the journal and
+//! processed by the [`JobKind::Imports`] job. the archive is read on a
+//! blocking task that sends each entry, along with the contents of its
+//! files, to be inserted into the journal. every entry is inserted in its
+//! own transaction so the progress of an import is visible while it runs
+//!
+//! [`JobKind::Imports`]: crate::jobs::JobKind::Imports
+
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::io::Read;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use bytes::{Bytes, BytesMut};
+use chrono::{DateTime, NaiveDate, Utc};
+use futures::StreamExt;
+use postgres_types as pg_types;
+use serde::{Serialize, Deserialize};
+use tokio::sync::mpsc;
+
+use crate::config;
+use crate::db::{self, GenericClient, PgError};
+use crate::db::ids::{CustomFieldId, EntryId, EntryUid, FileEntryId, FileEntryUid, ImportId, JournalId, UserId};
+use crate::error::{self, Context, BoxDynError};
+use crate::sec::encryption::JournalKey;
+use crate::sec::scan::{self, Verdict};
+use crate::state::Storage;
+
+use super::{custom_field, entry_word_count, is_planned_date, mention, tag, CustomField, CustomFieldOptions, CreateCustomFieldError, Journal, JournalDir, MultiEntry};
+use super::image_meta::{self, ImageDetails};
+use super::task::{EntryTask, TaskInput};
+use super::upload::UploadPolicy;
+
+pub mod archive;
+pub mod dayone;
+pub mod jrnl;
+pub mod markdown;
+
+/// the max number of entries waiting to be inserted
+const IMPORT_QUEUE: usize = 4;
+
+/// the max number of warnings stored for an import. any after are only
+/// logged
+const MAX_WARNINGS: usize = 100;
+
+/// the max number of bytes read for a single file of an archive. the files
+/// of an entry are held in memory until they are written so this applies
+/// even if the upload policy of the journal does not limit the file size
+const MAX_ARCHIVE_FILE_SIZE: u64 = 256 * 1024 * 1024;
+
+#[derive(Debug, thiserror::Error)]
+#[error("the provided string is not a valid import source")]
+pub struct InvalidImportSource;
+
+/// the app that an import was exported from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ImportSource {
+    /// the json or zip export of Day One
+    DayOne,
+
+    /// a zip of markdown files with optional yaml front matter
+    Markdown,
+
+    /// the plain text format of jrnl
+    Jrnl,
+
+    /// a json export of this server
+    Archive,
+}
+
+impl ImportSource {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ImportSource::DayOne => "dayone",
+            ImportSource::Markdown => "markdown",
+            ImportSource::Jrnl => "jrnl",
+            ImportSource::Archive => "archive",
+        }
+    }
+
+    /// true if the source has locations and weather that need the custom
+    /// fields of [`FieldNames`]
+    fn has_location(&self) -> bool {
+        matches!(self, ImportSource::DayOne)
+    }
+
+    /// reads the archive at the given path and sends its entries. files
+    /// larger than the given number of bytes are left out
+    fn read(&self, path: PathBuf, max_file_size: u64, sender: mpsc::Sender<ImportItem>) -> Result<(), error::Error> {
+        match self {
+            ImportSource::DayOne => dayone::read(path, max_file_size, sender),
+            ImportSource::Markdown => markdown::read(path, sender),
+            ImportSource::Jrnl => jrnl::read(path, sender),
+            ImportSource::Archive => archive::read(path, max_file_size, sender),
+        }
+    }
+}
+
+impl Display for ImportSource {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for ImportSource {
+    type Err = InvalidImportSource;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "dayone" => Ok(ImportSource::DayOne),
+            "markdown" => Ok(ImportSource::Markdown),
+            "jrnl" => Ok(ImportSource::Jrnl),
+            "archive" => Ok(ImportSource::Archive),
+            _ => Err(InvalidImportSource)
+        }
+    }
+}
+
+impl<'a> pg_types::FromSql<'a> for ImportSource {
+    fn from_sql(ty: &pg_types::Type, raw: &'a [u8]) -> Result<Self, BoxDynError> {
+        let v = <&str as pg_types::FromSql>::from_sql(ty, raw)?;
+
+        Ok(Self::from_str(v)?)
+    }
+
+    fn accepts(ty: &pg_types::Type) -> bool {
+        <&str as pg_types::FromSql>::accepts(ty)
+    }
+}
+
+impl pg_types::ToSql for ImportSource {
+    fn to_sql(&self, ty: &pg_types::Type, w: &mut BytesMut) -> Result<pg_types::IsNull, BoxDynError> {
+        self.as_str()
+            .to_sql(ty, w)
+    }
+
+    fn accepts(ty: &pg_types::Type) -> bool {
+        <&str as pg_types::ToSql>::accepts(ty)
+    }
+
+    pg_types::to_sql_checked!();
+}
+
+/// an uploaded archive to be imported into a journal
+#[derive(Debug, Serialize)]
+pub struct JournalImport {
+    pub id: ImportId,
+    pub journals_id: JournalId,
+    pub users_id: UserId,
+    pub source: ImportSource,
+
+    /// the number of entries in the archive. None until the archive has
+    /// been read
+    pub total: Option<i32>,
+
+    /// the number of entries that have been looked at
+    pub processed: i32,
+
+    /// the number of entries that have been added to the journal
+    pub imported: i32,
+
+    /// the number of files that have been added to the journal
+    pub files: i32,
+
+    /// the reason the import failed
+    pub error: Option<String>,
+
+    /// entries that were skipped and files that could not be read from the
+    /// archive
+    pub warnings: Vec<String>,
+    pub created: DateTime<Utc>,
+
+    /// when the archive finished uploading. the import is not started
+    /// until then
+    pub uploaded: Option<DateTime<Utc>>,
+    pub started: Option<DateTime<Utc>>,
+    pub completed: Option<DateTime<Utc>>,
+    pub failed: Option<DateTime<Utc>>,
+}
+
+impl JournalImport {
+    fn map_row(row: tokio_postgres::Row) -> Self {
+        Self {
+            id: row.get(0),
+            journals_id: row.get(1),
+            users_id: row.get(2),
+            source: row.get(3),
+            total: row.get(4),
+            processed: row.get(5),
+            imported: row.get(6),
+            files: row.get(7),
+            error: row.get(8),
+            warnings: row.get(9),
+            created: row.get(10),
+            uploaded: row.get(11),
+            started: row.get(12),
+            completed: row.get(13),
+            failed: row.get(14),
+        }
+    }
+
+    pub async fn create(
+        conn: &impl GenericClient,
+        journals_id: &JournalId,
+        users_id: &UserId,
+        source: ImportSource,
+    ) -> Result<Self, PgError> {
+        let created = Utc::now();
+
+        let row = conn.query_one(
+            "\
+            insert into journal_imports (journals_id, users_id, source, created) values \
+            ($1, $2, $3, $4) \
+            returning id",
+            &[journals_id, users_id, &source, &created]
+        ).await?;
+
+        Ok(Self {
+            id: row.get(0),
+            journals_id: *journals_id,
+            users_id: *users_id,
+            source,
+            total: None,
+            processed: 0,
+            imported: 0,
+            files: 0,
+            error: None,
+            warnings: Vec::new(),
+            created,
+            uploaded: None,
+            started: None,
+            completed: None,
+            failed: None,
+        })
+    }
+
+    pub async fn retrieve(
+        conn: &impl GenericClient,
+        journals_id: &JournalId,
+        imports_id: &ImportId,
+    ) -> Result<Option<Self>, PgError> {
+        conn.query_opt(
+            "\
+            select journal_imports.id, \
+                   journal_imports.journals_id, \
+                   journal_imports.users_id, \
+                   journal_imports.source, \
+                   journal_imports.total, \
+                   journal_imports.processed, \
+                   journal_imports.imported, \
+                   journal_imports.files, \
+                   journal_imports.error, \
+                   journal_imports.warnings, \
+                   journal_imports.created, \
+                   journal_imports.uploaded, \
+                   journal_imports.started, \
+                   journal_imports.completed, \
+                   journal_imports.failed \
+            from journal_imports \
+            where journal_imports.journals_id = $1 and \
+                  journal_imports.id = $2",
+            &[journals_id, imports_id]
+        )
+            .await
+            .map(|maybe| maybe.map(Self::map_row))
+    }
+
+    /// marks the oldest uploaded import that has not started as started and
+    /// returns it
+    pub async fn claim_pending(conn: &impl GenericClient) -> Result<Option<Self>, PgError> {
+        let started = Utc::now();
+
+        conn.query_opt(
+            "\
+            update journal_imports \
+            set started = $1 \
+            where id = ( \
+                select journal_imports.id \
+                from journal_imports \
+                where journal_imports.uploaded is not null and \
+                      journal_imports.started is null \
+                order by journal_imports.created \
+                limit 1 \
+                for update skip locked \
+            ) \
+            returning id, \
+                      journals_id, \
+                      users_id, \
+                      source, \
+                      total, \
+                      processed, \
+                      imported, \
+                      files, \
+                      error, \
+                      warnings, \
+                      created, \
+                      uploaded, \
+                      started, \
+                      completed, \
+                      failed",
+            &[&started]
+        )
+            .await
+            .map(|maybe| maybe.map(Self::map_row))
+    }
+
+    async fn update_progress(&self, conn: &impl GenericClient) -> Result<(), PgError> {
+        conn.execute(
+            "\
+            update journal_imports \
+            set total = $2, \
+                processed = $3, \
+                imported = $4, \
+                files = $5, \
+                warnings = $6 \
+            where id = $1",
+            &[&self.id, &self.total, &self.processed, &self.imported, &self.files, &self.warnings]
+        ).await?;
+
+        Ok(())
+    }
+
+    pub async fn mark_uploaded(&mut self, conn: &impl GenericClient) -> Result<(), PgError> {
+        let now = Utc::now();
+
+        conn.execute(
+            "update journal_imports set uploaded = $2 where id = $1",
+            &[&self.id, &now]
+        ).await?;
+
+        self.uploaded = Some(now);
+
+        Ok(())
+    }
+
+    pub async fn mark_completed(&mut self, conn: &impl GenericClient) -> Result<(), PgError> {
+        let now = Utc::now();
+
+        conn.execute(
+            "update journal_imports set completed = $2 where id = $1",
+            &[&self.id, &now]
+        ).await?;
+
+        self.completed = Some(now);
+
+        Ok(())
+    }
+
+    pub async fn mark_failed(&mut self, conn: &impl GenericClient, error: String) -> Result<(), PgError> {
+        let now = Utc::now();
+
+        conn.execute(
+            "update journal_imports set failed = $2, error = $3 where id = $1",
+            &[&self.id, &now, &error]
+        ).await?;
+
+        self.failed = Some(now);
+        self.error = Some(error);
+
+        Ok(())
+    }
+
+    fn add_warning(&mut self, warning: String) {
+        tracing::warn!(imports_id = %self.id, "{warning}");
+
+        if self.warnings.len() < MAX_WARNINGS {
+            self.warnings.push(warning);
+        }
+    }
+
+    /// reads the archive at the given path and inserts its entries into the
+    /// journal
+    pub async fn run(
+        &mut self,
+        conn: &mut db::Object,
+        journal: &Journal,
+        field_names: &FieldNames,
+        files: &FileOptions<'_>,
+        path: PathBuf,
+    ) -> Result<(), error::Error> {
+        let fields = ImportFields::ensure(conn, journal, field_names, self.source.has_location()).await?;
+
+        let source = self.source;
+        let max_file_size = file_size_limit(files.policy);
+
+        let (sender, mut receiver) = mpsc::channel(IMPORT_QUEUE);
+        let reader = tokio::task::spawn_blocking(move || source.read(path, max_file_size, sender));
+
+        let mut result = Ok(());
+
+        while let Some(item) = receiver.recv().await {
+            match item {
+                ImportItem::Total(total) => {
+                    self.total = Some(i32::try_from(total).unwrap_or(i32::MAX));
+                }
+                ImportItem::Entry(mut entry) => {
+                    let inserted = match prepare_files(&*conn, files, &mut entry).await {
+                        Ok(warnings) => {
+                            for warning in warnings {
+                                self.add_warning(warning);
+                            }
+
+                            insert_entry(conn, journal, &journal.users_id, &fields, files, entry).await
+                        }
+                        Err(err) => Err(err),
+                    };
+
+                    match inserted {
+                        Ok(files) => {
+                            self.imported += 1;
+                            self.files += files;
+                        }
+                        Err(err) => {
+                            result = Err(err);
+
+                            break;
+                        }
+                    }
+
+                    self.processed += 1;
+                }
+                ImportItem::Skipped(reason) => {
+                    self.add_warning(format!("skipped {reason}"));
+                    self.processed += 1;
+                }
+                ImportItem::Warning(warning) => {
+                    self.add_warning(warning);
+                }
+            }
+
+            self.update_progress(&*conn)
+                .await
+                .context("failed to update import progress")?;
+        }
+
+        // dropping the receiver stops the reader if an entry failed
+        drop(receiver);
+
+        let read = reader.await
+            .context("failed to join archive reader")?;
+
+        result?;
+        read
+    }
+}
+
+/// how the files of an import are checked and written. the checks are the
+/// same ones made on uploaded files
+#[derive(Debug)]
+pub struct FileOptions<'a> {
+    pub journal_dir: &'a JournalDir,
+
+    /// the key to encrypt the files with if the journal is encrypted
+    pub key: Option<&'a JournalKey>,
+
+    /// the free space of the storage directory is checked before each file
+    /// is written
+    pub storage: &'a Storage,
+
+    /// the upload policy of the journal. files larger than its max file size
+    /// are not read from the archive and files that it does not allow are
+    /// skipped with a warning
+    pub policy: &'a UploadPolicy,
+
+    /// files found to be infected are skipped with a warning
+    pub scan: Option<&'a config::Scan>,
+}
+
+/// the max number of bytes read for a single file of an archive imported
+/// into a journal with the given upload policy
+pub fn file_size_limit(policy: &UploadPolicy) -> u64 {
+    policy.max_file_size.map_or(MAX_ARCHIVE_FILE_SIZE, |max| max.min(MAX_ARCHIVE_FILE_SIZE))
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum ReadFileError {
+    #[error("file is larger than {0} bytes")]
+    TooLarge(u64),
+
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+}
+
+/// reads a file of an archive without reading more than the given number of
+/// bytes
+///
+/// the size that an archive records for a file is never used to allocate
+/// since the author of the archive controls it
+pub fn read_file(reader: impl Read, limit: u64) -> Result<Vec<u8>, ReadFileError> {
+    let mut contents = Vec::new();
+
+    reader.take(limit.saturating_add(1)).read_to_end(&mut contents)?;
+
+    if contents.len() as u64 > limit {
+        return Err(ReadFileError::TooLarge(limit));
+    }
+
+    Ok(contents)
+}
+
+/// the names of the custom fields that imported locations and weather are
+/// stored in
+#[derive(Debug)]
+pub struct FieldNames {
+    pub location: String,
+    pub weather: String,
+}
+
+impl FieldNames {
+    /// imported locations and weather go in the same fields that the
+    /// weather job uses
+    pub fn from_state(state: &crate::state::SharedState) -> Self {
+        match state.weather() {
+            Some(weather) => FieldNames {
+                location: weather.location_field.clone(),
+                weather: weather.weather_field.clone(),
+            },
+            None => FieldNames {
+                location: String::from("location"),
+                weather: String::from("weather"),
+            },
+        }
+    }
+}
+
+/// the custom fields of the journal used by an import
+#[derive(Debug)]
+struct ImportFields {
+    /// None if the journal has a field with the same name that is not a
+    /// text field
+    location: Option<CustomFieldId>,
+
+    /// None if the journal has a field with the same name that is not a
+    /// text field
+    weather: Option<CustomFieldId>,
+    named: HashMap<String, (CustomFieldId, custom_field::Type)>,
+}
+
+impl ImportFields {
+    /// retrieves the custom fields of the journal, creating the location
+    /// and weather fields if they do not exist and are needed
+    async fn ensure(
+        conn: &impl GenericClient,
+        journal: &Journal,
+        names: &FieldNames,
+        has_location: bool,
+    ) -> Result<Self, error::Error> {
+        let (location, weather) = if has_location {
+            (
+                ensure_text_field(conn, journal, &names.location).await?,
+                ensure_text_field(conn, journal, &names.weather).await?,
+            )
+        } else {
+            (None, None)
+        };
+
+        let mut named = HashMap::new();
+        let stream = CustomField::retrieve_journal_stream(conn, &journal.id)
+            .await
+            .context("failed to retrieve journal custom fields")?;
+
+        futures::pin_mut!(stream);
+
+        while let Some(try_record) = stream.next().await {
+            let record = try_record.context("failed to retrieve journal custom field")?;
+
+            named.insert(record.name, (record.id, record.config));
+        }
+
+        Ok(Self {
+            location,
+            weather,
+            named,
+        })
+    }
+
+    /// finds the field for an imported value and converts the value to the
+    /// type of the field. None if the journal does not have the field or the
+    /// value is not valid for it
+    fn resolve(&self, field: &ImportedField, value: &ImportedValue) -> Option<(CustomFieldId, custom_field::Value)> {
+        match field {
+            ImportedField::Location => self.location
+                .map(|id| (id, custom_field::Value::Text { value: value.to_string() })),
+            ImportedField::Weather => self.weather
+                .map(|id| (id, custom_field::Value::Text { value: value.to_string() })),
+            ImportedField::Named(name) => {
+                let (id, config) = self.named.get(name)?;
+
+                config.validate(value.to_value(config)?)
+                    .ok()
+                    .map(|value| (*id, value))
+            }
+        }
+    }
+}
+
+async fn ensure_text_field(conn: &impl GenericClient, journal: &Journal, name: &str) -> Result<Option<CustomFieldId>, error::Error> {
+    let found = custom_field::Type::retrieve_text_field(conn, &journal.id, name)
+        .await
+        .context("failed to retrieve custom field")?;
+
+    if found.is_some() {
+        return Ok(found);
+    }
+
+    let options = CustomFieldOptions::new(journal.id, name, custom_field::Type::Text {
+        minimum: None,
+        maximum: None,
+    });
+
+    match CustomField::create_field(conn, options).await {
+        Ok(field) => Ok(Some(field.id)),
+        // the journal has a field with the same name of a different type
+        Err(CreateCustomFieldError::NameExists) => Ok(None),
+        Err(err) => Err(error::Error::context_source(
+            "failed to create custom field",
+            err
+        )),
+    }
+}
+
+/// the custom fields that an import can fill in
+#[derive(Debug, Clone)]
+pub enum ImportedField {
+    Location,
+    Weather,
+
+    /// an existing field of the journal with the given name
+    Named(String),
+}
+
+impl ImportedField {
+    fn name<'a>(&'a self, names: &'a FieldNames) -> &'a str {
+        match self {
+            ImportedField::Location => &names.location,
+            ImportedField::Weather => &names.weather,
+            ImportedField::Named(name) => name,
+        }
+    }
+}
+
+/// a custom field value read from an import archive
+#[derive(Debug, Clone)]
+pub enum ImportedValue {
+    Text(String),
+    Integer(i64),
+    Float(f64),
+    Boolean(bool),
+
+    /// a value exported by this server
+    Value(custom_field::Value),
+}
+
+impl ImportedValue {
+    /// converts the value to the given field type if possible
+    fn to_value(&self, config: &custom_field::Type) -> Option<custom_field::Value> {
+        match (config, self) {
+            (_, ImportedValue::Value(value)) => Some(value.clone()),
+            (custom_field::Type::Integer { .. }, ImportedValue::Integer(value)) => Some(custom_field::Value::Integer {
+                value: i32::try_from(*value).ok()?,
+            }),
+            (custom_field::Type::Float { .. }, ImportedValue::Integer(value)) => Some(custom_field::Value::Float {
+                value: *value as f32,
+            }),
+            (custom_field::Type::Float { .. }, ImportedValue::Float(value)) => Some(custom_field::Value::Float {
+                value: *value as f32,
+            }),
+            (custom_field::Type::Boolean { .. }, ImportedValue::Boolean(value)) => Some(custom_field::Value::Boolean {
+                value: *value,
+            }),
+            (custom_field::Type::Select { .. }, ImportedValue::Text(value)) => Some(custom_field::Value::Select {
+                value: value.clone(),
+            }),
+            (custom_field::Type::Text { .. }, value) => Some(custom_field::Value::Text {
+                value: value.to_string(),
+            }),
+            _ => None
+        }
+    }
+}
+
+impl Display for ImportedValue {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match self {
+            ImportedValue::Text(value) => f.write_str(value),
+            ImportedValue::Integer(value) => write!(f, "{value}"),
+            ImportedValue::Float(value) => write!(f, "{value}"),
+            ImportedValue::Boolean(value) => write!(f, "{value}"),
+            ImportedValue::Value(custom_field::Value::Text { value }) |
+            ImportedValue::Value(custom_field::Value::Select { value }) => f.write_str(value),
+            ImportedValue::Value(value) => match serde_json::to_string(value) {
+                Ok(json) => f.write_str(&json),
+                Err(_) => Err(std::fmt::Error),
+            },
+        }
+    }
+}
+
+/// a file attached to an imported entry
+#[derive(Debug)]
+pub struct ImportedFile {
+    pub name: Option<String>,
+    pub mime: mime::Mime,
+    pub contents: Vec<u8>,
+
+    /// the details of an image. filled in before the file is written
+    pub image: Option<ImageDetails>,
+}
+
+/// an entry read from an import archive
+#[derive(Debug)]
+pub struct ImportedEntry {
+    pub date: NaiveDate,
+    pub title: Option<String>,
+    pub contents: Option<String>,
+    pub created: DateTime<Utc>,
+    pub updated: Option<DateTime<Utc>>,
+    pub tags: Vec<(String, Option<String>)>,
+
+    /// the text of each task and if it is done
+    pub tasks: Vec<(String, bool)>,
+    pub fields: Vec<(ImportedField, ImportedValue)>,
+    pub files: Vec<ImportedFile>,
+}
+
+/// the items sent from an archive reader
+#[derive(Debug)]
+pub enum ImportItem {
+    /// the number of entries in the archive. sent before any entries
+    Total(usize),
+    Entry(ImportedEntry),
+
+    /// an entry of the archive that could not be imported along with the
+    /// reason
+    Skipped(String),
+
+    /// a problem with an entry that was still imported. sent before the
+    /// entry
+    Warning(String),
+}
+
+/// an entry that would be created by an import
+#[derive(Debug, Serialize)]
+pub struct PreviewEntry {
+    pub date: NaiveDate,
+    pub title: Option<String>,
+    pub tags: Vec<String>,
+
+    /// the names of the custom fields that have a value
+    pub fields: Vec<String>,
+
+    /// the number of files attached to the entry
+    pub files: usize,
+}
+
+/// what an import would create without changing the journal
+#[derive(Debug, Serialize)]
+pub struct ImportPreview {
+    pub entries: Vec<PreviewEntry>,
+
+    /// the number of entries in the archive that could not be imported
+    pub skipped: usize,
+
+    /// entries that were skipped and files that could not be read from the
+    /// archive
+    pub warnings: Vec<String>,
+}
+
+/// reads the archive at the given path and reports the entries that would
+/// be created
+pub async fn preview(
+    source: ImportSource,
+    path: PathBuf,
+    names: &FieldNames,
+    policy: &UploadPolicy,
+) -> Result<ImportPreview, error::Error> {
+    let max_file_size = file_size_limit(policy);
+
+    let (sender, mut receiver) = mpsc::channel(IMPORT_QUEUE);
+    let reader = tokio::task::spawn_blocking(move || source.read(path, max_file_size, sender));
+
+    let mut preview = ImportPreview {
+        entries: Vec::new(),
+        skipped: 0,
+        warnings: Vec::new(),
+    };
+
+    while let Some(item) = receiver.recv().await {
+        match item {
+            ImportItem::Total(total) => {
+                preview.entries.reserve(total);
+            }
+            ImportItem::Entry(mut entry) => {
+                for warning in check_files(policy, &mut entry) {
+                    if preview.warnings.len() < MAX_WARNINGS {
+                        preview.warnings.push(warning);
+                    }
+                }
+
+                preview.entries.push(PreviewEntry {
+                    date: entry.date,
+                    title: entry.title,
+                    tags: entry.tags.into_iter()
+                        .filter_map(|(key, _)| tag::normalize_key(&key))
+                        .collect(),
+                    fields: entry.fields.iter()
+                        .map(|(field, _)| field.name(names).to_owned())
+                        .collect(),
+                    files: entry.files.len(),
+                });
+            }
+            ImportItem::Skipped(reason) => {
+                preview.skipped += 1;
+
+                if preview.warnings.len() < MAX_WARNINGS {
+                    preview.warnings.push(format!("skipped {reason}"));
+                }
+            }
+            ImportItem::Warning(warning) => {
+                if preview.warnings.len() < MAX_WARNINGS {
+                    preview.warnings.push(warning);
+                }
+            }
+        }
+    }
+
+    reader.await
+        .context("failed to join archive reader")??;
+
+    Ok(preview)
+}
+
+/// splits a leading markdown heading from the text to use as the title
+pub fn split_title(text: String) -> (Option<String>, Option<String>) {
+    let Some(first) = text.lines().next() else {
+        return (None, None);
+    };
+
+    let Some(heading) = first.strip_prefix("# ") else {
+        return (None, Some(text).filter(|text| !text.is_empty()));
+    };
+
+    let title = Some(heading.trim().to_owned())
+        .filter(|title| !title.is_empty());
+    let contents = text[first.len()..].trim().to_owned();
+
+    (title, Some(contents).filter(|contents| !contents.is_empty()))
+}
+
+/// removes the files of an entry that the upload policy does not allow and
+/// returns a warning for each of them. the same checks are made on uploaded
+/// files
+fn check_files(policy: &UploadPolicy, entry: &mut ImportedEntry) -> Vec<String> {
+    let mut warnings = Vec::new();
+    let mut kept = Vec::with_capacity(entry.files.len());
+
+    for file in std::mem::take(&mut entry.files) {
+        let name = file.name.as_deref().unwrap_or("unnamed");
+
+        if let Err(err) = policy.check_file(&file.mime, file.contents.len() as u64) {
+            warnings.push(format!("entry {}: skipped file \"{name}\": {err}", entry.date));
+
+            continue;
+        }
+
+        if !policy.allows_files(kept.len() + 1) {
+            warnings.push(format!(
+                "entry {}: skipped file \"{name}\": entries can only have {} files",
+                entry.date,
+                policy.max_files_per_entry.unwrap_or_default()
+            ));
+
+            continue;
+        }
+
+        kept.push(file);
+    }
+
+    entry.files = kept;
+
+    warnings
+}
+
+/// checks the files of an entry before the entry is inserted and returns a
+/// warning for each file that is skipped
+///
+/// the files are checked against the upload policy, images have their
+/// metadata read and stripped if required, and then the files are given to
+/// the scanner. this is done before the transaction of the entry is created
+/// so that it is not held while waiting on the scanner
+async fn prepare_files(
+    conn: &impl GenericClient,
+    options: &FileOptions<'_>,
+    entry: &mut ImportedEntry,
+) -> Result<Vec<String>, error::Error> {
+    let mut warnings = check_files(options.policy, entry);
+    let mut kept = Vec::with_capacity(entry.files.len());
+
+    for mut file in std::mem::take(&mut entry.files) {
+        let mut contents = Bytes::from(std::mem::take(&mut file.contents));
+
+        if image_meta::is_supported(&file.mime) {
+            let strip_metadata = options.policy.strip_metadata;
+            let subtype = file.mime.subtype().as_str().to_owned();
+
+            let result = tokio::task::spawn_blocking(move || image_meta::process(
+                contents,
+                &subtype,
+                strip_metadata
+            ))
+                .await
+                .context("failed to join image processing")?;
+
+            match result {
+                Ok(processed) => {
+                    contents = processed.contents;
+                    file.image = Some(processed.details);
+                }
+                Err(err) => {
+                    warnings.push(format!(
+                        "entry {}: skipped file \"{}\": {}",
+                        entry.date,
+                        file.name.as_deref().unwrap_or("unnamed"),
+                        error::describe(&err)
+                    ));
+
+                    continue;
+                }
+            }
+        }
+
+        if let Some(scan) = options.scan {
+            let hash = blake3::hash(&contents).to_hex();
+
+            let verdict = scan::scan_bytes_cached(conn, scan, &hash, contents.clone())
+                .await
+                .context("failed to scan imported file")?;
+
+            if let Verdict::Infected(signature) = verdict {
+                warnings.push(format!(
+                    "entry {}: skipped file \"{}\": file is infected with {signature}",
+                    entry.date,
+                    file.name.as_deref().unwrap_or("unnamed")
+                ));
+
+                continue;
+            }
+        }
+
+        file.contents = Vec::from(contents);
+
+        kept.push(file);
+    }
+
+    entry.files = kept;
+
+    Ok(warnings)
+}
+
+/// sends an item to be imported. returns false if the import has stopped
+pub fn send(sender: &mpsc::Sender<ImportItem>, item: ImportItem) -> bool {
+    sender.blocking_send(item).is_ok()
+}
+
+/// inserts an entry and its files into the journal
+///
+/// returns the number of files added
+async fn insert_entry(
+    conn: &mut db::Object,
+    journal: &Journal,
+    users_id: &UserId,
+    fields: &ImportFields,
+    files: &FileOptions<'_>,
+    entry: ImportedEntry,
+) -> Result<i32, error::Error> {
+    let transaction = conn.transaction()
+        .await
+        .context("failed to create transaction")?;
+
+    if journal.settings.multi_entry == MultiEntry::Single {
+        let exists = Journal::date_in_use(&transaction, &journal.id, &entry.date, None)
+            .await
+            .context("failed to check for existing entry date")?;
+
+        if exists {
+            return Err(error::Error::context(format!(
+                "journal already has an entry for {}", entry.date
+            )));
+        }
+    }
+
+    let uid = EntryUid::gen();
+    let planned = is_planned_date(&entry.date);
+    let number = Journal::next_entry_number(&transaction, &journal.id)
+        .await
+        .context("failed to retrieve next entry number")?;
+
+    let entries_id: EntryId = transaction.query_one(
+        "\
+        insert into entries (uid, journals_id, users_id, number, entry_date, planned, title, contents, word_count, created, updated) \
+        values ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11) \
+        returning id",
+        &[
+            &uid,
+            &journal.id,
+            users_id,
+            &number,
+            &entry.date,
+            &planned,
+            &entry.title,
+            &entry.contents,
+            &entry_word_count(entry.title.as_deref(), entry.contents.as_deref()),
+            &entry.created,
+            &entry.updated
+        ]
+    )
+        .await
+        .context("failed to insert imported entry")?
+        .get(0);
+
+    mention::sync_body(&transaction, &journal.id, &entries_id, entry.contents.as_deref())
+        .await
+        .context("failed to sync imported entry mentions")?;
+
+    for (key, value) in &entry.tags {
+        // tags that are not valid for this server are dropped
+        let Some(key) = tag::normalize_key(key) else {
+            continue;
+        };
+
+        transaction.execute(
+            "\
+            insert into entry_tags (entries_id, key, value, created) values \
+            ($1, $2, $3, $4) \
+            on conflict (entries_id, key) do nothing",
+            &[&entries_id, &key, value, &entry.created]
+        )
+            .await
+            .context("failed to insert imported entry tag")?;
+    }
+
+    for (field, value) in &entry.fields {
+        let Some((custom_fields_id, value)) = fields.resolve(field, value) else {
+            continue;
+        };
+
+        custom_field::Entry::set(&transaction, &custom_fields_id, &entries_id, &value)
+            .await
+            .context("failed to insert imported custom field")?;
+    }
+
+    if !entry.tasks.is_empty() {
+        let tasks = entry.tasks.into_iter()
+            .map(|(text, done)| TaskInput {
+                id: None,
+                text,
+                done,
+            })
+            .collect();
+
+        EntryTask::upsert_entry(&transaction, &entries_id, tasks, &entry.created)
+            .await
+            .context("failed to insert imported entry tasks")?;
+    }
+
+    let mut written = WrittenFiles::default();
+
+    let result = insert_files(&transaction, files, &entries_id, entry.files, &mut written).await;
+
+    let result = match result {
+        Ok(count) => transaction.commit()
+            .await
+            .context("failed to commit imported entry")
+            .map(|_| count),
+        Err(err) => Err(err),
+    };
+
+    written.cleanup(result).await
+}
+
+/// the files written for an entry that is being imported
+#[derive(Debug, Default)]
+struct WrittenFiles {
+    paths: Vec<PathBuf>,
+}
+
+impl WrittenFiles {
+    /// writes the contents of a file. the path is kept before writing so
+    /// that a file that was only partially written is also removed
+    async fn write(&mut self, path: PathBuf, contents: Vec<u8>) -> Result<(), std::io::Error> {
+        self.paths.push(path);
+
+        tokio::fs::write(&self.paths[self.paths.len() - 1], contents).await
+    }
+
+    /// removes the written files if the entry was not imported
+    async fn cleanup<T>(self, result: Result<T, error::Error>) -> Result<T, error::Error> {
+        if result.is_err() {
+            for path in self.paths {
+                match tokio::fs::remove_file(&path).await {
+                    Ok(()) => {}
+                    Err(err) if err.kind() == std::io::ErrorKind::NotFound => {}
+                    Err(err) => error::log_prefix_error("failed to remove imported file", &err),
+                }
+            }
+        }
+
+        result
+    }
+}
+
+/// inserts the files of an entry and writes their contents
+///
+/// the import fails if writing a file would drop the free space of the
+/// storage directory below the reserve
+async fn insert_files(
+    conn: &impl GenericClient,
+    options: &FileOptions<'_>,
+    entries_id: &EntryId,
+    files: Vec<ImportedFile>,
+    written: &mut WrittenFiles,
+) -> Result<i32, error::Error> {
+    let mut count = 0;
+
+    for file in files {
+        let uid = FileEntryUid::gen();
+        let created = Utc::now();
+        let size = file.contents.len() as i64;
+        let hash = blake3::hash(&file.contents).to_hex().to_string();
+        let mime_type = file.mime.type_().as_str().to_owned();
+        let mime_subtype = file.mime.subtype().as_str().to_owned();
+        let encrypted = options.key.is_some();
+
+        let file_entries_id: FileEntryId = conn.query_one(
+            "\
+            insert into file_entries ( \
+                uid, \
+                entries_id, \
+                name, \
+                mime_type, \
+                mime_subtype, \
+                size, \
+                hash, \
+                encrypted, \
+                created \
+            ) values ($1, $2, $3, $4, $5, $6, $7, $8, $9) \
+            returning id",
+            &[&uid, entries_id, &file.name, &mime_type, &mime_subtype, &size, &hash, &encrypted, &created]
+        )
+            .await
+            .context("failed to insert imported file entry")?
+            .get(0);
+
+        if let Some(details) = &file.image {
+            image_meta::store_upload(conn, &file_entries_id, Some(details))
+                .await
+                .context("failed to insert imported image details")?;
+        }
+
+        let contents = match options.key {
+            Some(key) => key.encrypt_all(&file.contents)
+                .context("failed to encrypt imported file")?,
+            None => file.contents,
+        };
+
+        if let Some(details) = options.storage.check_space(contents.len() as u64).await? {
+            return Err(error::Error::context(format!(
+                "not enough storage space to import files. {} bytes are available and {} bytes are needed with a reserve of {} bytes",
+                details.available,
+                details.needed,
+                details.reserve,
+            )));
+        }
+
+        let path = options.journal_dir.file_path(&file_entries_id);
+
+        written.write(path, contents)
+            .await
+            .context("failed to write imported file")?;
+
+        count += 1;
+    }
+
+    Ok(count)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn block_on<F>(future: F) -> F::Output
+    where
+        F: std::future::Future
+    {
+        tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap()
+            .block_on(future)
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("tj2_import_{name}_{}", std::process::id()));
+
+        std::fs::create_dir_all(&dir).unwrap();
+
+        dir
+    }
+
+    #[test]
+    fn failed_entry_removes_written_files() {
+        let dir = temp_dir("failed");
+        let first = dir.join("1");
+        let second = dir.join("2");
+
+        let result: Result<i32, error::Error> = block_on(async {
+            let mut written = WrittenFiles::default();
+
+            written.write(first.clone(), b"first".to_vec()).await.unwrap();
+            written.write(second.clone(), b"second".to_vec()).await.unwrap();
+
+            // a file that failed before it was created is also kept
+            written.paths.push(dir.join("3"));
+
+            written.cleanup(Err(error::Error::context("failed to commit imported entry"))).await
+        });
+
+        let exists = (first.exists(), second.exists());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert!(result.is_err());
+        assert_eq!(exists, (false, false));
+    }
+
+    #[test]
+    fn imported_entry_keeps_written_files() {
+        let dir = temp_dir("imported");
+        let path = dir.join("1");
+
+        let result = block_on(async {
+            let mut written = WrittenFiles::default();
+
+            written.write(path.clone(), b"kept".to_vec()).await.unwrap();
+
+            written.cleanup(Ok(1)).await
+        });
+
+        let contents = std::fs::read(&path);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(result.unwrap(), 1);
+        assert_eq!(contents.unwrap(), b"kept");
+    }
+
+    #[test]
+    fn imported_fields_resolve() {
+        let mood = CustomFieldId::new(2).unwrap();
+        let fields = ImportFields {
+            location: CustomFieldId::new(1).ok(),
+            weather: None,
+            named: HashMap::from([(String::from("mood"), (mood, custom_field::Type::Integer {
+                minimum: Some(1),
+                maximum: Some(10),
+                unit: None,
+            }))]),
+        };
+
+        assert!(matches!(
+            fields.resolve(&ImportedField::Location, &ImportedValue::Text(String::from("home"))),
+            Some((_, custom_field::Value::Text { value })) if value == "home"
+        ));
+        assert!(fields.resolve(&ImportedField::Weather, &ImportedValue::Text(String::from("sunny"))).is_none());
+        assert_eq!(
+            fields.resolve(&ImportedField::Named(String::from("mood")), &ImportedValue::Integer(7)),
+            Some((mood, custom_field::Value::Integer { value: 7 }))
+        );
+        // values outside of the field config and unknown fields are dropped
+        assert!(fields.resolve(&ImportedField::Named(String::from("mood")), &ImportedValue::Integer(11)).is_none());
+        assert!(fields.resolve(&ImportedField::Named(String::from("energy")), &ImportedValue::Integer(7)).is_none());
+    }
+
+    fn imported_file(name: &str, mime: mime::Mime, size: usize) -> ImportedFile {
+        ImportedFile {
+            name: Some(name.to_owned()),
+            mime,
+            contents: vec![0; size],
+            image: None,
+        }
+    }
+
+    #[test]
+    fn policy_skips_files() {
+        let policy = UploadPolicy {
+            max_file_size: Some(8),
+            allowed_mime_types: vec![String::from("image/*"), String::from("text/plain")],
+            max_files_per_entry: Some(2),
+            strip_metadata: false,
+        };
+        let mut entry = ImportedEntry {
+            date: NaiveDate::from_ymd_opt(2024, 1, 2).unwrap(),
+            title: None,
+            contents: None,
+            created: Utc::now(),
+            updated: None,
+            tags: Vec::new(),
+            tasks: Vec::new(),
+            fields: Vec::new(),
+            files: vec![
+                imported_file("run.exe", mime::APPLICATION_OCTET_STREAM, 4),
+                imported_file("large.png", mime::IMAGE_PNG, 16),
+                imported_file("first.txt", mime::TEXT_PLAIN, 4),
+                imported_file("second.jpeg", mime::IMAGE_JPEG, 8),
+                imported_file("third.txt", mime::TEXT_PLAIN, 4),
+            ],
+        };
+
+        let warnings = check_files(&policy, &mut entry);
+
+        let kept: Vec<&str> = entry.files.iter()
+            .filter_map(|file| file.name.as_deref())
+            .collect();
+
+        assert_eq!(kept, ["first.txt", "second.jpeg"]);
+        assert_eq!(warnings.len(), 3);
+        assert!(warnings[0].contains("\"run.exe\"") && warnings[0].contains("application/octet-stream"));
+        assert!(warnings[1].contains("\"large.png\"") && warnings[1].contains("max file size of 8 bytes"));
+        assert!(warnings[2].contains("\"third.txt\"") && warnings[2].contains("only have 2 files"));
+    }
+
+    #[test]
+    fn read_file_within_limit() {
+        let contents = read_file(&b"contents"[..], 8).unwrap();
+
+        assert_eq!(contents, b"contents");
+    }
+
+    #[test]
+    fn read_file_over_limit() {
+        let result = read_file(&b"contents"[..], 7);
+
+        assert!(matches!(result, Err(ReadFileError::TooLarge(7))));
+    }
+
+    #[test]
+    fn file_size_limit_is_capped() {
+        let mut policy = UploadPolicy {
+            max_file_size: None,
+            allowed_mime_types: Vec::new(),
+            max_files_per_entry: None,
+            strip_metadata: false,
+        };
+
+        assert_eq!(file_size_limit(&policy), MAX_ARCHIVE_FILE_SIZE);
+
+        policy.max_file_size = Some(1024);
+
+        assert_eq!(file_size_limit(&policy), 1024);
+
+        policy.max_file_size = Some(u64::MAX);
+
+        assert_eq!(file_size_limit(&policy), MAX_ARCHIVE_FILE_SIZE);
+    }
+}
@@ -0,0 +1,433 @@
+//! reading a json export of this server
+//!
+//! the "journal.json" of the archive describes the journal and its custom
+//! fields. every file in "entries/" is an entry along with its tags, tasks,
+//! and custom field values. the files of an entry are read from the paths
+//! listed in the entry. end-to-end encrypted entries can only be read by a
+//! client and are skipped. a file that is missing from the archive, larger
+//! than the max file size, or not the size listed in its entry is left out
+//! of its entry and reported as a warning
+//!
+//! archives from [`MIN_ARCHIVE_VERSION`] up to the current
+//! [`ARCHIVE_VERSION`] can be read. the json of an older archive is
+//! converted to the current layout one version at a time before it is
+//! parsed
+
+use std::fs::File;
+use std::path::PathBuf;
+
+use tokio::sync::mpsc;
+use zip::ZipArchive;
+
+use crate::error::{self, Context};
+use crate::journal::export::{
+    ArchiveCustomField,
+    ArchiveEntry,
+    ArchiveHeader,
+    ExportFormat,
+    ARCHIVE_VERSION,
+};
+
+use super::{read_file, send, ImportItem, ImportedEntry, ImportedField, ImportedFile, ImportedValue, ReadFileError};
+
+fn open(path: &PathBuf) -> Result<ZipArchive<File>, error::Error> {
+    let file = File::open(path)
+        .context("failed to open import archive")?;
+
+    ZipArchive::new(file)
+        .context("failed to open import zip")
+}
+
+/// the oldest archive version that can be imported
+const MIN_ARCHIVE_VERSION: u32 = 1;
+
+/// the json documents of an archive
+#[derive(Debug, Clone, Copy)]
+enum Document {
+    Journal,
+    CustomFields,
+    Entry,
+}
+
+/// converts a document of one version to the next version
+type Upgrade = fn(Document, &mut serde_json::Value) -> Result<(), error::Error>;
+
+/// the conversions from each version to the next starting at
+/// [`MIN_ARCHIVE_VERSION`]. when [`ARCHIVE_VERSION`] is incremented a
+/// conversion from the previous version is added to the end
+const UPGRADES: &[Upgrade] = &[];
+
+/// converts a document from the given version to the current version
+fn upgrade(version: u32, document: Document, value: &mut serde_json::Value) -> Result<(), error::Error> {
+    let start = version.checked_sub(MIN_ARCHIVE_VERSION)
+        .and_then(|index| UPGRADES.get(index as usize..))
+        .context(format!("archive version {version} is not supported"))?;
+
+    for step in start {
+        step(document, value)?;
+    }
+
+    Ok(())
+}
+
+fn read_value(archive: &mut ZipArchive<File>, name: &str) -> Result<serde_json::Value, error::Error> {
+    let file = archive.by_name(name)
+        .context(format!("archive is missing \"{name}\""))?;
+
+    serde_json::from_reader(file)
+        .context(format!("failed to parse \"{name}\""))
+}
+
+/// reads a document from the archive and converts it from the version of
+/// the archive
+fn read_json<T>(
+    archive: &mut ZipArchive<File>,
+    name: &str,
+    version: u32,
+    document: Document,
+) -> Result<T, error::Error>
+where
+    T: serde::de::DeserializeOwned
+{
+    let mut value = read_value(archive, name)?;
+
+    upgrade(version, document, &mut value)?;
+
+    serde_json::from_value(value)
+        .context(format!("failed to parse \"{name}\""))
+}
+
+fn read_header(archive: &mut ZipArchive<File>) -> Result<ArchiveHeader, error::Error> {
+    let mut value = read_value(archive, "journal.json")?;
+
+    let version = value.get("version")
+        .and_then(serde_json::Value::as_u64)
+        .and_then(|version| u32::try_from(version).ok())
+        .context("\"journal.json\" does not have a valid version")?;
+
+    if version > ARCHIVE_VERSION {
+        return Err(error::Error::context(format!(
+            "archive version {version} is newer than the supported version {ARCHIVE_VERSION}"
+        )));
+    }
+
+    if version < MIN_ARCHIVE_VERSION {
+        return Err(error::Error::context(format!(
+            "archive version {version} is older than the oldest supported version {MIN_ARCHIVE_VERSION}"
+        )));
+    }
+
+    upgrade(version, Document::Journal, &mut value)?;
+
+    let header: ArchiveHeader = serde_json::from_value(value)
+        .context("failed to parse \"journal.json\"")?;
+
+    if header.format != ExportFormat::Json {
+        return Err(error::Error::context(format!(
+            "archive is a {} export. only json exports can be imported",
+            header.format
+        )));
+    }
+
+    Ok(header)
+}
+
+/// reads the journal details and custom fields of an archive
+pub fn read_details(path: PathBuf) -> Result<(ArchiveHeader, Vec<ArchiveCustomField>), error::Error> {
+    let mut archive = open(&path)?;
+
+    let header = read_header(&mut archive)?;
+    let fields = read_json(&mut archive, "custom_fields.json", header.version, Document::CustomFields)?;
+
+    Ok((header, fields))
+}
+
+/// converts an archived entry
+///
+/// files that cannot be read from the archive are left out of the entry
+/// and reported in the returned warnings. the reason is returned if the
+/// entry cannot be imported
+fn convert(
+    archive: &mut ZipArchive<File>,
+    name: &str,
+    entry: ArchiveEntry,
+    max_file_size: u64,
+) -> Result<(ImportedEntry, Vec<String>), String> {
+    if entry.ciphertext.is_some() {
+        return Err(format!("{name}: end-to-end encrypted entries can only be imported by a client"));
+    }
+
+    let mut files = Vec::with_capacity(entry.files.len());
+    let mut warnings = Vec::new();
+
+    for file in entry.files {
+        // the size listed in the entry is only checked after reading since
+        // the author of the archive controls it
+        let read = match archive.by_name(&file.path) {
+            Ok(found) => read_file(found, max_file_size).map_err(|err| match err {
+                ReadFileError::TooLarge(limit) => format!(
+                    "{name}: file \"{}\" is larger than the max file size of {limit} bytes", file.path
+                ),
+                ReadFileError::Io(err) => format!("{name}: failed to read file \"{}\": {err}", file.path),
+            }),
+            Err(_) => Err(format!("{name}: file \"{}\" is missing from the archive", file.path)),
+        };
+
+        let contents = match read {
+            Ok(contents) if i64::try_from(contents.len()).ok() == Some(file.size) => contents,
+            Ok(contents) => {
+                warnings.push(format!(
+                    "{name}: file \"{}\" is {} bytes but the entry lists {} bytes",
+                    file.path,
+                    contents.len(),
+                    file.size
+                ));
+
+                continue;
+            }
+            Err(warning) => {
+                warnings.push(warning);
+
+                continue;
+            }
+        };
+
+        files.push(ImportedFile {
+            name: file.name,
+            mime: file.mime.parse().unwrap_or(mime::APPLICATION_OCTET_STREAM),
+            contents,
+            image: None,
+        });
+    }
+
+    Ok((ImportedEntry {
+        date: entry.date,
+        title: entry.title,
+        contents: entry.contents,
+        created: entry.created,
+        updated: entry.updated,
+        tags: entry.tags.into_iter()
+            .map(|tag| (tag.key, tag.value))
+            .collect(),
+        tasks: entry.tasks.into_iter()
+            .map(|task| (task.text, task.done))
+            .collect(),
+        fields: entry.custom_fields.into_iter()
+            .map(|field| (ImportedField::Named(field.name), ImportedValue::Value(field.value)))
+            .collect(),
+        files,
+    }, warnings))
+}
+
+/// reads a json export and sends its entries. files larger than the given
+/// number of bytes are left out
+pub fn read(path: PathBuf, max_file_size: u64, sender: mpsc::Sender<ImportItem>) -> Result<(), error::Error> {
+    let mut archive = open(&path)?;
+
+    let header = read_header(&mut archive)?;
+
+    let mut names: Vec<String> = archive.file_names()
+        .filter(|name| name.starts_with("entries/") && name.ends_with(".json"))
+        .map(str::to_owned)
+        .collect();
+
+    names.sort();
+
+    if !send(&sender, ImportItem::Total(names.len())) {
+        return Ok(());
+    }
+
+    for name in names {
+        let item = match read_json::<ArchiveEntry>(&mut archive, &name, header.version, Document::Entry) {
+            Ok(entry) => match convert(&mut archive, &name, entry, max_file_size) {
+                Ok((entry, warnings)) => {
+                    for warning in warnings {
+                        if !send(&sender, ImportItem::Warning(warning)) {
+                            return Ok(());
+                        }
+                    }
+
+                    ImportItem::Entry(entry)
+                }
+                Err(reason) => ImportItem::Skipped(reason),
+            },
+            Err(err) => ImportItem::Skipped(error::describe(&err)),
+        };
+
+        if !send(&sender, item) {
+            break;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::journal::custom_field;
+
+    /// returns the path of a fixture archive
+    fn fixture(name: &str) -> PathBuf {
+        PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/fixtures")
+            .join(name)
+    }
+
+    fn read_items(path: PathBuf) -> Vec<ImportItem> {
+        let (sender, mut receiver) = mpsc::channel(16);
+
+        read(path, 1024, sender).unwrap();
+
+        let mut rtn = Vec::new();
+
+        while let Ok(item) = receiver.try_recv() {
+            rtn.push(item);
+        }
+
+        rtn
+    }
+
+    #[test]
+    fn upgrades_cover_versions() {
+        assert_eq!(UPGRADES.len() as u32, ARCHIVE_VERSION - MIN_ARCHIVE_VERSION);
+    }
+
+    #[test]
+    fn v1_details() {
+        let (header, fields) = read_details(fixture("archive_v1.zip")).unwrap();
+
+        assert_eq!(header.version, 1);
+        assert_eq!(header.name, "Fixture");
+        assert_eq!(header.description.as_deref(), Some("a version 1 export"));
+        assert_eq!(fields.len(), 1);
+        assert_eq!(fields[0].name, "mood");
+        assert!(matches!(fields[0].config, custom_field::Type::Integer { minimum: Some(1), maximum: Some(10), .. }));
+    }
+
+    #[test]
+    fn v1_entries() {
+        let items = read_items(fixture("archive_v1.zip"));
+
+        assert!(matches!(items[0], ImportItem::Total(2)));
+
+        let entries: Vec<&ImportedEntry> = items.iter()
+            .filter_map(|item| match item {
+                ImportItem::Entry(entry) => Some(entry),
+                _ => None,
+            })
+            .collect();
+
+        assert_eq!(entries.len(), 2);
+
+        let first = entries[0];
+
+        assert_eq!(first.date.to_string(), "2024-01-02");
+        assert_eq!(first.title.as_deref(), Some("first"));
+        assert_eq!(first.contents.as_deref(), Some("hello from version 1"));
+        assert_eq!(first.tags, vec![(String::from("place"), Some(String::from("home")))]);
+        assert_eq!(first.tasks, vec![(String::from("write fixture"), true)]);
+        assert!(matches!(
+            first.fields.as_slice(),
+            [(ImportedField::Named(name), ImportedValue::Value(custom_field::Value::Integer { value: 7 }))] if name == "mood"
+        ));
+        assert_eq!(first.files.len(), 1);
+        assert_eq!(first.files[0].name.as_deref(), Some("note.txt"));
+        assert_eq!(first.files[0].mime, mime::TEXT_PLAIN);
+        assert_eq!(first.files[0].contents, b"hello fixture\n");
+
+        let second = entries[1];
+
+        assert_eq!(second.date.to_string(), "2024-01-03");
+        assert!(second.title.is_none());
+        assert!(second.tasks.is_empty());
+        assert!(second.files.is_empty());
+    }
+
+    #[test]
+    fn missing_file_is_reported() {
+        use std::io::Write;
+
+        let path = std::env::temp_dir().join(format!("tj2_archive_missing_{}.zip", std::process::id()));
+        let mut writer = zip::ZipWriter::new(File::create(&path).unwrap());
+        let options = zip::write::SimpleFileOptions::default();
+
+        writer.start_file("journal.json", options).unwrap();
+        writer.write_all(br#"{"version":1,"name":"Missing","description":null,"format":"json"}"#).unwrap();
+        writer.start_file("entries/2024-01-02_1.json", options).unwrap();
+        writer.write_all(br#"{
+            "uid":"missingEntry0001","number":1,"date":"2024-01-02","title":"kept",
+            "created":"2024-01-02T09:00:00Z","updated":null,"planned":false,
+            "tags":[],"custom_fields":[],
+            "files":[{"name":"gone.txt","mime":"text/plain","size":4,"path":"files/2024-01-02/gone.txt"}]
+        }"#).unwrap();
+        writer.start_file("entries/2024-01-03_2.json", options).unwrap();
+        writer.write_all(b"{ not json").unwrap();
+        writer.finish().unwrap();
+
+        let items = read_items(path.clone());
+
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(matches!(
+            items.as_slice(),
+            [
+                ImportItem::Total(2),
+                ImportItem::Warning(warning),
+                ImportItem::Entry(entry),
+                ImportItem::Skipped(reason),
+            ] if warning.contains("files/2024-01-02/gone.txt") &&
+                entry.title.as_deref() == Some("kept") &&
+                entry.files.is_empty() &&
+                reason.contains("entries/2024-01-03_2.json")
+        ));
+    }
+
+    #[test]
+    fn file_sizes_are_checked() {
+        use std::io::Write;
+
+        let path = std::env::temp_dir().join(format!("tj2_archive_sizes_{}.zip", std::process::id()));
+        let mut writer = zip::ZipWriter::new(File::create(&path).unwrap());
+        let options = zip::write::SimpleFileOptions::default();
+
+        writer.start_file("journal.json", options).unwrap();
+        writer.write_all(br#"{"version":1,"name":"Sizes","description":null,"format":"json"}"#).unwrap();
+        writer.start_file("entries/2024-01-02_1.json", options).unwrap();
+        writer.write_all(br#"{
+            "uid":"sizesEntry000001","number":1,"date":"2024-01-02","title":"sizes",
+            "created":"2024-01-02T09:00:00Z","updated":null,"planned":false,
+            "tags":[],"custom_fields":[],
+            "files":[
+                {"name":"wrong.txt","mime":"text/plain","size":4,"path":"files/wrong.txt"},
+                {"name":"large.txt","mime":"text/plain","size":2048,"path":"files/large.txt"},
+                {"name":"kept.txt","mime":"text/plain","size":4,"path":"files/kept.txt"}
+            ]
+        }"#).unwrap();
+        writer.start_file("files/wrong.txt", options).unwrap();
+        writer.write_all(&[b'a'; 64]).unwrap();
+        writer.start_file("files/large.txt", options).unwrap();
+        writer.write_all(&[b'a'; 2048]).unwrap();
+        writer.start_file("files/kept.txt", options).unwrap();
+        writer.write_all(b"kept").unwrap();
+        writer.finish().unwrap();
+
+        let items = read_items(path.clone());
+
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(matches!(
+            items.as_slice(),
+            [
+                ImportItem::Total(1),
+                ImportItem::Warning(wrong),
+                ImportItem::Warning(large),
+                ImportItem::Entry(entry),
+            ] if wrong.contains("files/wrong.txt") && wrong.contains("lists 4 bytes") &&
+                large.contains("files/large.txt") && large.contains("max file size") &&
+                entry.files.len() == 1 &&
+                entry.files[0].contents == b"kept"
+        ));
+    }
+}
@@ -0,0 +1,422 @@
+//! reading the export format of Day One
+//!
+//! Day One exports a journal as a json document with an "entries" list. a
+//! zip export contains one json document per journal along with "photos",
+//! "videos", "audios", and "pdfs" directories that hold the attachments of
+//! the entries named by their md5 hash
+//!
+//! the location and weather of an entry are stored as text in the location
+//! and weather custom fields. starred entries are given the "starred" tag
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::PathBuf;
+
+use chrono::{DateTime, Utc};
+use chrono_tz::Tz;
+use serde::Deserialize;
+use tokio::sync::mpsc;
+use zip::ZipArchive;
+
+use crate::error::{self, Context};
+
+use super::{read_file, send, split_title, ImportItem, ImportedEntry, ImportedField, ImportedFile, ImportedValue, ReadFileError};
+
+/// the directories of a zip export that hold attachments
+const MEDIA_DIRS: [&str; 4] = ["photos/", "videos/", "audios/", "pdfs/"];
+
+/// the prefix of the links that Day One puts in the text of an entry for
+/// its attachments
+const MOMENT_LINK: &str = "![](dayone-moment:";
+
+#[derive(Debug, Deserialize)]
+struct Export {
+    #[serde(default)]
+    entries: Vec<serde_json::Value>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Entry {
+    creation_date: DateTime<Utc>,
+    modified_date: Option<DateTime<Utc>>,
+    time_zone: Option<String>,
+    text: Option<String>,
+    #[serde(default)]
+    starred: bool,
+    #[serde(default)]
+    tags: Vec<String>,
+    location: Option<Location>,
+    weather: Option<Weather>,
+    #[serde(default)]
+    photos: Vec<Attachment>,
+    #[serde(default)]
+    videos: Vec<Attachment>,
+    #[serde(default)]
+    audios: Vec<Attachment>,
+    #[serde(default)]
+    pdf_attachments: Vec<Attachment>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Location {
+    place_name: Option<String>,
+    locality_name: Option<String>,
+    administrative_area: Option<String>,
+    country: Option<String>,
+    latitude: Option<f64>,
+    longitude: Option<f64>,
+}
+
+impl Location {
+    fn to_text(&self) -> Option<String> {
+        let mut parts: Vec<&str> = Vec::new();
+
+        for part in [&self.place_name, &self.locality_name, &self.administrative_area, &self.country] {
+            let Some(part) = part.as_deref().map(str::trim) else {
+                continue;
+            };
+
+            if !part.is_empty() && !parts.contains(&part) {
+                parts.push(part);
+            }
+        }
+
+        if !parts.is_empty() {
+            return Some(parts.join(", "));
+        }
+
+        match (self.latitude, self.longitude) {
+            (Some(lat), Some(lon)) => Some(format!("{lat},{lon}")),
+            _ => None
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Weather {
+    conditions_description: Option<String>,
+    temperature_celsius: Option<f64>,
+}
+
+impl Weather {
+    fn to_text(&self) -> Option<String> {
+        let conditions = self.conditions_description.as_deref()
+            .map(str::trim)
+            .filter(|value| !value.is_empty());
+        let temperature = self.temperature_celsius
+            .map(|value| format!("{value:.0}°C"));
+
+        match (conditions, temperature) {
+            (Some(conditions), Some(temperature)) => Some(format!("{conditions}, {temperature}")),
+            (Some(conditions), None) => Some(conditions.to_owned()),
+            (None, Some(temperature)) => Some(temperature),
+            (None, None) => None,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct Attachment {
+    md5: Option<String>,
+    filename: Option<String>,
+}
+
+/// removes the attachment links from the text of an entry since the
+/// attachments are added as files
+fn strip_moments(text: &str) -> String {
+    let mut rtn = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(start) = rest.find(MOMENT_LINK) {
+        rtn.push_str(&rest[..start]);
+
+        match rest[start..].find(')') {
+            Some(end) => rest = &rest[start + end + 1..],
+            None => {
+                rest = "";
+            }
+        }
+    }
+
+    rtn.push_str(rest);
+
+    // removing the links can leave behind runs of empty lines
+    while rtn.contains("\n\n\n") {
+        rtn = rtn.replace("\n\n\n", "\n\n");
+    }
+
+    rtn.trim().to_owned()
+}
+
+/// the attachments of a zip export keyed by their md5 hash
+type MediaIndex = HashMap<String, String>;
+
+fn media_index(archive: &ZipArchive<File>) -> MediaIndex {
+    let mut index = HashMap::new();
+
+    for name in archive.file_names() {
+        if !MEDIA_DIRS.iter().any(|dir| name.starts_with(dir)) {
+            continue;
+        }
+
+        let file_name = name.rsplit('/').next().unwrap_or(name);
+        let stem = file_name.split('.').next().unwrap_or(file_name);
+
+        if !stem.is_empty() {
+            index.insert(stem.to_lowercase(), name.to_owned());
+        }
+    }
+
+    index
+}
+
+/// reads the attachments of an entry from the archive
+///
+/// attachments larger than the given number of bytes are left out and
+/// reported in the warnings
+fn read_attachments(
+    archive: &mut ZipArchive<File>,
+    index: &MediaIndex,
+    max_file_size: u64,
+    attachments: Vec<Attachment>,
+    warnings: &mut Vec<String>,
+) -> Result<Vec<ImportedFile>, error::Error> {
+    let mut rtn = Vec::new();
+
+    for attachment in attachments {
+        let Some(name) = attachment.md5.and_then(|md5| index.get(&md5.to_lowercase())) else {
+            continue;
+        };
+
+        let file = archive.by_name(name)
+            .context("failed to find attachment in archive")?;
+
+        let contents = match read_file(file, max_file_size) {
+            Ok(contents) => contents,
+            Err(ReadFileError::TooLarge(limit)) => {
+                warnings.push(format!("attachment \"{name}\" is larger than the max file size of {limit} bytes"));
+
+                continue;
+            }
+            Err(ReadFileError::Io(err)) => return Err(error::Error::context_source(
+                "failed to read attachment from archive",
+                err
+            )),
+        };
+
+        let mime = mime_guess::from_path(name).first_or_octet_stream();
+        let file_name = attachment.filename
+            .or_else(|| name.rsplit('/').next().map(str::to_owned));
+
+        rtn.push(ImportedFile {
+            name: file_name,
+            mime,
+            contents,
+            image: None,
+        });
+    }
+
+    Ok(rtn)
+}
+
+/// converts a Day One entry
+///
+/// attachments that are left out of the entry are reported in the returned
+/// warnings
+fn convert(
+    entry: Entry,
+    archive: Option<&mut ZipArchive<File>>,
+    index: &MediaIndex,
+    max_file_size: u64,
+) -> Result<(ImportedEntry, Vec<String>), error::Error> {
+    let date = match entry.time_zone.as_deref().and_then(|tz| tz.parse::<Tz>().ok()) {
+        Some(tz) => entry.creation_date.with_timezone(&tz).date_naive(),
+        None => entry.creation_date.date_naive(),
+    };
+
+    let (title, contents) = match entry.text {
+        Some(text) => split_title(strip_moments(&text)),
+        None => (None, None),
+    };
+
+    let mut tags: Vec<(String, Option<String>)> = entry.tags.into_iter()
+        .map(|tag| (tag, None))
+        .collect();
+
+    if entry.starred {
+        tags.push((String::from("starred"), None));
+    }
+
+    let mut fields = Vec::new();
+
+    if let Some(location) = entry.location.as_ref().and_then(Location::to_text) {
+        fields.push((ImportedField::Location, ImportedValue::Text(location)));
+    }
+
+    if let Some(weather) = entry.weather.as_ref().and_then(Weather::to_text) {
+        fields.push((ImportedField::Weather, ImportedValue::Text(weather)));
+    }
+
+    let mut warnings = Vec::new();
+
+    let files = match archive {
+        Some(archive) => {
+            let mut files = Vec::new();
+
+            for attachments in [entry.photos, entry.videos, entry.audios, entry.pdf_attachments] {
+                files.extend(read_attachments(archive, index, max_file_size, attachments, &mut warnings)?);
+            }
+
+            files
+        }
+        None => Vec::new(),
+    };
+
+    Ok((ImportedEntry {
+        date,
+        title,
+        contents,
+        created: entry.creation_date,
+        updated: entry.modified_date.filter(|modified| *modified != entry.creation_date),
+        tags,
+        tasks: Vec::new(),
+        fields,
+        files,
+    }, warnings))
+}
+
+/// reads a Day One json document or zip export and sends its entries.
+/// attachments larger than the given number of bytes are left out
+pub fn read(path: PathBuf, max_file_size: u64, sender: mpsc::Sender<ImportItem>) -> Result<(), error::Error> {
+    let mut file = File::open(&path)
+        .context("failed to open import archive")?;
+
+    let mut magic = [0u8; 2];
+    let is_zip = file.read_exact(&mut magic).is_ok() && &magic == b"PK";
+
+    file.seek(SeekFrom::Start(0))
+        .context("failed to rewind import archive")?;
+
+    let (entries, mut archive) = if is_zip {
+        let mut archive = ZipArchive::new(file)
+            .context("failed to open import zip")?;
+
+        let documents: Vec<String> = archive.file_names()
+            .filter(|name| !name.contains('/') && name.ends_with(".json"))
+            .map(str::to_owned)
+            .collect();
+
+        if documents.is_empty() {
+            return Err(error::Error::context("import zip does not contain a Day One json document"));
+        }
+
+        let mut entries = Vec::new();
+
+        for name in documents {
+            let document = archive.by_name(&name)
+                .context("failed to find json document in archive")?;
+            let export: Export = serde_json::from_reader(document)
+                .context("invalid Day One json document")?;
+
+            entries.extend(export.entries);
+        }
+
+        (entries, Some(archive))
+    } else {
+        let export: Export = serde_json::from_reader(std::io::BufReader::new(file))
+            .context("invalid Day One json document")?;
+
+        (export.entries, None)
+    };
+
+    let index = archive.as_ref()
+        .map(media_index)
+        .unwrap_or_default();
+
+    if !send(&sender, ImportItem::Total(entries.len())) {
+        return Ok(());
+    }
+
+    for (number, value) in entries.into_iter().enumerate() {
+        let item = match serde_json::from_value::<Entry>(value) {
+            Ok(entry) => {
+                let (entry, warnings) = convert(entry, archive.as_mut(), &index, max_file_size)?;
+
+                for warning in warnings {
+                    if !send(&sender, ImportItem::Warning(format!("entry {}: {warning}", number + 1))) {
+                        return Ok(());
+                    }
+                }
+
+                ImportItem::Entry(entry)
+            }
+            Err(err) => ImportItem::Skipped(format!("entry {}: {err}", number + 1)),
+        };
+
+        if !send(&sender, item) {
+            break;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::io::Write;
+
+    fn read_items(path: PathBuf, max_file_size: u64) -> Vec<ImportItem> {
+        let (sender, mut receiver) = mpsc::channel(16);
+
+        read(path, max_file_size, sender).unwrap();
+
+        let mut rtn = Vec::new();
+
+        while let Ok(item) = receiver.try_recv() {
+            rtn.push(item);
+        }
+
+        rtn
+    }
+
+    #[test]
+    fn large_attachment_is_reported() {
+        let path = std::env::temp_dir().join(format!("tj2_dayone_large_{}.zip", std::process::id()));
+        let mut writer = zip::ZipWriter::new(File::create(&path).unwrap());
+        let options = zip::write::SimpleFileOptions::default();
+
+        writer.start_file("Journal.json", options).unwrap();
+        writer.write_all(br#"{"entries":[{
+            "creationDate":"2024-01-02T09:00:00Z",
+            "text":"with photos",
+            "photos":[{"md5":"aaaa"},{"md5":"bbbb"}]
+        }]}"#).unwrap();
+        writer.start_file("photos/aaaa.jpeg", options).unwrap();
+        writer.write_all(&[0u8; 16]).unwrap();
+        writer.start_file("photos/bbbb.jpeg", options).unwrap();
+        writer.write_all(&[0u8; 4]).unwrap();
+        writer.finish().unwrap();
+
+        let items = read_items(path.clone(), 8);
+
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(matches!(
+            items.as_slice(),
+            [
+                ImportItem::Total(1),
+                ImportItem::Warning(warning),
+                ImportItem::Entry(entry),
+            ] if warning.contains("photos/aaaa.jpeg") &&
+                entry.files.len() == 1 &&
+                entry.files[0].name.as_deref() == Some("bbbb.jpeg") &&
+                entry.files[0].contents.len() == 4
+        ));
+    }
+}
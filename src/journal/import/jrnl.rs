@@ -0,0 +1,172 @@
+//! reading the plain text format of jrnl
+//!
+//! every entry starts with a line like "[2024-01-05 09:12] title. body"
+//! where the title is the first sentence of the line. the rest of the line
+//! and the following lines up to the next entry are the body. "@tag" words
+//! are added as tags and an entry starred with a "*" next to the title is
+//! given the "starred" tag. the times have no time zone so they are
+//! treated as UTC
+
+use std::path::PathBuf;
+
+use chrono::NaiveDateTime;
+use tokio::sync::mpsc;
+
+use crate::error::{self, Context};
+
+use super::{send, ImportItem, ImportedEntry};
+
+/// the date formats that jrnl writes entries with
+const DATE_FORMATS: [&str; 4] = [
+    "%Y-%m-%d %H:%M",
+    "%Y-%m-%d %H:%M:%S",
+    "%Y-%m-%d %I:%M %p",
+    "%Y-%m-%d %I:%M:%S %p",
+];
+
+/// parses the date of an entry header line and returns the rest of the
+/// line
+fn parse_header(line: &str) -> Option<(NaiveDateTime, &str)> {
+    let rest = line.strip_prefix('[')?;
+    let (date, rest) = rest.split_once(']')?;
+
+    let date = DATE_FORMATS.iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(date.trim(), format).ok())?;
+
+    Some((date, rest.trim()))
+}
+
+/// splits the first sentence of a line from the rest of it
+fn split_sentence(line: &str) -> (&str, &str) {
+    let mut chars = line.char_indices().peekable();
+
+    while let Some((index, ch)) = chars.next() {
+        if !matches!(ch, '.' | '?' | '!') {
+            continue;
+        }
+
+        match chars.peek() {
+            Some((_, next)) if next.is_whitespace() => {
+                let end = index + ch.len_utf8();
+
+                return (&line[..end], line[end..].trim_start());
+            }
+            None => return (line, ""),
+            _ => {}
+        }
+    }
+
+    (line, "")
+}
+
+/// finds the "@tag" words of the text
+fn find_tags(text: &str, tags: &mut Vec<(String, Option<String>)>) {
+    for word in text.split_whitespace() {
+        let Some(tag) = word.strip_prefix('@') else {
+            continue;
+        };
+
+        let tag = tag.trim_end_matches(|ch: char| !ch.is_alphanumeric() && ch != '_' && ch != '-');
+
+        if !tag.is_empty() && !tags.iter().any(|(key, _)| key == tag) {
+            tags.push((tag.to_owned(), None));
+        }
+    }
+}
+
+struct RawEntry<'a> {
+    date: NaiveDateTime,
+    header: &'a str,
+    lines: Vec<&'a str>,
+}
+
+fn convert(raw: RawEntry<'_>) -> ImportedEntry {
+    let mut header = raw.header;
+    let mut starred = false;
+
+    if let Some(rest) = header.strip_prefix('*') {
+        header = rest.trim_start();
+        starred = true;
+    }
+
+    let (title, first) = split_sentence(header);
+    let mut title = title.trim();
+
+    if let Some(rest) = title.strip_suffix(" *") {
+        title = rest.trim_end();
+        starred = true;
+    }
+
+    let mut contents = String::from(first);
+
+    for line in raw.lines {
+        if !contents.is_empty() {
+            contents.push('\n');
+        }
+
+        contents.push_str(line);
+    }
+
+    let contents = contents.trim().to_owned();
+
+    let mut tags = Vec::new();
+
+    find_tags(title, &mut tags);
+    find_tags(&contents, &mut tags);
+
+    if starred {
+        tags.push((String::from("starred"), None));
+    }
+
+    let created = raw.date.and_utc();
+
+    ImportedEntry {
+        date: raw.date.date(),
+        title: Some(title.to_owned()).filter(|title| !title.is_empty()),
+        contents: Some(contents).filter(|contents| !contents.is_empty()),
+        created,
+        updated: None,
+        tags,
+        tasks: Vec::new(),
+        fields: Vec::new(),
+        files: Vec::new(),
+    }
+}
+
+/// reads a jrnl text file and sends its entries
+pub fn read(path: PathBuf, sender: mpsc::Sender<ImportItem>) -> Result<(), error::Error> {
+    let bytes = std::fs::read(&path)
+        .context("failed to read import file")?;
+    let text = String::from_utf8(bytes)
+        .context("jrnl file is not valid utf-8")?;
+
+    let mut entries: Vec<RawEntry<'_>> = Vec::new();
+
+    for line in text.lines() {
+        if let Some((date, header)) = parse_header(line) {
+            entries.push(RawEntry {
+                date,
+                header,
+                lines: Vec::new(),
+            });
+        } else if let Some(entry) = entries.last_mut() {
+            entry.lines.push(line);
+        }
+    }
+
+    if entries.is_empty() && !text.trim().is_empty() {
+        return Err(error::Error::context("file does not contain any jrnl entries"));
+    }
+
+    if !send(&sender, ImportItem::Total(entries.len())) {
+        return Ok(());
+    }
+
+    for raw in entries {
+        if !send(&sender, ImportItem::Entry(convert(raw))) {
+            break;
+        }
+    }
+
+    Ok(())
+}
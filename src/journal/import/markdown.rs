@@ -0,0 +1,172 @@
+//! reading a zip of markdown files
+//!
+//! every ".md" file in the zip is an entry. the date of an entry comes from
+//! the "date" of its front matter, the start of its file name, or a
+//! "year/month/day.md" path. the front matter is optional yaml between
+//! "---" lines and can provide a "title", "tags", and "fields". tags are
+//! either a list of names or the "key" and "value" objects written by a
+//! markdown export of this server. fields are a map of custom field names
+//! to values. a leading "# " heading is used as the title if the front
+//! matter does not have one
+
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::Read;
+use std::path::PathBuf;
+
+use chrono::{NaiveDate, NaiveTime, Utc};
+use serde::Deserialize;
+use tokio::sync::mpsc;
+use zip::ZipArchive;
+
+use crate::error::{self, Context};
+
+use super::{send, split_title, ImportItem, ImportedEntry, ImportedField, ImportedValue};
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum FrontMatterTag {
+    Name(String),
+    KeyValue {
+        key: String,
+        value: Option<String>,
+    },
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct FrontMatter {
+    date: Option<NaiveDate>,
+    title: Option<String>,
+    #[serde(default)]
+    tags: Vec<FrontMatterTag>,
+    #[serde(default)]
+    fields: BTreeMap<String, serde_yml::Value>,
+}
+
+/// splits the yaml front matter from the rest of the file
+fn split_front_matter(text: &str) -> Option<(&str, &str)> {
+    let rest = text.strip_prefix("---\n")
+        .or_else(|| text.strip_prefix("---\r\n"))?;
+
+    let mut offset = 0;
+
+    for line in rest.split_inclusive('\n') {
+        if line.trim_end() == "---" {
+            return Some((&rest[..offset], &rest[offset + line.len()..]));
+        }
+
+        offset += line.len();
+    }
+
+    None
+}
+
+/// finds the date of an entry from the path of its file
+fn path_date(name: &str) -> Option<NaiveDate> {
+    let file_name = name.rsplit('/').next()?;
+
+    if let Some(prefix) = file_name.get(..10) {
+        if let Ok(date) = NaiveDate::parse_from_str(prefix, "%Y-%m-%d") {
+            return Some(date);
+        }
+    }
+
+    // a "year/month/day.md" layout
+    let mut parts = name.trim_end_matches(".md").rsplit('/');
+    let day = parts.next()?;
+    let month = parts.next()?;
+    let year = parts.next()?;
+
+    NaiveDate::parse_from_str(&format!("{year}-{month}-{day}"), "%Y-%m-%d").ok()
+}
+
+fn field_value(value: serde_yml::Value) -> Option<ImportedValue> {
+    match value {
+        serde_yml::Value::Bool(value) => Some(ImportedValue::Boolean(value)),
+        serde_yml::Value::Number(number) => match number.as_i64() {
+            Some(value) => Some(ImportedValue::Integer(value)),
+            None => number.as_f64().map(ImportedValue::Float),
+        },
+        serde_yml::Value::String(value) => Some(ImportedValue::Text(value)),
+        _ => None
+    }
+}
+
+fn convert(name: &str, text: &str) -> Option<ImportedEntry> {
+    let (front_matter, body) = match split_front_matter(text) {
+        Some((yaml, body)) => (serde_yml::from_str::<FrontMatter>(yaml).ok()?, body),
+        None => (FrontMatter::default(), text),
+    };
+
+    let date = front_matter.date.or_else(|| path_date(name))?;
+
+    let (heading, contents) = split_title(body.trim().to_owned());
+    let title = front_matter.title
+        .map(|title| title.trim().to_owned())
+        .filter(|title| !title.is_empty())
+        .or(heading);
+
+    let tags = front_matter.tags.into_iter()
+        .map(|tag| match tag {
+            FrontMatterTag::Name(name) => (name, None),
+            FrontMatterTag::KeyValue { key, value } => (key, value),
+        })
+        .collect();
+
+    let fields = front_matter.fields.into_iter()
+        .filter_map(|(name, value)| field_value(value).map(|value| (ImportedField::Named(name), value)))
+        .collect();
+
+    Some(ImportedEntry {
+        date,
+        title,
+        contents,
+        created: date.and_time(NaiveTime::MIN).and_utc().min(Utc::now()),
+        updated: None,
+        tags,
+        tasks: Vec::new(),
+        fields,
+        files: Vec::new(),
+    })
+}
+
+/// reads a zip of markdown files and sends its entries
+pub fn read(path: PathBuf, sender: mpsc::Sender<ImportItem>) -> Result<(), error::Error> {
+    let file = File::open(&path)
+        .context("failed to open import archive")?;
+    let mut archive = ZipArchive::new(file)
+        .context("failed to open import zip")?;
+
+    let mut names: Vec<String> = archive.file_names()
+        .filter(|name| name.ends_with(".md") && !name.starts_with("__MACOSX/"))
+        .map(str::to_owned)
+        .collect();
+
+    names.sort();
+
+    if !send(&sender, ImportItem::Total(names.len())) {
+        return Ok(());
+    }
+
+    for name in names {
+        let mut text = String::new();
+
+        let read = archive.by_name(&name)
+            .context("failed to find markdown file in archive")?
+            .read_to_string(&mut text);
+
+        let item = match read {
+            Ok(_) => match convert(&name, &text) {
+                Some(entry) => ImportItem::Entry(entry),
+                None => ImportItem::Skipped(format!("{name}: invalid front matter or missing date")),
+            },
+            Err(err) => ImportItem::Skipped(format!("{name}: {err}")),
+        };
+
+        if !send(&sender, item) {
+            break;
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,78 @@
+//! live updates for journals
+//!
+//! events are published once the change that caused them has been committed
+//! and are forwarded to the clients connected to the "/ws" endpoint that
+//! have subscribed to the journal. events only contain the ids of what
+//! changed so clients retrieve the changes with their own permissions. events
+//! are not stored, a client that is not connected will not receive them
+
+use std::sync::Arc;
+
+use serde::Serialize;
+use tokio::sync::broadcast;
+
+use crate::db::ids::{EntryId, FileEntryId, JournalId};
+
+/// the number of events held for a slow receiver before it starts to miss
+/// them
+const CAPACITY: usize = 256;
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type")]
+pub enum LiveEvent {
+    #[serde(rename = "entry.created")]
+    EntryCreated {
+        journals_id: JournalId,
+        entries_id: EntryId,
+    },
+    #[serde(rename = "entry.updated")]
+    EntryUpdated {
+        journals_id: JournalId,
+        entries_id: EntryId,
+    },
+    #[serde(rename = "entry.deleted")]
+    EntryDeleted {
+        journals_id: JournalId,
+        entries_id: EntryId,
+    },
+    #[serde(rename = "file.received")]
+    FileReceived {
+        journals_id: JournalId,
+        entries_id: EntryId,
+        file_entry_id: FileEntryId,
+    },
+}
+
+impl LiveEvent {
+    /// the journal that the event happened in
+    pub fn journals_id(&self) -> &JournalId {
+        match self {
+            LiveEvent::EntryCreated { journals_id, .. } |
+            LiveEvent::EntryUpdated { journals_id, .. } |
+            LiveEvent::EntryDeleted { journals_id, .. } |
+            LiveEvent::FileReceived { journals_id, .. } => journals_id,
+        }
+    }
+}
+
+/// sends live events to every connected client
+#[derive(Debug, Clone)]
+pub struct LiveEvents(broadcast::Sender<Arc<LiveEvent>>);
+
+impl LiveEvents {
+    pub fn new() -> Self {
+        let (sender, _) = broadcast::channel(CAPACITY);
+
+        LiveEvents(sender)
+    }
+
+    /// sends the event to the current receivers. it is not an error for
+    /// there to be no receivers
+    pub fn publish(&self, event: LiveEvent) {
+        let _ = self.0.send(Arc::new(event));
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<Arc<LiveEvent>> {
+        self.0.subscribe()
+    }
+}
@@ -0,0 +1,88 @@
+use pulldown_cmark::{html, Event, Options, Parser};
+use serde::Deserialize;
+
+/// the available formats that entry contents can be rendered to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Render {
+    Html,
+}
+
+/// renders the given markdown to html
+///
+/// the resulting html is sanitized so that it is safe to display in a
+/// browser
+pub fn render_html(contents: &str) -> String {
+    let options = Options::ENABLE_TABLES
+        | Options::ENABLE_FOOTNOTES
+        | Options::ENABLE_STRIKETHROUGH
+        | Options::ENABLE_TASKLISTS;
+    let parser = Parser::new_ext(contents, options);
+
+    let mut unsafe_html = String::with_capacity(contents.len());
+    html::push_html(&mut unsafe_html, parser);
+
+    ammonia::clean(&unsafe_html)
+}
+
+/// removes the formatting of the given markdown. blocks and line breaks
+/// are replaced with a space
+fn plain_text(contents: &str) -> String {
+    let mut text = String::new();
+
+    for event in Parser::new(contents) {
+        match event {
+            Event::Text(value) | Event::Code(value) => text.push_str(&value),
+            Event::SoftBreak | Event::HardBreak | Event::End(_) => text.push(' '),
+            _ => {}
+        }
+    }
+
+    text
+}
+
+/// counts the words in the given markdown. formatting characters and link
+/// urls are not counted
+pub fn word_count(contents: &str) -> i32 {
+    let count = plain_text(contents)
+        .split_whitespace()
+        .filter(|word| word.chars().any(char::is_alphanumeric))
+        .count();
+
+    i32::try_from(count).unwrap_or(i32::MAX)
+}
+
+/// creates a short plain text summary of the given markdown
+///
+/// formatting is removed and whitespace is collapsed. summaries longer than
+/// the given number of characters are cut at the last word that fits
+pub fn summarize(contents: &str, max_chars: usize) -> String {
+    let text = plain_text(contents);
+
+    let mut summary = String::with_capacity(text.len().min(max_chars * 4));
+    let mut count = 0;
+
+    for word in text.split_whitespace() {
+        let len = word.chars().count();
+        let needed = if summary.is_empty() { len } else { len + 1 };
+
+        if count + needed > max_chars {
+            if summary.is_empty() {
+                summary.extend(word.chars().take(max_chars));
+            }
+
+            summary.push('…');
+
+            return summary;
+        }
+
+        if !summary.is_empty() {
+            summary.push(' ');
+        }
+
+        summary.push_str(word);
+        count += needed;
+    }
+
+    summary
+}
@@ -0,0 +1,639 @@
+//! people and places that the entries of a journal can mention
+//!
+//! each journal has its own registry of subjects. an entry mentions a
+//! subject either from its contents or from the list of subjects sent by the
+//! client. mentions in the contents are written as "@" followed by a name or
+//! alias, ex: "@sam" or "@[Sam Smith]" for names with spaces, and are found
+//! again every time the contents change. client mentions are only changed
+//! when the client sends a new list
+
+use std::collections::HashSet;
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::str::FromStr;
+
+use bytes::BytesMut;
+use chrono::{DateTime, NaiveDate, Utc};
+use futures::StreamExt;
+use postgres_types as pg_types;
+use serde::{Serialize, Deserialize};
+
+use crate::db::{self, GenericClient, PgError};
+use crate::db::ids::{EntryId, JournalId, SubjectId};
+use crate::error::BoxDynError;
+use crate::journal::WeekStart;
+use crate::journal::stats::{Bucket, DateRange};
+
+#[derive(Debug, thiserror::Error)]
+#[error("the provided string is not a valid subject kind")]
+pub struct InvalidSubjectKind;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SubjectKind {
+    Person,
+    Place,
+}
+
+impl SubjectKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SubjectKind::Person => "person",
+            SubjectKind::Place => "place",
+        }
+    }
+}
+
+impl Display for SubjectKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for SubjectKind {
+    type Err = InvalidSubjectKind;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "person" => Ok(SubjectKind::Person),
+            "place" => Ok(SubjectKind::Place),
+            _ => Err(InvalidSubjectKind),
+        }
+    }
+}
+
+impl<'a> pg_types::FromSql<'a> for SubjectKind {
+    fn from_sql(ty: &pg_types::Type, raw: &'a [u8]) -> Result<Self, BoxDynError> {
+        let v = <&str as pg_types::FromSql>::from_sql(ty, raw)?;
+
+        Ok(Self::from_str(v)?)
+    }
+
+    fn accepts(ty: &pg_types::Type) -> bool {
+        <&str as pg_types::FromSql>::accepts(ty)
+    }
+}
+
+impl pg_types::ToSql for SubjectKind {
+    fn to_sql(&self, ty: &pg_types::Type, w: &mut BytesMut) -> Result<pg_types::IsNull, BoxDynError> {
+        self.as_str()
+            .to_sql(ty, w)
+    }
+
+    fn accepts(ty: &pg_types::Type) -> bool {
+        <&str as pg_types::ToSql>::accepts(ty)
+    }
+
+    pg_types::to_sql_checked!();
+}
+
+/// finds the names mentioned in the given contents, lowercased
+///
+/// an "@" only starts a mention if it is not part of a word so that email
+/// addresses are not picked up
+pub fn parse_mentions(contents: &str) -> HashSet<String> {
+    let mut rtn = HashSet::new();
+    let mut prev: Option<char> = None;
+    for (index, ch) in contents.char_indices() {
+        let starts = ch == '@' && !prev.is_some_and(|c| c.is_alphanumeric() || c == '@');
+
+        prev = Some(ch);
+
+        if !starts {
+            continue;
+        }
+
+        let rest = &contents[index + 1..];
+
+        let name = if let Some(bracketed) = rest.strip_prefix('[') {
+            let Some(end) = bracketed.find([']', '\n']) else {
+                continue;
+            };
+
+            if !bracketed[end..].starts_with(']') {
+                continue;
+            }
+
+            bracketed[..end].trim()
+        } else {
+            let end = rest.find(|c: char| !(c.is_alphanumeric() || c == '_' || c == '-'))
+                .unwrap_or(rest.len());
+
+            rest[..end].trim_end_matches('-')
+        };
+
+        if !name.is_empty() {
+            rtn.insert(name.to_lowercase());
+        }
+    }
+
+    rtn
+}
+
+/// a person or place of a journal
+#[derive(Debug, Serialize)]
+pub struct Subject {
+    pub id: SubjectId,
+    pub journals_id: JournalId,
+    pub kind: SubjectKind,
+    pub name: String,
+
+    /// other names that will match the subject when mentioned in the
+    /// contents of an entry
+    pub aliases: Vec<String>,
+    pub description: Option<String>,
+    pub created: DateTime<Utc>,
+    pub updated: Option<DateTime<Utc>>,
+}
+
+/// a subject along with the entries that mention it
+#[derive(Debug, Serialize)]
+pub struct SubjectMentions {
+    #[serde(flatten)]
+    pub subject: Subject,
+
+    /// the number of entries that mention the subject
+    pub entries: i64,
+
+    /// the date of the most recent entry that mentions the subject
+    pub last_mentioned: Option<NaiveDate>,
+}
+
+impl Subject {
+    fn map_row(row: &tokio_postgres::Row) -> Self {
+        Self {
+            id: row.get(0),
+            journals_id: row.get(1),
+            kind: row.get(2),
+            name: row.get(3),
+            aliases: row.get(4),
+            description: row.get(5),
+            created: row.get(6),
+            updated: row.get(7),
+        }
+    }
+
+    /// retrieves the subjects of a journal with their mention counts ordered
+    /// by name. the kind will only retrieve subjects of that kind if given
+    pub async fn retrieve_journal(
+        conn: &impl GenericClient,
+        journals_id: &JournalId,
+        kind: Option<SubjectKind>,
+    ) -> Result<Vec<SubjectMentions>, PgError> {
+        let params: db::ParamsArray<'_, 2> = [journals_id, &kind];
+        let stream = conn.query_raw(
+            "\
+            select journal_subjects.id, \
+                   journal_subjects.journals_id, \
+                   journal_subjects.kind, \
+                   journal_subjects.name, \
+                   journal_subjects.aliases, \
+                   journal_subjects.description, \
+                   journal_subjects.created, \
+                   journal_subjects.updated, \
+                   count(distinct entries.id), \
+                   max(entries.entry_date) \
+            from journal_subjects \
+                left join entry_mentions on \
+                    journal_subjects.id = entry_mentions.subjects_id \
+                left join entries on \
+                    entry_mentions.entries_id = entries.id \
+            where journal_subjects.journals_id = $1 and \
+                  ($2::varchar is null or journal_subjects.kind = $2) \
+            group by journal_subjects.id \
+            order by journal_subjects.name",
+            params
+        ).await?;
+
+        futures::pin_mut!(stream);
+
+        let mut rtn = Vec::new();
+
+        while let Some(try_row) = stream.next().await {
+            let row = try_row?;
+
+            rtn.push(SubjectMentions {
+                subject: Self::map_row(&row),
+                entries: row.get(8),
+                last_mentioned: row.get(9),
+            });
+        }
+
+        Ok(rtn)
+    }
+
+    pub async fn retrieve_id(
+        conn: &impl GenericClient,
+        journals_id: &JournalId,
+        subjects_id: &SubjectId,
+    ) -> Result<Option<Self>, PgError> {
+        conn.query_opt(
+            "\
+            select journal_subjects.id, \
+                   journal_subjects.journals_id, \
+                   journal_subjects.kind, \
+                   journal_subjects.name, \
+                   journal_subjects.aliases, \
+                   journal_subjects.description, \
+                   journal_subjects.created, \
+                   journal_subjects.updated \
+            from journal_subjects \
+            where journal_subjects.journals_id = $1 and \
+                  journal_subjects.id = $2",
+            &[journals_id, subjects_id]
+        )
+            .await
+            .map(|maybe| maybe.map(|row| Self::map_row(&row)))
+    }
+
+    /// checks if another subject of the same kind in the journal already has
+    /// the given name
+    pub async fn name_exists(
+        conn: &impl GenericClient,
+        journals_id: &JournalId,
+        kind: SubjectKind,
+        name: &str,
+        ignore: Option<&SubjectId>,
+    ) -> Result<bool, PgError> {
+        conn.query_opt(
+            "\
+            select journal_subjects.id \
+            from journal_subjects \
+            where journal_subjects.journals_id = $1 and \
+                  journal_subjects.kind = $2 and \
+                  journal_subjects.name = $3 and \
+                  ($4::bigint is null or journal_subjects.id != $4)",
+            &[journals_id, &kind, &name, &ignore]
+        )
+            .await
+            .map(|maybe| maybe.is_some())
+    }
+
+    pub async fn create(
+        conn: &impl GenericClient,
+        journals_id: &JournalId,
+        kind: SubjectKind,
+        name: String,
+        aliases: Vec<String>,
+        description: Option<String>,
+    ) -> Result<Self, PgError> {
+        let created = Utc::now();
+
+        let row = conn.query_one(
+            "\
+            insert into journal_subjects (journals_id, kind, name, aliases, description, created) values \
+            ($1, $2, $3, $4, $5, $6) \
+            returning id",
+            &[journals_id, &kind, &name, &aliases, &description, &created]
+        ).await?;
+
+        Ok(Self {
+            id: row.get(0),
+            journals_id: *journals_id,
+            kind,
+            name,
+            aliases,
+            description,
+            created,
+            updated: None,
+        })
+    }
+
+    /// saves the current kind, name, aliases, and description of the subject
+    pub async fn update(&mut self, conn: &impl GenericClient) -> Result<(), PgError> {
+        let updated = Utc::now();
+
+        conn.execute(
+            "\
+            update journal_subjects \
+            set kind = $2, \
+                name = $3, \
+                aliases = $4, \
+                description = $5, \
+                updated = $6 \
+            where id = $1",
+            &[&self.id, &self.kind, &self.name, &self.aliases, &self.description, &updated]
+        ).await?;
+
+        self.updated = Some(updated);
+
+        Ok(())
+    }
+
+    /// deletes the subject and removes it from the entries that mention it
+    pub async fn delete(&self, conn: &impl GenericClient) -> Result<(), PgError> {
+        conn.execute(
+            "delete from entry_mentions where subjects_id = $1",
+            &[&self.id]
+        ).await?;
+
+        conn.execute(
+            "delete from journal_subjects where id = $1",
+            &[&self.id]
+        ).await?;
+
+        Ok(())
+    }
+}
+
+/// where the mention of a subject came from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum MentionSource {
+    Body,
+    Client,
+}
+
+impl MentionSource {
+    fn as_str(&self) -> &'static str {
+        match self {
+            MentionSource::Body => "body",
+            MentionSource::Client => "client",
+        }
+    }
+}
+
+/// retrieves the subjects mentioned by an entry from any source
+pub async fn retrieve_entry(conn: &impl GenericClient, entries_id: &EntryId) -> Result<Vec<SubjectId>, PgError> {
+    conn.query(
+        "\
+        select distinct entry_mentions.subjects_id \
+        from entry_mentions \
+        where entry_mentions.entries_id = $1 \
+        order by entry_mentions.subjects_id",
+        &[entries_id]
+    )
+        .await
+        .map(|rows| rows.into_iter().map(|row| row.get(0)).collect())
+}
+
+/// replaces the mentions of an entry that were found in its contents
+///
+/// names and aliases are matched without case. names that do not match a
+/// subject of the journal are ignored
+pub async fn sync_body(
+    conn: &impl GenericClient,
+    journals_id: &JournalId,
+    entries_id: &EntryId,
+    contents: Option<&str>,
+) -> Result<(), PgError> {
+    let source = MentionSource::Body.as_str();
+
+    conn.execute(
+        "delete from entry_mentions where entries_id = $1 and source = $2",
+        &[entries_id, &source]
+    ).await?;
+
+    let names: Vec<String> = contents.map(parse_mentions)
+        .unwrap_or_default()
+        .into_iter()
+        .collect();
+
+    if names.is_empty() {
+        return Ok(());
+    }
+
+    conn.execute(
+        "\
+        insert into entry_mentions (entries_id, subjects_id, source) \
+        select $2, journal_subjects.id, $3 \
+        from journal_subjects \
+        where journal_subjects.journals_id = $1 and ( \
+            lower(journal_subjects.name) = any($4) or \
+            exists ( \
+                select 1 \
+                from unnest(journal_subjects.aliases) as alias \
+                where lower(alias) = any($4) \
+            ) \
+        )",
+        &[journals_id, entries_id, &source, &names]
+    ).await?;
+
+    Ok(())
+}
+
+/// replaces the mentions of an entry that were sent by the client
+///
+/// returns the ids that are not subjects of the journal, in which case no
+/// changes are made
+pub async fn set_client(
+    conn: &impl GenericClient,
+    journals_id: &JournalId,
+    entries_id: &EntryId,
+    subjects: &[SubjectId],
+) -> Result<Result<(), Vec<SubjectId>>, PgError> {
+    let found: HashSet<SubjectId> = conn.query(
+        "\
+        select journal_subjects.id \
+        from journal_subjects \
+        where journal_subjects.journals_id = $1 and \
+              journal_subjects.id = any($2)",
+        &[journals_id, &subjects]
+    )
+        .await?
+        .into_iter()
+        .map(|row| row.get(0))
+        .collect();
+
+    let mut missing: Vec<SubjectId> = subjects.iter()
+        .filter(|id| !found.contains(id))
+        .copied()
+        .collect();
+
+    if !missing.is_empty() {
+        missing.sort();
+        missing.dedup();
+
+        return Ok(Err(missing));
+    }
+
+    let source = MentionSource::Client.as_str();
+
+    conn.execute(
+        "delete from entry_mentions where entries_id = $1 and source = $2",
+        &[entries_id, &source]
+    ).await?;
+
+    if !found.is_empty() {
+        let ids: Vec<SubjectId> = found.into_iter().collect();
+
+        conn.execute(
+            "\
+            insert into entry_mentions (entries_id, subjects_id, source) \
+            select $1, subjects.id, $2 \
+            from unnest($3::bigint[]) as subjects (id)",
+            &[entries_id, &source, &ids]
+        ).await?;
+    }
+
+    Ok(Ok(()))
+}
+
+/// replaces the mentions of a subject that were found in the contents of
+/// the entries of its journal
+///
+/// used when a subject is created or its name or aliases change so that
+/// entries written before then are found
+pub async fn sync_subject(conn: &impl GenericClient, subject: &Subject) -> Result<(), PgError> {
+    let source = MentionSource::Body.as_str();
+
+    conn.execute(
+        "delete from entry_mentions where subjects_id = $1 and source = $2",
+        &[&subject.id, &source]
+    ).await?;
+
+    let names: HashSet<String> = std::iter::once(&subject.name)
+        .chain(&subject.aliases)
+        .map(|name| name.to_lowercase())
+        .collect();
+    let params: db::ParamsArray<'_, 1> = [&subject.journals_id];
+    let stream = conn.query_raw(
+        "\
+        select entries.id, \
+               entries.contents \
+        from entries \
+        where entries.journals_id = $1 and \
+              entries.contents like '%@%'",
+        params
+    ).await?;
+
+    futures::pin_mut!(stream);
+
+    let mut ids: Vec<EntryId> = Vec::new();
+
+    while let Some(try_row) = stream.next().await {
+        let row = try_row?;
+        let contents: &str = row.get(1);
+
+        if !parse_mentions(contents).is_disjoint(&names) {
+            ids.push(row.get(0));
+        }
+    }
+
+    if !ids.is_empty() {
+        conn.execute(
+            "\
+            insert into entry_mentions (entries_id, subjects_id, source) \
+            select entries.id, $1, $2 \
+            from unnest($3::bigint[]) as entries (id)",
+            &[&subject.id, &source, &ids]
+        ).await?;
+    }
+
+    Ok(())
+}
+
+/// the number of entries that mention a subject for a single bucket
+#[derive(Debug, Serialize)]
+pub struct MentionCount {
+    /// the first day of the bucket. weeks start on the week start of the
+    /// journal
+    pub start: NaiveDate,
+    pub count: i64,
+}
+
+impl MentionCount {
+    /// retrieves the bucketed counts of the entries mentioning a subject
+    /// ordered by date
+    ///
+    /// buckets without any entries are not included
+    pub async fn retrieve(
+        conn: &impl GenericClient,
+        subjects_id: &SubjectId,
+        range: &DateRange,
+        bucket: Bucket,
+        week_start: WeekStart,
+    ) -> Result<Vec<Self>, PgError> {
+        let trunc = bucket.as_trunc();
+        let offset = match bucket {
+            Bucket::Weekly => week_start.monday_offset(),
+            Bucket::Daily | Bucket::Monthly => 0,
+        };
+        let params: db::ParamsArray<'_, 5> = [
+            subjects_id,
+            &range.from,
+            &range.to,
+            &trunc,
+            &offset,
+        ];
+        let stream = conn.query_raw(
+            "\
+            select date_trunc($4, (entries.entry_date + $5::integer)::timestamp)::date - $5::integer as bucket, \
+                   count(distinct entries.id) \
+            from entry_mentions \
+                join entries on \
+                    entry_mentions.entries_id = entries.id \
+            where entry_mentions.subjects_id = $1 and \
+                  ($2::date is null or entries.entry_date >= $2) and \
+                  ($3::date is null or entries.entry_date <= $3) and \
+                  not entries.planned \
+            group by bucket \
+            order by bucket",
+            params
+        ).await?;
+
+        futures::pin_mut!(stream);
+
+        let mut rtn = Vec::new();
+
+        while let Some(try_row) = stream.next().await {
+            let row = try_row?;
+
+            rtn.push(Self {
+                start: row.get(0),
+                count: row.get(1),
+            });
+        }
+
+        Ok(rtn)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn mentions(contents: &str) -> Vec<String> {
+        let mut rtn: Vec<String> = parse_mentions(contents).into_iter().collect();
+        rtn.sort();
+        rtn
+    }
+
+    #[test]
+    fn plain_names() {
+        assert_eq!(mentions("lunch with @Bob and @alice_b."), ["alice_b", "bob"]);
+        assert_eq!(mentions("(@bob)"), ["bob"]);
+    }
+
+    #[test]
+    fn bracketed_names() {
+        assert_eq!(mentions("met @[Jane Doe] at @[ The Cafe ]"), ["jane doe", "the cafe"]);
+        assert_eq!(mentions("@[Ann] and @[ann]"), ["ann"]);
+        assert!(mentions("@[ ]").is_empty());
+    }
+
+    #[test]
+    fn unclosed_bracket() {
+        assert!(mentions("@[Jane\nDoe]").is_empty());
+        assert!(mentions("@[Jane Doe").is_empty());
+        assert_eq!(mentions("@[Jane\n@bob"), ["bob"]);
+    }
+
+    #[test]
+    fn email_addresses() {
+        assert!(mentions("send it to jane@example.com").is_empty());
+        assert!(mentions("é@example.com").is_empty());
+        assert!(mentions("@@bob").is_empty());
+    }
+
+    #[test]
+    fn trailing_dash() {
+        assert_eq!(mentions("@bob- was there"), ["bob"]);
+        assert_eq!(mentions("@mary-jane"), ["mary-jane"]);
+        assert!(mentions("@-").is_empty());
+    }
+
+    #[test]
+    fn non_ascii_names() {
+        assert_eq!(mentions("@José, @Zoë. and @[Ærø Island]"), ["josé", "zoë", "ærø island"]);
+        assert_eq!(mentions("@東京"), ["東京"]);
+    }
+}
@@ -0,0 +1,204 @@
+//! the order that a user's journals are listed in
+//!
+//! pinned journals are always listed first. the remaining journals are
+//! sorted by the sort the user has chosen with the journal name used to
+//! break ties
+
+use std::collections::HashSet;
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::str::FromStr;
+
+use bytes::BytesMut;
+use postgres_types as pg_types;
+use serde::{Serialize, Deserialize};
+
+use crate::db::{GenericClient, PgError};
+use crate::db::ids::{JournalId, UserId};
+use crate::error::BoxDynError;
+
+#[derive(Debug, thiserror::Error)]
+#[error("the provided string is not a valid journal sort")]
+pub struct InvalidJournalSort;
+
+/// how the journals of a user are sorted
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JournalSort {
+    /// the position set by the user. journals without a position are listed
+    /// after the ones that have one
+    #[default]
+    Manual,
+
+    /// alphabetical by name
+    Name,
+
+    /// the journals with the most recent entry first
+    LastEntry,
+}
+
+impl JournalSort {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            JournalSort::Manual => "manual",
+            JournalSort::Name => "name",
+            JournalSort::LastEntry => "last_entry",
+        }
+    }
+
+    /// retrieves the sort chosen by the user
+    pub async fn retrieve(conn: &impl GenericClient, users_id: &UserId) -> Result<Self, PgError> {
+        conn.query_opt(
+            "\
+            select journal_sorts.sort \
+            from journal_sorts \
+            where journal_sorts.users_id = $1",
+            &[users_id]
+        )
+            .await
+            .map(|maybe| maybe.map(|row| row.get(0)).unwrap_or_default())
+    }
+
+    pub async fn update(&self, conn: &impl GenericClient, users_id: &UserId) -> Result<(), PgError> {
+        conn.execute(
+            "\
+            insert into journal_sorts (users_id, sort) values ($1, $2) \
+            on conflict (users_id) do update \
+                set sort = excluded.sort",
+            &[users_id, self]
+        ).await?;
+
+        Ok(())
+    }
+}
+
+impl Display for JournalSort {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for JournalSort {
+    type Err = InvalidJournalSort;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "manual" => Ok(JournalSort::Manual),
+            "name" => Ok(JournalSort::Name),
+            "last_entry" => Ok(JournalSort::LastEntry),
+            _ => Err(InvalidJournalSort)
+        }
+    }
+}
+
+impl<'a> pg_types::FromSql<'a> for JournalSort {
+    fn from_sql(ty: &pg_types::Type, raw: &'a [u8]) -> Result<Self, BoxDynError> {
+        let v = <&str as pg_types::FromSql>::from_sql(ty, raw)?;
+
+        Ok(Self::from_str(v)?)
+    }
+
+    fn accepts(ty: &pg_types::Type) -> bool {
+        <&str as pg_types::FromSql>::accepts(ty)
+    }
+}
+
+impl pg_types::ToSql for JournalSort {
+    fn to_sql(&self, ty: &pg_types::Type, w: &mut BytesMut) -> Result<pg_types::IsNull, BoxDynError> {
+        self.as_str()
+            .to_sql(ty, w)
+    }
+
+    fn accepts(ty: &pg_types::Type) -> bool {
+        <&str as pg_types::ToSql>::accepts(ty)
+    }
+
+    pg_types::to_sql_checked!();
+}
+
+/// the placement of a journal provided by a client
+#[derive(Debug, Deserialize)]
+pub struct OrderInput {
+    pub id: JournalId,
+    #[serde(default)]
+    pub pinned: bool,
+}
+
+/// the result of replacing the order of a user's journals
+#[derive(Debug, Default)]
+pub struct OrderReplace {
+    /// ids that were given but are not journals of the user
+    pub not_found: Vec<JournalId>,
+
+    /// ids that were given more than once
+    pub duplicates: Vec<JournalId>,
+}
+
+impl OrderReplace {
+    pub fn is_valid(&self) -> bool {
+        self.not_found.is_empty() && self.duplicates.is_empty()
+    }
+}
+
+/// replaces the positions of a user's journals with the given list. journals
+/// that are not in the list lose their position and are unpinned
+///
+/// nothing is changed if the result is not valid
+pub async fn replace(
+    conn: &impl GenericClient,
+    users_id: &UserId,
+    order: &[OrderInput],
+) -> Result<OrderReplace, PgError> {
+    let mut result = OrderReplace::default();
+    let mut seen = HashSet::with_capacity(order.len());
+
+    for input in order {
+        if !seen.insert(input.id) {
+            result.duplicates.push(input.id);
+        }
+    }
+
+    let ids: Vec<JournalId> = seen.into_iter().collect();
+    let rows = conn.query(
+        "\
+        select journals.id \
+        from journals \
+        where journals.users_id = $1 and \
+              journals.id = any($2)",
+        &[users_id, &ids]
+    ).await?;
+    let owned: HashSet<JournalId> = rows.into_iter()
+        .map(|row| row.get(0))
+        .collect();
+
+    for id in ids {
+        if !owned.contains(&id) {
+            result.not_found.push(id);
+        }
+    }
+
+    if !result.is_valid() {
+        return Ok(result);
+    }
+
+    conn.execute(
+        "delete from journal_orders where users_id = $1",
+        &[users_id]
+    ).await?;
+
+    for (position, input) in order.iter().enumerate() {
+        let position = position as i32;
+
+        conn.execute(
+            "\
+            insert into journal_orders ( \
+                users_id, \
+                journals_id, \
+                position, \
+                pinned \
+            ) values ($1, $2, $3, $4)",
+            &[users_id, &input.id, &position, &input.pinned]
+        ).await?;
+    }
+
+    Ok(result)
+}
@@ -0,0 +1,230 @@
+//! writing prompts that entries can be linked to
+//!
+//! prompts without a user are provided by the server and are available to
+//! everyone. users are also able to add their own prompts that only they
+//! can see. the prompt of the day is picked from the available prompts
+//! using a hash of the user and date so it stays the same for the whole day
+
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::Serialize;
+
+use crate::db::{GenericClient, PgError};
+use crate::db::ids::{PromptId, UserId};
+
+/// the prompts that are added when the server does not have any
+pub const DEFAULT_PROMPTS: [&str; 20] = [
+    "What made you smile today?",
+    "What is something you learned recently?",
+    "Describe a moment from today that you want to remember.",
+    "What is one thing you are grateful for right now?",
+    "What is taking up most of your thoughts lately?",
+    "Who did you talk to today and what did you talk about?",
+    "What is something you are looking forward to?",
+    "What was the hardest part of your day?",
+    "Describe the place you are in right now.",
+    "What would you tell yourself from a year ago?",
+    "What is a small win you had this week?",
+    "What is something you want to let go of?",
+    "What did you eat today and who did you share it with?",
+    "What is a question you have been thinking about?",
+    "How did you take care of yourself today?",
+    "What surprised you today?",
+    "What is something you would like to do differently tomorrow?",
+    "Describe someone who has been on your mind.",
+    "What is a habit you are trying to build or break?",
+    "If today had a title, what would it be?",
+];
+
+#[derive(Debug, Serialize)]
+pub struct Prompt {
+    pub id: PromptId,
+
+    /// the user that created the prompt. prompts from the server do not
+    /// have a user
+    pub users_id: Option<UserId>,
+    pub text: String,
+    pub created: DateTime<Utc>,
+    pub updated: Option<DateTime<Utc>>,
+}
+
+impl Prompt {
+    fn map_row(row: tokio_postgres::Row) -> Self {
+        Self {
+            id: row.get(0),
+            users_id: row.get(1),
+            text: row.get(2),
+            created: row.get(3),
+            updated: row.get(4),
+        }
+    }
+
+    /// retrieves the prompt if it is from the server or was created by the
+    /// specified [`UserId`]
+    pub async fn retrieve_id(
+        conn: &impl GenericClient,
+        prompts_id: &PromptId,
+        users_id: &UserId,
+    ) -> Result<Option<Self>, PgError> {
+        conn.query_opt(
+            "\
+            select prompts.id, \
+                   prompts.users_id, \
+                   prompts.text, \
+                   prompts.created, \
+                   prompts.updated \
+            from prompts \
+            where prompts.id = $1 and \
+                  (prompts.users_id is null or prompts.users_id = $2)",
+            &[prompts_id, users_id]
+        )
+            .await
+            .map(|maybe| maybe.map(Self::map_row))
+    }
+
+    /// retrieves the prompts from the server followed by the prompts of the
+    /// specified [`UserId`]
+    pub async fn retrieve_available(conn: &impl GenericClient, users_id: &UserId) -> Result<Vec<Self>, PgError> {
+        conn.query(
+            "\
+            select prompts.id, \
+                   prompts.users_id, \
+                   prompts.text, \
+                   prompts.created, \
+                   prompts.updated \
+            from prompts \
+            where prompts.users_id is null or \
+                  prompts.users_id = $1 \
+            order by prompts.users_id nulls first, \
+                     prompts.id",
+            &[users_id]
+        )
+            .await
+            .map(|rows| rows.into_iter().map(Self::map_row).collect())
+    }
+
+    /// picks the prompt for the specified [`UserId`] on the given date. the
+    /// same prompt is picked every time for the same user and date
+    pub async fn retrieve_daily(
+        conn: &impl GenericClient,
+        users_id: &UserId,
+        date: &NaiveDate,
+    ) -> Result<Option<Self>, PgError> {
+        let count: i64 = conn.query_one(
+            "select count(*) from prompts where users_id is null or users_id = $1",
+            &[users_id]
+        )
+            .await?
+            .get(0);
+
+        if count == 0 {
+            return Ok(None);
+        }
+
+        let mut hasher = blake3::Hasher::new();
+        hasher.update(&users_id.inner().to_be_bytes());
+        hasher.update(date.to_string().as_bytes());
+
+        let hash = hasher.finalize();
+        let mut bytes = [0; 8];
+        bytes.copy_from_slice(&hash.as_bytes()[..8]);
+
+        let offset = (u64::from_be_bytes(bytes) % count as u64) as i64;
+
+        conn.query_opt(
+            "\
+            select prompts.id, \
+                   prompts.users_id, \
+                   prompts.text, \
+                   prompts.created, \
+                   prompts.updated \
+            from prompts \
+            where prompts.users_id is null or \
+                  prompts.users_id = $1 \
+            order by prompts.users_id nulls first, \
+                     prompts.id \
+            offset $2 \
+            limit 1",
+            &[users_id, &offset]
+        )
+            .await
+            .map(|maybe| maybe.map(Self::map_row))
+    }
+
+    /// creates a new prompt for the specified [`UserId`]
+    pub async fn create(conn: &impl GenericClient, users_id: &UserId, text: String) -> Result<Self, PgError> {
+        let created = Utc::now();
+
+        let row = conn.query_one(
+            "\
+            insert into prompts (users_id, text, created) values \
+            ($1, $2, $3) \
+            returning id",
+            &[users_id, &text, &created]
+        ).await?;
+
+        Ok(Self {
+            id: row.get(0),
+            users_id: Some(*users_id),
+            text,
+            created,
+            updated: None,
+        })
+    }
+
+    /// changes the text of the prompt
+    pub async fn update_text(&mut self, conn: &impl GenericClient, text: String) -> Result<(), PgError> {
+        let updated = Utc::now();
+
+        conn.execute(
+            "update prompts set text = $2, updated = $3 where id = $1",
+            &[&self.id, &text, &updated]
+        ).await?;
+
+        self.text = text;
+        self.updated = Some(updated);
+
+        Ok(())
+    }
+
+    /// deletes the prompt. entries that were linked to it are kept
+    pub async fn delete(&self, conn: &impl GenericClient) -> Result<(), PgError> {
+        conn.execute(
+            "update entries set prompts_id = null where prompts_id = $1",
+            &[&self.id]
+        ).await?;
+
+        conn.execute(
+            "delete from prompts where id = $1",
+            &[&self.id]
+        ).await?;
+
+        Ok(())
+    }
+}
+
+/// adds the default prompts if the server does not have any prompts of its
+/// own
+pub async fn seed_defaults(conn: &impl GenericClient) -> Result<(), PgError> {
+    let found: bool = conn.query_one(
+        "select exists (select 1 from prompts where users_id is null)",
+        &[]
+    )
+        .await?
+        .get(0);
+
+    if found {
+        return Ok(());
+    }
+
+    let created = Utc::now();
+    let texts = DEFAULT_PROMPTS.as_slice();
+
+    conn.execute(
+        "\
+        insert into prompts (text, created) \
+        select unnest($1::varchar[]), $2",
+        &[&texts, &created]
+    ).await?;
+
+    Ok(())
+}
@@ -0,0 +1,517 @@
+use std::collections::BTreeMap;
+
+use bytes::BytesMut;
+use chrono::{NaiveDate, DateTime, Utc};
+use futures::{Stream, StreamExt};
+use postgres_types as pg_types;
+use serde::{Serialize, Deserialize};
+use similar::{DiffOp, TextDiff};
+
+use crate::db::{self, GenericClient, PgError};
+use crate::db::ids::{EntryId, JournalId, UserId, RevisionId, CustomFieldId};
+use crate::error::BoxDynError;
+use crate::journal::custom_field;
+use crate::journal::e2e::Ciphertext;
+
+/// a single tag stored in a snapshot
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SnapshotTag {
+    pub key: String,
+    pub value: Option<String>,
+}
+
+/// a single custom field value stored in a snapshot
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SnapshotCustomField {
+    pub custom_fields_id: CustomFieldId,
+    pub value: custom_field::Value,
+}
+
+/// the state of an entry at a given point in time
+///
+/// files are not included as their contents are not versioned
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub date: NaiveDate,
+    pub title: Option<String>,
+    pub contents: Option<String>,
+    pub tags: Vec<SnapshotTag>,
+    pub custom_fields: Vec<SnapshotCustomField>,
+
+    /// only present for entries in an end-to-end encrypted journal
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ciphertext: Option<Ciphertext>,
+}
+
+impl Snapshot {
+    /// captures the current state of the specified entry
+    pub async fn capture(conn: &impl GenericClient, entries_id: &EntryId) -> Result<Option<Self>, PgError> {
+        let Some(row) = conn.query_opt(
+            "\
+            select entries.entry_date, \
+                   entries.title, \
+                   entries.contents, \
+                   entries.ciphertext \
+            from entries \
+            where entries.id = $1",
+            &[entries_id]
+        ).await? else {
+            return Ok(None);
+        };
+
+        let tags = conn.query(
+            "\
+            select entry_tags.key, \
+                   entry_tags.value \
+            from entry_tags \
+            where entry_tags.entries_id = $1 \
+            order by entry_tags.key",
+            &[entries_id]
+        )
+            .await?
+            .into_iter()
+            .map(|row| SnapshotTag {
+                key: row.get(0),
+                value: row.get(1),
+            })
+            .collect();
+
+        let custom_fields = conn.query(
+            "\
+            select custom_field_entries.custom_fields_id, \
+                   custom_field_entries.value \
+            from custom_field_entries \
+            where custom_field_entries.entries_id = $1 \
+            order by custom_field_entries.custom_fields_id",
+            &[entries_id]
+        )
+            .await?
+            .into_iter()
+            .map(|row| SnapshotCustomField {
+                custom_fields_id: row.get(0),
+                value: row.get(1),
+            })
+            .collect();
+
+        Ok(Some(Self {
+            date: row.get(0),
+            title: row.get(1),
+            contents: row.get(2),
+            tags,
+            custom_fields,
+            ciphertext: row.get(3),
+        }))
+    }
+
+    /// overwrites the specified entry with the data from the snapshot
+    ///
+    /// custom fields that no longer exist in the journal will be skipped
+    pub async fn apply(
+        &self,
+        conn: &impl GenericClient,
+        journals_id: &JournalId,
+        entries_id: &EntryId,
+        updated: &DateTime<Utc>,
+    ) -> Result<(), PgError> {
+        let planned = super::is_planned_date(&self.date);
+        let word_count = super::entry_word_count(self.title.as_deref(), self.contents.as_deref());
+
+        conn.execute(
+            "\
+            update entries \
+            set entry_date = $2, \
+                title = $3, \
+                contents = $4, \
+                updated = $5, \
+                planned = $6, \
+                ciphertext = $7, \
+                word_count = $8 \
+            where id = $1",
+            &[entries_id, &self.date, &self.title, &self.contents, updated, &planned, &self.ciphertext, &word_count]
+        ).await?;
+
+        conn.execute(
+            "delete from entry_tags where entries_id = $1",
+            &[entries_id]
+        ).await?;
+
+        for tag in &self.tags {
+            conn.execute(
+                "\
+                insert into entry_tags (entries_id, key, value, created) values \
+                ($1, $2, $3, $4)",
+                &[entries_id, &tag.key, &tag.value, updated]
+            ).await?;
+        }
+
+        conn.execute(
+            "delete from custom_field_entries where entries_id = $1",
+            &[entries_id]
+        ).await?;
+
+        for field in &self.custom_fields {
+            conn.execute(
+                "\
+                insert into custom_field_entries (custom_fields_id, entries_id, value, created) \
+                select custom_fields.id, $2, $3, $4 \
+                from custom_fields \
+                where custom_fields.id = $1 and \
+                      custom_fields.journals_id = $5",
+                &[&field.custom_fields_id, entries_id, &field.value, updated, journals_id]
+            ).await?;
+        }
+
+        super::mention::sync_body(conn, journals_id, entries_id, self.contents.as_deref()).await?;
+
+        Ok(())
+    }
+
+    /// creates a list of the differences between this snapshot and the
+    /// given snapshot
+    pub fn diff(&self, to: &Snapshot) -> SnapshotDiff {
+        let date = (self.date != to.date).then(|| Change {
+            from: self.date,
+            to: to.date,
+        });
+        let title = (self.title != to.title).then(|| Change {
+            from: self.title.clone(),
+            to: to.title.clone(),
+        });
+        let contents = (self.contents != to.contents).then(|| Change {
+            from: self.contents.clone(),
+            to: to.contents.clone(),
+        });
+        let contents_lines = if contents.is_some() {
+            diff_lines(
+                self.contents.as_deref().unwrap_or(""),
+                to.contents.as_deref().unwrap_or("")
+            )
+        } else {
+            Vec::new()
+        };
+
+        let mut tags = Vec::new();
+        let mut to_tags: BTreeMap<&String, &Option<String>> = to.tags.iter()
+            .map(|tag| (&tag.key, &tag.value))
+            .collect();
+
+        for tag in &self.tags {
+            match to_tags.remove(&tag.key) {
+                Some(value) => if *value != tag.value {
+                    tags.push(TagChange::Changed {
+                        key: tag.key.clone(),
+                        from: tag.value.clone(),
+                        to: value.clone(),
+                    });
+                },
+                None => tags.push(TagChange::Removed {
+                    key: tag.key.clone(),
+                    value: tag.value.clone(),
+                }),
+            }
+        }
+
+        for (key, value) in to_tags {
+            tags.push(TagChange::Added {
+                key: key.clone(),
+                value: value.clone(),
+            });
+        }
+
+        let mut custom_fields = Vec::new();
+        let mut to_fields: BTreeMap<&CustomFieldId, &custom_field::Value> = to.custom_fields.iter()
+            .map(|field| (&field.custom_fields_id, &field.value))
+            .collect();
+
+        for field in &self.custom_fields {
+            match to_fields.remove(&field.custom_fields_id) {
+                Some(value) => if *value != field.value {
+                    custom_fields.push(CustomFieldChange::Changed {
+                        custom_fields_id: field.custom_fields_id,
+                        from: field.value.clone(),
+                        to: value.clone(),
+                    });
+                },
+                None => custom_fields.push(CustomFieldChange::Removed {
+                    custom_fields_id: field.custom_fields_id,
+                    value: field.value.clone(),
+                }),
+            }
+        }
+
+        for (custom_fields_id, value) in to_fields {
+            custom_fields.push(CustomFieldChange::Added {
+                custom_fields_id: *custom_fields_id,
+                value: value.clone(),
+            });
+        }
+
+        SnapshotDiff {
+            date,
+            title,
+            contents,
+            contents_lines,
+            tags,
+            custom_fields,
+        }
+    }
+}
+
+impl pg_types::ToSql for Snapshot {
+    fn to_sql(&self, ty: &pg_types::Type, w: &mut BytesMut) -> Result<pg_types::IsNull, BoxDynError> {
+        let wrapper: pg_types::Json<&Self> = pg_types::Json(self);
+
+        wrapper.to_sql(ty, w)
+    }
+
+    fn accepts(ty: &pg_types::Type) -> bool {
+        <pg_types::Json<Self> as pg_types::ToSql>::accepts(ty)
+    }
+
+    pg_types::to_sql_checked!();
+}
+
+impl<'a> pg_types::FromSql<'a> for Snapshot {
+    fn from_sql(ty: &pg_types::Type, raw: &'a [u8]) -> Result<Self, BoxDynError> {
+        let parsed: pg_types::Json<Self> = pg_types::Json::from_sql(ty, raw)?;
+
+        Ok(parsed.0)
+    }
+
+    fn accepts(ty: &pg_types::Type) -> bool {
+        <pg_types::Json<Self> as pg_types::FromSql>::accepts(ty)
+    }
+}
+
+/// a value that was changed between two snapshots
+#[derive(Debug, Serialize)]
+pub struct Change<T> {
+    pub from: T,
+    pub to: T,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "type")]
+pub enum TagChange {
+    Added {
+        key: String,
+        value: Option<String>,
+    },
+    Removed {
+        key: String,
+        value: Option<String>,
+    },
+    Changed {
+        key: String,
+        from: Option<String>,
+        to: Option<String>,
+    },
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "type")]
+pub enum CustomFieldChange {
+    Added {
+        custom_fields_id: CustomFieldId,
+        value: custom_field::Value,
+    },
+    Removed {
+        custom_fields_id: CustomFieldId,
+        value: custom_field::Value,
+    },
+    Changed {
+        custom_fields_id: CustomFieldId,
+        from: custom_field::Value,
+        to: custom_field::Value,
+    },
+}
+
+/// a run of lines from a line by line diff. line numbers start at 1
+#[derive(Debug, Serialize)]
+#[serde(tag = "type")]
+pub enum LineChange {
+    Equal {
+        from_line: usize,
+        to_line: usize,
+        lines: Vec<String>,
+    },
+    Removed {
+        from_line: usize,
+        lines: Vec<String>,
+    },
+    Added {
+        to_line: usize,
+        lines: Vec<String>,
+    },
+}
+
+/// creates a line by line diff of two strings
+///
+/// a replaced run of lines is given as the removed lines followed by the
+/// added lines
+fn diff_lines(from: &str, to: &str) -> Vec<LineChange> {
+    let diff = TextDiff::from_lines(from, to);
+    let from_lines = diff.old_slices();
+    let to_lines = diff.new_slices();
+    let collect = |slices: &[&str], start: usize, len: usize| -> Vec<String> {
+        slices[start..start + len].iter()
+            .map(|line| line.trim_end_matches(['\n', '\r']).to_owned())
+            .collect()
+    };
+
+    let mut rtn = Vec::new();
+
+    for op in diff.ops() {
+        match *op {
+            DiffOp::Equal { old_index, new_index, len } => rtn.push(LineChange::Equal {
+                from_line: old_index + 1,
+                to_line: new_index + 1,
+                lines: collect(from_lines, old_index, len),
+            }),
+            DiffOp::Delete { old_index, old_len, .. } => rtn.push(LineChange::Removed {
+                from_line: old_index + 1,
+                lines: collect(from_lines, old_index, old_len),
+            }),
+            DiffOp::Insert { new_index, new_len, .. } => rtn.push(LineChange::Added {
+                to_line: new_index + 1,
+                lines: collect(to_lines, new_index, new_len),
+            }),
+            DiffOp::Replace { old_index, old_len, new_index, new_len } => {
+                rtn.push(LineChange::Removed {
+                    from_line: old_index + 1,
+                    lines: collect(from_lines, old_index, old_len),
+                });
+                rtn.push(LineChange::Added {
+                    to_line: new_index + 1,
+                    lines: collect(to_lines, new_index, new_len),
+                });
+            }
+        }
+    }
+
+    rtn
+}
+
+/// the differences between two snapshots
+///
+/// fields that did not change are left out
+#[derive(Debug, Serialize)]
+pub struct SnapshotDiff {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub date: Option<Change<NaiveDate>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub title: Option<Change<Option<String>>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub contents: Option<Change<Option<String>>>,
+    /// the line by line changes to the contents. empty if the contents did
+    /// not change
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub contents_lines: Vec<LineChange>,
+    pub tags: Vec<TagChange>,
+    pub custom_fields: Vec<CustomFieldChange>,
+}
+
+/// a recorded snapshot of an entry before it was changed
+#[derive(Debug, Serialize)]
+pub struct Revision {
+    pub id: RevisionId,
+    pub entries_id: EntryId,
+    pub users_id: UserId,
+    pub revision: i64,
+    pub snapshot: Snapshot,
+    pub created: DateTime<Utc>,
+}
+
+impl Revision {
+    fn map_row(row: tokio_postgres::Row) -> Self {
+        Self {
+            id: row.get(0),
+            entries_id: row.get(1),
+            users_id: row.get(2),
+            revision: row.get(3),
+            snapshot: row.get(4),
+            created: row.get(5),
+        }
+    }
+
+    /// records the current state of an entry as a new revision
+    ///
+    /// returns None if the entry does not exist
+    pub async fn record(
+        conn: &impl GenericClient,
+        entries_id: &EntryId,
+        users_id: &UserId,
+    ) -> Result<Option<Self>, PgError> {
+        let Some(snapshot) = Snapshot::capture(conn, entries_id).await? else {
+            return Ok(None);
+        };
+
+        let created = Utc::now();
+
+        let row = conn.query_one(
+            "\
+            insert into entry_revisions (entries_id, users_id, revision, snapshot, created) \
+            select $1, $2, coalesce(max(entry_revisions.revision), 0) + 1, $3, $4 \
+            from entry_revisions \
+            where entry_revisions.entries_id = $1 \
+            returning id, revision",
+            &[entries_id, users_id, &snapshot, &created]
+        ).await?;
+
+        Ok(Some(Self {
+            id: row.get(0),
+            entries_id: *entries_id,
+            users_id: *users_id,
+            revision: row.get(1),
+            snapshot,
+            created,
+        }))
+    }
+
+    /// retrieves the specified revision of an entry
+    pub async fn retrieve(
+        conn: &impl GenericClient,
+        entries_id: &EntryId,
+        revision: &i64,
+    ) -> Result<Option<Self>, PgError> {
+        conn.query_opt(
+            "\
+            select entry_revisions.id, \
+                   entry_revisions.entries_id, \
+                   entry_revisions.users_id, \
+                   entry_revisions.revision, \
+                   entry_revisions.snapshot, \
+                   entry_revisions.created \
+            from entry_revisions \
+            where entry_revisions.entries_id = $1 and \
+                  entry_revisions.revision = $2",
+            &[entries_id, revision]
+        )
+            .await
+            .map(|maybe| maybe.map(Self::map_row))
+    }
+
+    /// retrieves all revisions for an entry with the newest first
+    pub async fn retrieve_entry_stream(
+        conn: &impl GenericClient,
+        entries_id: &EntryId,
+    ) -> Result<impl Stream<Item = Result<Self, PgError>>, PgError> {
+        let params: db::ParamsArray<'_, 1> = [entries_id];
+
+        Ok(conn.query_raw(
+            "\
+            select entry_revisions.id, \
+                   entry_revisions.entries_id, \
+                   entry_revisions.users_id, \
+                   entry_revisions.revision, \
+                   entry_revisions.snapshot, \
+                   entry_revisions.created \
+            from entry_revisions \
+            where entry_revisions.entries_id = $1 \
+            order by entry_revisions.revision desc",
+            params
+        )
+            .await?
+            .map(|result| result.map(Self::map_row)))
+    }
+}
@@ -0,0 +1,284 @@
+//! sharing journals with other users
+//!
+//! each user that a journal is shared with is given a role that only holds
+//! permissions for that journal. the permissions reference the journal with
+//! their ref_id so they are checked with [`has_permission_ref`] and do not
+//! grant anything for other journals
+//!
+//! [`has_permission_ref`]: crate::sec::authz::has_permission_ref
+
+use std::collections::HashMap;
+use std::fmt::Write;
+
+use chrono::{DateTime, Utc};
+use serde::{Serialize, Deserialize};
+
+use crate::db::{self, GenericClient, PgError};
+use crate::db::ids::{JournalId, RoleId, RoleUid, UserId};
+use crate::sec::authz::{self, Scope, Ability};
+
+use super::Journal;
+
+/// a single ability that a shared user has for a journal
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct SharePermission {
+    pub scope: Scope,
+    pub ability: Ability,
+}
+
+impl SharePermission {
+    const fn new(scope: Scope, ability: Ability) -> Self {
+        SharePermission { scope, ability }
+    }
+
+    /// only the entries of a journal and reading or updating the journal
+    /// itself can be shared. deleting the journal stays with the owner
+    pub fn is_shareable(&self) -> bool {
+        match self.scope {
+            Scope::Entries => true,
+            Scope::Journals => matches!(self.ability, Ability::Read | Ability::Update),
+            _ => false,
+        }
+    }
+}
+
+/// common sets of permissions for a shared user
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Preset {
+    /// can read the journal and its entries
+    ReadOnly,
+
+    /// can also create and update entries
+    Contributor,
+
+    /// can also delete entries and update the journal
+    Manager,
+}
+
+impl Preset {
+    pub fn permissions(&self) -> Vec<SharePermission> {
+        let mut rtn = vec![
+            SharePermission::new(Scope::Journals, Ability::Read),
+            SharePermission::new(Scope::Entries, Ability::Read),
+        ];
+
+        if matches!(self, Preset::Contributor | Preset::Manager) {
+            rtn.push(SharePermission::new(Scope::Entries, Ability::Create));
+            rtn.push(SharePermission::new(Scope::Entries, Ability::Update));
+        }
+
+        if matches!(self, Preset::Manager) {
+            rtn.push(SharePermission::new(Scope::Entries, Ability::Delete));
+            rtn.push(SharePermission::new(Scope::Journals, Ability::Update));
+        }
+
+        rtn
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct JournalShare {
+    pub journals_id: JournalId,
+    pub users_id: UserId,
+    pub username: String,
+
+    #[serde(skip)]
+    pub role_id: RoleId,
+    pub permissions: Vec<SharePermission>,
+    pub created: DateTime<Utc>,
+    pub updated: Option<DateTime<Utc>>,
+}
+
+impl JournalShare {
+    /// retrieves every user that the journal is shared with ordered by
+    /// username
+    pub async fn retrieve_journal(conn: &impl GenericClient, journals_id: &JournalId) -> Result<Vec<Self>, PgError> {
+        let rows = conn.query(
+            "\
+            select journal_shares.journals_id, \
+                   journal_shares.users_id, \
+                   users.username, \
+                   journal_shares.role_id, \
+                   journal_shares.created, \
+                   journal_shares.updated \
+            from journal_shares \
+                join users on \
+                    journal_shares.users_id = users.id \
+            where journal_shares.journals_id = $1 \
+            order by users.username",
+            &[journals_id]
+        ).await?;
+
+        let mut rtn: Vec<Self> = rows.into_iter()
+            .map(|row| Self {
+                journals_id: row.get(0),
+                users_id: row.get(1),
+                username: row.get(2),
+                role_id: row.get(3),
+                permissions: Vec::new(),
+                created: row.get(4),
+                updated: row.get(5),
+            })
+            .collect();
+
+        let mut permissions = retrieve_permissions(conn, journals_id).await?;
+
+        for share in &mut rtn {
+            share.permissions = permissions.remove(&share.role_id)
+                .unwrap_or_default();
+        }
+
+        Ok(rtn)
+    }
+
+    pub async fn retrieve(conn: &impl GenericClient, journals_id: &JournalId, users_id: &UserId) -> Result<Option<Self>, PgError> {
+        let shares = Self::retrieve_journal(conn, journals_id).await?;
+
+        Ok(shares.into_iter().find(|share| share.users_id == *users_id))
+    }
+
+    /// shares the journal with the user, replacing the permissions the user
+    /// already had for the journal
+    pub async fn set(
+        conn: &impl GenericClient,
+        journal: &Journal,
+        users_id: &UserId,
+        permissions: Vec<SharePermission>,
+    ) -> Result<(), PgError> {
+        let now = Utc::now();
+        let found = conn.query_opt(
+            "\
+            update journal_shares \
+            set updated = $3 \
+            where journals_id = $1 and \
+                  users_id = $2 \
+            returning role_id",
+            &[&journal.id, users_id, &now]
+        ).await?;
+
+        let role_id: RoleId = match found {
+            Some(row) => {
+                let role_id = row.get(0);
+
+                conn.execute(
+                    "delete from authz_permissions where role_id = $1",
+                    &[&role_id]
+                ).await?;
+
+                role_id
+            }
+            None => {
+                let uid = RoleUid::gen();
+                let name = format!("journal_share:{}:{}", journal.uid, users_id);
+
+                let row = conn.query_one(
+                    "\
+                    insert into authz_roles (uid, name, created) values \
+                    ($1, $2, $3) \
+                    returning id",
+                    &[&uid, &name, &now]
+                ).await?;
+                let role_id = row.get(0);
+
+                authz::assign_user_role(conn, role_id, *users_id).await?;
+
+                conn.execute(
+                    "\
+                    insert into journal_shares (journals_id, users_id, role_id, created) values \
+                    ($1, $2, $3, $4)",
+                    &[&journal.id, users_id, &role_id, &now]
+                ).await?;
+
+                role_id
+            }
+        };
+
+        if permissions.is_empty() {
+            return Ok(());
+        }
+
+        let mut params: db::ParamsVec<'_> = vec![&role_id, &journal.id, &now];
+        let mut query = String::from(
+            "insert into authz_permissions (role_id, ref_id, scope, ability, added) values "
+        );
+
+        for (index, permission) in permissions.iter().enumerate() {
+            if index > 0 {
+                query.push_str(", ");
+            }
+
+            write!(
+                &mut query,
+                "($1, $2, ${}, ${}, $3)",
+                db::push_param(&mut params, &permission.scope),
+                db::push_param(&mut params, &permission.ability),
+            ).unwrap();
+        }
+
+        conn.execute(query.as_str(), &params).await?;
+
+        Ok(())
+    }
+
+    /// stops sharing the journal with the user and removes the role that
+    /// held their permissions
+    pub async fn delete(&self, conn: &impl GenericClient) -> Result<(), PgError> {
+        conn.execute(
+            "delete from authz_permissions where role_id = $1",
+            &[&self.role_id]
+        ).await?;
+
+        conn.execute(
+            "delete from user_roles where role_id = $1",
+            &[&self.role_id]
+        ).await?;
+
+        conn.execute(
+            "delete from journal_shares where journals_id = $1 and users_id = $2",
+            &[&self.journals_id, &self.users_id]
+        ).await?;
+
+        conn.execute(
+            "delete from authz_roles where id = $1",
+            &[&self.role_id]
+        ).await?;
+
+        Ok(())
+    }
+}
+
+/// retrieves the permissions of every share role for the journal grouped by
+/// role
+async fn retrieve_permissions(
+    conn: &impl GenericClient,
+    journals_id: &JournalId,
+) -> Result<HashMap<RoleId, Vec<SharePermission>>, PgError> {
+    let rows = conn.query(
+        "\
+        select authz_permissions.role_id, \
+               authz_permissions.scope, \
+               authz_permissions.ability \
+        from authz_permissions \
+            join journal_shares on \
+                authz_permissions.role_id = journal_shares.role_id \
+        where journal_shares.journals_id = $1 and \
+              authz_permissions.ref_id = $1 \
+        order by authz_permissions.scope, \
+                 authz_permissions.ability",
+        &[journals_id]
+    ).await?;
+
+    let mut rtn: HashMap<RoleId, Vec<SharePermission>> = HashMap::new();
+
+    for row in rows {
+        rtn.entry(row.get(0))
+            .or_default()
+            .push(SharePermission {
+                scope: row.get(1),
+                ability: row.get(2),
+            });
+    }
+
+    Ok(rtn)
+}
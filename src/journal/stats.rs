@@ -0,0 +1,467 @@
+//! aggregate statistics for the entries of a journal
+//!
+//! planned entries are not counted since they have not been written yet
+
+use chrono::{Days, NaiveDate};
+use futures::StreamExt;
+use serde::{Serialize, Deserialize};
+
+use crate::db::{self, GenericClient, PgError};
+use crate::db::ids::{JournalId, CustomFieldId};
+use crate::journal::WeekStart;
+use crate::journal::custom_field::Type;
+use crate::journal::custom_field::unit::{Unit, UnitSystem};
+
+/// the range of entry dates to compute statistics for. both ends are
+/// inclusive and None is unbounded
+#[derive(Debug, Clone, Copy)]
+pub struct DateRange {
+    pub from: Option<NaiveDate>,
+    pub to: Option<NaiveDate>,
+}
+
+/// the number of entries and words written in a month
+#[derive(Debug, Serialize)]
+pub struct MonthCount {
+    /// the first day of the month
+    pub month: NaiveDate,
+    pub count: i64,
+
+    /// the total words of the entries in the month
+    pub words: i64,
+}
+
+impl MonthCount {
+    pub async fn retrieve(
+        conn: &impl GenericClient,
+        journals_id: &JournalId,
+        range: &DateRange,
+    ) -> Result<Vec<Self>, PgError> {
+        let params: db::ParamsArray<'_, 3> = [journals_id, &range.from, &range.to];
+        let stream = conn.query_raw(
+            "\
+            select date_trunc('month', entries.entry_date::timestamp)::date as month, \
+                   count(*), \
+                   coalesce(sum(entries.word_count), 0)::bigint \
+            from entries \
+            where entries.journals_id = $1 and \
+                  ($2::date is null or entries.entry_date >= $2) and \
+                  ($3::date is null or entries.entry_date <= $3) and \
+                  not entries.planned \
+            group by month \
+            order by month",
+            params
+        ).await?;
+
+        futures::pin_mut!(stream);
+
+        let mut rtn = Vec::new();
+
+        while let Some(try_row) = stream.next().await {
+            let row = try_row?;
+
+            rtn.push(Self {
+                month: row.get(0),
+                count: row.get(1),
+                words: row.get(2),
+            });
+        }
+
+        Ok(rtn)
+    }
+}
+
+/// the number of entries that have a tag
+#[derive(Debug, Serialize)]
+pub struct TagCount {
+    pub key: String,
+    pub count: i64,
+}
+
+impl TagCount {
+    /// retrieves the tags ordered from most to least used
+    pub async fn retrieve(
+        conn: &impl GenericClient,
+        journals_id: &JournalId,
+        range: &DateRange,
+    ) -> Result<Vec<Self>, PgError> {
+        let params: db::ParamsArray<'_, 3> = [journals_id, &range.from, &range.to];
+        let stream = conn.query_raw(
+            "\
+            select entry_tags.key, \
+                   count(*) as total \
+            from entry_tags \
+                join entries on \
+                    entry_tags.entries_id = entries.id \
+            where entries.journals_id = $1 and \
+                  ($2::date is null or entries.entry_date >= $2) and \
+                  ($3::date is null or entries.entry_date <= $3) and \
+                  not entries.planned \
+            group by entry_tags.key \
+            order by total desc, \
+                     entry_tags.key",
+            params
+        ).await?;
+
+        futures::pin_mut!(stream);
+
+        let mut rtn = Vec::new();
+
+        while let Some(try_row) = stream.next().await {
+            let row = try_row?;
+
+            rtn.push(Self {
+                key: row.get(0),
+                count: row.get(1),
+            });
+        }
+
+        Ok(rtn)
+    }
+}
+
+/// a run of consecutive days that have an entry
+#[derive(Debug, Clone, Serialize)]
+pub struct Streak {
+    pub start: NaiveDate,
+    pub end: NaiveDate,
+    pub days: u64,
+}
+
+/// the current and longest streaks of a journal
+#[derive(Debug, Serialize)]
+pub struct Streaks {
+    /// the streak that ends on the given day or the day before it. a streak
+    /// is not broken until a full day has passed without an entry
+    pub current: Option<Streak>,
+    pub longest: Option<Streak>,
+}
+
+impl Streaks {
+    /// computes the streaks using the given day as "today"
+    pub async fn retrieve(
+        conn: &impl GenericClient,
+        journals_id: &JournalId,
+        range: &DateRange,
+        today: &NaiveDate,
+    ) -> Result<Self, PgError> {
+        let params: db::ParamsArray<'_, 3> = [journals_id, &range.from, &range.to];
+        let stream = conn.query_raw(
+            "\
+            select distinct entries.entry_date \
+            from entries \
+            where entries.journals_id = $1 and \
+                  ($2::date is null or entries.entry_date >= $2) and \
+                  ($3::date is null or entries.entry_date <= $3) and \
+                  not entries.planned \
+            order by entries.entry_date",
+            params
+        ).await?;
+
+        futures::pin_mut!(stream);
+
+        let mut dates = Vec::new();
+
+        while let Some(try_row) = stream.next().await {
+            dates.push(try_row?.get(0));
+        }
+
+        Ok(Self::from_dates(&dates, today))
+    }
+
+    /// computes the streaks from a sorted list of unique dates
+    pub fn from_dates(dates: &[NaiveDate], today: &NaiveDate) -> Self {
+        let mut longest: Option<Streak> = None;
+        let mut working: Option<Streak> = None;
+
+        for date in dates {
+            working = match working {
+                Some(mut streak) if streak.end.checked_add_days(Days::new(1)) == Some(*date) => {
+                    streak.end = *date;
+                    streak.days += 1;
+
+                    Some(streak)
+                }
+                _ => Some(Streak {
+                    start: *date,
+                    end: *date,
+                    days: 1,
+                }),
+            };
+
+            if let Some(streak) = &working {
+                if longest.as_ref().map_or(true, |long| streak.days > long.days) {
+                    longest = Some(streak.clone());
+                }
+            }
+        }
+
+        let yesterday = today.checked_sub_days(Days::new(1));
+        let current = working.filter(|streak| {
+            streak.end == *today || Some(streak.end) == yesterday
+        });
+
+        Self {
+            current,
+            longest,
+        }
+    }
+}
+
+/// the aggregate of a numeric custom field
+///
+/// range values are measured by the distance between the low and high
+#[derive(Debug, Serialize)]
+pub struct FieldStats {
+    pub custom_fields_id: CustomFieldId,
+    pub name: String,
+    pub count: i64,
+    pub minimum: f64,
+    pub maximum: f64,
+    pub average: f64,
+
+    /// the unit of the values if the field has one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub unit: Option<Unit>,
+
+    /// the values are distances between the low and high of a range
+    #[serde(skip)]
+    pub range: bool,
+}
+
+impl FieldStats {
+    pub async fn retrieve(
+        conn: &impl GenericClient,
+        journals_id: &JournalId,
+        range: &DateRange,
+    ) -> Result<Vec<Self>, PgError> {
+        let params: db::ParamsArray<'_, 3> = [journals_id, &range.from, &range.to];
+        let stream = conn.query_raw(
+            "\
+            with numeric_values as ( \
+                select custom_field_entries.custom_fields_id, \
+                       case custom_field_entries.value ->> 'type' \
+                           when 'Integer' then (custom_field_entries.value ->> 'value')::float8 \
+                           when 'Float' then (custom_field_entries.value ->> 'value')::float8 \
+                           else (custom_field_entries.value ->> 'high')::float8 - \
+                                (custom_field_entries.value ->> 'low')::float8 \
+                       end as value \
+                from custom_field_entries \
+                    join entries on \
+                        custom_field_entries.entries_id = entries.id \
+                where entries.journals_id = $1 and \
+                      ($2::date is null or entries.entry_date >= $2) and \
+                      ($3::date is null or entries.entry_date <= $3) and \
+                      not entries.planned and \
+                      custom_field_entries.value ->> 'type' in ('Integer', 'IntegerRange', 'Float', 'FloatRange') \
+            ) \
+            select custom_fields.id, \
+                   custom_fields.name, \
+                   count(*), \
+                   min(numeric_values.value), \
+                   max(numeric_values.value), \
+                   avg(numeric_values.value), \
+                   custom_fields.config \
+            from numeric_values \
+                join custom_fields on \
+                    numeric_values.custom_fields_id = custom_fields.id \
+            group by custom_fields.id, \
+                     custom_fields.name, \
+                     custom_fields.\"order\" \
+            order by custom_fields.\"order\" desc, \
+                     custom_fields.name",
+            params
+        ).await?;
+
+        futures::pin_mut!(stream);
+
+        let mut rtn = Vec::new();
+
+        while let Some(try_row) = stream.next().await {
+            let row = try_row?;
+            let config: Type = row.get(6);
+
+            rtn.push(Self {
+                custom_fields_id: row.get(0),
+                name: row.get(1),
+                count: row.get(2),
+                minimum: row.get(3),
+                maximum: row.get(4),
+                average: row.get(5),
+                unit: config.unit(),
+                range: config.is_range(),
+            });
+        }
+
+        Ok(rtn)
+    }
+
+    /// converts the values to the equivalent unit in the measurement system.
+    /// fields without a unit are left as is
+    pub fn convert_units(&mut self, system: UnitSystem) {
+        let Some(from) = self.unit else {
+            return;
+        };
+
+        let to = from.in_system(system);
+
+        let convert = |value: f64| if self.range {
+            from.convert_difference(value, to)
+        } else {
+            from.convert(value, to)
+        };
+
+        if let (Some(minimum), Some(maximum), Some(average)) = (
+            convert(self.minimum),
+            convert(self.maximum),
+            convert(self.average),
+        ) {
+            self.minimum = minimum;
+            self.maximum = maximum;
+            self.average = average;
+            self.unit = Some(to);
+        }
+    }
+}
+
+/// the size of the buckets that values are grouped into for a trend
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Bucket {
+    #[default]
+    Daily,
+    Weekly,
+    Monthly,
+}
+
+impl Bucket {
+    /// the field name used by postgres date_trunc
+    pub fn as_trunc(&self) -> &'static str {
+        match self {
+            Bucket::Daily => "day",
+            Bucket::Weekly => "week",
+            Bucket::Monthly => "month",
+        }
+    }
+}
+
+/// the aggregate of a numeric custom field for a single bucket
+///
+/// range values are measured by the distance between the low and high with
+/// the average low and high included so that a band can be drawn
+#[derive(Debug, Serialize)]
+pub struct TrendPoint {
+    /// the first day of the bucket. weeks start on the week start of the
+    /// journal
+    pub start: NaiveDate,
+    pub count: i64,
+    pub minimum: f64,
+    pub maximum: f64,
+    pub average: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub average_low: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub average_high: Option<f64>,
+}
+
+impl TrendPoint {
+    /// converts the values of the point from the unit to the equivalent unit
+    /// in the measurement system. the average of a range is converted as a
+    /// difference
+    pub fn convert_units(&mut self, from: Unit, system: UnitSystem, range: bool) {
+        let to = from.in_system(system);
+
+        // the units are always of the same dimension so the conversion will
+        // not fail
+        let convert = |value: f64| from.convert(value, to).unwrap_or(value);
+
+        self.minimum = convert(self.minimum);
+        self.maximum = convert(self.maximum);
+        self.average = if range {
+            from.convert_difference(self.average, to).unwrap_or(self.average)
+        } else {
+            convert(self.average)
+        };
+        self.average_low = self.average_low.map(convert);
+        self.average_high = self.average_high.map(convert);
+    }
+
+    /// retrieves the bucketed values of a custom field ordered by date
+    ///
+    /// buckets without any values are not included
+    pub async fn retrieve(
+        conn: &impl GenericClient,
+        journals_id: &JournalId,
+        custom_fields_id: &CustomFieldId,
+        range: &DateRange,
+        bucket: Bucket,
+        week_start: WeekStart,
+    ) -> Result<Vec<Self>, PgError> {
+        let trunc = bucket.as_trunc();
+        let offset = match bucket {
+            Bucket::Weekly => week_start.monday_offset(),
+            Bucket::Daily | Bucket::Monthly => 0,
+        };
+        let params: db::ParamsArray<'_, 6> = [
+            journals_id,
+            custom_fields_id,
+            &range.from,
+            &range.to,
+            &trunc,
+            &offset,
+        ];
+        let stream = conn.query_raw(
+            "\
+            with field_values as ( \
+                select entries.entry_date, \
+                       case custom_field_entries.value ->> 'type' \
+                           when 'Integer' then (custom_field_entries.value ->> 'value')::float8 \
+                           when 'Float' then (custom_field_entries.value ->> 'value')::float8 \
+                           else null \
+                       end as value, \
+                       (custom_field_entries.value ->> 'low')::float8 as low, \
+                       (custom_field_entries.value ->> 'high')::float8 as high \
+                from custom_field_entries \
+                    join entries on \
+                        custom_field_entries.entries_id = entries.id \
+                where entries.journals_id = $1 and \
+                      custom_field_entries.custom_fields_id = $2 and \
+                      ($3::date is null or entries.entry_date >= $3) and \
+                      ($4::date is null or entries.entry_date <= $4) and \
+                      not entries.planned and \
+                      custom_field_entries.value ->> 'type' in ('Integer', 'IntegerRange', 'Float', 'FloatRange') \
+            ) \
+            select date_trunc($5, (field_values.entry_date + $6::integer)::timestamp)::date - $6::integer as bucket, \
+                   count(*), \
+                   min(coalesce(field_values.value, field_values.low)), \
+                   max(coalesce(field_values.value, field_values.high)), \
+                   avg(coalesce(field_values.value, field_values.high - field_values.low)), \
+                   avg(field_values.low), \
+                   avg(field_values.high) \
+            from field_values \
+            group by bucket \
+            order by bucket",
+            params
+        ).await?;
+
+        futures::pin_mut!(stream);
+
+        let mut rtn = Vec::new();
+
+        while let Some(try_row) = stream.next().await {
+            let row = try_row?;
+
+            rtn.push(Self {
+                start: row.get(0),
+                count: row.get(1),
+                minimum: row.get(2),
+                maximum: row.get(3),
+                average: row.get(4),
+                average_low: row.get(5),
+                average_high: row.get(6),
+            });
+        }
+
+        Ok(rtn)
+    }
+}
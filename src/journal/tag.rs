@@ -0,0 +1,119 @@
+//! hierarchical entry tags
+//!
+//! tag keys can be split into namespaces with "/". ex: "health/sleep" and
+//! "health/diet" are both under "health". searching for a tag key will match
+//! the key itself and any key under it
+
+use futures::StreamExt;
+use serde::Serialize;
+
+use crate::db::{self, GenericClient, PgError};
+use crate::db::ids::JournalId;
+
+/// the separator between the segments of a tag key
+pub const SEPARATOR: char = '/';
+
+/// normalizes a tag key by trimming the whitespace around each segment
+///
+/// returns None if the key or any of its segments are empty. ex: "health//"
+/// or "/sleep"
+pub fn normalize_key(given: &str) -> Option<String> {
+    let mut rtn = String::with_capacity(given.len());
+
+    for segment in given.split(SEPARATOR) {
+        let trimmed = segment.trim();
+
+        if trimmed.is_empty() {
+            return None;
+        }
+
+        if !rtn.is_empty() {
+            rtn.push(SEPARATOR);
+        }
+
+        rtn.push_str(trimmed);
+    }
+
+    Some(rtn)
+}
+
+/// the sql condition that matches a tag key or any key under it. the
+/// parameter is expected to be a normalized key
+pub fn key_filter(column: &str, param: usize) -> String {
+    format!("({column} = ${param} or starts_with({column}, ${param} || '{SEPARATOR}'))")
+}
+
+/// a segment of the tag hierarchy for a journal
+#[derive(Debug, Serialize)]
+pub struct TagNode {
+    /// the last segment of the key
+    pub name: String,
+
+    /// the full key of the node
+    pub key: String,
+
+    /// the number of entries that have the key or a key under it
+    pub entries: i64,
+    pub children: Vec<TagNode>,
+}
+
+impl TagNode {
+    /// retrieves the tag hierarchy of a journal ordered by key
+    ///
+    /// every parent of a key is included even if no entry is tagged with it
+    /// directly
+    pub async fn retrieve_journal(conn: &impl GenericClient, journals_id: &JournalId) -> Result<Vec<Self>, PgError> {
+        let params: db::ParamsArray<'_, 1> = [journals_id];
+        let stream = conn.query_raw(
+            "\
+            select tag_prefixes.key, \
+                   count(distinct tag_prefixes.entries_id) as entries \
+            from ( \
+                select entry_tags.entries_id, \
+                       array_to_string(segments.list[1:depth], '/') as key \
+                from entry_tags \
+                    join entries on \
+                        entry_tags.entries_id = entries.id \
+                    cross join lateral ( \
+                        select string_to_array(entry_tags.key, '/') as list \
+                    ) as segments \
+                    cross join lateral generate_series(1, cardinality(segments.list)) as depth \
+                where entries.journals_id = $1 \
+            ) as tag_prefixes \
+            group by tag_prefixes.key \
+            order by tag_prefixes.key collate \"C\"",
+            params
+        ).await?;
+
+        futures::pin_mut!(stream);
+
+        let mut rtn = Vec::new();
+
+        while let Some(try_row) = stream.next().await {
+            let row = try_row?;
+            let key: String = row.get(0);
+
+            insert(&mut rtn, &key, &key, row.get(1));
+        }
+
+        Ok(rtn)
+    }
+}
+
+/// places a key into the hierarchy. parents are always ordered before their
+/// children so they will already exist
+fn insert(nodes: &mut Vec<TagNode>, key: &str, remaining: &str, entries: i64) {
+    match remaining.split_once(SEPARATOR) {
+        Some((parent, rest)) => {
+            if let Some(found) = nodes.iter_mut().rev().find(|node| node.name == parent) {
+                insert(&mut found.children, key, rest, entries);
+            }
+        }
+        None => nodes.push(TagNode {
+            name: remaining.to_owned(),
+            key: key.to_owned(),
+            entries,
+            children: Vec::new(),
+        }),
+    }
+}
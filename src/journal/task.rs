@@ -0,0 +1,195 @@
+//! checklist items that are attached to an entry
+
+use std::collections::HashMap;
+
+use chrono::{DateTime, Utc};
+use serde::{Serialize, Deserialize};
+
+use crate::db::{GenericClient, PgError};
+use crate::db::ids::{EntryId, EntryTaskId};
+
+/// a single checklist item of an entry
+#[derive(Debug, Clone, Serialize)]
+pub struct EntryTask {
+    pub id: EntryTaskId,
+    pub position: i32,
+    pub text: String,
+    pub done: bool,
+    pub created: DateTime<Utc>,
+    pub updated: Option<DateTime<Utc>>,
+}
+
+/// a task provided by a client. tasks without an id are created
+#[derive(Debug, Deserialize)]
+pub struct TaskInput {
+    pub id: Option<EntryTaskId>,
+    pub text: String,
+    #[serde(default)]
+    pub done: bool,
+}
+
+/// the result of replacing the tasks of an entry
+#[derive(Debug)]
+pub struct TasksUpsert {
+    /// the tasks of the entry in order
+    pub valid: Vec<EntryTask>,
+
+    /// ids that were given but are not attached to the entry
+    pub not_found: Vec<EntryTaskId>,
+}
+
+impl EntryTask {
+    fn map_row(row: tokio_postgres::Row) -> Self {
+        Self {
+            id: row.get(0),
+            position: row.get(1),
+            text: row.get(2),
+            done: row.get(3),
+            created: row.get(4),
+            updated: row.get(5),
+        }
+    }
+
+    /// retrieves the tasks of an entry in order
+    pub async fn retrieve_entry(conn: &impl GenericClient, entries_id: &EntryId) -> Result<Vec<Self>, PgError> {
+        let rows = conn.query(
+            "\
+            select entry_tasks.id, \
+                   entry_tasks.position, \
+                   entry_tasks.text, \
+                   entry_tasks.done, \
+                   entry_tasks.created, \
+                   entry_tasks.updated \
+            from entry_tasks \
+            where entry_tasks.entries_id = $1 \
+            order by entry_tasks.position",
+            &[entries_id]
+        ).await?;
+
+        Ok(rows.into_iter().map(Self::map_row).collect())
+    }
+
+    /// replaces the tasks of an entry with the given list
+    ///
+    /// the position of a task is its index in the list. existing tasks that
+    /// are not in the list are deleted and tasks with empty text are skipped
+    pub async fn upsert_entry(
+        conn: &impl GenericClient,
+        entries_id: &EntryId,
+        given: Vec<TaskInput>,
+        now: &DateTime<Utc>,
+    ) -> Result<TasksUpsert, PgError> {
+        let mut current: HashMap<EntryTaskId, EntryTask> = Self::retrieve_entry(conn, entries_id)
+            .await?
+            .into_iter()
+            .map(|task| (task.id, task))
+            .collect();
+        let mut valid = Vec::with_capacity(given.len());
+        let mut not_found = Vec::new();
+        let mut position: i32 = 0;
+
+        for input in given {
+            let text = input.text.trim();
+
+            if text.is_empty() {
+                continue;
+            }
+
+            if let Some(id) = input.id {
+                let Some(mut task) = current.remove(&id) else {
+                    not_found.push(id);
+
+                    continue;
+                };
+
+                if task.text != text || task.done != input.done || task.position != position {
+                    task.text = text.to_owned();
+                    task.done = input.done;
+                    task.position = position;
+                    task.updated = Some(*now);
+
+                    conn.execute(
+                        "\
+                        update entry_tasks \
+                        set position = $3, \
+                            text = $4, \
+                            done = $5, \
+                            updated = $6 \
+                        where entries_id = $1 and \
+                              id = $2",
+                        &[entries_id, &task.id, &task.position, &task.text, &task.done, &task.updated]
+                    ).await?;
+                }
+
+                valid.push(task);
+            } else {
+                let row = conn.query_one(
+                    "\
+                    insert into entry_tasks (entries_id, position, text, done, created) values \
+                    ($1, $2, $3, $4, $5) \
+                    returning id",
+                    &[entries_id, &position, &text, &input.done, now]
+                ).await?;
+
+                valid.push(EntryTask {
+                    id: row.get(0),
+                    position,
+                    text: text.to_owned(),
+                    done: input.done,
+                    created: *now,
+                    updated: None,
+                });
+            }
+
+            position += 1;
+        }
+
+        if !current.is_empty() {
+            let ids: Vec<EntryTaskId> = current.into_keys().collect();
+
+            conn.execute(
+                "delete from entry_tasks where entries_id = $1 and id = any($2)",
+                &[entries_id, &ids]
+            ).await?;
+        }
+
+        Ok(TasksUpsert {
+            valid,
+            not_found,
+        })
+    }
+
+    /// flips the done state of a single task
+    pub async fn toggle(
+        conn: &impl GenericClient,
+        entries_id: &EntryId,
+        id: &EntryTaskId,
+        now: &DateTime<Utc>,
+    ) -> Result<Option<Self>, PgError> {
+        conn.query_opt(
+            "\
+            update entry_tasks \
+            set done = not done, \
+                updated = $3 \
+            where entries_id = $1 and \
+                  id = $2 \
+            returning id, \
+                      position, \
+                      text, \
+                      done, \
+                      created, \
+                      updated",
+            &[entries_id, id, now]
+        )
+            .await
+            .map(|maybe| maybe.map(Self::map_row))
+    }
+
+    /// deletes all the tasks of an entry
+    pub async fn delete_entry(conn: &impl GenericClient, entries_id: &EntryId) -> Result<u64, PgError> {
+        conn.execute(
+            "delete from entry_tasks where entries_id = $1",
+            &[entries_id]
+        ).await
+    }
+}
@@ -0,0 +1,100 @@
+//! scaled down copies of image file entries
+//!
+//! thumbnails are stored as jpegs next to the original file and are created
+//! when an image is uploaded or the first time one is requested
+
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+
+use image::codecs::jpeg::JpegEncoder;
+
+use crate::error::{self, Context};
+use crate::journal::FileEntry;
+use crate::sec::encryption::JournalKey;
+
+/// the max width and height of a thumbnail
+pub const THUMBNAIL_SIZE: u32 = 256;
+
+/// the quality used when encoding a thumbnail
+const THUMBNAIL_QUALITY: u8 = 80;
+
+/// checks if a thumbnail can be created for the file entry
+pub fn is_supported(file_entry: &FileEntry) -> bool {
+    file_entry.mime_type == "image" && matches!(
+        file_entry.mime_subtype.as_str(),
+        "jpeg" | "png" | "gif" | "webp"
+    )
+}
+
+fn create_blocking(source: &Path, dest: &Path, key: Option<&JournalKey>) -> Result<(), error::Error> {
+    let original = match key {
+        Some(key) => {
+            let mut file = std::fs::File::open(source)
+                .context("failed to open image")?;
+            let mut contents = Vec::new();
+
+            key.decrypt_copy(&mut file, &mut contents)
+                .context("failed to decrypt image")?;
+
+            image::ImageReader::new(Cursor::new(contents))
+                .with_guessed_format()
+                .context("failed to guess image format")?
+                .decode()
+                .context("failed to decode image")?
+        }
+        None => image::ImageReader::open(source)
+            .context("failed to open image")?
+            .with_guessed_format()
+            .context("failed to guess image format")?
+            .decode()
+            .context("failed to decode image")?
+    };
+    let scaled = original.thumbnail(THUMBNAIL_SIZE, THUMBNAIL_SIZE)
+        .into_rgb8();
+
+    let mut encoded = Vec::new();
+    let encoder = JpegEncoder::new_with_quality(&mut encoded, THUMBNAIL_QUALITY);
+
+    scaled.write_with_encoder(encoder)
+        .context("failed to encode thumbnail")?;
+
+    if let Some(key) = key {
+        encoded = key.encrypt_all(&encoded)
+            .context("failed to encrypt thumbnail")?;
+    }
+
+    // the thumbnail is written to a temporary file first so that a partial
+    // thumbnail is never served
+    let tmp = dest.with_extension("thumb.tmp");
+
+    if let Err(err) = std::fs::write(&tmp, &encoded) {
+        let _ = std::fs::remove_file(&tmp);
+
+        return Err(error::Error::context_source(
+            "failed to write thumbnail file",
+            err
+        ));
+    }
+
+    std::fs::rename(&tmp, dest)
+        .context("failed to move thumbnail into place")
+}
+
+/// creates a thumbnail of the source image at the given path. if a key is
+/// given then the source is decrypted and the thumbnail is encrypted with it
+///
+/// decoding and encoding is done on the blocking thread pool
+pub async fn create(source: PathBuf, dest: PathBuf, key: Option<JournalKey>) -> Result<(), error::Error> {
+    tokio::task::spawn_blocking(move || create_blocking(&source, &dest, key.as_ref()))
+        .await
+        .context("failed to join thumbnail task")?
+}
+
+/// removes the thumbnail at the given path if it exists
+pub async fn remove(path: &Path) -> Result<(), std::io::Error> {
+    match tokio::fs::remove_file(path).await {
+        Ok(()) => Ok(()),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(()),
+        Err(err) => Err(err),
+    }
+}
@@ -0,0 +1,219 @@
+//! limits on the files uploaded to a journal
+//!
+//! the [`config::Upload`] limits apply to every journal. the owner of a
+//! journal can override any of them with a [`JournalUploadPolicy`]. the
+//! number of files is checked when an entry is created or updated while
+//! the size and mime type are checked when the contents are uploaded. the
+//! files of an import are checked before they are written. see
+//! [`image_meta`](super::image_meta) for how metadata is stripped
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+use crate::config;
+use crate::db::{GenericClient, PgError};
+use crate::db::ids::JournalId;
+
+/// checks that the given string is a mime type or a type ending with "/*"
+pub fn valid_mime_pattern(given: &str) -> bool {
+    let Some((mime_type, subtype)) = given.split_once('/') else {
+        return false;
+    };
+
+    let is_token = |value: &str| !value.is_empty() && value.chars()
+        .all(|ch| ch.is_ascii_alphanumeric() || "!#$&-^_.+".contains(ch));
+
+    is_token(mime_type) && (subtype == "*" || is_token(subtype))
+}
+
+/// the upload limits that the owner of a journal has set. a limit that is
+/// not set uses the limit from the config
+#[derive(Debug, Serialize)]
+pub struct JournalUploadPolicy {
+    pub journals_id: JournalId,
+    pub max_file_size: Option<i64>,
+    pub allowed_mime_types: Option<Vec<String>>,
+    pub max_files_per_entry: Option<i32>,
+    pub strip_metadata: Option<bool>,
+    pub created: DateTime<Utc>,
+    pub updated: Option<DateTime<Utc>>,
+}
+
+impl JournalUploadPolicy {
+    pub async fn retrieve(
+        conn: &impl GenericClient,
+        journals_id: &JournalId,
+    ) -> Result<Option<Self>, PgError> {
+        conn.query_opt(
+            "\
+            select journal_upload_policies.journals_id, \
+                   journal_upload_policies.max_file_size, \
+                   journal_upload_policies.allowed_mime_types, \
+                   journal_upload_policies.max_files_per_entry, \
+                   journal_upload_policies.strip_metadata, \
+                   journal_upload_policies.created, \
+                   journal_upload_policies.updated \
+            from journal_upload_policies \
+            where journal_upload_policies.journals_id = $1",
+            &[journals_id]
+        )
+            .await
+            .map(|maybe| maybe.map(|row| Self {
+                journals_id: row.get(0),
+                max_file_size: row.get(1),
+                allowed_mime_types: row.get(2),
+                max_files_per_entry: row.get(3),
+                strip_metadata: row.get(4),
+                created: row.get(5),
+                updated: row.get(6),
+            }))
+    }
+
+    /// sets the upload limits of the journal, replacing the previous ones
+    pub async fn set(
+        conn: &impl GenericClient,
+        journals_id: &JournalId,
+        max_file_size: Option<i64>,
+        allowed_mime_types: Option<Vec<String>>,
+        max_files_per_entry: Option<i32>,
+        strip_metadata: Option<bool>,
+    ) -> Result<Self, PgError> {
+        let now = Utc::now();
+
+        let row = conn.query_one(
+            "\
+            insert into journal_upload_policies (journals_id, max_file_size, allowed_mime_types, max_files_per_entry, strip_metadata, created) values \
+            ($1, $2, $3, $4, $5, $6) \
+            on conflict (journals_id) do update \
+                set max_file_size = excluded.max_file_size, \
+                    allowed_mime_types = excluded.allowed_mime_types, \
+                    max_files_per_entry = excluded.max_files_per_entry, \
+                    strip_metadata = excluded.strip_metadata, \
+                    updated = excluded.created \
+            returning created, updated",
+            &[journals_id, &max_file_size, &allowed_mime_types, &max_files_per_entry, &strip_metadata, &now]
+        ).await?;
+
+        Ok(Self {
+            journals_id: *journals_id,
+            max_file_size,
+            allowed_mime_types,
+            max_files_per_entry,
+            strip_metadata,
+            created: row.get(0),
+            updated: row.get(1),
+        })
+    }
+
+    /// removes the upload limits of the journal
+    ///
+    /// returns false if the journal did not have any
+    pub async fn delete(conn: &impl GenericClient, journals_id: &JournalId) -> Result<bool, PgError> {
+        let result = conn.execute(
+            "delete from journal_upload_policies where journals_id = $1",
+            &[journals_id]
+        ).await?;
+
+        Ok(result == 1)
+    }
+}
+
+/// the reason that a file is not allowed by an [`UploadPolicy`]
+#[derive(Debug, thiserror::Error)]
+pub enum PolicyViolation {
+    #[error("files of type \"{0}\" are not allowed")]
+    MimeTypeNotAllowed(String),
+
+    #[error("file is larger than the max file size of {0} bytes")]
+    FileTooLarge(u64),
+}
+
+/// the upload limits that apply to a journal
+#[derive(Debug, Clone, Serialize)]
+pub struct UploadPolicy {
+    pub max_file_size: Option<u64>,
+    pub allowed_mime_types: Vec<String>,
+    pub max_files_per_entry: Option<u32>,
+    pub strip_metadata: bool,
+}
+
+impl UploadPolicy {
+    /// combines the config limits with the limits of the journal
+    pub fn resolve(config: &config::Upload, journal: Option<&JournalUploadPolicy>) -> Self {
+        let mut policy = UploadPolicy {
+            max_file_size: config.max_file_size,
+            allowed_mime_types: config.allowed_mime_types.clone(),
+            max_files_per_entry: config.max_files_per_entry,
+            strip_metadata: config.strip_metadata,
+        };
+
+        if let Some(journal) = journal {
+            if let Some(max_file_size) = journal.max_file_size {
+                policy.max_file_size = u64::try_from(max_file_size).ok();
+            }
+
+            if let Some(allowed_mime_types) = &journal.allowed_mime_types {
+                policy.allowed_mime_types = allowed_mime_types.clone();
+            }
+
+            if let Some(max_files_per_entry) = journal.max_files_per_entry {
+                policy.max_files_per_entry = u32::try_from(max_files_per_entry).ok();
+            }
+
+            if let Some(strip_metadata) = journal.strip_metadata {
+                policy.strip_metadata = strip_metadata;
+            }
+        }
+
+        policy
+    }
+
+    /// retrieves the limits of the journal and combines them with the
+    /// config limits
+    pub async fn retrieve(
+        conn: &impl GenericClient,
+        config: &config::Upload,
+        journals_id: &JournalId,
+    ) -> Result<Self, PgError> {
+        let journal = JournalUploadPolicy::retrieve(conn, journals_id).await?;
+
+        Ok(Self::resolve(config, journal.as_ref()))
+    }
+
+    /// checks the mime type and then the size of a file
+    pub fn check_file(&self, mime: &mime::Mime, size: u64) -> Result<(), PolicyViolation> {
+        if !self.allows_mime(mime) {
+            return Err(PolicyViolation::MimeTypeNotAllowed(mime.essence_str().to_owned()));
+        }
+
+        if !self.allows_size(size) {
+            return Err(PolicyViolation::FileTooLarge(self.max_file_size.unwrap_or(0)));
+        }
+
+        Ok(())
+    }
+
+    pub fn allows_size(&self, size: u64) -> bool {
+        self.max_file_size.map_or(true, |max| size <= max)
+    }
+
+    pub fn allows_files(&self, count: usize) -> bool {
+        self.max_files_per_entry.map_or(true, |max| count <= max as usize)
+    }
+
+    pub fn allows_mime(&self, given: &mime::Mime) -> bool {
+        if self.allowed_mime_types.is_empty() {
+            return true;
+        }
+
+        let essence = given.essence_str();
+
+        self.allowed_mime_types.iter().any(|allowed| {
+            if let Some(mime_type) = allowed.strip_suffix("/*") {
+                mime_type.eq_ignore_ascii_case(given.type_().as_str())
+            } else {
+                allowed.eq_ignore_ascii_case(essence)
+            }
+        })
+    }
+}
@@ -0,0 +1,57 @@
+//! tracking the entries that a user has read
+//!
+//! an entry is unread for a user if they have not viewed it since it was
+//! created or last updated
+
+use chrono::Utc;
+
+use crate::db::{GenericClient, PgError};
+use crate::db::ids::{EntryId, JournalId, UserId};
+
+/// the sql condition that matches entries the user has not viewed since they
+/// were last changed. expects the entries table to be available as
+/// "entries"
+pub fn unread_filter(param: usize) -> String {
+    format!(
+        "not exists ( \
+            select 1 \
+            from entry_views \
+            where entry_views.entries_id = entries.id and \
+                  entry_views.users_id = ${param} and \
+                  entry_views.viewed >= coalesce(entries.updated, entries.created) \
+        )"
+    )
+}
+
+/// marks the given entries of the journal as viewed by the user. if no
+/// entries are given then every entry of the journal is marked
+///
+/// returns the number of entries marked
+pub async fn mark_viewed(
+    conn: &impl GenericClient,
+    users_id: &UserId,
+    journals_id: &JournalId,
+    entries: Option<&[EntryId]>,
+) -> Result<u64, PgError> {
+    let viewed = Utc::now();
+
+    conn.execute(
+        "\
+        insert into entry_views (users_id, entries_id, viewed) \
+        select $1, entries.id, $3 \
+        from entries \
+        where entries.journals_id = $2 and \
+              ($4::bigint[] is null or entries.id = any($4)) \
+        on conflict (users_id, entries_id) do update \
+            set viewed = excluded.viewed",
+        &[users_id, journals_id, &viewed, &entries]
+    ).await
+}
+
+/// removes the views of an entry
+pub async fn delete_entry(conn: &impl GenericClient, entries_id: &EntryId) -> Result<u64, PgError> {
+    conn.execute(
+        "delete from entry_views where entries_id = $1",
+        &[entries_id]
+    ).await
+}
@@ -0,0 +1,313 @@
+//! filling in the weather of new entries
+//!
+//! new entries are queued when weather is configured and the
+//! [`JobKind::Weather`] job asks the provider for the weather on the date of
+//! the entry. the location comes from the location custom field of the
+//! entry or the default location of the journal and the weather is stored
+//! in the weather custom field. entries of end-to-end encrypted journals
+//! are never queued
+//!
+//! [`JobKind::Weather`]: crate::jobs::JobKind::Weather
+
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use serde::{Serialize, Deserialize};
+
+use crate::config;
+use crate::db::{GenericClient, PgError};
+use crate::db::ids::{CustomFieldId, EntryId, JournalId};
+use crate::error::{self, Context};
+
+use super::custom_field;
+
+/// the max number of entries retrieved in a single run of the job
+pub const BATCH_SIZE: i64 = 20;
+
+/// the number of minutes that a claimed entry is not claimed again
+const CLAIM_MINUTES: i64 = 15;
+
+/// the default location used for entries of a journal that do not have a
+/// location of their own
+#[derive(Debug, Serialize)]
+pub struct JournalLocation {
+    pub journals_id: JournalId,
+    pub location: String,
+    pub created: DateTime<Utc>,
+    pub updated: Option<DateTime<Utc>>,
+}
+
+impl JournalLocation {
+    pub async fn retrieve(
+        conn: &impl GenericClient,
+        journals_id: &JournalId,
+    ) -> Result<Option<Self>, PgError> {
+        conn.query_opt(
+            "\
+            select journal_locations.journals_id, \
+                   journal_locations.location, \
+                   journal_locations.created, \
+                   journal_locations.updated \
+            from journal_locations \
+            where journal_locations.journals_id = $1",
+            &[journals_id]
+        )
+            .await
+            .map(|maybe| maybe.map(|row| Self {
+                journals_id: row.get(0),
+                location: row.get(1),
+                created: row.get(2),
+                updated: row.get(3),
+            }))
+    }
+
+    /// sets the default location of the journal, replacing the previous one
+    pub async fn set(
+        conn: &impl GenericClient,
+        journals_id: &JournalId,
+        location: &str,
+    ) -> Result<Self, PgError> {
+        let now = Utc::now();
+
+        let row = conn.query_one(
+            "\
+            insert into journal_locations (journals_id, location, created) values \
+            ($1, $2, $3) \
+            on conflict (journals_id) do update \
+                set location = excluded.location, \
+                    updated = excluded.created \
+            returning created, updated",
+            &[journals_id, &location, &now]
+        ).await?;
+
+        Ok(Self {
+            journals_id: *journals_id,
+            location: location.to_owned(),
+            created: row.get(0),
+            updated: row.get(1),
+        })
+    }
+
+    /// removes the default location of the journal
+    ///
+    /// returns false if the journal did not have one
+    pub async fn delete(conn: &impl GenericClient, journals_id: &JournalId) -> Result<bool, PgError> {
+        let result = conn.execute(
+            "delete from journal_locations where journals_id = $1",
+            &[journals_id]
+        ).await?;
+
+        Ok(result == 1)
+    }
+}
+
+/// queues an entry to have its weather filled in
+pub async fn queue(conn: &impl GenericClient, entries_id: &EntryId) -> Result<(), PgError> {
+    let queued = Utc::now();
+
+    conn.execute(
+        "\
+        insert into entry_weather (entries_id, queued) values ($1, $2) \
+        on conflict (entries_id) do nothing",
+        &[entries_id, &queued]
+    ).await?;
+
+    Ok(())
+}
+
+/// an entry waiting for its weather
+#[derive(Debug)]
+pub struct Pending {
+    pub entries_id: EntryId,
+    pub journals_id: JournalId,
+    pub date: NaiveDate,
+}
+
+/// claims a batch of queued entries that have not been filled in
+///
+/// the claimed entries are not claimed again for [`CLAIM_MINUTES`] so that
+/// the provider is not asked twice while waiting on it outside of a
+/// transaction. if the server stops before storing the result then they
+/// will be attempted again once the claim expires
+pub async fn claim_pending(
+    conn: &impl GenericClient,
+    now: &DateTime<Utc>,
+    limit: i64,
+) -> Result<Vec<Pending>, PgError> {
+    let claimed = *now + Duration::minutes(CLAIM_MINUTES);
+
+    conn.query(
+        "\
+        with pending as ( \
+            select entry_weather.entries_id \
+            from entry_weather \
+                join entries on \
+                    entry_weather.entries_id = entries.id \
+                join journals on \
+                    entries.journals_id = journals.id \
+            where entry_weather.fetched is null and \
+                  (entry_weather.claimed is null or entry_weather.claimed <= $2) and \
+                  not journals.e2e \
+            order by entry_weather.queued \
+            limit $1 \
+            for update of entry_weather skip locked \
+        ) \
+        update entry_weather \
+        set claimed = $3 \
+        from pending, entries \
+        where entry_weather.entries_id = pending.entries_id and \
+              entry_weather.entries_id = entries.id \
+        returning entries.id, \
+                  entries.journals_id, \
+                  entries.entry_date",
+        &[&limit, now, &claimed]
+    )
+        .await
+        .map(|rows| rows.into_iter().map(|row| Pending {
+            entries_id: row.get(0),
+            journals_id: row.get(1),
+            date: row.get(2),
+        }).collect())
+}
+
+/// retrieves the location of an entry from the location field of the entry
+/// or the default location of the journal
+pub async fn retrieve_location(
+    conn: &impl GenericClient,
+    weather: &config::Weather,
+    pending: &Pending,
+) -> Result<Option<String>, PgError> {
+    let row = conn.query_one(
+        "\
+        select coalesce( \
+            ( \
+                select nullif(trim(custom_field_entries.value ->> 'value'), '') \
+                from custom_field_entries \
+                    join custom_fields on \
+                        custom_field_entries.custom_fields_id = custom_fields.id \
+                where custom_field_entries.entries_id = $1 and \
+                      custom_fields.journals_id = $2 and \
+                      custom_fields.name = $3 and \
+                      custom_field_entries.value ->> 'type' = 'Text' \
+            ), \
+            ( \
+                select journal_locations.location \
+                from journal_locations \
+                where journal_locations.journals_id = $2 \
+            ) \
+        )",
+        &[&pending.entries_id, &pending.journals_id, &weather.location_field]
+    ).await?;
+
+    Ok(row.get(0))
+}
+
+#[derive(Debug, Deserialize)]
+struct ProviderWeather {
+    summary: String,
+}
+
+/// asks the provider for the weather at the location on the given date
+pub async fn fetch(
+    http: &reqwest::Client,
+    weather: &config::Weather,
+    location: &str,
+    date: &NaiveDate,
+) -> Result<String, error::Error> {
+    let date = date.format("%Y-%m-%d").to_string();
+
+    let mut req = http.get(weather.url.clone())
+        .query(&[("location", location), ("date", date.as_str())])
+        .timeout(std::time::Duration::from_secs(30));
+
+    if let Some(api_key) = &weather.api_key {
+        req = req.bearer_auth(api_key);
+    }
+
+    let res = req.send()
+        .await
+        .context("failed to send request to weather provider")?
+        .error_for_status()
+        .context("weather provider rejected request")?;
+
+    let is_json = res.headers()
+        .get("content-type")
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("application/json"));
+
+    let body = res.text()
+        .await
+        .context("failed to read weather provider response")?;
+
+    let summary = if is_json {
+        serde_json::from_str::<ProviderWeather>(&body)
+            .context("invalid json from weather provider")?
+            .summary
+    } else {
+        body
+    };
+
+    Ok(summary.trim().replace('\0', ""))
+}
+
+/// stores the weather in the weather field of the entry. an existing value
+/// is left alone so that the weather written by the user is not replaced
+///
+/// returns false if the entry already had a value
+pub async fn fill_field(
+    conn: &impl GenericClient,
+    custom_fields_id: &CustomFieldId,
+    entries_id: &EntryId,
+    summary: &str,
+) -> Result<bool, PgError> {
+    let value = custom_field::Value::Text {
+        value: summary.to_owned(),
+    };
+    let now = Utc::now();
+
+    let inserted = conn.execute(
+        "\
+        insert into custom_field_entries (custom_fields_id, entries_id, value, created) values \
+        ($1, $2, $3, $4) \
+        on conflict (custom_fields_id, entries_id) do nothing",
+        &[custom_fields_id, entries_id, &value, &now]
+    ).await?;
+
+    if inserted == 0 {
+        return Ok(false);
+    }
+
+    conn.execute(
+        "update entries set updated = $2 where id = $1",
+        &[entries_id, &now]
+    ).await?;
+
+    Ok(true)
+}
+
+/// stores the result of retrieving the weather of an entry. the error is
+/// stored instead of the summary so the entry is not attempted again
+pub async fn store(
+    conn: &impl GenericClient,
+    entries_id: &EntryId,
+    location: Option<&str>,
+    result: Result<&str, String>,
+) -> Result<(), PgError> {
+    let (summary, error) = match result {
+        Ok(summary) => (Some(summary), None),
+        Err(err) => (None, Some(err)),
+    };
+    let fetched = Utc::now();
+
+    conn.execute(
+        "\
+        update entry_weather \
+        set location = $2, \
+            summary = $3, \
+            error = $4, \
+            fetched = $5, \
+            claimed = null \
+        where entries_id = $1",
+        &[entries_id, &location, &summary, &error, &fetched]
+    ).await?;
+
+    Ok(())
+}
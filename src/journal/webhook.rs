@@ -0,0 +1,717 @@
+//! webhook notifications for journal events
+//!
+//! events are queued as deliveries in the same transaction as the change
+//! that caused them. the [`JobKind::WebhookDelivery`] job sends the pending
+//! deliveries and retries failed ones with an exponential backoff until
+//! [`MAX_ATTEMPTS`] is reached
+//!
+//! when the signing secret of a webhook is rotated the previous secret is
+//! kept for [`SECRET_OVERLAP_HOURS`] and deliveries are signed with both so
+//! that the target can switch to the new secret without dropping events.
+//! the signature header then contains both signatures separated by a comma,
+//! ex: "sha256=<new>,sha256=<previous>"
+//!
+//! [`JobKind::WebhookDelivery`]: crate::jobs::JobKind::WebhookDelivery
+
+use std::fmt::{Display, Formatter, Result as FmtResult, Write};
+use std::str::FromStr;
+
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use bytes::BytesMut;
+use chrono::{DateTime, Duration, Utc};
+use futures::{Stream, StreamExt};
+use postgres_types as pg_types;
+use rand::RngCore;
+use ring::hmac;
+use serde::{Serialize, Deserialize};
+
+use crate::db::{self, GenericClient, PgError};
+use crate::db::ids::{JournalId, UserId, WebhookId, WebhookDeliveryId};
+use crate::error::BoxDynError;
+
+/// the number of attempts made for a delivery before it is marked as failed
+pub const MAX_ATTEMPTS: i32 = 8;
+
+/// the max number of deliveries sent in a single run of the delivery job
+pub const BATCH_SIZE: i64 = 50;
+
+/// the number of minutes a claimed delivery is held before it can be claimed
+/// again
+const CLAIM_MINUTES: i64 = 15;
+
+/// the number of hours the previous secret of a webhook is used to sign
+/// deliveries after the secret is rotated
+pub const SECRET_OVERLAP_HOURS: i64 = 24;
+
+/// the number of random bytes used for a signing secret
+const SECRET_LEN: usize = 32;
+
+/// the header containing the hex encoded HMAC-SHA256 of the request body
+pub const SIGNATURE_HEADER: &str = "x-tj2-signature";
+
+/// the header containing the event of the delivery
+pub const EVENT_HEADER: &str = "x-tj2-event";
+
+/// the header containing the id of the delivery
+pub const DELIVERY_HEADER: &str = "x-tj2-delivery";
+
+#[derive(Debug, thiserror::Error)]
+#[error("the provided string is not a valid webhook event")]
+pub struct InvalidWebhookEvent;
+
+/// the journal events that a webhook can subscribe to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum WebhookEvent {
+    #[serde(rename = "entry.created")]
+    EntryCreated,
+    #[serde(rename = "entry.updated")]
+    EntryUpdated,
+    #[serde(rename = "file.received")]
+    FileReceived,
+    #[serde(rename = "sync.completed")]
+    SyncCompleted,
+    #[serde(rename = "reminder.due")]
+    ReminderDue,
+    #[serde(rename = "journal.schema_updated")]
+    SchemaUpdated,
+}
+
+impl WebhookEvent {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            WebhookEvent::EntryCreated => "entry.created",
+            WebhookEvent::EntryUpdated => "entry.updated",
+            WebhookEvent::FileReceived => "file.received",
+            WebhookEvent::SyncCompleted => "sync.completed",
+            WebhookEvent::ReminderDue => "reminder.due",
+            WebhookEvent::SchemaUpdated => "journal.schema_updated",
+        }
+    }
+}
+
+impl Display for WebhookEvent {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for WebhookEvent {
+    type Err = InvalidWebhookEvent;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "entry.created" => Ok(WebhookEvent::EntryCreated),
+            "entry.updated" => Ok(WebhookEvent::EntryUpdated),
+            "file.received" => Ok(WebhookEvent::FileReceived),
+            "sync.completed" => Ok(WebhookEvent::SyncCompleted),
+            "reminder.due" => Ok(WebhookEvent::ReminderDue),
+            "journal.schema_updated" => Ok(WebhookEvent::SchemaUpdated),
+            _ => Err(InvalidWebhookEvent)
+        }
+    }
+}
+
+impl<'a> pg_types::FromSql<'a> for WebhookEvent {
+    fn from_sql(ty: &pg_types::Type, raw: &'a [u8]) -> Result<Self, BoxDynError> {
+        let v = <&str as pg_types::FromSql>::from_sql(ty, raw)?;
+
+        Ok(Self::from_str(v)?)
+    }
+
+    fn accepts(ty: &pg_types::Type) -> bool {
+        <&str as pg_types::FromSql>::accepts(ty)
+    }
+}
+
+impl pg_types::ToSql for WebhookEvent {
+    fn to_sql(&self, ty: &pg_types::Type, w: &mut BytesMut) -> Result<pg_types::IsNull, BoxDynError> {
+        self.as_str()
+            .to_sql(ty, w)
+    }
+
+    fn accepts(ty: &pg_types::Type) -> bool {
+        <&str as pg_types::ToSql>::accepts(ty)
+    }
+
+    pg_types::to_sql_checked!();
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("the provided string is not a valid delivery status")]
+pub struct InvalidDeliveryStatus;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DeliveryStatus {
+    /// waiting for the first attempt or a retry
+    Pending,
+
+    /// the target responded with a 2xx status
+    Delivered,
+
+    /// all attempts were used without a successful response
+    Failed,
+}
+
+impl DeliveryStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DeliveryStatus::Pending => "pending",
+            DeliveryStatus::Delivered => "delivered",
+            DeliveryStatus::Failed => "failed",
+        }
+    }
+}
+
+impl FromStr for DeliveryStatus {
+    type Err = InvalidDeliveryStatus;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "pending" => Ok(DeliveryStatus::Pending),
+            "delivered" => Ok(DeliveryStatus::Delivered),
+            "failed" => Ok(DeliveryStatus::Failed),
+            _ => Err(InvalidDeliveryStatus)
+        }
+    }
+}
+
+impl<'a> pg_types::FromSql<'a> for DeliveryStatus {
+    fn from_sql(ty: &pg_types::Type, raw: &'a [u8]) -> Result<Self, BoxDynError> {
+        let v = <&str as pg_types::FromSql>::from_sql(ty, raw)?;
+
+        Ok(Self::from_str(v)?)
+    }
+
+    fn accepts(ty: &pg_types::Type) -> bool {
+        <&str as pg_types::FromSql>::accepts(ty)
+    }
+}
+
+impl pg_types::ToSql for DeliveryStatus {
+    fn to_sql(&self, ty: &pg_types::Type, w: &mut BytesMut) -> Result<pg_types::IsNull, BoxDynError> {
+        self.as_str()
+            .to_sql(ty, w)
+    }
+
+    fn accepts(ty: &pg_types::Type) -> bool {
+        <&str as pg_types::ToSql>::accepts(ty)
+    }
+
+    pg_types::to_sql_checked!();
+}
+
+/// generates a new random signing secret
+pub fn gen_secret() -> Result<String, rand::Error> {
+    let mut bytes = [0; SECRET_LEN];
+
+    rand::thread_rng().try_fill_bytes(&mut bytes)?;
+
+    Ok(URL_SAFE_NO_PAD.encode(bytes))
+}
+
+/// creates the hex encoded HMAC-SHA256 of the body using the secret
+pub fn sign(secret: &str, body: &[u8]) -> String {
+    let key = hmac::Key::new(hmac::HMAC_SHA256, secret.as_bytes());
+    let tag = hmac::sign(&key, body);
+    let mut rtn = String::with_capacity(tag.as_ref().len() * 2);
+
+    for byte in tag.as_ref() {
+        let _ = write!(rtn, "{byte:02x}");
+    }
+
+    rtn
+}
+
+/// creates the value of the signature header for the given secrets
+pub fn signature_header(secret: &str, previous: Option<&str>, body: &[u8]) -> String {
+    let mut rtn = format!("sha256={}", sign(secret, body));
+
+    if let Some(previous) = previous {
+        rtn.push_str(",sha256=");
+        rtn.push_str(&sign(previous, body));
+    }
+
+    rtn
+}
+
+/// the amount of time to wait before the next attempt of a delivery
+pub fn backoff(attempts: i32) -> Duration {
+    Duration::minutes(1i64 << attempts.clamp(0, 10))
+}
+
+#[derive(Debug, Serialize)]
+pub struct Webhook {
+    pub id: WebhookId,
+    pub journals_id: JournalId,
+    pub users_id: UserId,
+    pub url: String,
+    pub events: Vec<WebhookEvent>,
+
+    #[serde(skip)]
+    pub secret: String,
+
+    /// the secret before the last rotation
+    #[serde(skip)]
+    pub previous_secret: Option<String>,
+
+    /// when the previous secret stops being used to sign deliveries
+    pub previous_expires: Option<DateTime<Utc>>,
+    pub enabled: bool,
+    pub created: DateTime<Utc>,
+    pub updated: Option<DateTime<Utc>>,
+}
+
+impl Webhook {
+    fn map_row(row: tokio_postgres::Row) -> Self {
+        Self {
+            id: row.get(0),
+            journals_id: row.get(1),
+            users_id: row.get(2),
+            url: row.get(3),
+            events: row.get(4),
+            secret: row.get(5),
+            previous_secret: row.get(6),
+            previous_expires: row.get(7),
+            enabled: row.get(8),
+            created: row.get(9),
+            updated: row.get(10),
+        }
+    }
+
+    pub async fn create(
+        conn: &impl GenericClient,
+        journals_id: &JournalId,
+        users_id: &UserId,
+        url: String,
+        events: Vec<WebhookEvent>,
+        secret: String,
+    ) -> Result<Self, PgError> {
+        let created = Utc::now();
+
+        let row = conn.query_one(
+            "\
+            insert into journal_webhooks ( \
+                journals_id, \
+                users_id, \
+                url, \
+                events, \
+                secret, \
+                created \
+            ) values ($1, $2, $3, $4, $5, $6) \
+            returning id",
+            &[journals_id, users_id, &url, &events, &secret, &created]
+        ).await?;
+
+        Ok(Self {
+            id: row.get(0),
+            journals_id: *journals_id,
+            users_id: *users_id,
+            url,
+            events,
+            secret,
+            previous_secret: None,
+            previous_expires: None,
+            enabled: true,
+            created,
+            updated: None,
+        })
+    }
+
+    pub async fn retrieve(
+        conn: &impl GenericClient,
+        journals_id: &JournalId,
+        webhooks_id: &WebhookId,
+    ) -> Result<Option<Self>, PgError> {
+        conn.query_opt(
+            "\
+            select journal_webhooks.id, \
+                   journal_webhooks.journals_id, \
+                   journal_webhooks.users_id, \
+                   journal_webhooks.url, \
+                   journal_webhooks.events, \
+                   journal_webhooks.secret, \
+                   journal_webhooks.previous_secret, \
+                   journal_webhooks.previous_expires, \
+                   journal_webhooks.enabled, \
+                   journal_webhooks.created, \
+                   journal_webhooks.updated \
+            from journal_webhooks \
+            where journal_webhooks.journals_id = $1 and \
+                  journal_webhooks.id = $2",
+            &[journals_id, webhooks_id]
+        )
+            .await
+            .map(|maybe| maybe.map(Self::map_row))
+    }
+
+    pub async fn retrieve_journal_stream(
+        conn: &impl GenericClient,
+        journals_id: &JournalId,
+    ) -> Result<impl Stream<Item = Result<Self, PgError>>, PgError> {
+        let params: db::ParamsArray<'_, 1> = [journals_id];
+
+        Ok(conn.query_raw(
+            "\
+            select journal_webhooks.id, \
+                   journal_webhooks.journals_id, \
+                   journal_webhooks.users_id, \
+                   journal_webhooks.url, \
+                   journal_webhooks.events, \
+                   journal_webhooks.secret, \
+                   journal_webhooks.previous_secret, \
+                   journal_webhooks.previous_expires, \
+                   journal_webhooks.enabled, \
+                   journal_webhooks.created, \
+                   journal_webhooks.updated \
+            from journal_webhooks \
+            where journal_webhooks.journals_id = $1 \
+            order by journal_webhooks.id",
+            params
+        )
+            .await?
+            .map(|stream| stream.map(Self::map_row)))
+    }
+
+    /// replaces the secret of the webhook. the current secret is kept as the
+    /// previous secret for [`SECRET_OVERLAP_HOURS`]
+    pub fn rotate_secret(&mut self, secret: String) {
+        let previous = std::mem::replace(&mut self.secret, secret);
+
+        self.previous_secret = Some(previous);
+        self.previous_expires = Some(Utc::now() + Duration::hours(SECRET_OVERLAP_HOURS));
+    }
+
+    /// updates the url, events, secrets, and enabled state of the webhook
+    pub async fn update(&mut self, conn: &impl GenericClient) -> Result<(), PgError> {
+        let updated = Utc::now();
+
+        conn.execute(
+            "\
+            update journal_webhooks \
+            set url = $2, \
+                events = $3, \
+                secret = $4, \
+                previous_secret = $5, \
+                previous_expires = $6, \
+                enabled = $7, \
+                updated = $8 \
+            where id = $1",
+            &[
+                &self.id,
+                &self.url,
+                &self.events,
+                &self.secret,
+                &self.previous_secret,
+                &self.previous_expires,
+                &self.enabled,
+                &updated,
+            ]
+        ).await?;
+
+        self.updated = Some(updated);
+
+        Ok(())
+    }
+
+    /// deletes the webhook along with its delivery history
+    pub async fn delete(&self, conn: &impl GenericClient) -> Result<(), PgError> {
+        conn.execute(
+            "delete from journal_webhooks where id = $1",
+            &[&self.id]
+        ).await?;
+
+        Ok(())
+    }
+}
+
+/// queues a delivery of the event for every enabled webhook of the journal
+/// that is subscribed to it
+///
+/// returns the number of deliveries queued
+pub async fn queue<T>(
+    conn: &impl GenericClient,
+    journals_id: &JournalId,
+    event: WebhookEvent,
+    data: &T,
+) -> Result<u64, PgError>
+where
+    T: Serialize,
+{
+    let created = Utc::now();
+    let payload = serde_json::json!({
+        "event": event,
+        "journals_id": journals_id,
+        "created": created,
+        "data": data,
+    });
+
+    conn.execute(
+        "\
+        insert into webhook_deliveries ( \
+            journal_webhooks_id, \
+            event, \
+            payload, \
+            status, \
+            next_attempt, \
+            created \
+        ) \
+        select journal_webhooks.id, \
+               $2, \
+               $3, \
+               $4, \
+               $5, \
+               $5 \
+        from journal_webhooks \
+        where journal_webhooks.journals_id = $1 and \
+              journal_webhooks.enabled and \
+              $2 = any(journal_webhooks.events)",
+        &[journals_id, &event, &payload, &DeliveryStatus::Pending, &created]
+    ).await
+}
+
+#[derive(Debug, Serialize)]
+pub struct Delivery {
+    pub id: WebhookDeliveryId,
+    pub journal_webhooks_id: WebhookId,
+    pub event: WebhookEvent,
+
+    #[serde(skip)]
+    pub payload: serde_json::Value,
+    pub status: DeliveryStatus,
+    pub attempts: i32,
+    pub next_attempt: DateTime<Utc>,
+
+    /// the http status of the last attempt if a response was received
+    pub response_status: Option<i32>,
+
+    /// the number of milliseconds the last attempt took
+    pub latency_ms: Option<i32>,
+
+    /// the reason the last attempt failed
+    pub error: Option<String>,
+    pub created: DateTime<Utc>,
+    pub delivered: Option<DateTime<Utc>>,
+}
+
+impl Delivery {
+    fn map_row(row: tokio_postgres::Row) -> Self {
+        Self {
+            id: row.get(0),
+            journal_webhooks_id: row.get(1),
+            event: row.get(2),
+            payload: row.get(3),
+            status: row.get(4),
+            attempts: row.get(5),
+            next_attempt: row.get(6),
+            response_status: row.get(7),
+            latency_ms: row.get(8),
+            error: row.get(9),
+            created: row.get(10),
+            delivered: row.get(11),
+        }
+    }
+
+    /// retrieves the most recent deliveries of a webhook
+    pub async fn retrieve_webhook(
+        conn: &impl GenericClient,
+        webhooks_id: &WebhookId,
+        limit: i64,
+    ) -> Result<Vec<Self>, PgError> {
+        conn.query(
+            "\
+            select webhook_deliveries.id, \
+                   webhook_deliveries.journal_webhooks_id, \
+                   webhook_deliveries.event, \
+                   webhook_deliveries.payload, \
+                   webhook_deliveries.status, \
+                   webhook_deliveries.attempts, \
+                   webhook_deliveries.next_attempt, \
+                   webhook_deliveries.response_status, \
+                   webhook_deliveries.latency_ms, \
+                   webhook_deliveries.error, \
+                   webhook_deliveries.created, \
+                   webhook_deliveries.delivered \
+            from webhook_deliveries \
+            where webhook_deliveries.journal_webhooks_id = $1 \
+            order by webhook_deliveries.created desc \
+            limit $2",
+            &[webhooks_id, &limit]
+        )
+            .await
+            .map(|rows| rows.into_iter().map(Self::map_row).collect())
+    }
+
+    pub async fn retrieve(
+        conn: &impl GenericClient,
+        webhooks_id: &WebhookId,
+        deliveries_id: &WebhookDeliveryId,
+    ) -> Result<Option<Self>, PgError> {
+        conn.query_opt(
+            "\
+            select webhook_deliveries.id, \
+                   webhook_deliveries.journal_webhooks_id, \
+                   webhook_deliveries.event, \
+                   webhook_deliveries.payload, \
+                   webhook_deliveries.status, \
+                   webhook_deliveries.attempts, \
+                   webhook_deliveries.next_attempt, \
+                   webhook_deliveries.response_status, \
+                   webhook_deliveries.latency_ms, \
+                   webhook_deliveries.error, \
+                   webhook_deliveries.created, \
+                   webhook_deliveries.delivered \
+            from webhook_deliveries \
+            where webhook_deliveries.journal_webhooks_id = $1 and \
+                  webhook_deliveries.id = $2",
+            &[webhooks_id, deliveries_id]
+        )
+            .await
+            .map(|maybe| maybe.map(Self::map_row))
+    }
+
+    /// claims a batch of pending deliveries that are ready to be attempted
+    /// along with the url and secrets of their webhook
+    ///
+    /// the next attempt of the claimed deliveries is moved forward by
+    /// [`CLAIM_MINUTES`] so that they are not claimed again while being sent.
+    /// if the server stops before recording the result then they will be
+    /// attempted again once the claim expires
+    pub async fn claim_ready(
+        conn: &impl GenericClient,
+        now: &DateTime<Utc>,
+    ) -> Result<Vec<ClaimedDelivery>, PgError> {
+        let claimed = *now + Duration::minutes(CLAIM_MINUTES);
+
+        conn.query(
+            "\
+            with ready as ( \
+                select webhook_deliveries.id \
+                from webhook_deliveries \
+                where webhook_deliveries.status = $1 and \
+                      webhook_deliveries.next_attempt <= $2 \
+                order by webhook_deliveries.next_attempt \
+                limit $3 \
+                for update skip locked \
+            ) \
+            update webhook_deliveries \
+            set next_attempt = $4 \
+            from ready, journal_webhooks \
+            where webhook_deliveries.id = ready.id and \
+                  webhook_deliveries.journal_webhooks_id = journal_webhooks.id \
+            returning webhook_deliveries.id, \
+                      webhook_deliveries.journal_webhooks_id, \
+                      webhook_deliveries.event, \
+                      webhook_deliveries.payload, \
+                      webhook_deliveries.status, \
+                      webhook_deliveries.attempts, \
+                      webhook_deliveries.next_attempt, \
+                      webhook_deliveries.response_status, \
+                      webhook_deliveries.latency_ms, \
+                      webhook_deliveries.error, \
+                      webhook_deliveries.created, \
+                      webhook_deliveries.delivered, \
+                      journal_webhooks.url, \
+                      journal_webhooks.secret, \
+                      case when journal_webhooks.previous_expires > $2 \
+                          then journal_webhooks.previous_secret \
+                      end",
+            &[&DeliveryStatus::Pending, now, &BATCH_SIZE, &claimed]
+        )
+            .await
+            .map(|rows| rows.into_iter()
+                .map(|row| ClaimedDelivery {
+                    url: row.get(12),
+                    secret: row.get(13),
+                    previous_secret: row.get(14),
+                    delivery: Self::map_row(row),
+                })
+                .collect())
+    }
+
+    /// queues a failed delivery to be attempted again with the original
+    /// payload. the attempts are reset so it is retried as if it was new
+    pub async fn redeliver(&mut self, conn: &impl GenericClient) -> Result<(), PgError> {
+        self.status = DeliveryStatus::Pending;
+        self.attempts = 0;
+        self.next_attempt = Utc::now();
+
+        conn.execute(
+            "\
+            update webhook_deliveries \
+            set status = $2, \
+                attempts = $3, \
+                next_attempt = $4 \
+            where id = $1",
+            &[&self.id, &self.status, &self.attempts, &self.next_attempt]
+        ).await?;
+
+        Ok(())
+    }
+
+    /// records the result of an attempt
+    ///
+    /// failed attempts are scheduled for a retry until [`MAX_ATTEMPTS`] is
+    /// reached
+    pub async fn record_attempt(
+        &mut self,
+        conn: &impl GenericClient,
+        response_status: Option<i32>,
+        latency_ms: Option<i32>,
+        error: Option<String>,
+    ) -> Result<(), PgError> {
+        let now = Utc::now();
+
+        self.attempts += 1;
+        self.response_status = response_status;
+        self.latency_ms = latency_ms;
+
+        if error.is_none() {
+            self.status = DeliveryStatus::Delivered;
+            self.delivered = Some(now);
+        } else if self.attempts >= MAX_ATTEMPTS {
+            self.status = DeliveryStatus::Failed;
+        } else {
+            self.next_attempt = now + backoff(self.attempts - 1);
+        }
+
+        self.error = error;
+
+        conn.execute(
+            "\
+            update webhook_deliveries \
+            set status = $2, \
+                attempts = $3, \
+                next_attempt = $4, \
+                response_status = $5, \
+                latency_ms = $6, \
+                error = $7, \
+                delivered = $8 \
+            where id = $1",
+            &[
+                &self.id,
+                &self.status,
+                &self.attempts,
+                &self.next_attempt,
+                &self.response_status,
+                &self.latency_ms,
+                &self.error,
+                &self.delivered,
+            ]
+        ).await?;
+
+        Ok(())
+    }
+}
+
+/// a delivery claimed for an attempt along with where to send it
+#[derive(Debug)]
+pub struct ClaimedDelivery {
+    pub delivery: Delivery,
+    pub url: String,
+    pub secret: String,
+
+    /// the secret before the last rotation if it has not expired
+    pub previous_secret: Option<String>,
+}
@@ -0,0 +1,214 @@
+//! runtime control of the tracing filter
+//!
+//! the filter created at startup from RUST_LOG and the cli verbosity along
+//! with the "log_filter" of the config is the base. the config directives
+//! are replaced when the config is reloaded. an override adds directives on
+//! top of the base for a limited amount of time and is reverted
+//! automatically once it expires
+
+use std::sync::{Arc, Mutex};
+
+use chrono::{DateTime, Duration, Utc};
+use serde::Serialize;
+use tracing_subscriber::{reload, EnvFilter, Registry};
+
+use crate::db::ids::UserId;
+use crate::error;
+
+/// the default amount of time an override is active for
+pub const DEFAULT_DURATION: Duration = Duration::minutes(15);
+
+/// the max amount of time an override can be active for
+pub const MAX_DURATION: Duration = Duration::hours(24);
+
+pub type FilterHandle = reload::Handle<EnvFilter, Registry>;
+
+#[derive(Debug, thiserror::Error)]
+pub enum OverrideError {
+    #[error("the given directives are invalid")]
+    InvalidDirectives(#[from] tracing_subscriber::filter::ParseError),
+
+    #[error("failed to reload the tracing filter")]
+    Reload(#[from] reload::Error),
+}
+
+/// directives that are added to the base filter
+#[derive(Debug, Clone, Serialize)]
+pub struct Override {
+    pub directives: String,
+    pub users_id: UserId,
+    pub created: DateTime<Utc>,
+    pub expires: DateTime<Utc>,
+}
+
+#[derive(Debug)]
+struct Current {
+    /// incremented every time the filter changes so that an expired revert
+    /// does not remove a newer override
+    generation: u64,
+
+    /// the directives from the config file
+    config: Option<String>,
+    active: Option<Override>,
+}
+
+#[derive(Debug)]
+struct Inner {
+    handle: FilterHandle,
+
+    /// the directives from RUST_LOG and the cli
+    startup: String,
+    current: Mutex<Current>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Logging(Arc<Inner>);
+
+impl Logging {
+    pub fn new(base: &EnvFilter, handle: FilterHandle) -> Self {
+        Logging(Arc::new(Inner {
+            handle,
+            startup: base.to_string(),
+            current: Mutex::new(Current {
+                generation: 0,
+                config: None,
+                active: None,
+            }),
+        }))
+    }
+
+    /// the directives of the filter created at startup and from the config
+    pub fn base(&self) -> String {
+        let current = self.0.current.lock().unwrap();
+
+        self.base_directives(&current)
+    }
+
+    /// replaces the directives from the config file. an active override is
+    /// kept on top of the new base
+    pub fn set_config(&self, directives: Option<String>) -> Result<(), OverrideError> {
+        let mut current = self.0.current.lock().unwrap();
+
+        let previous = std::mem::replace(&mut current.config, directives);
+        let active = current.active.as_ref().map(|value| value.directives.clone());
+
+        let result = self.build_filter(&current, active.as_deref())
+            .map_err(OverrideError::from)
+            .and_then(|filter| self.0.handle.reload(filter).map_err(OverrideError::from));
+
+        if let Err(err) = result {
+            current.config = previous;
+
+            return Err(err);
+        }
+
+        Ok(())
+    }
+
+    /// the currently active override if one is present
+    pub fn active(&self) -> Option<Override> {
+        self.0.current.lock()
+            .unwrap()
+            .active
+            .clone()
+    }
+
+    /// adds the given directives to the base filter until the duration has
+    /// passed
+    ///
+    /// any previous override is replaced
+    pub fn set(
+        &self,
+        directives: String,
+        users_id: UserId,
+        duration: Duration,
+    ) -> Result<Override, OverrideError> {
+        let created = Utc::now();
+        let value = Override {
+            directives,
+            users_id,
+            created,
+            expires: created + duration,
+        };
+
+        let generation = {
+            let mut current = self.0.current.lock().unwrap();
+
+            self.0.handle.reload(self.build_filter(&current, Some(&value.directives))?)?;
+
+            current.generation += 1;
+            current.active = Some(value.clone());
+            current.generation
+        };
+
+        let logging = self.clone();
+
+        tokio::spawn(async move {
+            if let Ok(wait) = duration.to_std() {
+                tokio::time::sleep(wait).await;
+            }
+
+            logging.expire(generation);
+        });
+
+        Ok(value)
+    }
+
+    /// removes the active override and restores the base filter
+    pub fn clear(&self) -> Result<Option<Override>, OverrideError> {
+        let mut current = self.0.current.lock().unwrap();
+
+        self.restore(&mut current)
+    }
+
+    /// reverts the override if it has not changed since it was set
+    fn expire(&self, generation: u64) {
+        let mut current = self.0.current.lock().unwrap();
+
+        if current.generation != generation {
+            return;
+        }
+
+        match self.restore(&mut current) {
+            Ok(Some(expired)) => {
+                tracing::info!(
+                    directives = %expired.directives,
+                    users_id = %expired.users_id,
+                    "tracing override expired"
+                );
+            }
+            Ok(None) => {}
+            Err(err) => {
+                error::log_prefix_error("failed to revert tracing override", &err);
+            }
+        }
+    }
+
+    fn restore(&self, current: &mut Current) -> Result<Option<Override>, OverrideError> {
+        self.0.handle.reload(self.build_filter(current, None)?)?;
+
+        current.generation += 1;
+
+        Ok(current.active.take())
+    }
+
+    fn base_directives(&self, current: &Current) -> String {
+        match &current.config {
+            Some(config) if !self.0.startup.is_empty() => format!("{},{config}", self.0.startup),
+            Some(config) => config.clone(),
+            None => self.0.startup.clone(),
+        }
+    }
+
+    fn build_filter(&self, current: &Current, directives: Option<&str>) -> Result<EnvFilter, tracing_subscriber::filter::ParseError> {
+        let base = self.base_directives(current);
+
+        match directives {
+            Some(directives) if !base.is_empty() => {
+                EnvFilter::try_new(format!("{base},{directives}"))
+            }
+            Some(directives) => EnvFilter::try_new(directives),
+            None => EnvFilter::try_new(&base),
+        }
+    }
+}
@@ -5,14 +5,18 @@ use clap::Parser;
 use futures::StreamExt;
 use futures::stream::FuturesUnordered;
 use tokio::runtime::Builder;
-use tracing_subscriber::{FmtSubscriber, EnvFilter};
+use tracing_subscriber::{fmt, reload, EnvFilter};
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
 
 mod serde;
 mod error;
 mod path;
 mod fs;
 mod config;
+mod logging;
 mod db;
+mod email;
 mod templates;
 mod sec;
 mod state;
@@ -20,10 +24,16 @@ mod state;
 mod cookie;
 mod header;
 
+mod workspace;
 mod user;
 mod journal;
+mod report;
+mod reminder;
+mod telemetry;
 
 mod router;
+mod jobs;
+mod cli;
 
 use error::{Error, Context};
 
@@ -43,8 +53,12 @@ fn main() {
         filter = filter.add_directive(log_str.parse().unwrap());
     }
 
-    if let Err(err) = FmtSubscriber::builder()
-        .with_env_filter(filter)
+    let (filter_layer, filter_handle) = reload::Layer::new(filter.clone());
+    let logging = logging::Logging::new(&filter, filter_handle);
+
+    if let Err(err) = tracing_subscriber::registry()
+        .with(filter_layer)
+        .with(fmt::layer())
         .try_init()
         .context("failed to initialize stdout logging") {
         error::log_error(&err);
@@ -61,7 +75,7 @@ fn main() {
         }
     };
 
-    if let Err(err) = setup(args, config) {
+    if let Err(err) = setup(args, config, logging) {
         error::log_error(&err);
 
         std::process::exit(1);
@@ -71,7 +85,7 @@ fn main() {
 }
 
 /// configures the tokio runtime and starts the init process for the server
-fn setup(args: config::CliArgs, config: config::Config) -> Result<(), Error> {
+fn setup(args: config::CliArgs, config: config::Config, logging: logging::Logging) -> Result<(), Error> {
     let mut builder = if config.settings.thread_pool == 1 {
         Builder::new_current_thread()
     } else {
@@ -84,13 +98,19 @@ fn setup(args: config::CliArgs, config: config::Config) -> Result<(), Error> {
         .build()
         .context("failed to create tokio runtime")?;
 
-    rt.block_on(init(args, config))
+    rt.block_on(init(args, config, logging))
 }
 
 /// initializes the server with the shared state, router configuration, and
 /// database setup
-async fn init(args: config::CliArgs, config: config::Config) -> Result<(), Error> {
-    let state = state::SharedState::new(&config)
+async fn init(args: config::CliArgs, config: config::Config, logging: logging::Logging) -> Result<(), Error> {
+    if let Some(command) = &args.command {
+        if cli::prepare(&config, command).await? {
+            return Ok(());
+        }
+    }
+
+    let state = state::SharedState::new(&config, logging)
         .await
         .context("failed to create SharedState")?;
 
@@ -98,6 +118,16 @@ async fn init(args: config::CliArgs, config: config::Config) -> Result<(), Error
         db::gen_test_data(&state).await?;
     }
 
+    if args.preview_telemetry {
+        return telemetry::preview(&state).await;
+    }
+
+    if let Some(command) = args.command {
+        return cli::run(&config, &state, command).await;
+    }
+
+    jobs::start(&state);
+
     let router = router::build(&state);
 
     let mut server_handles = Vec::with_capacity(config.settings.listeners.len());
@@ -112,8 +142,18 @@ async fn init(args: config::CliArgs, config: config::Config) -> Result<(), Error
         all_futs.push(tokio::spawn(start_server(listener, local_router, local_handle)));
     }
 
+    if let Some(addr) = config.settings.health.addr {
+        let handle = axum_server::Handle::new();
+
+        server_handles.push(handle.clone());
+        all_futs.push(tokio::spawn(start_health_server(addr, router::health::build(&state), handle)));
+    }
+
     all_futs.push(tokio::spawn(handle_signal(server_handles)));
 
+    #[cfg(unix)]
+    tokio::spawn(handle_reload(state.clone()));
+
     while (all_futs.next().await).is_some() {}
 
     tracing::info!("closing database connections");
@@ -148,6 +188,23 @@ async fn start_server(listener: config::Listener, router: Router, handle: axum_s
     }
 }
 
+/// entry point for a tokio task to start the plain http listener for the
+/// health checks
+async fn start_health_server(addr: SocketAddr, router: Router, handle: axum_server::Handle) {
+    let result = match create_listener(&addr) {
+        Ok(listener) => axum_server::from_tcp(listener)
+            .handle(handle)
+            .serve(router.into_make_service())
+            .await
+            .context("error when running health server"),
+        Err(err) => Err(err),
+    };
+
+    if let Err(err) = result {
+        error::log_error(&err);
+    }
+}
+
 /// creates an http server
 ///
 /// if the listener is specified to be a tls server it will be ignored
@@ -161,7 +218,7 @@ async fn create_server(
 
     axum_server::from_tcp(listener)
         .handle(handle)
-        .serve(router.into_make_service())
+        .serve(router.into_make_service_with_connect_info::<SocketAddr>())
         .await
         .context("error when running server")
 }
@@ -201,6 +258,29 @@ async fn create_server(
     }
 }
 
+/// reloads the config every time a SIGHUP is received
+#[cfg(unix)]
+async fn handle_reload(state: state::SharedState) {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut hangup = match signal(SignalKind::hangup()) {
+        Ok(stream) => stream,
+        Err(err) => {
+            tracing::error!("error when listening for SIGHUP. {err}");
+
+            return;
+        }
+    };
+
+    while hangup.recv().await.is_some() {
+        tracing::info!("received SIGHUP. reloading config");
+
+        if let Err(err) = state.reload().await {
+            error::log_prefix_error("failed to reload config", &err);
+        }
+    }
+}
+
 /// a signal handle that will shutdown all known listening servers
 async fn handle_signal(handles: Vec<axum_server::Handle>) {
     if let Err(err) = tokio::signal::ctrl_c().await {
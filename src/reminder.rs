@@ -0,0 +1,273 @@
+//! daily reminders for users to write an entry
+//!
+//! the [`JobKind::Reminders`] job checks the enabled reminders and notifies
+//! the user once the local time of their timezone has passed the reminder
+//! time and the journal has no entry for the local date. a reminder is only
+//! checked once per local day
+//!
+//! [`JobKind::Reminders`]: crate::jobs::JobKind::Reminders
+
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::str::FromStr;
+
+use bytes::BytesMut;
+use chrono::{DateTime, NaiveDate, NaiveTime, Utc};
+use chrono_tz::Tz;
+use postgres_types as pg_types;
+use serde::{Serialize, Deserialize};
+
+use crate::db::{GenericClient, PgError};
+use crate::db::ids::{JournalId, UserId};
+use crate::error::BoxDynError;
+
+#[derive(Debug, thiserror::Error)]
+#[error("the provided string is not a valid reminder notify")]
+pub struct InvalidReminderNotify;
+
+/// how a user is notified of a reminder
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ReminderNotify {
+    /// sends an email to the verified backup email of the user
+    Email,
+
+    /// sends a "reminder.due" event to the webhooks of the journal
+    Webhook,
+}
+
+impl ReminderNotify {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ReminderNotify::Email => "email",
+            ReminderNotify::Webhook => "webhook",
+        }
+    }
+}
+
+impl Display for ReminderNotify {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for ReminderNotify {
+    type Err = InvalidReminderNotify;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "email" => Ok(ReminderNotify::Email),
+            "webhook" => Ok(ReminderNotify::Webhook),
+            _ => Err(InvalidReminderNotify)
+        }
+    }
+}
+
+impl<'a> pg_types::FromSql<'a> for ReminderNotify {
+    fn from_sql(ty: &pg_types::Type, raw: &'a [u8]) -> Result<Self, BoxDynError> {
+        let v = <&str as pg_types::FromSql>::from_sql(ty, raw)?;
+
+        Ok(Self::from_str(v)?)
+    }
+
+    fn accepts(ty: &pg_types::Type) -> bool {
+        <&str as pg_types::FromSql>::accepts(ty)
+    }
+}
+
+impl pg_types::ToSql for ReminderNotify {
+    fn to_sql(&self, ty: &pg_types::Type, w: &mut BytesMut) -> Result<pg_types::IsNull, BoxDynError> {
+        self.as_str()
+            .to_sql(ty, w)
+    }
+
+    fn accepts(ty: &pg_types::Type) -> bool {
+        <&str as pg_types::ToSql>::accepts(ty)
+    }
+
+    pg_types::to_sql_checked!();
+}
+
+#[derive(Debug, Serialize)]
+pub struct Reminder {
+    pub users_id: UserId,
+    pub journals_id: JournalId,
+
+    /// the local time of day to send the reminder
+    pub remind_at: NaiveTime,
+
+    /// the IANA name of the timezone for the user. ex: "America/Denver"
+    pub timezone: String,
+    pub notify: ReminderNotify,
+    pub enabled: bool,
+
+    /// the local date of the last time the reminder was checked
+    pub last_sent: Option<NaiveDate>,
+    pub created: DateTime<Utc>,
+    pub updated: Option<DateTime<Utc>>,
+}
+
+impl Reminder {
+    fn map_row(row: tokio_postgres::Row) -> Self {
+        Self {
+            users_id: row.get(0),
+            journals_id: row.get(1),
+            remind_at: row.get(2),
+            timezone: row.get(3),
+            notify: row.get(4),
+            enabled: row.get(5),
+            last_sent: row.get(6),
+            created: row.get(7),
+            updated: row.get(8),
+        }
+    }
+
+    pub async fn retrieve(conn: &impl GenericClient, users_id: &UserId) -> Result<Option<Self>, PgError> {
+        conn.query_opt(
+            "\
+            select users_id, \
+                   journals_id, \
+                   remind_at, \
+                   timezone, \
+                   notify, \
+                   enabled, \
+                   last_sent, \
+                   created, \
+                   updated \
+            from user_reminders \
+            where users_id = $1",
+            &[users_id]
+        )
+            .await
+            .map(|maybe| maybe.map(Self::map_row))
+    }
+
+    /// retrieves every enabled reminder
+    pub async fn retrieve_enabled(conn: &impl GenericClient) -> Result<Vec<Self>, PgError> {
+        conn.query(
+            "\
+            select users_id, \
+                   journals_id, \
+                   remind_at, \
+                   timezone, \
+                   notify, \
+                   enabled, \
+                   last_sent, \
+                   created, \
+                   updated \
+            from user_reminders \
+            where enabled",
+            &[]
+        )
+            .await
+            .map(|rows| rows.into_iter().map(Self::map_row).collect())
+    }
+
+    /// creates or replaces the reminder of a user
+    ///
+    /// the timezone is expected to already be validated
+    pub async fn set(
+        conn: &impl GenericClient,
+        users_id: &UserId,
+        journals_id: &JournalId,
+        remind_at: NaiveTime,
+        timezone: Tz,
+        notify: ReminderNotify,
+        enabled: bool,
+    ) -> Result<Self, PgError> {
+        let now = Utc::now();
+        let timezone = timezone.name().to_owned();
+
+        let row = conn.query_one(
+            "\
+            insert into user_reminders ( \
+                users_id, \
+                journals_id, \
+                remind_at, \
+                timezone, \
+                notify, \
+                enabled, \
+                created \
+            ) values ($1, $2, $3, $4, $5, $6, $7) \
+            on conflict (users_id) do update \
+                set journals_id = excluded.journals_id, \
+                    remind_at = excluded.remind_at, \
+                    timezone = excluded.timezone, \
+                    notify = excluded.notify, \
+                    enabled = excluded.enabled, \
+                    updated = excluded.created \
+            returning last_sent, created, updated",
+            &[users_id, journals_id, &remind_at, &timezone, &notify, &enabled, &now]
+        ).await?;
+
+        Ok(Self {
+            users_id: *users_id,
+            journals_id: *journals_id,
+            remind_at,
+            timezone,
+            notify,
+            enabled,
+            last_sent: row.get(0),
+            created: row.get(1),
+            updated: row.get(2),
+        })
+    }
+
+    /// removes the reminder of a user
+    ///
+    /// returns false if the user did not have a reminder
+    pub async fn delete(conn: &impl GenericClient, users_id: &UserId) -> Result<bool, PgError> {
+        let result = conn.execute(
+            "delete from user_reminders where users_id = $1",
+            &[users_id]
+        ).await?;
+
+        Ok(result == 1)
+    }
+
+    /// the timezone of the reminder. falls back to UTC if the stored name is
+    /// no longer known
+    pub fn tz(&self) -> Tz {
+        self.timezone.parse().unwrap_or(Tz::UTC)
+    }
+
+    /// returns the local date of the user if the reminder is due
+    pub fn is_due(&self, now: &DateTime<Utc>) -> Option<NaiveDate> {
+        let local = now.with_timezone(&self.tz());
+        let today = local.date_naive();
+
+        if local.time() < self.remind_at || self.last_sent == Some(today) {
+            None
+        } else {
+            Some(today)
+        }
+    }
+
+    /// checks if the journal of the reminder has an entry for the given date
+    pub async fn has_entry(&self, conn: &impl GenericClient, date: &NaiveDate) -> Result<bool, PgError> {
+        conn.query_one(
+            "\
+            select exists ( \
+                select 1 \
+                from entries \
+                where journals_id = $1 and \
+                      entry_date = $2 \
+            )",
+            &[&self.journals_id, date]
+        )
+            .await
+            .map(|row| row.get(0))
+    }
+
+    /// records the local date that the reminder was checked on so that it is
+    /// not sent again for the same day
+    pub async fn mark_sent(&mut self, conn: &impl GenericClient, date: NaiveDate) -> Result<(), PgError> {
+        conn.execute(
+            "update user_reminders set last_sent = $2 where users_id = $1",
+            &[&self.users_id, &date]
+        ).await?;
+
+        self.last_sent = Some(date);
+
+        Ok(())
+    }
+}
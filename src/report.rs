@@ -0,0 +1,150 @@
+use chrono::{NaiveDate, DateTime, Days, Utc};
+use futures::{Stream, StreamExt};
+use serde::Serialize;
+
+use crate::db::{self, GenericClient, PgError};
+
+/// the number of days a user must have been seen in to be counted as active
+pub const ACTIVE_DAYS: u64 = 30;
+
+/// a summary of the deployment for a single UTC day
+#[derive(Debug, Serialize)]
+pub struct UsageReport {
+    pub date: NaiveDate,
+    pub total_users: i64,
+    pub active_users: i64,
+    pub entries_written: i64,
+    pub total_entries: i64,
+    pub storage_growth: i64,
+    pub storage_total: i64,
+    pub created: DateTime<Utc>,
+}
+
+/// the start of the given day in UTC
+fn day_start(date: &NaiveDate) -> DateTime<Utc> {
+    date.and_hms_opt(0, 0, 0)
+        .unwrap()
+        .and_utc()
+}
+
+impl UsageReport {
+    fn map_row(row: tokio_postgres::Row) -> Self {
+        Self {
+            date: row.get(0),
+            total_users: row.get(1),
+            active_users: row.get(2),
+            entries_written: row.get(3),
+            total_entries: row.get(4),
+            storage_growth: row.get(5),
+            storage_total: row.get(6),
+            created: row.get(7),
+        }
+    }
+
+    /// computes the report for the given day and stores it, replacing any
+    /// previous report for the same day
+    ///
+    /// a user is considered active if they were issued a session or
+    /// created / updated an entry in the [`ACTIVE_DAYS`] leading up to the
+    /// end of the day
+    pub async fn generate(conn: &impl GenericClient, date: &NaiveDate) -> Result<Self, PgError> {
+        let start = day_start(date);
+        let end = date.checked_add_days(Days::new(1))
+            .map(|next| day_start(&next))
+            .unwrap_or(start);
+        let active_start = date.checked_sub_days(Days::new(ACTIVE_DAYS - 1))
+            .map(|prev| day_start(&prev))
+            .unwrap_or(start);
+        let created = Utc::now();
+
+        let row = conn.query_one(
+            "\
+            insert into usage_reports ( \
+                report_date, \
+                total_users, \
+                active_users, \
+                entries_written, \
+                total_entries, \
+                storage_growth, \
+                storage_total, \
+                created \
+            ) \
+            select $1, \
+                   (select count(*) from users where created < $3), \
+                   (select count(distinct active.users_id) from ( \
+                       select authn_sessions.users_id \
+                       from authn_sessions \
+                       where authn_sessions.issued_on >= $4 and \
+                             authn_sessions.issued_on < $3 \
+                       union \
+                       select entries.users_id \
+                       from entries \
+                       where coalesce(entries.updated, entries.created) >= $4 and \
+                             coalesce(entries.updated, entries.created) < $3 \
+                   ) as active), \
+                   (select count(*) from entries where created >= $2 and created < $3), \
+                   (select count(*) from entries where created < $3), \
+                   (select coalesce(sum(size), 0)::bigint from file_entries where created >= $2 and created < $3), \
+                   (select coalesce(sum(size), 0)::bigint from file_entries where created < $3), \
+                   $5 \
+            on conflict (report_date) do update \
+                set total_users = excluded.total_users, \
+                    active_users = excluded.active_users, \
+                    entries_written = excluded.entries_written, \
+                    total_entries = excluded.total_entries, \
+                    storage_growth = excluded.storage_growth, \
+                    storage_total = excluded.storage_total, \
+                    created = excluded.created \
+            returning report_date, \
+                      total_users, \
+                      active_users, \
+                      entries_written, \
+                      total_entries, \
+                      storage_growth, \
+                      storage_total, \
+                      created",
+            &[date, &start, &end, &active_start, &created]
+        ).await?;
+
+        Ok(Self::map_row(row))
+    }
+
+    /// checks if a report already exists for the given day
+    pub async fn exists(conn: &impl GenericClient, date: &NaiveDate) -> Result<bool, PgError> {
+        conn.query_opt(
+            "select report_date from usage_reports where report_date = $1",
+            &[date]
+        )
+            .await
+            .map(|maybe| maybe.is_some())
+    }
+
+    /// retrieves the reports between the two days, inclusive, ordered by
+    /// date
+    pub async fn retrieve_range_stream(
+        conn: &impl GenericClient,
+        from: &NaiveDate,
+        to: &NaiveDate,
+    ) -> Result<impl Stream<Item = Result<Self, PgError>>, PgError> {
+        let params: db::ParamsArray<'_, 2> = [from, to];
+
+        Ok(conn.query_raw(
+            "\
+            select usage_reports.report_date, \
+                   usage_reports.total_users, \
+                   usage_reports.active_users, \
+                   usage_reports.entries_written, \
+                   usage_reports.total_entries, \
+                   usage_reports.storage_growth, \
+                   usage_reports.storage_total, \
+                   usage_reports.created \
+            from usage_reports \
+            where usage_reports.report_date >= $1 and \
+                  usage_reports.report_date <= $2 \
+            order by usage_reports.report_date",
+            params
+        )
+            .await?
+            .map(|result| result.map(Self::map_row)))
+    }
+}
@@ -5,27 +5,47 @@ use axum::Router;
 use axum::body::Body;
 use axum::error_handling::HandleErrorLayer;
 use axum::extract::ConnectInfo;
-use axum::http::{Uri, Request, HeaderMap, StatusCode};
+use axum::http::{header, Extensions, HeaderName, HeaderValue, Method, Uri, Request, HeaderMap, StatusCode, Version};
+use axum::middleware;
 use axum::response::{Response, IntoResponse};
 use axum::routing::{get, post};
 use tower::ServiceBuilder;
+use tower_http::compression::CompressionLayer;
+use tower_http::compression::predicate::{Predicate, SizeAbove};
+use tower_http::cors::{AllowOrigin, CorsLayer};
 use tower_http::trace::TraceLayer;
 use tower_http::classify::ServerErrorsFailureClass;
 use tracing::Span;
 use serde::Serialize;
 
+use crate::config;
+use crate::db;
 use crate::state;
 use crate::error::{self, Context};
 
 mod layer;
 mod assets;
+mod acl;
+mod csrf;
+mod security;
+
+pub mod health;
 
 pub mod macros;
 pub mod body;
 
 mod auth;
+mod account;
+mod settings;
+mod prompts;
+mod recovery;
+mod workspace;
 mod journals;
+mod entries;
 mod admin;
+mod live;
+#[cfg(feature = "graphql")]
+mod graphql;
 
 async fn ping() -> (StatusCode, &'static str) {
     (StatusCode::OK, "pong")
@@ -65,16 +85,112 @@ where
     wrapper
 }
 
-pub fn build(state: &state::SharedState) -> Router {
+/// the routes that are scoped to a workspace
+///
+/// these will be available at the root of the server and also under the
+/// workspace path prefix
+fn workspace_routes(state: &state::SharedState) -> Router<state::SharedState> {
+    let member_layer = middleware::from_fn_with_state(
+        state.clone(),
+        workspace::require_member
+    );
+
     Router::new()
         .route("/", get(retrieve_root))
+        .nest("/journals", journals::build(state)
+            .route_layer(member_layer.clone()))
+        .nest("/entries", entries::build(state)
+            .route_layer(member_layer.clone()))
+        .nest("/admin", admin::build(state)
+            .route_layer(member_layer)
+            .route_layer(middleware::from_fn_with_state(state.clone(), acl::admin_acl)))
+}
+
+/// checks that a response is text that benefits from being compressed.
+/// files, archives, and event streams are sent as is
+fn is_compressible(_status: StatusCode, _version: Version, headers: &HeaderMap, _extensions: &Extensions) -> bool {
+    let Some(content_type) = headers.get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok()) else {
+        return false;
+    };
+
+    if content_type.starts_with("text/event-stream") {
+        return false;
+    }
+
+    content_type.starts_with("application/json") ||
+        content_type.starts_with("text/") ||
+        content_type.starts_with("application/javascript") ||
+        content_type.starts_with("image/svg+xml")
+}
+
+/// creates the layer that responds to preflight requests and adds the cors
+/// headers for the allowed origins
+fn cors_layer(cors: &config::Cors) -> CorsLayer {
+    let allow_origin = if cors.allowed_origins.iter().any(|origin| origin == "*") {
+        AllowOrigin::any()
+    } else {
+        AllowOrigin::list(cors.allowed_origins.iter()
+            .filter_map(|origin| HeaderValue::from_str(origin).ok()))
+    };
+
+    let mut allow_headers = vec![
+        header::CONTENT_TYPE,
+        HeaderName::from_static("x-json-case"),
+        HeaderName::from_static("x-csrf-token"),
+    ];
+
+    allow_headers.extend(cors.allowed_headers.iter()
+        .filter_map(|name| HeaderName::from_bytes(name.as_bytes()).ok()));
+
+    CorsLayer::new()
+        .allow_origin(allow_origin)
+        .allow_methods([
+            Method::GET,
+            Method::POST,
+            Method::PUT,
+            Method::PATCH,
+            Method::DELETE,
+        ])
+        .allow_headers(allow_headers)
+        .allow_credentials(cors.allow_credentials)
+        .max_age(Duration::from_secs(cors.max_age))
+}
+
+pub fn build(state: &state::SharedState) -> Router {
+    let scoped = workspace_routes(state);
+    let router = Router::new()
         .route("/ping", get(ping))
+        .route("/healthz", get(health::healthz))
+        .route("/readyz", get(health::readyz))
+        .route("/ws", get(live::handle));
+
+    #[cfg(feature = "graphql")]
+    let router = router.route("/graphql", post(graphql::handle)
+        .layer(axum::Extension(graphql::schema(state))));
+
+    let router = router
         .route("/login", get(auth::login)
             .post(auth::request_login))
         .route("/logout", post(auth::request_logout))
-        .nest("/journals", journals::build(state))
-        .nest("/admin", admin::build(state))
+        .route("/auth/csrf", get(auth::retrieve_csrf))
+        .route("/auth/webauthn/register/options", post(auth::passkey::register_options))
+        .route("/auth/webauthn/register", post(auth::passkey::register))
+        .route("/auth/webauthn/authenticate/options", post(auth::passkey::authenticate_options))
+        .route("/auth/webauthn/authenticate", post(auth::passkey::authenticate))
+        .route("/auth/password/forgot", post(recovery::forgot_password))
+        .route("/auth/password/reset", post(recovery::reset_password))
+        .nest("/account", account::build(state))
+        .nest("/settings", settings::build(state))
+        .nest("/prompts", prompts::build(state))
+        .nest("/recovery", recovery::build(state))
+        .merge(scoped.clone())
+        .nest(&format!("/w/:{}", workspace::PATH_PARAM), scoped)
         .fallback(assets::handle)
+        .layer(middleware::from_fn(db::tx::finalize))
+        .layer(middleware::from_fn_with_state(state.clone(), csrf::require_token))
+        .layer(middleware::from_fn_with_state(state.clone(), body::json_case))
+        .layer(middleware::from_fn_with_state(state.clone(), security::add_headers))
         .layer(ServiceBuilder::new()
             .layer(layer::RIDLayer::new())
             .layer(TraceLayer::new_for_http()
@@ -84,7 +200,27 @@ pub fn build(state: &state::SharedState) -> Router {
                 .on_failure(on_failure))
             .layer(HandleErrorLayer::new(handle_error))
             .layer(layer::TimeoutLayer::new(Duration::new(90, 0))))
-        .with_state(state.clone())
+        .with_state(state.clone());
+
+    let router = if state.api().compression {
+        let predicate = SizeAbove::new(state.api().compression_min_size)
+            .and(is_compressible);
+
+        router.layer(CompressionLayer::new()
+            .gzip(true)
+            .br(true)
+            .compress_when(predicate))
+    } else {
+        router
+    };
+
+    // cross-origin requests are rejected by the browser without the
+    // headers so the layer is only added when origins are configured
+    if let Some(cors) = state.cors() {
+        router.layer(cors_layer(cors))
+    } else {
+        router
+    }
 }
 
 fn make_span_with(request: &Request<Body>) -> Span {
@@ -0,0 +1,125 @@
+use axum::Router;
+use axum::http::{StatusCode, HeaderMap, Uri};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, delete};
+use serde::{Deserialize, Serialize};
+use validator::ValidateEmail;
+
+use crate::db;
+use crate::email;
+use crate::error::{self, Context};
+use crate::router::{body, macros};
+use crate::sec::authn::recovery::RecoveryEmail;
+use crate::state;
+
+pub fn build(_state: &state::SharedState) -> Router<state::SharedState> {
+    Router::new()
+        .route("/recovery_email", get(retrieve_recovery_email)
+            .put(update_recovery_email)
+            .delete(delete_recovery_email))
+        .route("/passkeys", get(super::auth::passkey::retrieve_passkeys))
+        .route("/passkeys/:passkeys_id", delete(super::auth::passkey::delete_passkey))
+}
+
+async fn retrieve_recovery_email(
+    state: state::SharedState,
+    uri: Uri,
+    headers: HeaderMap,
+) -> Result<Response, error::Error> {
+    let conn = state.db_conn().await?;
+
+    let initiator = macros::require_initiator!(
+        &conn,
+        &headers,
+        Some(uri.clone())
+    );
+
+    macros::res_if_html!(state.templates(), &headers);
+
+    let result = RecoveryEmail::retrieve(&conn, &initiator.user.id)
+        .await
+        .context("failed to retrieve backup email")?;
+
+    if let Some(email) = result {
+        Ok(body::Json(email).into_response())
+    } else {
+        Ok(StatusCode::NOT_FOUND.into_response())
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateRecoveryEmail {
+    email: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "type")]
+pub enum UpdateRecoveryEmailResult {
+    InvalidEmail,
+    Updated(RecoveryEmail),
+}
+
+async fn update_recovery_email(
+    state: state::SharedState,
+    headers: HeaderMap,
+    body::Json(json): body::Json<UpdateRecoveryEmail>,
+) -> Result<Response, error::Error> {
+    let mut conn = state.db_conn().await?;
+    let transaction = conn.transaction()
+        .await
+        .context("failed to create transaction")?;
+
+    let initiator = macros::require_initiator!(
+        &transaction,
+        &headers,
+        None::<&str>
+    );
+
+    let email = json.email.trim().to_owned();
+
+    if !email.validate_email() {
+        return Ok(body::FieldError::new(
+            "email",
+            UpdateRecoveryEmailResult::InvalidEmail
+        ).into_response());
+    }
+
+    let (recovery_email, message) = RecoveryEmail::set(&transaction, &initiator.user.id, email).await?;
+
+    transaction.commit()
+        .await
+        .context("failed to commit transaction")?;
+
+    email::deliver(state.mailer(), message);
+
+    Ok(body::Json(UpdateRecoveryEmailResult::Updated(recovery_email)).into_response())
+}
+
+async fn delete_recovery_email(
+    db::Conn(mut conn): db::Conn,
+    headers: HeaderMap,
+) -> Result<Response, error::Error> {
+    let transaction = conn.transaction()
+        .await
+        .context("failed to create transaction")?;
+
+    let initiator = macros::require_initiator!(
+        &transaction,
+        &headers,
+        None::<&str>
+    );
+
+    let removed = RecoveryEmail::delete(&transaction, &initiator.user.id)
+        .await
+        .context("failed to delete backup email")?;
+
+    if !removed {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    }
+
+    transaction.commit()
+        .await
+        .context("failed to commit transaction")?;
+
+    Ok(StatusCode::OK.into_response())
+}
@@ -0,0 +1,37 @@
+use std::net::SocketAddr;
+
+use axum::extract::{ConnectInfo, Request};
+use axum::http::StatusCode;
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+
+use crate::sec::network::client_ip;
+use crate::state;
+
+/// middleware that will reject requests for the admin routes from addresses
+/// that are not allowed by the admin acl
+///
+/// denied attempts are logged with the client address and requested path
+pub async fn admin_acl(
+    state: state::SharedState,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    req: Request,
+    next: Next,
+) -> Response {
+    let network = state.network();
+    let client = client_ip(network, &peer, req.headers());
+
+    if !network.acl.admin.is_allowed(&client) {
+        tracing::warn!(
+            client = %client,
+            peer = %peer,
+            method = %req.method(),
+            uri = %req.uri(),
+            "admin acl denied request"
+        );
+
+        return StatusCode::FORBIDDEN.into_response();
+    }
+
+    next.run(req).await
+}
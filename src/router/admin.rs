@@ -1,15 +1,26 @@
 use axum::Router;
 use axum::http::{Uri, HeaderMap};
 use axum::response::{IntoResponse, Response};
-use axum::routing::get;
+use axum::routing::{get, post, put, patch};
 
+use crate::db;
+use crate::db::ids::WorkspaceId;
 use crate::state;
-use crate::error;
+use crate::error::{self, Context};
 use crate::router::{body, macros};
+use crate::sec::authn::Initiator;
+use crate::sec::authz;
+use crate::workspace::WorkspaceUser;
 
 mod users;
+mod recovery;
 mod groups;
 mod roles;
+mod reports;
+mod workspaces;
+mod logging;
+mod config;
+mod jobs;
 
 pub fn build(_state: &state::SharedState) -> Router<state::SharedState> {
     Router::new()
@@ -17,9 +28,13 @@ pub fn build(_state: &state::SharedState) -> Router<state::SharedState> {
         .route("/users", get(users::retrieve_users)
             .post(users::create_user))
         .route("/users/new", get(users::retrieve_user))
+        .route("/users/bulk", post(users::create_users_bulk))
         .route("/users/:users_id", get(users::retrieve_user)
             .patch(users::update_user)
             .delete(users::delete_user))
+        .route("/users/:users_id/recovery", get(recovery::retrieve_pending))
+        .route("/users/:users_id/recovery/:recovery_id", post(recovery::approve_recovery))
+        .route("/users/:users_id/password_reset", post(recovery::issue_password_reset))
         .route("/groups", get(groups::retrieve_groups)
             .post(groups::create_group))
         .route("/groups/new", get(groups::retrieve_group))
@@ -32,6 +47,81 @@ pub fn build(_state: &state::SharedState) -> Router<state::SharedState> {
         .route("/roles/:role_id", get(roles::retrieve_role)
             .patch(roles::update_role)
             .delete(roles::delete_role))
+        .route("/workspaces", get(workspaces::retrieve_workspaces)
+            .post(workspaces::create_workspace))
+        .route("/workspaces/:workspaces_id", get(workspaces::retrieve_workspace)
+            .patch(workspaces::update_workspace))
+        .route("/workspaces/:workspaces_id/users/:users_id", put(workspaces::upsert_workspace_user)
+            .delete(workspaces::delete_workspace_user))
+        .route("/reports/usage", get(reports::retrieve_usage))
+        .route("/logging", get(logging::retrieve_logging)
+            .put(logging::update_logging)
+            .delete(logging::delete_logging))
+        .route("/config/reload", post(config::reload_config))
+        .route("/jobs", get(jobs::retrieve_jobs))
+        .route("/jobs/:name", patch(jobs::update_job))
+        .route("/jobs/:name/run", post(jobs::run_job))
+}
+
+/// how the initiator is allowed to manage a workspace
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Access {
+    /// the initiator has the server permission which applies to every
+    /// workspace
+    Server,
+
+    /// the initiator is an admin of the workspace and does not have the
+    /// server permission
+    Workspace,
+}
+
+impl Access {
+    /// determines the access of the initiator from their server permission
+    /// and their membership of the workspace
+    fn resolve(
+        has_permission: bool,
+        member: Option<&WorkspaceUser>,
+        workspaces_id: &WorkspaceId,
+    ) -> Option<Self> {
+        if has_permission {
+            Some(Access::Server)
+        } else if member.is_some_and(|member| member.admin && member.workspaces_id == *workspaces_id) {
+            Some(Access::Workspace)
+        } else {
+            None
+        }
+    }
+}
+
+/// retrieves how the initiator is allowed to manage the given workspace
+///
+/// the initiator needs the server permission for the scope and ability or
+/// must be an admin of the workspace
+pub async fn workspace_access(
+    conn: &impl db::GenericClient,
+    initiator: &Initiator,
+    workspaces_id: &WorkspaceId,
+    scope: authz::Scope,
+    ability: authz::Ability,
+) -> Result<Option<Access>, error::Error> {
+    let perm_check = authz::has_permission(
+        conn,
+        initiator.user.id,
+        scope,
+        ability
+    )
+        .await
+        .context("failed to retrieve permission for user")?;
+
+    let member = if perm_check {
+        None
+    } else {
+        WorkspaceUser::retrieve(conn, workspaces_id, &initiator.user.id)
+            .await
+            .context("failed to retrieve workspace member")?
+    };
+
+    Ok(Access::resolve(perm_check, member.as_ref(), workspaces_id))
 }
 
 async fn retrieve_admin(
@@ -51,3 +141,43 @@ async fn retrieve_admin(
 
     Ok(body::Json("okay").into_response())
 }
+
+#[cfg(test)]
+mod test {
+    use chrono::Utc;
+
+    use super::*;
+
+    fn member(workspaces_id: i64, admin: bool) -> WorkspaceUser {
+        WorkspaceUser {
+            workspaces_id: WorkspaceId::new(workspaces_id).unwrap(),
+            users_id: crate::db::ids::UserId::new(1).unwrap(),
+            admin,
+            added: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn workspace_admin_only_manages_own_workspace() {
+        let own = WorkspaceId::new(1).unwrap();
+        let other = WorkspaceId::new(2).unwrap();
+        let admin = member(1, true);
+
+        assert_eq!(Access::resolve(false, Some(&admin), &own), Some(Access::Workspace));
+        assert_eq!(Access::resolve(false, Some(&admin), &other), None);
+    }
+
+    #[test]
+    fn members_are_not_admins() {
+        let own = WorkspaceId::new(1).unwrap();
+
+        assert_eq!(Access::resolve(false, Some(&member(1, false)), &own), None);
+        assert_eq!(Access::resolve(false, None, &own), None);
+    }
+
+    #[test]
+    fn server_permission_manages_any_workspace() {
+        assert_eq!(Access::resolve(true, None, &WorkspaceId::new(1).unwrap()), Some(Access::Server));
+        assert_eq!(Access::resolve(true, Some(&member(2, false)), &WorkspaceId::new(1).unwrap()), Some(Access::Server));
+    }
+}
@@ -0,0 +1,56 @@
+use axum::http::{HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
+use serde::Serialize;
+
+use crate::error::{self, Context};
+use crate::router::body;
+use crate::router::macros;
+use crate::sec::authz;
+use crate::state;
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "type")]
+pub enum ReloadResult {
+    InvalidConfig {
+        message: String,
+    },
+}
+
+/// reads the config file again and applies the logging, asset, and template
+/// settings without restarting the server
+pub async fn reload_config(
+    state: state::SharedState,
+    headers: HeaderMap,
+) -> Result<Response, error::Error> {
+    let conn = state.db_conn().await?;
+
+    let initiator = macros::require_initiator!(&conn, &headers, None::<&'static str>);
+
+    let perm_check = authz::has_permission(
+        &conn,
+        initiator.user.id,
+        authz::Scope::Server,
+        authz::Ability::Update
+    )
+        .await
+        .context("failed to retrieve permission for user")?;
+
+    if !perm_check {
+        return Ok(StatusCode::UNAUTHORIZED.into_response());
+    }
+
+    if let Err(err) = state.reload().await {
+        error::log_prefix_error("failed to reload config", &err);
+
+        return Ok(body::FieldError::new(
+            "config",
+            ReloadResult::InvalidConfig {
+                message: err.to_string(),
+            }
+        ).into_response());
+    }
+
+    tracing::warn!(users_id = %initiator.user.id, "config reloaded");
+
+    Ok(StatusCode::OK.into_response())
+}
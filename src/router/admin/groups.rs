@@ -246,9 +246,9 @@ pub async fn create_group(
         .context("failed to create new group")?;
 
     let Some(group) = result else {
-        return Ok((
-            StatusCode::BAD_REQUEST,
-            body::Json(NewGroupResult::GroupExists)
+        return Ok(body::FieldError::new(
+            "name",
+            NewGroupResult::GroupExists
         ).into_response())
     };
 
@@ -256,11 +256,11 @@ pub async fn create_group(
         .await?;
 
     if !not_found.is_empty() {
-        return Ok((
-            StatusCode::BAD_REQUEST,
-            body::Json(NewGroupResult::UsersNotFound {
+        return Ok(body::FieldError::new(
+            "users",
+            NewGroupResult::UsersNotFound {
                 ids: not_found
-            })
+            }
         ).into_response());
     }
 
@@ -268,11 +268,11 @@ pub async fn create_group(
         .await?;
 
     if !not_found.is_empty() {
-        return Ok((
-            StatusCode::BAD_REQUEST,
-            body::Json(NewGroupResult::RolesNotFound {
+        return Ok(body::FieldError::new(
+            "roles",
+            NewGroupResult::RolesNotFound {
                 ids: not_found
-            })
+            }
         ).into_response());
     }
 
@@ -358,9 +358,9 @@ pub async fn update_group(
             .context("failed to update group")?;
 
         if !did_update {
-            return Ok((
-                StatusCode::BAD_REQUEST,
-                body::Json(UpdateGroupResult::GroupExists)
+            return Ok(body::FieldError::new(
+                "name",
+                UpdateGroupResult::GroupExists
             ).into_response());
         }
     }
@@ -369,11 +369,11 @@ pub async fn update_group(
         .await?;
 
     if !not_found.is_empty() {
-        return Ok((
-            StatusCode::BAD_REQUEST,
-            body::Json(UpdateGroupResult::UsersNotFound {
+        return Ok(body::FieldError::new(
+            "users",
+            UpdateGroupResult::UsersNotFound {
                 ids: not_found
-            })
+            }
         ).into_response());
     }
 
@@ -381,11 +381,11 @@ pub async fn update_group(
         .await?;
 
     if !not_found.is_empty() {
-        return Ok((
-            StatusCode::BAD_REQUEST,
-            body::Json(UpdateGroupResult::RolesNotFound {
+        return Ok(body::FieldError::new(
+            "roles",
+            UpdateGroupResult::RolesNotFound {
                 ids: not_found
-            })
+            }
         ).into_response());
     }
 
@@ -0,0 +1,188 @@
+use axum::extract::Path;
+use axum::http::{HeaderMap, StatusCode, Uri};
+use axum::response::{IntoResponse, Response};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::error::{self, Context};
+use crate::jobs::JobKind;
+use crate::jobs::schedule::{self, Job};
+use crate::router::body;
+use crate::router::macros;
+use crate::sec::authz;
+use crate::state;
+
+#[derive(Debug, Deserialize)]
+pub struct JobPath {
+    name: JobKind,
+}
+
+#[derive(Debug, Serialize)]
+pub struct JobFull {
+    #[serde(flatten)]
+    job: Job,
+
+    /// the next time the job will run. None if the job is disabled or the
+    /// schedule is invalid
+    next_run: Option<DateTime<Utc>>,
+}
+
+impl From<Job> for JobFull {
+    fn from(job: Job) -> Self {
+        let next_run = if job.enabled {
+            schedule::parse_schedule(&job.schedule)
+                .ok()
+                .and_then(|parsed| job.next_run(&parsed, &Utc::now()))
+        } else {
+            None
+        };
+
+        Self {
+            job,
+            next_run,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "type")]
+pub enum JobResult {
+    InvalidSchedule {
+        message: String,
+    },
+}
+
+/// lists the jobs of the server with the result of their last run
+pub async fn retrieve_jobs(
+    state: state::SharedState,
+    uri: Uri,
+    headers: HeaderMap,
+) -> Result<Response, error::Error> {
+    let conn = state.db_conn().await?;
+
+    let initiator = macros::require_initiator!(&conn, &headers, Some(uri));
+
+    macros::res_if_html!(state.templates(), &headers);
+
+    let perm_check = authz::has_permission(
+        &conn,
+        initiator.user.id,
+        authz::Scope::Server,
+        authz::Ability::Read
+    )
+        .await
+        .context("failed to retrieve permission for user")?;
+
+    if !perm_check {
+        return Ok(StatusCode::UNAUTHORIZED.into_response());
+    }
+
+    let jobs: Vec<JobFull> = Job::retrieve_all(&conn)
+        .await
+        .context("failed to retrieve jobs")?
+        .into_iter()
+        .map(JobFull::from)
+        .collect();
+
+    Ok(body::Json(jobs).into_response())
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateJob {
+    schedule: Option<String>,
+    enabled: Option<bool>,
+}
+
+/// updates the schedule of a job or enables / disables it
+pub async fn update_job(
+    state: state::SharedState,
+    headers: HeaderMap,
+    Path(JobPath { name }): Path<JobPath>,
+    body::Json(json): body::Json<UpdateJob>,
+) -> Result<Response, error::Error> {
+    let conn = state.db_conn().await?;
+
+    let initiator = macros::require_initiator!(&conn, &headers, None::<&'static str>);
+
+    let perm_check = authz::has_permission(
+        &conn,
+        initiator.user.id,
+        authz::Scope::Server,
+        authz::Ability::Update
+    )
+        .await
+        .context("failed to retrieve permission for user")?;
+
+    if !perm_check {
+        return Ok(StatusCode::UNAUTHORIZED.into_response());
+    }
+
+    let Some(mut job) = Job::retrieve(&conn, &name)
+        .await
+        .context("failed to retrieve job")? else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+
+    if let Some(given) = json.schedule {
+        let given = given.trim().to_owned();
+
+        if let Err(err) = schedule::parse_schedule(&given) {
+            return Ok(body::FieldError::new(
+                "schedule",
+                JobResult::InvalidSchedule {
+                    message: err.to_string(),
+                }
+            ).into_response());
+        }
+
+        job.schedule = given;
+    }
+
+    if let Some(enabled) = json.enabled {
+        job.enabled = enabled;
+    }
+
+    job.update(&conn)
+        .await
+        .context("failed to update job")?;
+
+    Ok(body::Json(JobFull::from(job)).into_response())
+}
+
+/// runs a job in the background without waiting for its schedule
+pub async fn run_job(
+    state: state::SharedState,
+    headers: HeaderMap,
+    Path(JobPath { name }): Path<JobPath>,
+) -> Result<Response, error::Error> {
+    let conn = state.db_conn().await?;
+
+    let initiator = macros::require_initiator!(&conn, &headers, None::<&'static str>);
+
+    let perm_check = authz::has_permission(
+        &conn,
+        initiator.user.id,
+        authz::Scope::Server,
+        authz::Ability::Update
+    )
+        .await
+        .context("failed to retrieve permission for user")?;
+
+    if !perm_check {
+        return Ok(StatusCode::UNAUTHORIZED.into_response());
+    }
+
+    tracing::info!(
+        users_id = %initiator.user.id,
+        job = %name,
+        "job triggered manually"
+    );
+
+    let local_state = state.clone();
+
+    tokio::spawn(async move {
+        schedule::run_job(&local_state, name).await;
+    });
+
+    Ok(StatusCode::ACCEPTED.into_response())
+}
@@ -0,0 +1,173 @@
+use axum::http::{HeaderMap, StatusCode, Uri};
+use axum::response::{IntoResponse, Response};
+use chrono::Duration;
+use serde::{Deserialize, Serialize};
+
+use crate::error::{self, Context};
+use crate::logging::{self, Override, OverrideError};
+use crate::router::body;
+use crate::router::macros;
+use crate::sec::authz;
+use crate::state;
+
+#[derive(Debug, Serialize)]
+pub struct LoggingFilter {
+    base: String,
+    active: Option<Override>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "type")]
+pub enum LoggingResult {
+    InvalidDirectives,
+    InvalidDuration,
+}
+
+/// retrieves the base tracing filter and the active override
+pub async fn retrieve_logging(
+    state: state::SharedState,
+    uri: Uri,
+    headers: HeaderMap,
+) -> Result<Response, error::Error> {
+    let conn = state.db_conn().await?;
+
+    let initiator = macros::require_initiator!(&conn, &headers, Some(uri));
+
+    macros::res_if_html!(state.templates(), &headers);
+
+    let perm_check = authz::has_permission(
+        &conn,
+        initiator.user.id,
+        authz::Scope::Server,
+        authz::Ability::Read
+    )
+        .await
+        .context("failed to retrieve permission for user")?;
+
+    if !perm_check {
+        return Ok(StatusCode::UNAUTHORIZED.into_response());
+    }
+
+    Ok(body::Json(LoggingFilter {
+        base: state.logging().base(),
+        active: state.logging().active(),
+    }).into_response())
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateLogging {
+    /// the directives to add to the base filter. uses the same format as
+    /// RUST_LOG
+    directives: String,
+
+    /// the number of minutes until the override is reverted
+    minutes: Option<i64>,
+}
+
+/// adds directives to the tracing filter until the given duration has
+/// passed. replaces any override that is already active
+pub async fn update_logging(
+    state: state::SharedState,
+    headers: HeaderMap,
+    body::Json(json): body::Json<UpdateLogging>,
+) -> Result<Response, error::Error> {
+    let conn = state.db_conn().await?;
+
+    let initiator = macros::require_initiator!(&conn, &headers, None::<&'static str>);
+
+    let perm_check = authz::has_permission(
+        &conn,
+        initiator.user.id,
+        authz::Scope::Server,
+        authz::Ability::Update
+    )
+        .await
+        .context("failed to retrieve permission for user")?;
+
+    if !perm_check {
+        return Ok(StatusCode::UNAUTHORIZED.into_response());
+    }
+
+    let duration = json.minutes.map_or(logging::DEFAULT_DURATION, Duration::minutes);
+
+    if duration <= Duration::zero() || duration > logging::MAX_DURATION {
+        return Ok(body::FieldError::new(
+            "minutes",
+            LoggingResult::InvalidDuration
+        ).into_response());
+    }
+
+    let directives = json.directives.trim().to_owned();
+
+    if directives.is_empty() {
+        return Ok(body::FieldError::new(
+            "directives",
+            LoggingResult::InvalidDirectives
+        ).into_response());
+    }
+
+    let active = match state.logging().set(directives, initiator.user.id, duration) {
+        Ok(active) => active,
+        Err(OverrideError::InvalidDirectives(_)) => {
+            return Ok(body::FieldError::new(
+                "directives",
+                LoggingResult::InvalidDirectives
+            ).into_response());
+        }
+        Err(err) => {
+            return Err(error::Error::context_source(
+                "failed to update tracing filter",
+                err
+            ));
+        }
+    };
+
+    tracing::warn!(
+        users_id = %active.users_id,
+        directives = %active.directives,
+        expires = %active.expires,
+        "tracing override set"
+    );
+
+    Ok(body::Json(LoggingFilter {
+        base: state.logging().base(),
+        active: Some(active),
+    }).into_response())
+}
+
+/// removes the active override and restores the base tracing filter
+pub async fn delete_logging(
+    state: state::SharedState,
+    headers: HeaderMap,
+) -> Result<Response, error::Error> {
+    let conn = state.db_conn().await?;
+
+    let initiator = macros::require_initiator!(&conn, &headers, None::<&'static str>);
+
+    let perm_check = authz::has_permission(
+        &conn,
+        initiator.user.id,
+        authz::Scope::Server,
+        authz::Ability::Update
+    )
+        .await
+        .context("failed to retrieve permission for user")?;
+
+    if !perm_check {
+        return Ok(StatusCode::UNAUTHORIZED.into_response());
+    }
+
+    let removed = state.logging()
+        .clear()
+        .context("failed to restore tracing filter")?;
+
+    if let Some(removed) = removed {
+        tracing::warn!(
+            users_id = %initiator.user.id,
+            directives = %removed.directives,
+            "tracing override removed"
+        );
+    }
+
+    Ok(StatusCode::OK.into_response())
+}
@@ -0,0 +1,211 @@
+use axum::extract::Path;
+use axum::http::{HeaderMap, Uri, StatusCode};
+use axum::response::{IntoResponse, Response};
+use futures::StreamExt;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::db;
+use crate::db::ids::{UserId, RecoveryId};
+use crate::error::{self, Context};
+use crate::router::body;
+use crate::router::macros;
+use crate::sec::authn::Initiator;
+use crate::sec::authn::recovery::{Recovery, RecoveryKind};
+use crate::sec::authz;
+use crate::state;
+use crate::user::User;
+use crate::workspace::{Workspace, WorkspaceUser};
+
+use super::{workspace_access, Access};
+use super::users::can_change_user;
+
+#[derive(Debug, Deserialize)]
+pub struct UserPath {
+    users_id: UserId,
+}
+
+/// checks that the initiator can manage the recovery of the given user
+///
+/// a workspace admin can only manage the users of their workspace that they
+/// are able to change
+async fn can_recover(
+    conn: &impl db::GenericClient,
+    initiator: &Initiator,
+    workspace: &Workspace,
+    users_id: &UserId,
+    ability: authz::Ability,
+) -> Result<bool, error::Error> {
+    let access = workspace_access(conn, initiator, &workspace.id, authz::Scope::Users, ability).await?;
+
+    let Some(access) = access else {
+        return Ok(false);
+    };
+
+    if access == Access::Server {
+        return Ok(true);
+    }
+
+    let member = WorkspaceUser::retrieve(conn, &workspace.id, users_id)
+        .await
+        .context("failed to retrieve workspace user")?;
+
+    if member.is_none() {
+        return Ok(false);
+    }
+
+    can_change_user(conn, access, &workspace.id, users_id).await
+}
+
+pub async fn retrieve_pending(
+    state: state::SharedState,
+    workspace: Workspace,
+    uri: Uri,
+    headers: HeaderMap,
+    Path(UserPath { users_id }): Path<UserPath>,
+) -> Result<Response, error::Error> {
+    let conn = state.db_conn().await?;
+
+    let initiator = macros::require_initiator!(
+        &conn,
+        &headers,
+        Some(uri.clone())
+    );
+
+    macros::res_if_html!(state.templates(), &headers);
+
+    if !can_recover(&conn, &initiator, &workspace, &users_id, authz::Ability::Read).await? {
+        return Ok(StatusCode::UNAUTHORIZED.into_response());
+    }
+
+    let pending = Recovery::retrieve_pending(&conn, &users_id)
+        .await
+        .context("failed to retrieve pending recovery requests")?;
+
+    futures::pin_mut!(pending);
+
+    let mut found = Vec::new();
+
+    while let Some(result) = pending.next().await {
+        found.push(result.context("failed to retrieve recovery record")?);
+    }
+
+    Ok(body::Json(found).into_response())
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RecoveryPath {
+    users_id: UserId,
+    recovery_id: RecoveryId,
+}
+
+/// approves a recovery request that requires admin approval before it can be
+/// completed
+pub async fn approve_recovery(
+    db::Conn(mut conn): db::Conn,
+    workspace: Workspace,
+    headers: HeaderMap,
+    Path(RecoveryPath { users_id, recovery_id }): Path<RecoveryPath>,
+) -> Result<Response, error::Error> {
+    let transaction = conn.transaction()
+        .await
+        .context("failed to create transaction")?;
+
+    let initiator = macros::require_initiator!(
+        &transaction,
+        &headers,
+        None::<&str>
+    );
+
+    if !can_recover(&transaction, &initiator, &workspace, &users_id, authz::Ability::Update).await? {
+        return Ok(StatusCode::UNAUTHORIZED.into_response());
+    }
+
+    let approved = Recovery::approve(&transaction, &users_id, &recovery_id, &initiator.user.id)
+        .await
+        .context("failed to approve recovery request")?;
+
+    if !approved {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    }
+
+    transaction.commit()
+        .await
+        .context("failed to commit transaction")?;
+
+    tracing::info!(
+        users_id = %users_id,
+        recovery_id = %recovery_id,
+        approved_by = %initiator.user.id,
+        "recovery request approved"
+    );
+
+    Ok(StatusCode::OK.into_response())
+}
+
+#[derive(Debug, Serialize)]
+pub struct IssuedReset {
+    token: String,
+    expires_on: DateTime<Utc>,
+}
+
+/// issues a password reset token for a user
+///
+/// the token is returned to the admin so that it can be given to users that
+/// do not have a verified backup email
+pub async fn issue_password_reset(
+    db::Conn(mut conn): db::Conn,
+    workspace: Workspace,
+    headers: HeaderMap,
+    Path(UserPath { users_id }): Path<UserPath>,
+) -> Result<Response, error::Error> {
+    let transaction = conn.transaction()
+        .await
+        .context("failed to create transaction")?;
+
+    let initiator = macros::require_initiator!(
+        &transaction,
+        &headers,
+        None::<&str>
+    );
+
+    if !can_recover(&transaction, &initiator, &workspace, &users_id, authz::Ability::Update).await? {
+        return Ok(StatusCode::UNAUTHORIZED.into_response());
+    }
+
+    let user = User::retrieve_id(&transaction, users_id)
+        .await
+        .context("failed to retrieve user")?;
+
+    if user.is_none() {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    }
+
+    let (recovery, token) = Recovery::issue(
+        &transaction,
+        &users_id,
+        RecoveryKind::Password,
+        &initiator.user.id
+    )
+        .await
+        .context("failed to issue password reset")?;
+
+    transaction.commit()
+        .await
+        .context("failed to commit transaction")?;
+
+    tracing::info!(
+        users_id = %users_id,
+        recovery_id = %recovery.id,
+        issued_by = %initiator.user.id,
+        "password reset issued"
+    );
+
+    Ok((
+        StatusCode::CREATED,
+        body::Json(IssuedReset {
+            token: token.as_base64(),
+            expires_on: recovery.expires_on,
+        })
+    ).into_response())
+}
@@ -0,0 +1,79 @@
+use axum::extract::Query;
+use axum::http::{HeaderMap, StatusCode, Uri};
+use axum::response::{IntoResponse, Response};
+use chrono::{Days, NaiveDate, Utc};
+use futures::StreamExt;
+use serde::{Deserialize, Serialize};
+
+use crate::error::{self, Context};
+use crate::report::{UsageReport, ACTIVE_DAYS};
+use crate::router::body;
+use crate::router::macros;
+use crate::state;
+use crate::sec::authz;
+
+#[derive(Debug, Deserialize)]
+pub struct UsageQuery {
+    from: Option<NaiveDate>,
+    to: Option<NaiveDate>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "type")]
+pub enum UsageResult {
+    InvalidRange,
+}
+
+/// retrieves the daily usage reports for the deployment
+///
+/// defaults to the last 30 days if no range is specified
+pub async fn retrieve_usage(
+    state: state::SharedState,
+    uri: Uri,
+    headers: HeaderMap,
+    Query(UsageQuery { from, to }): Query<UsageQuery>,
+) -> Result<Response, error::Error> {
+    let conn = state.db_conn().await?;
+
+    let initiator = macros::require_initiator!(&conn, &headers, Some(uri));
+
+    macros::res_if_html!(state.templates(), &headers);
+
+    let perm_check = authz::has_permission(
+        &conn,
+        initiator.user.id,
+        authz::Scope::Reports,
+        authz::Ability::Read
+    )
+        .await
+        .context("failed to retrieve permission for user")?;
+
+    if !perm_check {
+        return Ok(StatusCode::UNAUTHORIZED.into_response());
+    }
+
+    let to = to.unwrap_or_else(|| Utc::now().date_naive());
+    let from = from.or_else(|| to.checked_sub_days(Days::new(ACTIVE_DAYS)))
+        .unwrap_or(to);
+
+    if from > to {
+        return Ok(body::FieldError::new(
+            "from",
+            UsageResult::InvalidRange
+        ).into_response());
+    }
+
+    let stream = UsageReport::retrieve_range_stream(&conn, &from, &to)
+        .await
+        .context("failed to retrieve usage reports")?;
+
+    futures::pin_mut!(stream);
+
+    let mut found = Vec::new();
+
+    while let Some(try_record) = stream.next().await {
+        found.push(try_record.context("failed to retrieve usage report")?);
+    }
+
+    Ok(body::Json(found).into_response())
+}
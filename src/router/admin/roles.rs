@@ -302,9 +302,9 @@ pub async fn create_role(
         .context("failed to create new role")?;
 
     let Some(role) = result else {
-        return Ok((
-            StatusCode::BAD_REQUEST,
-            body::Json(NewRoleResult::RoleExists)
+        return Ok(body::FieldError::new(
+            "name",
+            NewRoleResult::RoleExists
         ).into_response());
     };
 
@@ -313,22 +313,22 @@ pub async fn create_role(
     let (users, not_found) = create_attached_users(&transaction, &role, json.users).await?;
 
     if !not_found.is_empty() {
-        return Ok((
-            StatusCode::BAD_REQUEST,
-            body::Json(NewRoleResult::UsersNotFound {
+        return Ok(body::FieldError::new(
+            "users",
+            NewRoleResult::UsersNotFound {
                 ids: not_found
-            })
+            }
         ).into_response());
     }
 
     let (groups, not_found) = create_attached_groups(&transaction, &role, json.groups).await?;
 
     if !not_found.is_empty() {
-        return Ok((
-            StatusCode::BAD_REQUEST,
-            body::Json(NewRoleResult::GroupsNotFound {
+        return Ok(body::FieldError::new(
+            "groups",
+            NewRoleResult::GroupsNotFound {
                 ids: not_found
-            })
+            }
         ).into_response());
     }
 
@@ -413,9 +413,9 @@ pub async fn update_role(
             .context("failed to update role")?;
 
         if !did_update {
-            return Ok((
-                StatusCode::BAD_REQUEST,
-                body::Json(UpdateRoleResult::RoleExists)
+            return Ok(body::FieldError::new(
+                "name",
+                UpdateRoleResult::RoleExists
             ).into_response());
         }
     }
@@ -426,22 +426,22 @@ pub async fn update_role(
     let (_attached, not_found) = update_attached_users(&transaction, &role, json.users).await?;
 
     if !not_found.is_empty() {
-        return Ok((
-            StatusCode::BAD_REQUEST,
-            body::Json(UpdateRoleResult::UsersNotFound {
+        return Ok(body::FieldError::new(
+            "users",
+            UpdateRoleResult::UsersNotFound {
                 ids: not_found
-            })
+            }
         ).into_response());
     }
 
     let (_attached, not_found) = update_attached_groups(&transaction, &role, json.groups).await?;
 
     if !not_found.is_empty() {
-        return Ok((
-            StatusCode::BAD_REQUEST,
-            body::Json(UpdateRoleResult::GroupsNotFound {
+        return Ok(body::FieldError::new(
+            "groups",
+            UpdateRoleResult::GroupsNotFound {
                 ids: not_found
-            })
+            }
         ).into_response());
     }
 
@@ -1,4 +1,6 @@
-use axum::extract::{Request, Path};
+use std::str::FromStr;
+
+use axum::extract::{FromRequest, Request, Path};
 use axum::http::{HeaderMap, Uri, StatusCode};
 use axum::response::{IntoResponse, Response};
 use chrono::{DateTime, Utc};
@@ -6,7 +8,7 @@ use futures::StreamExt;
 use serde::{Deserialize, Serialize};
 
 use crate::db;
-use crate::db::ids::{UserId, UserUid, GroupId, RoleId};
+use crate::db::ids::{UserId, UserUid, GroupId, RoleId, WorkspaceId};
 use crate::error::{self, Context};
 use crate::router::body;
 use crate::router::macros;
@@ -14,6 +16,9 @@ use crate::state;
 use crate::sec::{password, authz};
 use crate::sec::authz::{AttachedRole, create_attached_roles, update_attached_roles};
 use crate::user::{User, AttachedGroup, create_attached_groups, update_attached_groups};
+use crate::workspace::{Workspace, WorkspaceUser};
+
+use super::{workspace_access, Access};
 
 #[derive(Debug, Serialize)]
 pub struct UserPartial {
@@ -24,8 +29,44 @@ pub struct UserPartial {
     updated: Option<DateTime<Utc>>,
 }
 
+/// checks that the initiator is able to change the given user of the
+/// workspace
+///
+/// workspace admins can only change users that are not in other workspaces
+/// and do not have any groups or roles since those give permissions outside
+/// of the workspace
+pub(super) async fn can_change_user(
+    conn: &impl db::GenericClient,
+    access: Access,
+    workspaces_id: &WorkspaceId,
+    users_id: &UserId,
+) -> Result<bool, error::Error> {
+    if access == Access::Server {
+        return Ok(true);
+    }
+
+    let outside: bool = conn.query_one(
+        "\
+        select exists( \
+                   select 1 \
+                   from workspace_users \
+                   where workspace_users.users_id = $1 and \
+                         workspace_users.workspaces_id != $2 \
+               ) or \
+               exists(select 1 from user_roles where user_roles.users_id = $1) or \
+               exists(select 1 from group_users where group_users.users_id = $1)",
+        &[users_id, workspaces_id]
+    )
+        .await
+        .context("failed to retrieve permissions of user outside of workspace")?
+        .get(0);
+
+    Ok(!outside)
+}
+
 pub async fn retrieve_users(
     state: state::SharedState,
+    workspace: Workspace,
     req: Request,
 ) -> Result<Response, error::Error> {
     let conn = state.db_conn().await?;
@@ -38,25 +79,27 @@ pub async fn retrieve_users(
 
     macros::res_if_html!(state.templates(), req.headers());
 
-    let perm_check = authz::has_permission(
+    let access = workspace_access(
         &conn,
-        initiator.user.id,
+        &initiator,
+        &workspace.id,
         authz::Scope::Users,
         authz::Ability::Read
-    )
-        .await
-        .context("failed to retrieve permission for user")?;
+    ).await?;
 
-    if !perm_check {
+    if access.is_none() {
         return Ok(StatusCode::UNAUTHORIZED.into_response());
     }
 
-    let params: db::ParamsArray<'_, 0> = [];
+    let params: db::ParamsArray<'_, 1> = [&workspace.id];
     let users = conn.query_raw(
         "\
         with search_users as ( \
-            select * \
+            select users.* \
             from users \
+                join workspace_users on \
+                    users.id = workspace_users.users_id \
+            where workspace_users.workspaces_id = $1 \
         ) \
         select search_users.id, \
                search_users.uid, \
@@ -156,6 +199,7 @@ pub struct MaybeUserId {
 
 pub async fn retrieve_user(
     state: state::SharedState,
+    workspace: Workspace,
     headers: HeaderMap,
     uri: Uri,
     Path(MaybeUserId { users_id }): Path<MaybeUserId>,
@@ -174,19 +218,26 @@ pub async fn retrieve_user(
         Some(uri.clone())
     );
 
-    let perm_check = authz::has_permission(
+    let access = workspace_access(
         &conn,
-        initiator.user.id,
+        &initiator,
+        &workspace.id,
         authz::Scope::Users,
-        authz::Ability::Read,
-    )
-        .await
-        .context("failed to retrieve permission for user")?;
+        authz::Ability::Read
+    ).await?;
 
-    if !perm_check  {
+    if access.is_none() {
         return Ok(StatusCode::UNAUTHORIZED.into_response());
     }
 
+    let member = WorkspaceUser::retrieve(&conn, &workspace.id, &users_id)
+        .await
+        .context("failed to retrieve workspace user")?;
+
+    if member.is_none() {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    }
+
     let result = UserFull::retrieve(&conn, &users_id)
         .await
         .context("failed to retrieve user")?;
@@ -221,6 +272,7 @@ pub enum NewUserResult {
 
 pub async fn create_user(
     db::Conn(mut conn): db::Conn,
+    workspace: Workspace,
     headers: HeaderMap,
     body::Json(json): body::Json<NewUser>,
 ) -> Result<Response, error::Error> {
@@ -234,16 +286,20 @@ pub async fn create_user(
         None::<&str>
     );
 
-    let perm_check = authz::has_permission(
+    let access = workspace_access(
         &transaction,
-        initiator.user.id,
+        &initiator,
+        &workspace.id,
         authz::Scope::Users,
-        authz::Ability::Create,
-    )
-        .await
-        .context("failed to retrieve permision for user")?;
+        authz::Ability::Create
+    ).await?;
+
+    let Some(access) = access else {
+        return Ok(StatusCode::UNAUTHORIZED.into_response());
+    };
 
-    if !perm_check {
+    // groups and roles give permissions outside of the workspace
+    if access == Access::Workspace && (!json.groups.is_empty() || !json.roles.is_empty()) {
         return Ok(StatusCode::UNAUTHORIZED.into_response());
     }
 
@@ -255,31 +311,35 @@ pub async fn create_user(
         .context("failed to create new user")?;
 
     let Some(user) = result else {
-        return Ok((
-            StatusCode::BAD_REQUEST,
-            body::Json(NewUserResult::UsernameExists)
+        return Ok(body::FieldError::new(
+            "username",
+            NewUserResult::UsernameExists
         ).into_response())
     };
 
+    WorkspaceUser::upsert(&transaction, &workspace.id, &user.id, false)
+        .await
+        .context("failed to add user to workspace")?;
+
     let (groups, not_found) = create_attached_groups(&transaction, &user, json.groups).await?;
 
     if !not_found.is_empty() {
-        return Ok((
-            StatusCode::BAD_REQUEST,
-            body::Json(NewUserResult::GroupsNotFound {
+        return Ok(body::FieldError::new(
+            "groups",
+            NewUserResult::GroupsNotFound {
                 ids: not_found
-            })
+            }
         ).into_response());
     }
 
     let (roles, not_found) = create_attached_roles(&transaction, &user, json.roles).await?;
 
     if !not_found.is_empty() {
-        return Ok((
-            StatusCode::BAD_REQUEST,
-            body::Json(NewUserResult::RolesNotFound {
+        return Ok(body::FieldError::new(
+            "roles",
+            NewUserResult::RolesNotFound {
                 ids: not_found
-            })
+            }
         ).into_response());
     }
 
@@ -298,6 +358,306 @@ pub async fn create_user(
     })).into_response())
 }
 
+/// the max number of users that can be created in a single bulk request
+pub const MAX_BULK_USERS: usize = 500;
+
+#[derive(Debug, Deserialize)]
+pub struct BulkUsers {
+    users: Vec<BulkUser>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct BulkUser {
+    username: String,
+
+    /// a temporary password is generated if one is not provided
+    password: Option<String>,
+    #[serde(default)]
+    groups: Vec<GroupId>,
+    #[serde(default)]
+    roles: Vec<RoleId>,
+}
+
+/// a single row of a csv bulk request. groups and roles are lists of ids
+/// separated by ";"
+#[derive(Debug, Deserialize)]
+pub struct CsvUser {
+    username: String,
+    password: Option<String>,
+    groups: Option<String>,
+    roles: Option<String>,
+}
+
+impl TryFrom<CsvUser> for BulkUser {
+    type Error = BulkUserResult;
+
+    fn try_from(record: CsvUser) -> Result<Self, Self::Error> {
+        let Some(groups) = parse_id_list(record.groups.as_deref()) else {
+            return Err(BulkUserResult::InvalidIds {
+                field: "groups"
+            });
+        };
+
+        let Some(roles) = parse_id_list(record.roles.as_deref()) else {
+            return Err(BulkUserResult::InvalidIds {
+                field: "roles"
+            });
+        };
+
+        Ok(BulkUser {
+            username: record.username,
+            password: record.password,
+            groups,
+            roles,
+        })
+    }
+}
+
+fn parse_id_list<T>(given: Option<&str>) -> Option<Vec<T>>
+where
+    T: FromStr
+{
+    let Some(given) = given else {
+        return Some(Vec::new());
+    };
+
+    given.split(';')
+        .map(str::trim)
+        .filter(|id| !id.is_empty())
+        .map(|id| id.parse().ok())
+        .collect()
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "type")]
+pub enum BulkUserResult {
+    InvalidIds {
+        field: &'static str,
+    },
+    InvalidUsername,
+    UsernameExists,
+    GroupsNotFound {
+        ids: Vec<GroupId>,
+    },
+    RolesNotFound {
+        ids: Vec<RoleId>,
+    },
+    Created {
+        user: UserFull,
+
+        /// the generated password if one was not provided. this is the only
+        /// time that it will be available
+        #[serde(skip_serializing_if = "Option::is_none")]
+        temporary_password: Option<String>,
+    },
+}
+
+#[derive(Debug, Serialize)]
+pub struct BulkUserRow {
+    /// the index of the user in the request starting at 0
+    row: usize,
+    username: String,
+    result: BulkUserResult,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "type")]
+pub enum BulkUsersResult {
+    TooManyUsers {
+        max: usize,
+    },
+}
+
+/// creates a list of users in the current workspace
+///
+/// accepts a json body of `{"users": [...]}` or a csv body with the headers
+/// "username,password,groups,roles". each user is created independently so a
+/// failed row will not prevent the others from being created
+pub async fn create_users_bulk(
+    db::Conn(mut conn): db::Conn,
+    state: state::SharedState,
+    workspace: Workspace,
+    req: Request,
+) -> Result<Response, error::Error> {
+    let headers = req.headers().clone();
+
+    let mut transaction = conn.transaction()
+        .await
+        .context("failed to create transaction")?;
+
+    let initiator = macros::require_initiator!(
+        &transaction,
+        &headers,
+        None::<&str>
+    );
+
+    let access = workspace_access(
+        &transaction,
+        &initiator,
+        &workspace.id,
+        authz::Scope::Users,
+        authz::Ability::Create
+    ).await?;
+
+    let Some(access) = access else {
+        return Ok(StatusCode::UNAUTHORIZED.into_response());
+    };
+
+    let given: Vec<Result<BulkUser, BulkUserResult>> = if body::is_csv_content_type(&headers) {
+        match body::Csv::<CsvUser>::from_request(req, &state).await {
+            Ok(body::Csv(records)) => records.into_iter()
+                .map(BulkUser::try_from)
+                .collect(),
+            Err(rejection) => return Ok(rejection),
+        }
+    } else {
+        match body::Json::<BulkUsers>::from_request(req, &state).await {
+            Ok(body::Json(json)) => json.users.into_iter()
+                .map(Ok)
+                .collect(),
+            Err(rejection) => return Ok(rejection),
+        }
+    };
+
+    // groups and roles give permissions outside of the workspace
+    if access == Access::Workspace && given.iter()
+        .flatten()
+        .any(|bulk_user| !bulk_user.groups.is_empty() || !bulk_user.roles.is_empty()) {
+        return Ok(StatusCode::UNAUTHORIZED.into_response());
+    }
+
+    if given.len() > MAX_BULK_USERS {
+        return Ok(body::FieldError::new(
+            "users",
+            BulkUsersResult::TooManyUsers {
+                max: MAX_BULK_USERS,
+            }
+        ).into_response());
+    }
+
+    let mut rows = Vec::with_capacity(given.len());
+
+    for (row, result) in given.into_iter().enumerate() {
+        let bulk_user = match result {
+            Ok(bulk_user) => bulk_user,
+            Err(result) => {
+                rows.push(BulkUserRow {
+                    row,
+                    username: String::new(),
+                    result,
+                });
+
+                continue;
+            }
+        };
+
+        let username = bulk_user.username.trim().to_owned();
+
+        if username.is_empty() {
+            rows.push(BulkUserRow {
+                row,
+                username,
+                result: BulkUserResult::InvalidUsername,
+            });
+
+            continue;
+        }
+
+        let (password, temporary_password) = match bulk_user.password {
+            Some(password) => (password, None),
+            None => {
+                let temporary = password::temporary();
+
+                (temporary.clone(), Some(temporary))
+            }
+        };
+
+        let hashed = tokio::task::spawn_blocking(move || password::create(password))
+            .await
+            .context("failed to join password hash task")?
+            .context("failed to hash new user password")?;
+
+        // each user is created in a savepoint so that a failed row only
+        // rolls back its own changes
+        let savepoint = transaction.savepoint("bulk_user")
+            .await
+            .context("failed to create savepoint")?;
+
+        let result = User::create(&savepoint, &username, &hashed, 0)
+            .await
+            .context("failed to create new user")?;
+
+        let Some(user) = result else {
+            rows.push(BulkUserRow {
+                row,
+                username,
+                result: BulkUserResult::UsernameExists,
+            });
+
+            continue;
+        };
+
+        WorkspaceUser::upsert(&savepoint, &workspace.id, &user.id, false)
+            .await
+            .context("failed to add user to workspace")?;
+
+        let (groups, not_found) = create_attached_groups(&savepoint, &user, bulk_user.groups).await?;
+
+        if !not_found.is_empty() {
+            rows.push(BulkUserRow {
+                row,
+                username,
+                result: BulkUserResult::GroupsNotFound {
+                    ids: not_found
+                },
+            });
+
+            continue;
+        }
+
+        let (roles, not_found) = create_attached_roles(&savepoint, &user, bulk_user.roles).await?;
+
+        if !not_found.is_empty() {
+            rows.push(BulkUserRow {
+                row,
+                username,
+                result: BulkUserResult::RolesNotFound {
+                    ids: not_found
+                },
+            });
+
+            continue;
+        }
+
+        savepoint.commit()
+            .await
+            .context("failed to release savepoint")?;
+
+        rows.push(BulkUserRow {
+            row,
+            username,
+            result: BulkUserResult::Created {
+                user: UserFull {
+                    id: user.id,
+                    uid: user.uid,
+                    username: user.username,
+                    created: user.created,
+                    updated: user.updated,
+                    groups,
+                    roles,
+                },
+                temporary_password,
+            },
+        });
+    }
+
+    transaction.commit()
+        .await
+        .context("failed to commit transaction")?;
+
+    Ok(body::Json(rows).into_response())
+}
+
 #[derive(Debug, Deserialize)]
 pub struct UpdateUser {
     username: Option<String>,
@@ -320,6 +680,7 @@ pub enum UpdatedUserResult {
 
 pub async fn update_user(
     db::Conn(mut conn): db::Conn,
+    workspace: Workspace,
     headers: HeaderMap,
     Path(UserPath { users_id }): Path<UserPath>,
     body::Json(json): body::Json<UpdateUser>,
@@ -334,16 +695,31 @@ pub async fn update_user(
         None::<&str>
     );
 
-    let perm_check = authz::has_permission(
+    let access = workspace_access(
         &transaction,
-        initiator.user.id,
+        &initiator,
+        &workspace.id,
         authz::Scope::Users,
         authz::Ability::Update
-    )
+    ).await?;
+
+    let Some(access) = access else {
+        return Ok(StatusCode::UNAUTHORIZED.into_response());
+    };
+
+    let member = WorkspaceUser::retrieve(&transaction, &workspace.id, &users_id)
         .await
-        .context("failed to retrie user permission")?;
+        .context("failed to retrieve workspace user")?;
 
-    if !perm_check {
+    if member.is_none() {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    }
+
+    if access == Access::Workspace && (json.groups.is_some() || json.roles.is_some()) {
+        return Ok(StatusCode::UNAUTHORIZED.into_response());
+    }
+
+    if !can_change_user(&transaction, access, &workspace.id, &users_id).await? {
         return Ok(StatusCode::UNAUTHORIZED.into_response());
     }
 
@@ -371,9 +747,9 @@ pub async fn update_user(
             .context("failed to update user")?;
 
         if !result {
-            return Ok((
-                StatusCode::BAD_REQUEST,
-                body::Json(UpdatedUserResult::UsernameExists)
+            return Ok(body::FieldError::new(
+                "username",
+                UpdatedUserResult::UsernameExists
             ).into_response());
         }
     }
@@ -382,11 +758,11 @@ pub async fn update_user(
         .await?;
 
     if !not_found.is_empty() {
-        return Ok((
-            StatusCode::BAD_REQUEST,
-            body::Json(UpdatedUserResult::GroupsNotFound {
+        return Ok(body::FieldError::new(
+            "groups",
+            UpdatedUserResult::GroupsNotFound {
                 ids: not_found
-            })
+            }
         ).into_response());
     }
 
@@ -394,11 +770,11 @@ pub async fn update_user(
         .await?;
 
     if !not_found.is_empty() {
-        return Ok((
-            StatusCode::BAD_REQUEST,
-            body::Json(UpdatedUserResult::RolesNotFound {
+        return Ok(body::FieldError::new(
+            "roles",
+            UpdatedUserResult::RolesNotFound {
                 ids: not_found
-            })
+            }
         ).into_response())
     }
 
@@ -409,8 +785,13 @@ pub async fn update_user(
     Ok(StatusCode::OK.into_response())
 }
 
+/// deletes a user of the workspace
+///
+/// if the user is also a member of other workspaces then they are only
+/// removed from this workspace
 pub async fn delete_user(
     db::Conn(mut conn): db::Conn,
+    workspace: Workspace,
     headers: HeaderMap,
     Path(UserPath { users_id }): Path<UserPath>
 ) -> Result<Response, error::Error> {
@@ -424,18 +805,17 @@ pub async fn delete_user(
         None::<&str>
     );
 
-    let perm_check = authz::has_permission(
+    let access = workspace_access(
         &transaction,
-        initiator.user.id,
+        &initiator,
+        &workspace.id,
         authz::Scope::Users,
-        authz::Ability::Delete,
-    )
-        .await
-        .context("failed to retrieve permision for user")?;
+        authz::Ability::Delete
+    ).await?;
 
-    if !perm_check {
+    let Some(access) = access else {
         return Ok(StatusCode::UNAUTHORIZED.into_response());
-    }
+    };
 
     let result = UserFull::retrieve(&transaction, &users_id)
         .await
@@ -445,6 +825,42 @@ pub async fn delete_user(
         return Ok(StatusCode::NOT_FOUND.into_response());
     };
 
+    let member = WorkspaceUser::retrieve(&transaction, &workspace.id, &user.id)
+        .await
+        .context("failed to retrieve workspace user")?;
+
+    if member.is_none() {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    }
+
+    if !can_change_user(&transaction, access, &workspace.id, &user.id).await? {
+        return Ok(StatusCode::UNAUTHORIZED.into_response());
+    }
+
+    let other_workspaces: i64 = transaction.query_one(
+        "\
+        select count(*) \
+        from workspace_users \
+        where workspace_users.users_id = $1 and \
+              workspace_users.workspaces_id != $2",
+        &[&user.id, &workspace.id]
+    )
+        .await
+        .context("failed to retrieve other workspaces of user")?
+        .get(0);
+
+    if other_workspaces > 0 {
+        WorkspaceUser::delete(&transaction, &workspace.id, &user.id)
+            .await
+            .context("failed to remove user from workspace")?;
+
+        transaction.commit()
+            .await
+            .context("failed to commit transaction")?;
+
+        return Ok(StatusCode::OK.into_response());
+    }
+
     let _groups = transaction.execute(
         "delete from group_users where users_id = $1",
         &[&user.id]
@@ -473,6 +889,65 @@ pub async fn delete_user(
         .await
         .context("failed to delete from authn totp")?;
 
+    let _recovery_email = transaction.execute(
+        "delete from authn_recovery_email where users_id = $1",
+        &[&user.id]
+    )
+        .await
+        .context("failed to delete from authn recovery email")?;
+
+    let _recovery = transaction.execute(
+        "delete from authn_recovery where users_id = $1",
+        &[&user.id]
+    )
+        .await
+        .context("failed to delete from authn recovery")?;
+
+    let _approved = transaction.execute(
+        "update authn_recovery set approved_by = null where approved_by = $1",
+        &[&user.id]
+    )
+        .await
+        .context("failed to clear approvals from authn recovery")?;
+
+    let _reminders = transaction.execute(
+        "delete from user_reminders where users_id = $1",
+        &[&user.id]
+    )
+        .await
+        .context("failed to delete from user reminders")?;
+
+    let _preferences = transaction.execute(
+        "delete from user_preferences where users_id = $1",
+        &[&user.id]
+    )
+        .await
+        .context("failed to delete from user preferences")?;
+
+    let _entry_prompts = transaction.execute(
+        "\
+        update entries \
+        set prompts_id = null \
+        where prompts_id in (select id from prompts where users_id = $1)",
+        &[&user.id]
+    )
+        .await
+        .context("failed to unlink entries from user prompts")?;
+
+    let _prompts = transaction.execute(
+        "delete from prompts where users_id = $1",
+        &[&user.id]
+    )
+        .await
+        .context("failed to delete from prompts")?;
+
+    let _workspaces = transaction.execute(
+        "delete from workspace_users where users_id = $1",
+        &[&user.id]
+    )
+        .await
+        .context("failed to delete from workspace users")?;
+
     // need to do something with the journals that the user owns
     // as the most costly part will be removing any files
 
@@ -0,0 +1,460 @@
+use axum::extract::Path;
+use axum::http::{HeaderMap, Uri, StatusCode};
+use axum::response::{IntoResponse, Response};
+use chrono::{DateTime, Utc};
+use futures::StreamExt;
+use serde::{Deserialize, Serialize};
+
+use crate::db;
+use crate::db::ids::{UserId, WorkspaceId};
+use crate::error::{self, Context};
+use crate::router::body;
+use crate::router::macros;
+use crate::state;
+use crate::sec::authn::Initiator;
+use crate::sec::authz;
+use crate::user::User;
+use crate::workspace::{Workspace, WorkspaceUser, WorkspaceError};
+
+/// checks to see if the initiator has the server level permission or is an
+/// admin of the specified workspace
+async fn can_manage(
+    conn: &impl db::GenericClient,
+    initiator: &Initiator,
+    workspaces_id: &WorkspaceId,
+    ability: authz::Ability,
+) -> Result<bool, error::Error> {
+    let access = super::workspace_access(conn, initiator, workspaces_id, authz::Scope::Workspaces, ability).await?;
+
+    Ok(access.is_some())
+}
+
+/// trims the given host and converts it to lowercase
+fn clean_host(given: Option<String>) -> Option<String> {
+    let trimmed = given?.trim().to_ascii_lowercase();
+
+    if trimmed.is_empty() {
+        None
+    } else {
+        Some(trimmed)
+    }
+}
+
+/// checks that a given path prefix is only made of ascii alphanumeric, "-",
+/// or "_" characters
+fn valid_path_prefix(given: &Option<String>) -> bool {
+    let Some(prefix) = given else {
+        return true;
+    };
+
+    !prefix.is_empty() && prefix.chars()
+        .all(|ch| ch.is_ascii_alphanumeric() || ch == '-' || ch == '_')
+}
+
+pub async fn retrieve_workspaces(
+    state: state::SharedState,
+    uri: Uri,
+    headers: HeaderMap,
+) -> Result<Response, error::Error> {
+    let conn = state.db_conn().await?;
+
+    let initiator = macros::require_initiator!(
+        &conn,
+        &headers,
+        Some(uri.clone())
+    );
+
+    macros::res_if_html!(state.templates(), &headers);
+
+    let perm_check = authz::has_permission(
+        &conn,
+        initiator.user.id,
+        authz::Scope::Workspaces,
+        authz::Ability::Read
+    )
+        .await
+        .context("failed to retrieve permission for user")?;
+
+    if !perm_check {
+        return Ok(StatusCode::UNAUTHORIZED.into_response());
+    }
+
+    let workspaces = Workspace::retrieve_stream(&conn)
+        .await
+        .context("failed to retrieve workspaces")?;
+
+    futures::pin_mut!(workspaces);
+
+    let mut found = Vec::new();
+
+    while let Some(result) = workspaces.next().await {
+        found.push(result.context("failed to retrieve workspace record")?);
+    }
+
+    Ok(body::Json(found).into_response())
+}
+
+#[derive(Debug, Deserialize)]
+pub struct WorkspacePath {
+    workspaces_id: WorkspaceId,
+}
+
+#[derive(Debug, Serialize)]
+pub struct WorkspaceMember {
+    users_id: UserId,
+    username: String,
+    admin: bool,
+    added: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct WorkspaceFull {
+    #[serde(flatten)]
+    workspace: Workspace,
+    users: Vec<WorkspaceMember>,
+}
+
+impl WorkspaceFull {
+    async fn retrieve(
+        conn: &impl db::GenericClient,
+        workspaces_id: &WorkspaceId,
+    ) -> Result<Option<Self>, error::Error> {
+        let result = Workspace::retrieve_id(conn, workspaces_id)
+            .await
+            .context("failed to retrieve workspace")?;
+
+        let Some(workspace) = result else {
+            return Ok(None);
+        };
+
+        let params: db::ParamsArray<'_, 1> = [&workspace.id];
+        let stream = conn.query_raw(
+            "\
+            select workspace_users.users_id, \
+                   users.username, \
+                   workspace_users.admin, \
+                   workspace_users.added \
+            from workspace_users \
+                left join users on \
+                    workspace_users.users_id = users.id \
+            where workspace_users.workspaces_id = $1 \
+            order by users.username",
+            params
+        )
+            .await
+            .context("failed to retrieve workspace users")?;
+
+        futures::pin_mut!(stream);
+
+        let mut users = Vec::new();
+
+        while let Some(result) = stream.next().await {
+            let record = result.context("failed to retrieve workspace user record")?;
+
+            users.push(WorkspaceMember {
+                users_id: record.get(0),
+                username: record.get(1),
+                admin: record.get(2),
+                added: record.get(3),
+            });
+        }
+
+        Ok(Some(Self {
+            workspace,
+            users
+        }))
+    }
+}
+
+pub async fn retrieve_workspace(
+    state: state::SharedState,
+    uri: Uri,
+    headers: HeaderMap,
+    Path(WorkspacePath { workspaces_id }): Path<WorkspacePath>,
+) -> Result<Response, error::Error> {
+    let conn = state.db_conn().await?;
+
+    let initiator = macros::require_initiator!(
+        &conn,
+        &headers,
+        Some(uri.clone())
+    );
+
+    macros::res_if_html!(state.templates(), &headers);
+
+    if !can_manage(&conn, &initiator, &workspaces_id, authz::Ability::Read).await? {
+        return Ok(StatusCode::UNAUTHORIZED.into_response());
+    }
+
+    let result = WorkspaceFull::retrieve(&conn, &workspaces_id).await?;
+
+    if let Some(workspace) = result {
+        Ok(body::Json(workspace).into_response())
+    } else {
+        Ok(StatusCode::NOT_FOUND.into_response())
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct NewWorkspace {
+    name: String,
+    host: Option<String>,
+    path_prefix: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "type")]
+pub enum NewWorkspaceResult {
+    NameExists,
+    HostExists,
+    PathPrefixExists,
+    InvalidPathPrefix,
+    Created(Workspace),
+}
+
+pub async fn create_workspace(
+    db::Conn(mut conn): db::Conn,
+    headers: HeaderMap,
+    body::Json(json): body::Json<NewWorkspace>,
+) -> Result<Response, error::Error> {
+    let transaction = conn.transaction()
+        .await
+        .context("failed to create transaction")?;
+
+    let initiator = macros::require_initiator!(
+        &transaction,
+        &headers,
+        None::<&str>
+    );
+
+    let perm_check = authz::has_permission(
+        &transaction,
+        initiator.user.id,
+        authz::Scope::Workspaces,
+        authz::Ability::Create,
+    )
+        .await
+        .context("failed to retrieve permission for user")?;
+
+    if !perm_check {
+        return Ok(StatusCode::UNAUTHORIZED.into_response());
+    }
+
+    if !valid_path_prefix(&json.path_prefix) {
+        return Ok(body::FieldError::new(
+            "path_prefix",
+            NewWorkspaceResult::InvalidPathPrefix
+        ).into_response());
+    }
+
+    let result = Workspace::create(
+        &transaction,
+        json.name,
+        clean_host(json.host),
+        json.path_prefix
+    ).await;
+
+    let workspace = match result {
+        Ok(workspace) => workspace,
+        Err(err) => {
+            let (field, rtn) = match err {
+                WorkspaceError::NameExists => ("name", NewWorkspaceResult::NameExists),
+                WorkspaceError::HostExists => ("host", NewWorkspaceResult::HostExists),
+                WorkspaceError::PathPrefixExists => ("path_prefix", NewWorkspaceResult::PathPrefixExists),
+                WorkspaceError::Db(err) => return Err(error::Error::context_source(
+                    "failed to create workspace",
+                    err
+                ))
+            };
+
+            return Ok(body::FieldError::new(field, rtn).into_response());
+        }
+    };
+
+    WorkspaceUser::upsert(&transaction, &workspace.id, &initiator.user.id, true)
+        .await
+        .context("failed to add creator to workspace")?;
+
+    transaction.commit()
+        .await
+        .context("failed to commit transaction")?;
+
+    Ok((
+        StatusCode::CREATED,
+        body::Json(NewWorkspaceResult::Created(workspace))
+    ).into_response())
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateWorkspace {
+    name: String,
+    host: Option<String>,
+    path_prefix: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "type")]
+pub enum UpdateWorkspaceResult {
+    NameExists,
+    HostExists,
+    PathPrefixExists,
+    InvalidPathPrefix,
+    Updated(Workspace),
+}
+
+pub async fn update_workspace(
+    db::Conn(mut conn): db::Conn,
+    headers: HeaderMap,
+    Path(WorkspacePath { workspaces_id }): Path<WorkspacePath>,
+    body::Json(json): body::Json<UpdateWorkspace>,
+) -> Result<Response, error::Error> {
+    let transaction = conn.transaction()
+        .await
+        .context("failed to create transaction")?;
+
+    let initiator = macros::require_initiator!(
+        &transaction,
+        &headers,
+        None::<&str>
+    );
+
+    let perm_check = authz::has_permission(
+        &transaction,
+        initiator.user.id,
+        authz::Scope::Workspaces,
+        authz::Ability::Update,
+    )
+        .await
+        .context("failed to retrieve permission for user")?;
+
+    if !perm_check {
+        return Ok(StatusCode::UNAUTHORIZED.into_response());
+    }
+
+    let result = Workspace::retrieve_id(&transaction, &workspaces_id)
+        .await
+        .context("failed to retrieve workspace")?;
+
+    let Some(mut workspace) = result else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+
+    if !valid_path_prefix(&json.path_prefix) {
+        return Ok(body::FieldError::new(
+            "path_prefix",
+            UpdateWorkspaceResult::InvalidPathPrefix
+        ).into_response());
+    }
+
+    workspace.name = json.name;
+    workspace.host = clean_host(json.host);
+    workspace.path_prefix = json.path_prefix;
+
+    if let Err(err) = workspace.update(&transaction).await {
+        let (field, rtn) = match err {
+            WorkspaceError::NameExists => ("name", UpdateWorkspaceResult::NameExists),
+            WorkspaceError::HostExists => ("host", UpdateWorkspaceResult::HostExists),
+            WorkspaceError::PathPrefixExists => ("path_prefix", UpdateWorkspaceResult::PathPrefixExists),
+            WorkspaceError::Db(err) => return Err(error::Error::context_source(
+                "failed to update workspace",
+                err
+            ))
+        };
+
+        return Ok(body::FieldError::new(field, rtn).into_response());
+    }
+
+    transaction.commit()
+        .await
+        .context("failed to commit transaction")?;
+
+    Ok(body::Json(UpdateWorkspaceResult::Updated(workspace)).into_response())
+}
+
+#[derive(Debug, Deserialize)]
+pub struct WorkspaceUserPath {
+    workspaces_id: WorkspaceId,
+    users_id: UserId,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpsertWorkspaceUser {
+    admin: bool,
+}
+
+pub async fn upsert_workspace_user(
+    db::Conn(mut conn): db::Conn,
+    headers: HeaderMap,
+    Path(WorkspaceUserPath { workspaces_id, users_id }): Path<WorkspaceUserPath>,
+    body::Json(json): body::Json<UpsertWorkspaceUser>,
+) -> Result<Response, error::Error> {
+    let transaction = conn.transaction()
+        .await
+        .context("failed to create transaction")?;
+
+    let initiator = macros::require_initiator!(
+        &transaction,
+        &headers,
+        None::<&str>
+    );
+
+    if !can_manage(&transaction, &initiator, &workspaces_id, authz::Ability::Update).await? {
+        return Ok(StatusCode::UNAUTHORIZED.into_response());
+    }
+
+    let (workspace, user) = tokio::join!(
+        Workspace::retrieve_id(&transaction, &workspaces_id),
+        User::retrieve_id(&transaction, users_id),
+    );
+
+    let workspace = workspace.context("failed to retrieve workspace")?;
+    let user = user.context("failed to retrieve user")?;
+
+    let (Some(workspace), Some(user)) = (workspace, user) else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+
+    let member = WorkspaceUser::upsert(&transaction, &workspace.id, &user.id, json.admin)
+        .await
+        .context("failed to add user to workspace")?;
+
+    transaction.commit()
+        .await
+        .context("failed to commit transaction")?;
+
+    Ok(body::Json(member).into_response())
+}
+
+pub async fn delete_workspace_user(
+    db::Conn(mut conn): db::Conn,
+    headers: HeaderMap,
+    Path(WorkspaceUserPath { workspaces_id, users_id }): Path<WorkspaceUserPath>,
+) -> Result<Response, error::Error> {
+    let transaction = conn.transaction()
+        .await
+        .context("failed to create transaction")?;
+
+    let initiator = macros::require_initiator!(
+        &transaction,
+        &headers,
+        None::<&str>
+    );
+
+    if !can_manage(&transaction, &initiator, &workspaces_id, authz::Ability::Update).await? {
+        return Ok(StatusCode::UNAUTHORIZED.into_response());
+    }
+
+    let removed = WorkspaceUser::delete(&transaction, &workspaces_id, &users_id)
+        .await
+        .context("failed to remove user from workspace")?;
+
+    if !removed {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    }
+
+    transaction.commit()
+        .await
+        .context("failed to commit transaction")?;
+
+    Ok(StatusCode::OK.into_response())
+}
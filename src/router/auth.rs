@@ -1,18 +1,23 @@
 use argon2::{Argon2, PasswordVerifier};
 use argon2::password_hash::PasswordHash;
-use axum::extract::Query;
+use std::net::SocketAddr;
+
+use axum::extract::{ConnectInfo, Query};
 use axum::http::{StatusCode, HeaderMap};
 use axum::response::{IntoResponse, Response};
 use serde::{Deserialize, Serialize};
 
 use crate::error::{self, Context};
 use crate::header::{Location, is_accepting_html};
-use crate::router::body;
+use crate::router::{body, macros};
 use crate::sec::authn::{Session, Initiator, InitiatorError};
 use crate::sec::authn::session::SessionOptions;
+use crate::sec::network::client_ip;
 use crate::state;
 use crate::user;
 
+pub mod passkey;
+
 #[derive(Debug, Serialize)]
 #[serde(tag = "type", content = "value")]
 pub enum LoginResult {
@@ -24,6 +29,7 @@ pub enum LoginResult {
 pub enum LoginFailed {
     UsernameNotFound,
     InvalidPassword,
+    UserDisabled,
 }
 
 #[derive(Debug, Deserialize)]
@@ -87,7 +93,8 @@ pub async fn login(
 
             Ok((
                 Session::clear_cookie(),
-                body::SpaPage::new(state.templates())?
+                Session::clear_csrf_cookie(),
+                body::SpaPage::new(&state.templates())?
             ).into_response())
         } else {
             Ok(Location::to(
@@ -128,6 +135,8 @@ pub struct LoginRequest {
 
 pub async fn request_login(
     state: state::SharedState,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
     body::Json(login): body::Json<LoginRequest>,
 ) -> Result<Response, error::Error> {
     let mut conn = state.db()
@@ -171,15 +180,24 @@ pub async fn request_login(
         ).into_response());
     }
 
+    if user.disabled.is_some() {
+        return Ok((
+            StatusCode::FORBIDDEN,
+            body::Json(LoginResult::Failed(LoginFailed::UserDisabled))
+        ).into_response());
+    }
+
     let mut options = SessionOptions::new(user.id);
     options.authenticated = true;
     options.verified = true;
+    options.client(&headers, client_ip(state.network(), &peer, &headers));
 
     let session = Session::create(&transaction, options)
         .await
         .context("failed to create session for login")?;
 
     let session_cookie = session.build_cookie();
+    let csrf_cookie = session.build_csrf_cookie();
 
     transaction.commit()
         .await
@@ -187,10 +205,31 @@ pub async fn request_login(
 
     Ok((
         session_cookie,
+        csrf_cookie,
         body::Json(LoginResult::Success)
     ).into_response())
 }
 
+#[derive(Debug, Serialize)]
+pub struct CsrfToken {
+    token: String,
+}
+
+/// retrieves the csrf token of the current session for clients that can
+/// not read the csrf cookie
+pub async fn retrieve_csrf(
+    state: state::SharedState,
+    headers: HeaderMap,
+) -> Result<Response, error::Error> {
+    let conn = state.db_conn().await?;
+
+    let initiator = macros::require_initiator!(&conn, &headers, None::<&'static str>);
+
+    Ok(body::Json(CsrfToken {
+        token: initiator.session.csrf_token.as_base64(),
+    }).into_response())
+}
+
 pub async fn request_logout(
     state: state::SharedState,
     headers: HeaderMap,
@@ -212,6 +251,7 @@ pub async fn request_logout(
         }
         Err(err) => match err{
             InitiatorError::UserNotFound(session) |
+            InitiatorError::UserDisabled(session) |
             InitiatorError::Unauthenticated(session) |
             InitiatorError::Unverified(session) |
             InitiatorError::SessionExpired(session) => {
@@ -236,6 +276,7 @@ pub async fn request_logout(
 
     Ok((
         StatusCode::OK,
-        Session::clear_cookie()
+        Session::clear_cookie(),
+        Session::clear_csrf_cookie()
     ).into_response())
 }
@@ -0,0 +1,501 @@
+use std::net::SocketAddr;
+
+use axum::extract::{ConnectInfo, Path};
+use axum::http::{StatusCode, Uri, HeaderMap};
+use axum::response::{IntoResponse, Response};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::db::ids::{PasskeyId, PasskeyChallengeId};
+use crate::error::{self, Context};
+use crate::router::body;
+use crate::router::macros;
+use crate::sec::authn::Session;
+use crate::sec::authn::passkey::{
+    self,
+    Challenge,
+    ChallengeKind,
+    CreatePasskeyError,
+    Passkey,
+    CHALLENGE_DURATION,
+    ALG_ES256,
+    ALG_EDDSA,
+};
+use crate::sec::authn::session::SessionOptions;
+use crate::sec::network::client_ip;
+use crate::state;
+use crate::user::User;
+
+use super::{LoginFailed, LoginResult};
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "type")]
+pub enum PasskeyResult {
+    InvalidChallenge,
+    InvalidCredential,
+    InvalidEncoding,
+    VerificationFailed,
+    CredentialExists,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CredentialDescriptor {
+    #[serde(rename = "type")]
+    kind: &'static str,
+    id: String,
+}
+
+impl CredentialDescriptor {
+    fn from_passkey(passkey: &Passkey) -> Self {
+        Self {
+            kind: "public-key",
+            id: passkey::encode_base64(&passkey.credential_id),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct RelyingParty {
+    id: String,
+    name: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct UserEntity {
+    id: String,
+    name: String,
+    display_name: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CredentialParameter {
+    #[serde(rename = "type")]
+    kind: &'static str,
+    alg: i32,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AuthenticatorSelection {
+    resident_key: &'static str,
+    user_verification: &'static str,
+}
+
+/// the options for navigator.credentials.create()
+#[derive(Debug, Serialize)]
+pub struct CreationOptions {
+    challenge: String,
+    rp: RelyingParty,
+    user: UserEntity,
+    pub_key_cred_params: Vec<CredentialParameter>,
+    timeout: i64,
+    exclude_credentials: Vec<CredentialDescriptor>,
+    authenticator_selection: AuthenticatorSelection,
+    attestation: &'static str,
+}
+
+/// the options for navigator.credentials.get()
+#[derive(Debug, Serialize)]
+pub struct RequestOptions {
+    challenge: String,
+    rp_id: String,
+    timeout: i64,
+    allow_credentials: Vec<CredentialDescriptor>,
+    user_verification: &'static str,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ChallengeResponse<T> {
+    challenge_id: PasskeyChallengeId,
+    public_key: T,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PasskeyPartial {
+    id: PasskeyId,
+    name: Option<String>,
+    created: DateTime<Utc>,
+    last_used: Option<DateTime<Utc>>,
+}
+
+impl From<Passkey> for PasskeyPartial {
+    fn from(passkey: Passkey) -> Self {
+        Self {
+            id: passkey.id,
+            name: passkey.name,
+            created: passkey.created,
+            last_used: passkey.last_used,
+        }
+    }
+}
+
+/// creates the options a client needs to register a new passkey for the
+/// current user
+pub async fn register_options(
+    state: state::SharedState,
+    headers: HeaderMap,
+) -> Result<Response, error::Error> {
+    let Some(config) = state.webauthn() else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+
+    let conn = state.db_conn().await?;
+
+    let initiator = macros::require_initiator!(&conn, &headers, None::<&'static str>);
+
+    let existing = Passkey::retrieve_user(&conn, &initiator.user.id)
+        .await
+        .context("failed to retrieve user passkeys")?;
+
+    let challenge = Challenge::create(&conn, Some(&initiator.user.id), ChallengeKind::Register).await?;
+
+    Ok(body::Json(ChallengeResponse {
+        challenge_id: challenge.id,
+        public_key: CreationOptions {
+            challenge: passkey::encode_base64(&challenge.challenge),
+            rp: RelyingParty {
+                id: config.rp_id.clone(),
+                name: config.rp_name.clone(),
+            },
+            user: UserEntity {
+                id: passkey::encode_base64(initiator.user.uid.inner().as_bytes()),
+                name: initiator.user.username.clone(),
+                display_name: initiator.user.username.clone(),
+            },
+            pub_key_cred_params: vec![
+                CredentialParameter { kind: "public-key", alg: ALG_ES256 },
+                CredentialParameter { kind: "public-key", alg: ALG_EDDSA },
+            ],
+            timeout: CHALLENGE_DURATION.num_milliseconds(),
+            exclude_credentials: existing.iter()
+                .map(CredentialDescriptor::from_passkey)
+                .collect(),
+            authenticator_selection: AuthenticatorSelection {
+                resident_key: "preferred",
+                user_verification: "preferred",
+            },
+            attestation: "none",
+        },
+    }).into_response())
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RegisterBody {
+    challenge_id: PasskeyChallengeId,
+    client_data_json: String,
+    attestation_object: String,
+    name: Option<String>,
+}
+
+/// verifies the response from the authenticator and stores the new passkey
+/// for the current user
+pub async fn register(
+    state: state::SharedState,
+    headers: HeaderMap,
+    body::Json(json): body::Json<RegisterBody>,
+) -> Result<Response, error::Error> {
+    let Some(config) = state.webauthn() else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+
+    let mut conn = state.db_conn().await?;
+    let transaction = conn.transaction()
+        .await
+        .context("failed to create transaction")?;
+
+    let initiator = macros::require_initiator!(&transaction, &headers, None::<&'static str>);
+
+    let result = Challenge::take(&transaction, &json.challenge_id, ChallengeKind::Register)
+        .await
+        .context("failed to retrieve passkey challenge")?;
+
+    let Some(challenge) = result.filter(|c| c.users_id == Some(initiator.user.id)) else {
+        return Ok(body::FieldError::new(
+            "challenge_id",
+            PasskeyResult::InvalidChallenge
+        ).into_response());
+    };
+
+    let (Some(client_data_json), Some(attestation_object)) = (
+        passkey::decode_base64(&json.client_data_json),
+        passkey::decode_base64(&json.attestation_object),
+    ) else {
+        return Ok(body::FieldError::new(
+            "client_data_json",
+            PasskeyResult::InvalidEncoding
+        ).into_response());
+    };
+
+    let verified = match passkey::verify_registration(config, &challenge, &client_data_json, &attestation_object) {
+        Ok(verified) => verified,
+        Err(err) => {
+            tracing::debug!("passkey registration failed: {err}");
+
+            return Ok(body::FieldError::new(
+                "attestation_object",
+                PasskeyResult::VerificationFailed
+            ).into_response());
+        }
+    };
+
+    let name = json.name.filter(|name| !name.trim().is_empty());
+
+    let created = match Passkey::create(&transaction, &initiator.user.id, verified, name).await {
+        Ok(created) => created,
+        Err(CreatePasskeyError::CredentialExists) => {
+            return Ok(body::FieldError::new(
+                "attestation_object",
+                PasskeyResult::CredentialExists
+            ).into_response());
+        }
+        Err(CreatePasskeyError::Db(err)) => {
+            return Err(error::Error::context_source(
+                "failed to create passkey",
+                err
+            ));
+        }
+    };
+
+    transaction.commit()
+        .await
+        .context("failed to commit transaction")?;
+
+    Ok((
+        StatusCode::CREATED,
+        body::Json(PasskeyPartial::from(created))
+    ).into_response())
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AuthenticateOptionsBody {
+    username: Option<String>,
+}
+
+/// creates the options a client needs to log in with a passkey
+///
+/// if a username is given then the passkeys for that user are listed,
+/// otherwise the authenticator will offer any discoverable passkeys it has.
+/// the response is the same for unknown usernames so that this cannot be
+/// used to search for accounts
+pub async fn authenticate_options(
+    state: state::SharedState,
+    body::Json(json): body::Json<AuthenticateOptionsBody>,
+) -> Result<Response, error::Error> {
+    let Some(config) = state.webauthn() else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+
+    let conn = state.db_conn().await?;
+
+    let user = if let Some(username) = &json.username {
+        User::retrieve_username(&conn, username)
+            .await
+            .context("failed to retrieve user")?
+    } else {
+        None
+    };
+
+    let allow_credentials = if let Some(user) = &user {
+        Passkey::retrieve_user(&conn, &user.id)
+            .await
+            .context("failed to retrieve user passkeys")?
+            .iter()
+            .map(CredentialDescriptor::from_passkey)
+            .collect()
+    } else {
+        Vec::new()
+    };
+
+    let challenge = Challenge::create(
+        &conn,
+        user.as_ref().map(|user| &user.id),
+        ChallengeKind::Authenticate
+    ).await?;
+
+    Ok(body::Json(ChallengeResponse {
+        challenge_id: challenge.id,
+        public_key: RequestOptions {
+            challenge: passkey::encode_base64(&challenge.challenge),
+            rp_id: config.rp_id.clone(),
+            timeout: CHALLENGE_DURATION.num_milliseconds(),
+            allow_credentials,
+            user_verification: "preferred",
+        },
+    }).into_response())
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AuthenticateBody {
+    challenge_id: PasskeyChallengeId,
+    credential_id: String,
+    client_data_json: String,
+    authenticator_data: String,
+    signature: String,
+}
+
+/// verifies the signed challenge from the authenticator and creates a new
+/// session for the owner of the passkey
+pub async fn authenticate(
+    state: state::SharedState,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    body::Json(json): body::Json<AuthenticateBody>,
+) -> Result<Response, error::Error> {
+    let Some(config) = state.webauthn() else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+
+    let mut conn = state.db_conn().await?;
+    let transaction = conn.transaction()
+        .await
+        .context("failed to create transaction")?;
+
+    let result = Challenge::take(&transaction, &json.challenge_id, ChallengeKind::Authenticate)
+        .await
+        .context("failed to retrieve passkey challenge")?;
+
+    let Some(challenge) = result else {
+        return Ok(body::FieldError::new(
+            "challenge_id",
+            PasskeyResult::InvalidChallenge
+        ).into_response());
+    };
+
+    // the challenge is removed even if verification fails so that it cannot
+    // be retried
+    transaction.commit()
+        .await
+        .context("failed to commit transaction")?;
+
+    let transaction = conn.transaction()
+        .await
+        .context("failed to create transaction")?;
+
+    let (Some(credential_id), Some(client_data_json), Some(authenticator_data), Some(signature)) = (
+        passkey::decode_base64(&json.credential_id),
+        passkey::decode_base64(&json.client_data_json),
+        passkey::decode_base64(&json.authenticator_data),
+        passkey::decode_base64(&json.signature),
+    ) else {
+        return Ok(body::FieldError::new(
+            "credential_id",
+            PasskeyResult::InvalidEncoding
+        ).into_response());
+    };
+
+    let result = Passkey::retrieve_credential_id(&transaction, &credential_id)
+        .await
+        .context("failed to retrieve passkey")?;
+
+    let Some(mut found) = result.filter(|found| {
+        challenge.users_id.map_or(true, |users_id| users_id == found.users_id)
+    }) else {
+        return Ok(body::FieldError::new(
+            "credential_id",
+            PasskeyResult::InvalidCredential
+        ).into_response());
+    };
+
+    let sign_count = match passkey::verify_authentication(
+        config,
+        &challenge,
+        &found,
+        &client_data_json,
+        &authenticator_data,
+        &signature
+    ) {
+        Ok(sign_count) => sign_count,
+        Err(err) => {
+            tracing::debug!("passkey authentication failed: {err}");
+
+            return Ok((
+                StatusCode::FORBIDDEN,
+                body::Json(PasskeyResult::VerificationFailed)
+            ).into_response());
+        }
+    };
+
+    let user = User::retrieve_id(&transaction, found.users_id)
+        .await
+        .context("failed to retrieve user")?;
+
+    if user.map_or(true, |user| user.disabled.is_some()) {
+        return Ok((
+            StatusCode::FORBIDDEN,
+            body::Json(LoginResult::Failed(LoginFailed::UserDisabled))
+        ).into_response());
+    }
+
+    found.update_used(&transaction, sign_count)
+        .await
+        .context("failed to update passkey")?;
+
+    let mut options = SessionOptions::new(found.users_id);
+    options.authenticated = true;
+    options.verified = true;
+    options.client(&headers, client_ip(state.network(), &peer, &headers));
+
+    let session = Session::create(&transaction, options)
+        .await
+        .context("failed to create session for passkey login")?;
+
+    let session_cookie = session.build_cookie();
+    let csrf_cookie = session.build_csrf_cookie();
+
+    transaction.commit()
+        .await
+        .context("failed to commit transaction for passkey login")?;
+
+    Ok((
+        session_cookie,
+        csrf_cookie,
+        body::Json(LoginResult::Success)
+    ).into_response())
+}
+
+/// lists the passkeys registered to the current user
+pub async fn retrieve_passkeys(
+    state: state::SharedState,
+    uri: Uri,
+    headers: HeaderMap,
+) -> Result<Response, error::Error> {
+    let conn = state.db_conn().await?;
+
+    let initiator = macros::require_initiator!(&conn, &headers, Some(uri));
+
+    macros::res_if_html!(state.templates(), &headers);
+
+    let found: Vec<PasskeyPartial> = Passkey::retrieve_user(&conn, &initiator.user.id)
+        .await
+        .context("failed to retrieve user passkeys")?
+        .into_iter()
+        .map(PasskeyPartial::from)
+        .collect();
+
+    Ok(body::Json(found).into_response())
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PasskeyPath {
+    passkeys_id: PasskeyId,
+}
+
+/// removes a passkey from the current user
+pub async fn delete_passkey(
+    state: state::SharedState,
+    headers: HeaderMap,
+    Path(PasskeyPath { passkeys_id }): Path<PasskeyPath>,
+) -> Result<Response, error::Error> {
+    let conn = state.db_conn().await?;
+
+    let initiator = macros::require_initiator!(&conn, &headers, None::<&'static str>);
+
+    let deleted = Passkey::delete(&conn, &initiator.user.id, &passkeys_id)
+        .await
+        .context("failed to delete passkey")?;
+
+    if deleted {
+        Ok(StatusCode::OK.into_response())
+    } else {
+        Ok(StatusCode::NOT_FOUND.into_response())
+    }
+}
@@ -1,23 +1,43 @@
 use async_trait::async_trait;
 use axum::body::Body;
 use axum::extract::{Request, FromRequest};
-use axum::http::StatusCode;
+use axum::http::{HeaderMap, StatusCode};
+use axum::middleware::Next;
 use axum::response::{Response, IntoResponse};
-use bytes::{BytesMut, BufMut};
+use bytes::{Bytes, BytesMut, BufMut};
 use serde::Serialize;
 use serde::de::DeserializeOwned;
 
+use crate::config::JsonCase;
 use crate::error::{self, Context};
 use crate::error::log_prefix_error;
 use crate::state;
 
+mod case;
+
+/// the header a client can use to pick the naming convention of json bodies
+pub const JSON_CASE_HEADER: &str = "x-json-case";
+
+tokio::task_local! {
+    /// the naming convention of json bodies for the current request. set by
+    /// [`json_case`]
+    static JSON_CASE: JsonCase;
+}
+
 fn serialize_json(
     status: StatusCode,
     data: &impl Serialize
 ) -> Result<Response, serde_json::Error> {
+    let case = JSON_CASE.try_with(|case| *case)
+        .unwrap_or_default();
+
     let froze = {
         let mut buf = BytesMut::with_capacity(128).writer();
-        serde_json::to_writer(&mut buf, data)?;
+
+        match case {
+            JsonCase::Snake => serde_json::to_writer(&mut buf, data)?,
+            JsonCase::Camel => serde_json::to_writer(&mut buf, &case::Camel(data))?,
+        }
 
         buf.into_inner().freeze()
     };
@@ -74,32 +94,197 @@ where
     }
 }
 
-#[async_trait]
-impl<T> FromRequest<state::SharedState> for Json<T>
-where
-    T: DeserializeOwned
-{
-    type Rejection = Response;
+/// the details of a json request body that failed to parse
+#[derive(Debug, Serialize)]
+pub struct JsonError {
+    error: &'static str,
+    message: String,
+
+    /// the path to the field that failed to deserialize. "." is the root of
+    /// the document
+    #[serde(skip_serializing_if = "Option::is_none")]
+    path: Option<String>,
+
+    /// the type that was expected for the field if known
+    #[serde(skip_serializing_if = "Option::is_none")]
+    expected: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    line: Option<usize>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    column: Option<usize>,
+}
+
+impl JsonError {
+    fn new(error: &'static str, message: String) -> Self {
+        Self {
+            error,
+            message,
+            path: None,
+            expected: None,
+            line: None,
+            column: None,
+        }
+    }
+
+    /// creates the error from the path and error provided by the deserializer
+    fn from_path_error(err: serde_path_to_error::Error<serde_json::Error>) -> Self {
+        let path = err.path().to_string();
+
+        Self::from_json_error(err.into_inner(), path)
+    }
+
+    /// creates the error from a serde_json error at the given path
+    fn from_json_error(inner: serde_json::Error, mut path: String) -> Self {
+        let line = inner.line();
+        let column = inner.column();
+        let position = format!(" at line {line} column {column}");
+
+        let full = inner.to_string();
+        let message = full.strip_suffix(&position)
+            .unwrap_or(&full)
+            .to_owned();
 
-    async fn from_request(req: Request, state: &state::SharedState) -> Result<Self, Self::Rejection> {
-        match axum::Json::from_request(req, state).await {
-            Ok(axum::Json(inner)) => Ok(Self(inner)),
+        let error = match inner.classify() {
+            serde_json::error::Category::Data => "INVALID_DATA",
+            _ => "INVALID_JSON",
+        };
+
+        // missing fields are reported at the parent so the field name is
+        // added to the path
+        if let Some(field) = message.strip_prefix("missing field `")
+            .and_then(|rest| rest.strip_suffix('`')) {
+            if path == "." {
+                path = field.to_owned();
+            } else {
+                path = format!("{path}.{field}");
+            }
+        }
+
+        let expected = message.split_once(", expected ")
+            .map(|(_, expected)| expected.to_owned());
+
+        Self {
+            error,
+            message,
+            path: Some(path),
+            expected,
+            // errors from an already parsed value do not have a position
+            line: (line != 0).then_some(line),
+            column: (column != 0).then_some(column),
+        }
+    }
+}
+
+impl IntoResponse for JsonError {
+    fn into_response(self) -> Response {
+        match serialize_json(StatusCode::BAD_REQUEST, &self) {
+            Ok(res) => res,
             Err(err) => {
                 log_prefix_error(
-                    "failed to parse json request body",
+                    "failed to serialize json error response",
                     &err
                 );
 
-                Err(error_json(
-                    StatusCode::BAD_REQUEST,
-                    "INVALID_JSON",
-                    None
-                ))
+                error_json(StatusCode::BAD_REQUEST, self.error, None)
             }
         }
     }
 }
 
+/// checks to see if the request has a json content type
+fn is_json_content_type(headers: &HeaderMap) -> bool {
+    let Some(content_type) = headers.get("content-type") else {
+        return false;
+    };
+
+    let Ok(content_type_str) = content_type.to_str() else {
+        return false;
+    };
+
+    let Ok(mime) = content_type_str.parse::<mime::Mime>() else {
+        return false;
+    };
+
+    mime.type_() == "application" && (
+        mime.subtype() == "json" ||
+        mime.suffix().is_some_and(|suffix| suffix == "json")
+    )
+}
+
+/// attempts to deserialize the body of a request into the given type
+///
+/// failures will report the path of the field that could not be parsed
+async fn parse_json<T>(req: Request) -> Result<T, Response>
+where
+    T: DeserializeOwned
+{
+    if !is_json_content_type(req.headers()) {
+        return Err(JsonError::new(
+            "INVALID_CONTENT_TYPE",
+            String::from("expected request with \"content-type: application/json\"")
+        ).into_response());
+    }
+
+    let case = req.extensions()
+        .get::<JsonCase>()
+        .copied()
+        .unwrap_or_default();
+
+    let bytes = match Bytes::from_request(req, &()).await {
+        Ok(bytes) => bytes,
+        Err(err) => {
+            log_prefix_error(
+                "failed to read json request body",
+                &err
+            );
+
+            return Err(JsonError::new(
+                "INVALID_BODY",
+                String::from("failed to read request body")
+            ).into_response());
+        }
+    };
+
+    let mut deserializer = serde_json::Deserializer::from_slice(&bytes);
+
+    let result = match case {
+        JsonCase::Snake => serde_path_to_error::deserialize(&mut deserializer),
+        JsonCase::Camel => serde_path_to_error::deserialize(case::Snake(&mut deserializer)),
+    };
+
+    let value = match result {
+        Ok(value) => value,
+        Err(err) => {
+            let json_error = JsonError::from_path_error(err);
+
+            tracing::debug!("failed to parse json request body: {json_error:?}");
+
+            return Err(json_error.into_response());
+        }
+    };
+
+    if let Err(err) = deserializer.end() {
+        return Err(JsonError::from_json_error(err, String::from("."))
+            .into_response());
+    }
+
+    Ok(value)
+}
+
+#[async_trait]
+impl<T> FromRequest<state::SharedState> for Json<T>
+where
+    T: DeserializeOwned
+{
+    type Rejection = Response;
+
+    async fn from_request(req: Request, _state: &state::SharedState) -> Result<Self, Self::Rejection> {
+        parse_json(req).await.map(Self)
+    }
+}
+
 #[async_trait]
 impl<T> FromRequest<()> for Json<T>
 where
@@ -107,23 +292,158 @@ where
 {
     type Rejection = Response;
 
-    async fn from_request(req: Request, state: &()) -> Result<Self, Self::Rejection> {
-        match axum::Json::from_request(req, state).await {
-            Ok(axum::Json(inner)) => Ok(Self(inner)),
+    async fn from_request(req: Request, _state: &()) -> Result<Self, Self::Rejection> {
+        parse_json(req).await.map(Self)
+    }
+}
+
+/// a list of records from a csv request body. the first row of the body must
+/// be the headers of the records
+pub struct Csv<T>(pub Vec<T>);
+
+/// checks to see if the request has a csv content type
+pub fn is_csv_content_type(headers: &HeaderMap) -> bool {
+    let Some(content_type) = headers.get("content-type") else {
+        return false;
+    };
+
+    let Ok(content_type_str) = content_type.to_str() else {
+        return false;
+    };
+
+    let Ok(mime) = content_type_str.parse::<mime::Mime>() else {
+        return false;
+    };
+
+    mime.type_() == "text" && mime.subtype() == "csv"
+}
+
+/// attempts to deserialize each record of a csv request body into the given
+/// type
+///
+/// failures will report the line of the record that could not be parsed
+async fn parse_csv<T>(req: Request) -> Result<Vec<T>, Response>
+where
+    T: DeserializeOwned
+{
+    if !is_csv_content_type(req.headers()) {
+        return Err(JsonError::new(
+            "INVALID_CONTENT_TYPE",
+            String::from("expected request with \"content-type: text/csv\"")
+        ).into_response());
+    }
+
+    let bytes = match Bytes::from_request(req, &()).await {
+        Ok(bytes) => bytes,
+        Err(err) => {
+            log_prefix_error(
+                "failed to read csv request body",
+                &err
+            );
+
+            return Err(JsonError::new(
+                "INVALID_BODY",
+                String::from("failed to read request body")
+            ).into_response());
+        }
+    };
+
+    let mut reader = csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .from_reader(bytes.as_ref());
+    let mut rtn = Vec::new();
+
+    for result in reader.deserialize() {
+        match result {
+            Ok(record) => rtn.push(record),
             Err(err) => {
-                log_prefix_error(
-                    "failed to parse json request body",
-                    &err
+                let mut csv_error = JsonError::new(
+                    "INVALID_CSV",
+                    err.to_string()
                 );
+                csv_error.line = err.position()
+                    .map(|pos| pos.line() as usize);
 
-                Err(error_json(
-                    StatusCode::BAD_REQUEST,
-                    "INVALID_JSON",
-                    None
-                ))
+                tracing::debug!("failed to parse csv request body: {csv_error:?}");
+
+                return Err(csv_error.into_response());
             }
         }
     }
+
+    Ok(rtn)
+}
+
+#[async_trait]
+impl<T> FromRequest<state::SharedState> for Csv<T>
+where
+    T: DeserializeOwned
+{
+    type Rejection = Response;
+
+    async fn from_request(req: Request, _state: &state::SharedState) -> Result<Self, Self::Rejection> {
+        parse_csv(req).await.map(Self)
+    }
+}
+
+/// resolves the naming convention a client wants for json bodies
+///
+/// the "x-json-case" header takes priority over the server default. the
+/// server works with snake case so the struct fields of request and response
+/// bodies are renamed by [`Json`] for the rest of the request
+pub async fn json_case(
+    state: state::SharedState,
+    mut req: Request,
+    next: Next,
+) -> Response {
+    let case = match req.headers().get(JSON_CASE_HEADER) {
+        Some(value) => match value.to_str() {
+            Ok("snake") => JsonCase::Snake,
+            Ok("camel") => JsonCase::Camel,
+            _ => return JsonError::new(
+                "INVALID_JSON_CASE",
+                String::from("expected \"x-json-case\" to be \"snake\" or \"camel\"")
+            ).into_response()
+        },
+        None => state.api().json_case,
+    };
+
+    req.extensions_mut().insert(case);
+
+    JSON_CASE.scope(case, next.run(req)).await
+}
+
+/// a validation error for a specific field of a request body
+///
+/// the field is flattened into the serialized error so it is available next
+/// to the "type" of a tagged result
+#[derive(Debug, Serialize)]
+pub struct FieldError<T> {
+    field: String,
+
+    #[serde(flatten)]
+    error: T,
+}
+
+impl<T> FieldError<T> {
+    pub fn new<F>(field: F, error: T) -> Self
+    where
+        F: Into<String>
+    {
+        Self {
+            field: field.into(),
+            error,
+        }
+    }
+}
+
+impl<T> IntoResponse for FieldError<T>
+where
+    T: Serialize
+{
+    fn into_response(self) -> Response {
+        (StatusCode::BAD_REQUEST, Json(self)).into_response()
+    }
 }
 
 pub struct Html<T = String>{
@@ -0,0 +1,984 @@
+//! converts the field names of json bodies between snake case and camel case
+//!
+//! the types of the server use snake case field names. only the names of
+//! struct fields are renamed, the keys of maps are data, ex: the tags of an
+//! entry, and are left as is.
+//!
+//! serde serializes a struct with flattened fields as a map without a length
+//! so those maps are renamed as well. when deserializing, the fields of a
+//! struct are renamed with the list of fields the struct expects so names
+//! that are already camel case are kept. values that serde buffers before it
+//! knows the type, the variants of tagged or untagged enums, only have keys
+//! that look like camel case renamed
+
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Mutex, OnceLock, PoisonError};
+
+use serde::de::{self, DeserializeSeed, IntoDeserializer, Visitor};
+use serde::ser::{self, Serialize, Serializer};
+
+/// converts a snake case key to camel case
+pub fn snake_to_camel(key: &str) -> String {
+    let mut rtn = String::with_capacity(key.len());
+    let mut upper = false;
+
+    for ch in key.chars() {
+        if ch == '_' && !rtn.is_empty() {
+            upper = true;
+        } else if upper {
+            rtn.extend(ch.to_uppercase());
+            upper = false;
+        } else {
+            rtn.push(ch);
+        }
+    }
+
+    rtn
+}
+
+/// converts a camel case key to snake case
+pub fn camel_to_snake(key: &str) -> String {
+    let mut rtn = String::with_capacity(key.len() + 4);
+
+    for ch in key.chars() {
+        if ch.is_uppercase() {
+            if !rtn.is_empty() {
+                rtn.push('_');
+            }
+
+            rtn.extend(ch.to_lowercase());
+        } else {
+            rtn.push(ch);
+        }
+    }
+
+    rtn
+}
+
+/// checks if the key is a single camel case word. ex: "entriesId"
+fn is_camel(key: &str) -> bool {
+    key.starts_with(|ch: char| ch.is_ascii_lowercase()) &&
+        key.chars().all(|ch| ch.is_ascii_alphanumeric()) &&
+        key.chars().any(|ch| ch.is_ascii_uppercase())
+}
+
+/// the camel case name of a struct field
+///
+/// serializers expect static field names. the field names come from the
+/// types of the server so the renamed names are only created once
+fn camel_field(name: &'static str) -> &'static str {
+    static NAMES: OnceLock<Mutex<HashMap<&'static str, &'static str>>> = OnceLock::new();
+
+    if !name.contains('_') {
+        return name;
+    }
+
+    let mut names = NAMES.get_or_init(Default::default)
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner);
+
+    names.entry(name)
+        .or_insert_with(|| Box::leak(snake_to_camel(name).into_boxed_str()))
+}
+
+/// serializes the value with camel case struct fields
+pub struct Camel<'a, T: ?Sized>(pub &'a T);
+
+impl<T> Serialize for Camel<'_, T>
+where
+    T: Serialize + ?Sized
+{
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer
+    {
+        self.0.serialize(CamelSerializer(serializer))
+    }
+}
+
+struct CamelSerializer<S>(S);
+
+/// the compound serializers of [`CamelSerializer`]
+struct CamelCompound<S> {
+    inner: S,
+
+    /// renames the keys of a map. only used for structs with flattened
+    /// fields
+    rename_keys: bool,
+}
+
+impl<S> CamelCompound<S> {
+    fn new(inner: S) -> Self {
+        Self {
+            inner,
+            rename_keys: false,
+        }
+    }
+}
+
+macro_rules! forward_serialize {
+    ($($method:ident($ty:ty),)*) => {$(
+        fn $method(self, value: $ty) -> Result<Self::Ok, Self::Error> {
+            self.0.$method(value)
+        }
+    )*};
+}
+
+impl<S> Serializer for CamelSerializer<S>
+where
+    S: Serializer
+{
+    type Ok = S::Ok;
+    type Error = S::Error;
+    type SerializeSeq = CamelCompound<S::SerializeSeq>;
+    type SerializeTuple = CamelCompound<S::SerializeTuple>;
+    type SerializeTupleStruct = CamelCompound<S::SerializeTupleStruct>;
+    type SerializeTupleVariant = CamelCompound<S::SerializeTupleVariant>;
+    type SerializeMap = CamelCompound<S::SerializeMap>;
+    type SerializeStruct = CamelCompound<S::SerializeStruct>;
+    type SerializeStructVariant = CamelCompound<S::SerializeStructVariant>;
+
+    forward_serialize! {
+        serialize_bool(bool),
+        serialize_i8(i8),
+        serialize_i16(i16),
+        serialize_i32(i32),
+        serialize_i64(i64),
+        serialize_i128(i128),
+        serialize_u8(u8),
+        serialize_u16(u16),
+        serialize_u32(u32),
+        serialize_u64(u64),
+        serialize_u128(u128),
+        serialize_f32(f32),
+        serialize_f64(f64),
+        serialize_char(char),
+        serialize_str(&str),
+        serialize_bytes(&[u8]),
+        serialize_unit_struct(&'static str),
+    }
+
+    fn serialize_none(self) -> Result<Self::Ok, Self::Error> {
+        self.0.serialize_none()
+    }
+
+    fn serialize_some<T>(self, value: &T) -> Result<Self::Ok, Self::Error>
+    where
+        T: Serialize + ?Sized
+    {
+        self.0.serialize_some(&Camel(value))
+    }
+
+    fn serialize_unit(self) -> Result<Self::Ok, Self::Error> {
+        self.0.serialize_unit()
+    }
+
+    fn serialize_unit_variant(
+        self,
+        name: &'static str,
+        index: u32,
+        variant: &'static str,
+    ) -> Result<Self::Ok, Self::Error> {
+        self.0.serialize_unit_variant(name, index, variant)
+    }
+
+    fn serialize_newtype_struct<T>(self, name: &'static str, value: &T) -> Result<Self::Ok, Self::Error>
+    where
+        T: Serialize + ?Sized
+    {
+        self.0.serialize_newtype_struct(name, &Camel(value))
+    }
+
+    fn serialize_newtype_variant<T>(
+        self,
+        name: &'static str,
+        index: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> Result<Self::Ok, Self::Error>
+    where
+        T: Serialize + ?Sized
+    {
+        self.0.serialize_newtype_variant(name, index, variant, &Camel(value))
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<Self::SerializeSeq, Self::Error> {
+        self.0.serialize_seq(len).map(CamelCompound::new)
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<Self::SerializeTuple, Self::Error> {
+        self.0.serialize_tuple(len).map(CamelCompound::new)
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        name: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeTupleStruct, Self::Error> {
+        self.0.serialize_tuple_struct(name, len).map(CamelCompound::new)
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        name: &'static str,
+        index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeTupleVariant, Self::Error> {
+        self.0.serialize_tuple_variant(name, index, variant, len).map(CamelCompound::new)
+    }
+
+    fn serialize_map(self, len: Option<usize>) -> Result<Self::SerializeMap, Self::Error> {
+        // maps and collections always provide a length. serde only leaves it
+        // out for structs with flattened fields
+        self.0.serialize_map(len).map(|inner| CamelCompound {
+            inner,
+            rename_keys: len.is_none(),
+        })
+    }
+
+    fn serialize_struct(self, name: &'static str, len: usize) -> Result<Self::SerializeStruct, Self::Error> {
+        self.0.serialize_struct(name, len).map(CamelCompound::new)
+    }
+
+    fn serialize_struct_variant(
+        self,
+        name: &'static str,
+        index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeStructVariant, Self::Error> {
+        self.0.serialize_struct_variant(name, index, variant, len).map(CamelCompound::new)
+    }
+
+    fn is_human_readable(&self) -> bool {
+        self.0.is_human_readable()
+    }
+}
+
+impl<S> ser::SerializeSeq for CamelCompound<S>
+where
+    S: ser::SerializeSeq
+{
+    type Ok = S::Ok;
+    type Error = S::Error;
+
+    fn serialize_element<T>(&mut self, value: &T) -> Result<(), Self::Error>
+    where
+        T: Serialize + ?Sized
+    {
+        self.inner.serialize_element(&Camel(value))
+    }
+
+    fn end(self) -> Result<Self::Ok, Self::Error> {
+        self.inner.end()
+    }
+}
+
+impl<S> ser::SerializeTuple for CamelCompound<S>
+where
+    S: ser::SerializeTuple
+{
+    type Ok = S::Ok;
+    type Error = S::Error;
+
+    fn serialize_element<T>(&mut self, value: &T) -> Result<(), Self::Error>
+    where
+        T: Serialize + ?Sized
+    {
+        self.inner.serialize_element(&Camel(value))
+    }
+
+    fn end(self) -> Result<Self::Ok, Self::Error> {
+        self.inner.end()
+    }
+}
+
+impl<S> ser::SerializeTupleStruct for CamelCompound<S>
+where
+    S: ser::SerializeTupleStruct
+{
+    type Ok = S::Ok;
+    type Error = S::Error;
+
+    fn serialize_field<T>(&mut self, value: &T) -> Result<(), Self::Error>
+    where
+        T: Serialize + ?Sized
+    {
+        self.inner.serialize_field(&Camel(value))
+    }
+
+    fn end(self) -> Result<Self::Ok, Self::Error> {
+        self.inner.end()
+    }
+}
+
+impl<S> ser::SerializeTupleVariant for CamelCompound<S>
+where
+    S: ser::SerializeTupleVariant
+{
+    type Ok = S::Ok;
+    type Error = S::Error;
+
+    fn serialize_field<T>(&mut self, value: &T) -> Result<(), Self::Error>
+    where
+        T: Serialize + ?Sized
+    {
+        self.inner.serialize_field(&Camel(value))
+    }
+
+    fn end(self) -> Result<Self::Ok, Self::Error> {
+        self.inner.end()
+    }
+}
+
+impl<S> ser::SerializeMap for CamelCompound<S>
+where
+    S: ser::SerializeMap
+{
+    type Ok = S::Ok;
+    type Error = S::Error;
+
+    fn serialize_key<T>(&mut self, key: &T) -> Result<(), Self::Error>
+    where
+        T: Serialize + ?Sized
+    {
+        if self.rename_keys {
+            if let Ok(serde_json::Value::String(name)) = serde_json::to_value(key) {
+                return self.inner.serialize_key(&snake_to_camel(&name));
+            }
+        }
+
+        self.inner.serialize_key(key)
+    }
+
+    fn serialize_value<T>(&mut self, value: &T) -> Result<(), Self::Error>
+    where
+        T: Serialize + ?Sized
+    {
+        self.inner.serialize_value(&Camel(value))
+    }
+
+    fn end(self) -> Result<Self::Ok, Self::Error> {
+        self.inner.end()
+    }
+}
+
+impl<S> ser::SerializeStruct for CamelCompound<S>
+where
+    S: ser::SerializeStruct
+{
+    type Ok = S::Ok;
+    type Error = S::Error;
+
+    fn serialize_field<T>(&mut self, key: &'static str, value: &T) -> Result<(), Self::Error>
+    where
+        T: Serialize + ?Sized
+    {
+        self.inner.serialize_field(camel_field(key), &Camel(value))
+    }
+
+    fn skip_field(&mut self, key: &'static str) -> Result<(), Self::Error> {
+        self.inner.skip_field(camel_field(key))
+    }
+
+    fn end(self) -> Result<Self::Ok, Self::Error> {
+        self.inner.end()
+    }
+}
+
+impl<S> ser::SerializeStructVariant for CamelCompound<S>
+where
+    S: ser::SerializeStructVariant
+{
+    type Ok = S::Ok;
+    type Error = S::Error;
+
+    fn serialize_field<T>(&mut self, key: &'static str, value: &T) -> Result<(), Self::Error>
+    where
+        T: Serialize + ?Sized
+    {
+        self.inner.serialize_field(camel_field(key), &Camel(value))
+    }
+
+    fn skip_field(&mut self, key: &'static str) -> Result<(), Self::Error> {
+        self.inner.skip_field(camel_field(key))
+    }
+
+    fn end(self) -> Result<Self::Ok, Self::Error> {
+        self.inner.end()
+    }
+}
+
+/// how the keys of a map are renamed when deserializing
+#[derive(Debug, Clone, Copy)]
+enum Keys {
+    /// the keys are data and are not renamed
+    Keep,
+
+    /// the keys are the fields of a struct
+    Fields(&'static [&'static str]),
+
+    /// the type is not known yet so only keys that look like camel case are
+    /// renamed
+    Guess,
+}
+
+impl Keys {
+    fn rename(&self, key: String) -> String {
+        match self {
+            Keys::Keep => key,
+            Keys::Fields(fields) => {
+                if fields.contains(&key.as_str()) {
+                    return key;
+                }
+
+                let snake = camel_to_snake(&key);
+
+                if fields.contains(&snake.as_str()) {
+                    snake
+                } else {
+                    key
+                }
+            }
+            Keys::Guess => if is_camel(&key) {
+                camel_to_snake(&key)
+            } else {
+                key
+            },
+        }
+    }
+}
+
+/// deserializes from the given deserializer accepting camel case struct
+/// fields
+pub struct Snake<D>(pub D);
+
+macro_rules! forward_deserialize {
+    ($($method:ident,)*) => {$(
+        fn $method<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+        where
+            V: Visitor<'de>
+        {
+            self.0.$method(SnakeVisitor::new(visitor, Keys::Keep))
+        }
+    )*};
+}
+
+impl<'de, D> de::Deserializer<'de> for Snake<D>
+where
+    D: de::Deserializer<'de>
+{
+    type Error = D::Error;
+
+    forward_deserialize! {
+        deserialize_bool,
+        deserialize_i8,
+        deserialize_i16,
+        deserialize_i32,
+        deserialize_i64,
+        deserialize_i128,
+        deserialize_u8,
+        deserialize_u16,
+        deserialize_u32,
+        deserialize_u64,
+        deserialize_u128,
+        deserialize_f32,
+        deserialize_f64,
+        deserialize_char,
+        deserialize_str,
+        deserialize_string,
+        deserialize_bytes,
+        deserialize_byte_buf,
+        deserialize_option,
+        deserialize_unit,
+        deserialize_seq,
+        deserialize_map,
+        deserialize_identifier,
+    }
+
+    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>
+    {
+        self.0.deserialize_any(SnakeVisitor::new(visitor, Keys::Guess))
+    }
+
+    fn deserialize_unit_struct<V>(self, name: &'static str, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>
+    {
+        self.0.deserialize_unit_struct(name, SnakeVisitor::new(visitor, Keys::Keep))
+    }
+
+    fn deserialize_newtype_struct<V>(self, name: &'static str, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>
+    {
+        self.0.deserialize_newtype_struct(name, SnakeVisitor::new(visitor, Keys::Keep))
+    }
+
+    fn deserialize_tuple<V>(self, len: usize, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>
+    {
+        self.0.deserialize_tuple(len, SnakeVisitor::new(visitor, Keys::Keep))
+    }
+
+    fn deserialize_tuple_struct<V>(
+        self,
+        name: &'static str,
+        len: usize,
+        visitor: V
+    ) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>
+    {
+        self.0.deserialize_tuple_struct(name, len, SnakeVisitor::new(visitor, Keys::Keep))
+    }
+
+    fn deserialize_struct<V>(
+        self,
+        name: &'static str,
+        fields: &'static [&'static str],
+        visitor: V
+    ) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>
+    {
+        self.0.deserialize_struct(name, fields, SnakeVisitor::new(visitor, Keys::Fields(fields)))
+    }
+
+    fn deserialize_enum<V>(
+        self,
+        name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V
+    ) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>
+    {
+        self.0.deserialize_enum(name, variants, SnakeVisitor::new(visitor, Keys::Keep))
+    }
+
+    fn deserialize_ignored_any<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>
+    {
+        self.0.deserialize_ignored_any(visitor)
+    }
+
+    fn is_human_readable(&self) -> bool {
+        self.0.is_human_readable()
+    }
+}
+
+struct SnakeVisitor<V> {
+    inner: V,
+    keys: Keys,
+}
+
+impl<V> SnakeVisitor<V> {
+    fn new(inner: V, keys: Keys) -> Self {
+        Self { inner, keys }
+    }
+}
+
+macro_rules! forward_visit {
+    ($($method:ident($ty:ty),)*) => {$(
+        fn $method<E>(self, value: $ty) -> Result<Self::Value, E>
+        where
+            E: de::Error
+        {
+            self.inner.$method(value)
+        }
+    )*};
+}
+
+impl<'de, V> Visitor<'de> for SnakeVisitor<V>
+where
+    V: Visitor<'de>
+{
+    type Value = V::Value;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        self.inner.expecting(formatter)
+    }
+
+    forward_visit! {
+        visit_bool(bool),
+        visit_i8(i8),
+        visit_i16(i16),
+        visit_i32(i32),
+        visit_i64(i64),
+        visit_i128(i128),
+        visit_u8(u8),
+        visit_u16(u16),
+        visit_u32(u32),
+        visit_u64(u64),
+        visit_u128(u128),
+        visit_f32(f32),
+        visit_f64(f64),
+        visit_char(char),
+        visit_str(&str),
+        visit_borrowed_str(&'de str),
+        visit_string(String),
+        visit_bytes(&[u8]),
+        visit_borrowed_bytes(&'de [u8]),
+        visit_byte_buf(Vec<u8>),
+    }
+
+    fn visit_none<E>(self) -> Result<Self::Value, E>
+    where
+        E: de::Error
+    {
+        self.inner.visit_none()
+    }
+
+    fn visit_unit<E>(self) -> Result<Self::Value, E>
+    where
+        E: de::Error
+    {
+        self.inner.visit_unit()
+    }
+
+    fn visit_some<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
+    where
+        D: de::Deserializer<'de>
+    {
+        self.inner.visit_some(Snake(deserializer))
+    }
+
+    fn visit_newtype_struct<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
+    where
+        D: de::Deserializer<'de>
+    {
+        self.inner.visit_newtype_struct(Snake(deserializer))
+    }
+
+    fn visit_seq<A>(self, seq: A) -> Result<Self::Value, A::Error>
+    where
+        A: de::SeqAccess<'de>
+    {
+        self.inner.visit_seq(SnakeAccess::new(seq, Keys::Keep))
+    }
+
+    fn visit_map<A>(self, map: A) -> Result<Self::Value, A::Error>
+    where
+        A: de::MapAccess<'de>
+    {
+        self.inner.visit_map(SnakeAccess::new(map, self.keys))
+    }
+
+    fn visit_enum<A>(self, data: A) -> Result<Self::Value, A::Error>
+    where
+        A: de::EnumAccess<'de>
+    {
+        self.inner.visit_enum(SnakeAccess::new(data, Keys::Keep))
+    }
+}
+
+/// passes the deserializer of a value through [`Snake`]
+struct SnakeSeed<T>(T);
+
+impl<'de, T> DeserializeSeed<'de> for SnakeSeed<T>
+where
+    T: DeserializeSeed<'de>
+{
+    type Value = T::Value;
+
+    fn deserialize<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
+    where
+        D: de::Deserializer<'de>
+    {
+        self.0.deserialize(Snake(deserializer))
+    }
+}
+
+/// renames a key before handing it to the given seed
+struct KeySeed<T> {
+    inner: T,
+    keys: Keys,
+}
+
+impl<'de, T> DeserializeSeed<'de> for KeySeed<T>
+where
+    T: DeserializeSeed<'de>
+{
+    type Value = T::Value;
+
+    fn deserialize<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
+    where
+        D: de::Deserializer<'de>
+    {
+        let key = <String as de::Deserialize>::deserialize(deserializer)?;
+
+        self.inner.deserialize(self.keys.rename(key).into_deserializer())
+    }
+}
+
+/// the sequence, map, and enum access of [`SnakeVisitor`]
+struct SnakeAccess<A> {
+    inner: A,
+    keys: Keys,
+}
+
+impl<A> SnakeAccess<A> {
+    fn new(inner: A, keys: Keys) -> Self {
+        Self { inner, keys }
+    }
+}
+
+impl<'de, A> de::SeqAccess<'de> for SnakeAccess<A>
+where
+    A: de::SeqAccess<'de>
+{
+    type Error = A::Error;
+
+    fn next_element_seed<T>(&mut self, seed: T) -> Result<Option<T::Value>, Self::Error>
+    where
+        T: DeserializeSeed<'de>
+    {
+        self.inner.next_element_seed(SnakeSeed(seed))
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        self.inner.size_hint()
+    }
+}
+
+impl<'de, A> de::MapAccess<'de> for SnakeAccess<A>
+where
+    A: de::MapAccess<'de>
+{
+    type Error = A::Error;
+
+    fn next_key_seed<K>(&mut self, seed: K) -> Result<Option<K::Value>, Self::Error>
+    where
+        K: DeserializeSeed<'de>
+    {
+        match self.keys {
+            Keys::Keep => self.inner.next_key_seed(seed),
+            keys => self.inner.next_key_seed(KeySeed { inner: seed, keys }),
+        }
+    }
+
+    fn next_value_seed<V>(&mut self, seed: V) -> Result<V::Value, Self::Error>
+    where
+        V: DeserializeSeed<'de>
+    {
+        self.inner.next_value_seed(SnakeSeed(seed))
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        self.inner.size_hint()
+    }
+}
+
+impl<'de, A> de::EnumAccess<'de> for SnakeAccess<A>
+where
+    A: de::EnumAccess<'de>
+{
+    type Error = A::Error;
+    type Variant = SnakeAccess<A::Variant>;
+
+    fn variant_seed<V>(self, seed: V) -> Result<(V::Value, Self::Variant), Self::Error>
+    where
+        V: DeserializeSeed<'de>
+    {
+        self.inner.variant_seed(seed)
+            .map(|(value, variant)| (value, SnakeAccess::new(variant, Keys::Keep)))
+    }
+}
+
+impl<'de, A> de::VariantAccess<'de> for SnakeAccess<A>
+where
+    A: de::VariantAccess<'de>
+{
+    type Error = A::Error;
+
+    fn unit_variant(self) -> Result<(), Self::Error> {
+        self.inner.unit_variant()
+    }
+
+    fn newtype_variant_seed<T>(self, seed: T) -> Result<T::Value, Self::Error>
+    where
+        T: DeserializeSeed<'de>
+    {
+        self.inner.newtype_variant_seed(SnakeSeed(seed))
+    }
+
+    fn tuple_variant<V>(self, len: usize, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>
+    {
+        self.inner.tuple_variant(len, SnakeVisitor::new(visitor, Keys::Keep))
+    }
+
+    fn struct_variant<V>(self, fields: &'static [&'static str], visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>
+    {
+        self.inner.struct_variant(fields, SnakeVisitor::new(visitor, Keys::Fields(fields)))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::collections::BTreeMap;
+
+    use serde::{Serialize, Deserialize};
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Entry {
+        entry_date: String,
+        tags: BTreeMap<String, Option<String>>,
+        custom_fields: Vec<Field>,
+        #[serde(rename = "rawId")]
+        raw_id: i64,
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    #[serde(tag = "type")]
+    enum Field {
+        IntegerRange {
+            custom_fields_id: i64,
+            low_value: i32,
+        },
+        Empty,
+    }
+
+    #[derive(Debug, Serialize)]
+    struct Flattened {
+        #[serde(flatten)]
+        entry: Entry,
+        word_count: i32,
+    }
+
+    fn entry() -> Entry {
+        Entry {
+            entry_date: String::from("2024-01-01"),
+            tags: BTreeMap::from([
+                (String::from("my_tag"), Some(String::from("my_value"))),
+                (String::from("otherTag"), None),
+            ]),
+            custom_fields: vec![
+                Field::IntegerRange {
+                    custom_fields_id: 1,
+                    low_value: 2,
+                },
+                Field::Empty,
+            ],
+            raw_id: 3,
+        }
+    }
+
+    fn to_camel<T: Serialize>(value: &T) -> serde_json::Value {
+        serde_json::to_value(Camel(value)).unwrap()
+    }
+
+    fn from_snake<T: de::DeserializeOwned>(json: &str) -> T {
+        let mut deserializer = serde_json::Deserializer::from_str(json);
+
+        T::deserialize(Snake(&mut deserializer)).unwrap()
+    }
+
+    #[test]
+    fn case_conversion() {
+        assert_eq!(snake_to_camel("custom_fields_id"), "customFieldsId");
+        assert_eq!(snake_to_camel("_private"), "_private");
+        assert_eq!(camel_to_snake("customFieldsId"), "custom_fields_id");
+        assert_eq!(camel_to_snake("already_snake"), "already_snake");
+    }
+
+    #[test]
+    fn serialize_renames_fields_only() {
+        let expected = serde_json::json!({
+            "entryDate": "2024-01-01",
+            "tags": {
+                "my_tag": "my_value",
+                "otherTag": null,
+            },
+            "customFields": [
+                {
+                    "type": "IntegerRange",
+                    "customFieldsId": 1,
+                    "lowValue": 2,
+                },
+                {
+                    "type": "Empty",
+                },
+            ],
+            "rawId": 3,
+        });
+
+        assert_eq!(to_camel(&entry()), expected);
+    }
+
+    #[test]
+    fn serialize_renames_flattened_fields() {
+        let value = to_camel(&Flattened {
+            entry: entry(),
+            word_count: 10,
+        });
+
+        assert_eq!(value["wordCount"], 10);
+        assert_eq!(value["entryDate"], "2024-01-01");
+        assert_eq!(value["tags"]["my_tag"], "my_value");
+        assert!(value.get("entry_date").is_none());
+    }
+
+    #[test]
+    fn deserialize_keeps_map_keys() {
+        let found: Entry = from_snake(r#"{
+            "entryDate": "2024-01-01",
+            "tags": {"my_tag": "my_value", "otherTag": null},
+            "customFields": [
+                {"type": "IntegerRange", "customFieldsId": 1, "lowValue": 2},
+                {"type": "Empty"}
+            ],
+            "rawId": 3
+        }"#);
+
+        assert_eq!(found, entry());
+    }
+
+    #[test]
+    fn deserialize_accepts_snake_case() {
+        let found: Entry = from_snake(r#"{
+            "entry_date": "2024-01-01",
+            "tags": {"my_tag": "my_value", "otherTag": null},
+            "custom_fields": [
+                {"type": "IntegerRange", "custom_fields_id": 1, "low_value": 2},
+                {"type": "Empty"}
+            ],
+            "rawId": 3
+        }"#);
+
+        assert_eq!(found, entry());
+    }
+
+    #[test]
+    fn round_trip() {
+        let json = serde_json::to_string(&Camel(&entry())).unwrap();
+        let found: Entry = from_snake(&json);
+
+        assert_eq!(found, entry());
+    }
+
+    #[test]
+    fn round_trip_map() {
+        let tags = BTreeMap::from([
+            (String::from("snake_key"), 1),
+            (String::from("camelKey"), 2),
+        ]);
+
+        let json = serde_json::to_string(&Camel(&tags)).unwrap();
+        let found: BTreeMap<String, i32> = from_snake(&json);
+
+        assert_eq!(found, tags);
+    }
+}
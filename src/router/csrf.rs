@@ -0,0 +1,89 @@
+//! csrf protection for requests authenticated by the session cookie
+//!
+//! every session has a csrf token that is given to the client in a cookie
+//! that scripts can read and from "/auth/csrf". requests that change state
+//! and send the session cookie must include the token in the
+//! "x-csrf-token" header. requests without a session cookie are not
+//! authenticated by the browser so they are not checked. logging in and
+//! out are also not checked so that the plain html forms keep working
+
+use axum::extract::Request;
+use axum::http::{Method, StatusCode};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use serde::Serialize;
+
+use crate::error::{self, Context};
+use crate::router::body;
+use crate::sec::authn::Session;
+use crate::sec::authn::session::{self, Token, CSRF_HEADER};
+use crate::state;
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "type")]
+pub enum CsrfResult {
+    InvalidCsrfToken,
+}
+
+/// paths that are not checked
+const EXEMPT: [&str; 2] = ["/login", "/logout"];
+
+fn is_safe(method: &Method) -> bool {
+    method == Method::GET || method == Method::HEAD || method == Method::OPTIONS
+}
+
+async fn retrieve_session(state: &state::SharedState, token: &Token) -> Result<Option<Session>, error::Error> {
+    let conn = state.db_conn().await?;
+
+    Session::retrieve_token(&conn, token)
+        .await
+        .context("failed to retrieve session")
+}
+
+/// middleware that rejects requests that change state when the session
+/// cookie is sent without the csrf token of the session
+pub async fn require_token(
+    state: state::SharedState,
+    req: Request,
+    next: Next,
+) -> Response {
+    if is_safe(req.method()) || EXEMPT.contains(&req.uri().path()) {
+        return next.run(req).await;
+    }
+
+    let token = session::find_session_id(req.headers())
+        .ok()
+        .flatten()
+        .and_then(|value| Token::from_base64(value).ok());
+
+    let Some(token) = token else {
+        return next.run(req).await;
+    };
+
+    // an unknown session is rejected by the handler
+    let session = match retrieve_session(&state, &token).await {
+        Ok(Some(session)) => session,
+        Ok(None) => return next.run(req).await,
+        Err(err) => return err.into_response(),
+    };
+
+    let given = req.headers()
+        .get(CSRF_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| Token::from_base64(value).ok());
+
+    if given.is_some_and(|given| session.check_csrf(&given)) {
+        return next.run(req).await;
+    }
+
+    tracing::warn!(
+        method = %req.method(),
+        uri = %req.uri(),
+        "rejected request with a missing or invalid csrf token"
+    );
+
+    (
+        StatusCode::FORBIDDEN,
+        body::Json(CsrfResult::InvalidCsrfToken),
+    ).into_response()
+}
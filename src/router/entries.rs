@@ -0,0 +1,67 @@
+//! entry routes that do not specify a journal
+//!
+//! requests are redirected to the same route of the default journal for the
+//! user. a temporary redirect is used so that the method and body of the
+//! request are kept
+
+use axum::Router;
+use axum::extract::OriginalUri;
+use axum::http::{header, StatusCode, Uri, HeaderMap};
+use axum::response::{IntoResponse, Response};
+use axum::routing::any;
+
+use crate::state;
+use crate::error::{self, Context};
+use crate::journal::Journal;
+use crate::router::macros;
+use crate::workspace::Workspace;
+
+pub fn build(_state: &state::SharedState) -> Router<state::SharedState> {
+    Router::new()
+        .route("/", any(redirect_default))
+        .route("/*path", any(redirect_default))
+}
+
+/// redirects to the entries of the default journal. responds with not found
+/// if the user does not have a journal in the workspace
+async fn redirect_default(
+    state: state::SharedState,
+    workspace: Workspace,
+    uri: Uri,
+    OriginalUri(original): OriginalUri,
+    headers: HeaderMap,
+) -> Result<Response, error::Error> {
+    let conn = state.db_conn().await?;
+
+    let initiator = macros::require_initiator!(&conn, &headers, Some(original.clone()));
+
+    let result = Journal::retrieve_default(&conn, &initiator.user.id, &workspace.id)
+        .await
+        .context("failed to retrieve default journal")?;
+
+    let Some(journal) = result else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+
+    // the nested uri only has the path after "/entries" so whatever came
+    // before it, like a workspace prefix, is kept for the redirect
+    let rest = uri.path();
+    let full = original.path();
+    let prefix = full.strip_suffix(rest)
+        .unwrap_or(full)
+        .strip_suffix("/entries")
+        .unwrap_or("");
+    let rest = if rest == "/" { "" } else { rest };
+
+    let mut location = format!("{prefix}/journals/{}/entries{rest}", journal.id);
+
+    if let Some(query) = original.query() {
+        location.push('?');
+        location.push_str(query);
+    }
+
+    Ok((
+        StatusCode::TEMPORARY_REDIRECT,
+        [(header::LOCATION, location)]
+    ).into_response())
+}
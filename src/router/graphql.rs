@@ -0,0 +1,572 @@
+//! the graphql api
+//!
+//! exposes the same journals, entries, tags, custom fields, and users as the
+//! rest handlers with the same permission checks so a client can request an
+//! entry along with its files and field definitions in a single request.
+//! only queries are available, changes are made through the rest handlers.
+//!
+//! the request and response bodies are not renamed by the "x-json-case"
+//! header since graphql defines its own field names.
+
+use async_graphql::{
+    ComplexObject,
+    Context,
+    EmptyMutation,
+    EmptySubscription,
+    Json as GqlJson,
+    Object,
+    Schema,
+    SimpleObject,
+};
+use axum::{Extension, Json};
+use axum::http::{StatusCode, HeaderMap};
+use axum::response::{IntoResponse, Response};
+use chrono::{DateTime, NaiveDate, Utc};
+use futures::StreamExt;
+
+use crate::state;
+use crate::db::ids::{EntryId, JournalId, UserId, WorkspaceId};
+use crate::error::{self, Context as _};
+use crate::journal::{self, custom_field, tag, CustomField as CustomFieldRecord, FileEntry};
+use crate::journal::tag::TagNode;
+use crate::sec::authn::{Initiator, InitiatorError};
+use crate::sec::authz::{self, Scope, Ability};
+use crate::workspace::Workspace;
+
+use super::journals::entries::{EntryPartial, EntrySearch, Page};
+
+pub type GraphqlSchema = Schema<Query, EmptyMutation, EmptySubscription>;
+
+/// the max number of entries that can be requested at once
+const MAX_ENTRIES: u32 = 100;
+
+/// the max depth of nested selections in a single query
+const MAX_DEPTH: usize = 8;
+
+/// the max complexity of a single query. each selected field counts as one
+const MAX_COMPLEXITY: usize = 500;
+
+/// creates the schema for the server
+///
+/// nested lists like journals -> entries -> files each run their own queries
+/// so the depth and complexity of a request are limited to keep a single
+/// request from fanning out into thousands of database calls
+pub fn schema(state: &state::SharedState) -> GraphqlSchema {
+    Schema::build(Query, EmptyMutation, EmptySubscription)
+        .data(state.clone())
+        .limit_depth(MAX_DEPTH)
+        .limit_complexity(MAX_COMPLEXITY)
+        .finish()
+}
+
+/// the user that made the request
+struct Viewer {
+    users_id: UserId,
+
+    /// the workspace the request was made in
+    workspaces_id: WorkspaceId,
+}
+
+type GqlResult<T> = async_graphql::Result<T>;
+
+/// logs the error and hides the details from the client
+fn internal(err: error::Error) -> async_graphql::Error {
+    error::log_prefix_error("graphql error", &err);
+
+    async_graphql::Error::new("internal server error")
+}
+
+fn viewer<'a>(ctx: &'a Context<'_>) -> GqlResult<(&'a state::SharedState, &'a Viewer)> {
+    Ok((ctx.data::<state::SharedState>()?, ctx.data::<Viewer>()?))
+}
+
+/// checks that the viewer has the ability for the given scope
+async fn require(ctx: &Context<'_>, scope: Scope, ability: Ability) -> GqlResult<()> {
+    let (state, viewer) = viewer(ctx)?;
+    let conn = state.db_conn().await.map_err(internal)?;
+
+    let allowed = authz::has_permission(&conn, viewer.users_id, scope, ability)
+        .await
+        .context("failed to retrieve permission for user")
+        .map_err(internal)?;
+
+    if allowed {
+        Ok(())
+    } else {
+        Err(async_graphql::Error::new("unauthorized"))
+    }
+}
+
+#[derive(SimpleObject)]
+pub struct User {
+    id: i64,
+    uid: String,
+    username: String,
+    created: DateTime<Utc>,
+    updated: Option<DateTime<Utc>>,
+}
+
+#[derive(SimpleObject)]
+#[graphql(complex)]
+pub struct Journal {
+    id: i64,
+    uid: String,
+    name: String,
+    description: Option<String>,
+    e2e: bool,
+    schema_version: i64,
+    archived: Option<DateTime<Utc>>,
+    created: DateTime<Utc>,
+    updated: Option<DateTime<Utc>>,
+}
+
+impl From<journal::Journal> for Journal {
+    fn from(journal: journal::Journal) -> Self {
+        Self {
+            id: journal.id.into(),
+            uid: journal.uid.to_string(),
+            name: journal.name,
+            description: journal.description,
+            e2e: journal.e2e,
+            schema_version: journal.schema_version,
+            archived: journal.archived,
+            created: journal.created,
+            updated: journal.updated,
+        }
+    }
+}
+
+#[derive(SimpleObject)]
+pub struct CustomField {
+    id: i64,
+    uid: String,
+    name: String,
+    order: i32,
+
+    /// the configuration of the field as json
+    config: GqlJson<serde_json::Value>,
+    description: Option<String>,
+}
+
+#[derive(SimpleObject)]
+pub struct Tag {
+    key: String,
+    value: Option<String>,
+}
+
+#[derive(SimpleObject)]
+pub struct TagTree {
+    name: String,
+    key: String,
+    entries: i64,
+    children: Vec<TagTree>,
+}
+
+impl From<TagNode> for TagTree {
+    fn from(node: TagNode) -> Self {
+        Self {
+            name: node.name,
+            key: node.key,
+            entries: node.entries,
+            children: node.children.into_iter()
+                .map(TagTree::from)
+                .collect(),
+        }
+    }
+}
+
+#[derive(SimpleObject)]
+pub struct File {
+    id: i64,
+    uid: String,
+    name: Option<String>,
+    mime_type: String,
+    mime_subtype: String,
+    size: i64,
+    hash: Option<String>,
+    created: DateTime<Utc>,
+}
+
+#[derive(SimpleObject)]
+pub struct FieldValue {
+    custom_fields_id: i64,
+
+    /// the value of the field as json
+    value: GqlJson<serde_json::Value>,
+}
+
+#[derive(SimpleObject)]
+#[graphql(complex)]
+pub struct Entry {
+    id: i64,
+    uid: String,
+    journals_id: i64,
+    number: i64,
+    date: NaiveDate,
+    title: Option<String>,
+
+    /// not available for the entries of an end-to-end encrypted journal
+    contents: Option<String>,
+    planned: bool,
+    created: DateTime<Utc>,
+    updated: Option<DateTime<Utc>>,
+}
+
+impl From<journal::Entry> for Entry {
+    fn from(entry: journal::Entry) -> Self {
+        Self {
+            id: entry.id.into(),
+            uid: entry.uid.to_string(),
+            journals_id: entry.journals_id.into(),
+            number: entry.number,
+            date: entry.date,
+            title: entry.title,
+            contents: entry.contents,
+            planned: entry.planned,
+            created: entry.created,
+            updated: entry.updated,
+        }
+    }
+}
+
+impl From<EntryPartial> for Entry {
+    fn from(entry: EntryPartial) -> Self {
+        Self {
+            id: entry.id.into(),
+            uid: entry.uid.to_string(),
+            journals_id: entry.journals_id.into(),
+            number: entry.number,
+            date: entry.date,
+            title: entry.title,
+            contents: None,
+            planned: entry.planned,
+            created: entry.created,
+            updated: entry.updated,
+        }
+    }
+}
+
+fn to_json<T: serde::Serialize>(value: &T) -> GqlJson<serde_json::Value> {
+    GqlJson(serde_json::to_value(value).unwrap_or(serde_json::Value::Null))
+}
+
+fn entries_id(id: i64) -> GqlResult<EntryId> {
+    EntryId::new(id).map_err(|_| async_graphql::Error::new("invalid entry id"))
+}
+
+/// retrieves a journal owned by the viewer
+async fn find_journal(ctx: &Context<'_>, id: i64) -> GqlResult<Option<journal::Journal>> {
+    let (state, viewer) = viewer(ctx)?;
+    let Ok(journals_id) = JournalId::new(id) else {
+        return Ok(None);
+    };
+    let conn = state.db_conn().await.map_err(internal)?;
+
+    journal::Journal::retrieve_id(&conn, &journals_id, &viewer.users_id)
+        .await
+        .context("failed to retrieve journal")
+        .map_err(internal)
+}
+
+#[ComplexObject]
+impl Journal {
+    async fn custom_fields(&self, ctx: &Context<'_>) -> GqlResult<Vec<CustomField>> {
+        let (state, _) = viewer(ctx)?;
+        let conn = state.db_conn().await.map_err(internal)?;
+        let journals_id = JournalId::new(self.id)
+            .map_err(|_| async_graphql::Error::new("invalid journal id"))?;
+
+        let stream = CustomFieldRecord::retrieve_journal_stream(&conn, &journals_id)
+            .await
+            .context("failed to retrieve custom fields")
+            .map_err(internal)?;
+
+        futures::pin_mut!(stream);
+
+        let mut rtn = Vec::new();
+
+        while let Some(try_record) = stream.next().await {
+            let record = try_record.context("failed to retrieve custom field record")
+                .map_err(internal)?;
+
+            rtn.push(CustomField {
+                id: record.id.into(),
+                uid: record.uid.to_string(),
+                name: record.name,
+                order: record.order,
+                config: to_json(&record.config),
+                description: record.description,
+            });
+        }
+
+        Ok(rtn)
+    }
+
+    /// the tags of the journal as a hierarchy
+    async fn tags(&self, ctx: &Context<'_>) -> GqlResult<Vec<TagTree>> {
+        require(ctx, Scope::Entries, Ability::Read).await?;
+
+        let (state, _) = viewer(ctx)?;
+        let conn = state.db_conn().await.map_err(internal)?;
+        let journals_id = JournalId::new(self.id)
+            .map_err(|_| async_graphql::Error::new("invalid journal id"))?;
+
+        let tags = TagNode::retrieve_journal(&conn, &journals_id)
+            .await
+            .context("failed to retrieve journal tags")
+            .map_err(internal)?;
+
+        Ok(tags.into_iter().map(TagTree::from).collect())
+    }
+
+    /// the entries of the journal ordered by the most recent date
+    async fn entries(
+        &self,
+        ctx: &Context<'_>,
+        tag: Option<String>,
+        #[graphql(default = false)]
+        planned: bool,
+        #[graphql(default = 1)]
+        page: u32,
+        #[graphql(default = 25)]
+        size: u32,
+    ) -> GqlResult<Vec<Entry>> {
+        require(ctx, Scope::Entries, Ability::Read).await?;
+
+        let (state, viewer) = viewer(ctx)?;
+        let conn = state.db_conn().await.map_err(internal)?;
+        let page = Page::new(Some(page), Some(size.min(MAX_ENTRIES)));
+
+        let mut found = EntryPartial::search(&conn, &EntrySearch {
+            users_id: viewer.users_id,
+            journals_id: JournalId::new(self.id)
+                .map_err(|_| async_graphql::Error::new("invalid journal id"))?,
+            planned,
+            tag: tag.as_deref().and_then(tag::normalize_key),
+            fields: Vec::new(),
+            text: None,
+            unread_by: None,
+            prompts_id: None,
+            subjects_id: None,
+            cursor: None,
+            page: Some(page),
+        })
+            .await
+            .map_err(internal)?;
+
+        page.split_next(&mut found);
+
+        Ok(found.into_iter().map(Entry::from).collect())
+    }
+
+    async fn entry(&self, ctx: &Context<'_>, id: i64) -> GqlResult<Option<Entry>> {
+        require(ctx, Scope::Entries, Ability::Read).await?;
+
+        let (state, viewer) = viewer(ctx)?;
+        let conn = state.db_conn().await.map_err(internal)?;
+        let journals_id = JournalId::new(self.id)
+            .map_err(|_| async_graphql::Error::new("invalid journal id"))?;
+
+        let found = journal::Entry::retrieve_id(&conn, &journals_id, &viewer.users_id, &entries_id(id)?)
+            .await
+            .context("failed to retrieve entry")
+            .map_err(internal)?;
+
+        Ok(found.map(Entry::from))
+    }
+}
+
+#[ComplexObject]
+impl Entry {
+    async fn tags(&self, ctx: &Context<'_>) -> GqlResult<Vec<Tag>> {
+        let (state, _) = viewer(ctx)?;
+        let conn = state.db_conn().await.map_err(internal)?;
+
+        let tags = journal::EntryTag::retrieve_entry(&conn, entries_id(self.id)?)
+            .await
+            .context("failed to retrieve entry tags")
+            .map_err(internal)?;
+
+        Ok(tags.into_iter()
+            .map(|tag| Tag {
+                key: tag.key,
+                value: tag.value,
+            })
+            .collect())
+    }
+
+    async fn files(&self, ctx: &Context<'_>) -> GqlResult<Vec<File>> {
+        let (state, _) = viewer(ctx)?;
+        let conn = state.db_conn().await.map_err(internal)?;
+
+        let stream = FileEntry::retrieve_entry_stream(&conn, &entries_id(self.id)?)
+            .await
+            .context("failed to retrieve entry files")
+            .map_err(internal)?;
+
+        futures::pin_mut!(stream);
+
+        let mut rtn = Vec::new();
+
+        while let Some(try_record) = stream.next().await {
+            let record = try_record.context("failed to retrieve entry file")
+                .map_err(internal)?;
+
+            rtn.push(File {
+                id: record.id.into(),
+                uid: record.uid.to_string(),
+                name: record.name,
+                mime_type: record.mime_type,
+                mime_subtype: record.mime_subtype,
+                size: record.size,
+                hash: record.hash,
+                created: record.created,
+            });
+        }
+
+        Ok(rtn)
+    }
+
+    async fn custom_fields(&self, ctx: &Context<'_>) -> GqlResult<Vec<FieldValue>> {
+        let (state, _) = viewer(ctx)?;
+        let conn = state.db_conn().await.map_err(internal)?;
+
+        let fields = custom_field::Entry::retrieve_entry(&conn, &entries_id(self.id)?)
+            .await
+            .context("failed to retrieve entry custom fields")
+            .map_err(internal)?;
+
+        Ok(fields.into_iter()
+            .map(|field| FieldValue {
+                custom_fields_id: field.custom_fields_id.into(),
+                value: to_json(&field.value),
+            })
+            .collect())
+    }
+}
+
+pub struct Query;
+
+#[Object]
+impl Query {
+    /// the user that made the request
+    async fn me(&self, ctx: &Context<'_>) -> GqlResult<User> {
+        let (state, viewer) = viewer(ctx)?;
+        let conn = state.db_conn().await.map_err(internal)?;
+
+        let row = conn.query_one(
+            "\
+            select users.id, \
+                   users.uid, \
+                   users.username, \
+                   users.created, \
+                   users.updated \
+            from users \
+            where users.id = $1",
+            &[&viewer.users_id]
+        )
+            .await
+            .context("failed to retrieve user")
+            .map_err(internal)?;
+
+        Ok(User {
+            id: row.get::<_, UserId>(0).into(),
+            uid: row.get(1),
+            username: row.get(2),
+            created: row.get(3),
+            updated: row.get(4),
+        })
+    }
+
+    /// the users of the current workspace. requires permission to read users
+    async fn users(&self, ctx: &Context<'_>) -> GqlResult<Vec<User>> {
+        require(ctx, Scope::Users, Ability::Read).await?;
+
+        let (state, viewer) = viewer(ctx)?;
+        let conn = state.db_conn().await.map_err(internal)?;
+
+        let rows = conn.query(
+            "\
+            select users.id, \
+                   users.uid, \
+                   users.username, \
+                   users.created, \
+                   users.updated \
+            from users \
+                join workspace_users on \
+                    users.id = workspace_users.users_id \
+            where workspace_users.workspaces_id = $1 \
+            order by users.username",
+            &[&viewer.workspaces_id]
+        )
+            .await
+            .context("failed to retrieve users")
+            .map_err(internal)?;
+
+        Ok(rows.into_iter()
+            .map(|row| User {
+                id: row.get::<_, UserId>(0).into(),
+                uid: row.get(1),
+                username: row.get(2),
+                created: row.get(3),
+                updated: row.get(4),
+            })
+            .collect())
+    }
+
+    /// the journals owned by the user that made the request
+    async fn journals(&self, ctx: &Context<'_>) -> GqlResult<Vec<Journal>> {
+        require(ctx, Scope::Journals, Ability::Read).await?;
+
+        let (state, viewer) = viewer(ctx)?;
+        let conn = state.db_conn().await.map_err(internal)?;
+
+        let journals = journal::Journal::retrieve_user(&conn, &viewer.users_id)
+            .await
+            .context("failed to retrieve journals")
+            .map_err(internal)?;
+
+        Ok(journals.into_iter().map(Journal::from).collect())
+    }
+
+    async fn journal(&self, ctx: &Context<'_>, id: i64) -> GqlResult<Option<Journal>> {
+        require(ctx, Scope::Journals, Ability::Read).await?;
+
+        Ok(find_journal(ctx, id).await?.map(Journal::from))
+    }
+}
+
+/// executes a graphql request for the current user
+pub async fn handle(
+    state: state::SharedState,
+    Extension(schema): Extension<GraphqlSchema>,
+    workspace: Workspace,
+    headers: HeaderMap,
+    Json(request): Json<async_graphql::Request>,
+) -> Result<Response, error::Error> {
+    let conn = state.db_conn().await?;
+
+    let initiator = match Initiator::from_headers(&conn, &headers).await {
+        Ok(initiator) => initiator,
+        Err(InitiatorError::DbPg(err)) => return Err(error::Error::context_source(
+            "database error when retrieving session",
+            err
+        )),
+        Err(err) => {
+            error::log_prefix_error("failed to retrieve request initiator", &err);
+
+            return Ok(StatusCode::UNAUTHORIZED.into_response());
+        }
+    };
+
+    // the connection is not needed while resolving and holding on to it
+    // would take one from the pool for the entire request
+    drop(conn);
+
+    let response = schema.execute(request.data(Viewer {
+        users_id: initiator.user.id,
+        workspaces_id: workspace.id,
+    })).await;
+
+    Ok(Json(response).into_response())
+}
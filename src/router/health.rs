@@ -0,0 +1,105 @@
+//! health checks for container orchestration probes
+//!
+//! "/healthz" only reports that the process is running. "/readyz" checks
+//! the database, storage directory, and templates that are needed to
+//! respond to requests
+
+use std::time::Duration;
+
+use axum::Router;
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::routing::get;
+use serde::Serialize;
+
+use crate::error::{self, Context};
+use crate::state;
+
+use super::body;
+
+/// the max amount of time a single readiness check can take
+const CHECK_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// the routes for a listener that only responds to health checks
+pub fn build(state: &state::SharedState) -> Router {
+    Router::new()
+        .route("/healthz", get(healthz))
+        .route("/readyz", get(readyz))
+        .with_state(state.clone())
+}
+
+pub async fn healthz() -> (StatusCode, &'static str) {
+    (StatusCode::OK, "ok")
+}
+
+#[derive(Debug, Serialize)]
+pub struct Readiness {
+    database: bool,
+    storage: bool,
+    templates: bool,
+}
+
+impl Readiness {
+    fn is_ready(&self) -> bool {
+        self.database && self.storage && self.templates
+    }
+}
+
+async fn check_database(state: &state::SharedState) -> Result<(), error::Error> {
+    let conn = state.db_conn().await?;
+
+    conn.execute("select 1", &[])
+        .await
+        .context("failed to query database")?;
+
+    Ok(())
+}
+
+pub async fn readyz(state: state::SharedState) -> Response {
+    let database = match tokio::time::timeout(CHECK_TIMEOUT, check_database(&state)).await {
+        Ok(Ok(())) => true,
+        Ok(Err(err)) => {
+            error::log_prefix_error("readiness database check failed", &err);
+
+            false
+        }
+        Err(_) => {
+            tracing::warn!("readiness database check timed out");
+
+            false
+        }
+    };
+
+    let storage = match tokio::time::timeout(CHECK_TIMEOUT, state.storage().check_writable()).await {
+        Ok(Ok(())) => true,
+        Ok(Err(err)) => {
+            error::log_prefix_error("readiness storage check failed", &err);
+
+            false
+        }
+        Err(_) => {
+            tracing::warn!("readiness storage check timed out");
+
+            false
+        }
+    };
+
+    let templates = state.templates()
+        .get_template_names()
+        .next()
+        .is_some();
+
+    let readiness = Readiness {
+        database,
+        storage,
+        templates,
+    };
+
+    let status = if readiness.is_ready() {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+
+    (status, body::Json(readiness)).into_response()
+}
@@ -1,11 +1,11 @@
 use std::collections::{HashSet, HashMap};
 
 use axum::Router;
-use axum::extract::Path;
+use axum::extract::{Path, Query};
 use axum::http::{StatusCode, Uri, HeaderMap};
 use axum::response::{IntoResponse, Response};
-use axum::routing::get;
-use chrono::{Utc, DateTime};
+use axum::routing::{get, post, put};
+use chrono::{Utc, DateTime, NaiveDate};
 use futures::StreamExt;
 use serde::{Serialize, Deserialize};
 
@@ -21,30 +21,108 @@ use crate::db::ids::{
 use crate::error::{self, Context};
 use crate::journal::{
     custom_field,
+    e2e,
+    order::{self, JournalSort, OrderInput},
+    webhook::{self, WebhookEvent},
     Journal,
     JournalCreateError,
+    JournalSettings,
     JournalUpdateError,
     CustomField,
 };
 use crate::router::body;
 use crate::router::macros;
 use crate::sec::authz::{self, Scope, Ability};
+use crate::workspace::Workspace;
 
-mod entries;
+mod config;
+mod default;
+mod delete;
+pub(super) mod entries;
 
 pub fn build(_state: &state::SharedState) -> Router<state::SharedState> {
     Router::new()
         .route("/", get(retrieve_journals)
             .post(create_journal))
+        .route("/order", put(update_order))
         .route("/new", get(retrieve_journal))
+        .route("/default", get(default::retrieve_default)
+            .put(default::update_default)
+            .delete(default::delete_default))
         .route("/:journals_id", get(retrieve_journal)
-            .patch(update_journal))
+            .patch(update_journal)
+            .delete(delete::delete_journal))
+        .route("/:journals_id/config", get(config::export_config)
+            .post(config::import_config))
+        .route("/:journals_id/on-this-day", get(entries::retrieve_on_this_day))
+        .route("/:journals_id/stats", get(entries::stats::retrieve_stats))
+        .route("/:journals_id/tags", get(entries::tags::retrieve_tags))
+        .route("/:journals_id/custom_fields/:custom_fields_id/trend", get(entries::stats::retrieve_trend))
+        .route("/:journals_id/freeze", get(entries::freeze::retrieve_freeze)
+            .post(entries::freeze::create_freeze)
+            .delete(entries::freeze::lift_freeze))
+        .route("/:journals_id/archive", post(entries::archive::archive_journal)
+            .delete(entries::archive::unarchive_journal))
+        .route("/:journals_id/location", get(entries::location::retrieve_location)
+            .put(entries::location::update_location)
+            .delete(entries::location::delete_location))
+        .route("/:journals_id/settings", get(entries::settings::retrieve_settings)
+            .patch(entries::settings::update_settings))
+        .route("/:journals_id/goals", get(entries::goals::retrieve_goals)
+            .post(entries::goals::create_goal))
+        .route("/:journals_id/goals/:goals_id", get(entries::goals::retrieve_goal)
+            .patch(entries::goals::update_goal)
+            .delete(entries::goals::delete_goal))
+        .route("/:journals_id/goals/:goals_id/progress", get(entries::goals::retrieve_progress))
+        .route("/:journals_id/subjects", get(entries::subjects::retrieve_subjects)
+            .post(entries::subjects::create_subject))
+        .route("/:journals_id/subjects/:subjects_id", get(entries::subjects::retrieve_subject)
+            .patch(entries::subjects::update_subject)
+            .delete(entries::subjects::delete_subject))
+        .route("/:journals_id/subjects/:subjects_id/entries", get(entries::subjects::retrieve_subject_entries))
+        .route("/:journals_id/subjects/:subjects_id/counts", get(entries::subjects::retrieve_subject_counts))
+        .route("/:journals_id/upload-policy", get(entries::upload_policy::retrieve_policy)
+            .put(entries::upload_policy::update_policy)
+            .delete(entries::upload_policy::delete_policy))
+        .route("/:journals_id/e2e", get(entries::e2e::retrieve_escrow)
+            .put(entries::e2e::update_escrow))
+        .route("/:journals_id/webhooks", get(entries::webhooks::retrieve_webhooks)
+            .post(entries::webhooks::create_webhook))
+        .route("/:journals_id/webhooks/:webhooks_id", get(entries::webhooks::retrieve_webhook)
+            .patch(entries::webhooks::update_webhook)
+            .delete(entries::webhooks::delete_webhook))
+        .route("/:journals_id/webhooks/:webhooks_id/deliveries", get(entries::webhooks::retrieve_deliveries))
+        .route("/:journals_id/webhooks/:webhooks_id/deliveries/:deliveries_id/redeliver", post(entries::webhooks::redeliver))
+        .route("/:journals_id/shares", get(entries::shares::retrieve_shares))
+        .route("/:journals_id/shares/:users_id", get(entries::shares::retrieve_share)
+            .put(entries::shares::update_share)
+            .delete(entries::shares::delete_share))
+        .route("/:journals_id/export", post(entries::export::create_export))
+        .route("/:journals_id/export/:exports_id", get(entries::export::retrieve_export))
+        .route("/:journals_id/export/:exports_id/download", get(entries::export::download_export))
+        .route("/:journals_id/imports", post(entries::import::create_import))
+        .route("/:journals_id/imports/:imports_id", get(entries::import::retrieve_import))
+        .route("/:journals_id/files/metadata", post(entries::files::retrieve_metadata))
         .route("/:journals_id/entries", get(entries::retrieve_entries)
             .post(entries::create_entry))
         .route("/:journals_id/entries/new", get(entries::retrieve_entry))
+        .route("/:journals_id/entries/list", get(entries::list::retrieve_list))
+        .route("/:journals_id/entries/read", post(entries::views::mark_read))
+        .route("/:journals_id/entries/random", get(entries::retrieve_random_entry))
+        .route("/:journals_id/entries/export.ics", get(entries::ics::export_ics))
+        .route("/:journals_id/feed_token", get(entries::ics::retrieve_feed_token)
+            .post(entries::ics::create_feed_token)
+            .delete(entries::ics::delete_feed_token))
+        .route("/:journals_id/entries/by-number/:entry_number", get(entries::retrieve_entry_number))
         .route("/:journals_id/entries/:entries_id", get(entries::retrieve_entry)
             .patch(entries::update_entry)
             .delete(entries::delete_entry))
+        .route("/:journals_id/entries/:entries_id/reading", get(entries::reading::retrieve_reading))
+        .route("/:journals_id/entries/:entries_id/history", get(entries::history::retrieve_history))
+        .route("/:journals_id/entries/:entries_id/history/:revision", get(entries::history::retrieve_revision))
+        .route("/:journals_id/entries/:entries_id/history/:revision/diff", get(entries::history::diff_revision))
+        .route("/:journals_id/entries/:entries_id/history/:revision/restore", post(entries::history::restore_revision))
+        .route("/:journals_id/entries/:entries_id/tasks/:tasks_id/toggle", post(entries::tasks::toggle_task))
         .route("/:journals_id/entries/:entries_id/:file_entry_id", get(entries::files::retrieve_file)
             .put(entries::files::upload_file))
 }
@@ -56,55 +134,68 @@ pub struct JournalPartial {
     pub users_id: UserId,
     pub name: String,
     pub description: Option<String>,
+    pub e2e: bool,
+    pub schema_version: i64,
+    pub pinned: bool,
+    pub last_entry: Option<NaiveDate>,
+    pub archived: Option<DateTime<Utc>>,
     pub created: DateTime<Utc>,
     pub updated: Option<DateTime<Utc>>,
 }
 
-async fn retrieve_journals(
-    state: state::SharedState,
-    uri: Uri,
-    headers: HeaderMap,
-) -> Result<Response, error::Error> {
-    let conn = state.db_conn().await?;
-
-    let initiator = macros::require_initiator!(
-        &conn,
-        &headers,
-        Some(uri.clone())
-    );
-
-    macros::res_if_html!(state.templates(), &headers);
-
-    let perm_check = authz::has_permission(
-        &conn,
-        initiator.user.id,
-        Scope::Journals,
-        Ability::Read
-    )
+/// retrieves the journals of a user in a workspace using the sort the user
+/// has chosen. only archived journals are retrieved if archived is true
+async fn retrieve_partials(
+    conn: &impl db::GenericClient,
+    users_id: &UserId,
+    workspace: &Workspace,
+    archived: bool,
+) -> Result<Vec<JournalPartial>, error::Error> {
+    let sort = JournalSort::retrieve(conn, users_id)
         .await
-        .context("failed to retrieve permission for user")?;
+        .context("failed to retrieve journal sort")?;
 
-    if !perm_check {
-        return Ok(StatusCode::UNAUTHORIZED.into_response());
-    }
-
-    let params: db::ParamsArray<'_, 1> = [&initiator.user.id];
+    let params: db::ParamsArray<'_, 4> = [users_id, &workspace.id, &sort, &archived];
     let journals = conn.query_raw(
         "\
         with search_journals as ( \
             select * \
             from journals \
-            where journals.users_id = $1 \
+            where journals.users_id = $1 and \
+                  journals.workspaces_id = $2 and \
+                  (journals.archived is not null) = $4 \
+        ), \
+        last_entries as ( \
+            select entries.journals_id, \
+                   max(entries.entry_date) as entry_date \
+            from entries \
+                join search_journals on \
+                    entries.journals_id = search_journals.id \
+            where not entries.planned \
+            group by entries.journals_id \
         ) \
         select search_journals.id, \
                search_journals.uid, \
                search_journals.users_id, \
                search_journals.name, \
                search_journals.description, \
+               search_journals.e2e, \
+               search_journals.schema_version, \
+               coalesce(journal_orders.pinned, false) as pinned, \
+               last_entries.entry_date, \
+               search_journals.archived, \
                search_journals.created, \
                search_journals.updated \
         from search_journals \
-        order by search_journals.name",
+            left join journal_orders on \
+                search_journals.id = journal_orders.journals_id and \
+                journal_orders.users_id = $1 \
+            left join last_entries on \
+                search_journals.id = last_entries.journals_id \
+        order by pinned desc, \
+                 case when $3::varchar = 'manual' then journal_orders.position end asc nulls last, \
+                 case when $3::varchar = 'last_entry' then last_entries.entry_date end desc nulls last, \
+                 search_journals.name",
         params
     )
         .await
@@ -123,14 +214,143 @@ async fn retrieve_journals(
             users_id: record.get(2),
             name: record.get(3),
             description: record.get(4),
-            created: record.get(5),
-            updated: record.get(6),
+            e2e: record.get(5),
+            schema_version: record.get(6),
+            pinned: record.get(7),
+            last_entry: record.get(8),
+            archived: record.get(9),
+            created: record.get(10),
+            updated: record.get(11),
         });
     }
 
+    Ok(found)
+}
+
+#[derive(Debug, Deserialize)]
+pub struct JournalsQuery {
+    /// lists the archived journals instead
+    #[serde(default)]
+    archived: bool,
+}
+
+async fn retrieve_journals(
+    state: state::SharedState,
+    workspace: Workspace,
+    uri: Uri,
+    headers: HeaderMap,
+    Query(JournalsQuery { archived }): Query<JournalsQuery>,
+) -> Result<Response, error::Error> {
+    let conn = state.db_conn().await?;
+
+    let initiator = macros::require_initiator!(
+        &conn,
+        &headers,
+        Some(uri.clone())
+    );
+
+    macros::res_if_html!(state.templates(), &headers);
+
+    let perm_check = authz::has_permission(
+        &conn,
+        initiator.user.id,
+        Scope::Journals,
+        Ability::Read
+    )
+        .await
+        .context("failed to retrieve permission for user")?;
+
+    if !perm_check {
+        return Ok(StatusCode::UNAUTHORIZED.into_response());
+    }
+
+    let found = retrieve_partials(&conn, &initiator.user.id, &workspace, archived).await?;
+
     Ok(body::Json(found).into_response())
 }
 
+#[derive(Debug, Deserialize)]
+pub struct UpdateOrder {
+    sort: JournalSort,
+
+    /// the manual order of the journals. the current order is kept if this
+    /// is not provided
+    journals: Option<Vec<OrderInput>>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "type")]
+pub enum UpdateOrderResult {
+    JournalNotFound {
+        ids: Vec<JournalId>,
+    },
+    DuplicateJournals {
+        ids: Vec<JournalId>,
+    },
+    Updated {
+        sort: JournalSort,
+        journals: Vec<JournalPartial>,
+    },
+}
+
+/// updates how the journals of a user are listed
+async fn update_order(
+    tx: db::Tx,
+    workspace: Workspace,
+    headers: HeaderMap,
+    body::Json(json): body::Json<UpdateOrder>,
+) -> Result<Response, error::Error> {
+    let initiator = macros::require_initiator!(&*tx, &headers, None::<Uri>);
+
+    let perm_check = authz::has_permission(
+        &*tx,
+        initiator.user.id,
+        Scope::Journals,
+        Ability::Read
+    )
+        .await
+        .context("failed to retrieve permission for user")?;
+
+    if !perm_check {
+        return Ok(StatusCode::UNAUTHORIZED.into_response());
+    }
+
+    json.sort.update(&*tx, &initiator.user.id)
+        .await
+        .context("failed to update journal sort")?;
+
+    if let Some(journals) = json.journals {
+        let result = order::replace(&*tx, &initiator.user.id, &journals)
+            .await
+            .context("failed to update journal order")?;
+
+        if !result.duplicates.is_empty() {
+            return Ok(body::FieldError::new(
+                "journals",
+                UpdateOrderResult::DuplicateJournals {
+                    ids: result.duplicates
+                }
+            ).into_response());
+        }
+
+        if !result.not_found.is_empty() {
+            return Ok(body::FieldError::new(
+                "journals",
+                UpdateOrderResult::JournalNotFound {
+                    ids: result.not_found
+                }
+            ).into_response());
+        }
+    }
+
+    let journals = retrieve_partials(&*tx, &initiator.user.id, &workspace, false).await?;
+
+    Ok(body::Json(UpdateOrderResult::Updated {
+        sort: json.sort,
+        journals,
+    }).into_response())
+}
+
 #[derive(Debug, Deserialize)]
 pub struct MaybeJournalPath {
     journals_id: Option<JournalId>,
@@ -160,7 +380,14 @@ pub struct JournalFull {
     pub users_id: UserId,
     pub name: String,
     pub description: Option<String>,
+    pub e2e: bool,
+
+    /// increases every time the custom fields change. clients should refetch
+    /// the journal when the version differs from what they have cached
+    pub schema_version: i64,
     pub custom_fields: Vec<CustomFieldFull>,
+    pub archived: Option<DateTime<Utc>>,
+    pub settings: JournalSettings,
     pub created: DateTime<Utc>,
     pub updated: Option<DateTime<Utc>>,
 }
@@ -230,7 +457,11 @@ async fn retrieve_journal(
         users_id: journal.users_id,
         name: journal.name,
         description: journal.description,
+        e2e: journal.e2e,
+        schema_version: journal.schema_version,
         custom_fields,
+        archived: journal.archived,
+        settings: journal.settings,
         created: journal.created,
         updated: journal.updated,
     }).into_response())
@@ -248,6 +479,11 @@ pub struct NewCustomField {
 pub struct NewJournal {
     name: String,
     description: Option<String>,
+
+    /// the entries of the journal will be encrypted by the client. can only
+    /// be set when the journal is created
+    #[serde(default)]
+    e2e: bool,
     custom_fields: Vec<NewCustomField>,
 }
 
@@ -263,18 +499,15 @@ pub enum NewJournalResult {
 
 async fn create_journal(
     state: state::SharedState,
+    tx: db::Tx,
+    workspace: Workspace,
     headers: HeaderMap,
     body::Json(json): body::Json<NewJournal>,
 ) -> Result<Response, error::Error> {
-    let mut conn = state.db_conn().await?;
-    let transaction = conn.transaction()
-        .await
-        .context("failed to create transaction")?;
-
-    let initiator = macros::require_initiator!(&transaction, &headers, None::<Uri>);
+    let initiator = macros::require_initiator!(&*tx, &headers, None::<Uri>);
 
     let perm_check = authz::has_permission(
-        &transaction,
+        &*tx,
         initiator.user.id,
         Scope::Journals,
         Ability::Create
@@ -286,20 +519,21 @@ async fn create_journal(
         return Ok(StatusCode::UNAUTHORIZED.into_response());
     }
 
-    let mut options = Journal::create_options(initiator.user.id, json.name);
+    let mut options = Journal::create_options(workspace.id, initiator.user.id, json.name)
+        .e2e(json.e2e);
 
     if let Some(description) = json.description {
         options = options.description(description);
     }
 
-    let result = Journal::create(&transaction, options).await;
+    let result = Journal::create(&*tx, options).await;
 
     let journal = match result {
         Ok(journal) => journal,
         Err(err) => match err {
-            JournalCreateError::NameExists => return Ok((
-                StatusCode::BAD_REQUEST,
-                body::Json(NewJournalResult::NameExists)
+            JournalCreateError::NameExists => return Ok(body::FieldError::new(
+                "name",
+                NewJournalResult::NameExists
             ).into_response()),
             JournalCreateError::UserNotFound => return Err(
                 error::Error::context("specified user does not exist")
@@ -313,16 +547,25 @@ async fn create_journal(
         }
     };
 
+    if journal.e2e {
+        let kdf = e2e::KdfParams::generate()
+            .context("failed to generate key derivation parameters")?;
+
+        e2e::EscrowKey::create(&*tx, &journal.id, kdf)
+            .await
+            .context("failed to create journal escrow key")?;
+    }
+
     let (custom_fields, duplicates) = create_custom_fields(
-        &transaction, &journal, json.custom_fields
+        &*tx, &journal, json.custom_fields
     ).await?;
 
     if !duplicates.is_empty() {
-        return Ok((
-            StatusCode::BAD_REQUEST,
-            body::Json(NewJournalResult::DuplicateCustomFields {
+        return Ok(body::FieldError::new(
+            "custom_fields",
+            NewJournalResult::DuplicateCustomFields {
                 duplicates
-            })
+            }
         ).into_response());
     }
 
@@ -347,7 +590,7 @@ async fn create_journal(
         }
     };
 
-    if let Err(err) = transaction.commit().await {
+    if let Err(err) = tx.commit().await {
         if let Err(files_err) = tokio::fs::remove_dir(&files_dir).await {
             error::log_prefix_error(
                 "failed to remove journal files dir",
@@ -372,7 +615,11 @@ async fn create_journal(
         users_id: journal.users_id,
         name: journal.name,
         description: journal.description,
+        e2e: journal.e2e,
+        schema_version: journal.schema_version,
         custom_fields,
+        archived: journal.archived,
+        settings: journal.settings,
         created: journal.created,
         updated: journal.updated,
     })).into_response())
@@ -413,21 +660,23 @@ pub enum UpdateJournalResult {
     Updated(JournalFull),
 }
 
+/// the data sent with the "journal.schema_updated" webhook event
+#[derive(Debug, Serialize)]
+struct SchemaUpdated<'a> {
+    schema_version: i64,
+    custom_fields: &'a [CustomFieldFull],
+}
+
 async fn update_journal(
-    state: state::SharedState,
+    tx: db::Tx,
     headers: HeaderMap,
     Path(JournalPath { journals_id }): Path<JournalPath>,
     body::Json(json): body::Json<UpdateJournal>,
 ) -> Result<Response, error::Error> {
-    let mut conn = state.db_conn().await?;
-    let transaction = conn.transaction()
-        .await
-        .context("failed to create transaction")?;
-
-    let initiator = macros::require_initiator!(&transaction, &headers, None::<Uri>);
+    let initiator = macros::require_initiator!(&*tx, &headers, None::<Uri>);
 
     let perm_check = authz::has_permission(
-        &transaction,
+        &*tx,
         initiator.user.id,
         Scope::Journals,
         Ability::Update
@@ -439,7 +688,7 @@ async fn update_journal(
         return Ok(StatusCode::UNAUTHORIZED.into_response());
     }
 
-    let result = Journal::retrieve_id(&transaction, &journals_id, &initiator.user.id)
+    let result = Journal::retrieve_id(&*tx, &journals_id, &initiator.user.id)
         .await
         .context("failed to retrieve journal")?;
 
@@ -451,11 +700,11 @@ async fn update_journal(
     journal.description = json.description;
     journal.updated = Some(Utc::now());
 
-    if let Err(err) = journal.update(&transaction).await {
+    if let Err(err) = journal.update(&*tx).await {
         match err {
-            JournalUpdateError::NameExists => return Ok((
-                StatusCode::BAD_REQUEST,
-                body::Json(UpdateJournalResult::NameExists)
+            JournalUpdateError::NameExists => return Ok(body::FieldError::new(
+                "name",
+                UpdateJournalResult::NameExists
             ).into_response()),
             JournalUpdateError::NotFound => return Err(
                 error::Error::context(
@@ -471,33 +720,42 @@ async fn update_journal(
         }
     }
 
-    let UpdateResults {valid, not_found, duplicates} = update_custom_fields(
-        &transaction,
+    let UpdateResults {valid, not_found, duplicates, changed} = update_custom_fields(
+        &*tx,
         &journal,
         json.custom_fields,
     ).await?;
 
     if !duplicates.is_empty() {
-        return Ok((
-            StatusCode::BAD_REQUEST,
-            body::Json(UpdateJournalResult::DuplicateCustomFields {
+        return Ok(body::FieldError::new(
+            "custom_fields",
+            UpdateJournalResult::DuplicateCustomFields {
                 duplicates
-            })
+            }
         ).into_response());
     }
 
     if !not_found.is_empty() {
-        return Ok((
-            StatusCode::BAD_REQUEST,
-            body::Json(UpdateJournalResult::CustomFieldNotFound {
+        return Ok(body::FieldError::new(
+            "custom_fields",
+            UpdateJournalResult::CustomFieldNotFound {
                 ids: not_found
-            })
+            }
         ).into_response());
     }
 
-    transaction.commit()
-        .await
-        .context("failed to commit transaction")?;
+    if changed {
+        journal.bump_schema_version(&*tx)
+            .await
+            .context("failed to update journal schema version")?;
+
+        webhook::queue(&*tx, &journal.id, WebhookEvent::SchemaUpdated, &SchemaUpdated {
+            schema_version: journal.schema_version,
+            custom_fields: &valid,
+        })
+            .await
+            .context("failed to queue schema updated webhooks")?;
+    }
 
     Ok(body::Json(UpdateJournalResult::Updated(JournalFull {
         id: journal.id,
@@ -505,7 +763,11 @@ async fn update_journal(
         users_id: journal.users_id,
         name: journal.name,
         description: journal.description,
+        e2e: journal.e2e,
+        schema_version: journal.schema_version,
         custom_fields: valid,
+        archived: journal.archived,
+        settings: journal.settings,
         created: journal.created,
         updated: journal.updated,
     })).into_response())
@@ -563,6 +825,9 @@ struct UpdateResults {
     valid: Vec<CustomFieldFull>,
     not_found: Vec<CustomFieldId>,
     duplicates: Vec<String>,
+
+    /// fields were added, removed, or modified
+    changed: bool,
 }
 
 async fn update_custom_fields(
@@ -592,6 +857,7 @@ async fn update_custom_fields(
     let mut update_records = Vec::new();
     let mut insert_records = Vec::new();
     let mut existing_names = HashSet::new();
+    let mut changed = false;
 
     for field in update_fields {
         match field {
@@ -616,6 +882,11 @@ async fn update_custom_fields(
                     continue;
                 }
 
+                changed = changed ||
+                    found.name != existing_field.name ||
+                    found.order != existing_field.order ||
+                    found.description != existing_field.description;
+
                 found.name = existing_field.name;
                 found.order = existing_field.order;
                 found.description = existing_field.description;
@@ -658,10 +929,13 @@ async fn update_custom_fields(
             valid: Vec::new(),
             not_found,
             duplicates,
+            changed: false,
         });
     }
 
     if !insert_records.is_empty() {
+        changed = true;
+
         rtn.extend(insert_custom_fields(conn, insert_records).await?);
     }
 
@@ -716,11 +990,23 @@ async fn update_custom_fields(
     }));
 
     if !existing.is_empty() {
+        changed = true;
+
         let ids: Vec<CustomFieldId> = existing.into_keys()
             .collect();
 
         tracing::debug!("deleting ids: {ids:#?}");
 
+        conn.execute(
+            "\
+            delete from journal_goals \
+            where kind ->> 'type' = 'Field' and \
+                  (kind ->> 'custom_fields_id')::bigint = any($1)",
+            &[&ids]
+        )
+            .await
+            .context("failed to delete custom field goals")?;
+
         conn.execute(
             "delete from custom_fields where id = any($1)",
             &[&ids]
@@ -733,6 +1019,7 @@ async fn update_custom_fields(
         valid: rtn,
         not_found: Vec::new(),
         duplicates: Vec::new(),
+        changed,
     })
 }
 
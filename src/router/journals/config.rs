@@ -0,0 +1,351 @@
+//! portable journal configurations
+//!
+//! the configuration of a journal is its custom fields. it can be exported
+//! as a json document and imported into another journal, on this server or
+//! another, without any of the entries of the journal
+
+use std::collections::HashSet;
+
+use axum::extract::Path;
+use axum::http::{StatusCode, Uri, HeaderMap};
+use axum::response::{IntoResponse, Response};
+use chrono::Utc;
+use futures::StreamExt;
+use serde::{Serialize, Deserialize};
+
+use crate::state;
+use crate::db;
+use crate::db::ids::{CustomFieldId, CustomFieldUid};
+use crate::error::{self, Context};
+use crate::journal::{custom_field, CustomField, Journal};
+use crate::journal::webhook::{self, WebhookEvent};
+use crate::router::body;
+use crate::router::macros;
+use crate::sec::authz::{self, Scope, Ability};
+
+use super::{insert_custom_fields, CustomFieldFull, JournalPath, SchemaUpdated};
+
+/// the current version of the configuration document
+const CONFIG_VERSION: u32 = 1;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ConfigField {
+    name: String,
+    order: i32,
+    config: custom_field::Type,
+    description: Option<String>,
+}
+
+/// the portable configuration of a journal
+#[derive(Debug, Serialize, Deserialize)]
+pub struct JournalConfig {
+    version: u32,
+
+    /// the name of the journal that was exported. not used when importing
+    name: String,
+    description: Option<String>,
+    custom_fields: Vec<ConfigField>,
+}
+
+/// exports the configuration of a journal
+pub async fn export_config(
+    state: state::SharedState,
+    uri: Uri,
+    headers: HeaderMap,
+    Path(JournalPath { journals_id }): Path<JournalPath>,
+) -> Result<Response, error::Error> {
+    let conn = state.db_conn().await?;
+
+    let initiator = macros::require_initiator!(&conn, &headers, Some(uri));
+
+    let perm_check = authz::has_permission(
+        &conn,
+        initiator.user.id,
+        Scope::Journals,
+        Ability::Read
+    )
+        .await
+        .context("failed to retrieve permission for user")?;
+
+    if !perm_check {
+        return Ok(StatusCode::UNAUTHORIZED.into_response());
+    }
+
+    let result = Journal::retrieve_id(&conn, &journals_id, &initiator.user.id)
+        .await
+        .context("failed to retrieve journal")?;
+
+    let Some(journal) = result else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+
+    let mut custom_fields = Vec::new();
+    let fields = CustomField::retrieve_journal_stream(&conn, &journal.id)
+        .await
+        .context("failed to retrieve custom fields")?;
+
+    futures::pin_mut!(fields);
+
+    while let Some(try_record) = fields.next().await {
+        let record = try_record.context("failed to retrieve custom field record")?;
+
+        custom_fields.push(ConfigField {
+            name: record.name,
+            order: record.order,
+            config: record.config,
+            description: record.description,
+        });
+    }
+
+    Ok(body::Json(JournalConfig {
+        version: CONFIG_VERSION,
+        name: journal.name,
+        description: journal.description,
+        custom_fields,
+    }).into_response())
+}
+
+/// how to handle an imported custom field with the same name as an existing
+/// one
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OnConflict {
+    /// keeps the existing field and does not import the new one
+    #[default]
+    Skip,
+
+    /// imports the new field with a number appended to its name. ex:
+    /// "mood (2)"
+    Rename,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ImportConfig {
+    config: JournalConfig,
+
+    #[serde(default)]
+    on_conflict: OnConflict,
+
+    /// only reports the changes that would be made
+    #[serde(default)]
+    dry_run: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RenamedField {
+    from: String,
+    to: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SkippedField {
+    name: String,
+
+    /// the existing field has a different type, order, or description
+    differs: bool,
+}
+
+/// the changes made to the custom fields of a journal by an import
+#[derive(Debug, Default, Serialize)]
+pub struct ConfigDiff {
+    added: Vec<String>,
+    renamed: Vec<RenamedField>,
+    skipped: Vec<SkippedField>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "type")]
+pub enum ImportConfigResult {
+    UnsupportedVersion {
+        version: u32,
+    },
+    DuplicateCustomFields {
+        duplicates: Vec<String>,
+    },
+    Preview(ConfigDiff),
+    Imported {
+        schema_version: i64,
+        diff: ConfigDiff,
+    },
+}
+
+/// finds the first "name (n)" that is not already in use
+fn available_name(name: &str, used: &HashSet<String>) -> String {
+    let mut count = 2;
+
+    loop {
+        let attempt = format!("{name} ({count})");
+
+        if !used.contains(&attempt) {
+            return attempt;
+        }
+
+        count += 1;
+    }
+}
+
+/// checks if an existing field is different from an imported one
+fn field_differs(existing: &CustomField, given: &ConfigField) -> bool {
+    existing.order != given.order ||
+        existing.description != given.description ||
+        serde_json::to_value(&existing.config).ok() != serde_json::to_value(&given.config).ok()
+}
+
+/// imports a configuration into a journal
+///
+/// only custom fields are imported. the name and description of the journal
+/// are left unchanged
+pub async fn import_config(
+    tx: db::Tx,
+    headers: HeaderMap,
+    Path(JournalPath { journals_id }): Path<JournalPath>,
+    body::Json(json): body::Json<ImportConfig>,
+) -> Result<Response, error::Error> {
+    let initiator = macros::require_initiator!(&*tx, &headers, None::<Uri>);
+
+    let perm_check = authz::has_permission(
+        &*tx,
+        initiator.user.id,
+        Scope::Journals,
+        Ability::Update
+    )
+        .await
+        .context("failed to retrieve permission for user")?;
+
+    if !perm_check {
+        return Ok(StatusCode::UNAUTHORIZED.into_response());
+    }
+
+    let result = Journal::retrieve_id(&*tx, &journals_id, &initiator.user.id)
+        .await
+        .context("failed to retrieve journal")?;
+
+    let Some(mut journal) = result else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+
+    if json.config.version != CONFIG_VERSION {
+        return Ok(body::FieldError::new(
+            "config.version",
+            ImportConfigResult::UnsupportedVersion {
+                version: json.config.version
+            }
+        ).into_response());
+    }
+
+    let mut given_names = HashSet::new();
+    let mut duplicates = Vec::new();
+
+    for field in &json.config.custom_fields {
+        if !given_names.insert(field.name.as_str()) {
+            duplicates.push(field.name.clone());
+        }
+    }
+
+    if !duplicates.is_empty() {
+        return Ok(body::FieldError::new(
+            "config.custom_fields",
+            ImportConfigResult::DuplicateCustomFields {
+                duplicates
+            }
+        ).into_response());
+    }
+
+    let mut existing = Vec::new();
+    let stream = CustomField::retrieve_journal_stream(&*tx, &journal.id)
+        .await
+        .context("failed to retrieve current custom fields")?;
+
+    futures::pin_mut!(stream);
+
+    while let Some(try_record) = stream.next().await {
+        existing.push(try_record.context("failed to retrieve custom_field record")?);
+    }
+
+    let mut used: HashSet<String> = existing.iter()
+        .map(|field| field.name.clone())
+        .collect();
+    let created = Utc::now();
+    let mut diff = ConfigDiff::default();
+    let mut records = Vec::new();
+
+    for field in json.config.custom_fields {
+        let name = if let Some(found) = existing.iter().find(|e| e.name == field.name) {
+            match json.on_conflict {
+                OnConflict::Skip => {
+                    diff.skipped.push(SkippedField {
+                        differs: field_differs(found, &field),
+                        name: field.name,
+                    });
+
+                    continue;
+                }
+                OnConflict::Rename => {
+                    let to = available_name(&field.name, &used);
+
+                    diff.renamed.push(RenamedField {
+                        from: field.name,
+                        to: to.clone(),
+                    });
+
+                    to
+                }
+            }
+        } else {
+            diff.added.push(field.name.clone());
+
+            field.name
+        };
+
+        used.insert(name.clone());
+
+        records.push(CustomField {
+            id: CustomFieldId::zero(),
+            uid: CustomFieldUid::gen(),
+            journals_id: journal.id,
+            name,
+            order: field.order,
+            config: field.config,
+            description: field.description,
+            created,
+            updated: None,
+        });
+    }
+
+    if json.dry_run {
+        return Ok(body::Json(ImportConfigResult::Preview(diff)).into_response());
+    }
+
+    if !records.is_empty() {
+        let mut custom_fields: Vec<CustomFieldFull> = existing.into_iter()
+            .map(|record| CustomFieldFull {
+                id: record.id,
+                uid: record.uid,
+                name: record.name,
+                order: record.order,
+                config: record.config,
+                description: record.description,
+                created: record.created,
+                updated: record.updated,
+            })
+            .collect();
+
+        custom_fields.extend(insert_custom_fields(&*tx, records).await?);
+
+        journal.bump_schema_version(&*tx)
+            .await
+            .context("failed to update journal schema version")?;
+
+        webhook::queue(&*tx, &journal.id, WebhookEvent::SchemaUpdated, &SchemaUpdated {
+            schema_version: journal.schema_version,
+            custom_fields: &custom_fields,
+        })
+            .await
+            .context("failed to queue schema updated webhooks")?;
+    }
+
+    Ok(body::Json(ImportConfigResult::Imported {
+        schema_version: journal.schema_version,
+        diff,
+    }).into_response())
+}
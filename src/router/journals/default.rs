@@ -0,0 +1,148 @@
+//! the journal that is used when a journal is not specified
+//!
+//! the choice is stored with the preferences of the user. when it is not
+//! set, or the journal is archived or in another workspace, the first
+//! journal in the order of the user is used instead
+
+use axum::http::{StatusCode, HeaderMap};
+use axum::response::{IntoResponse, Response};
+use serde::{Serialize, Deserialize};
+
+use crate::state;
+use crate::db;
+use crate::db::ids::{JournalId, UserId};
+use crate::error::{self, Context};
+use crate::journal::Journal;
+use crate::router::body;
+use crate::router::macros;
+use crate::user::preferences::Preferences;
+use crate::workspace::Workspace;
+
+#[derive(Debug, Serialize)]
+pub struct DefaultJournal {
+    journals_id: JournalId,
+    name: String,
+
+    /// true if the user chose the journal, false if it is the fallback
+    preferred: bool,
+}
+
+/// retrieves the default journal along with if the user chose it
+async fn retrieve(
+    conn: &impl db::GenericClient,
+    users_id: &UserId,
+    workspace: &Workspace,
+) -> Result<Option<DefaultJournal>, error::Error> {
+    let preferences = Preferences::retrieve(conn, users_id)
+        .await
+        .context("failed to retrieve preferences")?;
+
+    let result = Journal::retrieve_default(conn, users_id, &workspace.id)
+        .await
+        .context("failed to retrieve default journal")?;
+
+    Ok(result.map(|journal| DefaultJournal {
+        preferred: preferences.default_journals_id == Some(journal.id),
+        journals_id: journal.id,
+        name: journal.name,
+    }))
+}
+
+/// retrieves the journal that is used when one is not specified
+pub async fn retrieve_default(
+    state: state::SharedState,
+    workspace: Workspace,
+    headers: HeaderMap,
+) -> Result<Response, error::Error> {
+    let conn = state.db_conn().await?;
+
+    let initiator = macros::require_initiator!(&conn, &headers, None::<&'static str>);
+
+    let Some(default) = retrieve(&conn, &initiator.user.id, &workspace).await? else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+
+    Ok(body::Json(default).into_response())
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateDefault {
+    journals_id: JournalId,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "type")]
+pub enum UpdateDefaultResult {
+    JournalNotFound,
+    JournalArchived,
+}
+
+/// chooses the journal that is used when one is not specified
+pub async fn update_default(
+    tx: db::Tx,
+    workspace: Workspace,
+    headers: HeaderMap,
+    body::Json(json): body::Json<UpdateDefault>,
+) -> Result<Response, error::Error> {
+    let initiator = macros::require_initiator!(&*tx, &headers, None::<&'static str>);
+
+    let result = Journal::retrieve_id(&*tx, &json.journals_id, &initiator.user.id)
+        .await
+        .context("failed to retrieve journal")?;
+
+    let Some(journal) = result.filter(|journal| journal.workspaces_id == workspace.id) else {
+        return Ok(body::FieldError::new(
+            "journals_id",
+            UpdateDefaultResult::JournalNotFound
+        ).into_response());
+    };
+
+    if journal.archived.is_some() {
+        return Ok(body::FieldError::new(
+            "journals_id",
+            UpdateDefaultResult::JournalArchived
+        ).into_response());
+    }
+
+    let mut preferences = Preferences::retrieve(&*tx, &initiator.user.id)
+        .await
+        .context("failed to retrieve preferences")?;
+
+    preferences.default_journals_id = Some(journal.id);
+    preferences.set(&*tx, &initiator.user.id)
+        .await
+        .context("failed to update preferences")?;
+
+    Ok(body::Json(DefaultJournal {
+        journals_id: journal.id,
+        name: journal.name,
+        preferred: true,
+    }).into_response())
+}
+
+/// removes the chosen default journal. the fallback journal is sent back if
+/// there is one
+pub async fn delete_default(
+    tx: db::Tx,
+    workspace: Workspace,
+    headers: HeaderMap,
+) -> Result<Response, error::Error> {
+    let initiator = macros::require_initiator!(&*tx, &headers, None::<&'static str>);
+
+    let mut preferences = Preferences::retrieve(&*tx, &initiator.user.id)
+        .await
+        .context("failed to retrieve preferences")?;
+
+    if preferences.default_journals_id.is_some() {
+        preferences.default_journals_id = None;
+        preferences.set(&*tx, &initiator.user.id)
+            .await
+            .context("failed to update preferences")?;
+    }
+
+    let Some(default) = retrieve(&*tx, &initiator.user.id, &workspace).await? else {
+        return Ok(StatusCode::NO_CONTENT.into_response());
+    };
+
+    Ok(body::Json(default).into_response())
+}
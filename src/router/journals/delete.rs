@@ -0,0 +1,173 @@
+//! deleting a journal
+//!
+//! the first request without a confirmation token responds with a token
+//! that must be sent back with a second request to delete the journal
+
+use axum::extract::{Path, Query};
+use axum::http::{StatusCode, Uri, HeaderMap};
+use axum::response::{IntoResponse, Response};
+use chrono::{DateTime, Utc};
+use serde::{Serialize, Deserialize};
+
+use crate::state;
+use crate::db;
+use crate::error::{self, Context};
+use crate::fs::{RemovedFile, RemovedFileError};
+use crate::journal::Journal;
+use crate::journal::delete::{self, DeleteToken, JournalDeletion};
+use crate::router::body;
+use crate::router::macros;
+use crate::router::journals::entries::auth;
+use crate::sec::authn::session::Token;
+use crate::sec::authz::{self, Scope, Ability};
+
+use super::JournalPath;
+
+#[derive(Debug, Deserialize)]
+pub struct DeleteQuery {
+    confirm: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "type")]
+pub enum DeleteJournalResult {
+    ConfirmationRequired {
+        token: String,
+        expires: DateTime<Utc>,
+    },
+    InvalidConfirmation,
+}
+
+/// deletes the journal along with its entries, custom fields, and shares
+///
+/// only the owner of the journal is allowed to delete it. the files of the
+/// journal are removed by the journal cleanup job
+pub async fn delete_journal(
+    state: state::SharedState,
+    tx: db::Tx,
+    headers: HeaderMap,
+    Path(JournalPath { journals_id }): Path<JournalPath>,
+    Query(query): Query<DeleteQuery>,
+) -> Result<Response, error::Error> {
+    let initiator = macros::require_initiator!(&*tx, &headers, None::<Uri>);
+
+    let perm_check = authz::has_permission(
+        &*tx,
+        initiator.user.id,
+        Scope::Journals,
+        Ability::Delete
+    )
+        .await
+        .context("failed to retrieve permission for user")?;
+
+    if !perm_check {
+        return Ok(StatusCode::UNAUTHORIZED.into_response());
+    }
+
+    let result = Journal::retrieve_id(&*tx, &journals_id, &initiator.user.id)
+        .await
+        .context("failed to retrieve journal")?;
+
+    let Some(journal) = result else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+
+    if journal.users_id != initiator.user.id {
+        return Ok(StatusCode::UNAUTHORIZED.into_response());
+    }
+
+    auth::archived_check!(journal);
+
+    auth::frozen_check!(&*tx, journal);
+
+    let Some(confirm) = query.confirm else {
+        let (token, expires) = DeleteToken::issue(&*tx, &journal.id).await?;
+
+        tx.commit()
+            .await
+            .context("failed to commit transaction")?;
+
+        return Ok((
+            StatusCode::PRECONDITION_REQUIRED,
+            body::Json(DeleteJournalResult::ConfirmationRequired {
+                token: token.as_base64(),
+                expires,
+            })
+        ).into_response());
+    };
+
+    let valid = match Token::from_base64(&confirm) {
+        Ok(token) => DeleteToken::consume(&*tx, &journal.id, &token)
+            .await
+            .context("failed to check delete token")?,
+        Err(_) => false,
+    };
+
+    if !valid {
+        return Ok(body::FieldError::new(
+            "confirm",
+            DeleteJournalResult::InvalidConfirmation
+        ).into_response());
+    }
+
+    delete::delete_rows(&*tx, &journal).await?;
+
+    let journal_dir = state.storage().journal_dir(&journal);
+
+    // the directory is moved so that nothing is written to it while the
+    // deletion is committed. it can be moved back if the commit fails
+    let marked = match RemovedFile::mark(journal_dir.root().clone()).await {
+        Ok(marked) => Some(marked),
+        Err(RemovedFileError::CurrNotFound) => None,
+        Err(err) => return Err(error::Error::context_source(
+            "failed to mark journal directory for removal",
+            err
+        )),
+    };
+
+    if let Some(directory) = marked.as_ref().map(|marked| marked.marked().to_path_buf()) {
+        let result = JournalDeletion::create(
+            &*tx,
+            &journal.id,
+            &initiator.user.id,
+            directory,
+        ).await;
+
+        if let Err(err) = result {
+            if let Some(marked) = marked {
+                rollback_directory(marked).await;
+            }
+
+            return Err(error::Error::context_source(
+                "failed to record journal deletion",
+                err
+            ));
+        }
+    }
+
+    if let Err(err) = tx.commit().await {
+        if let Some(marked) = marked {
+            rollback_directory(marked).await;
+        }
+
+        return Err(error::Error::context_source(
+            "failed to commit journal deletion",
+            err
+        ));
+    }
+
+    tracing::info!(journals_id = %journal.id, "deleted journal");
+
+    Ok(StatusCode::OK.into_response())
+}
+
+async fn rollback_directory(marked: RemovedFile) {
+    if let Err((marked, err)) = marked.rollback().await {
+        let prefix = format!(
+            "failed to rollback journal directory: \"{}\"",
+            marked.marked().display()
+        );
+
+        error::log_prefix_error(prefix.as_str(), &err);
+    }
+}
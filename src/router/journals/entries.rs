@@ -1,11 +1,14 @@
 use std::collections::{HashSet, HashMap};
 use std::fmt::Write;
 
-use axum::extract::Path;
+use axum::extract::{Path, Query};
 use axum::http::{StatusCode, Uri, HeaderMap};
 use axum::response::{IntoResponse, Response};
-use chrono::{NaiveDate, Utc, DateTime};
-use futures::StreamExt;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use chrono::{Datelike, Duration, Months, NaiveDate, Utc, DateTime};
+use futures::{Stream, StreamExt};
+use rand::Rng;
 use serde::{Serialize, Deserialize};
 
 use crate::state;
@@ -17,18 +20,54 @@ use crate::db::ids::{
     FileEntryUid,
     JournalId,
     UserId,
-    CustomFieldId
+    CustomFieldId,
+    EntryTaskId,
+    PromptId,
+    SubjectId,
 };
 use crate::error::{self, Context};
 use crate::fs::{CreatedFiles, RemovedFiles};
-use crate::journal::{custom_field, Journal, EntryTag, Entry, FileEntry, JournalDir};
+use crate::journal::custom_field::filter::FieldFilter;
+use crate::journal::custom_field::unit::UnitSystem;
+use crate::journal::e2e::Ciphertext;
+use crate::journal::markdown::{self, Render};
+use crate::journal::mention;
+use crate::journal::prompt::Prompt;
+use crate::journal::revision::Revision;
+use crate::journal::task::{EntryTask, TaskInput, TasksUpsert};
+use crate::journal::thumbnail;
+use crate::journal::upload::UploadPolicy;
+use crate::journal::live::LiveEvent;
+use crate::journal::webhook::{self, WebhookEvent};
+use crate::journal::{custom_field, entry_word_count, extract, tag, view, weather, Journal, EntryTag, Entry, FileEntry, JournalDir, MultiEntry};
 use crate::router::body;
 use crate::router::macros;
 use crate::sec::authz::{Scope, Ability};
+use crate::user::preferences::Preferences;
 
-mod auth;
+pub(super) mod auth;
 
+pub mod archive;
+pub mod e2e;
+pub mod export;
 pub mod files;
+pub mod freeze;
+pub mod goals;
+pub mod history;
+pub mod import;
+pub mod ics;
+pub mod list;
+pub mod location;
+pub mod reading;
+pub mod settings;
+pub mod shares;
+pub mod stats;
+pub mod subjects;
+pub mod tags;
+pub mod tasks;
+pub mod upload_policy;
+pub mod views;
+pub mod webhooks;
 
 #[derive(Debug, Deserialize)]
 pub struct JournalPath {
@@ -53,11 +92,494 @@ pub struct EntryPartial {
     pub uid: EntryUid,
     pub journals_id: JournalId,
     pub users_id: UserId,
+    pub number: i64,
     pub title: Option<String>,
     pub date: NaiveDate,
     pub created: DateTime<Utc>,
     pub updated: Option<DateTime<Utc>>,
+    pub planned: bool,
     pub tags: HashMap<String, Option<String>>,
+
+    /// the number of tasks that are not done
+    pub open_tasks: i64,
+}
+
+impl EntryPartial {
+    /// collects a stream of entry rows joined with their tags into a list
+    /// of entries
+    ///
+    /// the rows are expected to be ordered so that all the rows for an
+    /// entry are next to each other
+    async fn collect_stream<S>(entries: S) -> Result<Vec<Self>, error::Error>
+    where
+        S: Stream<Item = Result<tokio_postgres::Row, db::PgError>>
+    {
+        futures::pin_mut!(entries);
+
+        let mut found = Vec::new();
+        let mut current: Option<EntryPartial> = None;
+
+        while let Some(try_record) = entries.next().await {
+            let record = try_record.context("failed to retrieve journal entry")?;
+            let key: Option<String> = record.get(10);
+            let value: Option<String> = record.get(11);
+
+            if let Some(curr) = &mut current {
+                let id = record.get(0);
+
+                if curr.id == id {
+                    if let Some(key) = key {
+                        curr.tags.insert(key, value);
+                    }
+                } else {
+                    let tags = if let Some(key) = key {
+                        HashMap::from([(key, value)])
+                    } else {
+                        HashMap::new()
+                    };
+
+                    let mut swapping = EntryPartial {
+                        id,
+                        uid: record.get(1),
+                        journals_id: record.get(2),
+                        users_id: record.get(3),
+                        number: record.get(4),
+                        title: record.get(5),
+                        date: record.get(6),
+                        created: record.get(7),
+                        updated: record.get(8),
+                        planned: record.get(9),
+                        tags,
+                        open_tasks: record.get(12),
+                    };
+
+                    std::mem::swap(&mut swapping, curr);
+
+                    found.push(swapping);
+                }
+            } else {
+                let tags = if let Some(key) = key {
+                    HashMap::from([(key, value)])
+                } else {
+                    HashMap::new()
+                };
+
+                current = Some(EntryPartial {
+                    id: record.get(0),
+                    uid: record.get(1),
+                    journals_id: record.get(2),
+                    users_id: record.get(3),
+                    number: record.get(4),
+                    title: record.get(5),
+                    date: record.get(6),
+                    created: record.get(7),
+                    updated: record.get(8),
+                    planned: record.get(9),
+                    tags,
+                    open_tasks: record.get(12),
+                });
+            }
+        }
+
+        if let Some(curr) = current {
+            found.push(curr);
+        }
+
+        Ok(found)
+    }
+}
+
+/// the default number of entries in a page
+const DEFAULT_PAGE_SIZE: u32 = 25;
+
+/// the max number of entries in a page
+const MAX_PAGE_SIZE: u32 = 100;
+
+/// a page of entries when searching a journal
+#[derive(Debug, Clone, Copy)]
+pub struct Page {
+    /// the page number starting from 1
+    pub number: u32,
+    pub size: u32,
+}
+
+impl Page {
+    /// creates a page with the given values clamped to valid bounds
+    pub fn new(number: Option<u32>, size: Option<u32>) -> Self {
+        Self {
+            number: number.unwrap_or(1).max(1),
+            size: size.unwrap_or(DEFAULT_PAGE_SIZE).clamp(1, MAX_PAGE_SIZE),
+        }
+    }
+
+    /// one more than the page size so that callers can check if there is
+    /// a next page
+    fn limit(&self) -> i64 {
+        self.size as i64 + 1
+    }
+
+    fn offset(&self) -> i64 {
+        (self.number as i64 - 1) * self.size as i64
+    }
+
+    /// removes the extra entry retrieved past the end of the page. returns
+    /// true if there is a next page
+    pub fn split_next(&self, found: &mut Vec<EntryPartial>) -> bool {
+        if found.len() > self.size as usize {
+            found.truncate(self.size as usize);
+
+            true
+        } else {
+            false
+        }
+    }
+}
+
+/// the position after the last entry of a page when paging with cursors
+///
+/// clients are given the cursor as an opaque string
+#[derive(Debug, Clone, Copy)]
+pub struct Cursor {
+    pub date: NaiveDate,
+    pub id: EntryId,
+}
+
+impl Cursor {
+    /// the cursor that continues after the given entry
+    pub fn after(entry: &EntryPartial) -> Self {
+        Self {
+            date: entry.date,
+            id: entry.id,
+        }
+    }
+
+    pub fn encode(&self) -> String {
+        URL_SAFE_NO_PAD.encode(format!("{}:{}", self.date, self.id))
+    }
+
+    pub fn decode(given: &str) -> Option<Self> {
+        let bytes = URL_SAFE_NO_PAD.decode(given).ok()?;
+        let decoded = String::from_utf8(bytes).ok()?;
+        let (date, id) = decoded.split_once(':')?;
+
+        Some(Self {
+            date: date.parse().ok()?,
+            id: id.parse().ok()?,
+        })
+    }
+}
+
+/// the filters for searching the entries of a journal
+pub struct EntrySearch {
+    /// the owner of the journal
+    pub users_id: UserId,
+    pub journals_id: JournalId,
+
+    /// includes entries for dates that have not arrived yet
+    pub planned: bool,
+
+    /// only includes entries with the given tag key or a key under it. the
+    /// key is expected to be normalized
+    pub tag: Option<String>,
+
+    /// only includes entries with custom field values that match all of the
+    /// filters
+    pub fields: Vec<FieldFilter>,
+
+    /// only includes entries with a title, contents, or file text that
+    /// matches the full-text search
+    pub text: Option<String>,
+
+    /// only includes entries that the user has not viewed since they were
+    /// last changed
+    pub unread_by: Option<UserId>,
+
+    /// only includes entries that were written for the prompt
+    pub prompts_id: Option<PromptId>,
+
+    /// only includes entries that mention the person or place
+    pub subjects_id: Option<SubjectId>,
+
+    /// only includes entries after the cursor
+    pub cursor: Option<Cursor>,
+
+    /// retrieves all entries if not specified
+    pub page: Option<Page>,
+}
+
+impl EntrySearch {
+    /// writes the conditions that select the entries of the search. the
+    /// cursor and page are not included
+    fn push_conditions<'a>(&'a self, params: &mut db::ParamsVec<'a>) -> String {
+        let mut rtn = format!(
+            "entries.users_id = ${} and entries.journals_id = ${}",
+            db::push_param(params, &self.users_id),
+            db::push_param(params, &self.journals_id),
+        );
+
+        if !self.planned {
+            rtn.push_str(" and not entries.planned");
+        }
+
+        if let Some(tag) = &self.tag {
+            write!(
+                &mut rtn,
+                " and exists ( \
+                    select 1 \
+                    from entry_tags \
+                    where entry_tags.entries_id = entries.id and \
+                          {} \
+                )",
+                tag::key_filter("entry_tags.key", db::push_param(params, tag))
+            ).unwrap();
+        }
+
+        for filter in &self.fields {
+            filter.push_condition(&mut rtn, params);
+        }
+
+        if let Some(text) = &self.text {
+            write!(
+                &mut rtn,
+                " and {}",
+                extract::text_filter(db::push_param(params, text))
+            ).unwrap();
+        }
+
+        if let Some(users_id) = &self.unread_by {
+            write!(
+                &mut rtn,
+                " and {}",
+                view::unread_filter(db::push_param(params, users_id))
+            ).unwrap();
+        }
+
+        if let Some(prompts_id) = &self.prompts_id {
+            write!(
+                &mut rtn,
+                " and entries.prompts_id = ${}",
+                db::push_param(params, prompts_id)
+            ).unwrap();
+        }
+
+        if let Some(subjects_id) = &self.subjects_id {
+            write!(
+                &mut rtn,
+                " and exists (select 1 from entry_mentions where entry_mentions.entries_id = entries.id and entry_mentions.subjects_id = ${})",
+                db::push_param(params, subjects_id)
+            ).unwrap();
+        }
+
+        rtn
+    }
+}
+
+impl EntryPartial {
+    /// searches the entries of a journal ordered by the most recent date
+    ///
+    /// if a page is given then one more entry than the page size is
+    /// returned, see [`Page::split_next`]
+    pub async fn search(conn: &impl db::GenericClient, search: &EntrySearch) -> Result<Vec<Self>, error::Error> {
+        let limit = search.page.as_ref().map(Page::limit);
+        let offset = search.page.as_ref().map_or(0, Page::offset);
+        let mut params: db::ParamsVec<'_> = Vec::new();
+        let mut conditions = search.push_conditions(&mut params);
+
+        if let Some(cursor) = &search.cursor {
+            write!(
+                &mut conditions,
+                " and (entries.entry_date, entries.id) < (${}, ${})",
+                db::push_param(&mut params, &cursor.date),
+                db::push_param(&mut params, &cursor.id),
+            ).unwrap();
+        }
+
+        let limit_param = db::push_param(&mut params, &limit);
+        let offset_param = db::push_param(&mut params, &offset);
+
+        let entries = conn.query_raw(
+            &format!("\
+            with search_entries as ( \
+                select entries.*, \
+                       ( \
+                           select count(*) \
+                           from entry_tasks \
+                           where entry_tasks.entries_id = entries.id and \
+                                 not entry_tasks.done \
+                       ) as open_tasks \
+                from entries \
+                where {conditions} \
+                order by entries.entry_date desc, \
+                         entries.id desc \
+                limit ${limit_param} \
+                offset ${offset_param} \
+            ) \
+            select search_entries.id, \
+                   search_entries.uid, \
+                   search_entries.journals_id, \
+                   search_entries.users_id, \
+                   search_entries.number, \
+                   search_entries.title, \
+                   search_entries.entry_date, \
+                   search_entries.created, \
+                   search_entries.updated, \
+                   search_entries.planned, \
+                   entry_tags.key, \
+                   entry_tags.value, \
+                   search_entries.open_tasks \
+            from search_entries \
+                left join entry_tags on \
+                    search_entries.id = entry_tags.entries_id \
+            order by search_entries.entry_date desc, \
+                     search_entries.id desc"
+            ),
+            params
+        )
+            .await
+            .context("failed to retrieve journal entries")?;
+
+        Self::collect_stream(entries).await
+    }
+
+    /// counts the entries that match the search ignoring the cursor and page
+    pub async fn count(conn: &impl db::GenericClient, search: &EntrySearch) -> Result<i64, error::Error> {
+        let mut params: db::ParamsVec<'_> = Vec::new();
+        let conditions = search.push_conditions(&mut params);
+
+        conn.query_one(
+            &format!("select count(*) from entries where {conditions}"),
+            params.as_slice()
+        )
+            .await
+            .map(|row| row.get(0))
+            .context("failed to count journal entries")
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct EntriesQuery {
+    /// includes entries for dates that have not arrived yet
+    #[serde(default)]
+    planned: bool,
+
+    /// only includes entries with the given tag key or a key under it
+    tag: Option<String>,
+
+    /// a full-text search of the title, contents, and file text of entries
+    text: Option<String>,
+
+    /// only includes entries that have not been viewed since they were last
+    /// changed
+    #[serde(default)]
+    unread: bool,
+
+    /// only includes entries that were written for the prompt
+    prompt: Option<PromptId>,
+
+    /// only includes entries that mention the person or place
+    subject: Option<SubjectId>,
+
+    /// the page of entries to retrieve. all entries are retrieved if not
+    /// specified
+    page: Option<u32>,
+
+    /// the number of entries in a page
+    size: Option<u32>,
+
+    /// continues from the cursor of a previous page
+    cursor: Option<String>,
+
+    /// includes the total number of entries that match the search when
+    /// paging with cursors
+    #[serde(default)]
+    total: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PageLinks {
+    /// the relative link to the next page
+    next: Option<String>,
+}
+
+/// a page of entries when paging with cursors
+#[derive(Debug, Serialize)]
+pub struct EntriesPage {
+    entries: Vec<EntryPartial>,
+
+    /// the cursor for the next page if there are more entries
+    next_cursor: Option<String>,
+
+    /// the total number of entries that match the search if requested
+    total: Option<i64>,
+    links: PageLinks,
+}
+
+/// creates the relative link to the next page by replacing the cursor in
+/// the given query
+fn next_page_href(uri: &Uri, cursor: &str) -> String {
+    let mut serializer = url::form_urlencoded::Serializer::new(String::new());
+
+    for (key, value) in url::form_urlencoded::parse(uri.query().unwrap_or("").as_bytes()) {
+        if key != "cursor" {
+            serializer.append_pair(&key, &value);
+        }
+    }
+
+    serializer.append_pair("cursor", cursor);
+
+    format!("?{}", serializer.finish())
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "type")]
+pub enum EntriesResult {
+    InvalidCursor,
+    InvalidFieldFilter {
+        filter: String,
+        reason: String,
+    },
+}
+
+/// retrieves the "field" query parameters. the parameter can be given
+/// multiple times
+pub fn field_params(uri: &Uri) -> Vec<String> {
+    url::form_urlencoded::parse(uri.query().unwrap_or("").as_bytes())
+        .filter(|(key, _)| key == "field")
+        .map(|(_, value)| value.into_owned())
+        .collect()
+}
+
+/// parses the "field" query parameters into filters for the custom fields of
+/// a journal
+///
+/// returns the filter that failed to parse if one is invalid
+pub async fn field_filters(
+    conn: &impl db::GenericClient,
+    journals_id: &JournalId,
+    uri: &Uri,
+) -> Result<Result<Vec<FieldFilter>, EntriesResult>, error::Error> {
+    let given = field_params(uri);
+
+    if given.is_empty() {
+        return Ok(Ok(Vec::new()));
+    }
+
+    let known = custom_field::Type::retrieve_journal_map(conn, journals_id)
+        .await
+        .context("failed to retrieve journal custom fields")?;
+    let mut rtn = Vec::with_capacity(given.len());
+
+    for filter in given {
+        match FieldFilter::parse(&filter, &known) {
+            Ok(valid) => rtn.push(valid),
+            Err(err) => return Ok(Err(EntriesResult::InvalidFieldFilter {
+                filter,
+                reason: err.to_string(),
+            })),
+        }
+    }
+
+    Ok(Ok(rtn))
 }
 
 pub async fn retrieve_entries(
@@ -65,6 +587,7 @@ pub async fn retrieve_entries(
     uri: Uri,
     headers: HeaderMap,
     Path(JournalPath { journals_id }): Path<JournalPath>,
+    Query(query): Query<EntriesQuery>,
 ) -> Result<Response, error::Error> {
     let conn = state.db_conn().await?;
 
@@ -76,7 +599,7 @@ pub async fn retrieve_entries(
 
     macros::res_if_html!(state.templates(), &headers);
 
-    let result = Journal::retrieve_id(&conn, &journals_id, &initiator.user.id)
+    let result = Journal::retrieve_accessible(&conn, &journals_id, &initiator.user.id)
         .await
         .context("failed to retrieve default journal")?;
 
@@ -86,25 +609,298 @@ pub async fn retrieve_entries(
 
     auth::perm_check!(&conn, initiator, journal, Scope::Entries, Ability::Read);
 
-    let params: db::ParamsArray<'_, 2> = [&initiator.user.id, &journal.id];
+    let fields = match field_filters(&conn, &journal.id, &uri).await? {
+        Ok(valid) => valid,
+        Err(invalid) => return Ok(body::FieldError::new(
+            "field",
+            invalid
+        ).into_response()),
+    };
+
+    let cursor = match query.cursor.as_deref().map(Cursor::decode) {
+        Some(Some(valid)) => Some(valid),
+        Some(None) => return Ok(body::FieldError::new(
+            "cursor",
+            EntriesResult::InvalidCursor
+        ).into_response()),
+        None => None,
+    };
+
+    // offset pages are used if a page number is given, otherwise a size or
+    // cursor will page with cursors
+    let paging = query.page.is_none() && (cursor.is_some() || query.size.is_some());
+    let page = if paging {
+        Some(Page::new(None, query.size))
+    } else {
+        query.page.map(|number| Page::new(Some(number), query.size))
+    };
+    let search = EntrySearch {
+        users_id: journal.users_id,
+        journals_id: journal.id,
+        planned: query.planned,
+        tag: query.tag.as_deref().and_then(tag::normalize_key),
+        fields,
+        text: query.text.filter(|text| !text.trim().is_empty()),
+        unread_by: query.unread.then_some(initiator.user.id),
+        prompts_id: query.prompt,
+        subjects_id: query.subject,
+        cursor: if paging { cursor } else { None },
+        page,
+    };
+
+    let mut found = EntryPartial::search(&conn, &search).await?;
+    let has_next = page.is_some_and(|page| page.split_next(&mut found));
+
+    if !paging {
+        return Ok(body::Json(found).into_response());
+    }
+
+    let total = if query.total {
+        Some(EntryPartial::count(&conn, &search).await?)
+    } else {
+        None
+    };
+    let next_cursor = found.last()
+        .filter(|_| has_next)
+        .map(|last| Cursor::after(last).encode());
+    let next = next_cursor.as_deref()
+        .map(|cursor| next_page_href(&uri, cursor));
+
+    Ok(body::Json(EntriesPage {
+        entries: found,
+        next_cursor,
+        total,
+        links: PageLinks { next },
+    }).into_response())
+}
+
+/// the default number of years to look back for on this day entries
+const ON_THIS_DAY_YEARS: u32 = 10;
+
+/// the max number of years to look back for on this day entries
+const ON_THIS_DAY_MAX_YEARS: u32 = 100;
+
+/// the max number of days before and after the day to include
+const ON_THIS_DAY_MAX_NEARBY: u32 = 7;
+
+/// the max number of characters in the summary of an on this day entry
+const ON_THIS_DAY_SUMMARY_CHARS: usize = 200;
+
+#[derive(Debug, Deserialize)]
+pub struct OnThisDayQuery {
+    /// the day to look back from. either a full date or "MM-DD" for the
+    /// month and day of the current year. defaults to the current day
+    date: Option<String>,
+
+    /// the number of previous years to look back
+    years: Option<u32>,
+
+    /// the number of days before and after the day in previous years to
+    /// also include
+    #[serde(default)]
+    nearby: u32,
+
+    /// includes the entry from the same weekday of the previous month
+    #[serde(default)]
+    last_month: bool,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "type")]
+pub enum OnThisDayResult {
+    InvalidDate,
+    YearsTooLarge {
+        max: u32,
+    },
+    NearbyTooLarge {
+        max: u32,
+    },
+}
+
+#[derive(Debug, Serialize)]
+pub struct OnThisDayEntry {
+    #[serde(flatten)]
+    entry: EntryPartial,
+
+    /// the number of years between the entry and the requested day
+    years_ago: i32,
+
+    /// a short plain text version of the contents. encrypted entries do not
+    /// have one
+    summary: Option<String>,
+}
+
+/// the day that on this day entries are retrieved for
+enum OnThisDayDate {
+    Full(NaiveDate),
+    MonthDay(u32, u32),
+}
+
+impl OnThisDayDate {
+    /// parses either "YYYY-MM-DD" or "MM-DD"
+    fn parse(given: &str) -> Option<Self> {
+        if let Ok(date) = NaiveDate::parse_from_str(given, "%Y-%m-%d") {
+            return Some(Self::Full(date));
+        }
+
+        let (month, day) = given.split_once('-')?;
+
+        if month.len() != 2 || day.len() != 2 {
+            return None;
+        }
+
+        let month = month.parse().ok()?;
+        let day = day.parse().ok()?;
+
+        // a leap year is used so that "02-29" is accepted
+        NaiveDate::from_ymd_opt(2000, month, day)
+            .map(|_| Self::MonthDay(month, day))
+    }
+}
+
+/// creates the list of dates that are on the same month and day in the
+/// years before the given year along with the days nearby them
+///
+/// leap days are skipped for years that do not have them
+fn on_this_day_dates(year: i32, month: u32, day: u32, years: u32, nearby: u32) -> Vec<NaiveDate> {
+    let mut rtn = Vec::new();
+
+    for offset in 1..=years {
+        let Some(prev) = NaiveDate::from_ymd_opt(year - offset as i32, month, day) else {
+            continue;
+        };
+
+        for diff in -(nearby as i64)..=(nearby as i64) {
+            if let Some(near) = prev.checked_add_signed(Duration::days(diff)) {
+                rtn.push(near);
+            }
+        }
+    }
+
+    rtn
+}
+
+/// finds the day in the previous month that is closest to the given date
+/// and has the same weekday
+fn same_weekday_last_month(date: &NaiveDate) -> Option<NaiveDate> {
+    let prev = date.checked_sub_months(Months::new(1))?;
+
+    let diff = date.weekday().num_days_from_monday() as i64
+        - prev.weekday().num_days_from_monday() as i64;
+    let diff = match diff {
+        4..=6 => diff - 7,
+        -6..=-4 => diff + 7,
+        _ => diff,
+    };
+
+    prev.checked_add_signed(Duration::days(diff))
+}
+
+/// retrieves the entries that were written on the same day in previous
+/// years
+pub async fn retrieve_on_this_day(
+    state: state::SharedState,
+    uri: Uri,
+    headers: HeaderMap,
+    Path(JournalPath { journals_id }): Path<JournalPath>,
+    Query(query): Query<OnThisDayQuery>,
+) -> Result<Response, error::Error> {
+    let conn = state.db_conn().await?;
+
+    let initiator = macros::require_initiator!(&conn, &headers, Some(uri));
+
+    let result = Journal::retrieve_accessible(&conn, &journals_id, &initiator.user.id)
+        .await
+        .context("failed to retrieve default journal")?;
+
+    let Some(journal) = result else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+
+    auth::perm_check!(&conn, initiator, journal, Scope::Entries, Ability::Read);
+
+    let years = query.years.unwrap_or(ON_THIS_DAY_YEARS);
+
+    if years > ON_THIS_DAY_MAX_YEARS {
+        return Ok(body::FieldError::new(
+            "years",
+            OnThisDayResult::YearsTooLarge {
+                max: ON_THIS_DAY_MAX_YEARS,
+            }
+        ).into_response());
+    }
+
+    if query.nearby > ON_THIS_DAY_MAX_NEARBY {
+        return Ok(body::FieldError::new(
+            "nearby",
+            OnThisDayResult::NearbyTooLarge {
+                max: ON_THIS_DAY_MAX_NEARBY,
+            }
+        ).into_response());
+    }
+
+    let given = match query.date.as_deref().map(OnThisDayDate::parse) {
+        Some(Some(given)) => Some(given),
+        Some(None) => return Ok(body::FieldError::new(
+            "date",
+            OnThisDayResult::InvalidDate
+        ).into_response()),
+        None => None,
+    };
+
+    // the year to look back from comes from the current day in the timezone
+    // of the user unless a full date is given
+    let current = match given {
+        Some(OnThisDayDate::Full(date)) => date,
+        _ => Preferences::retrieve(&conn, &initiator.user.id)
+            .await
+            .context("failed to retrieve preferences")?
+            .today(),
+    };
+    let (year, month, day) = match given {
+        Some(OnThisDayDate::MonthDay(month, day)) => (current.year(), month, day),
+        _ => (current.year(), current.month(), current.day()),
+    };
+
+    let mut dates = on_this_day_dates(year, month, day, years, query.nearby);
+
+    if query.last_month {
+        if let Some(same_weekday) = NaiveDate::from_ymd_opt(year, month, day)
+            .and_then(|date| same_weekday_last_month(&date)) {
+            dates.push(same_weekday);
+        }
+    }
+
+    let params: db::ParamsArray<'_, 3> = [&journal.users_id, &journal.id, &dates];
     let entries = conn.query_raw(
         "\
         with search_entries as ( \
-            select * \
+            select entries.*, \
+                   ( \
+                       select count(*) \
+                       from entry_tasks \
+                       where entry_tasks.entries_id = entries.id and \
+                             not entry_tasks.done \
+                   ) as open_tasks \
             from entries \
             where entries.users_id = $1 and \
-                  entries.journals_id = $2 \
+                  entries.journals_id = $2 and \
+                  entries.entry_date = any($3) and \
+                  not entries.planned \
         ) \
         select search_entries.id, \
                search_entries.uid, \
                search_entries.journals_id, \
                search_entries.users_id, \
+               search_entries.number, \
                search_entries.title, \
                search_entries.entry_date, \
                search_entries.created, \
                search_entries.updated, \
+               search_entries.planned, \
                entry_tags.key, \
-               entry_tags.value \
+               entry_tags.value, \
+               search_entries.open_tasks \
         from search_entries \
             left join entry_tags on \
                 search_entries.id = entry_tags.entries_id \
@@ -112,72 +908,39 @@ pub async fn retrieve_entries(
         params
     )
         .await
-        .context("failed to retrieve journal entries")?;
+        .context("failed to retrieve on this day entries")?;
 
-    futures::pin_mut!(entries);
+    let found = EntryPartial::collect_stream(entries).await?;
 
-    let mut found = Vec::new();
-    let mut current: Option<EntryPartial> = None;
-
-    while let Some(try_record) = entries.next().await {
-        let record = try_record.context("failed to retrieve journal entry")?;
-        let key: Option<String> = record.get(8);
-        let value: Option<String> = record.get(9);
-
-        if let Some(curr) = &mut current {
-            let id = record.get(0);
-
-            if curr.id == id {
-                if let Some(key) = key {
-                    curr.tags.insert(key, value);
-                }
-            } else {
-                let tags = if let Some(key) = key {
-                    HashMap::from([(key, value)])
-                } else {
-                    HashMap::new()
-                };
-
-                let mut swapping = EntryPartial {
-                    id,
-                    uid: record.get(1),
-                    journals_id: record.get(2),
-                    users_id: record.get(3),
-                    title: record.get(4),
-                    date: record.get(5),
-                    created: record.get(6),
-                    updated: record.get(7),
-                    tags
-                };
-
-                std::mem::swap(&mut swapping, curr);
-
-                found.push(swapping);
-            }
-        } else {
-            let tags = if let Some(key) = key {
-                HashMap::from([(key, value)])
-            } else {
-                HashMap::new()
-            };
+    let ids: Vec<EntryId> = found.iter()
+        .map(|entry| entry.id)
+        .collect();
+    let contents = conn.query(
+        "\
+        select entries.id, \
+               left(entries.contents, $2) \
+        from entries \
+        where entries.id = any($1) and \
+              entries.contents is not null",
+        &[&ids, &((ON_THIS_DAY_SUMMARY_CHARS * 8) as i32)]
+    )
+        .await
+        .context("failed to retrieve on this day contents")?;
 
-            current = Some(EntryPartial {
-                id: record.get(0),
-                uid: record.get(1),
-                journals_id: record.get(2),
-                users_id: record.get(3),
-                title: record.get(4),
-                date: record.get(5),
-                created: record.get(6),
-                updated: record.get(7),
-                tags
-            });
-        }
-    }
+    let mut summaries: HashMap<EntryId, String> = contents.into_iter()
+        .map(|row| (
+            row.get(0),
+            markdown::summarize(row.get(1), ON_THIS_DAY_SUMMARY_CHARS)
+        ))
+        .collect();
 
-    if let Some(curr) = current {
-        found.push(curr);
-    }
+    let found: Vec<OnThisDayEntry> = found.into_iter()
+        .map(|entry| OnThisDayEntry {
+            years_ago: year - entry.date.year(),
+            summary: summaries.remove(&entry.id),
+            entry,
+        })
+        .collect();
 
     Ok(body::Json(found).into_response())
 }
@@ -191,17 +954,77 @@ where
     uid: EntryUid,
     journals_id: JournalId,
     users_id: UserId,
+    number: i64,
     date: NaiveDate,
     title: Option<String>,
     contents: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    contents_html: Option<String>,
     created: DateTime<Utc>,
     updated: Option<DateTime<Utc>>,
+    planned: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    ciphertext: Option<Ciphertext>,
+    prompts_id: Option<PromptId>,
+    word_count: i32,
     tags: Vec<EntryTag>,
+    tasks: Vec<EntryTask>,
     files: Vec<Files>,
     custom_fields: Vec<CustomFieldFull>,
+
+    /// the people and places mentioned by the entry
+    mentions: Vec<SubjectId>,
 }
 
 impl EntryFull<FileEntryFull> {
+    async fn from_entry(
+        conn: &impl db::GenericClient,
+        found: Entry,
+    ) -> Result<Self, db::PgError> {
+        let tags_fut = EntryTag::retrieve_entry(conn, found.id);
+        let files_fut = FileEntryFull::retrieve_entry(conn, &found.id);
+        let custom_fields_fut = CustomFieldFull::retrieve_entry(conn, &found.id);
+        let tasks_fut = EntryTask::retrieve_entry(conn, &found.id);
+        let mentions_fut = mention::retrieve_entry(conn, &found.id);
+
+        let (tags_res, files_res, custom_fields_res, tasks_res, mentions_res) = tokio::join!(
+            tags_fut,
+            files_fut,
+            custom_fields_fut,
+            tasks_fut,
+            mentions_fut
+        );
+
+        let tags = tags_res?;
+        let files = files_res?;
+        let custom_fields = custom_fields_res?;
+        let tasks = tasks_res?;
+        let mentions = mentions_res?;
+
+        Ok(Self {
+            id: found.id,
+            uid: found.uid,
+            journals_id: found.journals_id,
+            users_id: found.users_id,
+            number: found.number,
+            date: found.date,
+            title: found.title,
+            contents: found.contents,
+            contents_html: None,
+            created: found.created,
+            updated: found.updated,
+            planned: found.planned,
+            ciphertext: found.ciphertext,
+            prompts_id: found.prompts_id,
+            word_count: found.word_count,
+            tags,
+            tasks,
+            files,
+            custom_fields,
+            mentions,
+        })
+    }
+
     pub async fn retrieve_id(
         conn: &impl db::GenericClient,
         journals_id: &JournalId,
@@ -209,40 +1032,58 @@ impl EntryFull<FileEntryFull> {
         entries_id: &EntryId,
     ) -> Result<Option<Self>, db::PgError> {
         if let Some(found) = Entry::retrieve_id(conn, journals_id, users_id, entries_id).await? {
-            let tags_fut = EntryTag::retrieve_entry(conn, found.id);
-            let files_fut = FileEntryFull::retrieve_entry(conn, &found.id);
-            let custom_fields_fut = CustomFieldFull::retrieve_entry(conn, &found.id);
-
-            let (tags_res, files_res, custom_fields_res) = tokio::join!(tags_fut, files_fut, custom_fields_fut);
-
-            let tags = tags_res?;
-            let files = files_res?;
-            let custom_fields = custom_fields_res?;
-
-            Ok(Some(Self {
-                id: found.id,
-                uid: found.uid,
-                journals_id: found.journals_id,
-                users_id: found.users_id,
-                date: found.date,
-                title: found.title,
-                contents: found.contents,
-                created: found.created,
-                updated: found.updated,
-                tags,
-                files,
-                custom_fields,
-            }))
+            Ok(Some(Self::from_entry(conn, found).await?))
         } else {
             Ok(None)
         }
     }
+
+    pub async fn retrieve_number(
+        conn: &impl db::GenericClient,
+        journals_id: &JournalId,
+        users_id: &UserId,
+        number: &i64,
+    ) -> Result<Option<Self>, db::PgError> {
+        if let Some(found) = Entry::retrieve_number(conn, journals_id, users_id, number).await? {
+            Ok(Some(Self::from_entry(conn, found).await?))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// adds the sanitized html of the markdown contents
+    pub fn render_html(&mut self) {
+        self.contents_html = self.contents.as_deref()
+            .map(markdown::render_html);
+    }
+
+    /// adds converted values to any custom fields that have a unit
+    /// assigned
+    pub async fn convert_units(
+        &mut self,
+        conn: &impl db::GenericClient,
+        system: UnitSystem,
+    ) -> Result<(), db::PgError> {
+        let types = custom_field::Type::retrieve_journal_map(conn, &self.journals_id).await?;
+
+        for field in &mut self.custom_fields {
+            let Some(unit) = types.get(&field.custom_fields_id).and_then(|ty| ty.unit()) else {
+                continue;
+            };
+
+            field.converted = field.value.convert(unit, system);
+        }
+
+        Ok(())
+    }
 }
 
 #[derive(Debug, Serialize)]
 pub struct CustomFieldFull {
     custom_fields_id: CustomFieldId,
     value: custom_field::Value,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    converted: Option<custom_field::Converted>,
     created: DateTime<Utc>,
     updated: Option<DateTime<Utc>>,
 }
@@ -267,6 +1108,7 @@ impl CustomFieldFull {
             rtn.push(Self {
                 custom_fields_id: record.custom_fields_id,
                 value: record.value,
+                converted: None,
                 created: record.created,
                 updated: record.updated,
             });
@@ -285,6 +1127,7 @@ pub struct FileEntryFull {
     mime_subtype: String,
     mime_param: Option<String>,
     size: i64,
+    hash: Option<String>,
     created: DateTime<Utc>,
     updated: Option<DateTime<Utc>>,
 }
@@ -311,6 +1154,7 @@ impl FileEntryFull {
                 mime_subtype: record.mime_subtype,
                 mime_param: record.mime_param,
                 size: record.size,
+                hash: record.hash,
                 created: record.created,
                 updated: record.updated,
             });
@@ -320,11 +1164,22 @@ impl FileEntryFull {
     }
 }
 
+#[derive(Debug, Deserialize)]
+pub struct EntryQuery {
+    /// converts custom field values with units to the given measurement
+    /// system. defaults to the unit system preference of the user
+    units: Option<UnitSystem>,
+
+    /// renders the markdown contents of the entry to the given format
+    render: Option<Render>,
+}
+
 pub async fn retrieve_entry(
     state: state::SharedState,
     uri: Uri,
     headers: HeaderMap,
     Path(MaybeEntryPath { journals_id, entries_id }): Path<MaybeEntryPath>,
+    Query(query): Query<EntryQuery>,
 ) -> Result<Response, error::Error> {
     macros::res_if_html!(state.templates(), &headers);
 
@@ -336,7 +1191,7 @@ pub async fn retrieve_entry(
 
     let initiator = macros::require_initiator!(&conn, &headers, Some(uri));
 
-    let result = Journal::retrieve_id(&conn, &journals_id, &initiator.user.id)
+    let result = Journal::retrieve_accessible(&conn, &journals_id, &initiator.user.id)
         .await
         .context("failed to retrieve default journal")?;
 
@@ -349,21 +1204,245 @@ pub async fn retrieve_entry(
     let result = EntryFull::retrieve_id(
         &conn,
         &journal.id,
-        &initiator.user.id,
+        &journal.users_id,
         &entries_id
     )
         .await
         .context("failed to retrieve journal entry for date")?;
 
-    let Some(entry) = result else {
+    let Some(mut entry) = result else {
         return Ok(StatusCode::NOT_FOUND.into_response());
     };
 
+    let units = match query.units {
+        Some(units) => Some(units),
+        None => Preferences::retrieve(&conn, &initiator.user.id)
+            .await
+            .context("failed to retrieve preferences")?
+            .unit_system,
+    };
+
+    if let Some(system) = units {
+        entry.convert_units(&conn, system)
+            .await
+            .context("failed to convert custom field units")?;
+    }
+
+    if let Some(Render::Html) = query.render {
+        entry.render_html();
+    }
+
     tracing::debug!("entry: {entry:#?}");
 
     Ok(body::Json(entry).into_response())
 }
 
+#[derive(Debug, Deserialize)]
+pub struct RandomQuery {
+    /// only select entries that have the given tag key or a key under it
+    tag: Option<String>,
+
+    /// only select entries from the given year
+    year: Option<i32>,
+
+    /// only select entries that do or do not have files
+    files: Option<bool>,
+}
+
+/// retrieves a random entry from the journal
+///
+/// a random entry number is chosen between the lowest and highest entry
+/// numbers and the closest matching entry is selected so that the entire
+/// journal does not need to be sorted
+pub async fn retrieve_random_entry(
+    state: state::SharedState,
+    uri: Uri,
+    headers: HeaderMap,
+    Path(JournalPath { journals_id }): Path<JournalPath>,
+    Query(RandomQuery { tag, year, files }): Query<RandomQuery>,
+) -> Result<Response, error::Error> {
+    let conn = state.db_conn().await?;
+
+    let initiator = macros::require_initiator!(&conn, &headers, Some(uri));
+
+    let result = Journal::retrieve_accessible(&conn, &journals_id, &initiator.user.id)
+        .await
+        .context("failed to retrieve default journal")?;
+
+    let Some(journal) = result else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+
+    auth::perm_check!(&conn, initiator, journal, Scope::Entries, Ability::Read);
+
+    let bounds = conn.query_one(
+        "\
+        select min(entries.number), \
+               max(entries.number) \
+        from entries \
+        where entries.journals_id = $1 and \
+              entries.users_id = $2 and \
+              not entries.planned",
+        &[&journal.id, &journal.users_id]
+    )
+        .await
+        .context("failed to retrieve entry number bounds")?;
+
+    let (Some(low), Some(high)): (Option<i64>, Option<i64>) = (bounds.get(0), bounds.get(1)) else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+
+    let target = rand::thread_rng().gen_range(low..=high);
+    let year_range = year.and_then(|year| {
+        let start = NaiveDate::from_ymd_opt(year, 1, 1)?;
+        let end = NaiveDate::from_ymd_opt(year + 1, 1, 1)?;
+
+        Some((start, end))
+    });
+
+    let mut filters = String::new();
+    let mut params: db::ParamsVec<'_> = vec![&journal.id, &journal.users_id, &target];
+
+    let tag = tag.as_deref().and_then(tag::normalize_key);
+
+    if let Some(tag) = &tag {
+        write!(
+            &mut filters,
+            " and exists (\
+                select 1 \
+                from entry_tags \
+                where entry_tags.entries_id = entries.id and \
+                      {} \
+            )",
+            tag::key_filter("entry_tags.key", db::push_param(&mut params, tag))
+        ).unwrap();
+    }
+
+    if let Some((start, end)) = &year_range {
+        write!(
+            &mut filters,
+            " and entries.entry_date >= ${} and entries.entry_date < ${}",
+            db::push_param(&mut params, start),
+            db::push_param(&mut params, end),
+        ).unwrap();
+    }
+
+    if let Some(files) = files {
+        filters.push_str(if files {
+            " and exists (select 1 from file_entries where file_entries.entries_id = entries.id)"
+        } else {
+            " and not exists (select 1 from file_entries where file_entries.entries_id = entries.id)"
+        });
+    }
+
+    // looks for the closest entry after the target and then wraps around to
+    // the closest entry before it
+    let query = format!(
+        "\
+        (select entries.id \
+         from entries \
+         where entries.journals_id = $1 and \
+               entries.users_id = $2 and \
+               entries.number >= $3 and \
+               not entries.planned{filters} \
+         order by entries.number \
+         limit 1) \
+        union all \
+        (select entries.id \
+         from entries \
+         where entries.journals_id = $1 and \
+               entries.users_id = $2 and \
+               entries.number < $3 and \
+               not entries.planned{filters} \
+         order by entries.number desc \
+         limit 1) \
+        limit 1"
+    );
+
+    let result = conn.query_opt(&query, params.as_slice())
+        .await
+        .context("failed to retrieve random journal entry")?;
+
+    let Some(row) = result else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+
+    let entries_id: EntryId = row.get(0);
+
+    let result = EntryFull::retrieve_id(&conn, &journal.id, &journal.users_id, &entries_id)
+        .await
+        .context("failed to retrieve journal entry")?;
+
+    let Some(entry) = result else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+
+    Ok(body::Json(entry).into_response())
+}
+
+#[derive(Debug, Deserialize)]
+pub struct EntryNumberPath {
+    journals_id: JournalId,
+    entry_number: i64,
+}
+
+pub async fn retrieve_entry_number(
+    state: state::SharedState,
+    uri: Uri,
+    headers: HeaderMap,
+    Path(EntryNumberPath { journals_id, entry_number }): Path<EntryNumberPath>,
+    Query(query): Query<EntryQuery>,
+) -> Result<Response, error::Error> {
+    macros::res_if_html!(state.templates(), &headers);
+
+    let conn = state.db_conn().await?;
+
+    let initiator = macros::require_initiator!(&conn, &headers, Some(uri));
+
+    let result = Journal::retrieve_accessible(&conn, &journals_id, &initiator.user.id)
+        .await
+        .context("failed to retrieve default journal")?;
+
+    let Some(journal) = result else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+
+    auth::perm_check!(&conn, initiator, journal, Scope::Entries, Ability::Read);
+
+    let result = EntryFull::retrieve_number(
+        &conn,
+        &journal.id,
+        &journal.users_id,
+        &entry_number
+    )
+        .await
+        .context("failed to retrieve journal entry for number")?;
+
+    let Some(mut entry) = result else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+
+    let units = match query.units {
+        Some(units) => Some(units),
+        None => Preferences::retrieve(&conn, &initiator.user.id)
+            .await
+            .context("failed to retrieve preferences")?
+            .unit_system,
+    };
+
+    if let Some(system) = units {
+        entry.convert_units(&conn, system)
+            .await
+            .context("failed to convert custom field units")?;
+    }
+
+    if let Some(Render::Html) = query.render {
+        entry.render_html();
+    }
+
+    Ok(body::Json(entry).into_response())
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ClientData {
     key: String
@@ -391,8 +1470,22 @@ pub struct NewEntryBody {
     title: Option<String>,
     contents: Option<String>,
     tags: Vec<TagEntryBody>,
+    #[serde(default)]
+    tasks: Vec<TaskInput>,
     files: Vec<NewFileEntryBody>,
     custom_fields: Vec<CustomFieldEntry>,
+
+    /// the encrypted title, contents, and custom field values. required for
+    /// entries in an end-to-end encrypted journal
+    ciphertext: Option<Ciphertext>,
+
+    /// the prompt that the entry was written for
+    prompts_id: Option<PromptId>,
+
+    /// the people and places mentioned by the entry in addition to the ones
+    /// found in the contents
+    #[serde(default)]
+    mentions: Vec<SubjectId>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -401,8 +1494,26 @@ pub struct UpdatedEntryBody {
     title: Option<String>,
     contents: Option<String>,
     tags: Vec<TagEntryBody>,
+
+    /// the tasks of the entry. the current tasks are left unchanged if not
+    /// provided
+    tasks: Option<Vec<TaskInput>>,
     files: Vec<UpdatedFileEntryBody>,
     custom_fields: Vec<CustomFieldEntry>,
+
+    /// the encrypted title, contents, and custom field values. required for
+    /// entries in an end-to-end encrypted journal
+    ciphertext: Option<Ciphertext>,
+
+    /// the prompt that the entry was written for. the current prompt is
+    /// left unchanged if not provided and null removes it
+    #[serde(default, deserialize_with = "crate::serde::nested_opt")]
+    prompts_id: Option<Option<PromptId>>,
+
+    /// the people and places mentioned by the entry in addition to the ones
+    /// found in the contents. the current mentions are left unchanged if not
+    /// provided
+    mentions: Option<Vec<SubjectId>>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -436,19 +1547,81 @@ pub enum UpdatedFileEntryBody {
     New(NewFileEntryBody),
 }
 
-fn non_empty_str(given: String) -> Option<String> {
-    let trimmed = given.trim();
+fn non_empty_str(given: String) -> Option<String> {
+    let trimmed = given.trim();
+
+    if trimmed.is_empty() {
+        None
+    } else {
+        Some(trimmed.to_owned())
+    }
+}
+
+fn opt_non_empty_str(given: Option<String>) -> Option<String> {
+    if let Some(value) = given {
+        non_empty_str(value)
+    } else {
+        None
+    }
+}
+
+/// normalizes the keys and values of the given tags
+///
+/// tags with blank keys are ignored. returns the keys that have an empty
+/// "/" separated segment if there are any
+fn parse_tags(given: Vec<TagEntryBody>) -> Result<Vec<(String, Option<String>)>, Vec<String>> {
+    let mut valid = Vec::with_capacity(given.len());
+    let mut invalid = Vec::new();
+
+    for tag in given {
+        let Some(key) = non_empty_str(tag.key) else {
+            continue;
+        };
+
+        match tag::normalize_key(&key) {
+            Some(normalized) => valid.push((normalized, opt_non_empty_str(tag.value))),
+            None => invalid.push(key),
+        }
+    }
 
-    if trimmed.is_empty() {
-        None
+    if invalid.is_empty() {
+        Ok(valid)
     } else {
-        Some(trimmed.to_owned())
+        Err(invalid)
     }
 }
 
-fn opt_non_empty_str(given: Option<String>) -> Option<String> {
-    if let Some(value) = given {
-        non_empty_str(value)
+/// the ways that an entry body can conflict with the encryption of its
+/// journal
+enum E2eMismatch {
+    CiphertextRequired,
+    PlaintextNotAllowed,
+    JournalNotEncrypted,
+}
+
+/// checks that an entry in an end-to-end encrypted journal only provides
+/// ciphertext and that other entries do not provide any
+fn check_e2e(
+    journal: &Journal,
+    ciphertext: &Option<Ciphertext>,
+    title: &Option<String>,
+    contents: &Option<String>,
+    custom_fields: &[CustomFieldEntry],
+) -> Option<(&'static str, E2eMismatch)> {
+    if journal.e2e {
+        if title.is_some() {
+            Some(("title", E2eMismatch::PlaintextNotAllowed))
+        } else if contents.is_some() {
+            Some(("contents", E2eMismatch::PlaintextNotAllowed))
+        } else if !custom_fields.is_empty() {
+            Some(("custom_fields", E2eMismatch::PlaintextNotAllowed))
+        } else if ciphertext.as_ref().map_or(true, |given| given.is_empty()) {
+            Some(("ciphertext", E2eMismatch::CiphertextRequired))
+        } else {
+            None
+        }
+    } else if ciphertext.is_some() {
+        Some(("ciphertext", E2eMismatch::JournalNotEncrypted))
     } else {
         None
     }
@@ -466,23 +1639,43 @@ pub enum CreateEntryResult {
     CustomFieldDuplicates {
         ids: Vec<CustomFieldId>,
     },
+    InvalidTags {
+        keys: Vec<String>,
+    },
+    TooManyFiles {
+        max_files_per_entry: u32,
+    },
+    DateExists,
+    PromptNotFound,
+    SubjectNotFound {
+        ids: Vec<SubjectId>,
+    },
+    CiphertextRequired,
+    PlaintextNotAllowed,
+    JournalNotEncrypted,
     Created(ResultEntryFull)
 }
 
+impl From<E2eMismatch> for CreateEntryResult {
+    fn from(mismatch: E2eMismatch) -> Self {
+        match mismatch {
+            E2eMismatch::CiphertextRequired => Self::CiphertextRequired,
+            E2eMismatch::PlaintextNotAllowed => Self::PlaintextNotAllowed,
+            E2eMismatch::JournalNotEncrypted => Self::JournalNotEncrypted,
+        }
+    }
+}
+
 pub async fn create_entry(
     state: state::SharedState,
+    tx: db::Tx,
     headers: HeaderMap,
     Path(JournalPath { journals_id }): Path<JournalPath>,
     body::Json(json): body::Json<NewEntryBody>,
 ) -> Result<Response, error::Error> {
-    let mut conn = state.db_conn().await?;
-    let transaction = conn.transaction()
-        .await
-        .context("failed to create transaction")?;
+    let initiator = macros::require_initiator!(&*tx, &headers, None::<Uri>);
 
-    let initiator = macros::require_initiator!(&transaction, &headers, None::<Uri>);
-
-    let result = Journal::retrieve_id(&transaction, &journals_id, &initiator.user.id)
+    let result = Journal::retrieve_accessible(&*tx, &journals_id, &initiator.user.id)
         .await
         .context("failed to retrieve default journal")?;
 
@@ -490,23 +1683,100 @@ pub async fn create_entry(
         return Ok(StatusCode::NOT_FOUND.into_response());
     };
 
-    auth::perm_check!(&transaction, initiator, journal, Scope::Entries, Ability::Create);
+    auth::perm_check!(&*tx, initiator, journal, Scope::Entries, Ability::Create);
+
+    auth::archived_check!(journal);
+
+    auth::frozen_check!(&*tx, journal);
 
     let uid = EntryUid::gen();
     let journals_id = journal.id;
-    let users_id = initiator.user.id;
+    // entries are stored under the owner of the journal so that they are
+    // found the same way when created by a user the journal is shared with
+    let users_id = journal.users_id;
     let entry_date = json.date;
+    let today = Preferences::retrieve(&*tx, &initiator.user.id)
+        .await
+        .context("failed to retrieve preferences")?
+        .today();
+    let planned = entry_date > today;
     let title = opt_non_empty_str(json.title);
     let contents = opt_non_empty_str(json.contents);
+    let word_count = entry_word_count(title.as_deref(), contents.as_deref());
+    let ciphertext = json.ciphertext;
     let created = Utc::now();
 
+    if let Some((field, mismatch)) = check_e2e(&journal, &ciphertext, &title, &contents, &json.custom_fields) {
+        return Ok(body::FieldError::new(
+            field,
+            CreateEntryResult::from(mismatch)
+        ).into_response());
+    }
+
+    let given_tags = match parse_tags(json.tags) {
+        Ok(valid) => valid,
+        Err(keys) => return Ok(body::FieldError::new(
+            "tags",
+            CreateEntryResult::InvalidTags { keys }
+        ).into_response()),
+    };
+
+    let prompts_id = json.prompts_id;
+
+    if let Some(prompts_id) = &prompts_id {
+        let found = Prompt::retrieve_id(&*tx, prompts_id, &initiator.user.id)
+            .await
+            .context("failed to retrieve prompt")?;
+
+        if found.is_none() {
+            return Ok(body::FieldError::new(
+                "prompts_id",
+                CreateEntryResult::PromptNotFound
+            ).into_response());
+        }
+    }
+
+    if !json.files.is_empty() {
+        let policy = UploadPolicy::retrieve(&*tx, state.upload(), &journal.id)
+            .await
+            .context("failed to retrieve journal upload policy")?;
+
+        if !policy.allows_files(json.files.len()) {
+            return Ok(body::FieldError::new(
+                "files",
+                CreateEntryResult::TooManyFiles {
+                    max_files_per_entry: policy.max_files_per_entry.unwrap_or(0),
+                }
+            ).into_response());
+        }
+    }
+
+    let number = Journal::next_entry_number(&*tx, &journal.id)
+        .await
+        .context("failed to retrieve next entry number")?;
+
+    // the journal row is locked by reserving the entry number so another
+    // entry cannot take the date before this one is inserted
+    if journal.settings.multi_entry == MultiEntry::Single {
+        let in_use = Journal::date_in_use(&*tx, &journal.id, &entry_date, None)
+            .await
+            .context("failed to check for existing entry date")?;
+
+        if in_use {
+            return Ok(body::FieldError::new(
+                "date",
+                CreateEntryResult::DateExists
+            ).into_response());
+        }
+    }
+
     let id: EntryId = {
-        let result = transaction.query_one(
+        let result = tx.query_one(
             "\
-            insert into entries (uid, journals_id, users_id, entry_date, title, contents, created) \
-            values ($1, $2, $3, $4, $5, $6, $7) \
+            insert into entries (uid, journals_id, users_id, number, entry_date, planned, title, contents, ciphertext, prompts_id, word_count, created) \
+            values ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12) \
             returning id",
-            &[&uid, &journals_id, &users_id, &entry_date, &title, &contents, &created]
+            &[&uid, &journals_id, &users_id, &number, &entry_date, &planned, &title, &contents, &ciphertext, &prompts_id, &word_count, &created]
         )
             .await
             .context("failed to insert entry into database")?;
@@ -514,15 +1784,10 @@ pub async fn create_entry(
         result.get(0)
     };
 
-    let tags = if !json.tags.is_empty() {
+    let tags = if !given_tags.is_empty() {
         let mut rtn: Vec<EntryTag> = Vec::new();
 
-        for tag in json.tags {
-            let Some(key) = non_empty_str(tag.key) else {
-                continue;
-            };
-            let value = opt_non_empty_str(tag.value);
-
+        for (key, value) in given_tags {
             rtn.push(EntryTag {
                 key,
                 value,
@@ -531,52 +1796,88 @@ pub async fn create_entry(
             });
         }
 
-        upsert_tags(&transaction, &id, &rtn).await?;
+        upsert_tags(&*tx, &id, &rtn).await?;
 
         rtn
     } else {
         Vec::new()
     };
 
+    // the entry is new so any given ids are ignored
+    let tasks = if !json.tasks.is_empty() {
+        let given = json.tasks.into_iter()
+            .map(|task| TaskInput {
+                id: None,
+                ..task
+            })
+            .collect();
+
+        EntryTask::upsert_entry(&*tx, &id, given, &created)
+            .await
+            .context("failed to create entry tasks")?
+            .valid
+    } else {
+        Vec::new()
+    };
+
     let CustomFieldsUpsert {
         valid: custom_fields,
         not_found,
         invalid,
         duplicates,
     } = upsert_custom_fields(
-        &transaction,
+        &*tx,
         &journal.id,
         &id,
         json.custom_fields
     ).await?;
 
     if !not_found.is_empty() {
-        return Ok((
-            StatusCode::BAD_REQUEST,
-            body::Json(CreateEntryResult::CustomFieldNotFound {
+        return Ok(body::FieldError::new(
+            "custom_fields",
+            CreateEntryResult::CustomFieldNotFound {
                 ids: not_found,
-            })
+            }
         ).into_response());
     }
 
     if !invalid.is_empty() {
-        return Ok((
-            StatusCode::BAD_REQUEST,
-            body::Json(CreateEntryResult::CustomFieldInvalid {
+        return Ok(body::FieldError::new(
+            "custom_fields",
+            CreateEntryResult::CustomFieldInvalid {
                 invalid
-            })
+            }
         ).into_response());
     }
 
     if !duplicates.is_empty() {
-        return Ok((
-            StatusCode::BAD_REQUEST,
-            body::Json(CreateEntryResult::CustomFieldDuplicates {
+        return Ok(body::FieldError::new(
+            "custom_fields",
+            CreateEntryResult::CustomFieldDuplicates {
                 ids: duplicates,
-            })
+            }
+        ).into_response());
+    }
+
+    let result = mention::set_client(&*tx, &journal.id, &id, &json.mentions)
+        .await
+        .context("failed to set entry mentions")?;
+
+    if let Err(ids) = result {
+        return Ok(body::FieldError::new(
+            "mentions",
+            CreateEntryResult::SubjectNotFound { ids }
         ).into_response());
     }
 
+    mention::sync_body(&*tx, &journal.id, &id, contents.as_deref())
+        .await
+        .context("failed to sync entry mentions")?;
+
+    let mentions = mention::retrieve_entry(&*tx, &id)
+        .await
+        .context("failed to retrieve entry mentions")?;
+
     let (files, created_files) = if !json.files.is_empty() {
         let mut rtn: Vec<ResultFileEntry> = Vec::new();
 
@@ -596,6 +1897,8 @@ pub async fn create_entry(
                 mime_subtype,
                 mime_param: None,
                 size: 0,
+                hash: None,
+                encrypted: false,
                 created,
                 updated: None
             };
@@ -607,40 +1910,76 @@ pub async fn create_entry(
         }
 
         let dir = state.storage().journal_dir(&journal);
-        let created_files = insert_files(&transaction, &dir, &mut rtn).await?;
+        let created_files = insert_files(&*tx, &dir, &mut rtn).await?;
 
         (rtn, created_files)
     } else {
         (Vec::new(), CreatedFiles::new())
     };
 
-    let commit_result = transaction.commit()
-        .await;
-
-    if let Err(err) = commit_result {
-        created_files.log_rollback().await;
-
-        return Err(error::Error::context_source(
-            "failed to commit changes to journal entry",
-            err
-        ));
-    }
-
     let entry = ResultEntryFull {
         id,
         uid,
         journals_id,
         users_id,
+        number,
         date: entry_date,
         title,
         contents,
+        contents_html: None,
         created,
         updated: None,
+        planned,
+        ciphertext,
+        prompts_id,
+        word_count,
         tags,
+        tasks,
         files,
         custom_fields,
+        mentions,
     };
 
+    let queued = webhook::queue(&*tx, &journal.id, WebhookEvent::EntryCreated, &entry)
+        .await;
+
+    if let Err(err) = queued {
+        created_files.log_rollback().await;
+
+        return Err(error::Error::context_source(
+            "failed to queue entry created webhooks",
+            err
+        ));
+    }
+
+    if state.weather().is_some() && !journal.e2e {
+        if let Err(err) = weather::queue(&*tx, &entry.id).await {
+            created_files.log_rollback().await;
+
+            return Err(error::Error::context_source(
+                "failed to queue entry weather",
+                err
+            ));
+        }
+    }
+
+    let commit_result = tx.commit()
+        .await;
+
+    if let Err(err) = commit_result {
+        created_files.log_rollback().await;
+
+        return Err(error::Error::context_source(
+            "failed to commit changes to journal entry",
+            err
+        ));
+    }
+
+    state.live().publish(LiveEvent::EntryCreated {
+        journals_id: journal.id,
+        entries_id: entry.id,
+    });
+
     Ok((
         StatusCode::CREATED,
         body::Json(CreateEntryResult::Created(entry)),
@@ -659,23 +1998,46 @@ pub enum UpdateEntryResult {
     CustomFieldDuplicates {
         ids: Vec<CustomFieldId>,
     },
+    TasksNotFound {
+        ids: Vec<EntryTaskId>,
+    },
+    InvalidTags {
+        keys: Vec<String>,
+    },
+    TooManyFiles {
+        max_files_per_entry: u32,
+    },
+    DateExists,
+    PromptNotFound,
+    SubjectNotFound {
+        ids: Vec<SubjectId>,
+    },
+    CiphertextRequired,
+    PlaintextNotAllowed,
+    JournalNotEncrypted,
     Updated(ResultEntryFull)
 }
 
+impl From<E2eMismatch> for UpdateEntryResult {
+    fn from(mismatch: E2eMismatch) -> Self {
+        match mismatch {
+            E2eMismatch::CiphertextRequired => Self::CiphertextRequired,
+            E2eMismatch::PlaintextNotAllowed => Self::PlaintextNotAllowed,
+            E2eMismatch::JournalNotEncrypted => Self::JournalNotEncrypted,
+        }
+    }
+}
+
 pub async fn update_entry(
     state: state::SharedState,
+    tx: db::Tx,
     headers: HeaderMap,
     Path(EntryPath { journals_id, entries_id }): Path<EntryPath>,
     body::Json(json): body::Json<UpdatedEntryBody>,
 ) -> Result<Response, error::Error> {
-    let mut conn = state.db_conn().await?;
-    let transaction = conn.transaction()
-        .await
-        .context("failed to create transaction")?;
-
-    let initiator = macros::require_initiator!(&transaction, &headers, None::<Uri>);
+    let initiator = macros::require_initiator!(&*tx, &headers, None::<Uri>);
 
-    let result = Journal::retrieve_id(&transaction, &journals_id, &initiator.user.id)
+    let result = Journal::retrieve_accessible(&*tx, &journals_id, &initiator.user.id)
         .await
         .context("failed to retrieve default journal")?;
 
@@ -683,12 +2045,16 @@ pub async fn update_entry(
         return Ok(StatusCode::NOT_FOUND.into_response());
     };
 
-    auth::perm_check!(&transaction, initiator, journal, Scope::Entries, Ability::Update);
+    auth::perm_check!(&*tx, initiator, journal, Scope::Entries, Ability::Update);
+
+    auth::archived_check!(journal);
+
+    auth::frozen_check!(&*tx, journal);
 
     let result = Entry::retrieve_id(
-        &transaction,
+        &*tx,
         &journal.id,
-        &initiator.user.id,
+        &journal.users_id,
         &entries_id
     )
         .await
@@ -700,20 +2066,102 @@ pub async fn update_entry(
 
     tracing::debug!("entry: {entry:#?}");
 
+    Revision::record(&*tx, &entry.id, &initiator.user.id)
+        .await
+        .context("failed to record journal entry revision")?;
+
     let entry_date = json.date;
+    let today = Preferences::retrieve(&*tx, &initiator.user.id)
+        .await
+        .context("failed to retrieve preferences")?
+        .today();
+    let planned = entry_date > today;
     let title = opt_non_empty_str(json.title);
     let contents = opt_non_empty_str(json.contents);
+    let word_count = entry_word_count(title.as_deref(), contents.as_deref());
+    let ciphertext = json.ciphertext;
     let updated = Utc::now();
 
-    transaction.execute(
+    if let Some((field, mismatch)) = check_e2e(&journal, &ciphertext, &title, &contents, &json.custom_fields) {
+        return Ok(body::FieldError::new(
+            field,
+            UpdateEntryResult::from(mismatch)
+        ).into_response());
+    }
+
+    let given_tags = match parse_tags(json.tags) {
+        Ok(valid) => valid,
+        Err(keys) => return Ok(body::FieldError::new(
+            "tags",
+            UpdateEntryResult::InvalidTags { keys }
+        ).into_response()),
+    };
+
+    let prompts_id = match json.prompts_id {
+        Some(Some(prompts_id)) => {
+            let found = Prompt::retrieve_id(&*tx, &prompts_id, &initiator.user.id)
+                .await
+                .context("failed to retrieve prompt")?;
+
+            if found.is_none() {
+                return Ok(body::FieldError::new(
+                    "prompts_id",
+                    UpdateEntryResult::PromptNotFound
+                ).into_response());
+            }
+
+            Some(prompts_id)
+        }
+        Some(None) => None,
+        None => entry.prompts_id,
+    };
+
+    if journal.settings.multi_entry == MultiEntry::Single {
+        let in_use = Journal::date_in_use(&*tx, &journal.id, &entry_date, Some(&entries_id))
+            .await
+            .context("failed to check for existing entry date")?;
+
+        if in_use {
+            return Ok(body::FieldError::new(
+                "date",
+                UpdateEntryResult::DateExists
+            ).into_response());
+        }
+    }
+
+    // entries that were over the limit before it was set can still be
+    // updated as long as no files are added
+    let adding_files = json.files.iter()
+        .any(|file| matches!(file, UpdatedFileEntryBody::New(_)));
+
+    if adding_files {
+        let policy = UploadPolicy::retrieve(&*tx, state.upload(), &journal.id)
+            .await
+            .context("failed to retrieve journal upload policy")?;
+
+        if !policy.allows_files(json.files.len()) {
+            return Ok(body::FieldError::new(
+                "files",
+                UpdateEntryResult::TooManyFiles {
+                    max_files_per_entry: policy.max_files_per_entry.unwrap_or(0),
+                }
+            ).into_response());
+        }
+    }
+
+    tx.execute(
         "\
         update entries \
         set entry_date = $2, \
             title = $3, \
             contents = $4, \
-            updated = $5 \
+            updated = $5, \
+            planned = $6, \
+            ciphertext = $7, \
+            prompts_id = $8, \
+            word_count = $9 \
         where id = $1",
-        &[&entry.id, &entry_date, &title, &contents, &updated]
+        &[&entry.id, &entry_date, &title, &contents, &updated, &planned, &ciphertext, &prompts_id, &word_count]
     )
         .await
         .context("failed to update journal entry")?;
@@ -723,7 +2171,7 @@ pub async fn update_entry(
         let mut unchanged: Vec<EntryTag> = Vec::new();
         let mut current_tags: HashMap<String, EntryTag> = HashMap::new();
 
-        let tag_stream = EntryTag::retrieve_entry_stream(&transaction, entry.id)
+        let tag_stream = EntryTag::retrieve_entry_stream(&*tx, entry.id)
             .await
             .context("failed to retrieve entry tags")?;
 
@@ -735,12 +2183,7 @@ pub async fn update_entry(
             current_tags.insert(tag.key.clone(), tag);
         }
 
-        for tag in json.tags {
-            let Some(key) = non_empty_str(tag.key) else {
-                continue;
-            };
-            let value = opt_non_empty_str(tag.value);
-
+        for (key, value) in given_tags {
             if let Some(mut found) = current_tags.remove(&key) {
                 if found.value != value {
                     found.value = value.clone();
@@ -761,14 +2204,14 @@ pub async fn update_entry(
         }
 
         if !tags.is_empty() {
-            upsert_tags(&transaction, &entry.id, &tags).await?;
+            upsert_tags(&*tx, &entry.id, &tags).await?;
         }
 
         if !current_tags.is_empty() {
             let keys: Vec<String> = current_tags.into_keys()
                 .collect();
 
-            transaction.execute(
+            tx.execute(
                 "\
                 delete from entry_tags \
                 where entries_id = $1 and \
@@ -783,47 +2226,90 @@ pub async fn update_entry(
         tags
     };
 
+    let tasks = if let Some(given) = json.tasks {
+        let TasksUpsert { valid, not_found } = EntryTask::upsert_entry(&*tx, &entry.id, given, &updated)
+            .await
+            .context("failed to update entry tasks")?;
+
+        if !not_found.is_empty() {
+            return Ok(body::FieldError::new(
+                "tasks",
+                UpdateEntryResult::TasksNotFound {
+                    ids: not_found,
+                }
+            ).into_response());
+        }
+
+        valid
+    } else {
+        EntryTask::retrieve_entry(&*tx, &entry.id)
+            .await
+            .context("failed to retrieve entry tasks")?
+    };
+
     let CustomFieldsUpsert {
         valid: custom_fields,
         not_found,
         invalid,
         duplicates,
     } = upsert_custom_fields(
-        &transaction,
+        &*tx,
         &journal.id,
         &entry.id,
         json.custom_fields
     ).await?;
 
     if !not_found.is_empty() {
-        return Ok((
-            StatusCode::BAD_REQUEST,
-            body::Json(UpdateEntryResult::CustomFieldNotFound {
+        return Ok(body::FieldError::new(
+            "custom_fields",
+            UpdateEntryResult::CustomFieldNotFound {
                 ids: not_found,
-            })
+            }
         ).into_response());
     }
 
     if !invalid.is_empty() {
-        return Ok((
-            StatusCode::BAD_REQUEST,
-            body::Json(UpdateEntryResult::CustomFieldInvalid {
+        return Ok(body::FieldError::new(
+            "custom_fields",
+            UpdateEntryResult::CustomFieldInvalid {
                 invalid
-            })
+            }
         ).into_response());
     }
 
     if !duplicates.is_empty() {
-        return Ok((
-            StatusCode::BAD_REQUEST,
-            body::Json(UpdateEntryResult::CustomFieldDuplicates {
+        return Ok(body::FieldError::new(
+            "custom_fields",
+            UpdateEntryResult::CustomFieldDuplicates {
                 ids: duplicates,
-            })
+            }
         ).into_response());
     }
 
+    if let Some(given) = &json.mentions {
+        let result = mention::set_client(&*tx, &journal.id, &entry.id, given)
+            .await
+            .context("failed to set entry mentions")?;
+
+        if let Err(ids) = result {
+            return Ok(body::FieldError::new(
+                "mentions",
+                UpdateEntryResult::SubjectNotFound { ids }
+            ).into_response());
+        }
+    }
+
+    mention::sync_body(&*tx, &journal.id, &entry.id, contents.as_deref())
+        .await
+        .context("failed to sync entry mentions")?;
+
+    let mentions = mention::retrieve_entry(&*tx, &entry.id)
+        .await
+        .context("failed to retrieve entry mentions")?;
+
     let mut created_files = CreatedFiles::new();
     let mut removed_files = RemovedFiles::new();
+    let mut removed_thumbnails = Vec::new();
 
     let files = {
         let journal_dir = state.storage()
@@ -832,7 +2318,7 @@ pub async fn update_entry(
         let mut new_files = Vec::new();
         let mut updated_files = Vec::new();
         let mut current = HashMap::new();
-        let file_stream = FileEntry::retrieve_entry_stream(&transaction, &entry.id)
+        let file_stream = FileEntry::retrieve_entry_stream(&*tx, &entry.id)
             .await
             .context("failed to retrieve file entries")?;
 
@@ -861,6 +2347,8 @@ pub async fn update_entry(
                         mime_subtype,
                         mime_param: None,
                         size: 0,
+                        hash: None,
+                        encrypted: false,
                         created: updated,
                         updated: None
                     };
@@ -891,13 +2379,13 @@ pub async fn update_entry(
         if !new_files.is_empty() {
             let dir = state.storage().journal_dir(&journal);
 
-            created_files = insert_files(&transaction, &dir, &mut new_files).await?;
+            created_files = insert_files(&*tx, &dir, &mut new_files).await?;
             files.extend(new_files);
         }
 
         if !updated_files.is_empty() {
             for file in &updated_files {
-                if let Err(err) = file.inner.update(&transaction).await {
+                if let Err(err) = file.inner.update(&*tx).await {
                     created_files.log_rollback().await;
 
                     return Err(error::Error::context_source(
@@ -915,6 +2403,7 @@ pub async fn update_entry(
 
             for (id, record) in &current {
                 to_delete.push(id);
+                removed_thumbnails.push(*id);
 
                 if let Err(err) = removed_files.add(journal_dir.file_path(&record.id)).await {
                     created_files.log_rollback().await;
@@ -927,7 +2416,7 @@ pub async fn update_entry(
                 }
             }
 
-            let result = transaction.execute(
+            let result = tx.execute(
                 "delete from file_entries where id = any($1)",
                 &[&to_delete]
             ).await;
@@ -949,52 +2438,76 @@ pub async fn update_entry(
         files
     };
 
-    let commit_result = transaction.commit()
-        .await;
-
-    if let Err(err) = commit_result {
-        created_files.log_rollback().await;
-        removed_files.log_rollback().await;
-
-        return Err(error::Error::context_source(
-            "failed commit changes to journal entry",
-            err
-        ));
-    }
-
-    removed_files.log_clean().await;
-
     let entry = ResultEntryFull {
         id: entry.id,
         uid: entry.uid,
         journals_id: entry.journals_id,
         users_id: entry.users_id,
+        number: entry.number,
         date: entry_date,
         title,
         contents,
+        contents_html: None,
         created: entry.created,
         updated: Some(updated),
+        planned,
+        ciphertext,
+        prompts_id,
+        word_count,
         tags,
+        tasks,
         files,
         custom_fields,
+        mentions,
     };
 
+    let queued = webhook::queue(&*tx, &journal.id, WebhookEvent::EntryUpdated, &entry)
+        .await;
+
+    if let Err(err) = queued {
+        created_files.log_rollback().await;
+        removed_files.log_rollback().await;
+
+        return Err(error::Error::context_source(
+            "failed to queue entry updated webhooks",
+            err
+        ));
+    }
+
+    let commit_result = tx.commit()
+        .await;
+
+    if let Err(err) = commit_result {
+        created_files.log_rollback().await;
+        removed_files.log_rollback().await;
+
+        return Err(error::Error::context_source(
+            "failed commit changes to journal entry",
+            err
+        ));
+    }
+
+    state.live().publish(LiveEvent::EntryUpdated {
+        journals_id: journal.id,
+        entries_id: entry.id,
+    });
+
+    removed_files.log_clean().await;
+
+    remove_thumbnails(&state.storage().journal_dir(&journal), &removed_thumbnails).await;
+
     Ok(body::Json(UpdateEntryResult::Updated(entry)).into_response())
 }
 
 pub async fn delete_entry(
     state: state::SharedState,
+    tx: db::Tx,
     headers: HeaderMap,
     Path(EntryPath { journals_id, entries_id }): Path<EntryPath>,
 ) -> Result<Response, error::Error> {
-    let mut conn = state.db_conn().await?;
-    let transaction = conn.transaction()
-        .await
-        .context("failed to create transaction")?;
-
-    let initiator = macros::require_initiator!(&transaction, &headers, None::<Uri>);
+    let initiator = macros::require_initiator!(&*tx, &headers, None::<Uri>);
 
-    let result = Journal::retrieve_id(&transaction, &journals_id, &initiator.user.id)
+    let result = Journal::retrieve_accessible(&*tx, &journals_id, &initiator.user.id)
         .await
         .context("failed to retrieve default journal")?;
 
@@ -1002,12 +2515,16 @@ pub async fn delete_entry(
         return Ok(StatusCode::NOT_FOUND.into_response());
     };
 
-    auth::perm_check!(&transaction, initiator, journal, Scope::Entries, Ability::Delete);
+    auth::perm_check!(&*tx, initiator, journal, Scope::Entries, Ability::Delete);
+
+    auth::archived_check!(journal);
+
+    auth::frozen_check!(&*tx, journal);
 
     let result = EntryFull::retrieve_id(
-        &transaction,
+        &*tx,
         &journal.id,
-        &initiator.user.id,
+        &journal.users_id,
         &entries_id
     )
         .await
@@ -1017,7 +2534,7 @@ pub async fn delete_entry(
         return Ok(StatusCode::NOT_FOUND.into_response());
     };
 
-    let tags = transaction.execute(
+    let tags = tx.execute(
         "delete from entry_tags where entries_id = $1",
         &[&entry.id]
     )
@@ -1028,7 +2545,14 @@ pub async fn delete_entry(
         tracing::warn!("dangling tags for journal entry");
     }
 
-    let custom_fields = transaction.execute(
+    tx.execute(
+        "delete from entry_mentions where entries_id = $1",
+        &[&entry.id]
+    )
+        .await
+        .context("failed to delete mentions for journal entry")?;
+
+    let custom_fields = tx.execute(
         "delete from custom_field_entries where entries_id = $1",
         &[&entry.id]
     )
@@ -1039,7 +2563,22 @@ pub async fn delete_entry(
         tracing::warn!("dangling custom field entries for journal entry");
     }
 
-    let _files = transaction.execute(
+    EntryTask::delete_entry(&*tx, &entry.id)
+        .await
+        .context("failed to delete tasks for journal entry")?;
+
+    view::delete_entry(&*tx, &entry.id)
+        .await
+        .context("failed to delete views for journal entry")?;
+
+    tx.execute(
+        "delete from entry_revisions where entries_id = $1",
+        &[&entry.id]
+    )
+        .await
+        .context("failed to delete revisions for journal entry")?;
+
+    let _files = tx.execute(
         "delete from file_entries where entries_id = $1",
         &[&entry.id]
     )
@@ -1047,6 +2586,9 @@ pub async fn delete_entry(
         .context("failed to delete files for journal entry")?;
 
     let mut marked_files = RemovedFiles::new();
+    let thumbnails: Vec<FileEntryId> = entry.files.iter()
+        .map(|file| file.id)
+        .collect();
 
     if !entry.files.is_empty() {
         let journal_dir = state.storage().journal_dir(&journal);
@@ -1065,7 +2607,7 @@ pub async fn delete_entry(
         }
     }
 
-    let entry_result = transaction.execute(
+    let entry_result = tx.execute(
         "delete from entries where id = $1",
         &[&entry.id]
     ).await;
@@ -1088,7 +2630,7 @@ pub async fn delete_entry(
         }
     }
 
-    if let Err(err) = transaction.commit().await {
+    if let Err(err) = tx.commit().await {
         if !marked_files.is_empty() {
             marked_files.log_rollback().await;
         }
@@ -1102,10 +2644,27 @@ pub async fn delete_entry(
             marked_files.log_clean().await;
         }
 
+        remove_thumbnails(&state.storage().journal_dir(&journal), &thumbnails).await;
+
+        state.live().publish(LiveEvent::EntryDeleted {
+            journals_id: journal.id,
+            entries_id,
+        });
+
         Ok(StatusCode::OK.into_response())
     }
 }
 
+/// removes the thumbnails of deleted file entries. failures are logged since
+/// the file entries are already gone
+async fn remove_thumbnails(journal_dir: &JournalDir, ids: &[FileEntryId]) {
+    for id in ids {
+        if let Err(err) = thumbnail::remove(&journal_dir.thumbnail_path(id)).await {
+            error::log_prefix_error("failed to remove thumbnail", &err);
+        }
+    }
+}
+
 async fn insert_files(
     conn: &impl db::GenericClient,
     dir: &JournalDir,
@@ -1291,6 +2850,7 @@ async fn upsert_custom_fields(
             records.push(CustomFieldFull {
                 custom_fields_id: field.custom_fields_id,
                 value,
+                converted: None,
                 created: exists.created,
                 updated: Some(created),
             });
@@ -1298,6 +2858,7 @@ async fn upsert_custom_fields(
             records.push(CustomFieldFull {
                 custom_fields_id: field.custom_fields_id,
                 value,
+                converted: None,
                 created,
                 updated: None,
             });
@@ -1369,3 +2930,54 @@ async fn upsert_custom_fields(
         duplicates,
     })
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn search(users_id: UserId, unread_by: Option<UserId>) -> EntrySearch {
+        EntrySearch {
+            users_id,
+            journals_id: JournalId::new(1).unwrap(),
+            planned: false,
+            tag: None,
+            fields: Vec::new(),
+            text: None,
+            unread_by,
+            prompts_id: None,
+            subjects_id: None,
+            cursor: None,
+            page: None,
+        }
+    }
+
+    #[test]
+    fn unread_by_shared_user() {
+        let owner = UserId::new(1).unwrap();
+        let shared = UserId::new(2).unwrap();
+        let search = search(owner, Some(shared));
+        let mut params: db::ParamsVec<'_> = Vec::new();
+
+        let conditions = search.push_conditions(&mut params);
+
+        // the entries are still the ones stored under the owner while the
+        // views checked are the ones of the shared user
+        assert!(conditions.starts_with("entries.users_id = $1 and entries.journals_id = $2"));
+        assert!(conditions.contains("entry_views.users_id = $3"));
+        assert_eq!(params.len(), 3);
+        assert_eq!(format!("{:?}", params[0]), format!("{owner:?}"));
+        assert_eq!(format!("{:?}", params[2]), format!("{shared:?}"));
+    }
+
+    #[test]
+    fn unread_not_requested() {
+        let owner = UserId::new(1).unwrap();
+        let search = search(owner, None);
+        let mut params: db::ParamsVec<'_> = Vec::new();
+
+        let conditions = search.push_conditions(&mut params);
+
+        assert!(!conditions.contains("entry_views"));
+        assert_eq!(params.len(), 2);
+    }
+}
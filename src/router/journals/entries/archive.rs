@@ -0,0 +1,91 @@
+use axum::extract::Path;
+use axum::http::{StatusCode, HeaderMap};
+use axum::response::{IntoResponse, Response};
+use chrono::{DateTime, Utc};
+use serde::{Serialize, Deserialize};
+
+use crate::state;
+use crate::db::ids::JournalId;
+use crate::error::{self, Context};
+use crate::journal::Journal;
+use crate::router::body;
+use crate::router::macros;
+
+#[derive(Debug, Deserialize)]
+pub struct JournalPath {
+    journals_id: JournalId,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "type")]
+pub enum ArchiveResult {
+    JournalArchived {
+        archived: DateTime<Utc>,
+    },
+}
+
+/// the response sent when attempting to modify an archived journal
+pub fn archived_response(archived: DateTime<Utc>) -> Response {
+    (
+        StatusCode::LOCKED,
+        body::Json(ArchiveResult::JournalArchived {
+            archived,
+        })
+    ).into_response()
+}
+
+async fn set_archived(
+    state: state::SharedState,
+    headers: HeaderMap,
+    journals_id: JournalId,
+    archived: bool,
+) -> Result<Response, error::Error> {
+    let conn = state.db_conn().await?;
+
+    let initiator = macros::require_initiator!(&conn, &headers, None::<&'static str>);
+
+    let result = Journal::retrieve_id(&conn, &journals_id, &initiator.user.id)
+        .await
+        .context("failed to retrieve default journal")?;
+
+    let Some(mut journal) = result else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+
+    if journal.users_id != initiator.user.id {
+        return Ok(StatusCode::UNAUTHORIZED.into_response());
+    }
+
+    if journal.archived.is_some() == archived {
+        return Ok(StatusCode::OK.into_response());
+    }
+
+    journal.set_archived(&conn, archived)
+        .await
+        .context("failed to update journal archived")?;
+
+    Ok(StatusCode::OK.into_response())
+}
+
+/// archives the journal. the entries and files of the journal can still be
+/// read and exported but cannot be changed
+///
+/// only the owner of the journal is allowed to archive it
+pub async fn archive_journal(
+    state: state::SharedState,
+    headers: HeaderMap,
+    Path(JournalPath { journals_id }): Path<JournalPath>,
+) -> Result<Response, error::Error> {
+    set_archived(state, headers, journals_id, true).await
+}
+
+/// allows the entries and files of an archived journal to be changed again
+///
+/// only the owner of the journal is allowed to unarchive it
+pub async fn unarchive_journal(
+    state: state::SharedState,
+    headers: HeaderMap,
+    Path(JournalPath { journals_id }): Path<JournalPath>,
+) -> Result<Response, error::Error> {
+    set_archived(state, headers, journals_id, false).await
+}
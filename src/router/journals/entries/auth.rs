@@ -27,4 +27,28 @@ macro_rules! perm_check {
     }
 }
 
+/// rejects the request if the journal has been archived
+macro_rules! archived_check {
+    ($journal:expr) => {
+        if let Some(archived) = $journal.archived {
+            return Ok(crate::router::journals::entries::archive::archived_response(archived));
+        }
+    }
+}
+
+/// rejects the request if the journal is currently frozen
+macro_rules! frozen_check {
+    ($conn:expr, $journal:expr) => {
+        let frozen = crate::journal::freeze::Freeze::retrieve_active($conn, &$journal.id)
+            .await
+            .context("failed to retrieve journal freeze")?;
+
+        if let Some(freeze) = frozen {
+            return Ok(crate::router::journals::entries::freeze::frozen_response(&freeze));
+        }
+    }
+}
+
 pub(crate) use perm_check;
+pub(crate) use frozen_check;
+pub(crate) use archived_check;
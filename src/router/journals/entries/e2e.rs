@@ -0,0 +1,158 @@
+use axum::extract::Path;
+use axum::http::{StatusCode, HeaderMap};
+use axum::response::{IntoResponse, Response};
+use serde::{Serialize, Deserialize};
+
+use crate::state;
+use crate::db::GenericClient;
+use crate::db::ids::JournalId;
+use crate::error::{self, Context};
+use crate::journal::Journal;
+use crate::journal::e2e::{self, Ciphertext, EscrowKey, KdfParams};
+use crate::router::body;
+use crate::router::macros;
+use crate::sec::authz::{Scope, Ability};
+
+use super::auth;
+
+#[derive(Debug, Deserialize)]
+pub struct JournalPath {
+    journals_id: JournalId,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateEscrowBody {
+    /// the journal key wrapped with the key derived from the passphrase
+    wrapped_key: Ciphertext,
+
+    /// a new salt if the passphrase was changed
+    salt: Option<Ciphertext>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "type")]
+pub enum EscrowResult {
+    JournalNotEncrypted,
+    InvalidSalt {
+        min: usize,
+    },
+    EmptyKey,
+}
+
+/// retrieves the escrowed key of the journal, creating the key derivation
+/// parameters if they are missing
+async fn retrieve_or_create(
+    conn: &impl GenericClient,
+    journals_id: &JournalId,
+) -> Result<EscrowKey, error::Error> {
+    let result = EscrowKey::retrieve(conn, journals_id)
+        .await
+        .context("failed to retrieve journal escrow key")?;
+
+    if let Some(escrow) = result {
+        return Ok(escrow);
+    }
+
+    let kdf = KdfParams::generate()
+        .context("failed to generate key derivation parameters")?;
+
+    EscrowKey::create(conn, journals_id, kdf)
+        .await
+        .context("failed to create journal escrow key")
+}
+
+/// retrieves the key derivation parameters and the wrapped key of an
+/// encrypted journal
+pub async fn retrieve_escrow(
+    state: state::SharedState,
+    headers: HeaderMap,
+    Path(JournalPath { journals_id }): Path<JournalPath>,
+) -> Result<Response, error::Error> {
+    let conn = state.db_conn().await?;
+
+    let initiator = macros::require_initiator!(&conn, &headers, None::<&'static str>);
+
+    let result = Journal::retrieve_accessible(&conn, &journals_id, &initiator.user.id)
+        .await
+        .context("failed to retrieve default journal")?;
+
+    let Some(journal) = result else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+
+    auth::perm_check!(&conn, initiator, journal, Scope::Entries, Ability::Read);
+
+    if !journal.e2e {
+        return Ok((
+            StatusCode::BAD_REQUEST,
+            body::Json(EscrowResult::JournalNotEncrypted)
+        ).into_response());
+    }
+
+    let escrow = retrieve_or_create(&conn, &journal.id).await?;
+
+    Ok(body::Json(escrow).into_response())
+}
+
+/// stores the wrapped key of an encrypted journal
+///
+/// the server is never able to unwrap the key. only the owner of the journal
+/// is allowed to replace it
+pub async fn update_escrow(
+    state: state::SharedState,
+    headers: HeaderMap,
+    Path(JournalPath { journals_id }): Path<JournalPath>,
+    body::Json(json): body::Json<UpdateEscrowBody>,
+) -> Result<Response, error::Error> {
+    let conn = state.db_conn().await?;
+
+    let initiator = macros::require_initiator!(&conn, &headers, None::<&'static str>);
+
+    let result = Journal::retrieve_id(&conn, &journals_id, &initiator.user.id)
+        .await
+        .context("failed to retrieve default journal")?;
+
+    let Some(journal) = result else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+
+    if journal.users_id != initiator.user.id {
+        return Ok(StatusCode::UNAUTHORIZED.into_response());
+    }
+
+    if !journal.e2e {
+        return Ok((
+            StatusCode::BAD_REQUEST,
+            body::Json(EscrowResult::JournalNotEncrypted)
+        ).into_response());
+    }
+
+    if json.wrapped_key.is_empty() {
+        return Ok(body::FieldError::new(
+            "wrapped_key",
+            EscrowResult::EmptyKey
+        ).into_response());
+    }
+
+    if let Some(salt) = &json.salt {
+        if salt.len() < e2e::SALT_LEN {
+            return Ok(body::FieldError::new(
+                "salt",
+                EscrowResult::InvalidSalt { min: e2e::SALT_LEN }
+            ).into_response());
+        }
+    }
+
+    let mut escrow = retrieve_or_create(&conn, &journal.id).await?;
+
+    if let Some(salt) = json.salt {
+        escrow.kdf.salt = salt;
+    }
+
+    escrow.wrapped_key = Some(json.wrapped_key);
+    escrow.update(&conn)
+        .await
+        .context("failed to update journal escrow key")?;
+
+    Ok(body::Json(escrow).into_response())
+}
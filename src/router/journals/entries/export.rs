@@ -0,0 +1,273 @@
+use axum::body::Body;
+use axum::extract::Path;
+use axum::http::{StatusCode, HeaderMap};
+use axum::response::{IntoResponse, Response};
+use serde::Deserialize;
+use tokio_util::io::ReaderStream;
+
+use crate::state;
+use crate::db::ids::{ExportId, JournalId};
+use crate::error::{self, Context};
+use crate::journal::Journal;
+use crate::journal::export::{ExportFormat, JournalExport};
+use crate::journal::freeze::{self, Freeze};
+use crate::router::body;
+use crate::router::macros;
+use crate::sec::authz::{Scope, Ability};
+
+use super::auth;
+
+#[derive(Debug, Deserialize)]
+pub struct JournalPath {
+    journals_id: JournalId,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ExportPath {
+    journals_id: JournalId,
+    exports_id: ExportId,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct NewExportBody {
+    #[serde(default)]
+    format: ExportFormat,
+
+    /// freezes the journal until the export finishes
+    #[serde(default)]
+    freeze: bool,
+}
+
+/// creates the archive for an export and marks it as completed or failed
+async fn run_export(state: state::SharedState, journal: Journal, mut export: JournalExport) {
+    let conn = match state.db_conn().await {
+        Ok(conn) => conn,
+        Err(err) => {
+            error::log_prefix_error("failed to run journal export", &err);
+
+            return;
+        }
+    };
+
+    let journal_dir = state.storage().journal_dir(&journal);
+
+    // without a key only the unencrypted files can be exported so the
+    // archive will fail if any encrypted files are present
+    let key = match state.storage().journal_key(&conn, &journal).await {
+        Ok(key) => key,
+        Err(err) => {
+            error::log_prefix_error("failed to retrieve journal key for export", &err);
+
+            None
+        }
+    };
+
+    let space = match state.storage().check_space(0).await {
+        Ok(space) => space,
+        Err(err) => {
+            error::log_prefix_error("failed to check storage space for export", &err);
+
+            None
+        }
+    };
+
+    if let Some(details) = space {
+        tracing::warn!(
+            available = details.available,
+            "journal export failed due to insufficient storage"
+        );
+
+        if let Err(err) = export.mark_failed(&conn).await {
+            error::log_prefix_error("failed to update journal export", &err);
+        }
+
+        if let Err(err) = Freeze::lift_export(&conn, &export.id).await {
+            error::log_prefix_error("failed to lift journal export freeze", &err);
+        }
+
+        return;
+    }
+
+    let result = match export.write_archive(&conn, &journal, &journal_dir, key.as_ref()).await {
+        Ok(()) => export.mark_completed(&conn).await,
+        Err(err) => {
+            error::log_prefix_error("failed to write journal export", &err);
+
+            if let Err(err) = tokio::fs::remove_file(journal_dir.export_path(&export.id)).await {
+                if err.kind() != std::io::ErrorKind::NotFound {
+                    error::log_prefix_error("failed to remove journal export", &err);
+                }
+            }
+
+            export.mark_failed(&conn).await
+        }
+    };
+
+    if let Err(err) = result {
+        error::log_prefix_error("failed to update journal export", &err);
+    }
+
+    if let Err(err) = Freeze::lift_export(&conn, &export.id).await {
+        error::log_prefix_error("failed to lift journal export freeze", &err);
+    }
+}
+
+/// starts a new export of all the entries in a journal
+///
+/// the archive is created in the background and can be downloaded once the
+/// export is marked as completed
+pub async fn create_export(
+    state: state::SharedState,
+    headers: HeaderMap,
+    Path(JournalPath { journals_id }): Path<JournalPath>,
+    body::Json(json): body::Json<NewExportBody>,
+) -> Result<Response, error::Error> {
+    let conn = state.db_conn().await?;
+
+    let initiator = macros::require_initiator!(&conn, &headers, None::<&'static str>);
+
+    let result = Journal::retrieve_accessible(&conn, &journals_id, &initiator.user.id)
+        .await
+        .context("failed to retrieve default journal")?;
+
+    let Some(journal) = result else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+
+    auth::perm_check!(&conn, initiator, journal, Scope::Entries, Ability::Read);
+
+    if json.freeze {
+        if journal.users_id != initiator.user.id {
+            return Ok(StatusCode::UNAUTHORIZED.into_response());
+        }
+
+        let frozen = Freeze::retrieve_active(&conn, &journal.id)
+            .await
+            .context("failed to retrieve journal freeze")?;
+
+        if let Some(freeze) = frozen {
+            return Ok(super::freeze::frozen_response(&freeze));
+        }
+    }
+
+    let export = JournalExport::create(&conn, &journal.id, &initiator.user.id, json.format)
+        .await
+        .context("failed to create journal export")?;
+
+    if json.freeze {
+        Freeze::create(
+            &conn,
+            &journal.id,
+            &initiator.user.id,
+            Some(&export.id),
+            freeze::EXPORT_DURATION
+        )
+            .await
+            .context("failed to create journal export freeze")?;
+    }
+
+    let response = (
+        StatusCode::ACCEPTED,
+        body::Json(&export),
+    ).into_response();
+
+    tokio::spawn(run_export(state.clone(), journal, export));
+
+    Ok(response)
+}
+
+pub async fn retrieve_export(
+    state: state::SharedState,
+    headers: HeaderMap,
+    Path(ExportPath { journals_id, exports_id }): Path<ExportPath>,
+) -> Result<Response, error::Error> {
+    let conn = state.db_conn().await?;
+
+    let initiator = macros::require_initiator!(&conn, &headers, None::<&'static str>);
+
+    let result = Journal::retrieve_accessible(&conn, &journals_id, &initiator.user.id)
+        .await
+        .context("failed to retrieve default journal")?;
+
+    let Some(journal) = result else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+
+    auth::perm_check!(&conn, initiator, journal, Scope::Entries, Ability::Read);
+
+    let result = JournalExport::retrieve(&conn, &journal.id, &exports_id)
+        .await
+        .context("failed to retrieve journal export")?;
+
+    let Some(export) = result.filter(|export| export.users_id == initiator.user.id) else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+
+    Ok(body::Json(export).into_response())
+}
+
+/// streams the archive of a completed export
+pub async fn download_export(
+    state: state::SharedState,
+    headers: HeaderMap,
+    Path(ExportPath { journals_id, exports_id }): Path<ExportPath>,
+) -> Result<Response, error::Error> {
+    let conn = state.db_conn().await?;
+
+    let initiator = macros::require_initiator!(&conn, &headers, None::<&'static str>);
+
+    let result = Journal::retrieve_accessible(&conn, &journals_id, &initiator.user.id)
+        .await
+        .context("failed to retrieve default journal")?;
+
+    let Some(journal) = result else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+
+    auth::perm_check!(&conn, initiator, journal, Scope::Entries, Ability::Read);
+
+    let result = JournalExport::retrieve(&conn, &journal.id, &exports_id)
+        .await
+        .context("failed to retrieve journal export")?;
+
+    let Some(export) = result.filter(|export| export.users_id == initiator.user.id) else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+
+    if export.completed.is_none() {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    }
+
+    let file_path = state.storage()
+        .journal_dir(&journal)
+        .export_path(&export.id);
+    let file = tokio::fs::OpenOptions::new()
+        .read(true)
+        .open(&file_path)
+        .await
+        .context("failed to open journal export archive")?;
+    let metadata = file.metadata()
+        .await
+        .context("failed to retrieve journal export metadata")?;
+    let reader = ReaderStream::new(file);
+
+    let mut disposition = String::from("attachment; filename=\"");
+
+    for ch in journal.name.chars() {
+        if ch.is_ascii_alphanumeric() || ch == '-' || ch == '_' {
+            disposition.push(ch);
+        } else {
+            disposition.push('_');
+        }
+    }
+
+    disposition.push_str(&format!("-{}.zip\"", export.created.format("%Y%m%d")));
+
+    Response::builder()
+        .status(StatusCode::OK)
+        .header("content-type", "application/zip")
+        .header("content-length", metadata.len())
+        .header("content-disposition", disposition)
+        .body(Body::from_stream(reader))
+        .context("failed to create export response")
+}
@@ -1,23 +1,32 @@
+use std::collections::HashSet;
+use std::io::SeekFrom;
 use std::str::FromStr;
 
 use axum::body::Body;
-use axum::extract::Path;
+use axum::extract::{Path, Query};
 use axum::http::{StatusCode, HeaderMap};
 use axum::response::{IntoResponse, Response};
-use chrono::Utc;
+use bytes::Bytes;
+use chrono::{DateTime, Utc};
 use futures::StreamExt;
-use serde::Deserialize;
-use tokio::io::{AsyncWrite, AsyncWriteExt};
+use serde::{Serialize, Deserialize};
+use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWrite, AsyncWriteExt};
 use tokio_util::io::ReaderStream;
 
 use crate::state;
-use crate::db::ids::{JournalId, EntryId, FileEntryId};
+use crate::db::GenericClient;
+use crate::db::ids::{JournalId, EntryId, FileEntryId, FileEntryUid};
 use crate::error::{self, Context};
-use crate::fs::FileUpdater;
-use crate::journal::{Journal, FileEntry};
+use crate::fs::{self, FileUpdater, InsufficientStorage};
+use crate::journal::{audio, image_meta, thumbnail, Journal, FileEntry};
+use crate::journal::live::LiveEvent;
+use crate::journal::upload::{PolicyViolation, UploadPolicy};
+use crate::journal::webhook::{self, WebhookEvent};
 use crate::router::body;
 use crate::router::macros;
 use crate::sec::authz::{Scope, Ability};
+use crate::sec::encryption::Encryptor;
+use crate::sec::scan::{self, Verdict};
 
 use super::auth;
 
@@ -28,6 +37,178 @@ pub struct FileEntryPath {
     file_entry_id: FileEntryId,
 }
 
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FileSize {
+    /// the file as it was uploaded
+    #[default]
+    Original,
+
+    /// a scaled down jpeg of an image file
+    Thumb,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct FileQuery {
+    #[serde(default)]
+    size: FileSize,
+}
+
+/// the max number of file entries that can be requested at once
+const MAX_METADATA_IDS: usize = 200;
+
+#[derive(Debug, Deserialize)]
+pub struct JournalPath {
+    journals_id: JournalId,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct MetadataBody {
+    ids: Vec<FileEntryId>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "type")]
+pub enum MetadataResult {
+    TooManyIds {
+        max: usize,
+    },
+}
+
+#[derive(Debug, Serialize)]
+pub struct FileMetadata {
+    id: FileEntryId,
+    uid: FileEntryUid,
+    entries_id: EntryId,
+    name: Option<String>,
+    mime_type: String,
+    mime_subtype: String,
+    mime_param: Option<String>,
+    size: i64,
+    hash: Option<String>,
+    created: DateTime<Utc>,
+    updated: Option<DateTime<Utc>>,
+
+    /// the path to download the contents of the file. None if the file has
+    /// not been uploaded
+    download_url: Option<String>,
+
+    /// the length and transcript of audio files
+    audio: Option<audio::AudioDetails>,
+
+    /// the capture date of images
+    image: Option<image_meta::ImageDetails>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MetadataResponse {
+    files: Vec<FileMetadata>,
+
+    /// requested ids that do not exist in the journal
+    missing: Vec<FileEntryId>,
+}
+
+/// retrieves the metadata for a list of file entries in a journal
+///
+/// this is for clients that need the details of many files at once without
+/// making a request for each one
+pub async fn retrieve_metadata(
+    state: state::SharedState,
+    headers: HeaderMap,
+    Path(JournalPath { journals_id }): Path<JournalPath>,
+    body::Json(json): body::Json<MetadataBody>,
+) -> Result<Response, error::Error> {
+    let conn = state.db_conn().await?;
+
+    let initiator = macros::require_initiator!(&conn, &headers, None::<&'static str>);
+
+    let result = Journal::retrieve_accessible(&conn, &journals_id, &initiator.user.id)
+        .await
+        .context("failed to retrieve default journal")?;
+
+    let Some(journal) = result else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+
+    auth::perm_check!(&conn, initiator, journal, Scope::Entries, Ability::Read);
+
+    let mut requested: HashSet<FileEntryId> = HashSet::with_capacity(json.ids.len());
+    let mut ids = Vec::with_capacity(json.ids.len());
+
+    for id in json.ids {
+        if requested.insert(id) {
+            ids.push(id);
+        }
+    }
+
+    if ids.len() > MAX_METADATA_IDS {
+        return Ok(body::FieldError::new(
+            "ids",
+            MetadataResult::TooManyIds { max: MAX_METADATA_IDS }
+        ).into_response());
+    }
+
+    let mut audio_details = audio::retrieve_ids(&conn, &ids)
+        .await
+        .context("failed to retrieve audio details")?;
+
+    let mut image_details = image_meta::retrieve_ids(&conn, &ids)
+        .await
+        .context("failed to retrieve image details")?;
+
+    let stream = FileEntry::retrieve_journal_ids_stream(&conn, &journal.id, &ids)
+        .await
+        .context("failed to retrieve journal file entries")?;
+
+    futures::pin_mut!(stream);
+
+    let mut files = Vec::with_capacity(ids.len());
+
+    while let Some(try_record) = stream.next().await {
+        let record = try_record.context("failed to retrieve journal file entry")?;
+
+        requested.remove(&record.id);
+
+        let download_url = record.hash.as_ref().map(|_| format!(
+            "/journals/{}/entries/{}/{}",
+            journal.id,
+            record.entries_id,
+            record.id
+        ));
+
+        files.push(FileMetadata {
+            id: record.id,
+            uid: record.uid,
+            entries_id: record.entries_id,
+            name: record.name,
+            mime_type: record.mime_type,
+            mime_subtype: record.mime_subtype,
+            mime_param: record.mime_param,
+            size: record.size,
+            hash: record.hash,
+            created: record.created,
+            updated: record.updated,
+            download_url,
+            audio: audio_details.remove(&record.id),
+            image: image_details.remove(&record.id),
+        });
+    }
+
+    let missing = ids.into_iter()
+        .filter(|id| requested.contains(id))
+        .collect();
+
+    Ok(body::Json(MetadataResponse {
+        files,
+        missing,
+    }).into_response())
+}
+
+/// streams the contents of a file entry
+///
+/// `?size=thumb` returns a thumbnail for images instead. thumbnails that are
+/// missing are created before responding and non image files will respond
+/// with 404
 pub async fn retrieve_file(
     state: state::SharedState,
     headers: HeaderMap,
@@ -36,12 +217,13 @@ pub async fn retrieve_file(
         entries_id,
         file_entry_id
     }): Path<FileEntryPath>,
+    Query(FileQuery { size }): Query<FileQuery>,
 ) -> Result<Response, error::Error> {
     let conn = state.db_conn().await?;
 
     let initiator = macros::require_initiator!(&conn, &headers, None::<&'static str>);
 
-    let result = Journal::retrieve_id(&conn, &journals_id, &initiator.user.id)
+    let result = Journal::retrieve_accessible(&conn, &journals_id, &initiator.user.id)
         .await
         .context("failed to retrieve default journal")?;
 
@@ -59,23 +241,259 @@ pub async fn retrieve_file(
         return Ok(StatusCode::NOT_FOUND.into_response());
     };
 
+    if size == FileSize::Thumb {
+        return retrieve_thumbnail(&state, &conn, &journal, &file_entry).await;
+    }
+
+    let etag = file_entry.hash.as_ref()
+        .map(|hash| format!("\"{hash}\""));
+    let last_modified = file_entry.updated.unwrap_or(file_entry.created);
+    let last_modified_str = http_date(&last_modified);
+
+    if not_modified(&headers, etag.as_deref(), &last_modified) {
+        let mut builder = Response::builder()
+            .status(StatusCode::NOT_MODIFIED)
+            .header("last-modified", &last_modified_str)
+            .header("cache-control", "private, no-cache");
+
+        if let Some(etag) = &etag {
+            builder = builder.header("etag", etag);
+        }
+
+        return builder.body(Body::empty())
+            .context("failed to create file response");
+    }
+
+    let size = file_entry.size as u64;
+    let range = match requested_range(&headers, etag.as_deref(), &last_modified_str, size) {
+        Ok(range) => range,
+        Err(()) => return Response::builder()
+            .status(StatusCode::RANGE_NOT_SATISFIABLE)
+            .header("content-range", format!("bytes */{size}"))
+            .body(Body::empty())
+            .context("failed to create file response"),
+    };
+
+    let key = if file_entry.encrypted {
+        Some(state.storage().require_journal_key(&conn, &journal).await?)
+    } else {
+        None
+    };
+
     let file_path = state.storage()
-        .journal_file_entry(journal.id, file_entry.id);
-    let file = tokio::fs::OpenOptions::new()
+        .journal_file_entry(&journal, file_entry.id);
+    let mut file = tokio::fs::OpenOptions::new()
         .read(true)
         .open(&file_path)
         .await
         .context("failed to open file for journal file entry")?;
-    let reader = ReaderStream::new(file);
 
     let mime = file_entry.get_mime();
 
+    let mut builder = Response::builder()
+        .header("content-type", mime.to_string())
+        .header("accept-ranges", "bytes")
+        .header("last-modified", last_modified_str)
+        .header("cache-control", "private, no-cache");
+
+    if let Some(etag) = &etag {
+        builder = builder.header("etag", etag);
+    }
+
+    // uploaded files are not trusted. an svg or html file opened directly
+    // would otherwise run scripts as the server
+    let file_csp = &state.security_headers().file_content_security_policy;
+
+    if !file_csp.is_empty() {
+        builder = builder.header("content-security-policy", file_csp);
+    }
+
+    if let Some((start, end)) = range {
+        let length = end - start + 1;
+
+        let body = if let Some(key) = key {
+            Body::from_stream(key.decrypt_range(file, size, start, end))
+        } else {
+            file.seek(SeekFrom::Start(start))
+                .await
+                .context("failed to seek to range of journal file entry")?;
+
+            Body::from_stream(ReaderStream::new(file.take(length)))
+        };
+
+        builder.status(StatusCode::PARTIAL_CONTENT)
+            .header("content-length", length)
+            .header("content-range", format!("bytes {start}-{end}/{size}"))
+            .body(body)
+            .context("failed to create file response")
+    } else {
+        let body = match key {
+            Some(_) if size == 0 => Body::empty(),
+            Some(key) => Body::from_stream(key.decrypt_range(file, size, 0, size - 1)),
+            None => Body::from_stream(ReaderStream::new(file)),
+        };
+
+        builder.status(StatusCode::OK)
+            .header("content-length", size)
+            .body(body)
+            .context("failed to create file response")
+    }
+}
+
+/// formats a timestamp as an http date
+fn http_date(date: &DateTime<Utc>) -> String {
+    date.format("%a, %d %b %Y %H:%M:%S GMT").to_string()
+}
+
+fn header_str<'a>(headers: &'a HeaderMap, name: &str) -> Option<&'a str> {
+    headers.get(name)
+        .and_then(|value| value.to_str().ok())
+}
+
+/// checks If-None-Match and If-Modified-Since for a conditional request.
+/// If-Modified-Since is ignored when If-None-Match is present
+fn not_modified(headers: &HeaderMap, etag: Option<&str>, last_modified: &DateTime<Utc>) -> bool {
+    if let Some(if_none_match) = header_str(headers, "if-none-match") {
+        let Some(etag) = etag else {
+            return false;
+        };
+
+        return if_none_match.split(',')
+            .map(|tag| tag.trim())
+            .any(|tag| tag == "*" || tag.trim_start_matches("W/") == etag);
+    }
+
+    let Some(if_modified_since) = header_str(headers, "if-modified-since") else {
+        return false;
+    };
+
+    let Ok(since) = DateTime::parse_from_rfc2822(if_modified_since) else {
+        return false;
+    };
+
+    // http dates only have second precision
+    last_modified.timestamp() <= since.timestamp()
+}
+
+/// determines the inclusive byte range to respond with from the Range and
+/// If-Range headers
+///
+/// only a single range is supported. None is returned if the full file
+/// should be sent and an error if the range cannot be satisfied
+fn requested_range(
+    headers: &HeaderMap,
+    etag: Option<&str>,
+    last_modified: &str,
+    size: u64,
+) -> Result<Option<(u64, u64)>, ()> {
+    let Some(range) = header_str(headers, "range") else {
+        return Ok(None);
+    };
+
+    if let Some(if_range) = header_str(headers, "if-range") {
+        if Some(if_range) != etag && if_range != last_modified {
+            return Ok(None);
+        }
+    }
+
+    let Some(spec) = range.strip_prefix("bytes=") else {
+        return Ok(None);
+    };
+
+    if spec.contains(',') {
+        return Ok(None);
+    }
+
+    let Some((start, end)) = spec.trim().split_once('-') else {
+        return Err(());
+    };
+
+    let (start, end) = if start.is_empty() {
+        let suffix: u64 = end.parse().map_err(|_| ())?;
+
+        if suffix == 0 {
+            return Err(());
+        }
+
+        (size.saturating_sub(suffix), size.checked_sub(1).ok_or(())?)
+    } else {
+        let start: u64 = start.parse().map_err(|_| ())?;
+        let end = if end.is_empty() {
+            size.checked_sub(1).ok_or(())?
+        } else {
+            let end: u64 = end.parse().map_err(|_| ())?;
+
+            end.min(size.checked_sub(1).ok_or(())?)
+        };
+
+        (start, end)
+    };
+
+    if start > end || start >= size {
+        return Err(());
+    }
+
+    Ok(Some((start, end)))
+}
+
+async fn retrieve_thumbnail(
+    state: &state::SharedState,
+    conn: &impl GenericClient,
+    journal: &Journal,
+    file_entry: &FileEntry,
+) -> Result<Response, error::Error> {
+    if !thumbnail::is_supported(file_entry) {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    }
+
+    let key = if file_entry.encrypted {
+        Some(state.storage().require_journal_key(conn, journal).await?)
+    } else {
+        None
+    };
+
+    let journal_dir = state.storage().journal_dir(journal);
+    let thumb_path = journal_dir.thumbnail_path(&file_entry.id);
+
+    let exists = tokio::fs::try_exists(&thumb_path)
+        .await
+        .context("failed to check for thumbnail")?;
+
+    if !exists {
+        thumbnail::create(journal_dir.file_path(&file_entry.id), thumb_path.clone(), key.clone()).await?;
+    }
+
+    if let Some(key) = key {
+        let contents = tokio::fs::read(&thumb_path)
+            .await
+            .context("failed to read thumbnail for journal file entry")?;
+        let decrypted = key.decrypt_all(&contents)
+            .context("failed to decrypt thumbnail")?;
+
+        return Response::builder()
+            .status(StatusCode::OK)
+            .header("content-type", "image/jpeg")
+            .header("content-length", decrypted.len())
+            .body(Body::from(decrypted))
+            .context("failed to create thumbnail response");
+    }
+
+    let file = tokio::fs::OpenOptions::new()
+        .read(true)
+        .open(&thumb_path)
+        .await
+        .context("failed to open thumbnail for journal file entry")?;
+    let metadata = file.metadata()
+        .await
+        .context("failed to retrieve thumbnail metadata")?;
+    let reader = ReaderStream::new(file);
+
     Response::builder()
         .status(StatusCode::OK)
-        .header("content-type", mime.to_string())
-        .header("content-length", file_entry.size)
+        .header("content-type", "image/jpeg")
+        .header("content-length", metadata.len())
         .body(Body::from_stream(reader))
-        .context("failed to create file response")
+        .context("failed to create thumbnail response")
 }
 
 pub async fn upload_file(
@@ -95,7 +513,7 @@ pub async fn upload_file(
 
     let initiator = macros::require_initiator!(&transaction, &headers, None::<&'static str>);
 
-    let result = Journal::retrieve_id(&transaction, &journals_id, &initiator.user.id)
+    let result = Journal::retrieve_accessible(&transaction, &journals_id, &initiator.user.id)
         .await
         .context("failed to retrieve default journal")?;
 
@@ -107,6 +525,10 @@ pub async fn upload_file(
 
     auth::perm_check!(&transaction, initiator, journal, Scope::Entries, Ability::Update);
 
+    auth::archived_check!(journal);
+
+    auth::frozen_check!(&transaction, journal);
+
     let result = FileEntry::retrieve_file_entry(&transaction, &entries_id, &file_entry_id)
         .await
         .context("failed to retrieve journal entry file")?;
@@ -119,13 +541,91 @@ pub async fn upload_file(
 
     let mime = get_mime(&headers)?;
 
+    // a missing content-length still requires the reserve to be available
+    let expected = headers.get("content-length")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<u64>().ok())
+        .unwrap_or(0);
+
+    let policy = UploadPolicy::retrieve(&transaction, state.upload(), &journal.id)
+        .await
+        .context("failed to retrieve journal upload policy")?;
+
+    match policy.check_file(&mime, expected) {
+        Ok(()) => {}
+        Err(PolicyViolation::MimeTypeNotAllowed(mime_type)) => return Ok((
+            StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            body::Json(UploadPolicyResult::MimeTypeNotAllowed {
+                mime_type,
+                allowed_mime_types: policy.allowed_mime_types,
+            })
+        ).into_response()),
+        Err(PolicyViolation::FileTooLarge(_)) => return Ok(file_too_large_response(&policy)),
+    }
+
+    if let Some(details) = state.storage().check_space(expected).await? {
+        tracing::warn!(
+            available = details.available,
+            needed = details.needed,
+            "rejected file upload due to insufficient storage"
+        );
+
+        return Ok(insufficient_storage_response(details));
+    }
+
+    // images are read into memory so that their metadata can be read, and
+    // removed if required, before they are written
+    let (stream, image) = if image_meta::is_supported(&mime) {
+        let mut contents = Vec::new();
+
+        write_body(&mut contents, stream, None).await
+            .context("failed to read image upload")?;
+
+        if !policy.allows_size(contents.len() as u64) {
+            return Ok(file_too_large_response(&policy));
+        }
+
+        let strip_metadata = policy.strip_metadata;
+        let subtype = mime.subtype().as_str().to_owned();
+
+        let result = tokio::task::spawn_blocking(move || image_meta::process(
+            Bytes::from(contents),
+            &subtype,
+            strip_metadata
+        ))
+            .await
+            .context("failed to join image processing")?;
+
+        match result {
+            Ok(processed) => (Body::from(processed.contents), Some(processed.details)),
+            Err(err) => {
+                error::log_prefix_error("failed to strip image metadata", &err);
+
+                return Ok((
+                    StatusCode::UNPROCESSABLE_ENTITY,
+                    body::Json(UploadPolicyResult::InvalidImage)
+                ).into_response());
+            }
+        }
+    } else {
+        (stream, None)
+    };
+
     let file_path = state.storage()
-        .journal_file_entry(journal.id, file_entry.id);
+        .journal_file_entry(&journal, file_entry.id);
+    let key = state.storage()
+        .journal_key(&transaction, &journal)
+        .await?;
+    let encryptor = match &key {
+        Some(key) => Some(Encryptor::new(key.clone()).context("failed to create file encryptor")?),
+        None => None,
+    };
+
     let mut file_update = FileUpdater::new(file_path)
         .await
         .context("failed to create file updater")?;
 
-    let (written, _hash) = match write_body(&mut file_update, stream).await {
+    let (written, hash) = match write_body(&mut file_update, stream, encryptor).await {
         Ok(rtn) => rtn,
         Err(err) => {
             if let Err((_file_update, err)) = file_update.clean().await {
@@ -135,6 +635,16 @@ pub async fn upload_file(
                 );
             }
 
+            if fs::is_storage_full(&err) {
+                tracing::warn!("file upload failed due to a full file system");
+
+                return Ok(insufficient_storage_response(InsufficientStorage {
+                    available: 0,
+                    needed: expected,
+                    reserve: state.storage().reserve(),
+                }));
+            }
+
             return Err(error::Error::context_source(
                 "failed to write request body to temp file",
                 err
@@ -142,10 +652,68 @@ pub async fn upload_file(
         }
     };
 
+    // the content-length is only provided by the client so the size is
+    // checked again after the contents are written
+    if !policy.allows_size(written as u64) {
+        if let Err((_file_update, clean_err)) = file_update.clean().await {
+            error::log_prefix_error("failed to remove temp_path during upload", &clean_err);
+        }
+
+        return Ok(file_too_large_response(&policy));
+    }
+
+    if let Some(scan) = state.scan() {
+        // verdicts are cached outside of the transaction so that rejected
+        // uploads are remembered
+        let result = match state.db_conn().await {
+            Ok(conn) => scan::scan_file_cached(
+                &conn,
+                scan,
+                &hash.to_hex(),
+                file_update.temp_path(),
+                written as u64,
+                key.as_ref()
+            ).await,
+            Err(err) => Err(err),
+        };
+
+        let verdict = match result {
+            Ok(verdict) => verdict,
+            Err(err) => {
+                if let Err((_file_update, clean_err)) = file_update.clean().await {
+                    error::log_prefix_error("failed to remove temp_path during upload", &clean_err);
+                }
+
+                return Err(error::Error::context_source("failed to scan uploaded file", err));
+            }
+        };
+
+        if let Verdict::Infected(signature) = verdict {
+            if let Err((_file_update, clean_err)) = file_update.clean().await {
+                error::log_prefix_error("failed to remove temp_path during upload", &clean_err);
+            }
+
+            tracing::warn!(
+                users_id = %initiator.user.id,
+                journals_id = %journal.id,
+                file_entry_id = %file_entry.id,
+                signature,
+                "rejected infected file upload"
+            );
+
+            return Ok((
+                StatusCode::UNPROCESSABLE_ENTITY,
+                body::Json(ScanResult::FileInfected { signature })
+            ).into_response());
+        }
+    }
+
     file_entry.mime_type = get_mime_type(&mime);
     file_entry.mime_subtype = get_mime_subtype(&mime);
     file_entry.mime_param = get_mime_params(mime.params());
     file_entry.size = written;
+    file_entry.hash = Some(hash.to_hex().to_string());
+    file_entry.encrypted = key.is_some();
     file_entry.updated = Some(Utc::now());
 
     // update the database record
@@ -160,6 +728,17 @@ pub async fn upload_file(
         ));
     }
 
+    if let Err(err) = webhook::queue(&transaction, &journal.id, WebhookEvent::FileReceived, &file_entry).await {
+        if let Err((_file_update, clean_err)) = file_update.clean().await {
+            error::log_prefix_error("failed to clean file update", &clean_err);
+        }
+
+        return Err(error::Error::context_source(
+            "failed to queue file received webhooks",
+            err
+        ));
+    }
+
     let updated = file_update.update()
         .await
         .context("failed to update file")?;
@@ -180,15 +759,112 @@ pub async fn upload_file(
         error::log_prefix_error("failed to clean up file update", &clean_err);
     }
 
+    state.live().publish(LiveEvent::FileReceived {
+        journals_id: journal.id,
+        entries_id: file_entry.entries_id,
+        file_entry_id: file_entry.id,
+    });
+
+    let journal_dir = state.storage().journal_dir(&journal);
+
+    if let Err(err) = image_meta::store_upload(&conn, &file_entry.id, image.as_ref()).await {
+        error::log_prefix_error("failed to update image details for file entry", &err);
+    }
+
+    // the duration of audio files is only informational so a failure is
+    // logged and the upload still succeeds
+    if audio::is_audio(&file_entry) {
+        let duration = audio::duration(
+            journal_dir.file_path(&file_entry.id),
+            file_entry.mime_subtype.clone(),
+            key.clone()
+        ).await;
+
+        let result = match duration {
+            Ok(duration) => audio::store_upload(&conn, &file_entry.id, duration)
+                .await
+                .context("failed to store audio details"),
+            Err(err) => Err(err),
+        };
+
+        if let Err(err) = result {
+            error::log_prefix_error("failed to update audio details for file entry", &err);
+        }
+    }
+
+    // a failed thumbnail is not an error for the upload since it will be
+    // attempted again the next time it is requested
+    let thumb_path = journal_dir.thumbnail_path(&file_entry.id);
+
+    let thumb_result = if thumbnail::is_supported(&file_entry) {
+        thumbnail::create(journal_dir.file_path(&file_entry.id), thumb_path, key).await
+    } else {
+        thumbnail::remove(&thumb_path)
+            .await
+            .context("failed to remove previous thumbnail")
+    };
+
+    if let Err(err) = thumb_result {
+        error::log_prefix_error("failed to update thumbnail for file entry", &err);
+    }
+
     Ok((
         StatusCode::OK,
         body::Json(file_entry)
     ).into_response())
 }
 
-async fn write_body<'a, T>(
+#[derive(Debug, Serialize)]
+#[serde(tag = "type")]
+pub enum StorageResult {
+    InsufficientStorage(InsufficientStorage),
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "type")]
+pub enum ScanResult {
+    FileInfected {
+        signature: String,
+    },
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "type")]
+pub enum UploadPolicyResult {
+    MimeTypeNotAllowed {
+        mime_type: String,
+        allowed_mime_types: Vec<String>,
+    },
+    FileTooLarge {
+        max_file_size: u64,
+    },
+
+    /// the metadata of the image could not be removed
+    InvalidImage,
+}
+
+fn file_too_large_response(policy: &UploadPolicy) -> Response {
+    (
+        StatusCode::PAYLOAD_TOO_LARGE,
+        body::Json(UploadPolicyResult::FileTooLarge {
+            max_file_size: policy.max_file_size.unwrap_or(0),
+        })
+    ).into_response()
+}
+
+pub fn insufficient_storage_response(details: InsufficientStorage) -> Response {
+    (
+        StatusCode::INSUFFICIENT_STORAGE,
+        body::Json(StorageResult::InsufficientStorage(details))
+    ).into_response()
+}
+
+/// writes the request body to the writer, encrypting it if an encryptor is
+/// given. the size and hash returned are for the unencrypted contents
+pub(super) async fn write_body<'a, T>(
     writer: &'a mut T,
     stream: Body,
+    mut encryptor: Option<Encryptor>,
 ) -> Result<(i64, blake3::Hash), error::Error>
 where
     T: AsyncWrite + Unpin,
@@ -205,14 +881,32 @@ where
 
         hasher.update(slice);
 
-        let wrote = writer.write(slice)
-            .await
-            .context("failed to write bytes to stream")?;
+        if let Some(encryptor) = &mut encryptor {
+            let sealed = encryptor.update(slice)
+                .context("failed to encrypt bytes from stream")?;
 
-        written = written.checked_add(wrote)
+            writer.write_all(&sealed)
+                .await
+                .context("failed to write bytes to stream")?;
+        } else {
+            writer.write_all(slice)
+                .await
+                .context("failed to write bytes to stream")?;
+        }
+
+        written = written.checked_add(slice.len())
             .context("bytes written overflows usize")?;
     }
 
+    if let Some(encryptor) = encryptor {
+        let sealed = encryptor.finish()
+            .context("failed to encrypt bytes from stream")?;
+
+        writer.write_all(&sealed)
+            .await
+            .context("failed to write bytes to stream")?;
+    }
+
     writer.flush()
         .await
         .context("failed to flush contents of stream")?;
@@ -262,3 +956,103 @@ fn get_mime_params(params: mime::Params<'_>) -> Option<String> {
         None
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const LAST_MODIFIED: &str = "Tue, 06 Oct 2026 12:00:00 GMT";
+
+    fn range(value: &str, size: u64) -> Result<Option<(u64, u64)>, ()> {
+        let mut headers = HeaderMap::new();
+        headers.insert("range", value.parse().unwrap());
+
+        requested_range(&headers, Some("\"abc\""), LAST_MODIFIED, size)
+    }
+
+    #[test]
+    fn no_range_sends_full_file() {
+        assert_eq!(requested_range(&HeaderMap::new(), None, LAST_MODIFIED, 100), Ok(None));
+    }
+
+    #[test]
+    fn open_ended() {
+        assert_eq!(range("bytes=0-", 100), Ok(Some((0, 99))));
+        assert_eq!(range("bytes=40-", 100), Ok(Some((40, 99))));
+    }
+
+    #[test]
+    fn bounded() {
+        assert_eq!(range("bytes=10-19", 100), Ok(Some((10, 19))));
+        assert_eq!(range("bytes=99-99", 100), Ok(Some((99, 99))));
+    }
+
+    #[test]
+    fn suffix() {
+        assert_eq!(range("bytes=-10", 100), Ok(Some((90, 99))));
+        assert_eq!(range("bytes=-500", 100), Ok(Some((0, 99))));
+    }
+
+    #[test]
+    fn end_past_eof_is_clamped() {
+        assert_eq!(range("bytes=50-1000", 100), Ok(Some((50, 99))));
+    }
+
+    #[test]
+    fn start_past_eof() {
+        assert_eq!(range("bytes=100-", 100), Err(()));
+        assert_eq!(range("bytes=200-300", 100), Err(()));
+    }
+
+    #[test]
+    fn multiple_ranges_send_full_file() {
+        assert_eq!(range("bytes=0-10, 20-30", 100), Ok(None));
+    }
+
+    #[test]
+    fn other_units_send_full_file() {
+        assert_eq!(range("items=0-10", 100), Ok(None));
+    }
+
+    #[test]
+    fn malformed() {
+        let specs = [
+            "bytes=",
+            "bytes=10",
+            "bytes=-",
+            "bytes=-0",
+            "bytes=abc-",
+            "bytes=0-abc",
+            "bytes=--5",
+            "bytes=20-10",
+            "bytes=18446744073709551616-",
+        ];
+
+        for spec in specs {
+            assert_eq!(range(spec, 100), Err(()), "{spec} should not be satisfiable");
+        }
+    }
+
+    #[test]
+    fn empty_file() {
+        assert_eq!(range("bytes=0-", 0), Err(()));
+        assert_eq!(range("bytes=-10", 0), Err(()));
+    }
+
+    #[test]
+    fn if_range() {
+        let mut headers = HeaderMap::new();
+        headers.insert("range", "bytes=0-9".parse().unwrap());
+        headers.insert("if-range", "\"abc\"".parse().unwrap());
+
+        assert_eq!(requested_range(&headers, Some("\"abc\""), LAST_MODIFIED, 100), Ok(Some((0, 9))));
+
+        headers.insert("if-range", LAST_MODIFIED.parse().unwrap());
+
+        assert_eq!(requested_range(&headers, Some("\"abc\""), LAST_MODIFIED, 100), Ok(Some((0, 9))));
+
+        headers.insert("if-range", "\"old\"".parse().unwrap());
+
+        assert_eq!(requested_range(&headers, Some("\"abc\""), LAST_MODIFIED, 100), Ok(None));
+    }
+}
@@ -0,0 +1,155 @@
+use axum::extract::Path;
+use axum::http::{StatusCode, HeaderMap};
+use axum::response::{IntoResponse, Response};
+use chrono::{DateTime, Duration, Utc};
+use serde::{Serialize, Deserialize};
+
+use crate::state;
+use crate::db::ids::JournalId;
+use crate::error::{self, Context};
+use crate::journal::Journal;
+use crate::journal::freeze::{self, Freeze};
+use crate::router::body;
+use crate::router::macros;
+
+#[derive(Debug, Deserialize)]
+pub struct JournalPath {
+    journals_id: JournalId,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct NewFreezeBody {
+    /// the number of minutes to freeze the journal for
+    minutes: i64,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "type")]
+pub enum FreezeResult {
+    InvalidDuration {
+        max: i64,
+    },
+    JournalFrozen {
+        expires: DateTime<Utc>,
+    },
+}
+
+/// the response sent when attempting to modify a frozen journal
+pub fn frozen_response(freeze: &Freeze) -> Response {
+    (
+        StatusCode::LOCKED,
+        body::Json(FreezeResult::JournalFrozen {
+            expires: freeze.expires,
+        })
+    ).into_response()
+}
+
+pub async fn retrieve_freeze(
+    state: state::SharedState,
+    headers: HeaderMap,
+    Path(JournalPath { journals_id }): Path<JournalPath>,
+) -> Result<Response, error::Error> {
+    let conn = state.db_conn().await?;
+
+    let initiator = macros::require_initiator!(&conn, &headers, None::<&'static str>);
+
+    let result = Journal::retrieve_id(&conn, &journals_id, &initiator.user.id)
+        .await
+        .context("failed to retrieve default journal")?;
+
+    let Some(journal) = result else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+
+    let result = Freeze::retrieve_active(&conn, &journal.id)
+        .await
+        .context("failed to retrieve journal freeze")?;
+
+    let Some(freeze) = result else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+
+    Ok(body::Json(freeze).into_response())
+}
+
+/// freezes the journal for the given number of minutes
+///
+/// only the owner of the journal is allowed to freeze it
+pub async fn create_freeze(
+    state: state::SharedState,
+    headers: HeaderMap,
+    Path(JournalPath { journals_id }): Path<JournalPath>,
+    body::Json(json): body::Json<NewFreezeBody>,
+) -> Result<Response, error::Error> {
+    let conn = state.db_conn().await?;
+
+    let initiator = macros::require_initiator!(&conn, &headers, None::<&'static str>);
+
+    let result = Journal::retrieve_id(&conn, &journals_id, &initiator.user.id)
+        .await
+        .context("failed to retrieve default journal")?;
+
+    let Some(journal) = result else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+
+    if journal.users_id != initiator.user.id {
+        return Ok(StatusCode::UNAUTHORIZED.into_response());
+    }
+
+    let max = freeze::MAX_DURATION.num_minutes();
+
+    if json.minutes <= 0 || json.minutes > max {
+        return Ok(body::FieldError::new(
+            "minutes",
+            FreezeResult::InvalidDuration { max }
+        ).into_response());
+    }
+
+    let freeze = Freeze::create(
+        &conn,
+        &journal.id,
+        &initiator.user.id,
+        None,
+        Duration::minutes(json.minutes)
+    )
+        .await
+        .context("failed to create journal freeze")?;
+
+    Ok(body::Json(freeze).into_response())
+}
+
+/// lifts the freeze on a journal before it expires
+///
+/// only the owner of the journal is allowed to lift it
+pub async fn lift_freeze(
+    state: state::SharedState,
+    headers: HeaderMap,
+    Path(JournalPath { journals_id }): Path<JournalPath>,
+) -> Result<Response, error::Error> {
+    let conn = state.db_conn().await?;
+
+    let initiator = macros::require_initiator!(&conn, &headers, None::<&'static str>);
+
+    let result = Journal::retrieve_id(&conn, &journals_id, &initiator.user.id)
+        .await
+        .context("failed to retrieve default journal")?;
+
+    let Some(journal) = result else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+
+    if journal.users_id != initiator.user.id {
+        return Ok(StatusCode::UNAUTHORIZED.into_response());
+    }
+
+    let lifted = Freeze::lift(&conn, &journal.id)
+        .await
+        .context("failed to lift journal freeze")?;
+
+    if lifted {
+        Ok(StatusCode::OK.into_response())
+    } else {
+        Ok(StatusCode::NOT_FOUND.into_response())
+    }
+}
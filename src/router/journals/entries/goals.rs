@@ -0,0 +1,365 @@
+use axum::extract::{Path, Query};
+use axum::http::{StatusCode, HeaderMap};
+use axum::response::{IntoResponse, Response};
+use chrono::NaiveDate;
+use serde::{Serialize, Deserialize};
+
+use crate::state;
+use crate::db;
+use crate::db::ids::{GoalId, JournalId};
+use crate::error::{self, Context};
+use crate::journal::{custom_field, Journal};
+use crate::journal::goal::{Goal, GoalKind, GoalPeriod, GoalProgress};
+use crate::router::body;
+use crate::router::macros;
+use crate::sec::authz::{Scope, Ability};
+use crate::user::preferences::Preferences;
+
+use super::auth;
+
+/// the default number of periods retrieved for the progress of a goal
+const DEFAULT_PERIODS: u32 = 12;
+
+/// the max number of periods retrieved for the progress of a goal
+const MAX_PERIODS: u32 = 104;
+
+#[derive(Debug, Deserialize)]
+pub struct JournalPath {
+    journals_id: JournalId,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct GoalPath {
+    journals_id: JournalId,
+    goals_id: GoalId,
+}
+
+/// a goal along with its progress for the current period
+#[derive(Debug, Serialize)]
+pub struct GoalDetails {
+    #[serde(flatten)]
+    goal: Goal,
+    current: Option<GoalProgress>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct NewGoalBody {
+    name: String,
+    kind: GoalKind,
+    period: GoalPeriod,
+    target: i64,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateGoalBody {
+    name: Option<String>,
+    kind: Option<GoalKind>,
+    period: Option<GoalPeriod>,
+    target: Option<i64>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ProgressQuery {
+    /// the number of periods to retrieve ending with the current one
+    periods: Option<u32>,
+
+    /// the day to evaluate the progress from. defaults to the current day
+    /// of the user
+    date: Option<NaiveDate>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "type")]
+pub enum GoalResult {
+    NameEmpty,
+    InvalidTarget,
+    CustomFieldNotFound,
+    CustomFieldNotNumeric,
+    PeriodsTooLarge {
+        max: u32,
+    },
+}
+
+/// retrieves the journal for the initiator if they are allowed to read the
+/// entries of it
+macro_rules! readable_journal {
+    ($conn:expr, $initiator:expr, $journals_id:expr) => {{
+        let result = Journal::retrieve_accessible($conn, $journals_id, &$initiator.user.id)
+            .await
+            .context("failed to retrieve journal")?;
+
+        let Some(journal) = result else {
+            return Ok(StatusCode::NOT_FOUND.into_response());
+        };
+
+        auth::perm_check!($conn, $initiator, journal, Scope::Entries, Ability::Read);
+
+        journal
+    }};
+}
+
+/// retrieves the journal for the initiator if they are the owner
+macro_rules! owned_journal {
+    ($conn:expr, $initiator:expr, $journals_id:expr) => {{
+        let result = Journal::retrieve_id($conn, $journals_id, &$initiator.user.id)
+            .await
+            .context("failed to retrieve journal")?;
+
+        let Some(journal) = result else {
+            return Ok(StatusCode::NOT_FOUND.into_response());
+        };
+
+        if journal.users_id != $initiator.user.id {
+            return Ok(StatusCode::UNAUTHORIZED.into_response());
+        }
+
+        journal
+    }};
+}
+
+/// checks that the name and target are valid and that a custom field goal
+/// references a numeric field of the journal
+async fn validate_goal(
+    conn: &impl db::GenericClient,
+    journal: &Journal,
+    name: &str,
+    kind: &GoalKind,
+    target: i64,
+) -> Result<Result<(), (&'static str, GoalResult)>, error::Error> {
+    if name.is_empty() {
+        return Ok(Err(("name", GoalResult::NameEmpty)));
+    }
+
+    if target <= 0 {
+        return Ok(Err(("target", GoalResult::InvalidTarget)));
+    }
+
+    if let GoalKind::Field { custom_fields_id, .. } = kind {
+        let fields = custom_field::Type::retrieve_journal_map(conn, &journal.id)
+            .await
+            .context("failed to retrieve journal custom fields")?;
+
+        let Some(field) = fields.get(custom_fields_id) else {
+            return Ok(Err(("kind", GoalResult::CustomFieldNotFound)));
+        };
+
+        if !field.is_numeric() {
+            return Ok(Err(("kind", GoalResult::CustomFieldNotNumeric)));
+        }
+    }
+
+    Ok(Ok(()))
+}
+
+/// retrieves the goals of the journal along with their progress for the
+/// current period
+pub async fn retrieve_goals(
+    state: state::SharedState,
+    headers: HeaderMap,
+    Path(JournalPath { journals_id }): Path<JournalPath>,
+) -> Result<Response, error::Error> {
+    let conn = state.db_conn().await?;
+
+    let initiator = macros::require_initiator!(&conn, &headers, None::<&'static str>);
+
+    let journal = readable_journal!(&conn, initiator, &journals_id);
+
+    let today = Preferences::retrieve(&conn, &initiator.user.id)
+        .await
+        .context("failed to retrieve preferences")?
+        .today();
+
+    let goals = Goal::retrieve_journal(&conn, &journal.id)
+        .await
+        .context("failed to retrieve journal goals")?;
+
+    let mut rtn = Vec::with_capacity(goals.len());
+
+    for goal in goals {
+        let current = goal.progress(&conn, &today, journal.settings.week_start, 1)
+            .await
+            .context("failed to evaluate goal progress")?
+            .pop();
+
+        rtn.push(GoalDetails { goal, current });
+    }
+
+    Ok(body::Json(rtn).into_response())
+}
+
+pub async fn create_goal(
+    tx: db::Tx,
+    headers: HeaderMap,
+    Path(JournalPath { journals_id }): Path<JournalPath>,
+    body::Json(json): body::Json<NewGoalBody>,
+) -> Result<Response, error::Error> {
+    let initiator = macros::require_initiator!(&*tx, &headers, None::<&'static str>);
+
+    let journal = owned_journal!(&*tx, initiator, &journals_id);
+
+    let name = json.name.trim().to_owned();
+
+    if let Err((field, result)) = validate_goal(&*tx, &journal, &name, &json.kind, json.target).await? {
+        return Ok(body::FieldError::new(field, result).into_response());
+    }
+
+    let goal = Goal::create(&*tx, &journal.id, name, json.kind, json.period, json.target)
+        .await
+        .context("failed to create journal goal")?;
+
+    Ok((
+        StatusCode::CREATED,
+        body::Json(goal)
+    ).into_response())
+}
+
+pub async fn retrieve_goal(
+    state: state::SharedState,
+    headers: HeaderMap,
+    Path(GoalPath { journals_id, goals_id }): Path<GoalPath>,
+) -> Result<Response, error::Error> {
+    let conn = state.db_conn().await?;
+
+    let initiator = macros::require_initiator!(&conn, &headers, None::<&'static str>);
+
+    let journal = readable_journal!(&conn, initiator, &journals_id);
+
+    let result = Goal::retrieve_id(&conn, &journal.id, &goals_id)
+        .await
+        .context("failed to retrieve journal goal")?;
+
+    let Some(goal) = result else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+
+    let today = Preferences::retrieve(&conn, &initiator.user.id)
+        .await
+        .context("failed to retrieve preferences")?
+        .today();
+
+    let current = goal.progress(&conn, &today, journal.settings.week_start, 1)
+        .await
+        .context("failed to evaluate goal progress")?
+        .pop();
+
+    Ok(body::Json(GoalDetails { goal, current }).into_response())
+}
+
+/// changes the given fields of a goal. fields that are not given are left
+/// as is
+pub async fn update_goal(
+    tx: db::Tx,
+    headers: HeaderMap,
+    Path(GoalPath { journals_id, goals_id }): Path<GoalPath>,
+    body::Json(json): body::Json<UpdateGoalBody>,
+) -> Result<Response, error::Error> {
+    let initiator = macros::require_initiator!(&*tx, &headers, None::<&'static str>);
+
+    let journal = owned_journal!(&*tx, initiator, &journals_id);
+
+    let result = Goal::retrieve_id(&*tx, &journal.id, &goals_id)
+        .await
+        .context("failed to retrieve journal goal")?;
+
+    let Some(mut goal) = result else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+
+    if let Some(name) = json.name {
+        goal.name = name.trim().to_owned();
+    }
+
+    if let Some(kind) = json.kind {
+        goal.kind = kind;
+    }
+
+    if let Some(period) = json.period {
+        goal.period = period;
+    }
+
+    if let Some(target) = json.target {
+        goal.target = target;
+    }
+
+    if let Err((field, result)) = validate_goal(&*tx, &journal, &goal.name, &goal.kind, goal.target).await? {
+        return Ok(body::FieldError::new(field, result).into_response());
+    }
+
+    goal.update(&*tx)
+        .await
+        .context("failed to update journal goal")?;
+
+    Ok(body::Json(goal).into_response())
+}
+
+pub async fn delete_goal(
+    tx: db::Tx,
+    headers: HeaderMap,
+    Path(GoalPath { journals_id, goals_id }): Path<GoalPath>,
+) -> Result<Response, error::Error> {
+    let initiator = macros::require_initiator!(&*tx, &headers, None::<&'static str>);
+
+    let journal = owned_journal!(&*tx, initiator, &journals_id);
+
+    let result = Goal::retrieve_id(&*tx, &journal.id, &goals_id)
+        .await
+        .context("failed to retrieve journal goal")?;
+
+    let Some(goal) = result else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+
+    goal.delete(&*tx)
+        .await
+        .context("failed to delete journal goal")?;
+
+    Ok(StatusCode::OK.into_response())
+}
+
+/// retrieves the progress of a goal for the current period and the periods
+/// before it, oldest first
+pub async fn retrieve_progress(
+    state: state::SharedState,
+    headers: HeaderMap,
+    Path(GoalPath { journals_id, goals_id }): Path<GoalPath>,
+    Query(query): Query<ProgressQuery>,
+) -> Result<Response, error::Error> {
+    let conn = state.db_conn().await?;
+
+    let initiator = macros::require_initiator!(&conn, &headers, None::<&'static str>);
+
+    let journal = readable_journal!(&conn, initiator, &journals_id);
+
+    let periods = query.periods.unwrap_or(DEFAULT_PERIODS).max(1);
+
+    if periods > MAX_PERIODS {
+        return Ok(body::FieldError::new(
+            "periods",
+            GoalResult::PeriodsTooLarge {
+                max: MAX_PERIODS,
+            }
+        ).into_response());
+    }
+
+    let result = Goal::retrieve_id(&conn, &journal.id, &goals_id)
+        .await
+        .context("failed to retrieve journal goal")?;
+
+    let Some(goal) = result else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+
+    let date = match query.date {
+        Some(date) => date,
+        None => Preferences::retrieve(&conn, &initiator.user.id)
+            .await
+            .context("failed to retrieve preferences")?
+            .today(),
+    };
+
+    let progress = goal.progress(&conn, &date, journal.settings.week_start, periods)
+        .await
+        .context("failed to evaluate goal progress")?;
+
+    Ok(body::Json(progress).into_response())
+}
@@ -0,0 +1,289 @@
+use axum::extract::{Path, Query};
+use axum::http::{StatusCode, Uri, HeaderMap};
+use axum::response::{IntoResponse, Response};
+use chrono::{DateTime, Utc};
+use futures::StreamExt;
+use serde::{Serialize, Deserialize};
+
+use crate::state;
+use crate::db::ids::{EntryId, JournalId, UserId, RevisionId};
+use crate::error::{self, Context};
+use crate::journal::revision::{Revision, Snapshot};
+use crate::journal::{Journal, Entry, MultiEntry};
+use crate::router::body;
+use crate::router::macros;
+use crate::sec::authz::{Scope, Ability};
+
+use super::{auth, EntryFull};
+
+#[derive(Debug, Deserialize)]
+pub struct HistoryPath {
+    journals_id: JournalId,
+    entries_id: EntryId,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RevisionPath {
+    journals_id: JournalId,
+    entries_id: EntryId,
+    revision: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RevisionPartial {
+    id: RevisionId,
+    users_id: UserId,
+    revision: i64,
+    created: DateTime<Utc>,
+}
+
+pub async fn retrieve_history(
+    state: state::SharedState,
+    headers: HeaderMap,
+    Path(HistoryPath { journals_id, entries_id }): Path<HistoryPath>,
+) -> Result<Response, error::Error> {
+    let conn = state.db_conn().await?;
+
+    let initiator = macros::require_initiator!(&conn, &headers, None::<Uri>);
+
+    let result = Journal::retrieve_accessible(&conn, &journals_id, &initiator.user.id)
+        .await
+        .context("failed to retrieve default journal")?;
+
+    let Some(journal) = result else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+
+    auth::perm_check!(&conn, initiator, journal, Scope::Entries, Ability::Read);
+
+    let result = Entry::retrieve_id(&conn, &journal.id, &journal.users_id, &entries_id)
+        .await
+        .context("failed to retrieve journal entry")?;
+
+    let Some(entry) = result else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+
+    let stream = Revision::retrieve_entry_stream(&conn, &entry.id)
+        .await
+        .context("failed to retrieve entry revisions")?;
+
+    futures::pin_mut!(stream);
+
+    let mut found = Vec::new();
+
+    while let Some(try_record) = stream.next().await {
+        let record = try_record.context("failed to retrieve entry revision")?;
+
+        found.push(RevisionPartial {
+            id: record.id,
+            users_id: record.users_id,
+            revision: record.revision,
+            created: record.created,
+        });
+    }
+
+    Ok(body::Json(found).into_response())
+}
+
+pub async fn retrieve_revision(
+    state: state::SharedState,
+    headers: HeaderMap,
+    Path(RevisionPath { journals_id, entries_id, revision }): Path<RevisionPath>,
+) -> Result<Response, error::Error> {
+    let conn = state.db_conn().await?;
+
+    let initiator = macros::require_initiator!(&conn, &headers, None::<Uri>);
+
+    let result = Journal::retrieve_accessible(&conn, &journals_id, &initiator.user.id)
+        .await
+        .context("failed to retrieve default journal")?;
+
+    let Some(journal) = result else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+
+    auth::perm_check!(&conn, initiator, journal, Scope::Entries, Ability::Read);
+
+    let result = Entry::retrieve_id(&conn, &journal.id, &journal.users_id, &entries_id)
+        .await
+        .context("failed to retrieve journal entry")?;
+
+    let Some(entry) = result else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+
+    let result = Revision::retrieve(&conn, &entry.id, &revision)
+        .await
+        .context("failed to retrieve entry revision")?;
+
+    let Some(found) = result else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+
+    Ok(body::Json(found).into_response())
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DiffQuery {
+    against: Option<i64>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "type")]
+pub enum DiffResult {
+    AgainstNotFound {
+        revision: i64,
+    },
+}
+
+/// compares a revision against another revision or the current state of
+/// the entry if no revision is specified
+pub async fn diff_revision(
+    state: state::SharedState,
+    headers: HeaderMap,
+    Path(RevisionPath { journals_id, entries_id, revision }): Path<RevisionPath>,
+    Query(DiffQuery { against }): Query<DiffQuery>,
+) -> Result<Response, error::Error> {
+    let conn = state.db_conn().await?;
+
+    let initiator = macros::require_initiator!(&conn, &headers, None::<Uri>);
+
+    let result = Journal::retrieve_accessible(&conn, &journals_id, &initiator.user.id)
+        .await
+        .context("failed to retrieve default journal")?;
+
+    let Some(journal) = result else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+
+    auth::perm_check!(&conn, initiator, journal, Scope::Entries, Ability::Read);
+
+    let result = Entry::retrieve_id(&conn, &journal.id, &journal.users_id, &entries_id)
+        .await
+        .context("failed to retrieve journal entry")?;
+
+    let Some(entry) = result else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+
+    let result = Revision::retrieve(&conn, &entry.id, &revision)
+        .await
+        .context("failed to retrieve entry revision")?;
+
+    let Some(from) = result else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+
+    let to = if let Some(against) = against {
+        let result = Revision::retrieve(&conn, &entry.id, &against)
+            .await
+            .context("failed to retrieve entry revision")?;
+
+        let Some(found) = result else {
+            return Ok(body::FieldError::new(
+                "against",
+                DiffResult::AgainstNotFound {
+                    revision: against
+                }
+            ).into_response());
+        };
+
+        found.snapshot
+    } else {
+        Snapshot::capture(&conn, &entry.id)
+            .await
+            .context("failed to capture current journal entry")?
+            .context("journal entry was not found")?
+    };
+
+    Ok(body::Json(from.snapshot.diff(&to)).into_response())
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "type")]
+pub enum RestoreResult {
+    DateExists,
+}
+
+/// overwrites an entry with the data from a previous revision
+///
+/// the current state of the entry is recorded as a new revision before it
+/// is overwritten so that a restore can be undone
+pub async fn restore_revision(
+    state: state::SharedState,
+    headers: HeaderMap,
+    Path(RevisionPath { journals_id, entries_id, revision }): Path<RevisionPath>,
+) -> Result<Response, error::Error> {
+    let mut conn = state.db_conn().await?;
+    let transaction = conn.transaction()
+        .await
+        .context("failed to create transaction")?;
+
+    let initiator = macros::require_initiator!(&transaction, &headers, None::<Uri>);
+
+    let result = Journal::retrieve_accessible(&transaction, &journals_id, &initiator.user.id)
+        .await
+        .context("failed to retrieve default journal")?;
+
+    let Some(journal) = result else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+
+    auth::perm_check!(&transaction, initiator, journal, Scope::Entries, Ability::Update);
+
+    auth::archived_check!(journal);
+
+    auth::frozen_check!(&transaction, journal);
+
+    let result = Entry::retrieve_id(&transaction, &journal.id, &journal.users_id, &entries_id)
+        .await
+        .context("failed to retrieve journal entry")?;
+
+    let Some(entry) = result else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+
+    let result = Revision::retrieve(&transaction, &entry.id, &revision)
+        .await
+        .context("failed to retrieve entry revision")?;
+
+    let Some(found) = result else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+
+    if found.snapshot.date != entry.date && journal.settings.multi_entry == MultiEntry::Single {
+        let exists = Journal::date_in_use(&transaction, &journal.id, &found.snapshot.date, Some(&entry.id))
+            .await
+            .context("failed to check for existing entry date")?;
+
+        if exists {
+            return Ok(body::FieldError::new(
+                "date",
+                RestoreResult::DateExists
+            ).into_response());
+        }
+    }
+
+    Revision::record(&transaction, &entry.id, &initiator.user.id)
+        .await
+        .context("failed to record journal entry revision")?;
+
+    found.snapshot.apply(&transaction, &journal.id, &entry.id, &Utc::now())
+        .await
+        .context("failed to restore journal entry revision")?;
+
+    let result = EntryFull::retrieve_id(&transaction, &journal.id, &journal.users_id, &entry.id)
+        .await
+        .context("failed to retrieve restored journal entry")?;
+
+    transaction.commit()
+        .await
+        .context("failed to commit transaction")?;
+
+    let Some(restored) = result else {
+        return Err(error::Error::context("restored journal entry was not found"));
+    };
+
+    Ok(body::Json(restored).into_response())
+}
@@ -0,0 +1,404 @@
+use std::fmt::Write;
+
+use axum::body::Body;
+use axum::extract::{Path, Query};
+use axum::http::{StatusCode, HeaderMap};
+use axum::response::{IntoResponse, Response};
+use bytes::Bytes;
+use chrono::{DateTime, Days, NaiveDate, Utc};
+use futures::StreamExt;
+use serde::{Serialize, Deserialize};
+
+use crate::state;
+use crate::db;
+use crate::db::ids::{EntryUid, JournalId};
+use crate::error::{self, Context};
+use crate::journal::Journal;
+use crate::journal::feed::FeedToken;
+use crate::router::body;
+use crate::router::macros;
+use crate::sec::authn::session::Token;
+use crate::sec::authz::{Scope, Ability};
+
+use super::auth;
+
+/// the max number of octets allowed on a single line before it must be
+/// folded
+const LINE_LIMIT: usize = 75;
+
+#[derive(Debug, Deserialize)]
+pub struct JournalPath {
+    journals_id: JournalId,
+}
+
+/// the calendar component to use for each entry
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Component {
+    /// all day events that most calendar clients will display
+    #[default]
+    Event,
+
+    /// journal components that are meant for journal entries but are not
+    /// supported by all calendar clients
+    Journal,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct IcsQuery {
+    #[serde(default)]
+    component: Component,
+
+    /// the feed token of the journal for clients that cannot log in
+    token: Option<String>,
+}
+
+/// a feed token along with the token itself. only sent when the token is
+/// issued
+#[derive(Debug, Serialize)]
+pub struct FeedTokenSecret {
+    #[serde(flatten)]
+    feed: FeedToken,
+    token: String,
+}
+
+/// retrieves the journal for the initiator if they are the owner
+macro_rules! owned_journal {
+    ($conn:expr, $initiator:expr, $journals_id:expr) => {{
+        let result = Journal::retrieve_id($conn, $journals_id, &$initiator.user.id)
+            .await
+            .context("failed to retrieve journal")?;
+
+        let Some(journal) = result else {
+            return Ok(StatusCode::NOT_FOUND.into_response());
+        };
+
+        if journal.users_id != $initiator.user.id {
+            return Ok(StatusCode::UNAUTHORIZED.into_response());
+        }
+
+        journal
+    }};
+}
+
+/// escapes a text value as specified by RFC 5545
+fn escape_text(value: &str) -> String {
+    let mut rtn = String::with_capacity(value.len());
+
+    for ch in value.chars() {
+        match ch {
+            '\\' => rtn.push_str("\\\\"),
+            ';' => rtn.push_str("\\;"),
+            ',' => rtn.push_str("\\,"),
+            '\n' => rtn.push_str("\\n"),
+            '\r' => {}
+            _ => rtn.push(ch),
+        }
+    }
+
+    rtn
+}
+
+/// writes a content line to the buffer folding it if it is longer than the
+/// line limit
+fn write_line(buf: &mut String, line: &str) {
+    let mut count = 0;
+
+    for ch in line.chars() {
+        let len = ch.len_utf8();
+
+        if count + len > LINE_LIMIT {
+            buf.push_str("\r\n ");
+            count = 1;
+        }
+
+        buf.push(ch);
+        count += len;
+    }
+
+    buf.push_str("\r\n");
+}
+
+fn format_date(date: &NaiveDate) -> String {
+    date.format("%Y%m%d").to_string()
+}
+
+fn format_timestamp(timestamp: &DateTime<Utc>) -> String {
+    timestamp.format("%Y%m%dT%H%M%SZ").to_string()
+}
+
+/// the data from a single entry needed to create a calendar component
+struct IcsEntry {
+    uid: EntryUid,
+    date: NaiveDate,
+    title: Option<String>,
+    contents: Option<String>,
+    created: DateTime<Utc>,
+    updated: Option<DateTime<Utc>>,
+    tags: Vec<String>,
+}
+
+impl IcsEntry {
+    fn write(&self, buf: &mut String, component: &Component) {
+        let name = match component {
+            Component::Event => "VEVENT",
+            Component::Journal => "VJOURNAL",
+        };
+        let stamp = self.updated.as_ref().unwrap_or(&self.created);
+
+        write_line(buf, &format!("BEGIN:{name}"));
+        write_line(buf, &format!("UID:{}@tj2", self.uid));
+        write_line(buf, &format!("DTSTAMP:{}", format_timestamp(stamp)));
+        write_line(buf, &format!("CREATED:{}", format_timestamp(&self.created)));
+
+        if let Some(updated) = &self.updated {
+            write_line(buf, &format!("LAST-MODIFIED:{}", format_timestamp(updated)));
+        }
+
+        write_line(buf, &format!("DTSTART;VALUE=DATE:{}", format_date(&self.date)));
+
+        if let Component::Event = component {
+            if let Some(next) = self.date.checked_add_days(Days::new(1)) {
+                write_line(buf, &format!("DTEND;VALUE=DATE:{}", format_date(&next)));
+            }
+
+            write_line(buf, "TRANSP:TRANSPARENT");
+        }
+
+        if let Some(title) = &self.title {
+            write_line(buf, &format!("SUMMARY:{}", escape_text(title)));
+        }
+
+        if let Some(contents) = &self.contents {
+            write_line(buf, &format!("DESCRIPTION:{}", escape_text(contents)));
+        }
+
+        if !self.tags.is_empty() {
+            let mut categories = String::from("CATEGORIES:");
+
+            for (index, tag) in self.tags.iter().enumerate() {
+                if index > 0 {
+                    categories.push(',');
+                }
+
+                categories.push_str(&escape_text(tag));
+            }
+
+            write_line(buf, &categories);
+        }
+
+        write_line(buf, &format!("END:{name}"));
+    }
+}
+
+/// exports the entries of a journal as an iCalendar file
+///
+/// entries are streamed as they are retrieved from the database. tags are
+/// added as categories in the form of "key" or "key:value"
+///
+/// calendar clients that cannot log in can give the feed token of the
+/// journal with "?token=" instead of a session
+pub async fn export_ics(
+    state: state::SharedState,
+    headers: HeaderMap,
+    Path(JournalPath { journals_id }): Path<JournalPath>,
+    Query(IcsQuery { component, token }): Query<IcsQuery>,
+) -> Result<Response, error::Error> {
+    let conn = state.db_conn().await?;
+
+    let journal = if let Some(given) = token {
+        let Ok(token) = Token::from_base64(&given) else {
+            return Ok(StatusCode::UNAUTHORIZED.into_response());
+        };
+
+        let result = FeedToken::verify(&conn, &journals_id, &token)
+            .await
+            .context("failed to verify feed token")?;
+
+        let Some(feed) = result else {
+            return Ok(StatusCode::UNAUTHORIZED.into_response());
+        };
+
+        let result = Journal::retrieve_id(&conn, &journals_id, &feed.users_id)
+            .await
+            .context("failed to retrieve journal")?;
+
+        let Some(journal) = result else {
+            return Ok(StatusCode::NOT_FOUND.into_response());
+        };
+
+        journal
+    } else {
+        let initiator = macros::require_initiator!(&conn, &headers, None::<&'static str>);
+
+        let result = Journal::retrieve_accessible(&conn, &journals_id, &initiator.user.id)
+            .await
+            .context("failed to retrieve journal")?;
+
+        let Some(journal) = result else {
+            return Ok(StatusCode::NOT_FOUND.into_response());
+        };
+
+        auth::perm_check!(&conn, initiator, journal, Scope::Entries, Ability::Read);
+
+        journal
+    };
+
+    let params: db::ParamsArray<'_, 2> = [&journal.users_id, &journal.id];
+    let entries = conn.query_raw(
+        "\
+        select entries.uid, \
+               entries.entry_date, \
+               entries.title, \
+               entries.contents, \
+               entries.created, \
+               entries.updated, \
+               array( \
+                   select case when entry_tags.value is null \
+                       then entry_tags.key \
+                       else entry_tags.key || ':' || entry_tags.value \
+                   end \
+                   from entry_tags \
+                   where entry_tags.entries_id = entries.id \
+                   order by entry_tags.key \
+               ) as tags \
+        from entries \
+        where entries.users_id = $1 and \
+              entries.journals_id = $2 \
+        order by entries.entry_date",
+        params
+    )
+        .await
+        .context("failed to retrieve journal entries")?;
+
+    let mut header = String::new();
+    write_line(&mut header, "BEGIN:VCALENDAR");
+    write_line(&mut header, "VERSION:2.0");
+    write_line(&mut header, "PRODID:-//TJ2//Journal Export//EN");
+    write_line(&mut header, "CALSCALE:GREGORIAN");
+    write_line(&mut header, &format!("X-WR-CALNAME:{}", escape_text(&journal.name)));
+
+    if let Some(description) = &journal.description {
+        write_line(&mut header, &format!("X-WR-CALDESC:{}", escape_text(description)));
+    }
+
+    let mut footer = String::new();
+    write_line(&mut footer, "END:VCALENDAR");
+
+    // the connection is moved into the stream so that it is not returned to
+    // the pool until all the entries have been sent
+    let body_stream = entries.map(move |result| {
+        let _conn = &conn;
+        let record = result.context("failed to retrieve journal entry")?;
+
+        let entry = IcsEntry {
+            uid: record.get(0),
+            date: record.get(1),
+            title: record.get(2),
+            contents: record.get(3),
+            created: record.get(4),
+            updated: record.get(5),
+            tags: record.get(6),
+        };
+
+        let mut buf = String::new();
+        entry.write(&mut buf, &component);
+
+        Ok::<_, error::Error>(Bytes::from(buf))
+    });
+
+    let stream = futures::stream::once(async move { Ok(Bytes::from(header)) })
+        .chain(body_stream)
+        .chain(futures::stream::once(async move { Ok(Bytes::from(footer)) }));
+
+    let mut disposition = String::from("attachment; filename=\"");
+
+    for ch in journal.name.chars() {
+        if ch.is_ascii_alphanumeric() || ch == '-' || ch == '_' {
+            disposition.push(ch);
+        } else {
+            disposition.push('_');
+        }
+    }
+
+    write!(&mut disposition, ".ics\"")
+        .context("failed to create content disposition")?;
+
+    Response::builder()
+        .status(StatusCode::OK)
+        .header("content-type", "text/calendar; charset=utf-8")
+        .header("content-disposition", disposition)
+        .body(Body::from_stream(stream))
+        .context("failed to create ics response")
+}
+
+/// retrieves when the feed token of the journal was issued
+pub async fn retrieve_feed_token(
+    state: state::SharedState,
+    headers: HeaderMap,
+    Path(JournalPath { journals_id }): Path<JournalPath>,
+) -> Result<Response, error::Error> {
+    let conn = state.db_conn().await?;
+
+    let initiator = macros::require_initiator!(&conn, &headers, None::<&'static str>);
+
+    let journal = owned_journal!(&conn, initiator, &journals_id);
+
+    let result = FeedToken::retrieve(&conn, &journal.id)
+        .await
+        .context("failed to retrieve feed token")?;
+
+    let Some(feed) = result else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+
+    Ok(body::Json(feed).into_response())
+}
+
+/// issues a new feed token for the journal. any previous token stops working
+///
+/// the token is only included in this response
+pub async fn create_feed_token(
+    state: state::SharedState,
+    headers: HeaderMap,
+    Path(JournalPath { journals_id }): Path<JournalPath>,
+) -> Result<Response, error::Error> {
+    let conn = state.db_conn().await?;
+
+    let initiator = macros::require_initiator!(&conn, &headers, None::<&'static str>);
+
+    let journal = owned_journal!(&conn, initiator, &journals_id);
+
+    let (feed, token) = FeedToken::issue(&conn, &journal.id, &initiator.user.id).await?;
+
+    Ok((
+        StatusCode::CREATED,
+        body::Json(FeedTokenSecret {
+            feed,
+            token: token.as_base64(),
+        }),
+    ).into_response())
+}
+
+/// revokes the feed token of the journal
+pub async fn delete_feed_token(
+    state: state::SharedState,
+    headers: HeaderMap,
+    Path(JournalPath { journals_id }): Path<JournalPath>,
+) -> Result<Response, error::Error> {
+    let conn = state.db_conn().await?;
+
+    let initiator = macros::require_initiator!(&conn, &headers, None::<&'static str>);
+
+    let journal = owned_journal!(&conn, initiator, &journals_id);
+
+    let revoked = FeedToken::revoke(&conn, &journal.id)
+        .await
+        .context("failed to revoke feed token")?;
+
+    if revoked {
+        Ok(StatusCode::OK.into_response())
+    } else {
+        Ok(StatusCode::NOT_FOUND.into_response())
+    }
+}
@@ -0,0 +1,246 @@
+use axum::body::Body;
+use axum::extract::{Path, Query};
+use axum::http::{StatusCode, HeaderMap};
+use axum::response::{IntoResponse, Response};
+use serde::{Serialize, Deserialize};
+use tokio::io::AsyncWriteExt;
+
+use crate::state;
+use crate::db::ids::{ImportId, JournalId};
+use crate::error::{self, Context};
+use crate::fs::{self, InsufficientStorage};
+use crate::journal::Journal;
+use crate::journal::import::{self, ImportPreview, ImportSource, JournalImport};
+use crate::journal::upload::UploadPolicy;
+use crate::router::body;
+use crate::router::macros;
+use crate::sec::authz::{Scope, Ability};
+
+use super::auth;
+use super::files::{insufficient_storage_response, write_body};
+
+#[derive(Debug, Deserialize)]
+pub struct JournalPath {
+    journals_id: JournalId,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ImportPath {
+    journals_id: JournalId,
+    imports_id: ImportId,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ImportQuery {
+    source: ImportSource,
+
+    /// only reports the entries that would be created
+    #[serde(default)]
+    dry_run: bool,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "type")]
+pub enum ImportResult {
+    JournalEncrypted,
+    Preview(ImportPreview),
+    InvalidArchive {
+        message: String,
+    },
+}
+
+/// writes the request body to the given path. the file is removed if the
+/// body could not be written
+async fn write_upload(path: &std::path::Path, stream: Body) -> Result<(), error::Error> {
+    let mut file = tokio::fs::File::create(path)
+        .await
+        .context("failed to create import upload")?;
+
+    let written = match write_body(&mut file, stream, None).await {
+        Ok(_) => file.flush()
+            .await
+            .context("failed to flush import upload"),
+        Err(err) => Err(err),
+    };
+
+    if written.is_err() {
+        if let Err(err) = tokio::fs::remove_file(path).await {
+            error::log_prefix_error("failed to remove import upload", &err);
+        }
+    }
+
+    written
+}
+
+fn storage_full_response(state: &state::SharedState, expected: u64) -> Response {
+    tracing::warn!("import upload failed due to a full file system");
+
+    insufficient_storage_response(InsufficientStorage {
+        available: 0,
+        needed: expected,
+        reserve: state.storage().reserve(),
+    })
+}
+
+/// uploads an archive to be imported into the journal
+///
+/// the archive is stored until the imports job has added its entries to the
+/// journal. the progress of the import is available from the returned
+/// import. a dry run reads the archive right away and reports the entries
+/// that would be created without storing anything
+pub async fn create_import(
+    state: state::SharedState,
+    headers: HeaderMap,
+    Path(JournalPath { journals_id }): Path<JournalPath>,
+    Query(ImportQuery { source, dry_run }): Query<ImportQuery>,
+    stream: Body,
+) -> Result<Response, error::Error> {
+    let conn = state.db_conn().await?;
+
+    let initiator = macros::require_initiator!(&conn, &headers, None::<&'static str>);
+
+    let result = Journal::retrieve_accessible(&conn, &journals_id, &initiator.user.id)
+        .await
+        .context("failed to retrieve default journal")?;
+
+    let Some(journal) = result else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+
+    auth::perm_check!(&conn, initiator, journal, Scope::Entries, Ability::Create);
+
+    auth::archived_check!(journal);
+
+    auth::frozen_check!(&conn, journal);
+
+    // imported entries are plaintext which an end-to-end encrypted journal
+    // does not allow
+    if journal.e2e {
+        return Ok(body::FieldError::new(
+            "journals_id",
+            ImportResult::JournalEncrypted
+        ).into_response());
+    }
+
+    let expected = headers.get("content-length")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<u64>().ok())
+        .unwrap_or(0);
+
+    if let Some(details) = state.storage().check_space(expected).await? {
+        tracing::warn!(
+            available = details.available,
+            needed = details.needed,
+            "rejected import upload due to insufficient storage"
+        );
+
+        return Ok(insufficient_storage_response(details));
+    }
+
+    let journal_dir = state.storage().journal_dir(&journal);
+
+    journal_dir.create_imports_dir()
+        .await
+        .context("failed to create imports directory")?;
+
+    if dry_run {
+        let path = journal_dir.import_preview_path();
+
+        if let Err(err) = write_upload(&path, stream).await {
+            if fs::is_storage_full(&err) {
+                return Ok(storage_full_response(&state, expected));
+            }
+
+            return Err(error::Error::context_source(
+                "failed to write import upload",
+                err
+            ));
+        }
+
+        let policy = UploadPolicy::retrieve(&conn, state.upload(), &journal.id)
+            .await
+            .context("failed to retrieve journal upload policy")?;
+
+        let result = import::preview(
+            source,
+            path.clone(),
+            &import::FieldNames::from_state(&state),
+            &policy
+        ).await;
+
+        if let Err(err) = tokio::fs::remove_file(&path).await {
+            error::log_prefix_error("failed to remove import upload", &err);
+        }
+
+        return match result {
+            Ok(preview) => Ok(body::Json(ImportResult::Preview(preview)).into_response()),
+            Err(err) => Ok(body::FieldError::new(
+                "source",
+                ImportResult::InvalidArchive {
+                    message: err.to_string(),
+                }
+            ).into_response()),
+        };
+    }
+
+    let mut import = JournalImport::create(&conn, &journal.id, &initiator.user.id, source)
+        .await
+        .context("failed to create journal import")?;
+
+    let path = journal_dir.import_path(&import.id);
+
+    if let Err(err) = write_upload(&path, stream).await {
+        if let Err(err) = import.mark_failed(&conn, String::from("upload did not finish")).await {
+            error::log_prefix_error("failed to update journal import", &err);
+        }
+
+        if fs::is_storage_full(&err) {
+            return Ok(storage_full_response(&state, expected));
+        }
+
+        return Err(error::Error::context_source(
+            "failed to write import upload",
+            err
+        ));
+    }
+
+    import.mark_uploaded(&conn)
+        .await
+        .context("failed to update journal import")?;
+
+    Ok((
+        StatusCode::ACCEPTED,
+        body::Json(import),
+    ).into_response())
+}
+
+/// retrieves the progress of an import
+pub async fn retrieve_import(
+    state: state::SharedState,
+    headers: HeaderMap,
+    Path(ImportPath { journals_id, imports_id }): Path<ImportPath>,
+) -> Result<Response, error::Error> {
+    let conn = state.db_conn().await?;
+
+    let initiator = macros::require_initiator!(&conn, &headers, None::<&'static str>);
+
+    let result = Journal::retrieve_accessible(&conn, &journals_id, &initiator.user.id)
+        .await
+        .context("failed to retrieve default journal")?;
+
+    let Some(journal) = result else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+
+    auth::perm_check!(&conn, initiator, journal, Scope::Entries, Ability::Read);
+
+    let result = JournalImport::retrieve(&conn, &journal.id, &imports_id)
+        .await
+        .context("failed to retrieve journal import")?;
+
+    let Some(import) = result.filter(|import| import.users_id == initiator.user.id) else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+
+    Ok(body::Json(import).into_response())
+}
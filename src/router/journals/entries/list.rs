@@ -0,0 +1,150 @@
+//! server rendered pages of entries for browsers without javascript
+
+use axum::extract::{Path, Query};
+use axum::http::{StatusCode, Uri, HeaderMap};
+use axum::response::{IntoResponse, Response};
+use serde::{Serialize, Deserialize};
+use url::form_urlencoded;
+
+use crate::state;
+use crate::db::ids::JournalId;
+use crate::error::{self, Context};
+use crate::journal::{tag, Journal};
+use crate::router::body;
+use crate::router::macros;
+use crate::sec::authz::{Scope, Ability};
+use crate::user::preferences::{Preferences, RenderPreferences};
+
+use super::{auth, field_filters, field_params, EntryPartial, EntrySearch, Page};
+
+#[derive(Debug, Deserialize)]
+pub struct JournalPath {
+    journals_id: JournalId,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ListQuery {
+    page: Option<u32>,
+    size: Option<u32>,
+    tag: Option<String>,
+    #[serde(default)]
+    planned: bool,
+}
+
+#[derive(Debug, Serialize)]
+struct ListPage {
+    journal: String,
+    entries: Vec<EntryPartial>,
+    page: u32,
+    tag: Option<String>,
+    planned: bool,
+
+    /// the relative link to the previous page
+    prev: Option<String>,
+
+    /// the relative link to the next page
+    next: Option<String>,
+
+    #[serde(flatten)]
+    preferences: RenderPreferences,
+}
+
+/// creates the relative link to a page with the same filters
+fn page_href(query: &ListQuery, fields: &[String], page: &Page, number: u32) -> String {
+    let mut serializer = form_urlencoded::Serializer::new(String::new());
+
+    serializer.append_pair("page", &number.to_string());
+    serializer.append_pair("size", &page.size.to_string());
+
+    if let Some(tag) = &query.tag {
+        serializer.append_pair("tag", tag);
+    }
+
+    if query.planned {
+        serializer.append_pair("planned", "true");
+    }
+
+    for field in fields {
+        serializer.append_pair("field", field);
+    }
+
+    format!("list?{}", serializer.finish())
+}
+
+/// renders a page of entries as html
+///
+/// this is the fallback for browsers that cannot run the spa and uses the
+/// same search as [`super::retrieve_entries`]. pages are navigated with
+/// links so no scripts are required
+pub async fn retrieve_list(
+    state: state::SharedState,
+    uri: Uri,
+    headers: HeaderMap,
+    Path(JournalPath { journals_id }): Path<JournalPath>,
+    Query(query): Query<ListQuery>,
+) -> Result<Response, error::Error> {
+    let conn = state.db_conn().await?;
+
+    let initiator = macros::require_initiator!(&conn, &headers, Some(uri.clone()));
+
+    let result = Journal::retrieve_accessible(&conn, &journals_id, &initiator.user.id)
+        .await
+        .context("failed to retrieve default journal")?;
+
+    let Some(journal) = result else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+
+    auth::perm_check!(&conn, initiator, journal, Scope::Entries, Ability::Read);
+
+    let fields = match field_filters(&conn, &journal.id, &uri).await? {
+        Ok(valid) => valid,
+        Err(invalid) => return Ok(body::FieldError::new(
+            "field",
+            invalid
+        ).into_response()),
+    };
+    let given_fields = field_params(&uri);
+
+    let page = Page::new(query.page, query.size);
+    let mut entries = EntryPartial::search(&conn, &EntrySearch {
+        users_id: journal.users_id,
+        journals_id: journal.id,
+        planned: query.planned,
+        tag: query.tag.as_deref().and_then(tag::normalize_key),
+        fields,
+        text: None,
+        unread_by: None,
+        prompts_id: None,
+        subjects_id: None,
+        cursor: None,
+        page: Some(page),
+    }).await?;
+
+    let has_next = page.split_next(&mut entries);
+    let prev = (page.number > 1).then(|| page_href(&query, &given_fields, &page, page.number - 1));
+    let next = has_next.then(|| page_href(&query, &given_fields, &page, page.number + 1));
+
+    let preferences = Preferences::retrieve(&conn, &initiator.user.id)
+        .await
+        .context("failed to retrieve preferences")?;
+
+    let list = ListPage {
+        journal: journal.name,
+        entries,
+        page: page.number,
+        tag: query.tag,
+        planned: query.planned,
+        prev,
+        next,
+        preferences: preferences.render(),
+    };
+
+    let context = tera::Context::from_serialize(&list)
+        .context("failed to create entries list context")?;
+    let rendered = state.templates()
+        .render("pages/entries", &context)
+        .context("failed to render entries list page")?;
+
+    Ok(body::Html::new(rendered).into_response())
+}
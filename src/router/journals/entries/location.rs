@@ -0,0 +1,134 @@
+use axum::extract::Path;
+use axum::http::{StatusCode, HeaderMap};
+use axum::response::{IntoResponse, Response};
+use serde::{Serialize, Deserialize};
+
+use crate::state;
+use crate::db::ids::JournalId;
+use crate::error::{self, Context};
+use crate::journal::Journal;
+use crate::journal::weather::JournalLocation;
+use crate::router::body;
+use crate::router::macros;
+
+#[derive(Debug, Deserialize)]
+pub struct JournalPath {
+    journals_id: JournalId,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct LocationBody {
+    location: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "type")]
+pub enum LocationResult {
+    EmptyLocation,
+}
+
+/// retrieves the default location of the journal
+pub async fn retrieve_location(
+    state: state::SharedState,
+    headers: HeaderMap,
+    Path(JournalPath { journals_id }): Path<JournalPath>,
+) -> Result<Response, error::Error> {
+    let conn = state.db_conn().await?;
+
+    let initiator = macros::require_initiator!(&conn, &headers, None::<&'static str>);
+
+    let result = Journal::retrieve_id(&conn, &journals_id, &initiator.user.id)
+        .await
+        .context("failed to retrieve default journal")?;
+
+    let Some(journal) = result else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+
+    let result = JournalLocation::retrieve(&conn, &journal.id)
+        .await
+        .context("failed to retrieve journal location")?;
+
+    let Some(location) = result else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+
+    Ok(body::Json(location).into_response())
+}
+
+/// sets the location used for the weather of entries that do not have a
+/// location of their own
+///
+/// only the owner of the journal is allowed to set it
+pub async fn update_location(
+    state: state::SharedState,
+    headers: HeaderMap,
+    Path(JournalPath { journals_id }): Path<JournalPath>,
+    body::Json(json): body::Json<LocationBody>,
+) -> Result<Response, error::Error> {
+    let conn = state.db_conn().await?;
+
+    let initiator = macros::require_initiator!(&conn, &headers, None::<&'static str>);
+
+    let result = Journal::retrieve_id(&conn, &journals_id, &initiator.user.id)
+        .await
+        .context("failed to retrieve default journal")?;
+
+    let Some(journal) = result else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+
+    if journal.users_id != initiator.user.id {
+        return Ok(StatusCode::UNAUTHORIZED.into_response());
+    }
+
+    let location = json.location.trim();
+
+    if location.is_empty() {
+        return Ok(body::FieldError::new(
+            "location",
+            LocationResult::EmptyLocation
+        ).into_response());
+    }
+
+    let location = JournalLocation::set(&conn, &journal.id, location)
+        .await
+        .context("failed to update journal location")?;
+
+    Ok(body::Json(location).into_response())
+}
+
+/// removes the default location of the journal
+///
+/// only the owner of the journal is allowed to remove it
+pub async fn delete_location(
+    state: state::SharedState,
+    headers: HeaderMap,
+    Path(JournalPath { journals_id }): Path<JournalPath>,
+) -> Result<Response, error::Error> {
+    let conn = state.db_conn().await?;
+
+    let initiator = macros::require_initiator!(&conn, &headers, None::<&'static str>);
+
+    let result = Journal::retrieve_id(&conn, &journals_id, &initiator.user.id)
+        .await
+        .context("failed to retrieve default journal")?;
+
+    let Some(journal) = result else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+
+    if journal.users_id != initiator.user.id {
+        return Ok(StatusCode::UNAUTHORIZED.into_response());
+    }
+
+    let deleted = JournalLocation::delete(&conn, &journal.id)
+        .await
+        .context("failed to delete journal location")?;
+
+    if deleted {
+        Ok(StatusCode::NO_CONTENT.into_response())
+    } else {
+        Ok(StatusCode::NOT_FOUND.into_response())
+    }
+}
@@ -0,0 +1,241 @@
+use std::collections::HashMap;
+
+use axum::extract::Path;
+use axum::http::{StatusCode, Uri, HeaderMap};
+use axum::response::{IntoResponse, Response};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use chrono::{DateTime, NaiveDate, Utc};
+use futures::StreamExt;
+use serde::{Serialize, Deserialize};
+
+use crate::state;
+use crate::db::GenericClient;
+use crate::db::ids::{EntryId, JournalId};
+use crate::error::{self, Context};
+use crate::journal::custom_field;
+use crate::journal::markdown;
+use crate::journal::{CustomField, Entry, EntryTag, FileEntry, Journal};
+use crate::router::body;
+use crate::router::macros;
+use crate::sec::authz::{Scope, Ability};
+use crate::user::preferences::{Preferences, RenderPreferences};
+
+use super::auth;
+
+/// the largest image in bytes that will be inlined as a data uri. larger
+/// images are linked to instead
+const INLINE_MAX: i64 = 2 * 1024 * 1024;
+
+#[derive(Debug, Deserialize)]
+pub struct EntryPath {
+    journals_id: JournalId,
+    entries_id: EntryId,
+}
+
+#[derive(Debug, Serialize)]
+struct ReadingTag {
+    key: String,
+    value: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct ReadingField {
+    name: String,
+    value: String,
+}
+
+#[derive(Debug, Serialize)]
+struct ReadingFile {
+    name: String,
+    src: String,
+}
+
+#[derive(Debug, Serialize)]
+struct ReadingEntry {
+    journal: String,
+    date: NaiveDate,
+    title: Option<String>,
+    contents_html: Option<String>,
+    tags: Vec<ReadingTag>,
+    custom_fields: Vec<ReadingField>,
+    images: Vec<ReadingFile>,
+    files: Vec<ReadingFile>,
+    created: DateTime<Utc>,
+    updated: Option<DateTime<Utc>>,
+
+    #[serde(flatten)]
+    preferences: RenderPreferences,
+}
+
+/// creates a data uri for an image if it is small enough to be inlined
+async fn inline_image(
+    state: &state::SharedState,
+    conn: &impl GenericClient,
+    journal: &Journal,
+    file_entry: &FileEntry,
+) -> Result<Option<String>, error::Error> {
+    if file_entry.size > INLINE_MAX {
+        return Ok(None);
+    }
+
+    let file_path = state.storage()
+        .journal_file_entry(journal, file_entry.id);
+    let contents = tokio::fs::read(&file_path)
+        .await
+        .context("failed to read journal file entry")?;
+
+    let contents = if file_entry.encrypted {
+        state.storage()
+            .require_journal_key(conn, journal)
+            .await?
+            .decrypt_all(&contents)
+            .context("failed to decrypt journal file entry")?
+    } else {
+        contents
+    };
+
+    Ok(Some(format!(
+        "data:{};base64,{}",
+        file_entry.get_mime().essence_str(),
+        STANDARD.encode(contents)
+    )))
+}
+
+/// renders a self-contained html page of an entry for reading or printing
+///
+/// small images are inlined as data uris and everything else links back to
+/// the file entry. requires the session of the user to follow the links
+pub async fn retrieve_reading(
+    state: state::SharedState,
+    uri: Uri,
+    headers: HeaderMap,
+    Path(EntryPath { journals_id, entries_id }): Path<EntryPath>,
+) -> Result<Response, error::Error> {
+    let conn = state.db_conn().await?;
+
+    let initiator = macros::require_initiator!(&conn, &headers, Some(uri));
+
+    let result = Journal::retrieve_accessible(&conn, &journals_id, &initiator.user.id)
+        .await
+        .context("failed to retrieve default journal")?;
+
+    let Some(journal) = result else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+
+    auth::perm_check!(&conn, initiator, journal, Scope::Entries, Ability::Read);
+
+    // the server cannot read the contents of an encrypted entry so there is
+    // nothing to render
+    if journal.e2e {
+        return Ok(StatusCode::BAD_REQUEST.into_response());
+    }
+
+    let result = Entry::retrieve_id(&conn, &journal.id, &journal.users_id, &entries_id)
+        .await
+        .context("failed to retrieve journal entry")?;
+
+    let Some(entry) = result else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+
+    let tags = EntryTag::retrieve_entry(&conn, entry.id)
+        .await
+        .context("failed to retrieve entry tags")?
+        .into_iter()
+        .map(|tag| ReadingTag {
+            key: tag.key,
+            value: tag.value,
+        })
+        .collect();
+
+    let mut fields: HashMap<_, _> = HashMap::new();
+    let fields_stream = CustomField::retrieve_journal_stream(&conn, &journal.id)
+        .await
+        .context("failed to retrieve custom fields")?;
+
+    futures::pin_mut!(fields_stream);
+
+    while let Some(try_record) = fields_stream.next().await {
+        let record = try_record.context("failed to retrieve custom field")?;
+
+        fields.insert(record.id, (record.order, record.name, record.config));
+    }
+
+    let mut custom_fields: Vec<_> = custom_field::Entry::retrieve_entry(&conn, &entry.id)
+        .await
+        .context("failed to retrieve entry custom fields")?
+        .into_iter()
+        .filter_map(|field| fields.get(&field.custom_fields_id).map(|(order, name, config)| (
+            *order,
+            ReadingField {
+                name: name.clone(),
+                value: field.value.format(config),
+            }
+        )))
+        .collect();
+
+    custom_fields.sort_by(|(a_order, a), (b_order, b)| a_order.cmp(b_order)
+        .then_with(|| a.name.cmp(&b.name)));
+
+    let mut images = Vec::new();
+    let mut files = Vec::new();
+    let files_stream = FileEntry::retrieve_entry_stream(&conn, &entry.id)
+        .await
+        .context("failed to retrieve entry files")?;
+
+    futures::pin_mut!(files_stream);
+
+    while let Some(try_record) = files_stream.next().await {
+        let file_entry = try_record.context("failed to retrieve entry file")?;
+
+        // files that have not finished uploading have no contents yet
+        if file_entry.hash.is_none() {
+            continue;
+        }
+
+        let name = file_entry.name.clone()
+            .unwrap_or_else(|| file_entry.uid.to_string());
+        // relative to the reading page so that the workspace prefix is kept
+        let href = file_entry.id.to_string();
+
+        if file_entry.mime_type == "image" {
+            let src = inline_image(&state, &conn, &journal, &file_entry)
+                .await?
+                .unwrap_or(href);
+
+            images.push(ReadingFile { name, src });
+        } else {
+            files.push(ReadingFile { name, src: href });
+        }
+    }
+
+    let preferences = Preferences::retrieve(&conn, &initiator.user.id)
+        .await
+        .context("failed to retrieve preferences")?;
+
+    let reading = ReadingEntry {
+        journal: journal.name,
+        date: entry.date,
+        title: entry.title,
+        contents_html: entry.contents.as_deref().map(markdown::render_html),
+        tags,
+        custom_fields: custom_fields.into_iter()
+            .map(|(_, field)| field)
+            .collect(),
+        images,
+        files,
+        created: entry.created,
+        updated: entry.updated,
+        preferences: preferences.render(),
+    };
+
+    let context = tera::Context::from_serialize(&reading)
+        .context("failed to create reading context")?;
+    let page = state.templates()
+        .render("pages/reading", &context)
+        .context("failed to render reading page")?;
+
+    Ok(body::Html::new(page).into_response())
+}
@@ -0,0 +1,153 @@
+use axum::extract::Path;
+use axum::http::{StatusCode, HeaderMap};
+use axum::response::{IntoResponse, Response};
+use chrono::NaiveDate;
+use serde::{Serialize, Deserialize};
+
+use crate::state;
+use crate::db;
+use crate::db::ids::JournalId;
+use crate::error::{self, Context};
+use crate::journal::{DefaultView, Journal, JournalSettings, MultiEntry, WeekStart};
+use crate::router::body;
+use crate::router::macros;
+
+use super::upload_policy::{self, PolicyBody, PolicyDetails};
+
+#[derive(Debug, Deserialize)]
+pub struct JournalPath {
+    journals_id: JournalId,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SettingsDetails {
+    #[serde(flatten)]
+    settings: JournalSettings,
+
+    /// the upload limits of the journal. these are stored separately and
+    /// can also be changed with the upload policy routes
+    upload: PolicyDetails,
+}
+
+/// the settings to change. settings that are not given are left as is
+#[derive(Debug, Deserialize)]
+pub struct SettingsBody {
+    week_start: Option<WeekStart>,
+    default_view: Option<DefaultView>,
+    multi_entry: Option<MultiEntry>,
+    upload: Option<PolicyBody>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "type")]
+pub enum SettingsResult {
+    /// the journal cannot be limited to a single entry per date since these
+    /// dates already have more than one
+    SharedDates {
+        dates: Vec<NaiveDate>,
+    },
+}
+
+/// retrieves the settings of the journal
+pub async fn retrieve_settings(
+    state: state::SharedState,
+    headers: HeaderMap,
+    Path(JournalPath { journals_id }): Path<JournalPath>,
+) -> Result<Response, error::Error> {
+    let conn = state.db_conn().await?;
+
+    let initiator = macros::require_initiator!(&conn, &headers, None::<&'static str>);
+
+    let result = Journal::retrieve_id(&conn, &journals_id, &initiator.user.id)
+        .await
+        .context("failed to retrieve default journal")?;
+
+    let Some(journal) = result else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+
+    let upload = upload_policy::retrieve_details(&conn, state.upload(), &journal.id)
+        .await
+        .context("failed to retrieve journal upload policy")?;
+
+    Ok(body::Json(SettingsDetails {
+        settings: journal.settings,
+        upload,
+    }).into_response())
+}
+
+/// changes the given settings of the journal
+///
+/// only the owner of the journal is allowed to change them
+pub async fn update_settings(
+    state: state::SharedState,
+    tx: db::Tx,
+    headers: HeaderMap,
+    Path(JournalPath { journals_id }): Path<JournalPath>,
+    body::Json(json): body::Json<SettingsBody>,
+) -> Result<Response, error::Error> {
+    let initiator = macros::require_initiator!(&*tx, &headers, None::<&'static str>);
+
+    let result = Journal::retrieve_id(&*tx, &journals_id, &initiator.user.id)
+        .await
+        .context("failed to retrieve default journal")?;
+
+    let Some(mut journal) = result else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+
+    if journal.users_id != initiator.user.id {
+        return Ok(StatusCode::UNAUTHORIZED.into_response());
+    }
+
+    let mut settings = journal.settings.clone();
+
+    if let Some(week_start) = json.week_start {
+        settings.week_start = week_start;
+    }
+
+    if let Some(default_view) = json.default_view {
+        settings.default_view = default_view;
+    }
+
+    if let Some(multi_entry) = json.multi_entry {
+        if multi_entry == MultiEntry::Single && journal.settings.multi_entry != MultiEntry::Single {
+            let dates = Journal::retrieve_shared_dates(&*tx, &journal.id)
+                .await
+                .context("failed to retrieve shared entry dates")?;
+
+            if !dates.is_empty() {
+                return Ok(body::FieldError::new(
+                    "multi_entry",
+                    SettingsResult::SharedDates { dates }
+                ).into_response());
+            }
+        }
+
+        settings.multi_entry = multi_entry;
+    }
+
+    if let Some(upload) = json.upload {
+        let valid = match upload_policy::validate_policy(upload) {
+            Ok(valid) => valid,
+            Err(response) => return Ok(response),
+        };
+
+        valid.store(&*tx, &journal.id)
+            .await
+            .context("failed to update journal upload policy")?;
+    }
+
+    journal.update_settings(&*tx, settings)
+        .await
+        .context("failed to update journal settings")?;
+
+    let upload = upload_policy::retrieve_details(&*tx, state.upload(), &journal.id)
+        .await
+        .context("failed to retrieve journal upload policy")?;
+
+    Ok(body::Json(SettingsDetails {
+        settings: journal.settings,
+        upload,
+    }).into_response())
+}
@@ -0,0 +1,219 @@
+use std::collections::HashSet;
+
+use axum::extract::Path;
+use axum::http::{StatusCode, HeaderMap};
+use axum::response::{IntoResponse, Response};
+use serde::{Serialize, Deserialize};
+
+use crate::state;
+use crate::db;
+use crate::db::ids::{JournalId, UserId};
+use crate::error::{self, Context};
+use crate::journal::Journal;
+use crate::journal::share::{JournalShare, Preset, SharePermission};
+use crate::router::body;
+use crate::router::macros;
+use crate::sec::authz::{self, Scope, Ability};
+use crate::user::User;
+
+#[derive(Debug, Deserialize)]
+pub struct JournalPath {
+    journals_id: JournalId,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SharePath {
+    journals_id: JournalId,
+    users_id: UserId,
+}
+
+/// the permissions to give a shared user. a preset is used before the list
+/// of permissions if both are given
+#[derive(Debug, Deserialize)]
+pub struct ShareBody {
+    preset: Option<Preset>,
+    permissions: Option<Vec<SharePermission>>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "type")]
+pub enum ShareResult {
+    UserNotFound,
+    OwnerNotShareable,
+    NoPermissions,
+    NotShareable {
+        permissions: Vec<SharePermission>,
+    },
+}
+
+/// retrieves the journal if the initiator owns it and has the given ability
+/// for journals
+macro_rules! owned_journal {
+    ($conn:expr, $initiator:expr, $journals_id:expr, $ability:expr) => {{
+        let perm_check = authz::has_permission(
+            $conn,
+            $initiator.user.id,
+            Scope::Journals,
+            $ability
+        )
+            .await
+            .context("failed to retrieve permission for user")?;
+
+        if !perm_check {
+            return Ok(StatusCode::UNAUTHORIZED.into_response());
+        }
+
+        let result = Journal::retrieve_id($conn, $journals_id, &$initiator.user.id)
+            .await
+            .context("failed to retrieve journal")?;
+
+        let Some(journal) = result else {
+            return Ok(StatusCode::NOT_FOUND.into_response());
+        };
+
+        journal
+    }};
+}
+
+/// retrieves the users that the journal is shared with
+pub async fn retrieve_shares(
+    state: state::SharedState,
+    headers: HeaderMap,
+    Path(JournalPath { journals_id }): Path<JournalPath>,
+) -> Result<Response, error::Error> {
+    let conn = state.db_conn().await?;
+
+    let initiator = macros::require_initiator!(&conn, &headers, None::<&'static str>);
+
+    let journal = owned_journal!(&conn, initiator, &journals_id, Ability::Read);
+
+    let shares = JournalShare::retrieve_journal(&conn, &journal.id)
+        .await
+        .context("failed to retrieve journal shares")?;
+
+    Ok(body::Json(shares).into_response())
+}
+
+pub async fn retrieve_share(
+    state: state::SharedState,
+    headers: HeaderMap,
+    Path(SharePath { journals_id, users_id }): Path<SharePath>,
+) -> Result<Response, error::Error> {
+    let conn = state.db_conn().await?;
+
+    let initiator = macros::require_initiator!(&conn, &headers, None::<&'static str>);
+
+    let journal = owned_journal!(&conn, initiator, &journals_id, Ability::Read);
+
+    let result = JournalShare::retrieve(&conn, &journal.id, &users_id)
+        .await
+        .context("failed to retrieve journal share")?;
+
+    let Some(share) = result else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+
+    Ok(body::Json(share).into_response())
+}
+
+/// shares the journal with a user or replaces the permissions of a user the
+/// journal is already shared with
+pub async fn update_share(
+    tx: db::Tx,
+    headers: HeaderMap,
+    Path(SharePath { journals_id, users_id }): Path<SharePath>,
+    body::Json(json): body::Json<ShareBody>,
+) -> Result<Response, error::Error> {
+    let initiator = macros::require_initiator!(&*tx, &headers, None::<&'static str>);
+
+    let journal = owned_journal!(&*tx, initiator, &journals_id, Ability::Update);
+
+    if users_id == journal.users_id {
+        return Ok(body::FieldError::new(
+            "users_id",
+            ShareResult::OwnerNotShareable
+        ).into_response());
+    }
+
+    let found = User::retrieve_id(&*tx, users_id)
+        .await
+        .context("failed to retrieve user")?;
+
+    if found.is_none() {
+        return Ok(body::FieldError::new(
+            "users_id",
+            ShareResult::UserNotFound
+        ).into_response());
+    }
+
+    let (field, given) = match (json.preset, json.permissions) {
+        (Some(preset), _) => ("preset", preset.permissions()),
+        (None, Some(permissions)) => ("permissions", permissions),
+        (None, None) => ("permissions", Vec::new()),
+    };
+
+    let mut seen = HashSet::new();
+    let mut permissions = Vec::new();
+    let mut invalid = Vec::new();
+
+    for permission in given {
+        if !permission.is_shareable() {
+            invalid.push(permission);
+        } else if seen.insert(permission.clone()) {
+            permissions.push(permission);
+        }
+    }
+
+    if !invalid.is_empty() {
+        return Ok(body::FieldError::new(
+            field,
+            ShareResult::NotShareable {
+                permissions: invalid
+            }
+        ).into_response());
+    }
+
+    // removing every permission is done by deleting the share
+    if permissions.is_empty() {
+        return Ok(body::FieldError::new(
+            field,
+            ShareResult::NoPermissions
+        ).into_response());
+    }
+
+    JournalShare::set(&*tx, &journal, &users_id, permissions)
+        .await
+        .context("failed to update journal share")?;
+
+    let share = JournalShare::retrieve(&*tx, &journal.id, &users_id)
+        .await
+        .context("failed to retrieve journal share")?
+        .context("journal share not found after update")?;
+
+    Ok(body::Json(share).into_response())
+}
+
+/// stops sharing the journal with a user
+pub async fn delete_share(
+    tx: db::Tx,
+    headers: HeaderMap,
+    Path(SharePath { journals_id, users_id }): Path<SharePath>,
+) -> Result<Response, error::Error> {
+    let initiator = macros::require_initiator!(&*tx, &headers, None::<&'static str>);
+
+    let journal = owned_journal!(&*tx, initiator, &journals_id, Ability::Update);
+
+    let result = JournalShare::retrieve(&*tx, &journal.id, &users_id)
+        .await
+        .context("failed to retrieve journal share")?;
+
+    let Some(share) = result else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+
+    share.delete(&*tx)
+        .await
+        .context("failed to delete journal share")?;
+
+    Ok(StatusCode::NO_CONTENT.into_response())
+}
@@ -0,0 +1,240 @@
+use axum::extract::{Path, Query};
+use axum::http::{StatusCode, Uri, HeaderMap};
+use axum::response::{IntoResponse, Response};
+use chrono::NaiveDate;
+use serde::{Serialize, Deserialize};
+
+use crate::state;
+use crate::db::ids::{JournalId, CustomFieldId};
+use crate::error::{self, Context};
+use crate::journal::{Journal, CustomField};
+use crate::journal::custom_field::unit::{Unit, UnitSystem};
+use crate::journal::stats::{
+    Bucket,
+    DateRange,
+    MonthCount,
+    TagCount,
+    Streaks,
+    FieldStats,
+    TrendPoint,
+};
+use crate::router::body;
+use crate::router::macros;
+use crate::sec::authz::{Scope, Ability};
+use crate::user::preferences::Preferences;
+
+use super::auth;
+
+#[derive(Debug, Deserialize)]
+pub struct JournalPath {
+    journals_id: JournalId,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CustomFieldPath {
+    journals_id: JournalId,
+    custom_fields_id: CustomFieldId,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct StatsQuery {
+    from: Option<NaiveDate>,
+    to: Option<NaiveDate>,
+
+    /// converts custom field values with units to the given measurement
+    /// system. defaults to the unit system preference of the user
+    units: Option<UnitSystem>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "type")]
+pub enum StatsResult {
+    InvalidRange,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TrendQuery {
+    from: Option<NaiveDate>,
+    to: Option<NaiveDate>,
+    #[serde(default)]
+    bucket: Bucket,
+
+    /// converts the values to the given measurement system if the field has
+    /// a unit. defaults to the unit system preference of the user
+    units: Option<UnitSystem>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "type")]
+pub enum TrendResult {
+    InvalidRange,
+    NotNumeric,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Trend {
+    /// the unit of the values if the field has one
+    unit: Option<Unit>,
+    points: Vec<TrendPoint>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct JournalStats {
+    months: Vec<MonthCount>,
+
+    /// the total words of all the entries in the range
+    words: i64,
+    streaks: Streaks,
+    tags: Vec<TagCount>,
+    custom_fields: Vec<FieldStats>,
+}
+
+/// retrieves aggregate statistics for the entries of a journal
+///
+/// the optional range is inclusive and applies to all of the statistics
+pub async fn retrieve_stats(
+    state: state::SharedState,
+    uri: Uri,
+    headers: HeaderMap,
+    Path(JournalPath { journals_id }): Path<JournalPath>,
+    Query(StatsQuery { from, to, units }): Query<StatsQuery>,
+) -> Result<Response, error::Error> {
+    let conn = state.db_conn().await?;
+
+    let initiator = macros::require_initiator!(&conn, &headers, Some(uri));
+
+    let result = Journal::retrieve_accessible(&conn, &journals_id, &initiator.user.id)
+        .await
+        .context("failed to retrieve default journal")?;
+
+    let Some(journal) = result else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+
+    auth::perm_check!(&conn, initiator, journal, Scope::Entries, Ability::Read);
+
+    if let (Some(from), Some(to)) = (&from, &to) {
+        if from > to {
+            return Ok(body::FieldError::new(
+                "from",
+                StatsResult::InvalidRange
+            ).into_response());
+        }
+    }
+
+    let preferences = Preferences::retrieve(&conn, &initiator.user.id)
+        .await
+        .context("failed to retrieve preferences")?;
+
+    let range = DateRange { from, to };
+    let today = to.unwrap_or_else(|| preferences.today());
+    let units = units.or(preferences.unit_system);
+
+    let months = MonthCount::retrieve(&conn, &journal.id, &range)
+        .await
+        .context("failed to retrieve monthly entry counts")?;
+    let streaks = Streaks::retrieve(&conn, &journal.id, &range, &today)
+        .await
+        .context("failed to retrieve entry streaks")?;
+    let tags = TagCount::retrieve(&conn, &journal.id, &range)
+        .await
+        .context("failed to retrieve tag counts")?;
+    let mut custom_fields = FieldStats::retrieve(&conn, &journal.id, &range)
+        .await
+        .context("failed to retrieve custom field stats")?;
+
+    if let Some(system) = units {
+        for field in &mut custom_fields {
+            field.convert_units(system);
+        }
+    }
+
+    let words = months.iter().map(|month| month.words).sum();
+
+    Ok(body::Json(JournalStats {
+        months,
+        words,
+        streaks,
+        tags,
+        custom_fields,
+    }).into_response())
+}
+
+/// retrieves the values of a numeric custom field grouped into daily,
+/// weekly, or monthly buckets
+pub async fn retrieve_trend(
+    state: state::SharedState,
+    uri: Uri,
+    headers: HeaderMap,
+    Path(CustomFieldPath { journals_id, custom_fields_id }): Path<CustomFieldPath>,
+    Query(TrendQuery { from, to, bucket, units }): Query<TrendQuery>,
+) -> Result<Response, error::Error> {
+    let conn = state.db_conn().await?;
+
+    let initiator = macros::require_initiator!(&conn, &headers, Some(uri));
+
+    let result = Journal::retrieve_accessible(&conn, &journals_id, &initiator.user.id)
+        .await
+        .context("failed to retrieve default journal")?;
+
+    let Some(journal) = result else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+
+    auth::perm_check!(&conn, initiator, journal, Scope::Entries, Ability::Read);
+
+    let result = CustomField::retrieve(&conn, &journal.id, &custom_fields_id)
+        .await
+        .context("failed to retrieve custom field")?;
+
+    let Some(field) = result else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+
+    if !field.config.is_numeric() {
+        return Ok(body::FieldError::new(
+            "custom_fields_id",
+            TrendResult::NotNumeric
+        ).into_response());
+    }
+
+    if let (Some(from), Some(to)) = (&from, &to) {
+        if from > to {
+            return Ok(body::FieldError::new(
+                "from",
+                TrendResult::InvalidRange
+            ).into_response());
+        }
+    }
+
+    let range = DateRange { from, to };
+
+    let mut points = TrendPoint::retrieve(&conn, &journal.id, &field.id, &range, bucket, journal.settings.week_start)
+        .await
+        .context("failed to retrieve custom field trend")?;
+
+    let units = match units {
+        Some(units) => Some(units),
+        None => Preferences::retrieve(&conn, &initiator.user.id)
+            .await
+            .context("failed to retrieve preferences")?
+            .unit_system,
+    };
+
+    let mut unit = field.config.unit();
+
+    if let (Some(from), Some(system)) = (unit, units) {
+        let range = field.config.is_range();
+
+        for point in &mut points {
+            point.convert_units(from, system, range);
+        }
+
+        unit = Some(from.in_system(system));
+    }
+
+    Ok(body::Json(Trend {
+        unit,
+        points,
+    }).into_response())
+}
@@ -0,0 +1,397 @@
+use axum::extract::{Path, Query};
+use axum::http::{StatusCode, HeaderMap};
+use axum::response::{IntoResponse, Response};
+use chrono::NaiveDate;
+use serde::{Serialize, Deserialize};
+
+use crate::state;
+use crate::db;
+use crate::db::ids::{JournalId, SubjectId};
+use crate::error::{self, Context};
+use crate::journal::Journal;
+use crate::journal::mention::{self, MentionCount, Subject, SubjectKind};
+use crate::journal::stats::{Bucket, DateRange};
+use crate::router::body;
+use crate::router::macros;
+use crate::sec::authz::{Scope, Ability};
+
+use super::{auth, EntryPartial, EntrySearch};
+
+#[derive(Debug, Deserialize)]
+pub struct JournalPath {
+    journals_id: JournalId,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SubjectPath {
+    journals_id: JournalId,
+    subjects_id: SubjectId,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SubjectsQuery {
+    /// only retrieves subjects of the given kind
+    kind: Option<SubjectKind>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SubjectEntriesQuery {
+    /// includes entries for dates that have not arrived yet
+    #[serde(default)]
+    planned: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CountsQuery {
+    from: Option<NaiveDate>,
+    to: Option<NaiveDate>,
+    #[serde(default)]
+    bucket: Bucket,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct NewSubjectBody {
+    kind: SubjectKind,
+    name: String,
+    #[serde(default)]
+    aliases: Vec<String>,
+    description: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateSubjectBody {
+    kind: Option<SubjectKind>,
+    name: Option<String>,
+    aliases: Option<Vec<String>>,
+
+    /// null will remove the description
+    #[serde(default, deserialize_with = "crate::serde::nested_opt")]
+    description: Option<Option<String>>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "type")]
+pub enum SubjectResult {
+    NameEmpty,
+    NameExists,
+    InvalidRange,
+}
+
+/// retrieves the journal for the initiator if they are allowed to read the
+/// entries of it
+macro_rules! readable_journal {
+    ($conn:expr, $initiator:expr, $journals_id:expr) => {{
+        let result = Journal::retrieve_accessible($conn, $journals_id, &$initiator.user.id)
+            .await
+            .context("failed to retrieve journal")?;
+
+        let Some(journal) = result else {
+            return Ok(StatusCode::NOT_FOUND.into_response());
+        };
+
+        auth::perm_check!($conn, $initiator, journal, Scope::Entries, Ability::Read);
+
+        journal
+    }};
+}
+
+/// retrieves the journal for the initiator if they are the owner
+macro_rules! owned_journal {
+    ($conn:expr, $initiator:expr, $journals_id:expr) => {{
+        let result = Journal::retrieve_id($conn, $journals_id, &$initiator.user.id)
+            .await
+            .context("failed to retrieve journal")?;
+
+        let Some(journal) = result else {
+            return Ok(StatusCode::NOT_FOUND.into_response());
+        };
+
+        if journal.users_id != $initiator.user.id {
+            return Ok(StatusCode::UNAUTHORIZED.into_response());
+        }
+
+        journal
+    }};
+}
+
+/// trims the given aliases and drops any that are empty, repeated, or the
+/// same as the name
+fn clean_aliases(name: &str, aliases: Vec<String>) -> Vec<String> {
+    let mut rtn: Vec<String> = Vec::with_capacity(aliases.len());
+
+    for alias in aliases {
+        let trimmed = alias.trim();
+
+        if trimmed.is_empty() ||
+            trimmed.eq_ignore_ascii_case(name) ||
+            rtn.iter().any(|known| known.eq_ignore_ascii_case(trimmed)) {
+            continue;
+        }
+
+        rtn.push(trimmed.to_owned());
+    }
+
+    rtn
+}
+
+/// checks that the name is not empty and not in use by another subject of
+/// the same kind
+async fn validate_name(
+    conn: &impl db::GenericClient,
+    journal: &Journal,
+    kind: SubjectKind,
+    name: &str,
+    ignore: Option<&SubjectId>,
+) -> Result<Result<(), SubjectResult>, error::Error> {
+    if name.is_empty() {
+        return Ok(Err(SubjectResult::NameEmpty));
+    }
+
+    let exists = Subject::name_exists(conn, &journal.id, kind, name, ignore)
+        .await
+        .context("failed to check for existing subject name")?;
+
+    if exists {
+        Ok(Err(SubjectResult::NameExists))
+    } else {
+        Ok(Ok(()))
+    }
+}
+
+/// retrieves the people and places of the journal along with the number of
+/// entries that mention them
+pub async fn retrieve_subjects(
+    state: state::SharedState,
+    headers: HeaderMap,
+    Path(JournalPath { journals_id }): Path<JournalPath>,
+    Query(SubjectsQuery { kind }): Query<SubjectsQuery>,
+) -> Result<Response, error::Error> {
+    let conn = state.db_conn().await?;
+
+    let initiator = macros::require_initiator!(&conn, &headers, None::<&'static str>);
+
+    let journal = readable_journal!(&conn, initiator, &journals_id);
+
+    let subjects = Subject::retrieve_journal(&conn, &journal.id, kind)
+        .await
+        .context("failed to retrieve journal subjects")?;
+
+    Ok(body::Json(subjects).into_response())
+}
+
+/// creates a person or place for the journal. existing entries that mention
+/// the name or aliases in their contents are linked to it
+pub async fn create_subject(
+    tx: db::Tx,
+    headers: HeaderMap,
+    Path(JournalPath { journals_id }): Path<JournalPath>,
+    body::Json(json): body::Json<NewSubjectBody>,
+) -> Result<Response, error::Error> {
+    let initiator = macros::require_initiator!(&*tx, &headers, None::<&'static str>);
+
+    let journal = owned_journal!(&*tx, initiator, &journals_id);
+
+    let name = json.name.trim().to_owned();
+
+    if let Err(result) = validate_name(&*tx, &journal, json.kind, &name, None).await? {
+        return Ok(body::FieldError::new("name", result).into_response());
+    }
+
+    let aliases = clean_aliases(&name, json.aliases);
+    let description = json.description.filter(|desc| !desc.trim().is_empty());
+
+    let subject = Subject::create(&*tx, &journal.id, json.kind, name, aliases, description)
+        .await
+        .context("failed to create journal subject")?;
+
+    mention::sync_subject(&*tx, &subject)
+        .await
+        .context("failed to sync subject mentions")?;
+
+    Ok((
+        StatusCode::CREATED,
+        body::Json(subject)
+    ).into_response())
+}
+
+pub async fn retrieve_subject(
+    state: state::SharedState,
+    headers: HeaderMap,
+    Path(SubjectPath { journals_id, subjects_id }): Path<SubjectPath>,
+) -> Result<Response, error::Error> {
+    let conn = state.db_conn().await?;
+
+    let initiator = macros::require_initiator!(&conn, &headers, None::<&'static str>);
+
+    let journal = readable_journal!(&conn, initiator, &journals_id);
+
+    let result = Subject::retrieve_id(&conn, &journal.id, &subjects_id)
+        .await
+        .context("failed to retrieve journal subject")?;
+
+    let Some(subject) = result else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+
+    Ok(body::Json(subject).into_response())
+}
+
+/// changes the given fields of a person or place. fields that are not given
+/// are left as is
+pub async fn update_subject(
+    tx: db::Tx,
+    headers: HeaderMap,
+    Path(SubjectPath { journals_id, subjects_id }): Path<SubjectPath>,
+    body::Json(json): body::Json<UpdateSubjectBody>,
+) -> Result<Response, error::Error> {
+    let initiator = macros::require_initiator!(&*tx, &headers, None::<&'static str>);
+
+    let journal = owned_journal!(&*tx, initiator, &journals_id);
+
+    let result = Subject::retrieve_id(&*tx, &journal.id, &subjects_id)
+        .await
+        .context("failed to retrieve journal subject")?;
+
+    let Some(mut subject) = result else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+
+    if let Some(kind) = json.kind {
+        subject.kind = kind;
+    }
+
+    if let Some(name) = json.name {
+        subject.name = name.trim().to_owned();
+    }
+
+    if let Err(result) = validate_name(&*tx, &journal, subject.kind, &subject.name, Some(&subject.id)).await? {
+        return Ok(body::FieldError::new("name", result).into_response());
+    }
+
+    if let Some(aliases) = json.aliases {
+        subject.aliases = aliases;
+    }
+
+    subject.aliases = clean_aliases(&subject.name, std::mem::take(&mut subject.aliases));
+
+    if let Some(description) = json.description {
+        subject.description = description.filter(|desc| !desc.trim().is_empty());
+    }
+
+    subject.update(&*tx)
+        .await
+        .context("failed to update journal subject")?;
+
+    mention::sync_subject(&*tx, &subject)
+        .await
+        .context("failed to sync subject mentions")?;
+
+    Ok(body::Json(subject).into_response())
+}
+
+/// deletes a person or place. entries that mention it are kept
+pub async fn delete_subject(
+    tx: db::Tx,
+    headers: HeaderMap,
+    Path(SubjectPath { journals_id, subjects_id }): Path<SubjectPath>,
+) -> Result<Response, error::Error> {
+    let initiator = macros::require_initiator!(&*tx, &headers, None::<&'static str>);
+
+    let journal = owned_journal!(&*tx, initiator, &journals_id);
+
+    let result = Subject::retrieve_id(&*tx, &journal.id, &subjects_id)
+        .await
+        .context("failed to retrieve journal subject")?;
+
+    let Some(subject) = result else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+
+    subject.delete(&*tx)
+        .await
+        .context("failed to delete journal subject")?;
+
+    Ok(StatusCode::OK.into_response())
+}
+
+/// retrieves the entries that mention a person or place ordered by the most
+/// recent date
+///
+/// the entries list of the journal can also be filtered with "subject" if
+/// paging is needed
+pub async fn retrieve_subject_entries(
+    state: state::SharedState,
+    headers: HeaderMap,
+    Path(SubjectPath { journals_id, subjects_id }): Path<SubjectPath>,
+    Query(SubjectEntriesQuery { planned }): Query<SubjectEntriesQuery>,
+) -> Result<Response, error::Error> {
+    let conn = state.db_conn().await?;
+
+    let initiator = macros::require_initiator!(&conn, &headers, None::<&'static str>);
+
+    let journal = readable_journal!(&conn, initiator, &journals_id);
+
+    let result = Subject::retrieve_id(&conn, &journal.id, &subjects_id)
+        .await
+        .context("failed to retrieve journal subject")?;
+
+    let Some(subject) = result else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+
+    let entries = EntryPartial::search(&conn, &EntrySearch {
+        users_id: journal.users_id,
+        journals_id: journal.id,
+        planned,
+        tag: None,
+        fields: Vec::new(),
+        text: None,
+        unread_by: None,
+        prompts_id: None,
+        subjects_id: Some(subject.id),
+        cursor: None,
+        page: None,
+    }).await?;
+
+    Ok(body::Json(entries).into_response())
+}
+
+/// retrieves the number of entries that mention a person or place for each
+/// bucket in the optional range
+pub async fn retrieve_subject_counts(
+    state: state::SharedState,
+    headers: HeaderMap,
+    Path(SubjectPath { journals_id, subjects_id }): Path<SubjectPath>,
+    Query(CountsQuery { from, to, bucket }): Query<CountsQuery>,
+) -> Result<Response, error::Error> {
+    let conn = state.db_conn().await?;
+
+    let initiator = macros::require_initiator!(&conn, &headers, None::<&'static str>);
+
+    let journal = readable_journal!(&conn, initiator, &journals_id);
+
+    if let (Some(from), Some(to)) = (&from, &to) {
+        if from > to {
+            return Ok(body::FieldError::new(
+                "from",
+                SubjectResult::InvalidRange
+            ).into_response());
+        }
+    }
+
+    let result = Subject::retrieve_id(&conn, &journal.id, &subjects_id)
+        .await
+        .context("failed to retrieve journal subject")?;
+
+    let Some(subject) = result else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+
+    let range = DateRange { from, to };
+
+    let counts = MentionCount::retrieve(&conn, &subject.id, &range, bucket, journal.settings.week_start)
+        .await
+        .context("failed to retrieve subject mention counts")?;
+
+    Ok(body::Json(counts).into_response())
+}
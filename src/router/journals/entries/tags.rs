@@ -0,0 +1,49 @@
+use axum::extract::Path;
+use axum::http::{StatusCode, Uri, HeaderMap};
+use axum::response::{IntoResponse, Response};
+use serde::Deserialize;
+
+use crate::state;
+use crate::db::ids::JournalId;
+use crate::error::{self, Context};
+use crate::journal::Journal;
+use crate::journal::tag::TagNode;
+use crate::router::body;
+use crate::router::macros;
+use crate::sec::authz::{Scope, Ability};
+
+use super::auth;
+
+#[derive(Debug, Deserialize)]
+pub struct JournalPath {
+    journals_id: JournalId,
+}
+
+/// retrieves the tags of a journal as a hierarchy of their "/" separated
+/// segments
+pub async fn retrieve_tags(
+    state: state::SharedState,
+    uri: Uri,
+    headers: HeaderMap,
+    Path(JournalPath { journals_id }): Path<JournalPath>,
+) -> Result<Response, error::Error> {
+    let conn = state.db_conn().await?;
+
+    let initiator = macros::require_initiator!(&conn, &headers, Some(uri));
+
+    let result = Journal::retrieve_accessible(&conn, &journals_id, &initiator.user.id)
+        .await
+        .context("failed to retrieve default journal")?;
+
+    let Some(journal) = result else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+
+    auth::perm_check!(&conn, initiator, journal, Scope::Entries, Ability::Read);
+
+    let tags = TagNode::retrieve_journal(&conn, &journal.id)
+        .await
+        .context("failed to retrieve journal tags")?;
+
+    Ok(body::Json(tags).into_response())
+}
@@ -0,0 +1,74 @@
+use axum::extract::Path;
+use axum::http::{StatusCode, HeaderMap};
+use axum::response::{IntoResponse, Response};
+use chrono::Utc;
+use serde::Deserialize;
+
+use crate::state;
+use crate::db::ids::{EntryId, EntryTaskId, JournalId};
+use crate::error::{self, Context};
+use crate::journal::{Entry, Journal};
+use crate::journal::task::EntryTask;
+use crate::router::body;
+use crate::router::macros;
+use crate::sec::authz::{Scope, Ability};
+
+use super::auth;
+
+#[derive(Debug, Deserialize)]
+pub struct TaskPath {
+    journals_id: JournalId,
+    entries_id: EntryId,
+    tasks_id: EntryTaskId,
+}
+
+/// flips the done state of a single task without updating the rest of the
+/// entry
+pub async fn toggle_task(
+    state: state::SharedState,
+    headers: HeaderMap,
+    Path(TaskPath { journals_id, entries_id, tasks_id }): Path<TaskPath>,
+) -> Result<Response, error::Error> {
+    let mut conn = state.db_conn().await?;
+    let transaction = conn.transaction()
+        .await
+        .context("failed to create transaction")?;
+
+    let initiator = macros::require_initiator!(&transaction, &headers, None::<&'static str>);
+
+    let result = Journal::retrieve_accessible(&transaction, &journals_id, &initiator.user.id)
+        .await
+        .context("failed to retrieve default journal")?;
+
+    let Some(journal) = result else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+
+    auth::perm_check!(&transaction, initiator, journal, Scope::Entries, Ability::Update);
+
+    auth::archived_check!(journal);
+
+    auth::frozen_check!(&transaction, journal);
+
+    let result = Entry::retrieve_id(&transaction, &journal.id, &journal.users_id, &entries_id)
+        .await
+        .context("failed to retrieve journal entry")?;
+
+    let Some(entry) = result else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+
+    let result = EntryTask::toggle(&transaction, &entry.id, &tasks_id, &Utc::now())
+        .await
+        .context("failed to toggle entry task")?;
+
+    let Some(task) = result else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+
+    transaction.commit()
+        .await
+        .context("failed to commit transaction")?;
+
+    Ok(body::Json(task).into_response())
+}
@@ -0,0 +1,235 @@
+use axum::extract::Path;
+use axum::http::{StatusCode, HeaderMap};
+use axum::response::{IntoResponse, Response};
+use serde::{Serialize, Deserialize};
+
+use crate::config;
+use crate::state;
+use crate::db;
+use crate::db::ids::JournalId;
+use crate::error::{self, Context};
+use crate::journal::Journal;
+use crate::journal::upload::{self, JournalUploadPolicy, UploadPolicy};
+use crate::router::body;
+use crate::router::macros;
+
+#[derive(Debug, Deserialize)]
+pub struct JournalPath {
+    journals_id: JournalId,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PolicyDetails {
+    /// the limits set by the owner of the journal
+    journal: Option<JournalUploadPolicy>,
+
+    /// the limits that are enforced for the journal
+    effective: UploadPolicy,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PolicyBody {
+    max_file_size: Option<i64>,
+    allowed_mime_types: Option<Vec<String>>,
+    max_files_per_entry: Option<i32>,
+    strip_metadata: Option<bool>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "type")]
+pub enum PolicyResult {
+    InvalidMaxFileSize,
+    InvalidMaxFilesPerEntry,
+    InvalidMimeTypes {
+        mime_types: Vec<String>,
+    },
+}
+
+/// the limits of a [`PolicyBody`] after they have been checked
+#[derive(Debug)]
+pub struct ValidPolicy {
+    max_file_size: Option<i64>,
+    allowed_mime_types: Option<Vec<String>>,
+    max_files_per_entry: Option<i32>,
+    strip_metadata: Option<bool>,
+}
+
+impl ValidPolicy {
+    /// stores the limits for the journal, replacing the previous ones
+    pub async fn store(
+        self,
+        conn: &impl db::GenericClient,
+        journals_id: &JournalId,
+    ) -> Result<JournalUploadPolicy, db::PgError> {
+        JournalUploadPolicy::set(
+            conn,
+            journals_id,
+            self.max_file_size,
+            self.allowed_mime_types,
+            self.max_files_per_entry,
+            self.strip_metadata,
+        ).await
+    }
+}
+
+/// checks the given limits. the response to send is returned if any of
+/// them are invalid
+pub fn validate_policy(json: PolicyBody) -> Result<ValidPolicy, Response> {
+    if json.max_file_size.is_some_and(|size| size <= 0) {
+        return Err(body::FieldError::new(
+            "max_file_size",
+            PolicyResult::InvalidMaxFileSize
+        ).into_response());
+    }
+
+    if json.max_files_per_entry.is_some_and(|count| count < 0) {
+        return Err(body::FieldError::new(
+            "max_files_per_entry",
+            PolicyResult::InvalidMaxFilesPerEntry
+        ).into_response());
+    }
+
+    let allowed_mime_types = json.allowed_mime_types.map(|given| given.into_iter()
+        .map(|mime_type| mime_type.trim().to_lowercase())
+        .filter(|mime_type| !mime_type.is_empty())
+        .collect::<Vec<String>>());
+
+    if let Some(allowed) = &allowed_mime_types {
+        let invalid: Vec<String> = allowed.iter()
+            .filter(|mime_type| !upload::valid_mime_pattern(mime_type))
+            .cloned()
+            .collect();
+
+        if !invalid.is_empty() {
+            return Err(body::FieldError::new(
+                "allowed_mime_types",
+                PolicyResult::InvalidMimeTypes {
+                    mime_types: invalid
+                }
+            ).into_response());
+        }
+    }
+
+    Ok(ValidPolicy {
+        max_file_size: json.max_file_size,
+        allowed_mime_types,
+        max_files_per_entry: json.max_files_per_entry,
+        strip_metadata: json.strip_metadata,
+    })
+}
+
+/// retrieves the upload limits of the journal along with the limits that
+/// are enforced after applying the config
+pub async fn retrieve_details(
+    conn: &impl db::GenericClient,
+    config: &config::Upload,
+    journals_id: &JournalId,
+) -> Result<PolicyDetails, db::PgError> {
+    let policy = JournalUploadPolicy::retrieve(conn, journals_id).await?;
+    let effective = UploadPolicy::resolve(config, policy.as_ref());
+
+    Ok(PolicyDetails {
+        journal: policy,
+        effective,
+    })
+}
+
+/// retrieves the upload limits of the journal along with the limits that
+/// are enforced after applying the config
+pub async fn retrieve_policy(
+    state: state::SharedState,
+    headers: HeaderMap,
+    Path(JournalPath { journals_id }): Path<JournalPath>,
+) -> Result<Response, error::Error> {
+    let conn = state.db_conn().await?;
+
+    let initiator = macros::require_initiator!(&conn, &headers, None::<&'static str>);
+
+    let result = Journal::retrieve_id(&conn, &journals_id, &initiator.user.id)
+        .await
+        .context("failed to retrieve default journal")?;
+
+    let Some(journal) = result else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+
+    let details = retrieve_details(&conn, state.upload(), &journal.id)
+        .await
+        .context("failed to retrieve journal upload policy")?;
+
+    Ok(body::Json(details).into_response())
+}
+
+/// sets the upload limits of the journal. a limit that is not given uses
+/// the limit from the config
+///
+/// only the owner of the journal is allowed to set them
+pub async fn update_policy(
+    state: state::SharedState,
+    headers: HeaderMap,
+    Path(JournalPath { journals_id }): Path<JournalPath>,
+    body::Json(json): body::Json<PolicyBody>,
+) -> Result<Response, error::Error> {
+    let conn = state.db_conn().await?;
+
+    let initiator = macros::require_initiator!(&conn, &headers, None::<&'static str>);
+
+    let result = Journal::retrieve_id(&conn, &journals_id, &initiator.user.id)
+        .await
+        .context("failed to retrieve default journal")?;
+
+    let Some(journal) = result else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+
+    if journal.users_id != initiator.user.id {
+        return Ok(StatusCode::UNAUTHORIZED.into_response());
+    }
+
+    let valid = match validate_policy(json) {
+        Ok(valid) => valid,
+        Err(response) => return Ok(response),
+    };
+
+    let policy = valid.store(&conn, &journal.id)
+        .await
+        .context("failed to update journal upload policy")?;
+
+    Ok(body::Json(policy).into_response())
+}
+
+/// removes the upload limits of the journal so that only the config limits
+/// apply
+///
+/// only the owner of the journal is allowed to remove them
+pub async fn delete_policy(
+    state: state::SharedState,
+    headers: HeaderMap,
+    Path(JournalPath { journals_id }): Path<JournalPath>,
+) -> Result<Response, error::Error> {
+    let conn = state.db_conn().await?;
+
+    let initiator = macros::require_initiator!(&conn, &headers, None::<&'static str>);
+
+    let result = Journal::retrieve_id(&conn, &journals_id, &initiator.user.id)
+        .await
+        .context("failed to retrieve default journal")?;
+
+    let Some(journal) = result else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+
+    if journal.users_id != initiator.user.id {
+        return Ok(StatusCode::UNAUTHORIZED.into_response());
+    }
+
+    let deleted = JournalUploadPolicy::delete(&conn, &journal.id)
+        .await
+        .context("failed to delete journal upload policy")?;
+
+    if deleted {
+        Ok(StatusCode::NO_CONTENT.into_response())
+    } else {
+        Ok(StatusCode::NOT_FOUND.into_response())
+    }
+}
@@ -0,0 +1,62 @@
+use axum::extract::Path;
+use axum::http::{StatusCode, HeaderMap};
+use axum::response::{IntoResponse, Response};
+use serde::{Serialize, Deserialize};
+
+use crate::db;
+use crate::db::ids::{EntryId, JournalId};
+use crate::error::{self, Context};
+use crate::journal::{view, Journal};
+use crate::router::body;
+use crate::router::macros;
+use crate::sec::authz::{Scope, Ability};
+
+use super::auth;
+
+#[derive(Debug, Deserialize)]
+pub struct JournalPath {
+    journals_id: JournalId,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct MarkReadBody {
+    /// the entries to mark as read. every entry of the journal is marked if
+    /// not specified
+    entries: Option<Vec<EntryId>>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MarkedRead {
+    marked: u64,
+}
+
+/// marks entries of the journal as read by the initiator
+pub async fn mark_read(
+    tx: db::Tx,
+    headers: HeaderMap,
+    Path(JournalPath { journals_id }): Path<JournalPath>,
+    body::Json(json): body::Json<MarkReadBody>,
+) -> Result<Response, error::Error> {
+    let initiator = macros::require_initiator!(&*tx, &headers, None::<&'static str>);
+
+    let result = Journal::retrieve_accessible(&*tx, &journals_id, &initiator.user.id)
+        .await
+        .context("failed to retrieve journal")?;
+
+    let Some(journal) = result else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+
+    auth::perm_check!(&*tx, initiator, journal, Scope::Entries, Ability::Read);
+
+    let marked = view::mark_viewed(
+        &*tx,
+        &initiator.user.id,
+        &journal.id,
+        json.entries.as_deref()
+    )
+        .await
+        .context("failed to mark entries as read")?;
+
+    Ok(body::Json(MarkedRead { marked }).into_response())
+}
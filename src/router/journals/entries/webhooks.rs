@@ -0,0 +1,432 @@
+use std::collections::HashSet;
+
+use axum::extract::Path;
+use axum::http::{StatusCode, HeaderMap};
+use axum::response::{IntoResponse, Response};
+use futures::StreamExt;
+use serde::{Serialize, Deserialize};
+
+use crate::state;
+use crate::db::ids::{JournalId, WebhookId, WebhookDeliveryId};
+use crate::error::{self, Context};
+use crate::journal::Journal;
+use crate::journal::webhook::{self, Delivery, DeliveryStatus, Webhook, WebhookEvent};
+use crate::router::body;
+use crate::router::macros;
+use crate::sec::network;
+
+/// the number of deliveries returned in the history of a webhook
+const DELIVERY_HISTORY: i64 = 100;
+
+/// the max number of bytes of the payload included in the history of a
+/// webhook
+const PAYLOAD_SNAPSHOT: usize = 2 * 1024;
+
+#[derive(Debug, Deserialize)]
+pub struct JournalPath {
+    journals_id: JournalId,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct WebhookPath {
+    journals_id: JournalId,
+    webhooks_id: WebhookId,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DeliveryPath {
+    journals_id: JournalId,
+    webhooks_id: WebhookId,
+    deliveries_id: WebhookDeliveryId,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct NewWebhookBody {
+    url: String,
+    events: Vec<WebhookEvent>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateWebhookBody {
+    url: Option<String>,
+    events: Option<Vec<WebhookEvent>>,
+    enabled: Option<bool>,
+
+    /// generates a new signing secret for the webhook. the current secret
+    /// is still used to sign deliveries for a time after
+    #[serde(default)]
+    rotate_secret: bool,
+}
+
+/// a webhook along with its signing secret. only sent when the secret is
+/// created
+#[derive(Debug, Serialize)]
+pub struct WebhookSecret {
+    #[serde(flatten)]
+    webhook: Webhook,
+    secret: String,
+}
+
+/// a delivery in the history of a webhook
+#[derive(Debug, Serialize)]
+pub struct DeliveryRecord {
+    #[serde(flatten)]
+    delivery: Delivery,
+
+    /// the json of the payload. cut at [`PAYLOAD_SNAPSHOT`] bytes
+    payload: String,
+    payload_truncated: bool,
+}
+
+impl From<Delivery> for DeliveryRecord {
+    fn from(delivery: Delivery) -> Self {
+        let mut payload = delivery.payload.to_string();
+        let payload_truncated = payload.len() > PAYLOAD_SNAPSHOT;
+
+        if payload_truncated {
+            let mut end = PAYLOAD_SNAPSHOT;
+
+            while !payload.is_char_boundary(end) {
+                end -= 1;
+            }
+
+            payload.truncate(end);
+        }
+
+        Self {
+            delivery,
+            payload,
+            payload_truncated,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "type")]
+pub enum DeliveryResult {
+    NotFailed,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "type")]
+pub enum WebhookResult {
+    InvalidUrl,
+    UrlNotAllowed,
+    NoEvents,
+}
+
+/// checks that the url is an absolute http or https url and that the host
+/// does not resolve to a private address unless allowed by the network
+/// config
+async fn validate_url(state: &state::SharedState, given: &str) -> Result<(), WebhookResult> {
+    let Ok(url) = url::Url::parse(given) else {
+        return Err(WebhookResult::InvalidUrl);
+    };
+
+    if url.scheme() != "http" && url.scheme() != "https" {
+        return Err(WebhookResult::InvalidUrl);
+    }
+
+    network::check_url(&state.network().outbound_allow, &url)
+        .await
+        .map_err(|_| WebhookResult::UrlNotAllowed)
+}
+
+fn unique_events(events: Vec<WebhookEvent>) -> Vec<WebhookEvent> {
+    let mut seen = HashSet::new();
+
+    events.into_iter()
+        .filter(|event| seen.insert(*event))
+        .collect()
+}
+
+/// retrieves the journal for the initiator if they are the owner
+macro_rules! owned_journal {
+    ($conn:expr, $initiator:expr, $journals_id:expr) => {{
+        let result = Journal::retrieve_id($conn, $journals_id, &$initiator.user.id)
+            .await
+            .context("failed to retrieve default journal")?;
+
+        let Some(journal) = result else {
+            return Ok(StatusCode::NOT_FOUND.into_response());
+        };
+
+        if journal.users_id != $initiator.user.id {
+            return Ok(StatusCode::UNAUTHORIZED.into_response());
+        }
+
+        journal
+    }};
+}
+
+pub async fn retrieve_webhooks(
+    state: state::SharedState,
+    headers: HeaderMap,
+    Path(JournalPath { journals_id }): Path<JournalPath>,
+) -> Result<Response, error::Error> {
+    let conn = state.db_conn().await?;
+
+    let initiator = macros::require_initiator!(&conn, &headers, None::<&'static str>);
+
+    let journal = owned_journal!(&conn, initiator, &journals_id);
+
+    let stream = Webhook::retrieve_journal_stream(&conn, &journal.id)
+        .await
+        .context("failed to retrieve journal webhooks")?;
+
+    futures::pin_mut!(stream);
+
+    let mut found = Vec::new();
+
+    while let Some(try_record) = stream.next().await {
+        found.push(try_record.context("failed to retrieve journal webhook")?);
+    }
+
+    Ok(body::Json(found).into_response())
+}
+
+/// creates a webhook for the journal
+///
+/// the signing secret is only included in this response
+pub async fn create_webhook(
+    state: state::SharedState,
+    headers: HeaderMap,
+    Path(JournalPath { journals_id }): Path<JournalPath>,
+    body::Json(json): body::Json<NewWebhookBody>,
+) -> Result<Response, error::Error> {
+    let conn = state.db_conn().await?;
+
+    let initiator = macros::require_initiator!(&conn, &headers, None::<&'static str>);
+
+    let journal = owned_journal!(&conn, initiator, &journals_id);
+
+    let url = json.url.trim().to_owned();
+
+    if let Err(result) = validate_url(&state, &url).await {
+        return Ok(body::FieldError::new("url", result).into_response());
+    }
+
+    let events = unique_events(json.events);
+
+    if events.is_empty() {
+        return Ok(body::FieldError::new(
+            "events",
+            WebhookResult::NoEvents
+        ).into_response());
+    }
+
+    let secret = webhook::gen_secret()
+        .context("failed to generate webhook secret")?;
+
+    let webhook = Webhook::create(
+        &conn,
+        &journal.id,
+        &initiator.user.id,
+        url,
+        events,
+        secret.clone()
+    )
+        .await
+        .context("failed to create journal webhook")?;
+
+    Ok((
+        StatusCode::CREATED,
+        body::Json(WebhookSecret { webhook, secret }),
+    ).into_response())
+}
+
+pub async fn retrieve_webhook(
+    state: state::SharedState,
+    headers: HeaderMap,
+    Path(WebhookPath { journals_id, webhooks_id }): Path<WebhookPath>,
+) -> Result<Response, error::Error> {
+    let conn = state.db_conn().await?;
+
+    let initiator = macros::require_initiator!(&conn, &headers, None::<&'static str>);
+
+    let journal = owned_journal!(&conn, initiator, &journals_id);
+
+    let result = Webhook::retrieve(&conn, &journal.id, &webhooks_id)
+        .await
+        .context("failed to retrieve journal webhook")?;
+
+    let Some(webhook) = result else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+
+    Ok(body::Json(webhook).into_response())
+}
+
+/// updates a webhook of the journal
+///
+/// if the secret is rotated then the new secret is included in the response
+pub async fn update_webhook(
+    state: state::SharedState,
+    headers: HeaderMap,
+    Path(WebhookPath { journals_id, webhooks_id }): Path<WebhookPath>,
+    body::Json(json): body::Json<UpdateWebhookBody>,
+) -> Result<Response, error::Error> {
+    let conn = state.db_conn().await?;
+
+    let initiator = macros::require_initiator!(&conn, &headers, None::<&'static str>);
+
+    let journal = owned_journal!(&conn, initiator, &journals_id);
+
+    let result = Webhook::retrieve(&conn, &journal.id, &webhooks_id)
+        .await
+        .context("failed to retrieve journal webhook")?;
+
+    let Some(mut webhook) = result else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+
+    if let Some(url) = json.url {
+        let url = url.trim().to_owned();
+
+        if let Err(result) = validate_url(&state, &url).await {
+            return Ok(body::FieldError::new("url", result).into_response());
+        }
+
+        webhook.url = url;
+    }
+
+    if let Some(events) = json.events {
+        let events = unique_events(events);
+
+        if events.is_empty() {
+            return Ok(body::FieldError::new(
+                "events",
+                WebhookResult::NoEvents
+            ).into_response());
+        }
+
+        webhook.events = events;
+    }
+
+    if let Some(enabled) = json.enabled {
+        webhook.enabled = enabled;
+    }
+
+    if json.rotate_secret {
+        let secret = webhook::gen_secret()
+            .context("failed to generate webhook secret")?;
+
+        webhook.rotate_secret(secret);
+    }
+
+    webhook.update(&conn)
+        .await
+        .context("failed to update journal webhook")?;
+
+    if json.rotate_secret {
+        let secret = webhook.secret.clone();
+
+        Ok(body::Json(WebhookSecret { webhook, secret }).into_response())
+    } else {
+        Ok(body::Json(webhook).into_response())
+    }
+}
+
+pub async fn delete_webhook(
+    state: state::SharedState,
+    headers: HeaderMap,
+    Path(WebhookPath { journals_id, webhooks_id }): Path<WebhookPath>,
+) -> Result<Response, error::Error> {
+    let conn = state.db_conn().await?;
+
+    let initiator = macros::require_initiator!(&conn, &headers, None::<&'static str>);
+
+    let journal = owned_journal!(&conn, initiator, &journals_id);
+
+    let result = Webhook::retrieve(&conn, &journal.id, &webhooks_id)
+        .await
+        .context("failed to retrieve journal webhook")?;
+
+    let Some(webhook) = result else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+
+    webhook.delete(&conn)
+        .await
+        .context("failed to delete journal webhook")?;
+
+    Ok(StatusCode::NO_CONTENT.into_response())
+}
+
+/// retrieves the most recent deliveries of a webhook with the status,
+/// latency, and response of the last attempt along with a snapshot of the
+/// payload
+pub async fn retrieve_deliveries(
+    state: state::SharedState,
+    headers: HeaderMap,
+    Path(WebhookPath { journals_id, webhooks_id }): Path<WebhookPath>,
+) -> Result<Response, error::Error> {
+    let conn = state.db_conn().await?;
+
+    let initiator = macros::require_initiator!(&conn, &headers, None::<&'static str>);
+
+    let journal = owned_journal!(&conn, initiator, &journals_id);
+
+    let result = Webhook::retrieve(&conn, &journal.id, &webhooks_id)
+        .await
+        .context("failed to retrieve journal webhook")?;
+
+    let Some(webhook) = result else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+
+    let deliveries: Vec<DeliveryRecord> = Delivery::retrieve_webhook(&conn, &webhook.id, DELIVERY_HISTORY)
+        .await
+        .context("failed to retrieve webhook deliveries")?
+        .into_iter()
+        .map(DeliveryRecord::from)
+        .collect();
+
+    Ok(body::Json(deliveries).into_response())
+}
+
+/// queues a failed delivery to be sent again with the same payload. the
+/// delivery is signed with the current secret of the webhook when sent
+pub async fn redeliver(
+    state: state::SharedState,
+    headers: HeaderMap,
+    Path(DeliveryPath { journals_id, webhooks_id, deliveries_id }): Path<DeliveryPath>,
+) -> Result<Response, error::Error> {
+    let conn = state.db_conn().await?;
+
+    let initiator = macros::require_initiator!(&conn, &headers, None::<&'static str>);
+
+    let journal = owned_journal!(&conn, initiator, &journals_id);
+
+    let result = Webhook::retrieve(&conn, &journal.id, &webhooks_id)
+        .await
+        .context("failed to retrieve journal webhook")?;
+
+    let Some(webhook) = result else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+
+    let result = Delivery::retrieve(&conn, &webhook.id, &deliveries_id)
+        .await
+        .context("failed to retrieve webhook delivery")?;
+
+    let Some(mut delivery) = result else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+
+    if delivery.status != DeliveryStatus::Failed {
+        return Ok((
+            StatusCode::BAD_REQUEST,
+            body::Json(DeliveryResult::NotFailed)
+        ).into_response());
+    }
+
+    delivery.redeliver(&conn)
+        .await
+        .context("failed to queue webhook redelivery")?;
+
+    Ok((
+        StatusCode::ACCEPTED,
+        body::Json(DeliveryRecord::from(delivery))
+    ).into_response())
+}
@@ -0,0 +1,316 @@
+//! the websocket endpoint for live journal updates
+//!
+//! once connected a client sends "subscribe" and "unsubscribe" messages with
+//! the id of a journal. the permissions of the user are checked when
+//! subscribing and the [`LiveEvent`]s of every subscribed journal are sent as
+//! they happen. if the client falls behind, a "lagged" message is sent with
+//! the number of events that were missed so the client can retrieve the
+//! journal again
+//!
+//! the session and the subscribed journals are checked again every
+//! [`REVALIDATE_INTERVAL`]. journals the user can no longer read are
+//! unsubscribed and the socket is closed with a "session_ended" message once
+//! the session is logged out, expires, or the user is disabled
+//!
+//! [`LiveEvent`]: crate::journal::live::LiveEvent
+
+use std::collections::HashSet;
+use std::time::Duration;
+
+use axum::extract::ws::{Message, WebSocket, WebSocketUpgrade};
+use axum::http::{header, Uri, HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
+use serde::{Serialize, Deserialize};
+use tokio::sync::broadcast::error::RecvError;
+use tokio::time::{interval_at, Instant};
+
+use crate::state;
+use crate::db;
+use crate::db::ids::JournalId;
+use crate::error::{self, Context};
+use crate::journal::Journal;
+use crate::router::macros;
+use crate::sec::authn::{Initiator, InitiatorError};
+use crate::sec::authz::{self, Scope, Ability};
+
+/// how often the session of a connection and the journals it is subscribed
+/// to are checked again
+const REVALIDATE_INTERVAL: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ClientMessage {
+    Subscribe {
+        journals_id: JournalId,
+    },
+    Unsubscribe {
+        journals_id: JournalId,
+    },
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ServerMessage {
+    Subscribed {
+        journals_id: JournalId,
+    },
+    Unsubscribed {
+        journals_id: JournalId,
+    },
+    JournalNotFound {
+        journals_id: JournalId,
+    },
+    PermissionDenied {
+        journals_id: JournalId,
+    },
+    InvalidMessage,
+    Lagged {
+        missed: u64,
+    },
+    SessionEnded,
+    ServerError,
+}
+
+/// upgrades the request of an authenticated user to a websocket connection
+pub async fn handle(
+    state: state::SharedState,
+    headers: HeaderMap,
+    ws: WebSocketUpgrade,
+) -> Result<Response, error::Error> {
+    if !origin_allowed(&state, &headers) {
+        return Ok(StatusCode::FORBIDDEN.into_response());
+    }
+
+    let conn = state.db_conn().await?;
+
+    let initiator = macros::require_initiator!(&conn, &headers, None::<Uri>);
+
+    drop(conn);
+
+    Ok(ws.on_upgrade(move |socket| connection(state, initiator, socket)))
+}
+
+/// checks the origin of the upgrade request since browsers will send the
+/// session cookie with a websocket request from any site
+///
+/// the origin must be the same as the host of the request or one of the
+/// allowed cors origins. "*" is not accepted since it cannot be used with
+/// credentials. requests without an origin are not from a browser and are
+/// allowed
+fn origin_allowed(state: &state::SharedState, headers: &HeaderMap) -> bool {
+    let Some(origin) = headers.get(header::ORIGIN) else {
+        return true;
+    };
+
+    let Ok(origin) = origin.to_str() else {
+        return false;
+    };
+
+    if let Some(cors) = state.cors() {
+        if cors.allowed_origins.iter().any(|allowed| allowed == origin) {
+            return true;
+        }
+    }
+
+    let Some(host) = headers.get(header::HOST).and_then(|host| host.to_str().ok()) else {
+        return false;
+    };
+
+    origin.split_once("://")
+        .is_some_and(|(_, authority)| authority.eq_ignore_ascii_case(host))
+}
+
+async fn send<T>(socket: &mut WebSocket, msg: &T) -> bool
+where
+    T: Serialize
+{
+    let text = match serde_json::to_string(msg) {
+        Ok(text) => text,
+        Err(err) => {
+            error::log_prefix_error("failed to serialize websocket message", &err);
+
+            return true;
+        }
+    };
+
+    socket.send(Message::Text(text)).await.is_ok()
+}
+
+async fn connection(state: state::SharedState, mut initiator: Initiator, mut socket: WebSocket) {
+    let mut events = state.live().subscribe();
+    let mut subscribed = HashSet::new();
+    let mut revalidate = interval_at(Instant::now() + REVALIDATE_INTERVAL, REVALIDATE_INTERVAL);
+
+    loop {
+        let sent = tokio::select! {
+            received = socket.recv() => {
+                let Some(Ok(msg)) = received else {
+                    break;
+                };
+
+                match msg {
+                    Message::Text(text) => {
+                        let reply = match serde_json::from_str(&text) {
+                            Ok(msg) => handle_message(&state, &initiator, &mut subscribed, msg)
+                                .await
+                                .unwrap_or_else(|err| {
+                                    error::log_prefix_error("failed to handle websocket message", &err);
+
+                                    ServerMessage::ServerError
+                                }),
+                            Err(_) => ServerMessage::InvalidMessage,
+                        };
+
+                        send(&mut socket, &reply).await
+                    }
+                    Message::Close(_) => break,
+                    _ => true,
+                }
+            }
+            event = events.recv() => match event {
+                Ok(event) => if subscribed.contains(event.journals_id()) {
+                    send(&mut socket, &*event).await
+                } else {
+                    true
+                },
+                Err(RecvError::Lagged(missed)) => send(&mut socket, &ServerMessage::Lagged {
+                    missed
+                }).await,
+                Err(RecvError::Closed) => break,
+            },
+            _ = revalidate.tick() => match revalidate_connection(&state, &mut initiator, &mut subscribed).await {
+                Ok(Some(dropped)) => {
+                    let mut sent = true;
+
+                    for msg in dropped {
+                        if !send(&mut socket, &msg).await {
+                            sent = false;
+
+                            break;
+                        }
+                    }
+
+                    sent
+                }
+                Ok(None) => {
+                    if send(&mut socket, &ServerMessage::SessionEnded).await {
+                        let _ = socket.send(Message::Close(None)).await;
+                    }
+
+                    break;
+                }
+                Err(err) => {
+                    // the connection is kept and checked again on the next
+                    // tick
+                    error::log_prefix_error("failed to revalidate websocket connection", &err);
+
+                    true
+                }
+            }
+        };
+
+        if !sent {
+            break;
+        }
+    }
+}
+
+/// checks that the user can still read the entries of the journal. returns
+/// the message to send if they cannot
+async fn check_access(
+    conn: &impl db::GenericClient,
+    initiator: &Initiator,
+    journals_id: &JournalId,
+) -> Result<Option<ServerMessage>, error::Error> {
+    let result = Journal::retrieve_accessible(conn, journals_id, &initiator.user.id)
+        .await
+        .context("failed to retrieve journal")?;
+
+    let Some(journal) = result else {
+        return Ok(Some(ServerMessage::JournalNotFound { journals_id: *journals_id }));
+    };
+
+    let perm_check = if journal.users_id == initiator.user.id {
+        authz::has_permission(
+            conn,
+            initiator.user.id,
+            Scope::Entries,
+            Ability::Read
+        )
+            .await
+            .context("failed to retrieve permission for user")?
+    } else {
+        authz::has_permission_ref(
+            conn,
+            initiator.user.id,
+            Scope::Entries,
+            Ability::Read,
+            journal.id
+        )
+            .await
+            .context("failed to retrieve permission for user")?
+    };
+
+    if !perm_check {
+        return Ok(Some(ServerMessage::PermissionDenied { journals_id: *journals_id }));
+    }
+
+    Ok(None)
+}
+
+/// retrieves the session of the connection again and drops any subscribed
+/// journals that the user can no longer read. returns the messages for the
+/// dropped journals or none if the session is no longer valid
+async fn revalidate_connection(
+    state: &state::SharedState,
+    initiator: &mut Initiator,
+    subscribed: &mut HashSet<JournalId>,
+) -> Result<Option<Vec<ServerMessage>>, error::Error> {
+    let conn = state.db_conn().await?;
+
+    *initiator = match initiator.refresh(&conn).await {
+        Ok(refreshed) => refreshed,
+        Err(InitiatorError::DbPg(err)) => return Err(error::Error::context_source(
+            "failed to retrieve session",
+            err
+        )),
+        Err(_) => return Ok(None),
+    };
+
+    let mut dropped = Vec::new();
+
+    for journals_id in subscribed.clone() {
+        if let Some(msg) = check_access(&conn, initiator, &journals_id).await? {
+            subscribed.remove(&journals_id);
+            dropped.push(msg);
+        }
+    }
+
+    Ok(Some(dropped))
+}
+
+async fn handle_message(
+    state: &state::SharedState,
+    initiator: &Initiator,
+    subscribed: &mut HashSet<JournalId>,
+    msg: ClientMessage,
+) -> Result<ServerMessage, error::Error> {
+    match msg {
+        ClientMessage::Subscribe { journals_id } => {
+            let conn = state.db_conn().await?;
+
+            if let Some(msg) = check_access(&conn, initiator, &journals_id).await? {
+                return Ok(msg);
+            }
+
+            subscribed.insert(journals_id);
+
+            Ok(ServerMessage::Subscribed { journals_id })
+        }
+        ClientMessage::Unsubscribe { journals_id } => {
+            subscribed.remove(&journals_id);
+
+            Ok(ServerMessage::Unsubscribed { journals_id })
+        }
+    }
+}
@@ -33,7 +33,7 @@ macro_rules! res_if_html {
         };
 
         if is_html {
-            return Ok(crate::router::body::SpaPage::new($templates)?
+            return Ok(crate::router::body::SpaPage::new(&$templates)?
                 .into_response())
         }
     }
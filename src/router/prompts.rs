@@ -0,0 +1,207 @@
+use axum::Router;
+use axum::extract::Path;
+use axum::http::{StatusCode, HeaderMap};
+use axum::response::{IntoResponse, Response};
+use axum::routing::get;
+use serde::{Serialize, Deserialize};
+
+use crate::state;
+use crate::db;
+use crate::db::ids::PromptId;
+use crate::error::{self, Context};
+use crate::journal::prompt::Prompt;
+use crate::router::body;
+use crate::router::macros;
+use crate::user::preferences::Preferences;
+
+/// the max number of characters allowed in a prompt
+const MAX_PROMPT_CHARS: usize = 500;
+
+pub fn build(_state: &state::SharedState) -> Router<state::SharedState> {
+    Router::new()
+        .route("/", get(retrieve_prompts)
+            .post(create_prompt))
+        .route("/today", get(retrieve_today))
+        .route("/:prompts_id", get(retrieve_prompt)
+            .patch(update_prompt)
+            .delete(delete_prompt))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PromptPath {
+    prompts_id: PromptId,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "type")]
+pub enum PromptResult {
+    TextEmpty,
+    TextTooLong {
+        max: usize,
+    },
+}
+
+/// trims the given text and checks that it is within the limits
+fn validate_text(text: String) -> Result<String, PromptResult> {
+    let text = text.trim();
+
+    if text.is_empty() {
+        Err(PromptResult::TextEmpty)
+    } else if text.chars().count() > MAX_PROMPT_CHARS {
+        Err(PromptResult::TextTooLong {
+            max: MAX_PROMPT_CHARS,
+        })
+    } else {
+        Ok(text.to_owned())
+    }
+}
+
+/// retrieves the prompts from the server and the prompts of the current
+/// user
+async fn retrieve_prompts(
+    state: state::SharedState,
+    headers: HeaderMap,
+) -> Result<Response, error::Error> {
+    let conn = state.db_conn().await?;
+
+    let initiator = macros::require_initiator!(&conn, &headers, None::<&'static str>);
+
+    let prompts = Prompt::retrieve_available(&conn, &initiator.user.id)
+        .await
+        .context("failed to retrieve prompts")?;
+
+    Ok(body::Json(prompts).into_response())
+}
+
+/// retrieves the prompt for the current day of the user
+async fn retrieve_today(
+    state: state::SharedState,
+    headers: HeaderMap,
+) -> Result<Response, error::Error> {
+    let conn = state.db_conn().await?;
+
+    let initiator = macros::require_initiator!(&conn, &headers, None::<&'static str>);
+
+    let today = Preferences::retrieve(&conn, &initiator.user.id)
+        .await
+        .context("failed to retrieve preferences")?
+        .today();
+
+    let result = Prompt::retrieve_daily(&conn, &initiator.user.id, &today)
+        .await
+        .context("failed to retrieve daily prompt")?;
+
+    let Some(prompt) = result else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+
+    Ok(body::Json(prompt).into_response())
+}
+
+async fn retrieve_prompt(
+    state: state::SharedState,
+    headers: HeaderMap,
+    Path(PromptPath { prompts_id }): Path<PromptPath>,
+) -> Result<Response, error::Error> {
+    let conn = state.db_conn().await?;
+
+    let initiator = macros::require_initiator!(&conn, &headers, None::<&'static str>);
+
+    let result = Prompt::retrieve_id(&conn, &prompts_id, &initiator.user.id)
+        .await
+        .context("failed to retrieve prompt")?;
+
+    let Some(prompt) = result else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+
+    Ok(body::Json(prompt).into_response())
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PromptBody {
+    text: String,
+}
+
+async fn create_prompt(
+    tx: db::Tx,
+    headers: HeaderMap,
+    body::Json(json): body::Json<PromptBody>,
+) -> Result<Response, error::Error> {
+    let initiator = macros::require_initiator!(&*tx, &headers, None::<&'static str>);
+
+    let text = match validate_text(json.text) {
+        Ok(valid) => valid,
+        Err(result) => return Ok(body::FieldError::new("text", result).into_response()),
+    };
+
+    let prompt = Prompt::create(&*tx, &initiator.user.id, text)
+        .await
+        .context("failed to create prompt")?;
+
+    Ok((
+        StatusCode::CREATED,
+        body::Json(prompt)
+    ).into_response())
+}
+
+/// changes the text of a prompt. prompts from the server cannot be changed
+async fn update_prompt(
+    tx: db::Tx,
+    headers: HeaderMap,
+    Path(PromptPath { prompts_id }): Path<PromptPath>,
+    body::Json(json): body::Json<PromptBody>,
+) -> Result<Response, error::Error> {
+    let initiator = macros::require_initiator!(&*tx, &headers, None::<&'static str>);
+
+    let result = Prompt::retrieve_id(&*tx, &prompts_id, &initiator.user.id)
+        .await
+        .context("failed to retrieve prompt")?;
+
+    let Some(mut prompt) = result else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+
+    if prompt.users_id != Some(initiator.user.id) {
+        return Ok(StatusCode::UNAUTHORIZED.into_response());
+    }
+
+    let text = match validate_text(json.text) {
+        Ok(valid) => valid,
+        Err(result) => return Ok(body::FieldError::new("text", result).into_response()),
+    };
+
+    prompt.update_text(&*tx, text)
+        .await
+        .context("failed to update prompt")?;
+
+    Ok(body::Json(prompt).into_response())
+}
+
+/// deletes a prompt. entries that were written for it are kept but will no
+/// longer be linked to it
+async fn delete_prompt(
+    tx: db::Tx,
+    headers: HeaderMap,
+    Path(PromptPath { prompts_id }): Path<PromptPath>,
+) -> Result<Response, error::Error> {
+    let initiator = macros::require_initiator!(&*tx, &headers, None::<&'static str>);
+
+    let result = Prompt::retrieve_id(&*tx, &prompts_id, &initiator.user.id)
+        .await
+        .context("failed to retrieve prompt")?;
+
+    let Some(prompt) = result else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+
+    if prompt.users_id != Some(initiator.user.id) {
+        return Ok(StatusCode::UNAUTHORIZED.into_response());
+    }
+
+    prompt.delete(&*tx)
+        .await
+        .context("failed to delete prompt")?;
+
+    Ok(StatusCode::OK.into_response())
+}
@@ -0,0 +1,289 @@
+use axum::Router;
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::routing::post;
+use serde::{Deserialize, Serialize};
+
+use crate::db;
+use crate::email;
+use crate::error::{self, Context};
+use crate::router::body;
+use crate::sec::authn::recovery::{Recovery, RecoveryEmail, RecoveryError, RecoveryKind};
+use crate::sec::authn::session::Token;
+use crate::sec::password;
+use crate::state;
+use crate::user::User;
+
+pub fn build(_state: &state::SharedState) -> Router<state::SharedState> {
+    Router::new()
+        .route("/", post(request_recovery))
+        .route("/verify_email", post(verify_email))
+        .route("/complete", post(complete_recovery))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RequestRecovery {
+    username: String,
+    kind: RecoveryKind,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "type")]
+pub enum RequestRecoveryResult {
+    Requested,
+}
+
+/// starts a recovery request for the given username
+///
+/// the response will be the same if the user does not exist or does not
+/// have a verified backup email so that this cannot be used to search for
+/// accounts
+async fn request_recovery(
+    state: state::SharedState,
+    body::Json(json): body::Json<RequestRecovery>,
+) -> Result<Response, error::Error> {
+    request(state, json).await
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ForgotPassword {
+    username: String,
+}
+
+/// starts a password reset for the given username
+///
+/// same as a password recovery request
+pub async fn forgot_password(
+    state: state::SharedState,
+    body::Json(json): body::Json<ForgotPassword>,
+) -> Result<Response, error::Error> {
+    request(state, RequestRecovery {
+        username: json.username,
+        kind: RecoveryKind::Password,
+    }).await
+}
+
+async fn request(state: state::SharedState, json: RequestRecovery) -> Result<Response, error::Error> {
+    let mut conn = state.db_conn().await?;
+    let transaction = conn.transaction()
+        .await
+        .context("failed to create transaction")?;
+
+    let result = User::retrieve_username(&transaction, &json.username)
+        .await
+        .context("failed to retrieve user")?;
+
+    let mut message = None;
+
+    if let Some(user) = result {
+        match Recovery::create(&transaction, &user.id, json.kind).await {
+            Ok((_recovery, created)) => {
+                message = Some(created);
+            }
+            Err(RecoveryError::NoVerifiedEmail) => {}
+            // responding with anything different would show that the
+            // account exists
+            Err(RecoveryError::RateLimited) => {
+                tracing::warn!(users_id = %user.id, "recovery request rate limited");
+            }
+            Err(RecoveryError::Error(err)) => return Err(err),
+        }
+    }
+
+    transaction.commit()
+        .await
+        .context("failed to commit transaction")?;
+
+    if let Some(message) = message {
+        email::deliver(state.mailer(), message);
+    }
+
+    Ok((
+        StatusCode::ACCEPTED,
+        body::Json(RequestRecoveryResult::Requested)
+    ).into_response())
+}
+
+#[derive(Debug, Deserialize)]
+pub struct VerifyEmail {
+    token: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "type")]
+pub enum VerifyEmailResult {
+    InvalidToken,
+    Verified,
+}
+
+async fn verify_email(
+    db::Conn(conn): db::Conn,
+    body::Json(json): body::Json<VerifyEmail>,
+) -> Result<Response, error::Error> {
+    let Ok(token) = Token::from_base64(&json.token) else {
+        return Ok(body::FieldError::new(
+            "token",
+            VerifyEmailResult::InvalidToken
+        ).into_response());
+    };
+
+    let verified = RecoveryEmail::verify(&conn, &token)
+        .await
+        .context("failed to verify backup email")?;
+
+    if verified {
+        Ok(body::Json(VerifyEmailResult::Verified).into_response())
+    } else {
+        Ok(body::FieldError::new(
+            "token",
+            VerifyEmailResult::InvalidToken
+        ).into_response())
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CompleteRecovery {
+    token: String,
+    password: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "type")]
+pub enum CompleteRecoveryResult {
+    InvalidToken,
+    PendingApproval,
+    MissingPassword,
+    Completed,
+}
+
+async fn complete_recovery(
+    db::Conn(mut conn): db::Conn,
+    body::Json(json): body::Json<CompleteRecovery>,
+) -> Result<Response, error::Error> {
+    complete(&mut conn, json, None).await
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ResetPassword {
+    token: String,
+    password: String,
+}
+
+/// completes a password reset with the token from a password recovery
+/// request. existing sessions for the user are removed
+pub async fn reset_password(
+    db::Conn(mut conn): db::Conn,
+    body::Json(json): body::Json<ResetPassword>,
+) -> Result<Response, error::Error> {
+    complete(&mut conn, CompleteRecovery {
+        token: json.token,
+        password: Some(json.password),
+    }, Some(RecoveryKind::Password)).await
+}
+
+/// completes the recovery request for the given token
+///
+/// if a kind is specified then tokens for other kinds of recovery are
+/// treated as invalid
+async fn complete(
+    conn: &mut db::Object,
+    json: CompleteRecovery,
+    kind: Option<RecoveryKind>,
+) -> Result<Response, error::Error> {
+    let transaction = conn.transaction()
+        .await
+        .context("failed to create transaction")?;
+
+    let Ok(token) = Token::from_base64(&json.token) else {
+        return Ok(body::FieldError::new(
+            "token",
+            CompleteRecoveryResult::InvalidToken
+        ).into_response());
+    };
+
+    let result = Recovery::retrieve_token(&transaction, &token)
+        .await
+        .context("failed to retrieve recovery request")?;
+
+    let Some(recovery) = result.filter(|found| kind.is_none_or(|k| found.kind == k)) else {
+        return Ok(body::FieldError::new(
+            "token",
+            CompleteRecoveryResult::InvalidToken
+        ).into_response());
+    };
+
+    db::lock::acquire(&transaction, db::lock::Namespace::Recovery, recovery.id)
+        .await
+        .context("failed to acquire recovery lock")?;
+
+    // another request may have completed the recovery while waiting for the
+    // lock so the request is retrieved again
+    let result = Recovery::retrieve_token(&transaction, &token)
+        .await
+        .context("failed to retrieve recovery request")?;
+
+    let Some(mut recovery) = result.filter(|found| found.id == recovery.id) else {
+        return Ok(body::FieldError::new(
+            "token",
+            CompleteRecoveryResult::InvalidToken
+        ).into_response());
+    };
+
+    if !recovery.is_approved() {
+        return Ok((
+            StatusCode::FORBIDDEN,
+            body::Json(CompleteRecoveryResult::PendingApproval)
+        ).into_response());
+    }
+
+    match recovery.kind {
+        RecoveryKind::Password => {
+            let Some(given) = json.password else {
+                return Ok(body::FieldError::new(
+                    "password",
+                    CompleteRecoveryResult::MissingPassword
+                ).into_response());
+            };
+
+            let mut user = User::retrieve_id(&transaction, recovery.users_id)
+                .await
+                .context("failed to retrieve user")?
+                .context("user for recovery request was not found")?;
+
+            user.password = password::create(&given)
+                .context("failed to hash password for user")?;
+            user.version = 0;
+
+            user.update(&transaction)
+                .await
+                .context("failed to update user password")?;
+        }
+        RecoveryKind::Totp => {
+            transaction.execute(
+                "delete from authn_totp where users_id = $1",
+                &[&recovery.users_id]
+            )
+                .await
+                .context("failed to delete from authn totp")?;
+        }
+    }
+
+    transaction.execute(
+        "delete from authn_sessions where users_id = $1",
+        &[&recovery.users_id]
+    )
+        .await
+        .context("failed to delete sessions for user")?;
+
+    recovery.mark_used(&transaction)
+        .await
+        .context("failed to mark recovery request as used")?;
+
+    transaction.commit()
+        .await
+        .context("failed to commit transaction")?;
+
+    tracing::info!(users_id = %recovery.users_id, kind = %recovery.kind, "account recovery completed");
+
+    Ok(body::Json(CompleteRecoveryResult::Completed).into_response())
+}
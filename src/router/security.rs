@@ -0,0 +1,50 @@
+//! headers that limit what browsers will do with responses
+//!
+//! every response is sent with "x-content-type-options", "referrer-policy",
+//! and "content-security-policy". "strict-transport-security" is added when
+//! enabled in the config. handlers that set their own headers, like the
+//! files of an entry, are left as is
+
+use axum::extract::Request;
+use axum::http::{HeaderMap, HeaderName, HeaderValue};
+use axum::http::header;
+use axum::middleware::Next;
+use axum::response::Response;
+
+use crate::state;
+
+fn set_default(headers: &mut HeaderMap, name: HeaderName, value: &str) {
+    if value.is_empty() || headers.contains_key(&name) {
+        return;
+    }
+
+    // values are checked when the config is loaded
+    if let Ok(value) = HeaderValue::from_str(value) {
+        headers.insert(name, value);
+    }
+}
+
+/// middleware that adds the security headers from the config to responses
+pub async fn add_headers(
+    state: state::SharedState,
+    req: Request,
+    next: Next,
+) -> Response {
+    let mut res = next.run(req).await;
+    let config = state.security_headers();
+    let headers = res.headers_mut();
+
+    set_default(headers, header::X_CONTENT_TYPE_OPTIONS, "nosniff");
+    set_default(headers, header::REFERRER_POLICY, &config.referrer_policy);
+    set_default(headers, header::CONTENT_SECURITY_POLICY, &config.content_security_policy);
+
+    if config.hsts == Some(true) {
+        set_default(
+            headers,
+            header::STRICT_TRANSPORT_SECURITY,
+            &format!("max-age={}", config.hsts_max_age)
+        );
+    }
+
+    res
+}
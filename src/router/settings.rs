@@ -0,0 +1,21 @@
+use axum::Router;
+use axum::routing::{get, patch};
+
+use crate::state;
+
+mod sessions;
+mod preferences;
+mod reminder;
+
+pub fn build(_state: &state::SharedState) -> Router<state::SharedState> {
+    Router::new()
+        .route("/sessions", get(sessions::retrieve_sessions)
+            .delete(sessions::delete_other_sessions))
+        .route("/sessions/:sessions_id", patch(sessions::update_session)
+            .delete(sessions::delete_session))
+        .route("/preferences", get(preferences::retrieve_preferences)
+            .patch(preferences::update_preferences))
+        .route("/reminder", get(reminder::retrieve_reminder)
+            .put(reminder::update_reminder)
+            .delete(reminder::delete_reminder))
+}
@@ -0,0 +1,128 @@
+use axum::http::{HeaderMap, Uri};
+use axum::response::{IntoResponse, Response};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+
+use crate::db::ids::JournalId;
+use crate::error::{self, Context};
+use crate::journal::{Journal, WeekStart};
+use crate::journal::custom_field::unit::UnitSystem;
+use crate::router::{body, macros};
+use crate::state;
+use crate::user::preferences::{self, DateFormat, Preferences};
+
+/// retrieves the preferences of the current user
+pub async fn retrieve_preferences(
+    state: state::SharedState,
+    uri: Uri,
+    headers: HeaderMap,
+) -> Result<Response, error::Error> {
+    let conn = state.db_conn().await?;
+
+    let initiator = macros::require_initiator!(&conn, &headers, Some(uri));
+
+    macros::res_if_html!(state.templates(), &headers);
+
+    let preferences = Preferences::retrieve(&conn, &initiator.user.id)
+        .await
+        .context("failed to retrieve preferences")?;
+
+    Ok(body::Json(preferences).into_response())
+}
+
+/// the preferences to change. preferences that are not given are left as
+/// is and a null default journal or unit system removes it
+#[derive(Debug, Deserialize)]
+pub struct UpdatePreferences {
+    timezone: Option<String>,
+    locale: Option<String>,
+    date_format: Option<DateFormat>,
+    #[serde(default, deserialize_with = "crate::serde::nested_opt")]
+    default_journals_id: Option<Option<JournalId>>,
+    week_start: Option<WeekStart>,
+    #[serde(default, deserialize_with = "crate::serde::nested_opt")]
+    unit_system: Option<Option<UnitSystem>>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "type")]
+pub enum UpdatePreferencesResult {
+    InvalidTimezone,
+    InvalidLocale,
+    JournalNotFound,
+    Updated(Preferences),
+}
+
+/// changes the given preferences of the current user
+pub async fn update_preferences(
+    state: state::SharedState,
+    headers: HeaderMap,
+    body::Json(json): body::Json<UpdatePreferences>,
+) -> Result<Response, error::Error> {
+    let conn = state.db_conn().await?;
+
+    let initiator = macros::require_initiator!(&conn, &headers, None::<&'static str>);
+
+    let mut current = Preferences::retrieve(&conn, &initiator.user.id)
+        .await
+        .context("failed to retrieve preferences")?;
+
+    if let Some(timezone) = json.timezone {
+        let Ok(timezone) = timezone.parse::<Tz>() else {
+            return Ok(body::FieldError::new(
+                "timezone",
+                UpdatePreferencesResult::InvalidTimezone
+            ).into_response());
+        };
+
+        current.timezone = timezone.name().to_owned();
+    }
+
+    if let Some(locale) = json.locale {
+        let locale = locale.trim();
+
+        if !preferences::valid_locale(locale) {
+            return Ok(body::FieldError::new(
+                "locale",
+                UpdatePreferencesResult::InvalidLocale
+            ).into_response());
+        }
+
+        current.locale = locale.to_owned();
+    }
+
+    if let Some(date_format) = json.date_format {
+        current.date_format = date_format;
+    }
+
+    if let Some(default_journals_id) = json.default_journals_id {
+        if let Some(journals_id) = &default_journals_id {
+            let journal = Journal::retrieve_id(&conn, journals_id, &initiator.user.id)
+                .await
+                .context("failed to retrieve journal")?;
+
+            if journal.is_none() {
+                return Ok(body::FieldError::new(
+                    "default_journals_id",
+                    UpdatePreferencesResult::JournalNotFound
+                ).into_response());
+            }
+        }
+
+        current.default_journals_id = default_journals_id;
+    }
+
+    if let Some(week_start) = json.week_start {
+        current.week_start = week_start;
+    }
+
+    if let Some(unit_system) = json.unit_system {
+        current.unit_system = unit_system;
+    }
+
+    current.set(&conn, &initiator.user.id)
+        .await
+        .context("failed to update preferences")?;
+
+    Ok(body::Json(UpdatePreferencesResult::Updated(current)).into_response())
+}
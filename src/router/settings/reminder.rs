@@ -0,0 +1,128 @@
+use axum::http::{StatusCode, HeaderMap, Uri};
+use axum::response::{IntoResponse, Response};
+use chrono::NaiveTime;
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+
+use crate::db::ids::JournalId;
+use crate::error::{self, Context};
+use crate::journal::Journal;
+use crate::reminder::{Reminder, ReminderNotify};
+use crate::router::{body, macros};
+use crate::state;
+use crate::user::preferences::Preferences;
+
+/// retrieves the daily reminder of the current user
+pub async fn retrieve_reminder(
+    state: state::SharedState,
+    uri: Uri,
+    headers: HeaderMap,
+) -> Result<Response, error::Error> {
+    let conn = state.db_conn().await?;
+
+    let initiator = macros::require_initiator!(&conn, &headers, Some(uri));
+
+    macros::res_if_html!(state.templates(), &headers);
+
+    let result = Reminder::retrieve(&conn, &initiator.user.id)
+        .await
+        .context("failed to retrieve reminder")?;
+
+    let Some(reminder) = result else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+
+    Ok(body::Json(reminder).into_response())
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateReminder {
+    journals_id: JournalId,
+    remind_at: NaiveTime,
+
+    /// defaults to the timezone of the user preferences
+    timezone: Option<String>,
+    notify: ReminderNotify,
+    enabled: Option<bool>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "type")]
+pub enum UpdateReminderResult {
+    JournalNotFound,
+    InvalidTimezone,
+    Updated(Reminder),
+}
+
+/// creates or replaces the daily reminder of the current user
+pub async fn update_reminder(
+    state: state::SharedState,
+    headers: HeaderMap,
+    body::Json(json): body::Json<UpdateReminder>,
+) -> Result<Response, error::Error> {
+    let conn = state.db_conn().await?;
+
+    let initiator = macros::require_initiator!(&conn, &headers, None::<&'static str>);
+
+    let timezone = match json.timezone {
+        Some(timezone) => {
+            let Ok(timezone) = timezone.parse::<Tz>() else {
+                return Ok(body::FieldError::new(
+                    "timezone",
+                    UpdateReminderResult::InvalidTimezone
+                ).into_response());
+            };
+
+            timezone
+        }
+        None => Preferences::retrieve(&conn, &initiator.user.id)
+            .await
+            .context("failed to retrieve preferences")?
+            .tz(),
+    };
+
+    let journal = Journal::retrieve_id(&conn, &json.journals_id, &initiator.user.id)
+        .await
+        .context("failed to retrieve journal")?;
+
+    if journal.is_none() {
+        return Ok(body::FieldError::new(
+            "journals_id",
+            UpdateReminderResult::JournalNotFound
+        ).into_response());
+    }
+
+    let reminder = Reminder::set(
+        &conn,
+        &initiator.user.id,
+        &json.journals_id,
+        json.remind_at,
+        timezone,
+        json.notify,
+        json.enabled.unwrap_or(true),
+    )
+        .await
+        .context("failed to update reminder")?;
+
+    Ok(body::Json(UpdateReminderResult::Updated(reminder)).into_response())
+}
+
+/// removes the daily reminder of the current user
+pub async fn delete_reminder(
+    state: state::SharedState,
+    headers: HeaderMap,
+) -> Result<Response, error::Error> {
+    let conn = state.db_conn().await?;
+
+    let initiator = macros::require_initiator!(&conn, &headers, None::<&'static str>);
+
+    let deleted = Reminder::delete(&conn, &initiator.user.id)
+        .await
+        .context("failed to delete reminder")?;
+
+    if !deleted {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    }
+
+    Ok(StatusCode::OK.into_response())
+}
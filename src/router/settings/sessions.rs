@@ -0,0 +1,172 @@
+use std::net::IpAddr;
+
+use axum::extract::Path;
+use axum::http::{StatusCode, HeaderMap, Uri};
+use axum::response::{IntoResponse, Response};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::db::ids::SessionId;
+use crate::error::{self, Context};
+use crate::router::{body, macros};
+use crate::sec::authn::Session;
+use crate::state;
+
+/// the max number of characters in the name of a session
+const MAX_NAME_LEN: usize = 64;
+
+#[derive(Debug, Serialize)]
+pub struct SessionPartial {
+    id: SessionId,
+    issued_on: DateTime<Utc>,
+    expires_on: DateTime<Utc>,
+    last_used: Option<DateTime<Utc>>,
+    user_agent: Option<String>,
+    ip_addr: Option<IpAddr>,
+    name: Option<String>,
+
+    /// the session used to make this request
+    current: bool,
+}
+
+impl SessionPartial {
+    fn from_session(session: Session, current: &Session) -> Self {
+        SessionPartial {
+            current: session.id == current.id,
+            id: session.id,
+            issued_on: session.issued_on,
+            expires_on: session.expires_on,
+            last_used: session.last_used,
+            user_agent: session.user_agent,
+            ip_addr: session.ip_addr,
+            name: session.name,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SessionPath {
+    sessions_id: SessionId,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DeletedSessions {
+    deleted: u64,
+}
+
+/// lists the active sessions of the current user
+pub async fn retrieve_sessions(
+    state: state::SharedState,
+    uri: Uri,
+    headers: HeaderMap,
+) -> Result<Response, error::Error> {
+    let conn = state.db_conn().await?;
+
+    let initiator = macros::require_initiator!(&conn, &headers, Some(uri));
+
+    macros::res_if_html!(state.templates(), &headers);
+
+    let sessions: Vec<SessionPartial> = Session::retrieve_user(&conn, &initiator.user.id)
+        .await
+        .context("failed to retrieve user sessions")?
+        .into_iter()
+        .map(|session| SessionPartial::from_session(session, &initiator.session))
+        .collect();
+
+    Ok(body::Json(sessions).into_response())
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateSession {
+    name: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "type")]
+pub enum UpdateSessionResult {
+    NameTooLong,
+    Updated(SessionPartial),
+}
+
+/// names a session of the current user. an empty name will remove it
+pub async fn update_session(
+    state: state::SharedState,
+    headers: HeaderMap,
+    Path(SessionPath { sessions_id }): Path<SessionPath>,
+    body::Json(json): body::Json<UpdateSession>,
+) -> Result<Response, error::Error> {
+    let conn = state.db_conn().await?;
+
+    let initiator = macros::require_initiator!(&conn, &headers, None::<&'static str>);
+
+    let name = json.name.as_deref()
+        .map(str::trim)
+        .filter(|name| !name.is_empty());
+
+    if name.is_some_and(|name| name.chars().count() > MAX_NAME_LEN) {
+        return Ok(body::FieldError::new(
+            "name",
+            UpdateSessionResult::NameTooLong
+        ).into_response());
+    }
+
+    let result = Session::update_name(&conn, &initiator.user.id, &sessions_id, name)
+        .await
+        .context("failed to update user session")?;
+
+    let Some(session) = result else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+
+    Ok(body::Json(UpdateSessionResult::Updated(
+        SessionPartial::from_session(session, &initiator.session)
+    )).into_response())
+}
+
+/// revokes a single session of the current user
+///
+/// revoking the current session will also clear the session cookie
+pub async fn delete_session(
+    state: state::SharedState,
+    headers: HeaderMap,
+    Path(SessionPath { sessions_id }): Path<SessionPath>,
+) -> Result<Response, error::Error> {
+    let conn = state.db_conn().await?;
+
+    let initiator = macros::require_initiator!(&conn, &headers, None::<&'static str>);
+
+    let deleted = Session::delete_id(&conn, &initiator.user.id, &sessions_id)
+        .await
+        .context("failed to delete user session")?;
+
+    if !deleted {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    }
+
+    if sessions_id == initiator.session.id {
+        Ok((
+            Session::clear_cookie(),
+            Session::clear_csrf_cookie(),
+            StatusCode::OK,
+        ).into_response())
+    } else {
+        Ok(StatusCode::OK.into_response())
+    }
+}
+
+/// revokes every session of the current user except the one used to make
+/// this request
+pub async fn delete_other_sessions(
+    state: state::SharedState,
+    headers: HeaderMap,
+) -> Result<Response, error::Error> {
+    let conn = state.db_conn().await?;
+
+    let initiator = macros::require_initiator!(&conn, &headers, None::<&'static str>);
+
+    let deleted = initiator.session.delete_others(&conn)
+        .await
+        .context("failed to delete other user sessions")?;
+
+    Ok(body::Json(DeletedSessions { deleted }).into_response())
+}
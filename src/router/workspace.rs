@@ -0,0 +1,162 @@
+use std::str::FromStr;
+
+use async_trait::async_trait;
+use axum::extract::{FromRequestParts, RawPathParams, Request};
+use axum::http::StatusCode;
+use axum::http::request::Parts;
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+
+use crate::db;
+use crate::db::ids::{JournalId, WorkspaceId};
+use crate::error::{self, Context};
+use crate::sec::authn::Initiator;
+use crate::state;
+use crate::workspace::Workspace;
+
+/// the name of the path parameter used when selecting a workspace by path
+/// prefix, "/w/{workspace}/..."
+pub const PATH_PARAM: &str = "workspace";
+
+/// attempts to find the workspace for a request
+///
+/// the path prefix takes priority over the host header. if the host does not
+/// match a known workspace then the default workspace will be used. a path
+/// prefix that is not found will result in None
+async fn resolve(
+    conn: &impl db::GenericClient,
+    parts: &mut Parts,
+    state: &state::SharedState,
+) -> Result<Option<Workspace>, error::Error> {
+    if let Ok(params) = RawPathParams::from_request_parts(parts, state).await {
+        for (key, value) in &params {
+            if key == PATH_PARAM {
+                return Workspace::retrieve_path_prefix(conn, value)
+                    .await
+                    .context("failed to retrieve workspace by path prefix");
+            }
+        }
+    }
+
+    if let Some(value) = parts.headers.get("host") {
+        let host_str = value.to_str()
+            .context("host header contains invalid utf8 characters")?;
+        let host = match host_str.rsplit_once(':') {
+            Some((name, port)) if port.chars().all(|ch| ch.is_ascii_digit()) => name,
+            _ => host_str,
+        };
+
+        let found = Workspace::retrieve_host(conn, host)
+            .await
+            .context("failed to retrieve workspace by host")?;
+
+        if found.is_some() {
+            return Ok(found);
+        }
+    }
+
+    let default = Workspace::retrieve_default(conn)
+        .await
+        .context("failed to retrieve default workspace")?
+        .context("default workspace was not found")?;
+
+    Ok(Some(default))
+}
+
+#[async_trait]
+impl FromRequestParts<state::SharedState> for Workspace {
+    type Rejection = Response;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &state::SharedState
+    ) -> Result<Self, Self::Rejection> {
+        if let Some(found) = parts.extensions.get::<Workspace>() {
+            return Ok(found.clone());
+        }
+
+        let conn = state.db_conn()
+            .await
+            .map_err(IntoResponse::into_response)?;
+
+        match resolve(&conn, parts, state).await {
+            Ok(Some(workspace)) => {
+                parts.extensions.insert(workspace.clone());
+
+                Ok(workspace)
+            }
+            Ok(None) => Err(StatusCode::NOT_FOUND.into_response()),
+            Err(err) => Err(err.into_response()),
+        }
+    }
+}
+
+/// checks to see if the journal specified in the path params belongs to the
+/// given workspace
+///
+/// if no journal is specified then this will return true
+async fn journal_in_workspace(
+    conn: &impl db::GenericClient,
+    params: &RawPathParams,
+    workspace: &Workspace,
+) -> Result<bool, error::Error> {
+    for (key, value) in params {
+        if key != "journals_id" {
+            continue;
+        }
+
+        let Ok(journals_id) = JournalId::from_str(value) else {
+            return Ok(true);
+        };
+
+        let result = conn.query_opt(
+            "select journals.workspaces_id from journals where journals.id = $1",
+            &[&journals_id]
+        )
+            .await
+            .context("failed to retrieve journal workspace")?;
+
+        return Ok(match result {
+            Some(row) => row.get::<usize, WorkspaceId>(0) == workspace.id,
+            None => true,
+        });
+    }
+
+    Ok(true)
+}
+
+/// middleware that will reject any authenticated user that is not a member of
+/// the requested workspace
+///
+/// unauthenticated requests are passed through so that the handlers are able
+/// to redirect to the login page. any journal specified in the path must also
+/// belong to the workspace otherwise it will be treated as not found
+pub async fn require_member(
+    state: state::SharedState,
+    workspace: Workspace,
+    params: RawPathParams,
+    mut req: Request,
+    next: Next,
+) -> Result<Response, error::Error> {
+    {
+        let conn = state.db_conn().await?;
+
+        if !journal_in_workspace(&conn, &params, &workspace).await? {
+            return Ok(StatusCode::NOT_FOUND.into_response());
+        }
+
+        if let Ok(initiator) = Initiator::from_headers(&conn, req.headers()).await {
+            let member = workspace.retrieve_member(&conn, &initiator.user.id)
+                .await
+                .context("failed to retrieve workspace member")?;
+
+            if member.is_none() {
+                return Ok(StatusCode::FORBIDDEN.into_response());
+            }
+        }
+    }
+
+    req.extensions_mut().insert(workspace);
+
+    Ok(next.run(req).await)
+}
@@ -1,3 +1,6 @@
 pub mod authn;
 pub mod authz;
 pub mod password;
+pub mod network;
+pub mod encryption;
+pub mod scan;
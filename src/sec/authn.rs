@@ -4,6 +4,8 @@ use crate::db;
 use crate::user;
 
 pub mod session;
+pub mod recovery;
+pub mod passkey;
 pub use session::Session;
 
 #[derive(Debug, thiserror::Error)]
@@ -17,6 +19,9 @@ pub enum InitiatorError {
     #[error("failed to find the user for the session")]
     UserNotFound(Session),
 
+    #[error("the user for the session is disabled")]
+    UserDisabled(Session),
+
     #[error("given session is not authenticated")]
     Unauthenticated(Session),
 
@@ -75,16 +80,38 @@ impl Initiator {
     ) -> Result<Self, InitiatorError> {
         let token = Self::get_token(headers)?;
 
-        let Some(session) = Session::retrieve_token(conn, &token).await? else {
+        Self::from_token(conn, &token, true).await
+    }
+
+    /// retrieves the initiator again to check that the session is still
+    /// valid and the user is not disabled. the session is not touched
+    pub async fn refresh(&self, conn: &impl db::GenericClient) -> Result<Self, InitiatorError> {
+        Self::from_token(conn, &self.session.token, false).await
+    }
+
+    async fn from_token(
+        conn: &impl db::GenericClient,
+        token: &session::Token,
+        touch: bool,
+    ) -> Result<Self, InitiatorError> {
+        let Some(session) = Session::retrieve_token(conn, token).await? else {
             return Err(InitiatorError::SessionNotFound);
         };
 
-        let session = Self::validate_session(session)?;
+        let mut session = Self::validate_session(session)?;
+
+        if touch {
+            session.touch(conn).await?;
+        }
 
         let Some(user) = user::User::retrieve_id(conn, session.users_id).await? else {
             return Err(InitiatorError::UserNotFound(session));
         };
 
+        if user.disabled.is_some() {
+            return Err(InitiatorError::UserDisabled(session));
+        }
+
         Ok(Initiator {
             user,
             session
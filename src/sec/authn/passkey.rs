@@ -0,0 +1,597 @@
+//! passkey (WebAuthn) registration and authentication
+//!
+//! only "none" attestation is supported so the authenticator that created a
+//! passkey is not verified. the supported key types are ES256 and EdDSA which
+//! covers the platform and roaming authenticators currently available
+
+use std::io::Cursor;
+
+use base64::Engine as _;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use chrono::{DateTime, Duration, Utc};
+use rand::RngCore;
+use ring::signature::{self, UnparsedPublicKey, VerificationAlgorithm};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+
+use crate::config;
+use crate::db;
+use crate::db::ids::{UserId, PasskeyId, PasskeyChallengeId};
+use crate::error::{self, Context};
+
+/// the number of random bytes in a challenge
+pub const CHALLENGE_LEN: usize = 32;
+
+/// the amount of time a client has to respond to a challenge
+pub const CHALLENGE_DURATION: Duration = Duration::minutes(5);
+
+/// COSE algorithm identifier for ECDSA with P-256 and SHA-256
+pub const ALG_ES256: i32 = -7;
+
+/// COSE algorithm identifier for EdDSA with Ed25519
+pub const ALG_EDDSA: i32 = -8;
+
+/// the user was present when the authenticator was used
+const FLAG_USER_PRESENT: u8 = 0x01;
+
+/// the authenticator data contains attested credential data
+const FLAG_ATTESTED: u8 = 0x40;
+
+/// the potential errors when verifying a response from an authenticator
+#[derive(Debug, thiserror::Error)]
+pub enum PasskeyError {
+    #[error("the client data is not valid")]
+    InvalidClientData,
+
+    #[error("the client data type does not match the ceremony")]
+    TypeMismatch,
+
+    #[error("the client data challenge does not match")]
+    ChallengeMismatch,
+
+    #[error("the client data origin does not match")]
+    OriginMismatch,
+
+    #[error("the attestation object is not valid")]
+    InvalidAttestation,
+
+    #[error("the authenticator data is not valid")]
+    InvalidAuthData,
+
+    #[error("the relying party id hash does not match")]
+    RpIdMismatch,
+
+    #[error("the user was not present")]
+    UserNotPresent,
+
+    #[error("the public key is not a supported type")]
+    UnsupportedKey,
+
+    #[error("the signature is not valid")]
+    InvalidSignature,
+
+    #[error("the signature counter did not increase")]
+    CounterRegressed,
+}
+
+/// the type of ceremony that a challenge was created for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChallengeKind {
+    Register,
+    Authenticate,
+}
+
+impl ChallengeKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ChallengeKind::Register => "register",
+            ChallengeKind::Authenticate => "authenticate",
+        }
+    }
+
+    /// the expected "type" of the client data
+    fn client_data_type(&self) -> &'static str {
+        match self {
+            ChallengeKind::Register => "webauthn.create",
+            ChallengeKind::Authenticate => "webauthn.get",
+        }
+    }
+}
+
+/// a random value that an authenticator must sign to prove possession of a
+/// passkey
+#[derive(Debug)]
+pub struct Challenge {
+    pub id: PasskeyChallengeId,
+    pub users_id: Option<UserId>,
+    pub kind: ChallengeKind,
+    pub challenge: Vec<u8>,
+    pub expires_on: DateTime<Utc>,
+}
+
+impl Challenge {
+    /// creates a new challenge and removes any that have expired
+    pub async fn create(
+        conn: &impl db::GenericClient,
+        users_id: Option<&UserId>,
+        kind: ChallengeKind,
+    ) -> Result<Self, error::Error> {
+        let issued_on = Utc::now();
+        let expires_on = issued_on + CHALLENGE_DURATION;
+        let mut challenge = vec![0; CHALLENGE_LEN];
+
+        rand::thread_rng().try_fill_bytes(&mut challenge)
+            .context("failed to create passkey challenge")?;
+
+        conn.execute(
+            "delete from authn_passkey_challenges where expires_on < $1",
+            &[&issued_on]
+        )
+            .await
+            .context("failed to remove expired passkey challenges")?;
+
+        let row = conn.query_one(
+            "\
+            insert into authn_passkey_challenges (users_id, kind, challenge, issued_on, expires_on) values \
+            ($1, $2, $3, $4, $5) \
+            returning id",
+            &[&users_id, &kind.as_str(), &challenge, &issued_on, &expires_on]
+        )
+            .await
+            .context("failed to create passkey challenge")?;
+
+        Ok(Self {
+            id: row.get(0),
+            users_id: users_id.copied(),
+            kind,
+            challenge,
+            expires_on,
+        })
+    }
+
+    /// removes the challenge so that it can only be used once
+    ///
+    /// returns None if the challenge does not exist or has expired
+    pub async fn take(
+        conn: &impl db::GenericClient,
+        id: &PasskeyChallengeId,
+        kind: ChallengeKind,
+    ) -> Result<Option<Self>, db::PgError> {
+        let result = conn.query_opt(
+            "\
+            delete from authn_passkey_challenges \
+            where id = $1 and \
+                  kind = $2 \
+            returning users_id, \
+                      challenge, \
+                      expires_on",
+            &[id, &kind.as_str()]
+        ).await?;
+
+        Ok(result.map(|row| Self {
+            id: *id,
+            users_id: row.get(0),
+            kind,
+            challenge: row.get(1),
+            expires_on: row.get(2),
+        }).filter(|challenge| challenge.expires_on > Utc::now()))
+    }
+}
+
+/// a passkey registered to a user
+#[derive(Debug)]
+pub struct Passkey {
+    pub id: PasskeyId,
+    pub users_id: UserId,
+    pub credential_id: Vec<u8>,
+    pub public_key: Vec<u8>,
+    pub alg: i32,
+    pub sign_count: i64,
+    pub name: Option<String>,
+    pub created: DateTime<Utc>,
+    pub last_used: Option<DateTime<Utc>>,
+}
+
+/// the potential errors when creating a passkey
+#[derive(Debug, thiserror::Error)]
+pub enum CreatePasskeyError {
+    #[error("the credential id is already registered")]
+    CredentialExists,
+
+    #[error(transparent)]
+    Db(#[from] db::PgError),
+}
+
+impl Passkey {
+    fn map_row(row: tokio_postgres::Row) -> Self {
+        Self {
+            id: row.get(0),
+            users_id: row.get(1),
+            credential_id: row.get(2),
+            public_key: row.get(3),
+            alg: row.get(4),
+            sign_count: row.get(5),
+            name: row.get(6),
+            created: row.get(7),
+            last_used: row.get(8),
+        }
+    }
+
+    pub async fn create(
+        conn: &impl db::GenericClient,
+        users_id: &UserId,
+        verified: VerifiedCredential,
+        name: Option<String>,
+    ) -> Result<Self, CreatePasskeyError> {
+        let created = Utc::now();
+        let sign_count = verified.sign_count as i64;
+
+        let result = conn.query_one(
+            "\
+            insert into authn_passkeys ( \
+                users_id, \
+                credential_id, \
+                public_key, \
+                alg, \
+                sign_count, \
+                name, \
+                created \
+            ) values ($1, $2, $3, $4, $5, $6, $7) \
+            returning id",
+            &[
+                users_id,
+                &verified.credential_id,
+                &verified.public_key,
+                &verified.alg,
+                &sign_count,
+                &name,
+                &created,
+            ]
+        ).await;
+
+        match result {
+            Ok(row) => Ok(Self {
+                id: row.get(0),
+                users_id: *users_id,
+                credential_id: verified.credential_id,
+                public_key: verified.public_key,
+                alg: verified.alg,
+                sign_count,
+                name,
+                created,
+                last_used: None,
+            }),
+            Err(err) => match db::ErrorKind::check(&err) {
+                Some(db::ErrorKind::Unique("authn_passkeys_credential_id_key")) =>
+                    Err(CreatePasskeyError::CredentialExists),
+                _ => Err(CreatePasskeyError::Db(err)),
+            }
+        }
+    }
+
+    pub async fn retrieve_credential_id(
+        conn: &impl db::GenericClient,
+        credential_id: &[u8],
+    ) -> Result<Option<Self>, db::PgError> {
+        conn.query_opt(
+            "\
+            select authn_passkeys.id, \
+                   authn_passkeys.users_id, \
+                   authn_passkeys.credential_id, \
+                   authn_passkeys.public_key, \
+                   authn_passkeys.alg, \
+                   authn_passkeys.sign_count, \
+                   authn_passkeys.name, \
+                   authn_passkeys.created, \
+                   authn_passkeys.last_used \
+            from authn_passkeys \
+            where authn_passkeys.credential_id = $1",
+            &[&credential_id]
+        )
+            .await
+            .map(|maybe| maybe.map(Self::map_row))
+    }
+
+    pub async fn retrieve_user(
+        conn: &impl db::GenericClient,
+        users_id: &UserId,
+    ) -> Result<Vec<Self>, db::PgError> {
+        conn.query(
+            "\
+            select authn_passkeys.id, \
+                   authn_passkeys.users_id, \
+                   authn_passkeys.credential_id, \
+                   authn_passkeys.public_key, \
+                   authn_passkeys.alg, \
+                   authn_passkeys.sign_count, \
+                   authn_passkeys.name, \
+                   authn_passkeys.created, \
+                   authn_passkeys.last_used \
+            from authn_passkeys \
+            where authn_passkeys.users_id = $1 \
+            order by authn_passkeys.created",
+            &[users_id]
+        )
+            .await
+            .map(|rows| rows.into_iter()
+                .map(Self::map_row)
+                .collect())
+    }
+
+    /// records a successful authentication with the new signature counter
+    pub async fn update_used(&mut self, conn: &impl db::GenericClient, sign_count: u32) -> Result<(), db::PgError> {
+        let last_used = Utc::now();
+        let sign_count = sign_count as i64;
+
+        conn.execute(
+            "\
+            update authn_passkeys \
+            set sign_count = $2, \
+                last_used = $3 \
+            where id = $1",
+            &[&self.id, &sign_count, &last_used]
+        ).await?;
+
+        self.sign_count = sign_count;
+        self.last_used = Some(last_used);
+
+        Ok(())
+    }
+
+    /// removes a passkey from the user
+    ///
+    /// returns false if the passkey was not found
+    pub async fn delete(
+        conn: &impl db::GenericClient,
+        users_id: &UserId,
+        id: &PasskeyId,
+    ) -> Result<bool, db::PgError> {
+        conn.execute(
+            "delete from authn_passkeys where users_id = $1 and id = $2",
+            &[users_id, id]
+        )
+            .await
+            .map(|count| count > 0)
+    }
+}
+
+/// the details of a newly registered passkey that have been verified
+#[derive(Debug)]
+pub struct VerifiedCredential {
+    pub credential_id: Vec<u8>,
+    pub public_key: Vec<u8>,
+    pub alg: i32,
+    pub sign_count: u32,
+}
+
+#[derive(Debug, Deserialize)]
+struct ClientData {
+    #[serde(rename = "type")]
+    kind: String,
+    challenge: String,
+    origin: String,
+}
+
+/// checks that the client data was created for the given challenge and
+/// origin
+fn verify_client_data(
+    config: &config::Webauthn,
+    challenge: &Challenge,
+    client_data_json: &[u8],
+) -> Result<(), PasskeyError> {
+    let client_data: ClientData = serde_json::from_slice(client_data_json)
+        .map_err(|_| PasskeyError::InvalidClientData)?;
+
+    if client_data.kind != challenge.kind.client_data_type() {
+        return Err(PasskeyError::TypeMismatch);
+    }
+
+    let given = decode_base64(&client_data.challenge)
+        .ok_or(PasskeyError::InvalidClientData)?;
+
+    if given != challenge.challenge {
+        return Err(PasskeyError::ChallengeMismatch);
+    }
+
+    if client_data.origin != config.origin {
+        return Err(PasskeyError::OriginMismatch);
+    }
+
+    Ok(())
+}
+
+/// the parsed fields of authenticator data
+struct AuthData {
+    rp_id_hash: [u8; 32],
+    flags: u8,
+    sign_count: u32,
+    attested: Option<(Vec<u8>, ciborium::Value)>,
+}
+
+impl AuthData {
+    fn parse(data: &[u8]) -> Result<Self, PasskeyError> {
+        if data.len() < 37 {
+            return Err(PasskeyError::InvalidAuthData);
+        }
+
+        let mut rp_id_hash = [0; 32];
+        rp_id_hash.copy_from_slice(&data[0..32]);
+
+        let flags = data[32];
+        let sign_count = u32::from_be_bytes([data[33], data[34], data[35], data[36]]);
+
+        let attested = if flags & FLAG_ATTESTED != 0 {
+            // 16 bytes for the aaguid followed by the length of the
+            // credential id
+            let rest = data.get(37..).ok_or(PasskeyError::InvalidAuthData)?;
+            let len_bytes = rest.get(16..18).ok_or(PasskeyError::InvalidAuthData)?;
+            let len = u16::from_be_bytes([len_bytes[0], len_bytes[1]]) as usize;
+            let credential_id = rest.get(18..(18 + len))
+                .ok_or(PasskeyError::InvalidAuthData)?
+                .to_vec();
+
+            let mut cursor = Cursor::new(&rest[(18 + len)..]);
+            let public_key: ciborium::Value = ciborium::de::from_reader(&mut cursor)
+                .map_err(|_| PasskeyError::InvalidAuthData)?;
+
+            Some((credential_id, public_key))
+        } else {
+            None
+        };
+
+        Ok(Self {
+            rp_id_hash,
+            flags,
+            sign_count,
+            attested,
+        })
+    }
+
+    /// checks the relying party and that the user was present
+    fn verify(&self, config: &config::Webauthn) -> Result<(), PasskeyError> {
+        let expected = Sha256::digest(config.rp_id.as_bytes());
+
+        if self.rp_id_hash.as_slice() != expected.as_slice() {
+            return Err(PasskeyError::RpIdMismatch);
+        }
+
+        if self.flags & FLAG_USER_PRESENT == 0 {
+            return Err(PasskeyError::UserNotPresent);
+        }
+
+        Ok(())
+    }
+}
+
+/// retrieves an integer from a cbor value
+fn cbor_int(value: &ciborium::Value) -> Option<i128> {
+    value.as_integer().map(i128::from)
+}
+
+/// converts a COSE key into the algorithm and the public key bytes expected
+/// when verifying signatures
+fn parse_cose_key(value: ciborium::Value) -> Result<(i32, Vec<u8>), PasskeyError> {
+    let ciborium::Value::Map(entries) = value else {
+        return Err(PasskeyError::UnsupportedKey);
+    };
+
+    let mut kty = None;
+    let mut alg = None;
+    let mut crv = None;
+    let mut x = None;
+    let mut y = None;
+
+    for (key, value) in entries {
+        match cbor_int(&key) {
+            Some(1) => kty = cbor_int(&value),
+            Some(3) => alg = cbor_int(&value),
+            Some(-1) => crv = cbor_int(&value),
+            Some(-2) => x = value.into_bytes().ok(),
+            Some(-3) => y = value.into_bytes().ok(),
+            _ => {}
+        }
+    }
+
+    match (kty, alg, crv, x, y) {
+        // EC2 key on the P-256 curve
+        (Some(2), Some(-7), Some(1), Some(x), Some(y)) if x.len() == 32 && y.len() == 32 => {
+            let mut key = Vec::with_capacity(65);
+            key.push(0x04);
+            key.extend(x);
+            key.extend(y);
+
+            Ok((ALG_ES256, key))
+        }
+        // OKP key on the Ed25519 curve
+        (Some(1), Some(-8), Some(6), Some(x), _) if x.len() == 32 => {
+            Ok((ALG_EDDSA, x))
+        }
+        _ => Err(PasskeyError::UnsupportedKey),
+    }
+}
+
+/// decodes a base64 url string that may or may not be padded
+pub fn decode_base64(given: &str) -> Option<Vec<u8>> {
+    URL_SAFE_NO_PAD.decode(given.trim_end_matches('=')).ok()
+}
+
+/// encodes bytes into a base64 url string without padding
+pub fn encode_base64(given: &[u8]) -> String {
+    URL_SAFE_NO_PAD.encode(given)
+}
+
+/// verifies the response of an authenticator when creating a new passkey
+pub fn verify_registration(
+    config: &config::Webauthn,
+    challenge: &Challenge,
+    client_data_json: &[u8],
+    attestation_object: &[u8],
+) -> Result<VerifiedCredential, PasskeyError> {
+    verify_client_data(config, challenge, client_data_json)?;
+
+    let attestation: ciborium::Value = ciborium::de::from_reader(attestation_object)
+        .map_err(|_| PasskeyError::InvalidAttestation)?;
+
+    let ciborium::Value::Map(entries) = attestation else {
+        return Err(PasskeyError::InvalidAttestation);
+    };
+
+    let auth_data = entries.into_iter()
+        .find(|(key, _)| key.as_text() == Some("authData"))
+        .and_then(|(_, value)| value.into_bytes().ok())
+        .ok_or(PasskeyError::InvalidAttestation)?;
+
+    let parsed = AuthData::parse(&auth_data)?;
+    parsed.verify(config)?;
+
+    let Some((credential_id, public_key)) = parsed.attested else {
+        return Err(PasskeyError::InvalidAuthData);
+    };
+
+    let (alg, public_key) = parse_cose_key(public_key)?;
+
+    Ok(VerifiedCredential {
+        credential_id,
+        public_key,
+        alg,
+        sign_count: parsed.sign_count,
+    })
+}
+
+/// verifies the response of an authenticator when logging in with a passkey
+///
+/// returns the new signature counter for the passkey
+pub fn verify_authentication(
+    config: &config::Webauthn,
+    challenge: &Challenge,
+    passkey: &Passkey,
+    client_data_json: &[u8],
+    authenticator_data: &[u8],
+    sig: &[u8],
+) -> Result<u32, PasskeyError> {
+    verify_client_data(config, challenge, client_data_json)?;
+
+    let parsed = AuthData::parse(authenticator_data)?;
+    parsed.verify(config)?;
+
+    let algorithm: &'static dyn VerificationAlgorithm = match passkey.alg {
+        ALG_ES256 => &signature::ECDSA_P256_SHA256_ASN1,
+        ALG_EDDSA => &signature::ED25519,
+        _ => return Err(PasskeyError::UnsupportedKey),
+    };
+
+    let mut message = authenticator_data.to_vec();
+    message.extend(Sha256::digest(client_data_json));
+
+    UnparsedPublicKey::new(algorithm, &passkey.public_key)
+        .verify(&message, sig)
+        .map_err(|_| PasskeyError::InvalidSignature)?;
+
+    // authenticators that do not support counters will always send 0
+    if (parsed.sign_count != 0 || passkey.sign_count != 0) &&
+        parsed.sign_count as i64 <= passkey.sign_count {
+        return Err(PasskeyError::CounterRegressed);
+    }
+
+    Ok(parsed.sign_count)
+}
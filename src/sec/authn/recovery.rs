@@ -0,0 +1,483 @@
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::str::FromStr;
+
+use bytes::BytesMut;
+use chrono::{DateTime, Duration, Utc};
+use futures::{Stream, StreamExt};
+use postgres_types as pg_types;
+use serde::{Serialize, Deserialize};
+
+use crate::db;
+use crate::db::ids::{UserId, RecoveryId};
+use crate::email::Message;
+use crate::error::{self, Context, BoxDynError};
+use crate::sec::authn::session::Token;
+
+/// the amount of time a backup email has to be verified before the token
+/// expires
+pub const VERIFY_DURATION: Duration = Duration::hours(24);
+
+/// the amount of time a recovery token is valid for
+pub const RECOVERY_DURATION: Duration = Duration::hours(1);
+
+/// the window of time used when rate limiting recovery requests
+pub const RECOVERY_WINDOW: Duration = Duration::hours(1);
+
+/// the max number of recovery requests a user can make in the recovery
+/// window
+pub const RECOVERY_MAX_REQUESTS: i64 = 3;
+
+/// hashes the given token for storage in the database
+///
+/// the raw token is only ever sent to the backup email
+fn hash_token(token: &Token) -> Vec<u8> {
+    blake3::hash(token.as_ref()).as_bytes().to_vec()
+}
+
+/// creates the message for verifying a backup email
+fn verify_message(email: &str, token: &Token) -> Message {
+    Message::new(
+        email,
+        "Verify your backup email",
+        format!(
+            "Use the following token to verify your backup email. The token \
+            expires in {} hours.\n\n{}\n",
+            VERIFY_DURATION.num_hours(),
+            token.as_base64()
+        )
+    )
+}
+
+/// creates the message for completing an account recovery
+fn recovery_message(email: &str, kind: RecoveryKind, token: &Token) -> Message {
+    Message::new(
+        email,
+        "Account recovery",
+        format!(
+            "A {kind} recovery was requested for your account. Use the \
+            following token to complete it. The token expires in {} minutes.\n\n\
+            If you did not request this then you can ignore this email.\n\n{}\n",
+            RECOVERY_DURATION.num_minutes(),
+            token.as_base64()
+        )
+    )
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("the provided string is not a valid recovery kind")]
+pub struct InvalidRecoveryKind;
+
+/// the available types of account recovery
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RecoveryKind {
+    /// resets the password of the user
+    Password,
+
+    /// removes the totp 2FA of the user. requires approval from an admin
+    /// before it can be completed
+    Totp,
+}
+
+impl RecoveryKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RecoveryKind::Password => "password",
+            RecoveryKind::Totp => "totp",
+        }
+    }
+
+    /// checks if the recovery kind requires approval from an admin
+    pub fn requires_approval(&self) -> bool {
+        matches!(self, RecoveryKind::Totp)
+    }
+}
+
+impl Display for RecoveryKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for RecoveryKind {
+    type Err = InvalidRecoveryKind;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "password" => Ok(RecoveryKind::Password),
+            "totp" => Ok(RecoveryKind::Totp),
+            _ => Err(InvalidRecoveryKind)
+        }
+    }
+}
+
+impl<'a> pg_types::FromSql<'a> for RecoveryKind {
+    fn from_sql(ty: &pg_types::Type, raw: &'a [u8]) -> Result<Self, BoxDynError> {
+        let v = <&str as pg_types::FromSql>::from_sql(ty, raw)?;
+
+        Ok(Self::from_str(v)?)
+    }
+
+    fn accepts(ty: &pg_types::Type) -> bool {
+        <&str as pg_types::FromSql>::accepts(ty)
+    }
+}
+
+impl pg_types::ToSql for RecoveryKind {
+    fn to_sql(&self, ty: &pg_types::Type, w: &mut BytesMut) -> Result<pg_types::IsNull, BoxDynError> {
+        self.as_str()
+            .to_sql(ty, w)
+    }
+
+    fn accepts(ty: &pg_types::Type) -> bool {
+        <&str as pg_types::ToSql>::accepts(ty)
+    }
+
+    pg_types::to_sql_checked!();
+}
+
+/// the secondary email of a user that is only used for account recovery
+#[derive(Debug, Serialize)]
+pub struct RecoveryEmail {
+    pub users_id: UserId,
+    pub email: String,
+    pub verified: Option<DateTime<Utc>>,
+    pub created: DateTime<Utc>,
+    pub updated: Option<DateTime<Utc>>,
+}
+
+impl RecoveryEmail {
+    /// retrieves the backup email for the specified user
+    pub async fn retrieve(conn: &impl db::GenericClient, users_id: &UserId) -> Result<Option<Self>, db::PgError> {
+        conn.query_opt(
+            "\
+            select users_id, \
+                   email, \
+                   verified, \
+                   created, \
+                   updated \
+            from authn_recovery_email \
+            where users_id = $1",
+            &[users_id]
+        )
+            .await
+            .map(|maybe| maybe.map(|row| Self {
+                users_id: row.get(0),
+                email: row.get(1),
+                verified: row.get(2),
+                created: row.get(3),
+                updated: row.get(4),
+            }))
+    }
+
+    /// sets the backup email for a user
+    ///
+    /// the email will be marked as unverified. the returned message contains
+    /// the new verification token and should be sent once the transaction is
+    /// committed
+    pub async fn set(conn: &impl db::GenericClient, users_id: &UserId, email: String) -> Result<(Self, Message), error::Error> {
+        let token = Token::new()
+            .context("failed to create verification token")?;
+        let hashed = hash_token(&token);
+        let now = Utc::now();
+        let expires = now + VERIFY_DURATION;
+
+        let row = conn.query_one(
+            "\
+            insert into authn_recovery_email (users_id, email, verify_token, verify_expires, created) values \
+            ($1, $2, $3, $4, $5) \
+            on conflict (users_id) do update \
+                set email = excluded.email, \
+                    verify_token = excluded.verify_token, \
+                    verify_expires = excluded.verify_expires, \
+                    verified = null, \
+                    updated = excluded.created \
+            returning created, updated",
+            &[users_id, &email, &hashed, &expires, &now]
+        )
+            .await
+            .context("failed to set backup email")?;
+
+        let message = verify_message(&email, &token);
+
+        Ok((Self {
+            users_id: *users_id,
+            email,
+            verified: None,
+            created: row.get(0),
+            updated: row.get(1),
+        }, message))
+    }
+
+    /// attempts to verify a backup email with the given token
+    ///
+    /// returns false if the token was not found or has expired
+    pub async fn verify(conn: &impl db::GenericClient, token: &Token) -> Result<bool, db::PgError> {
+        let hashed = hash_token(token);
+        let now = Utc::now();
+
+        let result = conn.execute(
+            "\
+            update authn_recovery_email \
+            set verified = $2, \
+                verify_token = null, \
+                verify_expires = null \
+            where verify_token = $1 and \
+                  verify_expires > $2",
+            &[&hashed, &now]
+        ).await?;
+
+        Ok(result == 1)
+    }
+
+    /// removes the backup email for the specified user
+    pub async fn delete(conn: &impl db::GenericClient, users_id: &UserId) -> Result<bool, db::PgError> {
+        let result = conn.execute(
+            "delete from authn_recovery_email where users_id = $1",
+            &[users_id]
+        ).await?;
+
+        Ok(result == 1)
+    }
+}
+
+/// the potential errors when requesting account recovery
+#[derive(Debug, thiserror::Error)]
+pub enum RecoveryError {
+    #[error("the user does not have a verified backup email")]
+    NoVerifiedEmail,
+
+    #[error("too many recovery requests have been made")]
+    RateLimited,
+
+    #[error(transparent)]
+    Error(#[from] error::Error),
+}
+
+/// a request to recover a user account
+#[derive(Debug, Serialize)]
+pub struct Recovery {
+    pub id: RecoveryId,
+    pub users_id: UserId,
+    pub kind: RecoveryKind,
+    pub issued_on: DateTime<Utc>,
+    pub expires_on: DateTime<Utc>,
+    pub approved_by: Option<UserId>,
+    pub approved_on: Option<DateTime<Utc>>,
+    pub used_on: Option<DateTime<Utc>>,
+}
+
+impl Recovery {
+    fn map_row(row: tokio_postgres::Row) -> Self {
+        Self {
+            id: row.get(0),
+            users_id: row.get(1),
+            kind: row.get(2),
+            issued_on: row.get(3),
+            expires_on: row.get(4),
+            approved_by: row.get(5),
+            approved_on: row.get(6),
+            used_on: row.get(7),
+        }
+    }
+
+    /// creates a new recovery request for the user along with the message
+    /// containing the token for the verified backup email. the message
+    /// should be sent once the transaction is committed
+    ///
+    /// a user is only allowed to make a limited number of requests in the
+    /// recovery window. must be called inside of a transaction
+    pub async fn create(
+        conn: &impl db::GenericClient,
+        users_id: &UserId,
+        kind: RecoveryKind,
+    ) -> Result<(Self, Message), RecoveryError> {
+        // prevents concurrent requests from getting past the rate limit
+        db::lock::acquire(conn, db::lock::Namespace::User, *users_id)
+            .await
+            .context("failed to acquire recovery lock for user")?;
+
+        let email = RecoveryEmail::retrieve(conn, users_id)
+            .await
+            .context("failed to retrieve backup email")?;
+
+        let Some(email) = email.filter(|e| e.verified.is_some()) else {
+            return Err(RecoveryError::NoVerifiedEmail);
+        };
+
+        let issued_on = Utc::now();
+        let window_start = issued_on - RECOVERY_WINDOW;
+
+        let count: i64 = conn.query_one(
+            "\
+            select count(*) \
+            from authn_recovery \
+            where users_id = $1 and \
+                  issued_on > $2",
+            &[users_id, &window_start]
+        )
+            .await
+            .context("failed to retrieve recovery request count")?
+            .get(0);
+
+        if count >= RECOVERY_MAX_REQUESTS {
+            return Err(RecoveryError::RateLimited);
+        }
+
+        let (recovery, token) = Self::insert(conn, users_id, kind, issued_on, None).await?;
+
+        let message = recovery_message(&email.email, kind, &token);
+
+        Ok((recovery, message))
+    }
+
+    /// issues a recovery request on behalf of an admin
+    ///
+    /// the request is approved by the admin and the token is returned
+    /// directly instead of being sent to the backup email so it can be given
+    /// to users that do not have one
+    pub async fn issue(
+        conn: &impl db::GenericClient,
+        users_id: &UserId,
+        kind: RecoveryKind,
+        issued_by: &UserId,
+    ) -> Result<(Self, Token), error::Error> {
+        Self::insert(conn, users_id, kind, Utc::now(), Some(issued_by)).await
+    }
+
+    async fn insert(
+        conn: &impl db::GenericClient,
+        users_id: &UserId,
+        kind: RecoveryKind,
+        issued_on: DateTime<Utc>,
+        approved_by: Option<&UserId>,
+    ) -> Result<(Self, Token), error::Error> {
+        let token = Token::new()
+            .context("failed to create recovery token")?;
+        let hashed = hash_token(&token);
+        let expires_on = issued_on + RECOVERY_DURATION;
+        let approved_on = approved_by.map(|_| issued_on);
+
+        let row = conn.query_one(
+            "\
+            insert into authn_recovery (token, users_id, kind, issued_on, expires_on, approved_by, approved_on) values \
+            ($1, $2, $3, $4, $5, $6, $7) \
+            returning id",
+            &[&hashed, users_id, &kind, &issued_on, &expires_on, &approved_by, &approved_on]
+        )
+            .await
+            .context("failed to create recovery request")?;
+
+        Ok((Self {
+            id: row.get(0),
+            users_id: *users_id,
+            kind,
+            issued_on,
+            expires_on,
+            approved_by: approved_by.copied(),
+            approved_on,
+            used_on: None,
+        }, token))
+    }
+
+    /// retrieves an unused and unexpired recovery request for the given
+    /// token
+    pub async fn retrieve_token(conn: &impl db::GenericClient, token: &Token) -> Result<Option<Self>, db::PgError> {
+        let hashed = hash_token(token);
+        let now = Utc::now();
+
+        conn.query_opt(
+            "\
+            select id, \
+                   users_id, \
+                   kind, \
+                   issued_on, \
+                   expires_on, \
+                   approved_by, \
+                   approved_on, \
+                   used_on \
+            from authn_recovery \
+            where token = $1 and \
+                  expires_on > $2 and \
+                  used_on is null",
+            &[&hashed, &now]
+        )
+            .await
+            .map(|maybe| maybe.map(Self::map_row))
+    }
+
+    /// retrieves the pending recovery requests for a user
+    pub async fn retrieve_pending(
+        conn: &impl db::GenericClient,
+        users_id: &UserId,
+    ) -> Result<impl Stream<Item = Result<Self, db::PgError>>, db::PgError> {
+        let now = Utc::now();
+        let params: db::ParamsArray<'_, 2> = [users_id, &now];
+
+        Ok(conn.query_raw(
+            "\
+            select id, \
+                   users_id, \
+                   kind, \
+                   issued_on, \
+                   expires_on, \
+                   approved_by, \
+                   approved_on, \
+                   used_on \
+            from authn_recovery \
+            where users_id = $1 and \
+                  expires_on > $2 and \
+                  used_on is null \
+            order by issued_on desc",
+            params
+        )
+            .await?
+            .map(|result| result.map(Self::map_row)))
+    }
+
+    /// approves a pending recovery request for the specified user
+    ///
+    /// returns false if the request was not found, has expired, or was
+    /// already used
+    pub async fn approve(
+        conn: &impl db::GenericClient,
+        users_id: &UserId,
+        recovery_id: &RecoveryId,
+        approved_by: &UserId,
+    ) -> Result<bool, db::PgError> {
+        let now = Utc::now();
+
+        let result = conn.execute(
+            "\
+            update authn_recovery \
+            set approved_by = $3, \
+                approved_on = $4 \
+            where id = $1 and \
+                  users_id = $2 and \
+                  expires_on > $4 and \
+                  used_on is null",
+            &[recovery_id, users_id, approved_by, &now]
+        ).await?;
+
+        Ok(result == 1)
+    }
+
+    /// checks if the recovery request can be completed
+    pub fn is_approved(&self) -> bool {
+        !self.kind.requires_approval() || self.approved_on.is_some()
+    }
+
+    /// marks the recovery request as used
+    pub async fn mark_used(&mut self, conn: &impl db::GenericClient) -> Result<(), db::PgError> {
+        let now = Utc::now();
+
+        conn.execute(
+            "update authn_recovery set used_on = $2 where id = $1",
+            &[&self.id, &now]
+        ).await?;
+
+        self.used_on = Some(now);
+
+        Ok(())
+    }
+}
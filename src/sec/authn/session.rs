@@ -1,4 +1,5 @@
 use std::fmt;
+use std::net::IpAddr;
 
 use axum::http::HeaderMap;
 use base64::Engine as _;
@@ -11,11 +12,27 @@ use postgres_types as pg_types;
 
 use crate::error::{self, Context, BoxDynError};
 use crate::db;
+use crate::db::ids::{SessionId, UserId};
 use crate::cookie;
 
 pub const SESSION_ID_KEY: &str = "session_id";
+
+/// the cookie that holds the csrf token of the session. it is readable by
+/// scripts so that the token can be sent back in [`CSRF_HEADER`]
+pub const CSRF_TOKEN_KEY: &str = "csrf_token";
+
+/// the header that must contain the csrf token of the session for requests
+/// that change state
+pub const CSRF_HEADER: &str = "x-csrf-token";
 pub const SESSION_TOKEN_LEN: usize = 48;
 
+/// the max number of characters kept from the user agent of a login
+pub const USER_AGENT_LEN: usize = 512;
+
+/// the minimum amount of time between updates to the last used timestamp of
+/// a session
+pub const LAST_USED_INTERVAL: Duration = Duration::minutes(1);
+
 #[derive(Debug, thiserror::Error)]
 #[error("invalid base64 string provided")]
 pub struct InvalidBase64;
@@ -47,6 +64,12 @@ impl Token {
     }
 }
 
+impl AsRef<[u8]> for Token {
+    fn as_ref(&self) -> &[u8] {
+        self.0.as_slice()
+    }
+}
+
 impl fmt::Display for Token {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         for byte in self.0 {
@@ -89,11 +112,22 @@ impl<'a> pg_types::FromSql<'a> for Token {
 #[derive(Debug, Clone)]
 pub struct Session {
     pub token: Token,
+    pub id: SessionId,
     pub users_id: db::ids::UserId,
     pub issued_on: DateTime<Utc>,
     pub expires_on: DateTime<Utc>,
     pub authenticated: bool,
     pub verified: bool,
+    pub user_agent: Option<String>,
+    pub ip_addr: Option<IpAddr>,
+    pub last_used: Option<DateTime<Utc>>,
+
+    /// a name given by the user to recognize the device of the session
+    pub name: Option<String>,
+
+    /// the token that must be sent in [`CSRF_HEADER`] for requests that
+    /// change state
+    pub csrf_token: Token,
 }
 
 pub struct SessionOptions {
@@ -101,6 +135,8 @@ pub struct SessionOptions {
     pub duration: Duration,
     pub authenticated: bool,
     pub verified: bool,
+    pub user_agent: Option<String>,
+    pub ip_addr: Option<IpAddr>,
 }
 
 impl SessionOptions {
@@ -113,8 +149,18 @@ impl SessionOptions {
             duration: Duration::days(7),
             authenticated: false,
             verified: false,
+            user_agent: None,
+            ip_addr: None,
         }
     }
+
+    /// records the user agent and address of the client that is logging in
+    pub fn client(&mut self, headers: &HeaderMap, ip_addr: IpAddr) {
+        self.user_agent = headers.get("user-agent")
+            .and_then(|value| value.to_str().ok())
+            .map(|value| value.chars().take(USER_AGENT_LEN).collect());
+        self.ip_addr = Some(ip_addr);
+    }
 }
 
 impl Session {
@@ -125,68 +171,189 @@ impl Session {
             .context("failed to add duration to expires_on")?;
         let authenticated = options.authenticated;
         let verified = options.verified;
+        let user_agent = options.user_agent;
+        let ip_addr = options.ip_addr;
+        let csrf_token = Token::new()
+            .context("failed to create csrf token")?;
         let mut attempts = 3usize;
         let mut token: Token;
+        let id: SessionId;
 
         loop {
             attempts -= 1;
             token = Token::new()
                 .context("failed to create token")?;
 
-            let result = conn.execute(
+            let result = conn.query_opt(
                 "\
-                insert into authn_sessions (token, users_id, issued_on, expires_on, authenticated, verified) values \
-                ($1, $2, $3, $4, $5, $6)",
-                &[&token, &users_id, &issued_on, &expires_on, &authenticated, &verified]
+                insert into authn_sessions (token, users_id, issued_on, expires_on, authenticated, verified, user_agent, ip_addr, csrf_token) values \
+                ($1, $2, $3, $4, $5, $6, $7, $8, $9) \
+                on conflict (token) do nothing \
+                returning id",
+                &[&token, &users_id, &issued_on, &expires_on, &authenticated, &verified, &user_agent, &ip_addr, &csrf_token]
             )
                 .await
                 .context("failed to insert session")?;
 
-            if result == 0 {
-                if attempts == 0 {
-                    return Err(error::Error::context("failed to insert session"));
-                }
-            } else {
+            if let Some(row) = result {
+                id = row.get(0);
+
                 break;
+            } else if attempts == 0 {
+                return Err(error::Error::context("failed to insert session"));
             }
         }
 
         Ok(Self {
             token,
+            id,
             users_id,
             issued_on,
             expires_on,
             authenticated,
             verified,
+            user_agent,
+            ip_addr,
+            last_used: None,
+            name: None,
+            csrf_token,
         })
     }
 
+    fn map_row(row: tokio_postgres::Row) -> Self {
+        Self {
+            token: row.get(0),
+            id: row.get(1),
+            users_id: row.get(2),
+            issued_on: row.get(3),
+            expires_on: row.get(4),
+            authenticated: row.get(5),
+            verified: row.get(6),
+            user_agent: row.get(7),
+            ip_addr: row.get(8),
+            last_used: row.get(9),
+            name: row.get(10),
+            csrf_token: row.get(11),
+        }
+    }
+
     pub async fn retrieve_token(conn: &impl db::GenericClient, token: &Token) -> Result<Option<Self>, db::PgError> {
         let maybe = conn.query_opt(
             "\
             select token, \
+                   id, \
                    users_id, \
                    issued_on, \
                    expires_on, \
                    authenticated, \
-                   verified \
+                   verified, \
+                   user_agent, \
+                   ip_addr, \
+                   last_used, \
+                   name, \
+                   csrf_token \
             from authn_sessions \
             where token = $1",
             &[token]
         ).await?;
 
-        if let Some(row) = maybe {
-            Ok(Some(Self {
-                token: row.get(0),
-                users_id: row.get(1),
-                issued_on: row.get(2),
-                expires_on: row.get(3),
-                authenticated: row.get(4),
-                verified: row.get(5),
-            }))
-        } else {
-            Ok(None)
+        Ok(maybe.map(Self::map_row))
+    }
+
+    /// retrieves the unexpired sessions of a user with the most recently used
+    /// first
+    pub async fn retrieve_user(conn: &impl db::GenericClient, users_id: &UserId) -> Result<Vec<Self>, db::PgError> {
+        let rows = conn.query(
+            "\
+            select token, \
+                   id, \
+                   users_id, \
+                   issued_on, \
+                   expires_on, \
+                   authenticated, \
+                   verified, \
+                   user_agent, \
+                   ip_addr, \
+                   last_used, \
+                   name, \
+                   csrf_token \
+            from authn_sessions \
+            where users_id = $1 and \
+                  expires_on > now() \
+            order by coalesce(last_used, issued_on) desc",
+            &[users_id]
+        ).await?;
+
+        Ok(rows.into_iter().map(Self::map_row).collect())
+    }
+
+    /// updates the last used timestamp if enough time has passed since the
+    /// previous update
+    pub async fn touch(&mut self, conn: &impl db::GenericClient) -> Result<(), db::PgError> {
+        let now = Utc::now();
+
+        if self.last_used.is_some_and(|last_used| now - last_used < LAST_USED_INTERVAL) {
+            return Ok(());
         }
+
+        conn.execute(
+            "update authn_sessions set last_used = $2 where token = $1",
+            &[&self.token, &now]
+        ).await?;
+
+        self.last_used = Some(now);
+
+        Ok(())
+    }
+
+    /// sets the name of a session of a user by its id
+    pub async fn update_name(
+        conn: &impl db::GenericClient,
+        users_id: &UserId,
+        id: &SessionId,
+        name: Option<&str>,
+    ) -> Result<Option<Self>, db::PgError> {
+        let maybe = conn.query_opt(
+            "\
+            update authn_sessions \
+            set name = $3 \
+            where users_id = $1 and \
+                  id = $2 and \
+                  expires_on > now() \
+            returning token, \
+                      id, \
+                      users_id, \
+                      issued_on, \
+                      expires_on, \
+                      authenticated, \
+                      verified, \
+                      user_agent, \
+                      ip_addr, \
+                      last_used, \
+                      name, \
+                      csrf_token",
+            &[users_id, id, &name]
+        ).await?;
+
+        Ok(maybe.map(Self::map_row))
+    }
+
+    /// deletes a session of a user by its id
+    pub async fn delete_id(conn: &impl db::GenericClient, users_id: &UserId, id: &SessionId) -> Result<bool, db::PgError> {
+        let result = conn.execute(
+            "delete from authn_sessions where users_id = $1 and id = $2",
+            &[users_id, id]
+        ).await?;
+
+        Ok(result == 1)
+    }
+
+    /// deletes all sessions of the user except for this one
+    pub async fn delete_others(&self, conn: &impl db::GenericClient) -> Result<u64, db::PgError> {
+        conn.execute(
+            "delete from authn_sessions where users_id = $1 and token != $2",
+            &[&self.users_id, &self.token]
+        ).await
     }
 
     pub async fn delete(&self, conn: &impl db::GenericClient) -> Result<bool, db::PgError> {
@@ -207,6 +374,31 @@ impl Session {
             .with_same_site(cookie::SameSite::Strict)
     }
 
+    /// the cookie that lets the client read the csrf token of the session
+    pub fn build_csrf_cookie(&self) -> cookie::SetCookie {
+        cookie::SetCookie::new(CSRF_TOKEN_KEY, self.csrf_token.as_base64())
+            .with_expires(self.expires_on)
+            .with_path("/")
+            .with_secure(true)
+            .with_same_site(cookie::SameSite::Strict)
+    }
+
+    pub fn clear_csrf_cookie() -> cookie::SetCookie {
+        cookie::SetCookie::new(CSRF_TOKEN_KEY, "")
+            .with_max_age(std::time::Duration::from_secs(0))
+            .with_path("/")
+            .with_secure(true)
+            .with_same_site(cookie::SameSite::Strict)
+    }
+
+    /// checks the given token against the csrf token of the session without
+    /// exiting early on the first differing byte
+    pub fn check_csrf(&self, given: &Token) -> bool {
+        self.csrf_token.0.iter()
+            .zip(given.0.iter())
+            .fold(0u8, |diff, (a, b)| diff | (a ^ b)) == 0
+    }
+
     pub fn clear_cookie() -> cookie::SetCookie {
         cookie::SetCookie::new(SESSION_ID_KEY, "")
             .with_max_age(std::time::Duration::from_secs(0))
@@ -94,6 +94,9 @@ pub enum Scope {
     Journals,
     Entries,
     Roles,
+    Workspaces,
+    Reports,
+    Server,
 }
 
 impl Scope {
@@ -104,6 +107,9 @@ impl Scope {
             Scope::Journals => "journals",
             Scope::Entries => "entries",
             Scope::Roles => "roles",
+            Scope::Workspaces => "workspaces",
+            Scope::Reports => "reports",
+            Scope::Server => "server",
         }
     }
 }
@@ -124,6 +130,9 @@ impl FromStr for Scope {
             "journals" => Ok(Scope::Journals),
             "entries" => Ok(Scope::Entries),
             "roles" => Ok(Scope::Roles),
+            "workspaces" => Ok(Scope::Workspaces),
+            "reports" => Ok(Scope::Reports),
+            "server" => Ok(Scope::Server),
             _ => Err(InvalidScope),
         }
     }
@@ -230,6 +239,28 @@ impl Role {
             }))
     }
 
+    pub async fn retrieve_name(conn: &impl db::GenericClient, name: &str) -> Result<Option<Self>, db::PgError> {
+        conn.query_opt(
+            "\
+            select authz_roles.id, \
+                   authz_roles.uid, \
+                   authz_roles.name, \
+                   authz_roles.created, \
+                   authz_roles.updated \
+            from authz_roles \
+            where authz_roles.name = $1",
+            &[&name]
+        )
+            .await
+            .map(|result| result.map(|row| Self {
+                id: row.get(0),
+                uid: row.get(1),
+                name: row.get(2),
+                created: row.get(3),
+                updated: row.get(4),
+            }))
+    }
+
     pub async fn create(conn: &impl db::GenericClient, name: &str) -> Result<Option<Self>, db::PgError> {
         let uid = RoleUid::gen();
         let created = Utc::now();
@@ -0,0 +1,569 @@
+//! encryption at rest for journal files
+//!
+//! every journal has its own randomly generated key that is stored in the
+//! database wrapped by the master key of the server. files are split into
+//! chunks that are sealed separately so that a range of a file can be
+//! decrypted without reading the entire file. the nonce of a chunk is made
+//! from a random prefix stored in the file header, the index of the chunk,
+//! and a flag for the final chunk so that chunks cannot be reordered or
+//! truncated without failing to open
+
+use std::io::{Read, Write};
+use std::path::Path;
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use bytes::Bytes;
+use chrono::Utc;
+use futures::Stream;
+use rand::RngCore;
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM, NONCE_LEN};
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+use crate::db::{GenericClient, PgError};
+use crate::db::ids::JournalId;
+use crate::error::{self, Context};
+
+/// the number of bytes in a master or journal key
+pub const KEY_LEN: usize = 32;
+
+/// the max number of plaintext bytes in a chunk
+pub const CHUNK_SIZE: usize = 64 * 1024;
+
+/// the number of bytes added to each sealed chunk
+const TAG_LEN: usize = 16;
+
+/// identifies an encrypted file
+const MAGIC: &[u8; 4] = b"TJ2E";
+
+/// the version of the file format
+const VERSION: u8 = 1;
+
+/// the number of random bytes used for the nonces of a file
+const PREFIX_LEN: usize = 7;
+
+/// the number of bytes before the first chunk of a file
+pub const HEADER_LEN: usize = MAGIC.len() + 1 + PREFIX_LEN;
+
+#[derive(Debug, thiserror::Error)]
+pub enum EncryptionError {
+    #[error("the encrypted file header is invalid")]
+    InvalidHeader,
+
+    #[error("failed to open encrypted chunk")]
+    Open,
+
+    #[error("failed to seal chunk")]
+    Seal,
+
+    #[error("the wrapped journal key is invalid")]
+    InvalidWrappedKey,
+
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+
+    #[error(transparent)]
+    Rand(#[from] rand::Error),
+}
+
+impl From<EncryptionError> for std::io::Error {
+    fn from(err: EncryptionError) -> Self {
+        match err {
+            EncryptionError::Io(err) => err,
+            err => std::io::Error::new(std::io::ErrorKind::InvalidData, err),
+        }
+    }
+}
+
+fn aead_key(bytes: &[u8; KEY_LEN]) -> LessSafeKey {
+    LessSafeKey::new(UnboundKey::new(&AES_256_GCM, bytes).expect("invalid key length for AES_256_GCM"))
+}
+
+/// the server key used to wrap the keys of journals
+#[derive(Clone)]
+pub struct MasterKey {
+    key: LessSafeKey,
+    fingerprint: String,
+}
+
+impl std::fmt::Debug for MasterKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MasterKey")
+            .field("fingerprint", &self.fingerprint)
+            .finish_non_exhaustive()
+    }
+}
+
+impl MasterKey {
+    fn from_bytes(bytes: &[u8; KEY_LEN]) -> Self {
+        let fingerprint = blake3::hash(bytes).to_hex()[..16].to_owned();
+
+        MasterKey {
+            key: aead_key(bytes),
+            fingerprint,
+        }
+    }
+
+    /// creates a new random key along with its base64 encoded bytes that
+    /// can be written to a key file
+    pub fn generate() -> Result<(Self, String), EncryptionError> {
+        let mut bytes = [0; KEY_LEN];
+
+        rand::thread_rng().try_fill_bytes(&mut bytes)?;
+
+        Ok((Self::from_bytes(&bytes), STANDARD.encode(bytes)))
+    }
+
+    /// loads the key from a file containing the base64 encoded bytes of the
+    /// key. ex: the output of `openssl rand -base64 32`
+    pub fn load(path: &Path) -> Result<Self, error::Error> {
+        let contents = std::fs::read_to_string(path)
+            .context("failed to read master key file")?;
+        let decoded = STANDARD.decode(contents.trim())
+            .context("master key file is not valid base64")?;
+        let bytes: [u8; KEY_LEN] = decoded.try_into()
+            .map_err(|_| error::Error::context(format!("master key must be {KEY_LEN} bytes")))?;
+
+        Ok(Self::from_bytes(&bytes))
+    }
+
+    /// a short hex string that identifies the key without revealing it
+    pub fn fingerprint(&self) -> &str {
+        &self.fingerprint
+    }
+
+    fn wrap(&self, journals_id: &JournalId, key: &[u8; KEY_LEN]) -> Result<Vec<u8>, EncryptionError> {
+        let mut nonce = [0; NONCE_LEN];
+
+        rand::thread_rng().try_fill_bytes(&mut nonce)?;
+
+        let mut sealed = key.to_vec();
+
+        self.key.seal_in_place_append_tag(
+            Nonce::assume_unique_for_key(nonce),
+            Aad::from(journals_id.inner().to_be_bytes()),
+            &mut sealed
+        ).map_err(|_| EncryptionError::Seal)?;
+
+        let mut rtn = Vec::with_capacity(NONCE_LEN + sealed.len());
+        rtn.extend_from_slice(&nonce);
+        rtn.extend_from_slice(&sealed);
+
+        Ok(rtn)
+    }
+
+    fn open_wrapped(&self, journals_id: &JournalId, wrapped: &[u8]) -> Result<[u8; KEY_LEN], EncryptionError> {
+        if wrapped.len() != NONCE_LEN + KEY_LEN + TAG_LEN {
+            return Err(EncryptionError::InvalidWrappedKey);
+        }
+
+        let (nonce, sealed) = wrapped.split_at(NONCE_LEN);
+        let nonce = Nonce::try_assume_unique_for_key(nonce)
+            .map_err(|_| EncryptionError::InvalidWrappedKey)?;
+        let mut sealed = sealed.to_vec();
+
+        let opened = self.key.open_in_place(
+            nonce,
+            Aad::from(journals_id.inner().to_be_bytes()),
+            &mut sealed
+        ).map_err(|_| EncryptionError::InvalidWrappedKey)?;
+
+        (&*opened).try_into()
+            .map_err(|_| EncryptionError::InvalidWrappedKey)
+    }
+
+    fn unwrap(&self, journals_id: &JournalId, wrapped: &[u8]) -> Result<JournalKey, EncryptionError> {
+        let bytes = self.open_wrapped(journals_id, wrapped)?;
+
+        Ok(JournalKey(aead_key(&bytes)))
+    }
+
+    /// checks if the wrapped key of a journal was wrapped by this key
+    pub fn can_unwrap(&self, journals_id: &JournalId, wrapped: &[u8]) -> bool {
+        self.open_wrapped(journals_id, wrapped).is_ok()
+    }
+
+    /// unwraps the key of a journal with this key and wraps it again with
+    /// the given key. the key of the journal is not changed so the files of
+    /// the journal do not need to be encrypted again
+    pub fn rewrap(&self, to: &MasterKey, journals_id: &JournalId, wrapped: &[u8]) -> Result<Vec<u8>, EncryptionError> {
+        let bytes = self.open_wrapped(journals_id, wrapped)?;
+
+        to.wrap(journals_id, &bytes)
+    }
+}
+
+/// the key used to encrypt the files of a single journal
+#[derive(Clone)]
+pub struct JournalKey(LessSafeKey);
+
+impl std::fmt::Debug for JournalKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("JournalKey")
+    }
+}
+
+impl JournalKey {
+    /// retrieves the key of a journal, creating it if the journal does not
+    /// have one
+    pub async fn retrieve_or_create(
+        conn: &impl GenericClient,
+        master: &MasterKey,
+        journals_id: &JournalId,
+    ) -> Result<Self, error::Error> {
+        if let Some(found) = retrieve_wrapped(conn, journals_id).await.context("failed to retrieve journal key")? {
+            return master.unwrap(journals_id, &found)
+                .context("failed to unwrap journal key");
+        }
+
+        let mut bytes = [0; KEY_LEN];
+
+        rand::thread_rng().try_fill_bytes(&mut bytes)
+            .context("failed to generate journal key")?;
+
+        let wrapped = master.wrap(journals_id, &bytes)
+            .context("failed to wrap journal key")?;
+
+        // another request may have created the key at the same time so the
+        // stored key is the one that is used
+        let stored = store_wrapped(conn, journals_id, wrapped)
+            .await
+            .context("failed to store journal key")?;
+
+        master.unwrap(journals_id, &stored)
+            .context("failed to unwrap journal key")
+    }
+
+    /// creates the nonce for a chunk of a file
+    fn chunk_nonce(prefix: &[u8; PREFIX_LEN], index: u32, last: bool) -> Nonce {
+        let mut nonce = [0; NONCE_LEN];
+        nonce[..PREFIX_LEN].copy_from_slice(prefix);
+        nonce[PREFIX_LEN..NONCE_LEN - 1].copy_from_slice(&index.to_be_bytes());
+        nonce[NONCE_LEN - 1] = last as u8;
+
+        Nonce::assume_unique_for_key(nonce)
+    }
+
+    fn seal_chunk(&self, prefix: &[u8; PREFIX_LEN], index: u32, last: bool, data: &mut Vec<u8>) -> Result<(), EncryptionError> {
+        self.0.seal_in_place_append_tag(
+            Self::chunk_nonce(prefix, index, last),
+            Aad::empty(),
+            data
+        ).map_err(|_| EncryptionError::Seal)
+    }
+
+    fn open_chunk<'a>(&self, prefix: &[u8; PREFIX_LEN], index: u32, last: bool, data: &'a mut [u8]) -> Result<&'a mut [u8], EncryptionError> {
+        self.0.open_in_place(
+            Self::chunk_nonce(prefix, index, last),
+            Aad::empty(),
+            data
+        ).map_err(|_| EncryptionError::Open)
+    }
+
+    /// encrypts the entire contents of a small file
+    pub fn encrypt_all(&self, data: &[u8]) -> Result<Vec<u8>, EncryptionError> {
+        let mut encryptor = Encryptor::new(self.clone())?;
+        let mut rtn = encryptor.update(data)?;
+
+        rtn.extend(encryptor.finish()?);
+
+        Ok(rtn)
+    }
+
+    /// decrypts the entire contents of a small file
+    pub fn decrypt_all(&self, data: &[u8]) -> Result<Vec<u8>, EncryptionError> {
+        let mut rtn = Vec::with_capacity(data.len());
+
+        self.decrypt_copy(&mut &data[..], &mut rtn)?;
+
+        Ok(rtn)
+    }
+
+    /// decrypts a file from the reader and writes the plaintext to the
+    /// writer
+    pub fn decrypt_copy<R, W>(&self, reader: &mut R, writer: &mut W) -> Result<u64, EncryptionError>
+    where
+        R: Read,
+        W: Write,
+    {
+        let mut header = [0; HEADER_LEN];
+        reader.read_exact(&mut header)
+            .map_err(|_| EncryptionError::InvalidHeader)?;
+        let prefix = parse_header(&header)?;
+
+        let mut current = Vec::with_capacity(CHUNK_SIZE + TAG_LEN);
+        let mut next = Vec::with_capacity(CHUNK_SIZE + TAG_LEN);
+        let mut index = 0u32;
+        let mut written = 0u64;
+
+        read_full(reader, &mut current)?;
+
+        loop {
+            // the next chunk is read first since a full chunk can still be
+            // the last one
+            let last = if current.len() < CHUNK_SIZE + TAG_LEN {
+                true
+            } else {
+                read_full(reader, &mut next)?;
+
+                next.is_empty()
+            };
+
+            let opened = self.open_chunk(&prefix, index, last, &mut current)?;
+
+            writer.write_all(opened)?;
+            written += opened.len() as u64;
+
+            if last {
+                break;
+            }
+
+            std::mem::swap(&mut current, &mut next);
+            index = index.checked_add(1).ok_or(EncryptionError::Open)?;
+        }
+
+        Ok(written)
+    }
+
+    /// creates a stream of the plaintext bytes in the inclusive range of an
+    /// encrypted file
+    ///
+    /// size is the number of plaintext bytes in the file which is used to
+    /// find the chunks that contain the range
+    pub fn decrypt_range<R>(
+        &self,
+        reader: R,
+        size: u64,
+        start: u64,
+        end: u64,
+    ) -> impl Stream<Item = Result<Bytes, std::io::Error>>
+    where
+        R: AsyncRead + tokio::io::AsyncSeek + Unpin,
+    {
+        let chunk = CHUNK_SIZE as u64;
+        let last_index = size.saturating_sub(1) / chunk;
+        let state = RangeState {
+            key: self.clone(),
+            reader,
+            prefix: None,
+            index: start / chunk,
+            last_index,
+            size,
+            position: start,
+            end,
+        };
+
+        futures::stream::try_unfold(state, move |mut state| async move {
+            if state.position > state.end || state.position >= state.size {
+                return Ok(None);
+            }
+
+            let prefix = match state.prefix {
+                Some(prefix) => prefix,
+                None => {
+                    let mut header = [0; HEADER_LEN];
+                    state.reader.read_exact(&mut header)
+                        .await
+                        .map_err(|_| EncryptionError::InvalidHeader)?;
+                    let prefix = parse_header(&header)?;
+
+                    let offset = HEADER_LEN as u64 + state.index * (chunk + TAG_LEN as u64);
+
+                    tokio::io::AsyncSeekExt::seek(&mut state.reader, std::io::SeekFrom::Start(offset))
+                        .await?;
+
+                    state.prefix = Some(prefix);
+
+                    prefix
+                }
+            };
+
+            let chunk_start = state.index * chunk;
+            let chunk_len = (state.size - chunk_start).min(chunk) as usize;
+            let mut data = vec![0; chunk_len + TAG_LEN];
+
+            state.reader.read_exact(&mut data).await?;
+
+            let index: u32 = state.index.try_into()
+                .map_err(|_| EncryptionError::Open)?;
+            let opened = state.key.open_chunk(&prefix, index, state.index == state.last_index, &mut data)?;
+
+            let from = (state.position - chunk_start) as usize;
+            let to = ((state.end - chunk_start) as usize).min(opened.len().saturating_sub(1));
+            let bytes = Bytes::copy_from_slice(&opened[from..=to]);
+
+            state.position = chunk_start + to as u64 + 1;
+            state.index += 1;
+
+            Ok::<_, std::io::Error>(Some((bytes, state)))
+        })
+    }
+}
+
+struct RangeState<R> {
+    key: JournalKey,
+    reader: R,
+    prefix: Option<[u8; PREFIX_LEN]>,
+    index: u64,
+    last_index: u64,
+    size: u64,
+    position: u64,
+    end: u64,
+}
+
+fn parse_header(header: &[u8; HEADER_LEN]) -> Result<[u8; PREFIX_LEN], EncryptionError> {
+    if &header[..MAGIC.len()] != MAGIC || header[MAGIC.len()] != VERSION {
+        return Err(EncryptionError::InvalidHeader);
+    }
+
+    let mut prefix = [0; PREFIX_LEN];
+    prefix.copy_from_slice(&header[MAGIC.len() + 1..]);
+
+    Ok(prefix)
+}
+
+/// reads a full sealed chunk or until the end of the reader
+fn read_full<R>(reader: &mut R, buf: &mut Vec<u8>) -> Result<(), EncryptionError>
+where
+    R: Read,
+{
+    buf.clear();
+
+    (&mut *reader).take((CHUNK_SIZE + TAG_LEN) as u64)
+        .read_to_end(buf)?;
+
+    Ok(())
+}
+
+/// encrypts a file as it is received
+///
+/// the final chunk is held until finish is called since a chunk cannot be
+/// sealed until it is known if it is the last one
+pub struct Encryptor {
+    key: JournalKey,
+    prefix: [u8; PREFIX_LEN],
+    index: u32,
+    header_sent: bool,
+    buffer: Vec<u8>,
+}
+
+impl Encryptor {
+    pub fn new(key: JournalKey) -> Result<Self, EncryptionError> {
+        let mut prefix = [0; PREFIX_LEN];
+
+        rand::thread_rng().try_fill_bytes(&mut prefix)?;
+
+        Ok(Encryptor {
+            key,
+            prefix,
+            index: 0,
+            header_sent: false,
+            buffer: Vec::with_capacity(CHUNK_SIZE),
+        })
+    }
+
+    fn header(&mut self, output: &mut Vec<u8>) {
+        if !self.header_sent {
+            output.extend_from_slice(MAGIC);
+            output.push(VERSION);
+            output.extend_from_slice(&self.prefix);
+
+            self.header_sent = true;
+        }
+    }
+
+    /// adds data to the file and returns the bytes that are ready to be
+    /// written
+    pub fn update(&mut self, mut data: &[u8]) -> Result<Vec<u8>, EncryptionError> {
+        let mut output = Vec::new();
+
+        self.header(&mut output);
+
+        while !data.is_empty() {
+            if self.buffer.len() == CHUNK_SIZE {
+                let mut chunk = std::mem::replace(&mut self.buffer, Vec::with_capacity(CHUNK_SIZE));
+
+                self.key.seal_chunk(&self.prefix, self.index, false, &mut chunk)?;
+                self.index = self.index.checked_add(1).ok_or(EncryptionError::Seal)?;
+
+                output.extend(chunk);
+            }
+
+            let take = (CHUNK_SIZE - self.buffer.len()).min(data.len());
+
+            self.buffer.extend_from_slice(&data[..take]);
+            data = &data[take..];
+        }
+
+        Ok(output)
+    }
+
+    /// seals the final chunk and returns the remaining bytes of the file
+    pub fn finish(mut self) -> Result<Vec<u8>, EncryptionError> {
+        let mut output = Vec::new();
+
+        self.header(&mut output);
+
+        let mut chunk = std::mem::take(&mut self.buffer);
+
+        self.key.seal_chunk(&self.prefix, self.index, true, &mut chunk)?;
+
+        output.extend(chunk);
+
+        Ok(output)
+    }
+}
+
+/// retrieves the wrapped key of a journal
+async fn retrieve_wrapped(conn: &impl GenericClient, journals_id: &JournalId) -> Result<Option<Vec<u8>>, PgError> {
+    conn.query_opt(
+        "\
+        select journal_keys.wrapped \
+        from journal_keys \
+        where journal_keys.journals_id = $1",
+        &[journals_id]
+    )
+        .await
+        .map(|maybe| maybe.map(|row| row.get(0)))
+}
+
+/// retrieves the wrapped keys of every journal ordered by journal
+pub async fn retrieve_all_wrapped(conn: &impl GenericClient) -> Result<Vec<(JournalId, Vec<u8>)>, PgError> {
+    conn.query(
+        "\
+        select journal_keys.journals_id, \
+               journal_keys.wrapped \
+        from journal_keys \
+        order by journal_keys.journals_id",
+        &[]
+    )
+        .await
+        .map(|rows| rows.into_iter().map(|row| (row.get(0), row.get(1))).collect())
+}
+
+/// replaces the wrapped key of a journal
+pub async fn replace_wrapped(conn: &impl GenericClient, journals_id: &JournalId, wrapped: &[u8]) -> Result<(), PgError> {
+    conn.execute(
+        "update journal_keys set wrapped = $2 where journals_id = $1",
+        &[journals_id, &wrapped]
+    ).await?;
+
+    Ok(())
+}
+
+/// stores the wrapped key of a journal. if the journal already has a key
+/// then the existing key is returned
+async fn store_wrapped(conn: &impl GenericClient, journals_id: &JournalId, wrapped: Vec<u8>) -> Result<Vec<u8>, PgError> {
+    let created = Utc::now();
+
+    conn.query_one(
+        "\
+        insert into journal_keys (journals_id, wrapped, created) values ($1, $2, $3) \
+        on conflict (journals_id) do update \
+            set journals_id = journal_keys.journals_id \
+        returning wrapped",
+        &[journals_id, &wrapped, &created]
+    )
+        .await
+        .map(|row| row.get(0))
+}
@@ -0,0 +1,332 @@
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::sync::Arc;
+
+use axum::http::HeaderMap;
+use ipnet::IpNet;
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+use url::{Host, Url};
+
+use crate::config;
+
+/// retrieves the ip address of the client that made the request
+///
+/// if the connecting peer is a trusted proxy then the "x-forwarded-for"
+/// header will be walked from right to left until an address that is not a
+/// trusted proxy is found. if the header is missing or malformed then the
+/// peer address will be used
+pub fn client_ip(network: &config::Network, peer: &SocketAddr, headers: &HeaderMap) -> IpAddr {
+    let peer_ip = peer.ip().to_canonical();
+
+    if !is_trusted(network, &peer_ip) {
+        return peer_ip;
+    }
+
+    let mut forwarded = Vec::new();
+
+    for value in headers.get_all("x-forwarded-for") {
+        let Ok(value_str) = value.to_str() else {
+            return peer_ip;
+        };
+
+        forwarded.extend(value_str.split(','));
+    }
+
+    let mut client = peer_ip;
+
+    for addr in forwarded.into_iter().rev() {
+        let Ok(ip) = addr.trim().parse::<IpAddr>() else {
+            return peer_ip;
+        };
+
+        client = ip.to_canonical();
+
+        if !is_trusted(network, &client) {
+            return client;
+        }
+    }
+
+    client
+}
+
+/// checks if the given address is a trusted proxy
+fn is_trusted(network: &config::Network, addr: &IpAddr) -> bool {
+    network.trusted_proxies.iter().any(|net| net.contains(addr))
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum OutboundError {
+    #[error("the url does not have a host")]
+    MissingHost,
+
+    #[error("failed to resolve host")]
+    Resolve(#[from] std::io::Error),
+
+    #[error("the host resolves to an address that is not allowed")]
+    NotAllowed,
+}
+
+/// checks if the given address is reachable on the public internet
+///
+/// unspecified, loopback, private, link-local (including the cloud metadata
+/// address 169.254.169.254), shared, multicast, documentation, and reserved
+/// addresses are not public. ipv6 addresses that embed an ipv4 address are
+/// checked using the embedded address
+pub fn is_public(addr: &IpAddr) -> bool {
+    match addr.to_canonical() {
+        IpAddr::V4(v4) => {
+            let [a, b, c, _] = v4.octets();
+
+            !(a == 0 ||
+                v4.is_loopback() ||
+                v4.is_private() ||
+                v4.is_link_local() ||
+                v4.is_multicast() ||
+                v4.is_broadcast() ||
+                v4.is_documentation() ||
+                // 100.64.0.0/10 shared address space
+                (a == 100 && (b & 0xc0) == 64) ||
+                // 192.0.0.0/24 protocol assignments
+                (a == 192 && b == 0 && c == 0) ||
+                // 198.18.0.0/15 benchmarking
+                (a == 198 && (b & 0xfe) == 18) ||
+                // 240.0.0.0/4 reserved
+                a >= 240)
+        }
+        IpAddr::V6(v6) => {
+            let seg = v6.segments();
+
+            // 64:ff9b::/96 nat64
+            if seg[0] == 0x64 && seg[1] == 0xff9b && seg[2..6] == [0, 0, 0, 0] {
+                return is_public(&IpAddr::V4(embedded_v4(seg[6], seg[7])));
+            }
+
+            // 2002::/16 6to4
+            if seg[0] == 0x2002 {
+                return is_public(&IpAddr::V4(embedded_v4(seg[1], seg[2])));
+            }
+
+            !(v6.is_unspecified() ||
+                v6.is_loopback() ||
+                v6.is_multicast() ||
+                // fc00::/7 unique local
+                (seg[0] & 0xfe00) == 0xfc00 ||
+                // fe80::/10 link-local
+                (seg[0] & 0xffc0) == 0xfe80 ||
+                // 2001:db8::/32 documentation
+                (seg[0] == 0x2001 && seg[1] == 0xdb8))
+        }
+    }
+}
+
+fn embedded_v4(high: u16, low: u16) -> Ipv4Addr {
+    let [a, b] = high.to_be_bytes();
+    let [c, d] = low.to_be_bytes();
+
+    Ipv4Addr::new(a, b, c, d)
+}
+
+/// checks if a request to a user provided url is allowed to reach the given
+/// address. public addresses are always allowed and private ones only when
+/// listed in the allow list
+pub fn is_outbound_allowed(allow: &[IpNet], addr: &IpAddr) -> bool {
+    let addr = addr.to_canonical();
+
+    is_public(&addr) || allow.iter().any(|net| net.contains(&addr))
+}
+
+/// resolves the host of the url and checks that every address it resolves to
+/// is allowed
+pub async fn check_url(allow: &[IpNet], url: &Url) -> Result<(), OutboundError> {
+    let port = url.port_or_known_default().unwrap_or(0);
+
+    let addrs: Vec<SocketAddr> = match url.host() {
+        Some(Host::Domain(domain)) => tokio::net::lookup_host((domain, port))
+            .await?
+            .collect(),
+        Some(Host::Ipv4(ip)) => vec![SocketAddr::new(ip.into(), port)],
+        Some(Host::Ipv6(ip)) => vec![SocketAddr::new(ip.into(), port)],
+        None => return Err(OutboundError::MissingHost),
+    };
+
+    if addrs.is_empty() || !addrs.iter().all(|addr| is_outbound_allowed(allow, &addr.ip())) {
+        Err(OutboundError::NotAllowed)
+    } else {
+        Ok(())
+    }
+}
+
+/// the dns resolver for the client that sends requests to user provided urls
+///
+/// addresses that are not allowed are dropped when the connection is made so
+/// a host that changes what it resolves to after being checked still cannot
+/// reach a private address
+#[derive(Debug)]
+pub struct OutboundResolver {
+    allow: Arc<Vec<IpNet>>,
+}
+
+impl OutboundResolver {
+    pub fn new(allow: Vec<IpNet>) -> Self {
+        Self {
+            allow: Arc::new(allow),
+        }
+    }
+}
+
+impl Resolve for OutboundResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        let allow = self.allow.clone();
+        let host = name.as_str().to_owned();
+
+        Box::pin(async move {
+            let addrs: Vec<SocketAddr> = tokio::net::lookup_host((host.as_str(), 0))
+                .await?
+                .filter(|addr| is_outbound_allowed(&allow, &addr.ip()))
+                .collect();
+
+            if addrs.is_empty() {
+                return Err(OutboundError::NotAllowed.into());
+            }
+
+            Ok(Box::new(addrs.into_iter()) as Addrs)
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn network(trusted: &[&str]) -> config::Network {
+        config::Network {
+            trusted_proxies: trusted.iter()
+                .map(|net| net.parse().unwrap())
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    fn forwarded(values: &[&str]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+
+        for value in values {
+            headers.append("x-forwarded-for", value.parse().unwrap());
+        }
+
+        headers
+    }
+
+    fn peer(addr: &str) -> SocketAddr {
+        SocketAddr::new(addr.parse().unwrap(), 443)
+    }
+
+    #[test]
+    fn untrusted_peer_ignores_header() {
+        let network = network(&["10.0.0.0/8"]);
+        let headers = forwarded(&["203.0.113.5"]);
+
+        assert_eq!(
+            client_ip(&network, &peer("198.51.100.7"), &headers),
+            "198.51.100.7".parse::<IpAddr>().unwrap()
+        );
+    }
+
+    #[test]
+    fn trusted_proxy_walks_right_to_left() {
+        let network = network(&["10.0.0.0/8"]);
+        let headers = forwarded(&["203.0.113.5, 10.0.0.2"]);
+
+        assert_eq!(
+            client_ip(&network, &peer("10.0.0.1"), &headers),
+            "203.0.113.5".parse::<IpAddr>().unwrap()
+        );
+    }
+
+    #[test]
+    fn spoofed_leftmost_entry_is_ignored() {
+        let network = network(&["10.0.0.0/8"]);
+        // the client sent its own header with a made up address and the
+        // proxy appended the address it saw
+        let headers = forwarded(&["1.2.3.4, 198.51.100.7", "10.0.0.2"]);
+
+        assert_eq!(
+            client_ip(&network, &peer("10.0.0.1"), &headers),
+            "198.51.100.7".parse::<IpAddr>().unwrap()
+        );
+    }
+
+    #[test]
+    fn malformed_header_uses_peer() {
+        let network = network(&["10.0.0.0/8"]);
+        let headers = forwarded(&["203.0.113.5, not-an-ip"]);
+
+        assert_eq!(
+            client_ip(&network, &peer("10.0.0.1"), &headers),
+            "10.0.0.1".parse::<IpAddr>().unwrap()
+        );
+    }
+
+    #[test]
+    fn missing_header_uses_peer() {
+        let network = network(&["10.0.0.0/8"]);
+
+        assert_eq!(
+            client_ip(&network, &peer("10.0.0.1"), &HeaderMap::new()),
+            "10.0.0.1".parse::<IpAddr>().unwrap()
+        );
+    }
+
+    #[test]
+    fn all_trusted_uses_leftmost() {
+        let network = network(&["10.0.0.0/8"]);
+        let headers = forwarded(&["10.0.0.3, 10.0.0.2"]);
+
+        assert_eq!(
+            client_ip(&network, &peer("10.0.0.1"), &headers),
+            "10.0.0.3".parse::<IpAddr>().unwrap()
+        );
+    }
+
+    #[test]
+    fn mapped_ipv4_peer_is_trusted() {
+        let network = network(&["10.0.0.0/8"]);
+        let headers = forwarded(&["203.0.113.5"]);
+
+        assert_eq!(
+            client_ip(&network, &peer("::ffff:10.0.0.1"), &headers),
+            "203.0.113.5".parse::<IpAddr>().unwrap()
+        );
+    }
+
+    #[test]
+    fn public_addresses() {
+        let public = ["8.8.8.8", "2606:4700::1111", "64:ff9b::808:808"];
+        let private = [
+            "0.0.0.0",
+            "127.0.0.1",
+            "10.1.2.3",
+            "172.16.0.1",
+            "192.168.1.1",
+            "169.254.169.254",
+            "100.64.0.1",
+            "198.18.0.1",
+            "240.0.0.1",
+            "::1",
+            "::",
+            "fc00::1",
+            "fe80::1",
+            "2001:db8::1",
+            "::ffff:127.0.0.1",
+            "64:ff9b::7f00:1",
+            "2002:a00:1::",
+        ];
+
+        for addr in public {
+            assert!(is_public(&addr.parse().unwrap()), "{addr} should be public");
+        }
+
+        for addr in private {
+            assert!(!is_public(&addr.parse().unwrap()), "{addr} should not be public");
+        }
+    }
+}
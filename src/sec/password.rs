@@ -1,7 +1,13 @@
 use argon2::Argon2;
 use argon2::password_hash::{PasswordHasher, SaltString};
+use base64::Engine as _;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use rand::RngCore;
 use rand::rngs::OsRng;
 
+/// the number of random bytes used for a temporary password
+pub const TEMPORARY_LEN: usize = 12;
+
 #[derive(Debug, thiserror::Error)]
 #[error("an error occurred when attempt to create the argon2 hash")]
 pub struct HashError;
@@ -19,6 +25,16 @@ where
     }
 }
 
+/// generates a random password that can be given to a user to log in with
+/// for the first time
+pub fn temporary() -> String {
+    let mut bytes = [0; TEMPORARY_LEN];
+
+    OsRng.fill_bytes(&mut bytes);
+
+    URL_SAFE_NO_PAD.encode(bytes)
+}
+
 fn get_config() -> Argon2<'static> {
     Argon2::default()
 }
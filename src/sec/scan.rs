@@ -0,0 +1,403 @@
+//! malware scanning of uploaded files
+//!
+//! files are given to the [`config::Scanner`] after they are written to a
+//! temp file and before they replace the current contents of a file entry.
+//! the files of an import are scanned from memory before they are written.
+//! encrypted files are decrypted as they are sent so the scanner always
+//! sees the original contents. clamd is sent the file with the "INSTREAM"
+//! command while a command is given the file on stdin
+//!
+//! verdicts are cached by the hash of the plaintext contents so that the
+//! same file uploaded again is not sent to the scanner. a cached verdict is
+//! only used if it is newer than the max verdict age and, for clamd, was
+//! given with the same signature database version
+
+use std::future::Future;
+use std::path::Path;
+use std::process::Stdio;
+use std::time::Duration;
+
+use bytes::Bytes;
+use chrono::{Duration as ChronoDuration, Utc};
+use futures::stream::{BoxStream, StreamExt};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio_util::io::ReaderStream;
+
+use crate::config;
+use crate::db::{GenericClient, PgError};
+use crate::error::{self, Context};
+use crate::sec::encryption::JournalKey;
+
+/// the size of the chunks read from the file
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// the max number of bytes read from the response of clamd
+const MAX_REPLY: u64 = 4 * 1024;
+
+/// the result of scanning a file
+#[derive(Debug)]
+pub enum Verdict {
+    Clean,
+
+    /// the file contains malware. the name of the threat is provided by
+    /// the scanner
+    Infected(String),
+}
+
+impl Verdict {
+    /// the name of the threat if infected
+    fn signature(&self) -> Option<&str> {
+        match self {
+            Verdict::Clean => None,
+            Verdict::Infected(signature) => Some(signature),
+        }
+    }
+}
+
+type FileStream = BoxStream<'static, Result<Bytes, std::io::Error>>;
+
+async fn open_file(path: &Path, size: u64, key: Option<&JournalKey>) -> Result<FileStream, error::Error> {
+    let file = tokio::fs::File::open(path)
+        .await
+        .context("failed to open file for scanning")?;
+
+    match key {
+        Some(_) if size == 0 => Ok(futures::stream::empty().boxed()),
+        Some(key) => Ok(key.decrypt_range(file, size, 0, size - 1).boxed()),
+        None => Ok(ReaderStream::with_capacity(file, CHUNK_SIZE).boxed()),
+    }
+}
+
+/// sends the file at the given path to the scanner. size is the number of
+/// plaintext bytes in the file
+pub async fn scan_file(
+    scan: &config::Scan,
+    path: &Path,
+    size: u64,
+    key: Option<&JournalKey>,
+) -> Result<Verdict, error::Error> {
+    let stream = open_file(path, size, key).await?;
+
+    scan_stream(scan, stream).await
+}
+
+/// sends the given contents to the scanner
+pub async fn scan_bytes(scan: &config::Scan, contents: Bytes) -> Result<Verdict, error::Error> {
+    scan_stream(scan, futures::stream::once(async { Ok(contents) }).boxed()).await
+}
+
+async fn scan_stream(scan: &config::Scan, stream: FileStream) -> Result<Verdict, error::Error> {
+    let result = match &scan.scanner {
+        #[cfg(unix)]
+        config::Scanner::ClamdSocket(socket) => {
+            let conn = tokio::net::UnixStream::connect(socket)
+                .await
+                .context("failed to connect to clamd socket")?;
+
+            tokio::time::timeout(Duration::from_secs(scan.timeout), clamd(conn, stream)).await
+        }
+        #[cfg(not(unix))]
+        config::Scanner::ClamdSocket(_) => {
+            return Err(error::Error::context("clamd socket is only available on unix"));
+        }
+        config::Scanner::ClamdAddr(addr) => {
+            let conn = tokio::net::TcpStream::connect(addr.as_str())
+                .await
+                .context("failed to connect to clamd address")?;
+
+            tokio::time::timeout(Duration::from_secs(scan.timeout), clamd(conn, stream)).await
+        }
+        config::Scanner::Command(command) => {
+            tokio::time::timeout(Duration::from_secs(scan.timeout), run_command(command, stream)).await
+        }
+    };
+
+    result.context("file scan timed out")?
+}
+
+/// scans the file unless a verdict for the same contents is cached
+///
+/// hash is the hex encoded blake3 hash of the plaintext contents. failing
+/// to read or store a cached verdict is logged and the file is scanned as
+/// if nothing was cached
+pub async fn scan_file_cached(
+    conn: &impl GenericClient,
+    scan: &config::Scan,
+    hash: &str,
+    path: &Path,
+    size: u64,
+    key: Option<&JournalKey>,
+) -> Result<Verdict, error::Error> {
+    scan_cached(conn, scan, hash, || scan_file(scan, path, size, key)).await
+}
+
+/// scans the contents unless a verdict for the same contents is cached. see
+/// [`scan_file_cached`]
+pub async fn scan_bytes_cached(
+    conn: &impl GenericClient,
+    scan: &config::Scan,
+    hash: &str,
+    contents: Bytes,
+) -> Result<Verdict, error::Error> {
+    scan_cached(conn, scan, hash, || scan_bytes(scan, contents)).await
+}
+
+async fn scan_cached<F, Fut>(
+    conn: &impl GenericClient,
+    scan: &config::Scan,
+    hash: &str,
+    scan_contents: F,
+) -> Result<Verdict, error::Error>
+where
+    F: FnOnce() -> Fut,
+    Fut: Future<Output = Result<Verdict, error::Error>>,
+{
+    if scan.max_verdict_age == 0 {
+        return scan_contents().await;
+    }
+
+    let definitions = definitions(scan).await?;
+
+    match cached_verdict(conn, hash, definitions.as_deref(), scan.max_verdict_age).await {
+        Ok(Some(verdict)) => return Ok(verdict),
+        Ok(None) => {}
+        Err(err) => error::log_prefix_error("failed to retrieve cached scan verdict", &err),
+    }
+
+    let verdict = scan_contents().await?;
+
+    if let Err(err) = store_verdict(conn, hash, definitions.as_deref(), &verdict).await {
+        error::log_prefix_error("failed to store scan verdict", &err);
+    }
+
+    Ok(verdict)
+}
+
+/// retrieves the version of the signature database of the scanner. ex:
+/// "ClamAV 1.3.1/27300/Mon Jun 10 08:35:54 2024"
+///
+/// a command does not have a version so only the max verdict age applies
+async fn definitions(scan: &config::Scan) -> Result<Option<String>, error::Error> {
+    let result = match &scan.scanner {
+        #[cfg(unix)]
+        config::Scanner::ClamdSocket(socket) => {
+            let conn = tokio::net::UnixStream::connect(socket)
+                .await
+                .context("failed to connect to clamd socket")?;
+
+            tokio::time::timeout(Duration::from_secs(scan.timeout), clamd_version(conn)).await
+        }
+        #[cfg(not(unix))]
+        config::Scanner::ClamdSocket(_) => {
+            return Err(error::Error::context("clamd socket is only available on unix"));
+        }
+        config::Scanner::ClamdAddr(addr) => {
+            let conn = tokio::net::TcpStream::connect(addr.as_str())
+                .await
+                .context("failed to connect to clamd address")?;
+
+            tokio::time::timeout(Duration::from_secs(scan.timeout), clamd_version(conn)).await
+        }
+        config::Scanner::Command(_) => return Ok(None),
+    };
+
+    result.context("clamd version timed out")?
+        .map(Some)
+}
+
+/// sends the "VERSION" command to clamd
+async fn clamd_version<S>(mut conn: S) -> Result<String, error::Error>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    conn.write_all(b"zVERSION\0")
+        .await
+        .context("failed to send command to clamd")?;
+    conn.flush()
+        .await
+        .context("failed to send command to clamd")?;
+
+    let mut reply = Vec::new();
+
+    (&mut conn).take(MAX_REPLY)
+        .read_to_end(&mut reply)
+        .await
+        .context("failed to read reply from clamd")?;
+
+    let reply = String::from_utf8_lossy(&reply);
+    let version = reply.trim_end_matches(['\0', '\n']);
+
+    if version.is_empty() {
+        Err(error::Error::context("clamd did not reply with a version"))
+    } else {
+        Ok(version.to_owned())
+    }
+}
+
+/// retrieves the verdict for the hash if it is newer than the max age and
+/// was given by the same definitions
+async fn cached_verdict(
+    conn: &impl GenericClient,
+    hash: &str,
+    definitions: Option<&str>,
+    max_age: u64,
+) -> Result<Option<Verdict>, PgError> {
+    // the config limits the max age so that it fits in a chrono duration
+    let after = Utc::now() - ChronoDuration::seconds(max_age as i64);
+
+    conn.query_opt(
+        "\
+        select scan_verdicts.signature \
+        from scan_verdicts \
+        where scan_verdicts.hash = $1 and \
+              scan_verdicts.definitions is not distinct from $2 and \
+              scan_verdicts.scanned > $3",
+        &[&hash, &definitions, &after]
+    )
+        .await
+        .map(|maybe| maybe.map(|row| match row.get::<_, Option<String>>(0) {
+            Some(signature) => Verdict::Infected(signature),
+            None => Verdict::Clean,
+        }))
+}
+
+/// stores the verdict for the hash replacing any previous verdict
+async fn store_verdict(
+    conn: &impl GenericClient,
+    hash: &str,
+    definitions: Option<&str>,
+    verdict: &Verdict,
+) -> Result<(), PgError> {
+    let scanned = Utc::now();
+
+    conn.execute(
+        "\
+        insert into scan_verdicts (hash, signature, definitions, scanned) values \
+        ($1, $2, $3, $4) \
+        on conflict (hash) do update set \
+            signature = excluded.signature, \
+            definitions = excluded.definitions, \
+            scanned = excluded.scanned",
+        &[&hash, &verdict.signature(), &definitions, &scanned]
+    ).await?;
+
+    Ok(())
+}
+
+/// sends the stream to clamd with the "INSTREAM" command. each chunk is
+/// prefixed with its length and a zero length chunk ends the stream
+async fn clamd<S>(mut conn: S, mut stream: FileStream) -> Result<Verdict, error::Error>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    conn.write_all(b"zINSTREAM\0")
+        .await
+        .context("failed to send command to clamd")?;
+
+    while let Some(result) = stream.next().await {
+        let bytes = result.context("failed to read file for scanning")?;
+
+        for chunk in bytes.chunks(CHUNK_SIZE) {
+            // chunks are never larger than CHUNK_SIZE
+            let len = chunk.len() as u32;
+
+            conn.write_all(&len.to_be_bytes())
+                .await
+                .context("failed to send file to clamd")?;
+            conn.write_all(chunk)
+                .await
+                .context("failed to send file to clamd")?;
+        }
+    }
+
+    conn.write_all(&0u32.to_be_bytes())
+        .await
+        .context("failed to send file to clamd")?;
+    conn.flush()
+        .await
+        .context("failed to send file to clamd")?;
+
+    let mut reply = Vec::new();
+
+    (&mut conn).take(MAX_REPLY)
+        .read_to_end(&mut reply)
+        .await
+        .context("failed to read reply from clamd")?;
+
+    parse_clamd_reply(&String::from_utf8_lossy(&reply))
+}
+
+/// parses the reply of clamd. ex: "stream: OK" or
+/// "stream: Eicar-Test-Signature FOUND"
+fn parse_clamd_reply(reply: &str) -> Result<Verdict, error::Error> {
+    let reply = reply.trim_end_matches(['\0', '\n']);
+    let status = reply.strip_prefix("stream: ")
+        .unwrap_or(reply);
+
+    if status == "OK" {
+        Ok(Verdict::Clean)
+    } else if let Some(signature) = status.strip_suffix(" FOUND") {
+        Ok(Verdict::Infected(signature.to_owned()))
+    } else {
+        Err(error::Error::context(format!("clamd failed to scan file: \"{reply}\"")))
+    }
+}
+
+/// gives the stream to the command on stdin
+async fn run_command(command: &[String], mut stream: FileStream) -> Result<Verdict, error::Error> {
+    let (program, args) = command.split_first()
+        .context("scan command is empty")?;
+
+    let mut child = tokio::process::Command::new(program)
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .kill_on_drop(true)
+        .spawn()
+        .context("failed to start scan command")?;
+
+    let mut stdin = child.stdin.take()
+        .context("scan command stdin is not available")?;
+
+    // stdin is written while the output is collected so that a command
+    // writing to stdout before reading all of stdin does not block
+    let writer = tokio::spawn(async move {
+        while let Some(result) = stream.next().await {
+            let bytes = result.context("failed to read file for scanning")?;
+
+            // the command may exit before reading all of stdin once it has
+            // found a threat
+            if stdin.write_all(&bytes).await.is_err() {
+                break;
+            }
+        }
+
+        Ok::<_, error::Error>(())
+    });
+
+    let output = child.wait_with_output()
+        .await
+        .context("failed to run scan command")?;
+
+    writer.await
+        .context("failed to join scan writer")??;
+
+    match output.status.code() {
+        Some(0) => Ok(Verdict::Clean),
+        Some(1) => {
+            let stdout = String::from_utf8_lossy(&output.stdout);
+            let signature = stdout.trim();
+
+            if signature.is_empty() {
+                Ok(Verdict::Infected(String::from("unknown")))
+            } else {
+                Ok(Verdict::Infected(signature.to_owned()))
+            }
+        }
+        _ => Err(error::Error::context(format!(
+            "scan command exited with {}",
+            output.status
+        ))),
+    }
+}
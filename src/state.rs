@@ -1,7 +1,7 @@
 use std::collections::HashMap;
 use std::convert::Infallible;
 use std::path::{Path, PathBuf};
-use std::sync::Arc;
+use std::sync::{Arc, RwLock};
 
 use async_trait::async_trait;
 use axum::extract::FromRequestParts;
@@ -9,38 +9,200 @@ use axum::http::request::Parts;
 
 use crate::config;
 use crate::db;
-use crate::db::ids::{JournalId, FileEntryId};
+use crate::db::ids::FileEntryId;
+use crate::email::Mailer;
 use crate::error::{self, Context};
+use crate::fs::{self, InsufficientStorage};
+use crate::jobs::JobKind;
 use crate::journal::{Journal, JournalDir};
+use crate::journal::live::LiveEvents;
+use crate::logging::Logging;
+use crate::sec::encryption::{JournalKey, MasterKey};
+use crate::sec::network::OutboundResolver;
 use crate::templates;
 
 #[derive(Debug, Clone)]
 pub struct SharedState(Arc<State>);
 
 impl SharedState {
-    pub async fn new(config: &config::Config) -> Result<Self, error::Error> {
+    pub async fn new(config: &config::Config, logging: Logging) -> Result<Self, error::Error> {
         let db_pool = db::from_config(config).await?;
+
+        {
+            let conn = db_pool.get()
+                .await
+                .context("failed to retrieve database connection")?;
+
+            JournalDir::migrate_legacy(&conn, &config.settings.storage).await?;
+        }
+
         let templates = templates::initialize(config)?;
+        let master_key = match &config.settings.encryption {
+            Some(encryption) => Some(MasterKey::load(&encryption.key_file)?),
+            None => None,
+        };
+        let http = reqwest::Client::builder()
+            .user_agent(concat!("TJ2/", env!("CARGO_PKG_VERSION")))
+            .timeout(std::time::Duration::from_secs(10))
+            .build()
+            .context("failed to create http client")?;
+        let outbound_http = reqwest::Client::builder()
+            .user_agent(concat!("TJ2/", env!("CARGO_PKG_VERSION")))
+            .timeout(std::time::Duration::from_secs(10))
+            .redirect(reqwest::redirect::Policy::none())
+            .dns_resolver(Arc::new(OutboundResolver::new(
+                config.settings.network.outbound_allow.clone()
+            )))
+            .build()
+            .context("failed to create outbound http client")?;
+
+        logging.set_config(config.settings.log_filter.clone())
+            .context("failed to apply config log filter")?;
+        // resolve the default so that the listeners are not needed later
+        let mut security_headers = config.settings.security_headers.clone();
+        security_headers.hsts = Some(config.settings.use_hsts());
+
+        let mailer = match &config.settings.smtp {
+            Some(smtp) => Some(Mailer::from_config(smtp)?),
+            None => None,
+        };
 
         Ok(SharedState(Arc::new(State {
             db_pool,
-            assets: Assets {
-                files: config.settings.assets.files.clone(),
-                directories: config.settings.assets.directories.clone(),
-            },
+            config_path: config.path.clone(),
+            assets: RwLock::new(Arc::new(Assets::from_config(config))),
             storage: Storage {
                 path: config.settings.storage.clone(),
+                reserve: config.settings.storage_reserve,
+                master_key,
             },
-            templates,
+            templates: RwLock::new(Arc::new(templates)),
+            network: config.settings.network.clone(),
+            api: config.settings.api.clone(),
+            cors: config.settings.cors.clone(),
+            security_headers,
+            webauthn: config.settings.webauthn.clone(),
+            telemetry: config.settings.telemetry.clone(),
+            extraction: config.settings.extraction.clone(),
+            transcription: config.settings.transcription.clone(),
+            scan: config.settings.scan.clone(),
+            upload: config.settings.upload.clone(),
+            weather: config.settings.weather.clone(),
+            jobs: config.settings.jobs.clone(),
+            logging,
+            http,
+            outbound_http,
+            mailer,
+            live: LiveEvents::new(),
         })))
     }
 
-    pub fn assets(&self) -> &Assets {
-        &self.0.assets
+    pub fn assets(&self) -> Arc<Assets> {
+        self.0.assets.read().unwrap().clone()
+    }
+
+    pub fn templates(&self) -> Arc<tera::Tera> {
+        self.0.templates.read().unwrap().clone()
+    }
+
+    /// reads the config file again and applies the logging, asset, and
+    /// template settings. other settings require a restart
+    pub async fn reload(&self) -> Result<(), error::Error> {
+        let path = self.0.config_path.clone();
+
+        let (config, templates) = tokio::task::spawn_blocking(move || {
+            let config = config::Config::from_path(&path)?;
+            let templates = templates::initialize(&config)?;
+
+            Ok::<_, error::Error>((config, templates))
+        })
+            .await
+            .context("failed to join config reload")??;
+
+        self.0.logging.set_config(config.settings.log_filter.clone())
+            .context("failed to apply config log filter")?;
+
+        *self.0.assets.write().unwrap() = Arc::new(Assets::from_config(&config));
+        *self.0.templates.write().unwrap() = Arc::new(templates);
+
+        tracing::info!("reloaded config from \"{}\"", config.path.display());
+
+        Ok(())
+    }
+
+    pub fn network(&self) -> &config::Network {
+        &self.0.network
+    }
+
+    pub fn api(&self) -> &config::Api {
+        &self.0.api
+    }
+
+    pub fn cors(&self) -> Option<&config::Cors> {
+        self.0.cors.as_ref()
+    }
+
+    pub fn security_headers(&self) -> &config::SecurityHeaders {
+        &self.0.security_headers
+    }
+
+    pub fn webauthn(&self) -> Option<&config::Webauthn> {
+        self.0.webauthn.as_ref()
+    }
+
+    pub fn telemetry(&self) -> &config::Telemetry {
+        &self.0.telemetry
+    }
+
+    pub fn extraction(&self) -> &config::Extraction {
+        &self.0.extraction
+    }
+
+    pub fn transcription(&self) -> Option<&config::Transcription> {
+        self.0.transcription.as_ref()
+    }
+
+    pub fn scan(&self) -> Option<&config::Scan> {
+        self.0.scan.as_ref()
+    }
+
+    pub fn upload(&self) -> &config::Upload {
+        &self.0.upload
     }
 
-    pub fn templates(&self) -> &tera::Tera {
-        &self.0.templates
+    pub fn weather(&self) -> Option<&config::Weather> {
+        self.0.weather.as_ref()
+    }
+
+    /// the job settings from the config
+    pub fn jobs(&self) -> &HashMap<JobKind, config::Job> {
+        &self.0.jobs
+    }
+
+    pub fn logging(&self) -> &Logging {
+        &self.0.logging
+    }
+
+    /// the client used for requests to other servers
+    pub fn http(&self) -> &reqwest::Client {
+        &self.0.http
+    }
+
+    /// the client used for requests to user provided urls. only addresses
+    /// allowed by the network config are reached and redirects are not
+    /// followed
+    pub fn outbound_http(&self) -> &reqwest::Client {
+        &self.0.outbound_http
+    }
+
+    /// the mailer for sending emails if smtp is configured
+    pub fn mailer(&self) -> Option<&Mailer> {
+        self.0.mailer.as_ref()
+    }
+
+    /// the live events sent to connected clients
+    pub fn live(&self) -> &LiveEvents {
+        &self.0.live
     }
 
     pub fn db(&self) -> &db::Pool {
@@ -70,9 +232,29 @@ impl FromRequestParts<SharedState> for SharedState {
 #[derive(Debug)]
 pub struct State {
     db_pool: db::Pool,
-    assets: Assets,
+
+    /// the config file that is read again when reloading
+    config_path: PathBuf,
+    assets: RwLock<Arc<Assets>>,
     storage: Storage,
-    templates: tera::Tera,
+    templates: RwLock<Arc<tera::Tera>>,
+    network: config::Network,
+    api: config::Api,
+    cors: Option<config::Cors>,
+    security_headers: config::SecurityHeaders,
+    webauthn: Option<config::Webauthn>,
+    telemetry: config::Telemetry,
+    extraction: config::Extraction,
+    transcription: Option<config::Transcription>,
+    scan: Option<config::Scan>,
+    upload: config::Upload,
+    weather: Option<config::Weather>,
+    jobs: HashMap<JobKind, config::Job>,
+    logging: Logging,
+    http: reqwest::Client,
+    outbound_http: reqwest::Client,
+    mailer: Option<Mailer>,
+    live: LiveEvents,
 }
 
 #[derive(Debug)]
@@ -82,6 +264,13 @@ pub struct Assets {
 }
 
 impl Assets {
+    fn from_config(config: &config::Config) -> Self {
+        Assets {
+            files: config.settings.assets.files.clone(),
+            directories: config.settings.assets.directories.clone(),
+        }
+    }
+
     pub fn get_file(&self, uri: &str) -> Option<&Path> {
         if let Some(found) = self.files.get(uri) {
             Some(found)
@@ -105,19 +294,86 @@ impl Assets {
 
 #[derive(Debug)]
 pub struct Storage {
-    path: PathBuf
+    path: PathBuf,
+    reserve: u64,
+    master_key: Option<MasterKey>,
 }
 
 impl Storage {
+    /// the number of bytes that must stay free in the storage directory
+    pub fn reserve(&self) -> u64 {
+        self.reserve
+    }
+
+    /// checks if journal files are encrypted when written
+    pub fn is_encrypted(&self) -> bool {
+        self.master_key.is_some()
+    }
+
+    /// checks that a file can be written to and removed from the storage
+    /// directory
+    pub async fn check_writable(&self) -> Result<(), std::io::Error> {
+        let path = self.path.join(".readyz");
+
+        tokio::fs::write(&path, b"ok").await?;
+        tokio::fs::remove_file(&path).await
+    }
+
+    /// checks that writing the given number of bytes will not go below the
+    /// reserve of the storage directory
+    pub async fn check_space(&self, needed: u64) -> Result<Option<InsufficientStorage>, error::Error> {
+        let available = fs::available_space(self.path.clone())
+            .await
+            .context("failed to retrieve available storage space")?;
+
+        if available.saturating_sub(needed) < self.reserve {
+            Ok(Some(InsufficientStorage {
+                available,
+                needed,
+                reserve: self.reserve,
+            }))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// retrieves the key used to encrypt the files of a journal. None is
+    /// returned if encryption is not enabled
+    pub async fn journal_key(
+        &self,
+        conn: &impl db::GenericClient,
+        journal: &Journal,
+    ) -> Result<Option<JournalKey>, error::Error> {
+        let Some(master_key) = &self.master_key else {
+            return Ok(None);
+        };
+
+        JournalKey::retrieve_or_create(conn, master_key, &journal.id)
+            .await
+            .map(Some)
+    }
+
+    /// retrieves the key needed to read an encrypted file of a journal
+    pub async fn require_journal_key(
+        &self,
+        conn: &impl db::GenericClient,
+        journal: &Journal,
+    ) -> Result<JournalKey, error::Error> {
+        self.journal_key(conn, journal)
+            .await?
+            .context("journal file is encrypted but encryption is not configured")
+    }
+
     pub fn journal_dir(&self, journal: &Journal) -> JournalDir {
         JournalDir::new(&self.path, journal)
     }
 
     pub fn journal_file_entry(
         &self,
-        journal_id: JournalId,
+        journal: &Journal,
         file_entry_id: FileEntryId
     ) -> PathBuf {
-        self.path.join(format!("journals/{journal_id}/files/{file_entry_id}.file"))
+        self.journal_dir(journal)
+            .file_path(&file_entry_id)
     }
 }
@@ -0,0 +1,121 @@
+//! opt-in usage telemetry
+//!
+//! when `settings.telemetry.enabled` is true the [`JobKind::Telemetry`] job
+//! sends a [`Report`] to the configured endpoint once a day. the report only
+//! contains:
+//!
+//! - the version of the server
+//! - the number of users, journals, entries, and files, rounded down into
+//!   power of ten buckets. ex: 1234 entries is sent as "1000-9999"
+//! - which optional features are enabled in the config
+//!
+//! nothing that identifies the server, its users, or their data is sent.
+//! there are no ids, names, addresses, or contents in the report. run the
+//! server with `--preview-telemetry` to print the exact report that would be
+//! sent without sending it.
+//!
+//! [`JobKind::Telemetry`]: crate::jobs::JobKind::Telemetry
+
+use serde::Serialize;
+
+use crate::db::GenericClient;
+use crate::error::{self, Context};
+use crate::state;
+
+/// the largest bucket. anything above is sent as "100000+"
+const MAX_BUCKET: i64 = 100_000;
+
+/// the coarse counts of the server
+#[derive(Debug, Serialize)]
+pub struct Counts {
+    pub users: String,
+    pub journals: String,
+    pub entries: String,
+    pub files: String,
+}
+
+/// the optional features that are enabled in the config
+#[derive(Debug, Serialize)]
+pub struct Features {
+    pub webauthn: bool,
+    pub encryption: bool,
+    pub smtp: bool,
+}
+
+/// the report sent to the telemetry endpoint
+#[derive(Debug, Serialize)]
+pub struct Report {
+    pub version: &'static str,
+    pub counts: Counts,
+    pub features: Features,
+}
+
+impl Report {
+    /// collects the report from the current state of the server
+    pub async fn collect(state: &state::SharedState, conn: &impl GenericClient) -> Result<Self, error::Error> {
+        let row = conn.query_one(
+            "\
+            select (select count(*) from users), \
+                   (select count(*) from journals), \
+                   (select count(*) from entries), \
+                   (select count(*) from file_entries)",
+            &[]
+        )
+            .await
+            .context("failed to retrieve telemetry counts")?;
+
+        Ok(Report {
+            version: env!("CARGO_PKG_VERSION"),
+            counts: Counts {
+                users: bucket(row.get(0)),
+                journals: bucket(row.get(1)),
+                entries: bucket(row.get(2)),
+                files: bucket(row.get(3)),
+            },
+            features: Features {
+                webauthn: state.webauthn().is_some(),
+                encryption: state.storage().is_encrypted(),
+                smtp: state.mailer().is_some(),
+            },
+        })
+    }
+}
+
+/// rounds the count down into a power of ten bucket
+fn bucket(count: i64) -> String {
+    if count <= 0 {
+        return String::from("0");
+    }
+
+    if count >= MAX_BUCKET {
+        return format!("{MAX_BUCKET}+");
+    }
+
+    let mut low = 1;
+
+    while low * 10 <= count {
+        low *= 10;
+    }
+
+    format!("{low}-{}", low * 10 - 1)
+}
+
+/// prints the report that would be sent to stdout
+pub async fn preview(state: &state::SharedState) -> Result<(), error::Error> {
+    let conn = state.db_conn().await?;
+    let report = Report::collect(state, &conn).await?;
+
+    let json = serde_json::to_string_pretty(&report)
+        .context("failed to serialize telemetry report")?;
+
+    println!("{json}");
+
+    let telemetry = state.telemetry();
+
+    match (&telemetry.endpoint, telemetry.enabled) {
+        (Some(endpoint), true) => eprintln!("reports are sent daily to {endpoint}"),
+        _ => eprintln!("telemetry is disabled, no reports are sent"),
+    }
+
+    Ok(())
+}
@@ -9,6 +9,8 @@ use crate::db::ids::{UserId, UserUid, GroupId, GroupUid, RoleId};
 use crate::sec::authz::Role;
 use crate::error::{self, Context};
 
+pub mod preferences;
+
 #[derive(Debug)]
 pub struct User {
     pub id: UserId,
@@ -16,6 +18,9 @@ pub struct User {
     pub username: String,
     pub password: String,
     pub version: i64,
+
+    /// when the account was disabled. a disabled user cannot log in
+    pub disabled: Option<DateTime<Utc>>,
     pub created: DateTime<Utc>,
     pub updated: Option<DateTime<Utc>>,
 }
@@ -29,6 +34,7 @@ impl User {
                    username, \
                    password, \
                    version, \
+                   disabled, \
                    created, \
                    updated \
             from users \
@@ -42,8 +48,9 @@ impl User {
                 username: row.get(2),
                 password: row.get(3),
                 version: row.get(4),
-                created: row.get(5),
-                updated: row.get(6),
+                disabled: row.get(5),
+                created: row.get(6),
+                updated: row.get(7),
             }))
     }
 
@@ -55,8 +62,9 @@ impl User {
                    username, \
                    password, \
                    version, \
+                   disabled, \
                    created, \
-                   updated
+                   updated \
             from users \
             where id = $1",
             &[&id]
@@ -68,8 +76,9 @@ impl User {
                 username: row.get(2),
                 password: row.get(3),
                 version: row.get(4),
-                created: row.get(5),
-                updated: row.get(6),
+                disabled: row.get(5),
+                created: row.get(6),
+                updated: row.get(7),
             }))
     }
 
@@ -93,6 +102,7 @@ impl User {
                 username: username.to_owned(),
                 password: hash.to_owned(),
                 version,
+                disabled: None,
                 created,
                 updated: None,
             })),
@@ -100,6 +110,20 @@ impl User {
         }
     }
 
+    /// disables or enables the account of the user
+    pub async fn set_disabled(&mut self, conn: &impl db::GenericClient, disabled: bool) -> Result<(), db::PgError> {
+        let disabled = disabled.then(Utc::now);
+
+        conn.execute(
+            "update users set disabled = $2 where id = $1",
+            &[&self.id, &disabled]
+        ).await?;
+
+        self.disabled = disabled;
+
+        Ok(())
+    }
+
     pub async fn update(&mut self, conn: &impl db::GenericClient) -> Result<bool, db::PgError> {
         self.updated = Some(Utc::now());
 
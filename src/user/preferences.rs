@@ -0,0 +1,269 @@
+//! preferences that change how dates are shown and calculated for a user
+//!
+//! users that have not set any preferences use the defaults of UTC, "en",
+//! iso dates, and weeks that start on monday. the timezone decides what day
+//! "today" is for the user so that entries written late in the day are not
+//! treated as planned
+
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::str::FromStr;
+
+use bytes::BytesMut;
+use chrono::{DateTime, NaiveDate, Utc};
+use chrono_tz::Tz;
+use postgres_types as pg_types;
+use serde::{Serialize, Deserialize};
+
+use crate::db::{GenericClient, PgError};
+use crate::db::ids::{JournalId, UserId};
+use crate::error::BoxDynError;
+use crate::journal::WeekStart;
+use crate::journal::custom_field::unit::UnitSystem;
+
+/// the timezone used when a user has not set one
+pub const DEFAULT_TIMEZONE: &str = "UTC";
+
+/// the locale used when a user has not set one
+pub const DEFAULT_LOCALE: &str = "en";
+
+/// checks that the given string looks like a BCP 47 language tag. ex:
+/// "en", "en-US", "zh-Hant-TW"
+pub fn valid_locale(given: &str) -> bool {
+    let mut parts = given.split('-');
+
+    let Some(language) = parts.next() else {
+        return false;
+    };
+
+    if !(2..=3).contains(&language.len()) || !language.chars().all(|ch| ch.is_ascii_alphabetic()) {
+        return false;
+    }
+
+    parts.all(|part| (2..=8).contains(&part.len()) && part.chars().all(|ch| ch.is_ascii_alphanumeric()))
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("the provided string is not a valid date format")]
+pub struct InvalidDateFormat;
+
+/// how dates are written for a user
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DateFormat {
+    /// 2024-01-31
+    #[default]
+    Iso,
+
+    /// 01/31/2024
+    Us,
+
+    /// 31/01/2024
+    Eu,
+
+    /// January 31, 2024
+    Long,
+}
+
+impl DateFormat {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DateFormat::Iso => "iso",
+            DateFormat::Us => "us",
+            DateFormat::Eu => "eu",
+            DateFormat::Long => "long",
+        }
+    }
+
+    /// the strftime pattern of the format
+    pub fn pattern(&self) -> &'static str {
+        match self {
+            DateFormat::Iso => "%Y-%m-%d",
+            DateFormat::Us => "%m/%d/%Y",
+            DateFormat::Eu => "%d/%m/%Y",
+            DateFormat::Long => "%B %-d, %Y",
+        }
+    }
+}
+
+impl Display for DateFormat {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for DateFormat {
+    type Err = InvalidDateFormat;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "iso" => Ok(DateFormat::Iso),
+            "us" => Ok(DateFormat::Us),
+            "eu" => Ok(DateFormat::Eu),
+            "long" => Ok(DateFormat::Long),
+            _ => Err(InvalidDateFormat),
+        }
+    }
+}
+
+impl<'a> pg_types::FromSql<'a> for DateFormat {
+    fn from_sql(ty: &pg_types::Type, raw: &'a [u8]) -> Result<Self, BoxDynError> {
+        let v = <&str as pg_types::FromSql>::from_sql(ty, raw)?;
+
+        Ok(Self::from_str(v)?)
+    }
+
+    fn accepts(ty: &pg_types::Type) -> bool {
+        <&str as pg_types::FromSql>::accepts(ty)
+    }
+}
+
+impl pg_types::ToSql for DateFormat {
+    fn to_sql(&self, ty: &pg_types::Type, w: &mut BytesMut) -> Result<pg_types::IsNull, BoxDynError> {
+        self.as_str()
+            .to_sql(ty, w)
+    }
+
+    fn accepts(ty: &pg_types::Type) -> bool {
+        <&str as pg_types::ToSql>::accepts(ty)
+    }
+
+    pg_types::to_sql_checked!();
+}
+
+/// the preferences that templates use to write dates
+#[derive(Debug, Serialize)]
+pub struct RenderPreferences {
+    pub locale: String,
+    pub timezone: String,
+
+    /// the strftime pattern for dates
+    pub date_format: &'static str,
+
+    /// the strftime pattern for timestamps
+    pub datetime_format: String,
+}
+
+/// the preferences of a user
+#[derive(Debug, Clone, Serialize)]
+pub struct Preferences {
+    /// the IANA name of the timezone for the user. ex: "America/Denver"
+    pub timezone: String,
+
+    /// the BCP 47 language tag for the user. ex: "en-US"
+    pub locale: String,
+
+    pub date_format: DateFormat,
+
+    /// the journal that is used when a journal is not specified
+    pub default_journals_id: Option<JournalId>,
+
+    /// the first day of the week for calendars
+    pub week_start: WeekStart,
+
+    /// the measurement system that custom field values with units are
+    /// converted to when a request does not specify one. values are left in
+    /// their own unit if not set
+    pub unit_system: Option<UnitSystem>,
+
+    pub updated: Option<DateTime<Utc>>,
+}
+
+impl Default for Preferences {
+    fn default() -> Self {
+        Self {
+            timezone: DEFAULT_TIMEZONE.to_owned(),
+            locale: DEFAULT_LOCALE.to_owned(),
+            date_format: DateFormat::default(),
+            default_journals_id: None,
+            week_start: WeekStart::default(),
+            unit_system: None,
+            updated: None,
+        }
+    }
+}
+
+impl Preferences {
+    /// retrieves the preferences of the user or the defaults if they have
+    /// not set any
+    pub async fn retrieve(conn: &impl GenericClient, users_id: &UserId) -> Result<Self, PgError> {
+        conn.query_opt(
+            "\
+            select user_preferences.timezone, \
+                   user_preferences.locale, \
+                   user_preferences.date_format, \
+                   user_preferences.default_journals_id, \
+                   user_preferences.week_start, \
+                   user_preferences.unit_system, \
+                   user_preferences.updated \
+            from user_preferences \
+            where user_preferences.users_id = $1",
+            &[users_id]
+        )
+            .await
+            .map(|maybe| maybe.map(|row| Self {
+                timezone: row.get(0),
+                locale: row.get(1),
+                date_format: row.get(2),
+                default_journals_id: row.get(3),
+                week_start: row.get(4),
+                unit_system: row.get(5),
+                updated: row.get(6),
+            }).unwrap_or_default())
+    }
+
+    /// stores the preferences of the user, replacing the previous ones
+    ///
+    /// the timezone and locale are expected to already be validated
+    pub async fn set(&mut self, conn: &impl GenericClient, users_id: &UserId) -> Result<(), PgError> {
+        let now = Utc::now();
+
+        conn.execute(
+            "\
+            insert into user_preferences (users_id, timezone, locale, date_format, default_journals_id, week_start, unit_system, updated) values \
+            ($1, $2, $3, $4, $5, $6, $7, $8) \
+            on conflict (users_id) do update \
+                set timezone = excluded.timezone, \
+                    locale = excluded.locale, \
+                    date_format = excluded.date_format, \
+                    default_journals_id = excluded.default_journals_id, \
+                    week_start = excluded.week_start, \
+                    unit_system = excluded.unit_system, \
+                    updated = excluded.updated",
+            &[
+                users_id,
+                &self.timezone,
+                &self.locale,
+                &self.date_format,
+                &self.default_journals_id,
+                &self.week_start,
+                &self.unit_system,
+                &now
+            ]
+        ).await?;
+
+        self.updated = Some(now);
+
+        Ok(())
+    }
+
+    /// the timezone of the user. falls back to UTC if the stored name is no
+    /// longer known
+    pub fn tz(&self) -> Tz {
+        self.timezone.parse().unwrap_or(Tz::UTC)
+    }
+
+    /// the preferences to give to a template
+    pub fn render(&self) -> RenderPreferences {
+        RenderPreferences {
+            locale: self.locale.clone(),
+            timezone: self.tz().name().to_owned(),
+            date_format: self.date_format.pattern(),
+            datetime_format: format!("{} %H:%M", self.date_format.pattern()),
+        }
+    }
+
+    /// the current date in the timezone of the user
+    pub fn today(&self) -> NaiveDate {
+        Utc::now().with_timezone(&self.tz()).date_naive()
+    }
+}
@@ -0,0 +1,340 @@
+use chrono::{DateTime, Utc};
+use futures::{Stream, StreamExt};
+use serde::Serialize;
+
+use crate::db::{self, GenericClient, PgError};
+use crate::db::ids::{WorkspaceId, WorkspaceUid, UserId};
+
+/// the name of the workspace that is created when the server first starts
+///
+/// any request that cannot be matched to a host or path prefix will be
+/// assigned to this workspace
+pub const DEFAULT_WORKSPACE: &str = "default";
+
+/// the potential errors when creating or updating a workspace
+#[derive(Debug, thiserror::Error)]
+pub enum WorkspaceError {
+    /// the given workspace name already exists
+    #[error("the given workspace name already exists")]
+    NameExists,
+
+    /// the given host is already assigned to another workspace
+    #[error("the given host is already assigned to another workspace")]
+    HostExists,
+
+    /// the given path prefix is already assigned to another workspace
+    #[error("the given path prefix is already assigned to another workspace")]
+    PathPrefixExists,
+
+    #[error(transparent)]
+    Db(#[from] PgError),
+}
+
+impl WorkspaceError {
+    fn from_pg(err: PgError) -> Self {
+        if let Some(db::ErrorKind::Unique(constraint)) = db::ErrorKind::check(&err) {
+            match constraint {
+                "workspaces_name_key" => Self::NameExists,
+                "workspaces_host_key" => Self::HostExists,
+                "workspaces_path_prefix_key" => Self::PathPrefixExists,
+                _ => Self::Db(err),
+            }
+        } else {
+            Self::Db(err)
+        }
+    }
+}
+
+/// an isolated group of users and journals on the server
+///
+/// a workspace is selected for a request either by the host header or by
+/// a path prefix of "/w/{path_prefix}"
+#[derive(Debug, Clone, Serialize)]
+pub struct Workspace {
+    /// the assigned workspace id from the database
+    pub id: WorkspaceId,
+
+    /// the generated workspace uid from the server
+    pub uid: WorkspaceUid,
+
+    /// the unique name of the workspace
+    pub name: String,
+
+    /// the optional hostname that will select this workspace
+    pub host: Option<String>,
+
+    /// the optional path prefix that will select this workspace
+    pub path_prefix: Option<String>,
+
+    /// timestamp of when the workspace was created
+    pub created: DateTime<Utc>,
+
+    /// timestamp of when the workspace was updated
+    pub updated: Option<DateTime<Utc>>,
+}
+
+impl Workspace {
+    fn map_row(row: tokio_postgres::Row) -> Self {
+        Self {
+            id: row.get(0),
+            uid: row.get(1),
+            name: row.get(2),
+            host: row.get(3),
+            path_prefix: row.get(4),
+            created: row.get(5),
+            updated: row.get(6),
+        }
+    }
+
+    /// attempts to create a new workspace with the given name, host, and
+    /// path prefix
+    pub async fn create(
+        conn: &impl GenericClient,
+        name: String,
+        host: Option<String>,
+        path_prefix: Option<String>,
+    ) -> Result<Self, WorkspaceError> {
+        let uid = WorkspaceUid::gen();
+        let created = Utc::now();
+
+        let result = conn.query_one(
+            "\
+            insert into workspaces (uid, name, host, path_prefix, created) values \
+            ($1, $2, $3, $4, $5) \
+            returning id",
+            &[&uid, &name, &host, &path_prefix, &created]
+        ).await;
+
+        match result {
+            Ok(row) => Ok(Self {
+                id: row.get(0),
+                uid,
+                name,
+                host,
+                path_prefix,
+                created,
+                updated: None,
+            }),
+            Err(err) => Err(WorkspaceError::from_pg(err))
+        }
+    }
+
+    /// retrieves the workspace with the specified [`WorkspaceId`]
+    pub async fn retrieve_id(conn: &impl GenericClient, workspaces_id: &WorkspaceId) -> Result<Option<Self>, PgError> {
+        conn.query_opt(
+            "\
+            select workspaces.id, \
+                   workspaces.uid, \
+                   workspaces.name, \
+                   workspaces.host, \
+                   workspaces.path_prefix, \
+                   workspaces.created, \
+                   workspaces.updated \
+            from workspaces \
+            where workspaces.id = $1",
+            &[workspaces_id]
+        )
+            .await
+            .map(|maybe| maybe.map(Self::map_row))
+    }
+
+    /// retrieves the workspace with the specified name
+    pub async fn retrieve_name(conn: &impl GenericClient, name: &str) -> Result<Option<Self>, PgError> {
+        conn.query_opt(
+            "\
+            select workspaces.id, \
+                   workspaces.uid, \
+                   workspaces.name, \
+                   workspaces.host, \
+                   workspaces.path_prefix, \
+                   workspaces.created, \
+                   workspaces.updated \
+            from workspaces \
+            where workspaces.name = $1",
+            &[&name]
+        )
+            .await
+            .map(|maybe| maybe.map(Self::map_row))
+    }
+
+    /// retrieves the workspace assigned to the given hostname
+    pub async fn retrieve_host(conn: &impl GenericClient, host: &str) -> Result<Option<Self>, PgError> {
+        conn.query_opt(
+            "\
+            select workspaces.id, \
+                   workspaces.uid, \
+                   workspaces.name, \
+                   workspaces.host, \
+                   workspaces.path_prefix, \
+                   workspaces.created, \
+                   workspaces.updated \
+            from workspaces \
+            where workspaces.host = $1",
+            &[&host]
+        )
+            .await
+            .map(|maybe| maybe.map(Self::map_row))
+    }
+
+    /// retrieves the workspace assigned to the given path prefix
+    pub async fn retrieve_path_prefix(conn: &impl GenericClient, path_prefix: &str) -> Result<Option<Self>, PgError> {
+        conn.query_opt(
+            "\
+            select workspaces.id, \
+                   workspaces.uid, \
+                   workspaces.name, \
+                   workspaces.host, \
+                   workspaces.path_prefix, \
+                   workspaces.created, \
+                   workspaces.updated \
+            from workspaces \
+            where workspaces.path_prefix = $1",
+            &[&path_prefix]
+        )
+            .await
+            .map(|maybe| maybe.map(Self::map_row))
+    }
+
+    /// retrieves the default workspace for the server
+    pub async fn retrieve_default(conn: &impl GenericClient) -> Result<Option<Self>, PgError> {
+        Self::retrieve_name(conn, DEFAULT_WORKSPACE).await
+    }
+
+    /// retrieves all workspaces ordered by name
+    pub async fn retrieve_stream(
+        conn: &impl GenericClient
+    ) -> Result<impl Stream<Item = Result<Self, PgError>>, PgError> {
+        let params: db::ParamsArray<'_, 0> = [];
+
+        Ok(conn.query_raw(
+            "\
+            select workspaces.id, \
+                   workspaces.uid, \
+                   workspaces.name, \
+                   workspaces.host, \
+                   workspaces.path_prefix, \
+                   workspaces.created, \
+                   workspaces.updated \
+            from workspaces \
+            order by workspaces.name",
+            params
+        )
+            .await?
+            .map(|result| result.map(Self::map_row)))
+    }
+
+    /// updates the name, host, and path prefix of the workspace
+    pub async fn update(&mut self, conn: &impl GenericClient) -> Result<(), WorkspaceError> {
+        self.updated = Some(Utc::now());
+
+        conn.execute(
+            "\
+            update workspaces \
+            set name = $2, \
+                host = $3, \
+                path_prefix = $4, \
+                updated = $5 \
+            where id = $1",
+            &[&self.id, &self.name, &self.host, &self.path_prefix, &self.updated]
+        )
+            .await
+            .map_err(WorkspaceError::from_pg)?;
+
+        Ok(())
+    }
+
+    /// retrieves the membership of a user for this workspace if present
+    pub async fn retrieve_member(
+        &self,
+        conn: &impl GenericClient,
+        users_id: &UserId
+    ) -> Result<Option<WorkspaceUser>, PgError> {
+        WorkspaceUser::retrieve(conn, &self.id, users_id).await
+    }
+}
+
+/// a user that is a member of a workspace
+#[derive(Debug, Serialize)]
+pub struct WorkspaceUser {
+    pub workspaces_id: WorkspaceId,
+    pub users_id: UserId,
+
+    /// the user is able to manage the members of the workspace and the
+    /// users that only belong to it. groups and roles can only be given by
+    /// a user with the server permission
+    pub admin: bool,
+    pub added: DateTime<Utc>,
+}
+
+impl WorkspaceUser {
+    /// retrieves the membership of the specified user for the workspace
+    pub async fn retrieve(
+        conn: &impl GenericClient,
+        workspaces_id: &WorkspaceId,
+        users_id: &UserId,
+    ) -> Result<Option<Self>, PgError> {
+        conn.query_opt(
+            "\
+            select workspace_users.workspaces_id, \
+                   workspace_users.users_id, \
+                   workspace_users.admin, \
+                   workspace_users.added \
+            from workspace_users \
+            where workspace_users.workspaces_id = $1 and \
+                  workspace_users.users_id = $2",
+            &[workspaces_id, users_id]
+        )
+            .await
+            .map(|maybe| maybe.map(|row| Self {
+                workspaces_id: row.get(0),
+                users_id: row.get(1),
+                admin: row.get(2),
+                added: row.get(3),
+            }))
+    }
+
+    /// adds the user to the workspace or updates the admin flag if the user
+    /// is already a member
+    pub async fn upsert(
+        conn: &impl GenericClient,
+        workspaces_id: &WorkspaceId,
+        users_id: &UserId,
+        admin: bool,
+    ) -> Result<Self, PgError> {
+        let added = Utc::now();
+
+        let row = conn.query_one(
+            "\
+            insert into workspace_users (workspaces_id, users_id, admin, added) values \
+            ($1, $2, $3, $4) \
+            on conflict (workspaces_id, users_id) do update \
+                set admin = excluded.admin \
+            returning added",
+            &[workspaces_id, users_id, &admin, &added]
+        ).await?;
+
+        Ok(Self {
+            workspaces_id: *workspaces_id,
+            users_id: *users_id,
+            admin,
+            added: row.get(0),
+        })
+    }
+
+    /// removes the user from the workspace
+    pub async fn delete(
+        conn: &impl GenericClient,
+        workspaces_id: &WorkspaceId,
+        users_id: &UserId,
+    ) -> Result<bool, PgError> {
+        let result = conn.execute(
+            "\
+            delete from workspace_users \
+            where workspaces_id = $1 and \
+                  users_id = $2",
+            &[workspaces_id, users_id]
+        ).await?;
+
+        Ok(result == 1)
+    }
+}